@@ -0,0 +1,40 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use vert::modules::arenas::Arenas;
+
+const COUNT: u64 = 10_000;
+
+fn insert(c: &mut Criterion) {
+    c.bench_function("arena insert 10k", |b| {
+        b.iter_batched(
+            Arenas::new,
+            |arenas| {
+                let keys: Vec<_> = (0..COUNT).map(|i| arenas.insert(i)).collect();
+                // returned, such that the keys are dropped outside of the measurement.
+                (arenas, keys)
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn remove_dropped(c: &mut Criterion) {
+    c.bench_function("arena remove_dropped 10k", |b| {
+        b.iter_batched(
+            || {
+                let arenas = Arenas::new();
+                for i in 0..COUNT {
+                    drop(arenas.insert(i));
+                }
+                arenas
+            },
+            |mut arenas| {
+                black_box(arenas.remove_dropped());
+                arenas
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, insert, remove_dropped);
+criterion_main!(benches);
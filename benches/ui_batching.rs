@@ -0,0 +1,57 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use glam::{dvec2, DVec2};
+use vert::{
+    batteries::stress_test::add_stress_divs,
+    modules::{
+        ui::{batching::get_batches, Board, BoardInput, FontCache},
+        Input,
+    },
+};
+
+const SIZE: DVec2 = DVec2::new(1920.0, 1080.0);
+const SEED: u64 = 42;
+
+/// A board with `count` divs, laid out.
+fn board_with_divs(count: usize, fonts: &mut FontCache, input: &Input) -> Board {
+    let mut board = Board::new(SIZE);
+    board.start_frame(BoardInput::from_input_module(input), SIZE);
+    add_stress_divs(&mut board, count, SEED);
+    board.end_frame(fonts);
+    board
+}
+
+fn ui_frame(c: &mut Criterion) {
+    let input = Input::new();
+    for count in [100, 1_000, 10_000] {
+        let mut fonts = FontCache::headless();
+        let mut board = board_with_divs(count, &mut fonts, &input);
+        c.bench_function(&format!("ui frame {count} divs"), |b| {
+            b.iter(|| {
+                board.start_frame(BoardInput::from_input_module(&input), SIZE);
+                add_stress_divs(&mut board, count, SEED);
+                board.end_frame(&mut fonts);
+            })
+        });
+    }
+}
+
+fn batching(c: &mut Criterion) {
+    let input = Input::new();
+    for count in [100, 1_000, 10_000] {
+        let mut fonts = FontCache::headless();
+        c.bench_function(&format!("ui batching {count} divs"), |b| {
+            // a new board each time, the batches of an unchanged board are cached.
+            b.iter_batched(
+                || board_with_divs(count, &mut fonts, &input),
+                |board| {
+                    black_box(get_batches(&board));
+                    board
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+}
+
+criterion_group!(benches, ui_frame, batching);
+criterion_main!(benches);
@@ -6,11 +6,20 @@ use glam::{vec2, vec3};
 use vert::{
     batteries::{FlyCam, GraphicsSettingsController},
     elements::{Color, Transform},
-    modules::{renderer::text_renderer::DrawText, DefaultModules},
+    modules::{
+        crash, input::CursorGrab, renderer::text_renderer::DrawText, CrashReport, DefaultModules,
+        TrackingAllocator,
+    },
     App, WinitConfig, WinitRunner,
 };
 
+// such that the stats overlay can show the allocations per scope.
+#[global_allocator]
+static ALLOC: TrackingAllocator = TrackingAllocator::system();
+
 fn main() {
+    _ = crash::init_logger();
+    crash::install_panic_hook("./crash_reports");
     let runner = WinitRunner::new(WinitConfig::default());
     let mut my_state = MyState::new(runner.window());
     _ = runner.run(&mut my_state);
@@ -61,8 +70,22 @@ impl MyState {
         // move camera:
 
         self.mods.gizmos.draw_xyz();
+        // press M to toggle mouse look:
+        if self
+            .mods
+            .input
+            .keys()
+            .just_pressed(vert::ext::KeyCode::KeyM)
+        {
+            let grab = match self.mods.input.cursor_grab() {
+                CursorGrab::Locked => CursorGrab::None,
+                _ => CursorGrab::Locked,
+            };
+            self.mods.input.set_cursor_grab(grab);
+        }
         FlyCam.update(&mut self.mods);
         self.graphics_controller.update(&mut self.mods);
+        self.mods.egui_stats();
         // /////////////////////////////////////////////////////////////////////////////
         // Draw some stuff (some things that are very bright)
         // /////////////////////////////////////////////////////////////////////////////
@@ -126,6 +149,10 @@ impl App for MyState {
         self.mods.receive_window_event(event);
     }
 
+    fn receive_device_event(&mut self, event: &winit::event::DeviceEvent) {
+        self.mods.receive_device_event(event);
+    }
+
     fn update(&mut self) -> vert::UpdateFlow {
         self.mods.begin_frame()?;
         self.update();
@@ -133,4 +160,8 @@ impl App for MyState {
         self.mods.end_frame();
         vert::UpdateFlow::Continue
     }
+
+    fn crashed(&mut self, report: &CrashReport) -> vert::UpdateFlow {
+        self.mods.crash_screen(report)
+    }
 }
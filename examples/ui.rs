@@ -74,10 +74,10 @@ impl MyApp {
             .color_mesh
             .draw_cubes(&[Transform::new(1.0, 1.0, 1.0)], None);
 
-        let size = self.mods.ctx.size;
+        let size = self.mods.screen.ui_size();
         self.ui.start_frame(
-            BoardInput::from_input_module(&self.mods.input),
-            dvec2(size.width as f64, size.height as f64),
+            BoardInput::from_input_and_screen(&self.mods.input, &self.mods.screen),
+            dvec2(size.x as f64, size.y as f64),
         );
 
         self.mods.world_rect.draw_textured_rect(
@@ -282,6 +282,9 @@ impl MyApp {
 
         self.ui.end_frame(&mut self.mods.fonts);
         self.mods.ui.draw_ui_board(&self.ui);
+        if let Some(icon) = self.ui.cursor_icon() {
+            self.mods.cursor.set_icon(icon);
+        }
         // std::thread::sleep(Duration::from_millis(150));
     }
 }
@@ -12,7 +12,7 @@ use vert::{
         renderer::ui_rect::UiRect,
         ui::{
             Align, Axis, Board, BoardInput, BorderRadius, Button, FontSize, Len, MainAlign,
-            Padding, Span, Text, TextSection,
+            Padding, Span, Text, TextEdit, TextSection,
         },
         DefaultModules,
     },
@@ -31,6 +31,7 @@ struct MyApp {
     ui: Board,
     graphics_settings: GraphicsSettingsController,
     font: OwnedPtr<Font>,
+    name: String,
 }
 
 impl App for MyApp {
@@ -63,6 +64,7 @@ impl MyApp {
             mods,
             graphics_settings,
             font,
+            name: String::new(),
         }
     }
     fn update(&mut self) {
@@ -74,10 +76,10 @@ impl MyApp {
             .color_mesh
             .draw_cubes(&[Transform::new(1.0, 1.0, 1.0)], None);
 
-        let size = self.mods.ctx.size;
+        let size = self.mods.screen.ui_size();
         self.ui.start_frame(
-            BoardInput::from_input_module(&self.mods.input),
-            dvec2(size.width as f64, size.height as f64),
+            BoardInput::from_input_and_screen(&self.mods.input, &self.mods.screen),
+            dvec2(size.x as f64, size.y as f64),
         );
 
         self.mods.world_rect.draw_textured_rect(
@@ -136,6 +138,7 @@ impl MyApp {
                 offset_x: Len::ZERO,
                 offset_y: Len::ZERO,
                 line_height: 1.0,
+                ..Default::default()
             },
             219912,
             parent,
@@ -145,9 +148,16 @@ impl MyApp {
         quad.color = Color::WHITE.alpha(0.2);
 
         let mut quad2 = self.ui.add(Button::default(), "wuad2", parent);
+        self.ui.add(TextEdit::new(&mut self.name), "name", parent);
 
         self.ui.end_frame(&mut self.mods.fonts);
         self.mods.ui.draw_ui_board(&self.ui);
+        if let Some(icon) = self.ui.cursor_icon() {
+            self.mods.cursor.set_icon(icon);
+        }
+        if self.ui.wants_text_input() {
+            self.mods.request_text_input();
+        }
         // std::thread::sleep(Duration::from_millis(150));
     }
 }
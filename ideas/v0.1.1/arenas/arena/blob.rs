@@ -4,13 +4,19 @@ use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::ops::Deref;
 use std::ptr::NonNull;
-use std::{mem, ptr};
+use std::ptr;
 
 /// like a Vec<T> but untyped.
+///
+/// Knows how to drop its items without knowing T, so dropping a Blob drops all items and frees the memory.
 pub(super) struct Blob {
     item_type_name: &'static str,
-    item_size: usize,
-    item_align: usize,
+    item_layout: Layout,
+    /// `ptr::drop_in_place::<T>` for the T this blob was created with.
+    drop_fn: unsafe fn(*mut u8),
+    /// byte offset of the interesting part inside each item, added to the pointers of `iter_raw_ptrs`.
+    /// 0 for plain blobs, the offset of the value inside an `Entry<T>` for arenas.
+    payload_offset: usize,
     ptr: NonNull<u8>,
     cap: usize,
     len: usize,
@@ -27,23 +33,40 @@ impl Blob {
         self.item_type_name
     }
 
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    pub fn item_size(&self) -> usize {
+        self.item_layout.size()
+    }
+
     pub fn new<T>() -> Blob {
         let type_name = type_name::<T>();
-        let item_size = mem::size_of::<T>();
-        let item_align = mem::align_of::<T>();
         Blob {
             item_type_name: type_name,
-            item_size,
-            item_align,
+            item_layout: Layout::new::<T>(),
+            drop_fn: drop_fn::<T>,
+            payload_offset: 0,
             ptr: NonNull::dangling(),
             len: 0,
             cap: 0,
         }
     }
 
+    pub fn with_payload_offset(mut self, payload_offset: usize) -> Blob {
+        debug_assert!(payload_offset <= self.item_layout.size());
+        self.payload_offset = payload_offset;
+        self
+    }
+
+    pub fn payload_offset(&self) -> usize {
+        self.payload_offset
+    }
+
     #[inline(always)]
     fn layout_for_cap(&self, cap: usize) -> Layout {
-        Layout::from_size_align(self.item_size * cap, self.item_align).unwrap()
+        Layout::from_size_align(self.item_layout.size() * cap, self.item_layout.align()).unwrap()
     }
 
     fn grow(&mut self) {
@@ -80,8 +103,7 @@ impl Blob {
     #[inline(always)]
     pub fn assert_t_matches<T>(&self) {
         debug_assert_eq!(type_name::<T>(), self.item_type_name);
-        debug_assert_eq!(mem::size_of::<T>(), self.item_size);
-        debug_assert_eq!(mem::align_of::<T>(), self.item_align);
+        debug_assert_eq!(Layout::new::<T>(), self.item_layout);
     }
 
     #[inline(always)]
@@ -152,16 +174,10 @@ impl Blob {
         }
     }
 
-    /// Warning!!! This **needs** to be called in orderto not leake resources!!!
-    pub fn free<T>(mut self) {
+    /// Same as dropping the blob, but checks that T matches.
+    pub fn free<T>(self) {
         self.assert_t_matches::<T>();
-        if self.cap != 0 {
-            while let Some(_) = self.pop::<T>() {}
-            let layout = self.layout_for_cap(self.cap);
-            unsafe {
-                alloc::dealloc(self.ptr.as_ptr(), layout);
-            }
-        }
+        drop(self);
     }
 
     pub fn typed_ref<'a, T>(&'a self) -> TypedBlobRef<'a, T> {
@@ -183,6 +199,33 @@ impl Blob {
     pub fn iter_raw_ptrs<'a>(&'a self) -> RawPtrIter<'a> {
         RawPtrIter::new(self)
     }
+
+    /// pointer to the first byte of the item at `index`, like the ones `iter_raw_ptrs` returns.
+    pub fn raw_ptr(&self, index: usize) -> Option<*const u8> {
+        if index >= self.len {
+            return None;
+        }
+        Some(unsafe { self.ptr.as_ptr().add(index * self.item_layout.size()) })
+    }
+}
+
+unsafe fn drop_fn<T>(ptr: *mut u8) {
+    ptr::drop_in_place(ptr as *mut T)
+}
+
+impl Drop for Blob {
+    fn drop(&mut self) {
+        if self.cap == 0 {
+            return;
+        }
+        for i in 0..self.len {
+            unsafe { (self.drop_fn)(self.ptr.as_ptr().add(i * self.item_layout.size())) }
+        }
+        let layout = self.layout_for_cap(self.cap);
+        unsafe {
+            alloc::dealloc(self.ptr.as_ptr(), layout);
+        }
+    }
 }
 
 // /////////////////////////////////////////////////////////////////////////////
@@ -207,8 +250,8 @@ impl<'a, T: Debug> Debug for TypedBlobRef<'a, T> {
 
         f.debug_struct("TypedBlob")
             .field("type_name", &self.blob.item_type_name)
-            .field("item_size", &self.blob.item_size)
-            .field("item_align", &self.blob.item_align)
+            .field("item_size", &self.blob.item_layout.size())
+            .field("item_align", &self.blob.item_layout.align())
             .field("entries", &BlobEntries { blob: self })
             .finish()
     }
@@ -246,6 +289,7 @@ impl<'a, T> DerefMut for TypedBlobMut<'a, T> {
 // /////////////////////////////////////////////////////////////////////////////
 
 /// the pointers are each offset apart from each other, where offset is the item size in the blob.
+/// Each pointer points to the first byte of an item, the payload offset of the blob is not added here.
 pub(super) struct RawPtrIter<'a> {
     blob: &'a Blob,
     /// at the beginning this is the same as the ptr of the blob.
@@ -272,7 +316,7 @@ impl<'a> Iterator for RawPtrIter<'a> {
             return None;
         }
         let ptr = self.byte_ptr;
-        self.byte_ptr = unsafe { self.byte_ptr.add(self.blob.item_size) };
+        self.byte_ptr = unsafe { self.byte_ptr.add(self.blob.item_layout.size()) };
         self.elements_done += 1;
         Some(ptr)
     }
@@ -379,7 +423,12 @@ mod tests {
                 };
                 blob.push(s);
             }
-            blob.free::<S>();
+            if _test_run % 2 == 0 {
+                blob.free::<S>();
+            } else {
+                // dropping without knowing S needs to clean up just the same.
+                drop(blob);
+            }
             // std::thread::sleep(Duration::from_millis(1));
             // println!("test_run {test_run} successful");
         }
@@ -3,6 +3,7 @@ use std::{
     fmt::Debug,
     iter::{self, Enumerate, FilterMap},
     marker::PhantomData,
+    mem::{self, MaybeUninit},
     slice::{Iter, IterMut},
 };
 
@@ -20,14 +21,60 @@ pub struct ArenaIndex {
 pub type Generation = u64;
 
 /// Important! Do not change the #[repr(C, u8, align(8))].
-/// We rely on skipping over 16 bytes (8 for tag and 8 for generation) to get to value: T.
+/// `RawPtrIter` relies on the tag being the first byte. The offset of value: T is not fixed
+/// (it depends on the alignment of T), it is probed once per type, see `Entry::payload_offset`.
 #[repr(C, u8, align(8))]
 #[derive(Clone, Debug)]
 enum Entry<T> {
-    Free { next_free: Option<usize> } = 0,
+    /// `gen` is the generation the next value put into this slot gets.
+    Free {
+        gen: Generation,
+        next_free: Option<usize>,
+    } = 0,
     Occupied { gen: Generation, value: T } = 1,
 }
+
+const FREE_TAG: u8 = 0;
+
 impl<T> Entry<T> {
+    /// Byte offset of `value` inside an occupied `Entry<T>`.
+    ///
+    /// Probed on an `Entry<MaybeUninit<T>>`, which has the same layout as `Entry<T>` because of the `repr(C)`,
+    /// such that no `T` is needed. Nothing uninitialized is read, only addresses are compared.
+    fn payload_offset() -> usize {
+        let probe: Entry<MaybeUninit<T>> = Entry::Occupied {
+            gen: 0,
+            value: MaybeUninit::uninit(),
+        };
+        let Entry::Occupied { value, .. } = &probe else {
+            unreachable!()
+        };
+        let offset = value as *const MaybeUninit<T> as usize - &probe as *const _ as usize;
+        debug_assert_eq!(
+            mem::size_of::<Entry<MaybeUninit<T>>>(),
+            mem::size_of::<Entry<T>>()
+        );
+        debug_assert_eq!(
+            mem::align_of::<Entry<MaybeUninit<T>>>(),
+            mem::align_of::<Entry<T>>()
+        );
+        debug_assert_eq!(offset % mem::align_of::<T>(), 0);
+        debug_assert!(offset + mem::size_of::<T>() <= mem::size_of::<Entry<T>>());
+        offset
+    }
+
+    /// Byte offset of `gen` inside an `Entry<T>`, the same for both variants. Probed like `payload_offset`.
+    fn generation_offset() -> usize {
+        let probe: Entry<MaybeUninit<T>> = Entry::Occupied {
+            gen: 0,
+            value: MaybeUninit::uninit(),
+        };
+        let Entry::Occupied { gen, .. } = &probe else {
+            unreachable!()
+        };
+        gen as *const Generation as usize - &probe as *const _ as usize
+    }
+
     fn is_occupied_by(&self, generation: Generation) -> bool {
         matches!(&self, Entry::Occupied { gen, .. } if *gen == generation)
     }
@@ -37,14 +84,32 @@ impl<T> Entry<T> {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArenaStats {
+    /// type name of the `Entry<T>`s.
+    pub type_name: &'static str,
+    pub len: usize,
+    /// number of entries, occupied or free.
+    pub capacity: usize,
+    /// length of the free list.
+    pub free: usize,
+    /// memory allocated by the blob.
+    pub bytes: usize,
+}
+
 /// an arena holds a type erazed blob in memory, that can be indexed by an ArenaIndex.
 /// Each entry is either filled or empty. Filled entries are reused when new entities come it.
+/// Dropping an arena drops all values in it, even without converting it into a `TypedArena<T>` first.
+///
+/// Every slot has its own generation, bumped when the value in it is removed. So an `ArenaIndex` only becomes
+/// stale when its own slot is removed, and reusing a slot is detected no matter what happens to other slots.
 ///
 /// Shamelessly inspired by https://docs.rs/generational-arena/latest/generational_arena/
 pub struct Arena {
     // blob of Entry<T>
     blob: Blob,
-    generation: Generation,
+    /// where the generation starts inside of an `Entry<T>`, see `Entry::generation_offset`.
+    generation_offset: usize,
     free_list_head: Option<usize>,
     len: usize,
 }
@@ -61,8 +126,8 @@ impl Debug for Arena {
 impl Arena {
     pub fn new<T>() -> Arena {
         Arena {
-            blob: Blob::new::<Entry<T>>(),
-            generation: 0,
+            blob: Self::entry_blob::<T>(),
+            generation_offset: Entry::<T>::generation_offset(),
             free_list_head: None,
             len: 0,
         }
@@ -70,14 +135,19 @@ impl Arena {
 
     pub fn with_capacity<T>(cap: usize) -> Arena {
         let mut arena = Arena {
-            blob: Blob::new::<Entry<T>>(),
-            generation: 0,
+            blob: Self::entry_blob::<T>(),
+            generation_offset: Entry::<T>::generation_offset(),
             free_list_head: None,
             len: 0,
         };
         arena.reserve::<T>(cap);
         arena
     }
+
+    fn entry_blob<T>() -> Blob {
+        Blob::new::<Entry<T>>().with_payload_offset(Entry::<T>::payload_offset())
+    }
+
     pub fn len(&self) -> usize {
         self.len
     }
@@ -86,6 +156,17 @@ impl Arena {
         self.len == 0
     }
 
+    /// Same fields as `vert::modules::arenas::ArenaStats`. Every entry that is not occupied is in the free list.
+    pub fn stats(&self) -> ArenaStats {
+        ArenaStats {
+            type_name: self.blob.item_type_name(),
+            len: self.len,
+            capacity: self.blob.len(),
+            free: self.blob.len() - self.len,
+            bytes: self.blob.capacity() * self.blob.item_size(),
+        }
+    }
+
     #[inline(always)]
     fn blob_mut<'a, T>(&'a mut self) -> TypedBlobMut<'a, Entry<T>> {
         self.assert_t_matches::<T>();
@@ -115,7 +196,7 @@ impl Arena {
 
         for i in start..end {
             let next_free = if i == end - 1 { old_head } else { Some(i + 1) };
-            let free_entry: Entry<T> = Entry::Free { next_free };
+            let free_entry: Entry<T> = Entry::Free { gen: 0, next_free };
             self.blob.push::<Entry<T>>(free_entry);
         }
 
@@ -137,10 +218,9 @@ impl Arena {
         match self.try_find_inner_empty_slot::<T>() {
             None => Err(value),
             Some(index) => {
-                let generation = self.generation;
                 let mut mut_blob = self.blob_mut::<T>();
                 mut_blob[index.index] = Entry::Occupied {
-                    gen: generation,
+                    gen: index.generation,
                     value,
                 };
                 Ok(index)
@@ -156,12 +236,12 @@ impl Arena {
             None => None,
             Some(i) => match self.blob.typed_ref::<Entry<T>>()[i] {
                 Entry::Occupied { .. } => panic!("corrupt free list"),
-                Entry::Free { next_free } => {
+                Entry::Free { gen, next_free } => {
                     self.free_list_head = next_free;
                     self.len += 1;
                     Some(ArenaIndex {
                         index: i,
-                        generation: self.generation,
+                        generation: gen,
                     })
                 }
             },
@@ -198,10 +278,10 @@ impl Arena {
             return None;
         }
         let new_free_entry_at_index = Entry::Free {
+            gen: i.generation.wrapping_add(1),
             next_free: free_list_head,
         };
         let entry = std::mem::replace(entry_at_index, new_free_entry_at_index);
-        self.generation += 1;
         self.free_list_head = Some(i.index);
         self.len -= 1;
 
@@ -307,21 +387,49 @@ impl Arena {
     pub fn iter_raw_ptrs<'a>(&'a self) -> RawPtrIter<'a> {
         RawPtrIter {
             iter: self.blob.iter_raw_ptrs(),
+            payload_offset: self.blob.payload_offset(),
         }
     }
-}
 
-// impl Drop for Arena {
-//     fn drop(&mut self) {
-//         panic!(
-//             "Untyped Arena dropped!
-// This is illegal, because the objects in the blob will not be dropped properly and leak memory.
-// Blob::free::<T>() needs to be called on the blob. This is done in the drop implementation of TypedArena<T>.
-// So convert the Untyped Arena into a TypedArena<T> first, before dropping it. Dropping without knowning the type T
-// is not possible because the T's stored in the arena could leak memory if their Drop implementation is not called."
-//         )
-//     }
-// }
+    /// Like `iter_raw_ptrs`, but also yields the index of each value.
+    pub fn iter_raw_ptrs_with_index<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (ArenaIndex, *const u8)> + 'a {
+        self.blob
+            .iter_raw_ptrs()
+            .enumerate()
+            .filter_map(|(index, entry_ptr)| {
+                let (generation, t_ptr) = unsafe { self.read_raw_entry(entry_ptr) }?;
+                Some((ArenaIndex { index, generation }, t_ptr))
+            })
+    }
+
+    /// Pointer to the value at `i` without knowing its type, None if `i` is stale.
+    pub fn get_raw_ptr(&self, i: ArenaIndex) -> Option<*const u8> {
+        let entry_ptr = self.blob.raw_ptr(i.index)?;
+        let (generation, t_ptr) = unsafe { self.read_raw_entry(entry_ptr) }?;
+        (generation == i.generation).then_some(t_ptr)
+    }
+
+    /// Generation and pointer to the value of an occupied entry, None if the entry is free.
+    ///
+    /// ### Safety
+    ///
+    /// `entry_ptr` must point to an `Entry<T>` in the blob of this arena.
+    unsafe fn read_raw_entry(&self, entry_ptr: *const u8) -> Option<(Generation, *const u8)> {
+        if *entry_ptr == FREE_TAG {
+            return None;
+        }
+        let generation = *(entry_ptr.add(self.generation_offset) as *const Generation);
+        Some((generation, entry_ptr.add(self.blob.payload_offset())))
+    }
+
+    /// Like `iter_raw_ptrs`, but the exclusive borrow makes it fine to write through the pointers.
+    /// The pointers come from the blob's allocation, not from the `&self` borrow, so this is not laundering a shared borrow.
+    pub fn iter_raw_ptrs_mut<'a>(&'a mut self) -> impl Iterator<Item = *mut u8> + 'a {
+        self.iter_raw_ptrs().map(|ptr| ptr as *mut u8)
+    }
+}
 
 impl<T> Borrow<TypedArena<T>> for Arena {
     fn borrow(&self) -> &TypedArena<T> {
@@ -390,6 +498,7 @@ impl<T> TypedArena<T> {
         self.arena.iter_mut()
     }
 
+    /// Same as dropping the arena, but checks that T matches.
     pub fn free(self) {
         self.arena.blob.free::<Entry<T>>();
     }
@@ -467,6 +576,8 @@ impl<'a, T: 'static> Iterator for ArenaIterMut<'a, T> {
 /// just a raw *const u8 pointer to the first byte of T is returned.
 pub struct RawPtrIter<'a> {
     iter: blob::RawPtrIter<'a>,
+    /// where T starts inside of an `Entry<T>`, see `Entry::payload_offset`.
+    payload_offset: usize,
 }
 
 impl<'a> Iterator for RawPtrIter<'a> {
@@ -475,18 +586,19 @@ impl<'a> Iterator for RawPtrIter<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             let next_ptr = self.iter.next()?;
-            // memory layout of the Entry is like this:
-            //          |   8b   |   8b   |   8b   |
-            // Free:     Free____|SomeNone|Nextfree|.....
+            // memory layout of the Entry is like this (for T with align <= 8):
+            //          |   8b   |   8b   |   8b   |   8b   |
+            // Free:     Free____|gggggggg|SomeNone|Nextfree|.....
             // Occupied: Occupied|gggggggg|T.........
+            // For higher aligned T there is padding between generation and T.
 
             // check if this entry is free, if so hop to next pointer:
             let tag_value = unsafe { *next_ptr };
-            if tag_value == 0 {
+            if tag_value == FREE_TAG {
                 continue;
             }
-            // jump 16 bytes ahead to our actual data:
-            let t_ptr = unsafe { next_ptr.add(16) };
+            // jump ahead to our actual data:
+            let t_ptr = unsafe { next_ptr.add(self.payload_offset) };
             return Some(t_ptr);
         }
     }
@@ -498,7 +610,9 @@ impl<'a> Iterator for RawPtrIter<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::TypedArena;
+    use std::{fmt::Debug, rc::Rc};
+
+    use super::{Entry, TypedArena};
 
     #[test]
     fn basic_functionality() {
@@ -518,4 +632,95 @@ mod tests {
 
         arena.free();
     }
+
+    #[test]
+    fn generation_per_slot() {
+        let mut arena: TypedArena<&str> = TypedArena::new();
+        let a = arena.insert("a");
+        let mut b = arena.insert("b");
+        // churn on the slot of b does not affect the generation of the slot of a.
+        for _ in 0..5 {
+            arena.remove(b);
+            let new_b = arena.insert("b");
+            assert_eq!(new_b.index, b.index);
+            assert_eq!(new_b.generation, b.generation + 1);
+            assert!(arena.get(b).is_none());
+            b = new_b;
+        }
+        assert_eq!(arena.get(a), Some(&"a"));
+
+        arena.remove(a);
+        let c = arena.insert("c");
+        assert_eq!((c.index, c.generation), (a.index, 1));
+        // the old index does not remove the new value (ABA):
+        assert_eq!(arena.remove(a), None);
+        assert_eq!(arena.get(c), Some(&"c"));
+        assert_eq!(arena.get(b), Some(&"b"));
+        assert_eq!(arena.len(), 2);
+    }
+
+    /// Free slots are counted from the free list, bytes from the size of the entries.
+    #[test]
+    fn stats() {
+        let mut arena: TypedArena<u64> = TypedArena::with_capacity(4);
+        let a = arena.insert(1);
+        arena.insert(2);
+        arena.remove(a);
+        let stats = arena.arena.stats();
+        assert_eq!((stats.len, stats.capacity, stats.free), (1, 4, 3));
+        assert_eq!(stats.bytes, 4 * std::mem::size_of::<super::Entry<u64>>());
+        arena.free();
+    }
+
+    /// Dropping an untyped arena drops the occupied entries with the drop function stored in its blob.
+    #[test]
+    fn drop_untyped() {
+        let counter = Rc::new(());
+        let mut arena: TypedArena<Rc<()>> = TypedArena::new();
+        let indices: Vec<_> = (0..10).map(|_| arena.insert(counter.clone())).collect();
+        arena.remove(indices[3]);
+        assert_eq!(Rc::strong_count(&counter), 10);
+        let untyped = arena.into_untyped();
+        drop(untyped);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    /// The raw pointers must point to the same values as the typed access, also for T with padding before it.
+    fn raw_ptrs_match_values<T: Clone + PartialEq + Debug + 'static>(values: [T; 3]) {
+        let mut arena: TypedArena<T> = TypedArena::new();
+        let indices = values.clone().map(|v| arena.insert(v));
+        arena.remove(indices[1]);
+
+        let raw: Vec<*const u8> = arena.arena.iter_raw_ptrs().collect();
+        let typed: Vec<*const u8> = arena
+            .iter()
+            .map(|(_, v)| v as *const T as *const u8)
+            .collect();
+        assert_eq!(raw, typed);
+        for ptr in raw.iter() {
+            assert_eq!(*ptr as usize % std::mem::align_of::<T>(), 0);
+        }
+        assert_eq!(unsafe { &*(raw[1] as *const T) }, &values[2]);
+        arena.free();
+    }
+
+    #[test]
+    fn raw_ptrs_high_alignment() {
+        #[derive(Debug, Clone, PartialEq)]
+        #[repr(align(32))]
+        struct Align32(u8);
+        #[derive(Debug, Clone, PartialEq)]
+        #[repr(align(64))]
+        struct Align64([u64; 3]);
+
+        // tag, padding up to the alignment of the variant union, generation, padding up to the alignment of T:
+        assert_eq!(Entry::<u64>::payload_offset(), 16);
+        assert_eq!(Entry::<Align32>::payload_offset(), 64);
+        assert_eq!(Entry::<Align64>::payload_offset(), 128);
+        raw_ptrs_match_values([1u8, 2, 3]);
+        raw_ptrs_match_values([1u128, 2, 3]);
+        raw_ptrs_match_values([Align32(1), Align32(2), Align32(3)]);
+        raw_ptrs_match_values([Align64([1; 3]), Align64([2; 3]), Align64([3; 3])]);
+        raw_ptrs_match_values([String::from("a"), "b".into(), "c".into()]);
+    }
 }
@@ -37,6 +37,11 @@ impl SingletonBlob {
         unsafe { std::mem::transmute(self.data) }
     }
 
+    /// pointer to the value, e.g. to assemble trait objects.
+    pub fn data_ptr(&self) -> *mut u8 {
+        self.data.as_ptr()
+    }
+
     pub fn free<T>(self) -> T {
         let t: Box<T> = unsafe { std::mem::transmute(self.data) };
         *t
@@ -18,7 +18,10 @@ use crate::{
     trait_reflection::{DynTrait, VTablePtr, VTablePtrWithMeta},
 };
 
-use self::arena::{Arena, ArenaIndex, TypedArena};
+use self::arena::{
+    singleton_blob::{SingletonBlob, TypedSingletonBlob},
+    Arena, ArenaIndex, TypedArena,
+};
 
 type ArenaAddress = TypeId;
 
@@ -27,6 +30,18 @@ pub struct Arenas {
     pub arenas: HashMap<TypeId, ComponentArena>,
     // maps a dyn_trait_id to the arena addresses and vtablepointers of all components that implement this trait
     dyn_traits_registry: DynTraitRegistry,
+    /// singleton components, at most one per type, e.g. settings or the score of a game.
+    resources: HashMap<TypeId, ComponentResource>,
+    /// incremented on every insert of or mutable access to a resource.
+    change_tick: u32,
+}
+
+/// A singleton component and the ticks it was added and last accessed mutably at.
+#[derive(Debug)]
+pub struct ComponentResource {
+    blob: SingletonBlob,
+    added_tick: u32,
+    changed_tick: u32,
 }
 
 #[derive(Debug)]
@@ -69,12 +84,15 @@ impl DynTraitRegistry {
                 component_resources_vtables: smallvec![],
             });
 
+        let vtables = if is_resource {
+            &mut implementors.component_resources_vtables
+        } else {
+            &mut implementors.component_vtables
+        };
         // this arena should not be part of the arena vtables already:
-        assert!(!implementors.component_vtables.iter().any(|e| e.0 == arena));
+        assert!(!vtables.iter().any(|e| e.0 == arena));
 
-        implementors
-            .component_vtables
-            .push((arena, ptr_with_meta.ptr));
+        vtables.push((arena, ptr_with_meta.ptr));
     }
 
     /// remove vtable pointer to the components in a certain arena, or to the singleton component resource of that arena.
@@ -91,12 +109,16 @@ impl DynTraitRegistry {
             .get_mut(&ptr_with_meta.dyn_trait_id)
             .expect("dyn traits should contain this arena");
 
+        let vtables = if is_resource {
+            &mut implementors.component_resources_vtables
+        } else {
+            &mut implementors.component_vtables
+        };
         // this arena should be part of the arena vtables already:
-        assert!(implementors
-            .component_vtables
+        assert!(vtables
             .iter()
             .any(|e| e.0 == arena && e.1 == ptr_with_meta.ptr));
-        implementors.component_vtables.retain(|e| e.0 != arena);
+        vtables.retain(|e| e.0 != arena);
     }
 }
 
@@ -202,6 +224,123 @@ impl Arenas {
             .flatten()
     }
 
+    /// Inserts the singleton resource of type `C`, returning the previous one.
+    pub fn insert_resource<C: Component>(&mut self, resource: C) -> Option<C> {
+        self.change_tick = self.change_tick.wrapping_add(1);
+        let arena_address = arena_address::<C>();
+        let resource = ComponentResource {
+            blob: SingletonBlob::new(resource),
+            added_tick: self.change_tick,
+            changed_tick: self.change_tick,
+        };
+        match self.resources.entry(arena_address) {
+            Entry::Occupied(mut e) => {
+                let old = std::mem::replace(e.get_mut(), resource);
+                Some(old.blob.into_typed::<C>().free())
+            }
+            Entry::Vacant(vacant) => {
+                let dyn_traits = unsafe { C::dyn_traits() };
+                for ptr_with_meta in dyn_traits {
+                    self.dyn_traits_registry
+                        .insert_vtable_ptr(arena_address, *ptr_with_meta, true)
+                }
+                vacant.insert(resource);
+                None
+            }
+        }
+    }
+
+    pub fn remove_resource<C: Component>(&mut self) -> Option<C> {
+        let arena_address = arena_address::<C>();
+        let resource = self.resources.remove(&arena_address)?;
+        let dyn_traits = unsafe { C::dyn_traits() };
+        for ptr_with_meta in dyn_traits {
+            self.dyn_traits_registry
+                .remove_vtable_ptr(arena_address, *ptr_with_meta, true)
+        }
+        Some(resource.blob.into_typed::<C>().free())
+    }
+
+    pub fn resource<C: Component>(&self) -> Option<&C> {
+        let resource = self.resources.get(&arena_address::<C>())?;
+        let typed: &TypedSingletonBlob<C> = resource.blob.borrow();
+        Some(typed.get())
+    }
+
+    /// Marks the resource as changed, even if it is not written to.
+    pub fn resource_mut<C: Component>(&mut self) -> Option<&mut C> {
+        let resource = self.resources.get_mut(&arena_address::<C>())?;
+        self.change_tick = self.change_tick.wrapping_add(1);
+        resource.changed_tick = self.change_tick;
+        resource.blob.assert_t_matches::<C>();
+        Some(resource.blob.get_mut())
+    }
+
+    /// The current tick. Store it and pass it to `resource_changed_since` later to find out if a resource changed in between.
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    /// True if the resource was inserted or accessed mutably after `tick`. False if there is no such resource.
+    pub fn resource_changed_since<C: Component>(&self, tick: u32) -> bool {
+        self.resources
+            .get(&arena_address::<C>())
+            .is_some_and(|r| tick_is_newer(r.changed_tick, tick))
+    }
+
+    /// True if the resource was inserted after `tick`. False if there is no such resource.
+    pub fn resource_added_since<C: Component>(&self, tick: u32) -> bool {
+        self.resources
+            .get(&arena_address::<C>())
+            .is_some_and(|r| tick_is_newer(r.added_tick, tick))
+    }
+
+    pub fn resource_implementors<T: DynTrait + ?Sized>(
+        &self,
+    ) -> SmallVec<[(TypeId, VTablePtr); 8]> {
+        self.dyn_traits_registry
+            .get(&T::id())
+            .map(|e| &e.component_resources_vtables)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// All resources implementing `T`.
+    pub fn iter_resource_traits<'a, T: DynTrait + ?Sized>(&'a self) -> impl Iterator<Item = &'a T> {
+        self.resource_implementors::<T>()
+            .into_iter()
+            .map(|(c_id, v_table_ptr)| {
+                let resource = self
+                    .resources
+                    .get(&c_id)
+                    .expect("Resource that is registered in dyn_traits not found!");
+                let ptr_pair = (resource.blob.data_ptr() as *const u8, v_table_ptr);
+                debug_assert_eq!(size_of::<&T>(), size_of::<(*const u8, &*const ())>()); // fat pointer
+                let trait_obj_ref: &'a T = unsafe { std::mem::transmute_copy(&ptr_pair) };
+                trait_obj_ref
+            })
+    }
+
+    /// All resources implementing `T`, each of them is marked as changed.
+    pub fn iter_resource_traits_mut<'a, T: DynTrait + ?Sized>(
+        &'a mut self,
+    ) -> impl Iterator<Item = &'a mut T> {
+        let implementors = self.resource_implementors::<T>();
+        self.change_tick = self.change_tick.wrapping_add(1);
+        let change_tick = self.change_tick;
+        // every resource is visited at most once, so the `&mut T` never alias.
+        self.resources
+            .iter_mut()
+            .filter_map(move |(c_id, resource)| {
+                let (_, v_table_ptr) = implementors.iter().find(|(id, _)| id == c_id)?;
+                resource.changed_tick = change_tick;
+                let ptr_pair = (resource.blob.data_ptr(), *v_table_ptr);
+                debug_assert_eq!(size_of::<&mut T>(), size_of::<(*mut u8, &*const ())>()); // fat pointer
+                let trait_obj_ref: &'a mut T = unsafe { std::mem::transmute_copy(&ptr_pair) };
+                Some(trait_obj_ref)
+            })
+    }
+
     pub fn implementors<T: DynTrait + ?Sized>(&self) -> SmallVec<[(TypeId, VTablePtr); 8]> {
         let dyn_trait_id = T::id();
         let implementors = self
@@ -233,27 +372,99 @@ impl Arenas {
             })
     }
 
-    pub fn iter_component_traits_mut<'a, T: DynTrait + ?Sized>(
-        &'a mut self,
-    ) -> impl Iterator<Item = &'a mut T> {
+    /// Like `iter_component_traits`, but also yields the component type and index each trait object came from,
+    /// e.g. to remove the component later or to get it back as its concrete type.
+    pub fn iter_component_traits_with_index<'a, T: DynTrait + ?Sized>(
+        &'a self,
+    ) -> impl Iterator<Item = (TypeId, ArenaIndex, &'a T)> {
         self.implementors::<T>()
             .into_iter()
             .flat_map(|(c_id, v_table_ptr)| {
-                // getting the arena as `get` instead of as `get_mut` here is
-                // not good, but we use unsafe anyway to get our way, so we do not care much here.
                 let component_arena = self
                     .arenas
                     .get(&c_id)
                     .expect("Arena that is registered in dyn_traits not found!");
+                component_arena
+                    .arena
+                    .iter_raw_ptrs_with_index()
+                    .map(move |(i, data_ptr)| {
+                        let ptr_pair = (data_ptr, v_table_ptr);
+                        debug_assert_eq!(size_of::<&T>(), size_of::<(*const u8, &*const ())>()); // fat pointer
+                        let trait_obj_ref: &'a T = unsafe { std::mem::transmute_copy(&ptr_pair) };
+                        (c_id, i, trait_obj_ref)
+                    })
+            })
+    }
 
-                component_arena.arena.iter_raw_ptrs().map(move |data_ptr| {
-                    // assemble a new trait object:
-                    let ptr_pair = (data_ptr, v_table_ptr);
-                    debug_assert_eq!(size_of::<&mut T>(), size_of::<(*const u8, &*const ())>()); // fat pointer
-                    let trait_obj_ref: &'a mut T = unsafe { std::mem::transmute_copy(&ptr_pair) };
-                    trait_obj_ref
-                })
+    /// The component of type `component` (its `Component::id`) at `i` as a `T` trait object.
+    /// None if there is no such component or it does not implement `T`.
+    pub fn get_trait<T: DynTrait + ?Sized>(&self, component: TypeId, i: ArenaIndex) -> Option<&T> {
+        let v_table_ptr = self.vtable_ptr::<T>(component)?;
+        let data_ptr = self.arenas.get(&component)?.arena.get_raw_ptr(i)?;
+        let ptr_pair = (data_ptr, v_table_ptr);
+        debug_assert_eq!(size_of::<&T>(), size_of::<(*const u8, &*const ())>()); // fat pointer
+        Some(unsafe { std::mem::transmute_copy(&ptr_pair) })
+    }
+
+    pub fn get_trait_mut<T: DynTrait + ?Sized>(
+        &mut self,
+        component: TypeId,
+        i: ArenaIndex,
+    ) -> Option<&mut T> {
+        let v_table_ptr = self.vtable_ptr::<T>(component)?;
+        // the exclusive borrow of the arena makes it fine to write through the pointer.
+        let data_ptr = self.arenas.get_mut(&component)?.arena.get_raw_ptr(i)? as *mut u8;
+        let ptr_pair = (data_ptr, v_table_ptr);
+        debug_assert_eq!(size_of::<&mut T>(), size_of::<(*mut u8, &*const ())>()); // fat pointer
+        Some(unsafe { std::mem::transmute_copy(&ptr_pair) })
+    }
+
+    fn vtable_ptr<T: DynTrait + ?Sized>(&self, component: TypeId) -> Option<VTablePtr> {
+        self.dyn_traits_registry
+            .get(&T::id())?
+            .component_vtables
+            .iter()
+            .find(|(c_id, _)| *c_id == component)
+            .map(|(_, v_table_ptr)| *v_table_ptr)
+    }
+
+    /// Every arena is borrowed mutably at most once: the arenas of all implementors are collected from a single
+    /// `iter_mut` over the arena map, so the `&mut T` handed out can never alias.
+    /// In debug builds, this panics if the registry lists the same component arena twice for `T`.
+    pub fn iter_component_traits_mut<'a, T: DynTrait + ?Sized>(
+        &'a mut self,
+    ) -> impl Iterator<Item = &'a mut T> {
+        let implementors = self.implementors::<T>();
+        #[cfg(debug_assertions)]
+        for (i, (c_id, _)) in implementors.iter().enumerate() {
+            assert!(
+                implementors[..i].iter().all(|(other, _)| other != c_id),
+                "aliased access: component arena {c_id:?} registered twice as implementor of the same dyn trait"
+            );
+        }
+        let arenas: SmallVec<[(&'a mut ComponentArena, VTablePtr); 8]> = self
+            .arenas
+            .iter_mut()
+            .filter_map(|(c_id, arena)| {
+                let (_, v_table_ptr) = implementors.iter().find(|(id, _)| id == c_id)?;
+                Some((arena, *v_table_ptr))
+            })
+            .collect();
+        debug_assert_eq!(
+            arenas.len(),
+            implementors.len(),
+            "Arena that is registered in dyn_traits not found!"
+        );
+
+        arenas.into_iter().flat_map(|(component_arena, v_table_ptr)| {
+            component_arena.arena.iter_raw_ptrs_mut().map(move |data_ptr| {
+                // assemble a new trait object:
+                let ptr_pair = (data_ptr, v_table_ptr);
+                debug_assert_eq!(size_of::<&mut T>(), size_of::<(*mut u8, &*const ())>()); // fat pointer
+                let trait_obj_ref: &'a mut T = unsafe { std::mem::transmute_copy(&ptr_pair) };
+                trait_obj_ref
             })
+        })
     }
 }
 
@@ -261,10 +472,18 @@ impl Debug for Arenas {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Arenas")
             .field("arenas", &self.arenas)
+            .field("resources", &self.resources)
+            .field("change_tick", &self.change_tick)
             .finish()
     }
 }
 
+/// ticks wrap around, so a tick is newer if it is less than half the range ahead.
+fn tick_is_newer(tick: u32, than: u32) -> bool {
+    let diff = tick.wrapping_sub(than);
+    diff != 0 && diff < u32::MAX / 2
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct ComponentArena {
@@ -307,3 +526,80 @@ impl<T: Component> TypedComponentArena<T> {
         self.arena.free();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    use super::Arenas;
+
+    trait Describe {
+        fn describe(&self) -> String;
+    }
+    reflect!(Describe);
+
+    struct Score(u32);
+    impl Describe for Score {
+        fn describe(&self) -> String {
+            format!("score {}", self.0)
+        }
+    }
+    reflect!(Score: Describe);
+    impl Component for Score {}
+
+    #[test]
+    fn resources() {
+        let mut arenas = Arenas::new();
+        assert!(arenas.resource::<Score>().is_none());
+        assert!(arenas.insert_resource(Score(1)).is_none());
+        let tick = arenas.change_tick();
+        assert!(!arenas.resource_changed_since::<Score>(tick));
+
+        arenas.resource_mut::<Score>().unwrap().0 += 1;
+        assert!(arenas.resource_changed_since::<Score>(tick));
+        assert!(!arenas.resource_added_since::<Score>(tick));
+        assert_eq!(arenas.resource::<Score>().unwrap().0, 2);
+
+        let descriptions: Vec<String> = arenas
+            .iter_resource_traits::<dyn Describe>()
+            .map(|d| d.describe())
+            .collect();
+        assert_eq!(descriptions, vec!["score 2".to_string()]);
+        // resources are not part of the component arenas:
+        assert_eq!(arenas.iter_component_traits::<dyn Describe>().count(), 0);
+
+        assert_eq!(arenas.insert_resource(Score(7)).unwrap().0, 2);
+        assert_eq!(arenas.remove_resource::<Score>().unwrap().0, 7);
+        assert_eq!(arenas.iter_resource_traits::<dyn Describe>().count(), 0);
+    }
+
+    #[test]
+    fn traits_with_index() {
+        let mut arenas = Arenas::new();
+        let a = arenas.insert(Score(1));
+        let b = arenas.insert(Score(2));
+        arenas.remove::<Score>(a);
+
+        let found: Vec<_> = arenas
+            .iter_component_traits_with_index::<dyn Describe>()
+            .map(|(c_id, i, d)| (c_id, i, d.describe()))
+            .collect();
+        assert_eq!(found.len(), 1);
+        let (c_id, i, description) = &found[0];
+        assert_eq!(*c_id, Score::id());
+        assert_eq!(description, "score 2");
+        assert_eq!(arenas.get::<Score>(*i).unwrap().0, 2);
+
+        assert!(arenas.get_trait::<dyn Describe>(Score::id(), a).is_none());
+        assert_eq!(
+            arenas
+                .get_trait::<dyn Describe>(Score::id(), b)
+                .unwrap()
+                .describe(),
+            "score 2"
+        );
+        assert!(arenas
+            .get_trait_mut::<dyn Describe>(Score::id(), b)
+            .is_some());
+    }
+}
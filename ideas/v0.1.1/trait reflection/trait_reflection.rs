@@ -3,7 +3,7 @@
 // // /////////////////////////////////////////////////////////////////////////////
 
 use smallvec::{smallvec, SmallVec};
-use std::any::TypeId;
+use std::{any::TypeId, mem::size_of};
 
 /// should only be implemented for `dyn MyTrait`
 pub trait DynTrait: 'static {
@@ -45,6 +45,8 @@ pub struct VTablePtrWithMeta {
     pub dyn_trait_id: TypeId,
     /// type name of the dyn trait: e.g. "dyn vert::trait_reflection::types::Render"
     pub dyn_trait_name: &'static str,
+    /// type id of the implementor, e.g. `Circle`.
+    pub implementor_id: TypeId,
 }
 unsafe impl Sync for VTablePtrWithMeta {}
 unsafe impl Send for VTablePtrWithMeta {}
@@ -59,6 +61,86 @@ pub fn vtable_pointer<C: Implementor, T: DynTrait + ?Sized>() -> Option<VTablePt
     }
 }
 
+/// Called by `reflect!` once per implementor, when its vtables are first requested.
+///
+/// Panics if a dyn trait is registered twice for the same implementor, e.g. `reflect!(Circle: Render, Render)`,
+/// or if an entry belongs to another implementor, instead of letting the arenas pick one of them at random.
+pub fn validate_dyn_traits<C: 'static>(impls: &[VTablePtrWithMeta]) {
+    let implementor_id = TypeId::of::<C>();
+    for (i, meta) in impls.iter().enumerate() {
+        assert!(
+            meta.implementor_id == implementor_id,
+            "reflect!: vtable of `{}` registered for `{}`",
+            meta.dyn_trait_name,
+            std::any::type_name::<C>()
+        );
+        if impls[..i]
+            .iter()
+            .any(|other| other.dyn_trait_id == meta.dyn_trait_id)
+        {
+            panic!(
+                "reflect!: `{}` registered `{}` twice",
+                std::any::type_name::<C>(),
+                meta.dyn_trait_name
+            );
+        }
+    }
+}
+
+/// The vtable pointer (second half) of a trait object.
+pub fn vtable_ptr_of<T: DynTrait + ?Sized>(trait_obj: &T) -> VTablePtr {
+    assert_eq!(size_of::<&T>(), size_of::<VTable>(), "not a trait object");
+    let vtable: VTable = unsafe { std::mem::transmute_copy(&trait_obj) };
+    vtable.ptr
+}
+
+/// The concrete type behind a trait object. Implemented for all sized types, so adding it as a supertrait
+/// (`trait Render: Reflected {}`) makes `implementor_id` a vtable method of `dyn Render`.
+pub trait Reflected: 'static {
+    fn implementor_id(&self) -> TypeId;
+}
+
+impl<T: 'static> Reflected for T {
+    fn implementor_id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+}
+
+/// Casting trait objects back to their concrete type, implemented for all `dyn MyTrait` with `reflect!(MyTrait)`
+/// where `MyTrait: Reflected`.
+///
+/// The vtable pointer can not tell the type: identical vtables of different types may be merged, and the same
+/// type can have several copies of its vtable across codegen units. So `is` compares the `TypeId` of the concrete
+/// type instead, which works for any trait object, no matter where it was created.
+pub trait Downcast {
+    fn is<C: 'static>(&self) -> bool;
+    fn downcast_ref<C: 'static>(&self) -> Option<&C>;
+    fn downcast_mut<C: 'static>(&mut self) -> Option<&mut C>;
+}
+
+impl<T: DynTrait + Reflected + ?Sized> Downcast for T {
+    fn is<C: 'static>(&self) -> bool {
+        self.implementor_id() == TypeId::of::<C>()
+    }
+
+    fn downcast_ref<C: 'static>(&self) -> Option<&C> {
+        if !self.is::<C>() {
+            return None;
+        }
+        // the data pointer of the trait object points to a `C`.
+        let data = self as *const T as *const C;
+        Some(unsafe { &*data })
+    }
+
+    fn downcast_mut<C: 'static>(&mut self) -> Option<&mut C> {
+        if !self.is::<C>() {
+            return None;
+        }
+        let data = self as *mut T as *mut C;
+        Some(unsafe { &mut *data })
+    }
+}
+
 pub trait MultipleReflectedTraits {
     unsafe fn vtable_pointers<C: Implementor>() -> SmallVec<[(TypeId, Option<VTablePtrWithMeta>); 4]>;
 }
@@ -123,6 +205,7 @@ multi_implements_impl_for_tuples!(A, B, C, D, E, F, G, H, I, J);
 /// trait Render { }
 /// impl DynTrait for dyn Render {}
 /// ```
+/// To downcast `dyn Render` objects, declare the trait as `trait Render: Reflected { }`.
 ///
 /// ### Use on structs, specifying traits:
 /// ```rust,no_run,ignore
@@ -137,60 +220,94 @@ multi_implements_impl_for_tuples!(A, B, C, D, E, F, G, H, I, J);
 ///
 /// impl Implementor for Circle {
 ///     unsafe fn dyn_traits() -> &'static [VTablePtrWithMeta] {
-///         const UNINIT: Circle =
-///             unsafe { std::mem::MaybeUninit::<Circle>::uninit().assume_init() };
-///         const IMPLS: &'static [VTablePtrWithMeta] = &[{
-///             const RENDER: &'static dyn Render = &UNINIT as &'static dyn Render;
-///             if std::mem::size_of::<&dyn Render>() != std::mem::size_of::<usize>() * 2 {
-///                 panic!("Error in Implementor::dyn_traits, invalid fat pointer")
-///             }
-///             let vtable = &RENDER as *const _ as *const VTable;
-///             VTablePtrWithMeta {
-///                 ptr: unsafe { (*vtable).ptr },
-///                 dyn_trait_id: TypeId::of::<dyn Render>(),
-///                 dyn_trait_name: std::any::type_name::<dyn Render>(),
-///             }
-///         }];
-///         IMPLS
+///         static ONCE: OnceLock<Box<[VTablePtrWithMeta]>> = OnceLock::new();
+///         ONCE.get_or_init(|| {
+///             Box::new([{
+///                 let ptr = NonNull::<Circle>::dangling().as_ptr() as *const dyn Render;
+///                 let vtable: VTable = unsafe { std::mem::transmute(ptr) };
+///                 VTablePtrWithMeta {
+///                     ptr: vtable.ptr,
+///                     dyn_trait_id: TypeId::of::<dyn Render>(),
+///                     dyn_trait_name: std::any::type_name::<dyn Render>(),
+///                     implementor_id: TypeId::of::<Circle>(),
+///                 }
+///             }])
+///         })
 ///     }
 /// }
 /// ```
+///
+/// Types and traits can be given as paths, and concrete instances of generic types work too:
+/// ```rust,no_run,ignore
+/// reflect!(shapes::Circle: render::Render);
+/// reflect!(InstanceBuffer<Color>: Render);
+/// ```
+///
+/// ### Use on generic structs:
+/// ```rust,no_run,ignore
+/// reflect!(impl<T> InstanceBuffer<T>: Render where T: Pod);
+/// ```
+/// The generic parameters are `'static`, other bounds go in the where clause.
+///
+/// The vtables of each type are collected once, on first use, behind a `OnceLock` (or a lock per generic type),
+/// so they can be requested from any thread. Registering a trait twice for one type panics then, see `validate_dyn_traits`.
 #[macro_export]
 macro_rules! reflect {
-    ($trait:ident) => {
-        impl DynTrait for dyn $trait {}
+    // one entry of `Implementor::dyn_traits`. The vtable is taken from a dangling pointer, no value is needed.
+    (@vtable $component:ty, $trait:path) => {{
+        let ptr = std::ptr::NonNull::<$component>::dangling().as_ptr() as *const dyn $trait;
+        let vtable: VTable = unsafe { std::mem::transmute(ptr) };
+        VTablePtrWithMeta {
+            ptr: vtable.ptr,
+            dyn_trait_id: std::any::TypeId::of::<dyn $trait>(),
+            dyn_trait_name: std::any::type_name::<dyn $trait>(),
+            implementor_id: std::any::TypeId::of::<$component>(),
+        }
+    }};
+    // generic components: a static in a generic fn is shared by all monomorphizations, so the vtables are cached per TypeId.
+    (impl<$($generic:ident),+> $component:ty : $($trait:path),+ $(where $($bounds:tt)+)?) => {
+        impl<$($generic: 'static),+> Implementor for $component $(where $($bounds)+)? {
+            unsafe fn dyn_traits() -> &'static [VTablePtrWithMeta]{
+                use std::{any::TypeId, collections::HashMap, sync::{OnceLock, RwLock}};
+                static IMPLS: OnceLock<RwLock<HashMap<TypeId, &'static [VTablePtrWithMeta]>>> = OnceLock::new();
+                let impls = IMPLS.get_or_init(Default::default);
+                let id = TypeId::of::<Self>();
+                if let Some(impls) = impls.read().unwrap().get(&id) {
+                    return impls;
+                }
+                let mut impls = impls.write().unwrap();
+                // checked again under the write lock, such that threads racing for the same type all get the same slice.
+                impls.entry(id).or_insert_with(|| {
+                    let new_impls = [$($crate::reflect!(@vtable $component, $trait)),+];
+                    validate_dyn_traits::<Self>(&new_impls);
+                    Box::leak(Box::new(new_impls))
+                })
+            }
+        }
     };
-    ($component:ident : $($trait:ident),* ) => {
+    ($component:ty : $($trait:path),+ $(,)?) => {
         impl Implementor for $component {
             unsafe fn dyn_traits() -> &'static [VTablePtrWithMeta]{
                 use std::sync::OnceLock;
                 static ONCE: OnceLock<Box<[VTablePtrWithMeta]>> = OnceLock::new();
-                ONCE.get_or_init(||{
-                    #[allow(invalid_value)]
-                    let uninit: $component =
-                        unsafe { std::mem::MaybeUninit::<$component>::uninit().assume_init() };
-                    let impls = vec![
-                        $(
-                            {
-                                let trait_obj: &dyn $trait = &uninit as &dyn $trait;
-                                // if std::mem::size_of::<&dyn $trait>() != std::mem::size_of::<usize>() * 2 {
-                                //     panic!("Error in Implementor::dyn_traits, invalid fat pointer...")
-                                // }
-                                let vtable = &trait_obj as *const _ as *const VTable;
-                                VTablePtrWithMeta {
-                                    ptr: unsafe { (*vtable).ptr },
-                                    dyn_trait_id: std::any::TypeId::of::<dyn $trait>(),
-                                    dyn_trait_name: std::any::type_name::<dyn $trait>(),
-                                }
-                            }
-                        ),*
-                    ];
-                    std::mem::forget(uninit);
-                    impls.into()
+                ONCE.get_or_init(|| {
+                    let impls = Box::new([$($crate::reflect!(@vtable $component, $trait)),+]);
+                    validate_dyn_traits::<Self>(&*impls);
+                    impls
                 })
             }
         }
     };
+    ($trait:path) => {
+        impl DynTrait for dyn $trait {}
+    };
+    ($($other:tt)*) => {
+        compile_error!(concat!(
+            "reflect! expects `MyTrait`, `MyType: TraitA, TraitB` or `impl<T> MyType<T>: TraitA where T: Bound`, got: `",
+            stringify!($($other)*),
+            "`"
+        ));
+    };
 }
 
 // // /////////////////////////////////////////////////////////////////////////////
@@ -252,4 +369,124 @@ mod tests {
         assert!(vtable_pointer::<Point, dyn Log>().is_some());
         assert!(vtable_pointer::<Point, dyn Update>().is_some());
     }
+
+    #[test]
+    fn test_downcast() {
+        struct Circle {
+            radius: f32,
+        }
+        struct Rect(u64);
+
+        pub trait Render: Reflected {}
+        reflect!(Render);
+
+        impl Render for Circle {}
+        impl Render for Rect {}
+        reflect!(Circle: Render);
+        reflect!(Rect: Render);
+
+        let circle = Circle { radius: 2.0 };
+        let render = vtable_pointer::<Circle, dyn Render>().unwrap();
+        // assemble the trait object like the arenas do, from the registered vtable:
+        let trait_obj: &dyn Render =
+            unsafe { std::mem::transmute((&circle as *const Circle, render.ptr)) };
+
+        assert!(trait_obj.is::<Circle>());
+        assert_eq!(trait_obj.downcast_ref::<Circle>().unwrap().radius, 2.0);
+        assert!(trait_obj.downcast_ref::<Rect>().is_none());
+    }
+
+    #[test]
+    fn test_downcast_merged_vtables() {
+        // same size, align and (empty) vtable, the linker is free to merge them.
+        struct A(u32);
+        struct B(u32);
+        pub trait Render: Reflected {}
+        reflect!(Render);
+        impl Render for A {}
+        impl Render for B {}
+
+        let mut b = B(7);
+        let trait_obj: &mut dyn Render = &mut b;
+        assert!(!trait_obj.is::<A>());
+        assert!(trait_obj.downcast_ref::<A>().is_none());
+        assert!(trait_obj.downcast_mut::<A>().is_none());
+        assert_eq!(trait_obj.downcast_mut::<B>().unwrap().0, 7);
+    }
+
+    #[test]
+    fn test_registration_from_many_threads() {
+        struct Buffer<T>(T);
+        pub trait Render {}
+        reflect!(Render);
+        impl<T> Render for Buffer<T> {}
+        reflect!(impl<T> Buffer<T>: Render);
+
+        let slices: Vec<(usize, usize)> = std::thread::scope(|s| {
+            let threads: Vec<_> = (0..8)
+                .map(|_| {
+                    s.spawn(|| unsafe {
+                        (
+                            Buffer::<u8>::dyn_traits().as_ptr() as usize,
+                            Buffer::<u16>::dyn_traits().as_ptr() as usize,
+                        )
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        assert!(slices.iter().all(|e| *e == slices[0]));
+        assert_ne!(slices[0].0, slices[0].1);
+    }
+
+    #[test]
+    #[should_panic(expected = "twice")]
+    fn test_duplicate_registration() {
+        struct Circle;
+        pub trait Render {}
+        reflect!(Render);
+        impl Render for Circle {}
+        reflect!(Circle: Render, Render);
+        vtable_pointer::<Circle, dyn Render>();
+    }
+
+    mod shapes {
+        pub struct Circle;
+        pub trait Render {}
+        impl Render for Circle {}
+    }
+
+    #[test]
+    fn test_macro_paths_and_generics() {
+        reflect!(shapes::Render);
+        reflect!(shapes::Circle: shapes::Render);
+        assert!(vtable_pointer::<shapes::Circle, dyn shapes::Render>().is_some());
+
+        struct InstanceBuffer<T>(Vec<T>);
+        pub trait Len: Reflected {
+            fn len(&self) -> usize;
+        }
+        reflect!(Len);
+        impl<T: Clone> Len for InstanceBuffer<T> {
+            fn len(&self) -> usize {
+                self.0.len()
+            }
+        }
+        reflect!(impl<T> InstanceBuffer<T>: Len where T: Clone);
+
+        let a = vtable_pointer::<InstanceBuffer<u8>, dyn Len>().unwrap();
+        let b = vtable_pointer::<InstanceBuffer<String>, dyn Len>().unwrap();
+        // every monomorphization has its own vtables:
+        assert_ne!(a.ptr, b.ptr);
+        assert_eq!(
+            a.implementor_id,
+            std::any::TypeId::of::<InstanceBuffer<u8>>()
+        );
+
+        let buffer = InstanceBuffer(vec![1u8, 2, 3]);
+        let trait_obj: &dyn Len = unsafe { std::mem::transmute((&buffer as *const _, a.ptr)) };
+        assert_eq!(trait_obj.len(), 3);
+        assert!(trait_obj.is::<InstanceBuffer<u8>>());
+        assert!(!trait_obj.is::<InstanceBuffer<String>>());
+    }
 }
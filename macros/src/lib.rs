@@ -1,10 +1,10 @@
 use proc_macro::TokenStream;
-use quote::{quote, ToTokens};
-use syn::{parse_quote, TypePath};
+use quote::quote;
+use syn::parse_quote;
 
 /// Derives the Lerp trait for a struct where each field implements Lerp.
 /// For example the Struct:
-/// ```rust,no-run
+/// ```rust,ignore
 /// struct Color{
 ///     r: f32,
 ///     g: f32,
@@ -14,57 +14,380 @@ use syn::{parse_quote, TypePath};
 ///
 /// Will get a lerp implementation that is:
 ///
-/// ```rust,no-run
+/// ```rust,ignore
 /// impl Lerp for Color{
 ///     fn lerp(&self, other: &Self, factor: f32) -> Self {
 ///         Color {
-///             r: self.r.lerp(&other.r, factor),
-///             g: self.g.lerp(&other.g, factor),
-///             b: self.b.lerp(&other.b, factor),
+///             r: Lerp::lerp(&self.r, &other.r, factor),
+///             g: Lerp::lerp(&self.g, &other.g, factor),
+///             b: Lerp::lerp(&self.b, &other.b, factor),
 ///         }
 ///     }
 /// }
 /// ```
 ///
 /// Don't use this Derive Macro if the fields should not be lerped independently.
-#[proc_macro_derive(Lerp)]
+///
+/// Fields that cannot be interpolated (ids, handles, ...) can be marked with `#[lerp(skip)]` (switches from `self` to
+/// `other` at factor 0.5) or `#[lerp(clone_from_a)]` (always the value of `self`). These fields need to be `Clone`.
+///
+/// Generic structs get a `Lerp` bound on every type parameter.
+/// Enums are lerped field by field if both values are the same variant, otherwise they switch at factor 0.5,
+/// which requires the enum to be `Clone`.
+#[proc_macro_derive(Lerp, attributes(lerp))]
 pub fn derive_lerp(input: TokenStream) -> TokenStream {
+    let mut derive_input: syn::DeriveInput = syn::parse(input).unwrap();
+    let ident = derive_input.ident.clone();
+
+    // every type parameter needs to be Lerp itself:
+    for param in derive_input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(Lerp));
+    }
+    let (impl_generics, ty_generics, where_clause) = derive_input.generics.split_for_impl();
+
+    let lerp_impl_body = match &derive_input.data {
+        syn::Data::Struct(stru) => {
+            let (pattern_a, pattern_b, construction) = lerp_fields(quote!(#ident), &stru.fields);
+            quote!(
+                let #pattern_a = self;
+                let #pattern_b = other;
+                #construction
+            )
+        }
+        syn::Data::Enum(enu) => {
+            let arms = enu.variants.iter().map(|variant| {
+                let variant_ident = &variant.ident;
+                let (pattern_a, pattern_b, construction) =
+                    lerp_fields(quote!(#ident::#variant_ident), &variant.fields);
+                quote!((#pattern_a, #pattern_b) => #construction)
+            });
+            quote!(
+                #[allow(unreachable_patterns)]
+                match (self, other) {
+                    #(#arms,)*
+                    // different variants cannot be interpolated, just switch halfway.
+                    _ => {
+                        if factor < 0.5 {
+                            self.clone()
+                        } else {
+                            other.clone()
+                        }
+                    }
+                }
+            )
+        }
+        syn::Data::Union(_) => panic!("Lerp cannot be derived on unions"),
+    };
+
+    quote!(
+        impl #impl_generics Lerp for #ident #ty_generics #where_clause {
+            fn lerp(&self, other: &Self, factor: f32) -> Self {
+                #lerp_impl_body
+            }
+        }
+    )
+    .into()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LerpFieldMode {
+    Lerp,
+    /// `#[lerp(skip)]`: not interpolated, switches from the first to the second value at factor 0.5.
+    Skip,
+    /// `#[lerp(clone_from_a)]`: not interpolated, always the first value.
+    CloneFromA,
+}
+
+fn lerp_field_mode(field: &syn::Field) -> LerpFieldMode {
+    let mut mode = LerpFieldMode::Lerp;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("lerp")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                mode = LerpFieldMode::Skip;
+                Ok(())
+            } else if meta.path.is_ident("clone_from_a") {
+                mode = LerpFieldMode::CloneFromA;
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip` or `clone_from_a`"))
+            }
+        })
+        .unwrap();
+    }
+    mode
+}
+
+/// Returns patterns that bind the fields of `a` and `b` and an expression that constructs the lerped value.
+fn lerp_fields(
+    path: proc_macro2::TokenStream,
+    fields: &syn::Fields,
+) -> (
+    proc_macro2::TokenStream,
+    proc_macro2::TokenStream,
+    proc_macro2::TokenStream,
+) {
+    let a_ident = |i: usize| syn::Ident::new(&format!("a{i}"), proc_macro2::Span::call_site());
+    let b_ident = |i: usize| syn::Ident::new(&format!("b{i}"), proc_macro2::Span::call_site());
+    // Lerp::lerp instead of a.lerp(), such that inherent lerp methods (e.g. on glam types) are not picked.
+    let values = fields.iter().enumerate().map(|(i, field)| {
+        let (a, b) = (a_ident(i), b_ident(i));
+        match lerp_field_mode(field) {
+            LerpFieldMode::Lerp => quote!(Lerp::lerp(#a, #b, factor)),
+            LerpFieldMode::Skip => quote!(if factor < 0.5 {
+                #a.clone()
+            } else {
+                #b.clone()
+            }),
+            LerpFieldMode::CloneFromA => quote!(#a.clone()),
+        }
+    });
+
+    match fields {
+        syn::Fields::Named(_) => {
+            let names: Vec<_> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+            let a_bindings = names.iter().enumerate().map(|(i, n)| {
+                let a = a_ident(i);
+                quote!(#n: #a)
+            });
+            let b_bindings = names.iter().enumerate().map(|(i, n)| {
+                let b = b_ident(i);
+                quote!(#n: #b)
+            });
+            let values = names.iter().zip(values).map(|(n, v)| quote!(#n: #v));
+            (
+                quote!(#path { #(#a_bindings),* }),
+                quote!(#path { #(#b_bindings),* }),
+                quote!(#path { #(#values),* }),
+            )
+        }
+        syn::Fields::Unnamed(_) => {
+            let a_bindings = (0..fields.len()).map(a_ident);
+            let b_bindings = (0..fields.len()).map(b_ident);
+            (
+                quote!(#path ( #(#a_bindings),* )),
+                quote!(#path ( #(#b_bindings),* )),
+                quote!(#path ( #(#values),* )),
+            )
+        }
+        syn::Fields::Unit => (quote!(#path), quote!(#path), quote!(#path)),
+    }
+}
+
+/// Derives the ToRaw trait for a struct where each field implements ToRaw, together with a
+/// `#[repr(C)]`, bytemuck-Pod raw twin struct that has the same name with a `Raw` suffix.
+/// For example the Struct:
+/// ```rust,ignore
+/// #[derive(ToRaw)]
+/// pub struct Light {
+///     pub color: Color,
+///     pub intensity: f32,
+/// }
+/// ```
+///
+/// Will get the raw struct and implementation:
+///
+/// ```rust,ignore
+/// #[repr(C)]
+/// #[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+/// pub struct LightRaw {
+///     pub color: <Color as ToRaw>::Raw,
+///     pub intensity: <f32 as ToRaw>::Raw,
+/// }
+///
+/// impl ToRaw for Light {
+///     type Raw = LightRaw;
+///     fn to_raw(&self) -> Self::Raw {
+///         LightRaw {
+///             color: self.color.to_raw(),
+///             intensity: self.intensity.to_raw(),
+///         }
+///     }
+/// }
+/// ```
+///
+/// Note: the raw struct must not contain any padding, otherwise the Pod derive fails to compile.
+/// Watch out for the alignment rules of wgsl, e.g. a vec3 is aligned like a vec4 in uniform buffers, the
+/// `WgslLayout` derive checks them.
+#[proc_macro_derive(ToRaw)]
+pub fn derive_to_raw(input: TokenStream) -> TokenStream {
     let derive_input: syn::DeriveInput = syn::parse(input).unwrap();
     let stru = match &derive_input.data {
         syn::Data::Struct(s) => s,
-        _ => panic!("Only derive Dependencies on structs"),
+        _ => panic!("ToRaw can only be derived on structs"),
     };
-    let stru_ident = derive_input.ident;
-    // let fields: Vec<TypePath>
-    // s.fields.iter().map(|e|)
+    if !derive_input.generics.params.is_empty() {
+        panic!("ToRaw cannot be derived on generic structs");
+    }
+    let vis = &derive_input.vis;
+    let stru_ident = &derive_input.ident;
+    let raw_ident = syn::Ident::new(&format!("{stru_ident}Raw"), stru_ident.span());
 
-    let lerp_impl_body = match &stru.fields {
+    let (raw_struct_body, to_raw_body) = match &stru.fields {
         syn::Fields::Named(_) => {
+            let raw_fields = stru.fields.iter().map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                let field_vis = &field.vis;
+                let ty = &field.ty;
+                quote!(#field_vis #ident : <#ty as ToRaw>::Raw)
+            });
             let field_iter = stru.fields.iter().map(|field| {
                 let ident = field.ident.as_ref().unwrap();
-                quote!(#ident : self.#ident.lerp(&other.#ident, factor))
+                quote!(#ident : self.#ident.to_raw())
             });
-            quote!(#stru_ident{#(#field_iter),*})
+            (
+                quote!({ #(#raw_fields),* }),
+                quote!(#raw_ident { #(#field_iter),* }),
+            )
         }
         syn::Fields::Unnamed(_) => {
-            let field_iter = stru
-                .fields
-                .iter()
-                .enumerate()
-                .map(|(i, _)| quote!(self.#i.lerp(&other.#i, factor)));
-            quote!(#stru_ident(#(#field_iter),*))
-        }
-        syn::Fields::Unit => {
-            quote!(#stru_ident)
+            let raw_fields = stru.fields.iter().map(|field| {
+                let field_vis = &field.vis;
+                let ty = &field.ty;
+                quote!(#field_vis <#ty as ToRaw>::Raw)
+            });
+            let field_iter = stru.fields.iter().enumerate().map(|(i, _)| {
+                let i = syn::Index::from(i);
+                quote!(self.#i.to_raw())
+            });
+            (
+                quote!(( #(#raw_fields),* );),
+                quote!(#raw_ident( #(#field_iter),* )),
+            )
         }
+        syn::Fields::Unit => panic!("ToRaw cannot be derived on unit structs"),
     };
 
     quote!(
-        impl Lerp for #stru_ident {
-            fn lerp(&self, other: &Self, factor: f32) -> Self {
-                #lerp_impl_body
+        #[repr(C)]
+        #[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+        #vis struct #raw_ident #raw_struct_body
+
+        impl ToRaw for #stru_ident {
+            type Raw = #raw_ident;
+
+            fn to_raw(&self) -> Self::Raw {
+                #to_raw_body
             }
         }
     )
     .into()
 }
+
+/// Derives the WgslLayout trait for a `#[repr(C)]` struct where each field implements WgslLayout, and checks at
+/// compile time that every field is at the offset the wgsl layout rules of uniform buffers require.
+/// For example:
+/// ```rust,ignore
+/// #[repr(C)]
+/// #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, WgslLayout)]
+/// struct Light {
+///     position: [f32; 3],
+///     color: [f32; 3], // error: a vec3 is aligned to 16 bytes, add a padding field before `color`.
+///     _pad: f32,
+/// }
+/// ```
+///
+/// The size of the struct also needs to match, so trailing padding has to be written out as fields too.
+/// Fields starting with an underscore are padding, they are not aligned and can be omitted in the shader.
+/// Structs that are used in arrays need `WgslArrayElement` too, which is implemented by hand.
+#[proc_macro_derive(WgslLayout)]
+pub fn derive_wgsl_layout(input: TokenStream) -> TokenStream {
+    let derive_input: syn::DeriveInput = syn::parse(input).unwrap();
+    let stru = match &derive_input.data {
+        syn::Data::Struct(s) => s,
+        _ => panic!("WgslLayout can only be derived on structs"),
+    };
+    if !derive_input.generics.params.is_empty() {
+        panic!("WgslLayout cannot be derived on generic structs");
+    }
+    let is_repr_c = derive_input.attrs.iter().any(|a| {
+        a.path().is_ident("repr")
+            && a.parse_nested_meta(|meta| {
+                if meta.path.is_ident("C") {
+                    Ok(())
+                } else {
+                    Err(meta.error("not C"))
+                }
+            })
+            .is_ok()
+    });
+    if !is_repr_c {
+        panic!("WgslLayout needs a #[repr(C)] struct");
+    }
+    let ident = &derive_input.ident;
+    let fields: Vec<(proc_macro2::TokenStream, String, &syn::Type)> = match &stru.fields {
+        syn::Fields::Named(_) => stru
+            .fields
+            .iter()
+            .map(|f| {
+                let name = f.ident.as_ref().unwrap();
+                (quote!(#name), name.to_string(), &f.ty)
+            })
+            .collect(),
+        syn::Fields::Unnamed(_) => stru
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                let index = syn::Index::from(i);
+                (quote!(#index), i.to_string(), &f.ty)
+            })
+            .collect(),
+        syn::Fields::Unit => panic!("WgslLayout cannot be derived on unit structs"),
+    };
+
+    let is_padding = |name: &str| name.starts_with('_');
+    let checks = fields.iter().map(|(access, name, ty)| {
+        if is_padding(name) {
+            return quote!(
+                offset = ::core::mem::offset_of!(#ident, #access) as u32
+                    + ::core::mem::size_of::<#ty>() as u32;
+            );
+        }
+        let message = format!(
+            "field `{name}` of `{ident}` is not at its wgsl offset, e.g. a vec3 is aligned to 16 bytes. Add padding before it."
+        );
+        quote!(
+            offset = offset.div_ceil(<#ty as WgslLayout>::ALIGN) * <#ty as WgslLayout>::ALIGN;
+            assert!(offset == ::core::mem::offset_of!(#ident, #access) as u32, #message);
+            offset += <#ty as WgslLayout>::SIZE;
+        )
+    });
+    let size_message = format!(
+        "size of `{ident}` differs from its wgsl size, add padding fields at the end to a multiple of its alignment."
+    );
+    let aligns = fields
+        .iter()
+        .filter(|(_, name, _)| !is_padding(name))
+        .map(|(_, _, ty)| quote!(<#ty as WgslLayout>::ALIGN));
+    let field_infos = fields.iter().filter(|(_, name, _)| !is_padding(name)).map(|(access, name, ty)| {
+        quote!((#name, ::core::mem::offset_of!(#ident, #access) as u32, <#ty as WgslLayout>::SIZE))
+    });
+
+    quote!(
+        impl WgslLayout for #ident {
+            // structs in uniform buffers are aligned to 16 bytes.
+            const ALIGN: u32 = 16;
+            const SIZE: u32 = {
+                let aligns: &[u32] = &[#(#aligns),*];
+                let mut align = 1;
+                let mut i = 0;
+                while i < aligns.len() {
+                    if aligns[i] > align {
+                        align = aligns[i];
+                    }
+                    i += 1;
+                }
+                let mut offset: u32 = 0;
+                #(#checks)*
+                let size = offset.div_ceil(align) * align;
+                assert!(size == ::core::mem::size_of::<#ident>() as u32, #size_message);
+                size
+            };
+            const FIELDS: &'static [(&'static str, u32, u32)] = &[#(#field_infos),*];
+        }
+
+        // evaluates the checks above, even if `SIZE` is never used.
+        const _: u32 = <#ident as WgslLayout>::SIZE;
+    )
+    .into()
+}
@@ -1,26 +1,95 @@
 use std::{
+    convert::Infallible,
+    fmt::Display,
     ops::{FromResidual, Try},
+    panic::{catch_unwind, AssertUnwindSafe},
     sync::Arc,
 };
 
 use winit::{
     dpi::PhysicalSize,
-    event::{Event, WindowEvent},
+    event::{DeviceEvent, Event, WindowEvent},
     event_loop::EventLoop,
     window::{Window, WindowBuilder},
 };
 
+use crate::modules::{alloc_scope, crash, CrashReport};
+
 pub enum UpdateFlow {
-    Exit(String),
+    Exit(ExitReason),
     Continue,
 }
 
+/// Why the app exits, returned by `WinitRunner::run`.
+///
+/// Strings convert into `ExitReason::Quit`, so `UpdateFlow::Exit("Close Requested".into())` works.
+#[derive(Debug)]
+pub enum ExitReason {
+    /// regular exit, e.g. the user closed the window.
+    Quit(String),
+    Error(anyhow::Error),
+    /// The app wants to be restarted. If `App::restart` does not restart it in process,
+    /// `WinitRunner::run` returns this, such that a launcher can restart the process.
+    Restart,
+    /// `App::update` panicked.
+    Crashed(Box<CrashReport>),
+}
+
+impl ExitReason {
+    /// Process exit code for launchers: 0 for quit, 1 for errors, 2 for restart requests and 101 (like an uncaught panic) for crashes.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ExitReason::Quit(_) => 0,
+            ExitReason::Error(_) => 1,
+            ExitReason::Restart => 2,
+            ExitReason::Crashed(_) => 101,
+        }
+    }
+}
+
+impl Display for ExitReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitReason::Quit(reason) => write!(f, "{reason}"),
+            ExitReason::Error(err) => write!(f, "Error: {err:?}"),
+            ExitReason::Restart => write!(f, "Restart requested"),
+            ExitReason::Crashed(report) => write!(f, "Crashed: {}", report.message),
+        }
+    }
+}
+
+impl From<&str> for ExitReason {
+    fn from(reason: &str) -> Self {
+        ExitReason::Quit(reason.to_string())
+    }
+}
+
+impl From<String> for ExitReason {
+    fn from(reason: String) -> Self {
+        ExitReason::Quit(reason)
+    }
+}
+
+impl From<anyhow::Error> for ExitReason {
+    fn from(err: anyhow::Error) -> Self {
+        ExitReason::Error(err)
+    }
+}
+
 impl FromResidual<UpdateFlow> for UpdateFlow {
     fn from_residual(residual: UpdateFlow) -> Self {
         residual
     }
 }
 
+/// `?` on errors in `App::update` exits with `ExitReason::Error`.
+impl<E: Into<anyhow::Error>> FromResidual<Result<Infallible, E>> for UpdateFlow {
+    fn from_residual(residual: Result<Infallible, E>) -> Self {
+        let Err(err) = residual;
+        UpdateFlow::Exit(ExitReason::Error(err.into()))
+    }
+}
+
 impl Try for UpdateFlow {
     type Output = ();
 
@@ -41,7 +110,27 @@ impl Try for UpdateFlow {
 pub trait App {
     fn receive_window_event(&mut self, event: &WindowEvent);
 
+    /// Device events are not bound to the window, e.g. raw mouse motion. See `Input::receive_device_event`.
+    fn receive_device_event(&mut self, _event: &DeviceEvent) {}
+
     fn update(&mut self) -> UpdateFlow;
+
+    /// Called once when the event loop exits, e.g. after `update` returned `UpdateFlow::Exit`.
+    /// Save state here and call `DefaultModules::shutdown`, everything is still alive at this point.
+    fn shutdown(&mut self) {}
+
+    /// Called every frame instead of `update` after `update` panicked. Install `crash::install_panic_hook`
+    /// to get a backtrace and log lines in the report. Return `DefaultModules::crash_screen` to show the report,
+    /// by default the app exits right away. If this panics too, the app exits.
+    fn crashed(&mut self, report: &CrashReport) -> UpdateFlow {
+        UpdateFlow::Exit(ExitReason::Crashed(Box::new(report.clone())))
+    }
+
+    /// Called when `update` returned `UpdateFlow::Exit(ExitReason::Restart)`. Return true if the app restarted in
+    /// process (e.g. with `DefaultModules::restart`), otherwise the runner exits with `ExitReason::Restart`.
+    fn restart(&mut self) -> anyhow::Result<bool> {
+        Ok(false)
+    }
 }
 
 pub struct WinitConfig {
@@ -92,8 +181,13 @@ impl WinitRunner {
         Self { event_loop, window }
     }
 
-    pub fn run(self, app: &mut dyn App) -> anyhow::Result<()> {
+    /// Runs until the app exits and returns why. Errors if the event loop itself failed.
+    pub fn run(self, app: &mut dyn App) -> anyhow::Result<ExitReason> {
         let window = self.window.clone();
+        // set if `App::update` panicked.
+        let mut crashed: Option<CrashReport> = None;
+        let mut exit_reason: Option<ExitReason> = None;
+        let exit_reason_mut = &mut exit_reason;
         self.event_loop.run(move |event, window_target| {
             // check what kinds of events received:
             match &event {
@@ -107,24 +201,66 @@ impl WinitRunner {
 
                     if matches!(event, WindowEvent::RedrawRequested) {
                         //  this is called every frame:
-                        match app.update() {
-                            UpdateFlow::Exit(reason) => {
-                                println!("Exit: {reason}");
-                                window_target.exit();
+                        let flow = match &crashed {
+                            None => catch_unwind(AssertUnwindSafe(|| {
+                                // allocations outside of the scopes of the modules are made by the app.
+                                let _scope = alloc_scope("app");
+                                app.update()
+                            })),
+                            Some(report) => catch_unwind(AssertUnwindSafe(|| app.crashed(report))),
+                        };
+                        let flow = match flow {
+                            Ok(flow) => flow,
+                            Err(_) if crashed.is_some() => {
+                                log::error!("App::crashed panicked");
+                                let report = crashed.take().unwrap();
+                                UpdateFlow::Exit(ExitReason::Crashed(Box::new(report)))
+                            }
+                            Err(payload) => {
+                                let report = crash::take_report().unwrap_or_else(|| {
+                                    let message = payload
+                                        .downcast_ref::<&str>()
+                                        .map(|s| s.to_string())
+                                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                                        .unwrap_or_default();
+                                    CrashReport::from_message(message)
+                                });
+                                crashed = Some(report);
+                                window.request_redraw();
+                                return;
+                            }
+                        };
+                        let reason = match flow {
+                            UpdateFlow::Continue => {
+                                window.request_redraw();
+                                return;
                             }
-                            UpdateFlow::Continue => window.request_redraw(),
-                        }
+                            UpdateFlow::Exit(ExitReason::Restart) => match app.restart() {
+                                Ok(true) => {
+                                    log::info!("App restarted");
+                                    crashed = None;
+                                    window.request_redraw();
+                                    return;
+                                }
+                                Ok(false) => ExitReason::Restart,
+                                Err(err) => ExitReason::Error(err.context("App::restart failed")),
+                            },
+                            UpdateFlow::Exit(reason) => reason,
+                        };
+                        println!("Exit: {reason}");
+                        *exit_reason_mut = Some(reason);
+                        window_target.exit();
                     }
                 }
-                Event::DeviceEvent { .. } => {}
+                Event::DeviceEvent { event, .. } => app.receive_device_event(event),
                 Event::UserEvent(_) => {}
                 Event::Suspended => {}
                 Event::Resumed => {}
                 Event::AboutToWait => {}
-                Event::LoopExiting => {}
+                Event::LoopExiting => app.shutdown(),
                 Event::MemoryWarning => {}
             }
         })?;
-        Ok(())
+        Ok(exit_reason.unwrap_or_else(|| "Event loop exited".into()))
     }
 }
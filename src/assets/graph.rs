@@ -0,0 +1,221 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+};
+
+use crate::utils::watcher::FileChangeWatcher;
+
+use super::{AssetSource, AssetT};
+
+/// Which assets depend on which other assets, e.g. a material on its textures or a tilemap on its tileset images.
+///
+/// Register loaded assets with `register` (which asks the loader for `AssetT::dependencies`) or add edges manually
+/// with `add_dependency`. When an asset changes, `reload_order` returns it together with everything that depends on it,
+/// directly or indirectly, such that every asset comes after all of its changed dependencies.
+///
+/// `DefaultModules::assets` is polled in `begin_frame`, reload what it reports and register it again:
+/// ```rust,ignore
+/// for source in modules.assets.changed().to_vec() {
+///     // tileset images first, then the tilemaps using them.
+///     let map = Tilemap::load_tmx(path_of(&source))?;
+///     modules.assets.register(source, &map);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct AssetGraph {
+    /// dependency -> assets that depend on it.
+    dependents: HashMap<AssetSource, Vec<AssetSource>>,
+    /// asset -> its dependencies.
+    dependencies: HashMap<AssetSource, Vec<AssetSource>>,
+    /// watches the files of all registered assets, recreated when the set of files changes.
+    watcher: Option<FileChangeWatcher>,
+    watched_files: HashSet<PathBuf>,
+    /// result of the last `update`.
+    changed: Vec<AssetSource>,
+}
+
+impl AssetGraph {
+    pub fn new() -> Self {
+        AssetGraph::default()
+    }
+
+    /// Replaces the dependencies of `source` with the ones the loaded asset declares.
+    pub fn register<T: AssetT>(&mut self, source: AssetSource, asset: &T) {
+        self.set_dependencies(source, asset.dependencies());
+    }
+
+    /// Replaces the dependencies of `source`, e.g. after it was reloaded and references other files now.
+    pub fn set_dependencies(&mut self, source: AssetSource, dependencies: Vec<AssetSource>) {
+        self.remove(&source);
+        for dependency in dependencies.iter() {
+            let dependents = self.dependents.entry(dependency.clone()).or_default();
+            if !dependents.contains(&source) {
+                dependents.push(source.clone());
+            }
+        }
+        self.dependencies.insert(source, dependencies);
+    }
+
+    pub fn add_dependency(&mut self, source: AssetSource, dependency: AssetSource) {
+        let dependencies = self.dependencies.entry(source.clone()).or_default();
+        if dependencies.contains(&dependency) {
+            return;
+        }
+        dependencies.push(dependency.clone());
+        self.dependents.entry(dependency).or_default().push(source);
+    }
+
+    /// Removes the edges to the dependencies of `source`. Assets depending on `source` keep their edges.
+    pub fn remove(&mut self, source: &AssetSource) {
+        let Some(dependencies) = self.dependencies.remove(source) else {
+            return;
+        };
+        for dependency in dependencies.iter() {
+            if let Some(dependents) = self.dependents.get_mut(dependency) {
+                dependents.retain(|d| d != source);
+                if dependents.is_empty() {
+                    self.dependents.remove(dependency);
+                }
+            }
+        }
+    }
+
+    pub fn dependencies(&self, source: &AssetSource) -> &[AssetSource] {
+        self.dependencies.get(source).map_or(&[], |d| d.as_slice())
+    }
+
+    pub fn dependents(&self, source: &AssetSource) -> &[AssetSource] {
+        self.dependents.get(source).map_or(&[], |d| d.as_slice())
+    }
+
+    /// The `changed` assets and all of their transitive dependents, each one after all of its changed dependencies.
+    /// Assets in a dependency cycle are reloaded once, in the order they were discovered.
+    pub fn reload_order(&self, changed: &[AssetSource]) -> Vec<AssetSource> {
+        // everything that needs a reload, in discovery order.
+        let mut affected: Vec<&AssetSource> = vec![];
+        let mut seen: HashSet<&AssetSource> = HashSet::new();
+        let mut queue: VecDeque<&AssetSource> = changed.iter().collect();
+        while let Some(source) = queue.pop_front() {
+            if !seen.insert(source) {
+                continue;
+            }
+            affected.push(source);
+            queue.extend(self.dependents(source));
+        }
+
+        // kahn's algorithm on the affected part of the graph.
+        let mut pending: HashMap<&AssetSource, usize> = affected
+            .iter()
+            .map(|s| {
+                let count = self
+                    .dependencies(s)
+                    .iter()
+                    .filter(|d| seen.contains(d))
+                    .count();
+                (*s, count)
+            })
+            .collect();
+        let mut order: Vec<AssetSource> = Vec::with_capacity(affected.len());
+        let mut done: HashSet<&AssetSource> = HashSet::new();
+        while order.len() < affected.len() {
+            let next = affected
+                .iter()
+                .find(|s| !done.contains(*s) && pending[*s] == 0)
+                .copied();
+            let next = match next {
+                Some(next) => next,
+                None => {
+                    let next = affected.iter().find(|s| !done.contains(*s)).unwrap();
+                    log::warn!("asset dependency cycle at {next:?}");
+                    next
+                }
+            };
+            done.insert(next);
+            for dependent in self.dependents(next) {
+                if let Some(count) = pending.get_mut(dependent) {
+                    *count = count.saturating_sub(1);
+                }
+            }
+            order.push(next.clone());
+        }
+        order
+    }
+
+    /// Polls the file changes for `changed`. Call once per frame.
+    pub fn update(&mut self) {
+        self.changed = self.poll_file_changes();
+    }
+
+    /// The assets to reload in this frame, in `reload_order`.
+    pub fn changed(&self) -> &[AssetSource] {
+        &self.changed
+    }
+
+    /// Changed files of registered assets and their dependencies, plus all transitive dependents, in `reload_order`.
+    pub fn poll_file_changes(&mut self) -> Vec<AssetSource> {
+        let files: HashSet<PathBuf> = self
+            .dependencies
+            .keys()
+            .chain(self.dependents.keys())
+            .filter_map(|s| match s {
+                AssetSource::File(path) => Some(path.clone()),
+                AssetSource::Url(_) => None,
+            })
+            .collect();
+        if self.watcher.is_none() || files != self.watched_files {
+            let paths: Vec<&str> = files.iter().filter_map(|f| f.to_str()).collect();
+            self.watcher = Some(FileChangeWatcher::new(&paths));
+            self.watched_files = files;
+        }
+        let Some(changed) = self.watcher.as_ref().unwrap().check_for_changes() else {
+            return vec![];
+        };
+        let changed: Vec<AssetSource> = changed
+            .into_iter()
+            .map(|p| AssetSource::File(p.clone()))
+            .collect();
+        self.reload_order(&changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dependents_reload_after_their_dependencies() {
+        let src = |s: &str| AssetSource::from(s);
+        let mut graph = AssetGraph::new();
+        graph.set_dependencies(src("material"), vec![src("albedo.png"), src("normal.png")]);
+        graph.set_dependencies(src("scene"), vec![src("material"), src("normal.png")]);
+        graph.add_dependency(src("theme"), src("font.ttf"));
+
+        assert_eq!(
+            graph.reload_order(&[src("normal.png")]),
+            vec![src("normal.png"), src("material"), src("scene")]
+        );
+        assert_eq!(graph.reload_order(&[src("font.ttf")]).len(), 2);
+
+        // the scene does not use the normal map anymore, but still the material.
+        graph.set_dependencies(src("scene"), vec![src("material")]);
+        assert_eq!(graph.dependents(&src("normal.png")), &[src("material")]);
+        assert_eq!(graph.reload_order(&[src("scene")]), vec![src("scene")]);
+    }
+
+    #[test]
+    fn watches_replaced_files() {
+        let src = |s: &str| AssetSource::from(s);
+        let mut graph = AssetGraph::new();
+        graph.set_dependencies(src("map.tmx"), vec![src("tiles.png")]);
+        graph.update();
+        assert!(graph.changed().is_empty());
+
+        // same number of files, but a different image.
+        graph.set_dependencies(src("map.tmx"), vec![src("other_tiles.png")]);
+        graph.update();
+        assert!(graph
+            .watched_files
+            .contains(&PathBuf::from("other_tiles.png")));
+        assert!(!graph.watched_files.contains(&PathBuf::from("tiles.png")));
+    }
+}
@@ -19,6 +19,8 @@ use std::{
 
 pub mod ptrs;
 pub use ptrs::{OwnedPtr, Ptr};
+pub mod graph;
+pub use graph::AssetGraph;
 
 use anyhow::anyhow;
 use image::RgbaImage;
@@ -34,6 +36,13 @@ pub trait AssetT: Sized {
             src.fetch().await
         }
     }
+
+    /// Other assets this one was built from, e.g. the images of a tileset. Used by the `AssetGraph` to reload
+    /// this asset when one of them changes. Only `Tilemap` has dependencies so far, materials are created from wgsl
+    /// strings and the ui takes fonts as `Ptr<Font>`, there is no theme asset.
+    fn dependencies(&self) -> Vec<AssetSource> {
+        vec![]
+    }
 }
 
 impl AssetT for RgbaImage {
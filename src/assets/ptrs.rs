@@ -8,25 +8,40 @@ use std::{
 #[derive(Debug)]
 pub struct OwnedPtr<T> {
     _inner: Box<T>,
+    #[cfg(debug_assertions)]
+    epoch: epochs::Epoch,
 }
 
 impl<T> OwnedPtr<T> {
     /// todo! add `new_in` custom allocator
     pub fn new(value: T) -> Self {
+        let _inner = Box::new(value);
         OwnedPtr {
-            _inner: Box::new(value),
+            #[cfg(debug_assertions)]
+            epoch: epochs::register(),
+            _inner,
         }
     }
 
     /// Warning! The Own<T> will be deallocated when dropped. Manually make sure all Ref<T> given out to it are not around anymore by then.
+    /// In debug builds, using a Ptr after that panics instead of reading freed memory.
     #[inline]
     pub fn ptr(&self) -> Ptr<T> {
         Ptr {
-            _inner: unsafe { std::mem::transmute(&*self._inner) },
+            _inner: NonNull::from(&*self._inner),
+            #[cfg(debug_assertions)]
+            epoch: self.epoch,
         }
     }
 }
 
+#[cfg(debug_assertions)]
+impl<T> Drop for OwnedPtr<T> {
+    fn drop(&mut self) {
+        epochs::unregister(self.epoch);
+    }
+}
+
 impl<T> Deref for OwnedPtr<T> {
     type Target = T;
 
@@ -58,9 +73,14 @@ impl<T> BorrowMut<T> for OwnedPtr<T> {
 /// Then you give out as many Refs as you want but make sure that they are no longer in circulation when you end the program / go to the next stage.
 ///
 /// Better than lifetime hell in games.
+///
+/// In debug builds every Ptr remembers the epoch of the OwnedPtr it came from. Dereferencing a Ptr whose OwnedPtr
+/// was dropped or replaced panics, even if the memory was reused by a new OwnedPtr since.
 #[derive(Debug)]
 pub struct Ptr<T> {
     _inner: NonNull<T>,
+    #[cfg(debug_assertions)]
+    epoch: epochs::Epoch,
 }
 
 use std::hash::Hash;
@@ -72,7 +92,7 @@ impl<T> Hash for Ptr<T> {
 
 impl<T> Borrow<T> for Ptr<T> {
     fn borrow(&self) -> &T {
-        unsafe { &*self._inner.as_ptr() }
+        self
     }
 }
 
@@ -80,15 +100,20 @@ impl<T> Deref for Ptr<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
+        #[cfg(debug_assertions)]
+        if !epochs::is_alive(self.epoch) {
+            panic!(
+                "Ptr<{}> used after its OwnedPtr was dropped",
+                std::any::type_name::<T>()
+            );
+        }
         unsafe { &*self._inner.as_ptr() }
     }
 }
 
 impl<T> Clone for Ptr<T> {
     fn clone(&self) -> Self {
-        Self {
-            _inner: self._inner.clone(),
-        }
+        *self
     }
 }
 
@@ -102,7 +127,20 @@ impl<T> Ptr<T> {
     pub fn eternal(value: T) -> Ptr<T> {
         let reference = Box::leak(Box::new(value));
         let _inner = NonNull::from(reference);
-        Ptr { _inner }
+        Ptr {
+            _inner,
+            // never unregistered.
+            #[cfg(debug_assertions)]
+            epoch: epochs::register(),
+        }
+    }
+
+    /// False if the OwnedPtr this Ptr came from was dropped. Always true in release builds, where it is not tracked.
+    pub fn is_alive(&self) -> bool {
+        #[cfg(debug_assertions)]
+        return epochs::is_alive(self.epoch);
+        #[cfg(not(debug_assertions))]
+        true
     }
 
     pub fn as_u64_hash(&self) -> u64 {
@@ -130,3 +168,62 @@ impl<T> Ord for Ptr<T> {
 }
 
 impl<T> Eq for Ptr<T> {}
+
+/// Debug builds only: every OwnedPtr holds a generation slot, dropping it bumps the generation of the slot.
+/// A Ptr remembers the slot and generation of its OwnedPtr, so checking it on deref is a single atomic load.
+/// Slots are reused by later OwnedPtrs, which start at the bumped generation, so old Ptrs stay invalid.
+#[cfg(debug_assertions)]
+mod epochs {
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    };
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct Epoch {
+        slot: &'static AtomicU64,
+        generation: u64,
+    }
+
+    /// slots of dropped OwnedPtrs. Slots are never freed, there are at most as many as OwnedPtrs alive at once.
+    static FREE_SLOTS: Mutex<Vec<&'static AtomicU64>> = Mutex::new(Vec::new());
+
+    pub fn register() -> Epoch {
+        let slot = FREE_SLOTS
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| Box::leak(Box::new(AtomicU64::new(0))));
+        Epoch {
+            slot,
+            generation: slot.load(Ordering::Acquire),
+        }
+    }
+
+    pub fn unregister(epoch: Epoch) {
+        epoch.slot.fetch_add(1, Ordering::Release);
+        FREE_SLOTS.lock().unwrap().push(epoch.slot);
+    }
+
+    pub fn is_alive(epoch: Epoch) -> bool {
+        epoch.slot.load(Ordering::Acquire) == epoch.generation
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::OwnedPtr;
+
+    #[test]
+    fn dangling_ptrs_are_detected() {
+        let owned = OwnedPtr::new(1u32);
+        let ptr = owned.ptr();
+        assert_eq!(*ptr, 1);
+        drop(owned);
+        assert!(!ptr.is_alive());
+        // the slot is reused by the next OwnedPtr, the old Ptr stays dangling.
+        let owned = OwnedPtr::new(2u32);
+        assert!(!ptr.is_alive());
+        assert!(owned.ptr().is_alive());
+    }
+}
@@ -0,0 +1,177 @@
+use std::f32::consts::PI;
+
+use glam::{vec3, Vec3};
+
+use crate::{
+    elements::{Camera3d, Transform},
+    modules::{DefaultModules, Time},
+};
+
+/// A camera that follows a target transform, with damping, look-ahead, a dead zone and trauma based shake.
+///
+/// Set `target` every frame (e.g. to the player transform) and call `update`.
+pub struct CameraRig {
+    pub target: Transform,
+    /// Offset of the camera from the focus point, in the local space of the target.
+    pub offset: Vec3,
+    /// Higher values make the camera position follow faster. 0.0 means no movement at all.
+    pub position_damping: f32,
+    /// Higher values make the camera rotation follow faster.
+    pub rotation_damping: f32,
+    /// How many seconds of the target velocity the focus point is moved ahead of the target.
+    pub look_ahead: f32,
+    /// The focus point does not move as long as the target stays within this radius around it.
+    pub dead_zone: f32,
+    pub shake: ShakeSettings,
+    /// in range 0.0 to 1.0
+    trauma: f32,
+    focus: Vec3,
+    pos: Vec3,
+    pitch: f32,
+    yaw: f32,
+    last_target_pos: Option<Vec3>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ShakeSettings {
+    /// trauma lost per second.
+    pub trauma_decay: f32,
+    /// maximum angle (radians) that pitch and yaw are offset by at full trauma.
+    pub max_angle: f32,
+    /// maximum positional offset at full trauma.
+    pub max_offset: f32,
+    /// how fast the shake noise changes.
+    pub frequency: f32,
+}
+
+impl Default for ShakeSettings {
+    fn default() -> Self {
+        Self {
+            trauma_decay: 1.0,
+            max_angle: 0.1,
+            max_offset: 0.3,
+            frequency: 15.0,
+        }
+    }
+}
+
+impl CameraRig {
+    pub fn new(target: Transform, offset: Vec3) -> Self {
+        let focus = target.position;
+        let pos = focus + target.rotation * offset;
+        let (pitch, yaw) = pitch_yaw_looking_at(pos, focus);
+        CameraRig {
+            target,
+            offset,
+            position_damping: 5.0,
+            rotation_damping: 10.0,
+            look_ahead: 0.0,
+            dead_zone: 0.0,
+            shake: ShakeSettings::default(),
+            trauma: 0.0,
+            focus,
+            pos,
+            pitch,
+            yaw,
+            last_target_pos: None,
+        }
+    }
+
+    /// Adds trauma in range 0.0 to 1.0. The shake amount is trauma squared, so small hits barely shake the camera.
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).clamp(0.0, 1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Teleports the camera to its desired position, without any damping. Useful after the target was teleported.
+    pub fn snap(&mut self) {
+        self.focus = self.target.position;
+        self.pos = self.focus + self.target.rotation * self.offset;
+        (self.pitch, self.yaw) = pitch_yaw_looking_at(self.pos, self.focus);
+        self.last_target_pos = None;
+    }
+
+    pub fn update(&mut self, deps: &mut DefaultModules) {
+        self.update2(&deps.time, &mut deps.camera)
+    }
+
+    pub fn update2(&mut self, time: &Time, camera: &mut Camera3d) {
+        let delta_time = time.delta().as_secs_f32();
+
+        // look ahead in the direction the target is moving:
+        let target_pos = self.target.position;
+        let velocity = match self.last_target_pos {
+            Some(last) if delta_time > 0.0 => (target_pos - last) / delta_time,
+            _ => Vec3::ZERO,
+        };
+        self.last_target_pos = Some(target_pos);
+        let desired_focus = target_pos + velocity * self.look_ahead;
+
+        // only move the focus point if the desired focus leaves the dead zone:
+        let to_desired = desired_focus - self.focus;
+        let distance = to_desired.length();
+        if distance > self.dead_zone {
+            let goal = desired_focus - to_desired / distance * self.dead_zone;
+            self.focus = self
+                .focus
+                .lerp(goal, damp_factor(self.position_damping, delta_time));
+        }
+
+        let desired_pos = self.focus + self.target.rotation * self.offset;
+        self.pos = self
+            .pos
+            .lerp(desired_pos, damp_factor(self.position_damping, delta_time));
+
+        let (desired_pitch, desired_yaw) = pitch_yaw_looking_at(self.pos, self.focus);
+        let rot_factor = damp_factor(self.rotation_damping, delta_time);
+        self.pitch += angle_diff(self.pitch, desired_pitch) * rot_factor;
+        self.yaw += angle_diff(self.yaw, desired_yaw) * rot_factor;
+
+        // shake:
+        self.trauma = (self.trauma - self.shake.trauma_decay * delta_time).max(0.0);
+        let shake = self.trauma * self.trauma;
+        let t = time.total().as_secs_f32() * self.shake.frequency;
+
+        let cam = &mut camera.transform;
+        cam.pos = self.pos
+            + vec3(noise(t, 0.0), noise(t, 1.0), noise(t, 2.0)) * self.shake.max_offset * shake;
+        cam.pitch = self.pitch + noise(t, 3.0) * self.shake.max_angle * shake;
+        cam.yaw = self.yaw + noise(t, 4.0) * self.shake.max_angle * shake;
+    }
+}
+
+/// frame rate independent lerp factor for exponential damping.
+fn damp_factor(damping: f32, delta_time: f32) -> f32 {
+    1.0 - (-damping * delta_time).exp()
+}
+
+/// shortest signed difference from angle `a` to angle `b`.
+fn angle_diff(a: f32, b: f32) -> f32 {
+    let diff = (b - a).rem_euclid(2.0 * PI);
+    if diff > PI {
+        diff - 2.0 * PI
+    } else {
+        diff
+    }
+}
+
+/// pitch and yaw for a `Camera3DTransform` at `from` looking at `to`.
+fn pitch_yaw_looking_at(from: Vec3, to: Vec3) -> (f32, f32) {
+    let dir = (to - from).normalize_or_zero();
+    if dir == Vec3::ZERO {
+        return (0.0, 0.0);
+    }
+    let pitch = dir.y.clamp(-1.0, 1.0).asin();
+    let yaw = dir.z.atan2(dir.x);
+    (pitch, yaw)
+}
+
+/// Cheap smooth noise in range -1.0 to 1.0, different seeds give uncorrelated looking curves.
+fn noise(t: f32, seed: f32) -> f32 {
+    let s = seed * 17.31;
+    ((t + s).sin() * 0.5 + (t * 2.13 + s * 1.7).sin() * 0.3 + (t * 4.37 + s * 2.9).sin() * 0.2)
+        .clamp(-1.0, 1.0)
+}
@@ -1,6 +1,6 @@
 use crate::{
     elements::Camera3d,
-    modules::{DefaultModules, Input, Time},
+    modules::{input::CursorGrab, DefaultModules, Input, Plugin, Time},
 };
 
 pub struct FlyCam;
@@ -19,7 +19,7 @@ impl FlyCam {
         const SPEED: f32 = 10.0;
         const ANGLE_SPEED: f32 = 1.8;
 
-        let delta_time = time.delta().as_secs_f32();
+        let delta_time = time.real_delta().as_secs_f32();
         let cam = &mut camera.transform;
         cam.pos += cam.forward() * wasd.y * SPEED * delta_time;
         cam.pos += cam.right() * wasd.x * SPEED * delta_time;
@@ -27,5 +27,31 @@ impl FlyCam {
 
         cam.pitch += arrows.y * ANGLE_SPEED * delta_time;
         cam.yaw += arrows.x * ANGLE_SPEED * delta_time;
+
+        // mouse look, only while the cursor is locked (see `Input::set_cursor_grab`):
+        const MOUSE_SENSITIVITY: f32 = 0.002;
+        if input.cursor_grab() == CursorGrab::Locked {
+            let mouse = input.raw_mouse_delta();
+            cam.yaw += mouse.x * MOUSE_SENSITIVITY;
+            cam.pitch -= mouse.y * MOUSE_SENSITIVITY;
+        }
+
+        // touch: pinch to move forward/backward, two finger pan to move sideways/up/down.
+        const TOUCH_PAN_SPEED: f32 = 0.02;
+        const TOUCH_PINCH_SPEED: f32 = 10.0;
+        let touches = input.touches();
+        if let Some(scale) = touches.pinch_scale() {
+            cam.pos += cam.forward() * (scale - 1.0) * TOUCH_PINCH_SPEED;
+        }
+        if let Some(pan) = touches.pan_delta() {
+            cam.pos -= cam.right() * pan.x * TOUCH_PAN_SPEED;
+            cam.pos.y += pan.y * TOUCH_PAN_SPEED;
+        }
+    }
+}
+
+impl Plugin for FlyCam {
+    fn begin_frame(&mut self, mods: &mut DefaultModules) {
+        self.update(mods);
     }
 }
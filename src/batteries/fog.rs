@@ -0,0 +1,333 @@
+use glam::Mat4;
+use wgpu::{FragmentState, MultisampleState, PrimitiveState, ShaderModuleDescriptor, VertexState};
+
+use crate::{
+    elements::{Color, UniformBuffer, WgslLayout},
+    modules::{
+        renderer::{
+            HdrTexture, RenderTarget, RenderTargetBinding, RenderTargets, HDR_COLOR_FORMAT,
+        },
+        DefaultModules, GraphicsContext, Plugin,
+    },
+    GpuRecreated,
+};
+
+/// How the fog gets denser with the distance to the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FogFalloff {
+    /// no fog before `start`, full fog after `end`.
+    Linear { start: f32, end: f32 },
+    /// `1 - e^(-density * distance)`.
+    Exponential { density: f32 },
+}
+
+/// Fog that is densest below `height` and thins out above it, e.g. for valleys and swamps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeightFog {
+    pub height: f32,
+    /// density at `height`.
+    pub density: f32,
+    /// how fast the density decreases above `height`, and increases below it.
+    pub falloff: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FogSettings {
+    /// the alpha is the maximum opacity of the fog.
+    pub color: Color,
+    pub falloff: FogFalloff,
+    /// added on top of the distance fog.
+    pub height_fog: Option<HeightFog>,
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        FogSettings {
+            color: Color::new(0.6, 0.65, 0.7),
+            falloff: FogFalloff::Exponential { density: 0.02 },
+            height_fog: None,
+        }
+    }
+}
+
+/// Distance and height fog, applied as a post effect that reads the depth buffer (`RenderTargets::DEPTH`) and blends
+/// the fog color over the hdr texture. Add it with `DefaultModules::add_plugin`, its position in the post effect chain
+/// can be changed in `DefaultModules::post_effects` (running it before bloom makes bright lights glow through fog).
+///
+/// The background (where nothing was drawn) is not fogged, set the clear color (or the sky horizon) to the fog color
+/// for a seamless look. Uses the main `Camera3d`, split screen views are fogged with the distances of the main camera.
+pub struct Fog {
+    pub settings: FogSettings,
+    gpu: Option<FogGpu>,
+    depth: RenderTargetBinding,
+    raw: FogRaw,
+}
+
+impl Fog {
+    pub fn new(settings: FogSettings) -> Self {
+        Fog {
+            settings,
+            gpu: None,
+            depth: RenderTargetBinding::new(RenderTargets::DEPTH),
+            raw: FogRaw::zeroed(),
+        }
+    }
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Fog::new(FogSettings::default())
+    }
+}
+
+impl Plugin for Fog {
+    fn initialize(&mut self, mods: &mut DefaultModules) {
+        self.gpu = Some(FogGpu::new(&mods.ctx));
+    }
+
+    fn deinitialize(&mut self, _mods: &mut DefaultModules) {
+        self.gpu = None;
+        self.depth = RenderTargetBinding::new(RenderTargets::DEPTH);
+    }
+
+    fn gpu_recreated(&mut self, ctx: &GraphicsContext, event: GpuRecreated) {
+        if event.device_lost {
+            self.gpu = Some(FogGpu::new(ctx));
+            self.depth = RenderTargetBinding::new(RenderTargets::DEPTH);
+        }
+    }
+
+    fn begin_frame(&mut self, mods: &mut DefaultModules) {
+        let camera = &mods.camera;
+        let view_proj = camera.projection.calc_matrix() * camera.transform.calc_matrix();
+        self.raw = FogRaw::new(
+            &self.settings,
+            view_proj.inverse(),
+            camera.transform.position().extend(1.0).into(),
+        );
+
+        let Some(gpu) = &mut self.gpu else {
+            return;
+        };
+        // the depth texture is multisampled if msaa is on, which needs another shader.
+        if let Some(target) = mods.render_targets.get(RenderTargets::DEPTH) {
+            if gpu.pipeline.as_ref().map(|e| e.sample_count) != Some(target.sample_count) {
+                gpu.pipeline = Some(DepthPipeline::new(&mods.ctx, &gpu.fog_layout, target));
+                self.depth = RenderTargetBinding::new(RenderTargets::DEPTH);
+            }
+        }
+        if let Some(pipeline) = &gpu.pipeline {
+            let device = &mods.ctx.device;
+            self.depth.get(&mods.render_targets, |target| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Fog Depth BindGroup"),
+                    layout: &pipeline.depth_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&target.view),
+                    }],
+                })
+            });
+        }
+    }
+
+    fn prepare(
+        &mut self,
+        _device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _encoder: &mut wgpu::CommandEncoder,
+    ) {
+        if let Some(gpu) = &mut self.gpu {
+            gpu.uniform.update_and_prepare(self.raw, queue);
+        }
+    }
+
+    fn post_process(&mut self, encoder: &mut wgpu::CommandEncoder, hdr: &HdrTexture) {
+        let (Some(gpu), Some(depth)) = (&self.gpu, self.depth.current()) else {
+            return;
+        };
+        let Some(pipeline) = &gpu.pipeline else {
+            return;
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Fog"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: hdr.view(),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&pipeline.pipeline);
+        render_pass.set_bind_group(0, depth, &[]);
+        render_pass.set_bind_group(1, &gpu.fog_bind_group, &[]);
+        // one triangle covering the screen.
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+struct FogGpu {
+    uniform: UniformBuffer<FogRaw>,
+    fog_layout: wgpu::BindGroupLayout,
+    fog_bind_group: wgpu::BindGroup,
+    /// created when the depth target is known.
+    pipeline: Option<DepthPipeline>,
+}
+
+impl FogGpu {
+    fn new(ctx: &GraphicsContext) -> Self {
+        let device = &ctx.device;
+        let uniform = UniformBuffer::new(FogRaw::zeroed(), device);
+        let fog_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Fog Settings BindGroupLayout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let fog_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Fog Settings BindGroup"),
+            layout: &fog_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.buffer().as_entire_binding(),
+            }],
+        });
+        FogGpu {
+            uniform,
+            fog_layout,
+            fog_bind_group,
+            pipeline: None,
+        }
+    }
+}
+
+struct DepthPipeline {
+    sample_count: u32,
+    depth_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl DepthPipeline {
+    fn new(
+        ctx: &GraphicsContext,
+        fog_layout: &wgpu::BindGroupLayout,
+        depth: &RenderTarget,
+    ) -> Self {
+        let device = &ctx.device;
+        let multisampled = depth.sample_count > 1;
+        let depth_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Fog Depth BindGroupLayout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled,
+                },
+                count: None,
+            }],
+        });
+
+        // the depth is only loaded per pixel, for msaa from the first sample.
+        let wgsl = include_str!("fog.wgsl");
+        let wgsl = if multisampled {
+            wgsl.replace("texture_depth_2d", "texture_depth_multisampled_2d")
+        } else {
+            wgsl.to_string()
+        };
+        UniformBuffer::<FogRaw>::debug_check_layout(&wgsl, 1, 0);
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Fog ShaderModule"),
+            source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Fog PipelineLayout"),
+            bind_group_layouts: &[&depth_layout, fog_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Fog Pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_COLOR_FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::COLOR,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+        });
+        DepthPipeline {
+            sample_count: depth.sample_count,
+            depth_layout,
+            pipeline,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable, WgslLayout)]
+struct FogRaw {
+    inv_view_proj: [[f32; 4]; 4],
+    camera_pos: [f32; 4],
+    color: Color,
+    /// start and end, or density and unused.
+    falloff: [f32; 2],
+    /// 0 for linear, 1 for exponential.
+    mode: f32,
+    /// 0 or 1.
+    height_fog: f32,
+    /// height, density, falloff.
+    height: [f32; 3],
+    _pad: f32,
+}
+
+impl FogRaw {
+    fn zeroed() -> Self {
+        bytemuck::Zeroable::zeroed()
+    }
+
+    fn new(settings: &FogSettings, inv_view_proj: Mat4, camera_pos: [f32; 4]) -> Self {
+        let (mode, falloff) = match settings.falloff {
+            FogFalloff::Linear { start, end } => (0.0, [start, end.max(start + 0.0001)]),
+            FogFalloff::Exponential { density } => (1.0, [density, 0.0]),
+        };
+        let (height_fog, height) = match settings.height_fog {
+            Some(h) => (1.0, [h.height, h.density, h.falloff.max(0.0001)]),
+            None => (0.0, [0.0; 3]),
+        };
+        FogRaw {
+            inv_view_proj: inv_view_proj.to_cols_array_2d(),
+            camera_pos,
+            color: settings.color,
+            falloff,
+            mode,
+            height_fog,
+            height,
+            _pad: 0.0,
+        }
+    }
+}
@@ -0,0 +1,65 @@
+struct Fog {
+    inv_view_proj: mat4x4<f32>,
+    camera_pos: vec4<f32>,
+    color: vec4<f32>,
+    falloff: vec2<f32>,
+    mode: f32,
+    height_fog: f32,
+    height: vec3<f32>,
+    _pad: f32,
+}
+
+@group(0) @binding(0)
+var depth_texture: texture_depth_2d;
+@group(1) @binding(0)
+var<uniform> fog: Fog;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // one triangle covering the screen.
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth = textureLoad(depth_texture, vec2<i32>(in.clip_position.xy), 0);
+    if depth >= 1.0 {
+        // the background, nothing was drawn here.
+        discard;
+    }
+    let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth, 1.0);
+    let world = fog.inv_view_proj * ndc;
+    let world_pos = world.xyz / world.w;
+    let to_pos = world_pos - fog.camera_pos.xyz;
+    let distance = length(to_pos);
+
+    var amount: f32;
+    if fog.mode < 0.5 {
+        amount = clamp((distance - fog.falloff.x) / (fog.falloff.y - fog.falloff.x), 0.0, 1.0);
+    } else {
+        amount = 1.0 - exp(-fog.falloff.x * distance);
+    }
+
+    if fog.height_fog > 0.5 {
+        // density(y) = density * e^(-falloff * (y - height)), integrated along the view ray.
+        let density = fog.height.y * exp(-fog.height.z * (fog.camera_pos.y - fog.height.x));
+        let dy = to_pos.y * fog.height.z;
+        var integral = distance;
+        if abs(dy) > 0.0001 {
+            integral = distance * (1.0 - exp(-dy)) / dy;
+        }
+        let height_amount = 1.0 - exp(-density * integral);
+        amount = 1.0 - (1.0 - amount) * (1.0 - clamp(height_amount, 0.0, 1.0));
+    }
+
+    return vec4<f32>(fog.color.rgb, amount * fog.color.a);
+}
@@ -1,8 +1,15 @@
 use std::f32::consts::PI;
 
 use crate::{
+    batteries::{Fog, FogFalloff, HeightFog},
     elements::camera3d::{Projection, ProjectionKind},
-    modules::DefaultModules,
+    modules::{
+        renderer::{
+            render_scale::{MAX_RENDER_SCALE, MIN_RENDER_SCALE},
+            AutoRenderScale, BloomQuality, UpscaleFilter,
+        },
+        DefaultModules,
+    },
 };
 
 pub struct GraphicsSettingsController {
@@ -44,7 +51,7 @@ impl GraphicsSettingsController {
             ui.label(format!(
                 "{} fps / {:.3} ms",
                 deps.time.fps().round() as i32,
-                deps.time.delta().as_secs_f32() * 1000.0
+                deps.time.real_delta().as_secs_f32() * 1000.0
             ));
             ui.label("Bloom");
             ui.add(egui::Checkbox::new(
@@ -56,12 +63,114 @@ impl GraphicsSettingsController {
                     &mut bloom_settings.blend_factor,
                     0.0..=1.0,
                 ));
+                ui.label("Bloom Quality");
+                for quality in BloomQuality::ALL {
+                    ui.radio_value(
+                        &mut bloom_settings.mip_count,
+                        quality.mip_count(),
+                        format!("{quality:?}"),
+                    );
+                }
+                ui.label("Bloom Threshold");
+                ui.add(egui::Slider::new(&mut bloom_settings.threshold, 0.0..=4.0));
+                ui.label("Bloom Threshold Knee");
+                ui.add(egui::Slider::new(&mut bloom_settings.knee, 0.0..=1.0));
+            }
+
+            ui.label("Post Effects");
+            // applied after the loop, because the chain cannot change while it is iterated.
+            let mut toggle = None;
+            let mut shift = None;
+            for effect in deps.post_effects.iter() {
+                ui.horizontal(|ui| {
+                    let mut enabled = effect.enabled;
+                    if ui.checkbox(&mut enabled, effect.name).changed() {
+                        toggle = Some((effect.key, enabled));
+                    }
+                    if ui.small_button("Up").clicked() {
+                        shift = Some((effect.key, true));
+                    }
+                    if ui.small_button("Down").clicked() {
+                        shift = Some((effect.key, false));
+                    }
+                });
+            }
+            if let Some((key, enabled)) = toggle {
+                deps.post_effects.set_enabled(key, enabled);
+            }
+            if let Some((key, up)) = shift {
+                deps.post_effects.shift(key, up);
+            }
+
+            let render_scale = &mut deps.render_scale;
+            ui.label("Render Scale");
+            ui.add_enabled(
+                render_scale.auto.is_none(),
+                egui::Slider::new(&mut render_scale.scale, MIN_RENDER_SCALE..=MAX_RENDER_SCALE),
+            );
+            let mut auto = render_scale.auto.is_some();
+            if ui.checkbox(&mut auto, "Automatic (60 fps)").changed() {
+                render_scale.auto = auto.then(AutoRenderScale::default);
+            }
+            let mut sharpen = matches!(render_scale.upscale, UpscaleFilter::Sharpen { .. });
+            ui.radio_value(&mut sharpen, false, "Bilinear Upscale");
+            ui.radio_value(&mut sharpen, true, "Sharpened Upscale");
+            render_scale.upscale = match (sharpen, render_scale.upscale) {
+                (false, _) => UpscaleFilter::Bilinear,
+                (true, UpscaleFilter::Sharpen { sharpness }) => {
+                    UpscaleFilter::Sharpen { sharpness }
+                }
+                (true, UpscaleFilter::Bilinear) => UpscaleFilter::Sharpen { sharpness: 0.5 },
+            };
+            if let UpscaleFilter::Sharpen { sharpness } = &mut render_scale.upscale {
+                ui.add(egui::Slider::new(sharpness, 0.0..=1.0));
             }
 
             let tone_mapping = deps.tone_mapping.enabled_mut();
             ui.label("Tonemapping");
             ui.radio_value(tone_mapping, false, "Disabled");
             ui.radio_value(tone_mapping, true, "Aces");
+            ui.label("Exposure Compensation (EV)");
+            ui.add(egui::Slider::new(
+                &mut deps.camera.exposure_compensation,
+                -5.0..=5.0,
+            ));
+
+            let streaming = &mut deps.streaming.settings;
+            ui.label("Streaming VRAM Budget (MB)");
+            let mut budget_mb = (streaming.vram_budget / (1024 * 1024)) as u32;
+            let budget_slider = egui::Slider::new(&mut budget_mb, 64..=8192).logarithmic(true);
+            if ui.add(budget_slider).changed() {
+                streaming.vram_budget = budget_mb as u64 * 1024 * 1024;
+            }
+
+            if let Some(fog) = deps.plugins.get_mut::<Fog>() {
+                let fog = &mut fog.settings;
+                ui.label("Fog");
+                ui.add(egui::Slider::new(&mut fog.color.a, 0.0..=1.0).text("Opacity"));
+                match &mut fog.falloff {
+                    FogFalloff::Linear { start, end } => {
+                        ui.add(egui::Slider::new(start, 0.0..=500.0).text("Start"));
+                        ui.add(egui::Slider::new(end, 0.0..=1000.0).text("End"));
+                    }
+                    FogFalloff::Exponential { density } => {
+                        ui.add(egui::Slider::new(density, 0.0..=0.2).text("Density"));
+                    }
+                }
+                let mut height_fog = fog.height_fog.is_some();
+                if ui.checkbox(&mut height_fog, "Height Fog").changed() {
+                    fog.height_fog = height_fog.then_some(HeightFog {
+                        height: 0.0,
+                        density: 0.05,
+                        falloff: 0.2,
+                    });
+                }
+                if let Some(h) = &mut fog.height_fog {
+                    ui.add(egui::Slider::new(&mut h.height, -50.0..=50.0).text("Height"));
+                    ui.add(egui::Slider::new(&mut h.density, 0.0..=0.5).text("Density"));
+                    ui.add(egui::Slider::new(&mut h.falloff, 0.0..=2.0).text("Falloff"));
+                }
+            }
             // /////////////////////////////////////////////////////////////////////////////
             // Camera Settings
             // /////////////////////////////////////////////////////////////////////////////
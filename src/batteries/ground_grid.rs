@@ -0,0 +1,246 @@
+use wgpu::{FragmentState, PrimitiveState, ShaderModuleDescriptor, VertexState};
+
+use crate::{
+    elements::{camera3d::Camera3dGR, Color, UniformBuffer, WgslLayout},
+    modules::{
+        renderer::{DEPTH_FORMAT, HDR_COLOR_FORMAT},
+        DefaultModules, GraphicsContext, Plugin,
+    },
+    GpuRecreated,
+};
+
+/// Looks of the `GroundGrid`, can be changed at any time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GroundGridSettings {
+    /// world units between two lines.
+    pub cell_size: f32,
+    /// every `major_every`th line is drawn with `major_color`.
+    pub major_every: u32,
+    /// the grid fades out towards this distance from the camera.
+    pub fade_distance: f32,
+    /// the grid lies in the xz plane at this y.
+    pub height: f32,
+    /// in pixels.
+    pub line_width: f32,
+    pub color: Color,
+    pub major_color: Color,
+    /// the x axis, where z is 0.
+    pub x_axis_color: Color,
+    /// the z axis, where x is 0.
+    pub z_axis_color: Color,
+}
+
+impl Default for GroundGridSettings {
+    fn default() -> Self {
+        GroundGridSettings {
+            cell_size: 1.0,
+            major_every: 10,
+            fade_distance: 150.0,
+            height: 0.0,
+            line_width: 1.0,
+            color: Color::new(0.3, 0.3, 0.3).alpha(0.5),
+            major_color: Color::new(0.5, 0.5, 0.5).alpha(0.8),
+            x_axis_color: Color::new(0.9, 0.2, 0.2),
+            z_axis_color: Color::new(0.2, 0.4, 0.9),
+        }
+    }
+}
+
+/// An infinite reference grid on the ground, as in editors: anti aliased lines with thicker major lines, the x and z
+/// axis highlighted, and fading out with the distance to the camera.
+///
+/// Add it with `DefaultModules::add_plugin`, it is drawn in the main pass after the other world geometry.
+/// The grid is a quad that moves with the camera, the lines are computed per pixel in the fragment shader.
+#[derive(Default)]
+pub struct GroundGrid {
+    pub settings: GroundGridSettings,
+    gpu: Option<GroundGridGpu>,
+}
+
+impl GroundGrid {
+    pub fn new(settings: GroundGridSettings) -> Self {
+        GroundGrid {
+            settings,
+            gpu: None,
+        }
+    }
+}
+
+impl Plugin for GroundGrid {
+    fn initialize(&mut self, mods: &mut DefaultModules) {
+        self.gpu = Some(GroundGridGpu::new(&mods.ctx, &self.settings));
+    }
+
+    fn deinitialize(&mut self, _mods: &mut DefaultModules) {
+        self.gpu = None;
+    }
+
+    fn gpu_recreated(&mut self, ctx: &GraphicsContext, event: GpuRecreated) {
+        if event.device_lost {
+            self.gpu = Some(GroundGridGpu::new(ctx, &self.settings));
+        }
+    }
+
+    fn prepare(
+        &mut self,
+        _device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _encoder: &mut wgpu::CommandEncoder,
+    ) {
+        if let Some(gpu) = &mut self.gpu {
+            gpu.uniform
+                .update_and_prepare(SettingsRaw::new(&self.settings), queue);
+        }
+    }
+
+    fn render<'e>(&'e self, render_pass: &mut wgpu::RenderPass<'e>, camera: &'e Camera3dGR) {
+        let Some(gpu) = &self.gpu else {
+            return;
+        };
+        render_pass.set_pipeline(&gpu.pipeline);
+        render_pass.set_bind_group(0, camera.bind_group(), &[]);
+        render_pass.set_bind_group(1, &gpu.bind_group, &[]);
+        // a quad of two triangles, the vertices are computed in the shader.
+        render_pass.draw(0..6, 0..1);
+    }
+}
+
+struct GroundGridGpu {
+    pipeline: wgpu::RenderPipeline,
+    uniform: UniformBuffer<SettingsRaw>,
+    bind_group: wgpu::BindGroup,
+}
+
+impl GroundGridGpu {
+    fn new(ctx: &GraphicsContext, settings: &GroundGridSettings) -> Self {
+        let uniform = UniformBuffer::new(SettingsRaw::new(settings), &ctx.device);
+        // the same layout as the one of the `Camera3dGR`, such that the camera bind groups of all views fit.
+        let camera_layout = uniform_bind_group_layout(&ctx.device, "GroundGrid Camera");
+        let settings_layout = uniform_bind_group_layout(&ctx.device, "GroundGrid Settings");
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("GroundGrid Settings BindGroup"),
+            layout: &settings_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.buffer().as_entire_binding(),
+            }],
+        });
+        let pipeline = create_pipeline(ctx, &camera_layout, &settings_layout);
+        GroundGridGpu {
+            pipeline,
+            uniform,
+            bind_group,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable, WgslLayout)]
+struct SettingsRaw {
+    color: Color,
+    major_color: Color,
+    x_axis_color: Color,
+    z_axis_color: Color,
+    cell_size: f32,
+    major_every: f32,
+    fade_distance: f32,
+    height: f32,
+    line_width: f32,
+    _pad: [f32; 3],
+}
+
+impl SettingsRaw {
+    fn new(settings: &GroundGridSettings) -> Self {
+        SettingsRaw {
+            color: settings.color,
+            major_color: settings.major_color,
+            x_axis_color: settings.x_axis_color,
+            z_axis_color: settings.z_axis_color,
+            cell_size: settings.cell_size.max(0.0001),
+            major_every: settings.major_every.max(1) as f32,
+            fade_distance: settings.fade_distance.max(0.0001),
+            height: settings.height,
+            line_width: settings.line_width,
+            _pad: [0.0; 3],
+        }
+    }
+}
+
+fn uniform_bind_group_layout(device: &wgpu::Device, label: &str) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some(&format!("{label} BindGroupLayout")),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    })
+}
+
+fn create_pipeline(
+    ctx: &GraphicsContext,
+    camera_layout: &wgpu::BindGroupLayout,
+    settings_layout: &wgpu::BindGroupLayout,
+) -> wgpu::RenderPipeline {
+    let label = "GroundGrid";
+    let device = &ctx.device;
+    let wgsl = include_str!("ground_grid.wgsl");
+    UniformBuffer::<SettingsRaw>::debug_check_layout(wgsl, 1, 0);
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(&format!("{label} ShaderModule")),
+        source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+    });
+
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(&format!("{label} PipelineLayout")),
+        bind_group_layouts: &[camera_layout, settings_layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("{label} Pipeline")),
+        layout: Some(&layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: HDR_COLOR_FORMAT,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // visible from below too.
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        // transparent: hidden behind objects, but does not write depth itself.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: ctx.msaa_sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
@@ -0,0 +1,85 @@
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+
+struct Settings {
+    color: vec4<f32>,
+    major_color: vec4<f32>,
+    x_axis_color: vec4<f32>,
+    z_axis_color: vec4<f32>,
+    cell_size: f32,
+    major_every: f32,
+    fade_distance: f32,
+    height: f32,
+    line_width: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@group(1) @binding(0)
+var<uniform> settings: Settings;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_pos: vec3<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    // the quad is centered below the camera and as big as the fade distance, so the grid looks infinite.
+    let xz = camera.view_pos.xz + corners[vertex_index] * settings.fade_distance;
+    let world_pos = vec3<f32>(xz.x, settings.height, xz.y);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_pos, 1.0);
+    out.world_pos = world_pos;
+    return out;
+}
+
+/// 1.0 on the lines of a grid with the given spacing, falling off to 0.0 over about a pixel.
+fn grid_lines(pos: vec2<f32>, spacing: f32) -> f32 {
+    let coord = pos / spacing;
+    let derivative = fwidth(coord);
+    // distance to the closest line in pixels, per axis:
+    let dist = abs(fract(coord - 0.5) - 0.5) / derivative;
+    let line = min(dist.x, dist.y);
+    return 1.0 - clamp(line - settings.line_width * 0.5 + 0.5, 0.0, 1.0);
+}
+
+/// 1.0 on the line where `value` is 0.
+fn axis_line(value: f32) -> f32 {
+    let dist = abs(value) / fwidth(value);
+    return 1.0 - clamp(dist - settings.line_width + 0.5, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pos = in.world_pos.xz;
+    let minor = grid_lines(pos, settings.cell_size);
+    let major = grid_lines(pos, settings.cell_size * settings.major_every);
+    let x_axis = axis_line(pos.y);
+    let z_axis = axis_line(pos.x);
+
+    var color = settings.color * minor;
+    color = mix(color, settings.major_color, major);
+    color = mix(color, settings.x_axis_color, x_axis);
+    color = mix(color, settings.z_axis_color, z_axis);
+
+    let distance = length(pos - camera.view_pos.xz);
+    let fade = 1.0 - smoothstep(settings.fade_distance * 0.3, settings.fade_distance, distance);
+    let alpha = color.a * fade;
+    if alpha <= 0.001 {
+        discard;
+    }
+    return vec4<f32>(color.rgb, alpha);
+}
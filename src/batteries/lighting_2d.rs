@@ -0,0 +1,510 @@
+use glam::{Mat4, Vec2};
+use wgpu::{
+    BufferUsages, FragmentState, MultisampleState, PrimitiveState, ShaderModuleDescriptor,
+    VertexState,
+};
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    elements::{
+        texture::rgba_bind_group_layout, BindableTexture, Color, GrowableBuffer, ToRaw,
+        TransformRaw, UniformBuffer, WgslArrayElement, WgslLayout,
+    },
+    modules::{
+        renderer::{
+            ui_rect::UiRect, world_rect::WorldRectRaw, HdrTexture, VertexT, HDR_COLOR_FORMAT,
+        },
+        DefaultModules, GraphicsContext, Plugin, PostEffectKey,
+    },
+    GpuRecreated, Ptr,
+};
+
+/// Lights beyond this number are ignored each frame.
+pub const MAX_LIGHTS_2D: usize = 32;
+/// Occluder edges beyond this number are ignored each frame.
+pub const MAX_OCCLUDER_SEGMENTS: usize = 256;
+
+const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// A light in the xy plane. Lights with a `cone` are spot lights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light2D {
+    pub position: Vec2,
+    pub color: Color,
+    pub intensity: f32,
+    /// the light fades out smoothly towards this distance.
+    pub radius: f32,
+    /// distance of the light above the plane, lower lights hit normal mapped sprites at flatter angles.
+    pub height: f32,
+    pub cone: Option<LightCone>,
+    /// blocked by the occluders.
+    pub shadows: bool,
+}
+
+impl Light2D {
+    pub fn point(position: Vec2, color: Color, radius: f32) -> Self {
+        Light2D {
+            position,
+            color,
+            intensity: 1.0,
+            radius,
+            height: radius * 0.25,
+            cone: None,
+            shadows: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightCone {
+    pub direction: Vec2,
+    /// half of the opening angle, in radians.
+    pub angle: f32,
+    /// fraction of the angle over which the edge fades out, from 0 (hard) to 1.
+    pub softness: f32,
+}
+
+/// 2D lights with shadows from occluder shapes, and normal mapped sprites.
+///
+/// A post effect that multiplies the hdr texture with the ambient color plus the light of all lights drawn this
+/// frame, before bloom (so lit areas can glow). Lights and occluders are drawn like gizmos, every frame:
+/// `draw_light`, `draw_occluder`. Shadows are hard and computed per pixel against every occluder edge.
+///
+/// Everything is lit by the distance to the light only. To shade a sprite by the angle of its surface to the light,
+/// draw its normal map with `draw_normal_map` with the same rect and transform (load normal maps with
+/// `Texture::from_normal_map`). Uses the main `Camera3d` looking at the xy plane, like `Fog`.
+pub struct Lighting2D {
+    /// light everywhere, also in shadows.
+    pub ambient: Color,
+    lights: Vec<LightRaw>,
+    segments: Vec<[f32; 4]>,
+    normal_sprites: Vec<(WorldRectRaw, Ptr<BindableTexture>)>,
+    view_proj: Mat4,
+    gpu: Option<LightingGpu>,
+}
+
+impl Lighting2D {
+    pub fn new(ambient: Color) -> Self {
+        Lighting2D {
+            ambient,
+            lights: vec![],
+            segments: vec![],
+            normal_sprites: vec![],
+            view_proj: Mat4::IDENTITY,
+            gpu: None,
+        }
+    }
+
+    pub fn draw_light(&mut self, light: Light2D) {
+        if self.lights.len() < MAX_LIGHTS_2D {
+            self.lights.push(LightRaw::new(&light));
+        }
+    }
+
+    /// A closed polygon that casts shadows, e.g. the outline of a wall.
+    pub fn draw_occluder(&mut self, points: &[Vec2]) {
+        if let [a, b] = points {
+            self.draw_occluder_segment(*a, *b);
+            return;
+        }
+        for (i, a) in points.iter().enumerate() {
+            self.draw_occluder_segment(*a, points[(i + 1) % points.len()]);
+        }
+    }
+
+    pub fn draw_occluder_segment(&mut self, a: Vec2, b: Vec2) {
+        if self.segments.len() < MAX_OCCLUDER_SEGMENTS {
+            self.segments.push([a.x, a.y, b.x, b.y]);
+        }
+    }
+
+    /// The normal map of a sprite, with the `rect` and `transform` it was drawn with in the `WorldRectRenderer`.
+    /// Its alpha masks where the normals are used.
+    pub fn draw_normal_map(
+        &mut self,
+        rect: UiRect,
+        transform: impl ToRaw<Raw = TransformRaw>,
+        normal_map: Ptr<BindableTexture>,
+    ) {
+        let sprite = WorldRectRaw {
+            ui_rect: rect,
+            transform: transform.to_raw(),
+        };
+        self.normal_sprites.push((sprite, normal_map));
+    }
+}
+
+impl Default for Lighting2D {
+    fn default() -> Self {
+        Lighting2D::new(Color::new(0.1, 0.1, 0.15))
+    }
+}
+
+impl Plugin for Lighting2D {
+    fn initialize(&mut self, mods: &mut DefaultModules) {
+        self.gpu = Some(LightingGpu::new(&mods.ctx, mods.screen_textures.size()));
+        mods.post_effects
+            .move_before(PostEffectKey::plugin::<Self>(), PostEffectKey::Bloom);
+    }
+
+    fn deinitialize(&mut self, _mods: &mut DefaultModules) {
+        self.gpu = None;
+    }
+
+    fn gpu_recreated(&mut self, ctx: &GraphicsContext, event: GpuRecreated) {
+        if event.device_lost {
+            let size = self.gpu.as_ref().map(|e| e.normals.size);
+            self.gpu = Some(LightingGpu::new(ctx, size.unwrap_or_else(|| ctx.size())));
+        }
+    }
+
+    fn begin_frame(&mut self, mods: &mut DefaultModules) {
+        let camera = &mods.camera;
+        self.view_proj = camera.projection.calc_matrix() * camera.transform.calc_matrix();
+        // the normals are drawn per pixel of the hdr texture.
+        let size = mods.screen_textures.size();
+        if let Some(gpu) = &mut self.gpu {
+            if gpu.normals.size != size {
+                gpu.normals = NormalTarget::new(&mods.ctx.device, &gpu.normals_layout, size);
+            }
+        }
+    }
+
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let lights = std::mem::take(&mut self.lights);
+        let segments = std::mem::take(&mut self.segments);
+        let mut sprites = std::mem::take(&mut self.normal_sprites);
+        let Some(gpu) = &mut self.gpu else {
+            return;
+        };
+
+        let mut raw = LightingRaw::zeroed();
+        raw.view_proj = self.view_proj.to_cols_array_2d();
+        raw.inv_view_proj = self.view_proj.inverse().to_cols_array_2d();
+        raw.ambient = self.ambient;
+        raw.counts = [lights.len() as u32, segments.len() as u32, 0, 0];
+        raw.lights[..lights.len()].copy_from_slice(&lights);
+        raw.segments[..segments.len()].copy_from_slice(&segments);
+        gpu.uniform.update_and_prepare(raw, queue);
+
+        // keep the draw order, only group neighbouring sprites with the same normal map.
+        gpu.sprite_ranges.clear();
+        let mut instances = Vec::with_capacity(sprites.len());
+        for (i, (sprite, texture)) in sprites.drain(..).enumerate() {
+            match gpu.sprite_ranges.last_mut() {
+                Some((range, last)) if *last == texture => range.end += 1,
+                _ => gpu.sprite_ranges.push((i as u32..i as u32 + 1, texture)),
+            }
+            instances.push(sprite);
+        }
+        gpu.sprites.prepare(&instances, device, queue);
+        self.normal_sprites = sprites;
+    }
+
+    fn post_process(&mut self, encoder: &mut wgpu::CommandEncoder, hdr: &HdrTexture) {
+        let Some(gpu) = &self.gpu else {
+            return;
+        };
+
+        // normals of the normal mapped sprites, flat where there are none.
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Lighting2D Normals"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &gpu.normals.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.5,
+                        g: 0.5,
+                        b: 1.0,
+                        a: 0.0,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        if !gpu.sprite_ranges.is_empty() {
+            render_pass.set_pipeline(&gpu.normal_pipeline);
+            render_pass.set_bind_group(0, &gpu.uniform_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, gpu.sprites.buffer().slice(..));
+            for (range, texture) in gpu.sprite_ranges.iter() {
+                render_pass.set_bind_group(1, &texture.bind_group, &[]);
+                render_pass.draw(0..6, range.clone());
+            }
+        }
+        drop(render_pass);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Lighting2D"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: hdr.view(),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&gpu.light_pipeline);
+        render_pass.set_bind_group(0, &gpu.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &gpu.normals.bind_group, &[]);
+        // one triangle covering the screen.
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+struct LightingGpu {
+    uniform: UniformBuffer<LightingRaw>,
+    uniform_bind_group: wgpu::BindGroup,
+    normals_layout: wgpu::BindGroupLayout,
+    normals: NormalTarget,
+    sprites: GrowableBuffer<WorldRectRaw>,
+    sprite_ranges: Vec<(std::ops::Range<u32>, Ptr<BindableTexture>)>,
+    normal_pipeline: wgpu::RenderPipeline,
+    light_pipeline: wgpu::RenderPipeline,
+}
+
+impl LightingGpu {
+    fn new(ctx: &GraphicsContext, size: PhysicalSize<u32>) -> Self {
+        let device = &ctx.device;
+        let uniform = UniformBuffer::new(LightingRaw::zeroed(), device);
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Lighting2D BindGroupLayout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Lighting2D BindGroup"),
+            layout: &uniform_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.buffer().as_entire_binding(),
+            }],
+        });
+        let normals_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Lighting2D Normals BindGroupLayout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let normals = NormalTarget::new(device, &normals_layout, size);
+
+        let wgsl = include_str!("lighting_2d.wgsl");
+        UniformBuffer::<LightingRaw>::debug_check_layout(wgsl, 0, 0);
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Lighting2D ShaderModule"),
+            source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+        });
+
+        let normal_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Lighting2D Normals PipelineLayout"),
+            bind_group_layouts: &[&uniform_layout, &rgba_bind_group_layout(device)],
+            push_constant_ranges: &[],
+        });
+        let _empty = &mut vec![];
+        let normal_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Lighting2D Normals Pipeline"),
+            layout: Some(&normal_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_normal",
+                buffers: &[WorldRectRaw::vertex_buffer_layout(0, true, _empty)],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_normal",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: NORMAL_FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+        });
+
+        let light_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Lighting2D PipelineLayout"),
+            bind_group_layouts: &[&uniform_layout, &normals_layout],
+            push_constant_ranges: &[],
+        });
+        let light_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Lighting2D Pipeline"),
+            layout: Some(&light_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_COLOR_FORMAT,
+                    // multiplies the hdr color with the light.
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::Src,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::COLOR,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+        });
+
+        LightingGpu {
+            uniform,
+            uniform_bind_group,
+            normals_layout,
+            normals,
+            sprites: GrowableBuffer::new(device, 64, BufferUsages::VERTEX),
+            sprite_ranges: vec![],
+            normal_pipeline,
+            light_pipeline,
+        }
+    }
+}
+
+struct NormalTarget {
+    size: PhysicalSize<u32>,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+impl NormalTarget {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, size: PhysicalSize<u32>) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Lighting2D Normals"),
+            size: wgpu::Extent3d {
+                width: size.width.max(1),
+                height: size.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: NORMAL_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Lighting2D Normals BindGroup"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+        });
+        NormalTarget {
+            size,
+            view,
+            bind_group,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable, WgslLayout)]
+struct LightRaw {
+    /// xy, height, radius.
+    pos: [f32; 4],
+    /// rgb times intensity, 1 if it casts shadows.
+    color: [f32; 4],
+    /// xy direction, cosine of the outer and inner angle. A full circle if there is no cone.
+    cone: [f32; 4],
+}
+
+impl LightRaw {
+    fn new(light: &Light2D) -> Self {
+        let c = light.color;
+        let cone = match light.cone {
+            Some(cone) => {
+                let dir = cone.direction.normalize_or_zero();
+                let inner = cone.angle * (1.0 - cone.softness.clamp(0.0, 1.0));
+                [
+                    dir.x,
+                    dir.y,
+                    cone.angle.cos(),
+                    inner.cos().max(cone.angle.cos() + 0.0001),
+                ]
+            }
+            None => [1.0, 0.0, -2.0, -1.0],
+        };
+        LightRaw {
+            pos: [
+                light.position.x,
+                light.position.y,
+                light.height,
+                light.radius.max(0.0001),
+            ],
+            color: [
+                c.r * light.intensity,
+                c.g * light.intensity,
+                c.b * light.intensity,
+                light.shadows as u32 as f32,
+            ],
+            cone,
+        }
+    }
+}
+
+impl WgslArrayElement for LightRaw {}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable, WgslLayout)]
+struct LightingRaw {
+    view_proj: [[f32; 4]; 4],
+    inv_view_proj: [[f32; 4]; 4],
+    ambient: Color,
+    /// lights, segments, unused, unused.
+    counts: [u32; 4],
+    lights: [LightRaw; MAX_LIGHTS_2D],
+    /// start xy, end xy.
+    segments: [[f32; 4]; MAX_OCCLUDER_SEGMENTS],
+}
+
+impl LightingRaw {
+    fn zeroed() -> Self {
+        bytemuck::Zeroable::zeroed()
+    }
+}
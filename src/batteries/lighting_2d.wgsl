@@ -0,0 +1,173 @@
+const MAX_LIGHTS: u32 = 32u;
+const MAX_SEGMENTS: u32 = 256u;
+
+struct Light {
+    /// xy, height, radius
+    pos: vec4<f32>,
+    /// rgb times intensity, w > 0.5 if it casts shadows
+    color: vec4<f32>,
+    /// xy direction, cosine of the outer and inner angle
+    cone: vec4<f32>,
+}
+
+struct Lighting {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    ambient: vec4<f32>,
+    counts: vec4<u32>,
+    lights: array<Light, MAX_LIGHTS>,
+    segments: array<vec4<f32>, MAX_SEGMENTS>,
+}
+
+@group(0) @binding(0)
+var<uniform> lighting: Lighting;
+
+// /////////////////////////////////////////////////////////////////////////////
+// Normals of normal mapped sprites, same vertices as the WorldRectRenderer
+// /////////////////////////////////////////////////////////////////////////////
+
+@group(1) @binding(0)
+var t_normal: texture_2d<f32>;
+@group(1) @binding(1)
+var s_normal: sampler;
+
+struct Instance {
+    @location(0) pos: vec4<f32>,
+    @location(1) uv: vec4<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) border_radius: vec4<f32>,
+    @location(4) col1: vec4<f32>,
+    @location(5) col2: vec4<f32>,
+    @location(6) col3: vec4<f32>,
+    @location(7) translation: vec4<f32>,
+}
+
+struct NormalVertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    /// world directions of the x and y axis of the sprite.
+    @location(1) tangent: vec2<f32>,
+    @location(2) bitangent: vec2<f32>,
+}
+
+@vertex
+fn vs_normal(@builtin(vertex_index) vertex_index: u32, instance: Instance) -> NormalVertexOutput {
+    let pos = instance.pos;
+    let uv = instance.uv;
+    var corner: vec2<f32>;
+    switch vertex_index {
+        case 0u, 4u: { corner = vec2<f32>(0.0, 0.0); }
+        case 1u: { corner = vec2<f32>(0.0, 1.0); }
+        case 2u, 5u: { corner = vec2<f32>(1.0, 1.0); }
+        default: { corner = vec2<f32>(1.0, 0.0); }
+    }
+    let px = pos.xy + corner * pos.zw;
+    // as if it was on a screen that is the xy plane, with 100 pixels per unit.
+    let xy_plane_offset = vec2<f32>(px.x / 100.0, -px.y / 100.0);
+    let model_matrix = mat4x4<f32>(instance.col1, instance.col2, instance.col3, instance.translation);
+
+    var out: NormalVertexOutput;
+    out.clip_position = lighting.view_proj * model_matrix * vec4<f32>(xy_plane_offset, 0.0, 1.0);
+    out.uv = uv.xy + corner * uv.zw;
+    out.tangent = normalize(instance.col1.xy);
+    out.bitangent = normalize(instance.col2.xy);
+    return out;
+}
+
+@fragment
+fn fs_normal(in: NormalVertexOutput) -> @location(0) vec4<f32> {
+    let texel = textureSample(t_normal, s_normal, in.uv);
+    // green points up in the image, which is +y of the sprite.
+    let n = texel.xyz * 2.0 - 1.0;
+    let world = normalize(vec3<f32>(n.x * in.tangent + n.y * in.bitangent, n.z));
+    return vec4<f32>(world * 0.5 + 0.5, texel.a);
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Lighting, multiplied onto the hdr texture
+// /////////////////////////////////////////////////////////////////////////////
+
+@group(1) @binding(0)
+var normals: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // one triangle covering the screen.
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn cross_2d(a: vec2<f32>, b: vec2<f32>) -> f32 {
+    return a.x * b.y - a.y * b.x;
+}
+
+/// true if the segment from p to q crosses the segment from a to b.
+fn crosses(p: vec2<f32>, q: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> bool {
+    let d1 = cross_2d(q - p, a - p);
+    let d2 = cross_2d(q - p, b - p);
+    let d3 = cross_2d(b - a, p - a);
+    let d4 = cross_2d(b - a, q - a);
+    return d1 * d2 < 0.0 && d3 * d4 < 0.0;
+}
+
+fn in_shadow(pos: vec2<f32>, light: vec2<f32>) -> bool {
+    for (var i = 0u; i < min(lighting.counts.y, MAX_SEGMENTS); i++) {
+        let segment = lighting.segments[i];
+        if crosses(pos, light, segment.xy, segment.zw) {
+            return true;
+        }
+    }
+    return false;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // intersect the view ray of the pixel with the xy plane.
+    let ndc = vec2<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0);
+    let near = lighting.inv_view_proj * vec4<f32>(ndc, 0.0, 1.0);
+    let far = lighting.inv_view_proj * vec4<f32>(ndc, 1.0, 1.0);
+    let a = near.xyz / near.w;
+    let b = far.xyz / far.w;
+    let t = select(0.0, -a.z / (b.z - a.z), abs(b.z - a.z) > 0.00001);
+    let pos = mix(a, b, t).xy;
+
+    // pixels without a normal map are lit fully, the angle to the light only matters for normal mapped sprites.
+    let encoded = textureLoad(normals, vec2<i32>(in.clip_position.xy), 0);
+    let normal = normalize(encoded.xyz * 2.0 - 1.0);
+
+    var light_sum = lighting.ambient.rgb;
+    for (var i = 0u; i < min(lighting.counts.x, MAX_LIGHTS); i++) {
+        let light = lighting.lights[i];
+        let to_light = light.pos.xy - pos;
+        let distance = length(to_light);
+        let radius = light.pos.w;
+        if distance >= radius {
+            continue;
+        }
+        let falloff = 1.0 - (distance * distance) / (radius * radius);
+        var amount = falloff * falloff;
+
+        let from_light = select(vec2<f32>(0.0), -to_light / distance, distance > 0.0);
+        amount *= smoothstep(light.cone.z, light.cone.w, dot(from_light, light.cone.xy));
+
+        let l = normalize(vec3<f32>(to_light, light.pos.z));
+        amount *= mix(1.0, max(dot(normal, l), 0.0), encoded.a);
+
+        if amount <= 0.0 {
+            continue;
+        }
+        if light.color.w > 0.5 && in_shadow(pos, light.pos.xy) {
+            continue;
+        }
+        light_sum += light.color.rgb * amount;
+    }
+    return vec4<f32>(light_sum, 1.0);
+}
@@ -1,8 +1,26 @@
 //! Batteries are also modules, but more for specific usecases.
-//! todo!() make batteries addable and removable at runtime by dynamically checking if their dependencies are satisfied.
+//! Some of them implement `Plugin`, so they can be added and removed at runtime with `DefaultModules::add_plugin`.
 
 pub mod fly_cam;
 pub use fly_cam::FlyCam;
 
+pub mod camera_rig;
+pub use camera_rig::{CameraRig, ShakeSettings};
+
 pub mod graphics_settings_controller;
 pub use graphics_settings_controller::GraphicsSettingsController;
+
+pub mod ground_grid;
+pub use ground_grid::{GroundGrid, GroundGridSettings};
+
+pub mod fog;
+pub use fog::{Fog, FogFalloff, FogSettings, HeightFog};
+
+pub mod sky;
+pub use sky::{DayNightCycle, Sky, SkySettings};
+
+pub mod lighting_2d;
+pub use lighting_2d::{Light2D, LightCone, Lighting2D};
+
+pub mod stress_test;
+pub use stress_test::{FrameTimeStats, StressTest, StressTestConfig};
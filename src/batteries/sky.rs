@@ -0,0 +1,524 @@
+use std::f32::consts::PI;
+
+use glam::{vec3, Vec3};
+use wgpu::{FragmentState, PrimitiveState, ShaderModuleDescriptor, VertexState};
+
+use crate::{
+    batteries::Fog,
+    elements::{camera3d::Camera3dGR, Color, UniformBuffer, WgslLayout},
+    modules::{
+        renderer::{DirectionalLight, DEPTH_FORMAT, HDR_COLOR_FORMAT},
+        DefaultModules, GraphicsContext, Plugin,
+    },
+    GpuRecreated,
+};
+
+/// Parameters of the `Sky`, can be changed at any time, e.g. by a `DayNightCycle`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkySettings {
+    /// direction towards the sun, does not need to be normalized.
+    pub sun_direction: Vec3,
+    /// haziness of the atmosphere, from 2 (clear) to 10 (hazy).
+    pub turbidity: f32,
+    /// the sky luminance is in kcd/m², this scales it to hdr values around 1.
+    pub exposure: f32,
+    /// brightness of the sun disc, above 1 to glow with bloom.
+    pub sun_intensity: f32,
+    /// in radians, the real sun is about 0.0047.
+    pub sun_angular_radius: f32,
+    /// below the horizon, at noon. Gets darker with the sun.
+    pub ground_color: Color,
+    /// added to the sky, visible when the sun has set.
+    pub night_color: Color,
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        SkySettings {
+            sun_direction: vec3(0.3, 0.6, -0.5),
+            turbidity: 2.5,
+            exposure: 0.06,
+            sun_intensity: 20.0,
+            sun_angular_radius: 0.02,
+            ground_color: Color::new(0.25, 0.23, 0.2),
+            night_color: Color::new(0.005, 0.008, 0.02),
+        }
+    }
+}
+
+/// A procedural sky after the Preetham model, driven by the sun direction: blue at noon, orange at sunset, dark at
+/// night. Add it with `DefaultModules::add_plugin`, it is drawn behind all other geometry of the main pass.
+///
+/// Meant for perspective cameras. `horizon_color` can be used as clear color or fog color, such that the edges of the
+/// world blend into the sky, see `DayNightCycle`, which does that automatically.
+#[derive(Default)]
+pub struct Sky {
+    pub settings: SkySettings,
+    gpu: Option<SkyGpu>,
+}
+
+impl Sky {
+    pub fn new(settings: SkySettings) -> Self {
+        Sky {
+            settings,
+            gpu: None,
+        }
+    }
+
+    /// The hdr color of the sky in a direction, the same as drawn by the shader (without the sun disc).
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let model = SkyModel::new(&self.settings);
+        let rgb = model.radiance(direction.normalize_or_zero(), &self.settings);
+        Color::new(rgb.x, rgb.y, rgb.z)
+    }
+
+    /// The average color of the sky slightly above the horizon.
+    pub fn horizon_color(&self) -> Color {
+        let model = SkyModel::new(&self.settings);
+        let mut sum = Vec3::ZERO;
+        const SAMPLES: usize = 8;
+        for i in 0..SAMPLES {
+            let angle = i as f32 / SAMPLES as f32 * 2.0 * PI;
+            let dir = vec3(angle.cos(), 0.05, angle.sin()).normalize();
+            sum += model.radiance(dir, &self.settings);
+        }
+        let rgb = sum / SAMPLES as f32;
+        Color::new(rgb.x, rgb.y, rgb.z)
+    }
+
+    /// Color of the sun light (without intensity): white at noon, orange at the horizon, black at night.
+    pub fn sun_color(&self) -> Color {
+        let rgb = sun_color(self.settings.sun_direction.normalize_or_zero());
+        Color::new(rgb.x, rgb.y, rgb.z)
+    }
+}
+
+impl Plugin for Sky {
+    fn initialize(&mut self, mods: &mut DefaultModules) {
+        self.gpu = Some(SkyGpu::new(&mods.ctx, &self.settings));
+    }
+
+    fn deinitialize(&mut self, _mods: &mut DefaultModules) {
+        self.gpu = None;
+    }
+
+    fn gpu_recreated(&mut self, ctx: &GraphicsContext, event: GpuRecreated) {
+        if event.device_lost {
+            self.gpu = Some(SkyGpu::new(ctx, &self.settings));
+        }
+    }
+
+    fn prepare(
+        &mut self,
+        _device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _encoder: &mut wgpu::CommandEncoder,
+    ) {
+        if let Some(gpu) = &mut self.gpu {
+            gpu.uniform
+                .update_and_prepare(SkyRaw::new(&self.settings), queue);
+        }
+    }
+
+    fn render_background<'e>(
+        &'e self,
+        render_pass: &mut wgpu::RenderPass<'e>,
+        camera: &'e Camera3dGR,
+    ) {
+        let Some(gpu) = &self.gpu else {
+            return;
+        };
+        render_pass.set_pipeline(&gpu.pipeline);
+        render_pass.set_bind_group(0, camera.bind_group(), &[]);
+        render_pass.set_bind_group(1, &gpu.bind_group, &[]);
+        // a cube around the camera, the vertices are computed in the shader.
+        render_pass.draw(0..36, 0..1);
+    }
+}
+
+/// Moves the sun of the `Sky` over the day, points the `ColorMeshRenderer::light` at it and sets the fog color to
+/// the horizon color of the sky.
+///
+/// Call `update` once per frame. Custom shaders can use `sun_direction` and `Sky::sun_color`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DayNightCycle {
+    /// in hours, from 0 to 24. The sun rises at 6 in the east (+x) and is highest at 12.
+    pub time_of_day: f32,
+    /// real seconds for a full day of 24 hours.
+    pub day_duration: f32,
+    /// angle in radians by which the path of the sun is tilted to the south (-z), 0 is straight over the top.
+    pub tilt: f32,
+    pub paused: bool,
+}
+
+impl Default for DayNightCycle {
+    fn default() -> Self {
+        DayNightCycle {
+            time_of_day: 10.0,
+            day_duration: 120.0,
+            tilt: 0.4,
+            paused: false,
+        }
+    }
+}
+
+impl DayNightCycle {
+    pub fn new(time_of_day: f32, day_duration: f32) -> Self {
+        DayNightCycle {
+            time_of_day,
+            day_duration,
+            ..Default::default()
+        }
+    }
+
+    /// Advances the time of day (scaled by `Time::delta`) and applies it to the light of the color meshes and to
+    /// the `Sky` and `Fog` plugins, if added.
+    pub fn update(&mut self, mods: &mut DefaultModules) {
+        if !self.paused && self.day_duration > 0.0 {
+            let hours = mods.time.delta().as_secs_f32() / self.day_duration * 24.0;
+            self.time_of_day = (self.time_of_day + hours).rem_euclid(24.0);
+        }
+        let sun_direction = self.sun_direction();
+        mods.color_mesh.light = self.light();
+        let Some(sky) = mods.plugins.get_mut::<Sky>() else {
+            return;
+        };
+        sky.settings.sun_direction = sun_direction;
+        let horizon = sky.horizon_color();
+        if let Some(fog) = mods.plugins.get_mut::<Fog>() {
+            fog.settings.color = horizon.alpha(fog.settings.color.a);
+        }
+    }
+
+    /// Direction towards the sun at the current time of day.
+    pub fn sun_direction(&self) -> Vec3 {
+        // 0 at 6 o'clock, PI/2 at noon.
+        let angle = (self.time_of_day / 24.0 - 0.25) * 2.0 * PI;
+        vec3(
+            angle.cos(),
+            angle.sin() * self.tilt.cos(),
+            -angle.sin() * self.tilt.sin(),
+        )
+    }
+
+    /// The sun at the current time of day, with a dim ambient at night.
+    pub fn light(&self) -> DirectionalLight {
+        let direction = self.sun_direction();
+        let sun = sun_color(direction);
+        let day = smoothstep(-0.1, 0.2, direction.y);
+        let DirectionalLight {
+            color,
+            ambient: default_ambient,
+            ..
+        } = DirectionalLight::default();
+        let ambient = 0.25 + 0.75 * day;
+        DirectionalLight {
+            direction,
+            color: Color::new(color.r * sun.x, color.g * sun.y, color.b * sun.z),
+            ambient: Color::new(
+                ambient * default_ambient.r,
+                ambient * default_ambient.g,
+                ambient * default_ambient.b,
+            ),
+        }
+    }
+
+    /// The sun is above the horizon.
+    pub fn is_day(&self) -> bool {
+        self.sun_direction().y > 0.0
+    }
+}
+
+struct SkyGpu {
+    pipeline: wgpu::RenderPipeline,
+    uniform: UniformBuffer<SkyRaw>,
+    bind_group: wgpu::BindGroup,
+}
+
+impl SkyGpu {
+    fn new(ctx: &GraphicsContext, settings: &SkySettings) -> Self {
+        let uniform = UniformBuffer::new(SkyRaw::new(settings), &ctx.device);
+        // the same layout as the one of the `Camera3dGR`, such that the camera bind groups of all views fit.
+        let camera_layout = uniform_bind_group_layout(&ctx.device, "Sky Camera");
+        let sky_layout = uniform_bind_group_layout(&ctx.device, "Sky Settings");
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sky Settings BindGroup"),
+            layout: &sky_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.buffer().as_entire_binding(),
+            }],
+        });
+        let pipeline = create_pipeline(ctx, &camera_layout, &sky_layout);
+        SkyGpu {
+            pipeline,
+            uniform,
+            bind_group,
+        }
+    }
+}
+
+/// The Preetham model: the luminance Y and chromaticity x,y of a direction are the zenith values scaled by the Perez
+/// distribution `F(theta, gamma) / F(0, theta_sun)`, theta being the angle to the zenith and gamma the angle to the sun.
+struct SkyModel {
+    sun: Vec3,
+    /// rows A to E of the Perez coefficients, the columns are Y, x and y.
+    perez: [Vec3; 5],
+    /// Y, x and y at the zenith, divided by `F(0, theta_sun)`.
+    zenith: Vec3,
+    /// 1 while the sun is up, fades to 0 after sunset.
+    day: f32,
+}
+
+impl SkyModel {
+    fn new(settings: &SkySettings) -> Self {
+        let sun = settings.sun_direction.normalize_or_zero();
+        let day = smoothstep(-0.15, 0.05, sun.y);
+        // the model is only defined for a sun above the horizon, after sunset the sky just fades out.
+        let theta_s = sun.y.clamp(0.02, 1.0).acos();
+        let t = settings.turbidity.clamp(1.7, 10.0);
+
+        let perez = [
+            vec3(
+                0.1787 * t - 1.4630,
+                -0.0193 * t - 0.2592,
+                -0.0167 * t - 0.2608,
+            ),
+            vec3(
+                -0.3554 * t + 0.4275,
+                -0.0665 * t + 0.0008,
+                -0.0950 * t + 0.0092,
+            ),
+            vec3(
+                -0.0227 * t + 5.3251,
+                -0.0004 * t + 0.2125,
+                -0.0079 * t + 0.2102,
+            ),
+            vec3(
+                0.1206 * t - 2.5771,
+                -0.0641 * t - 0.8989,
+                -0.0441 * t - 1.6537,
+            ),
+            vec3(
+                -0.0670 * t + 0.3703,
+                -0.0033 * t + 0.0452,
+                -0.0109 * t + 0.0529,
+            ),
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let (t1, t2, t3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let zenith_x = t * t * (0.00166 * t3 - 0.00375 * t2 + 0.00209 * t1)
+            + t * (-0.02903 * t3 + 0.06377 * t2 - 0.03202 * t1 + 0.00394)
+            + (0.11693 * t3 - 0.21196 * t2 + 0.06052 * t1 + 0.25886);
+        let zenith_yc = t * t * (0.00275 * t3 - 0.00610 * t2 + 0.00317 * t1)
+            + t * (-0.04214 * t3 + 0.08970 * t2 - 0.04153 * t1 + 0.00516)
+            + (0.15346 * t3 - 0.26756 * t2 + 0.06670 * t1 + 0.26688);
+
+        let f0 = perez_distribution(&perez, 1.0, theta_s);
+        SkyModel {
+            sun,
+            perez,
+            zenith: vec3(zenith_y, zenith_x, zenith_yc) / f0,
+            day,
+        }
+    }
+
+    /// Linear rgb, must match `sky.wgsl`.
+    fn radiance(&self, dir: Vec3, settings: &SkySettings) -> Vec3 {
+        let cos_theta = dir.y.max(0.01);
+        let gamma = dir.dot(self.sun).clamp(-1.0, 1.0).acos();
+        let yxy = self.zenith * perez_distribution(&self.perez, cos_theta, gamma);
+        let sky = yxy_to_rgb(yxy).max(Vec3::ZERO) * settings.exposure * self.day;
+        let night = Vec3::from_slice(&[
+            settings.night_color.r,
+            settings.night_color.g,
+            settings.night_color.b,
+        ]);
+        let ground = Vec3::from_slice(&[
+            settings.ground_color.r,
+            settings.ground_color.g,
+            settings.ground_color.b,
+        ]) * (0.05 + 0.95 * self.day);
+        (sky + night).lerp(ground, smoothstep(0.0, -0.05, dir.y))
+    }
+}
+
+fn perez_distribution(perez: &[Vec3; 5], cos_theta: f32, gamma: f32) -> Vec3 {
+    let [a, b, c, d, e] = *perez;
+    let cos_gamma = gamma.cos();
+    (Vec3::ONE + a * (b / cos_theta).exp())
+        * (Vec3::ONE + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+/// From luminance and chromaticity to linear srgb.
+fn yxy_to_rgb(yxy: Vec3) -> Vec3 {
+    let (lum, x, y) = (yxy.x, yxy.y, yxy.z.max(0.0001));
+    let xyz = vec3(x / y * lum, lum, (1.0 - x - y) / y * lum);
+    vec3(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    )
+}
+
+/// Sunlight loses its blue through the long path of the atmosphere at low angles.
+fn sun_color(sun: Vec3) -> Vec3 {
+    let day = smoothstep(-0.05, 0.05, sun.y);
+    vec3(1.0, 0.45, 0.2).lerp(Vec3::ONE, smoothstep(0.0, 0.4, sun.y)) * day
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable, WgslLayout)]
+struct SkyRaw {
+    /// xyz are the coefficients for Y, x and y.
+    perez: [[f32; 4]; 5],
+    /// xyz as in `SkyModel`, w is the day factor.
+    zenith: [f32; 4],
+    /// xyz is the direction, w the cosine of the angular radius.
+    sun: [f32; 4],
+    /// premultiplied with the intensity.
+    sun_color: [f32; 4],
+    ground_color: Color,
+    night_color: Color,
+    exposure: f32,
+    _pad: [f32; 3],
+}
+
+impl SkyRaw {
+    fn new(settings: &SkySettings) -> Self {
+        let model = SkyModel::new(settings);
+        let sun_color = sun_color(model.sun) * settings.sun_intensity;
+        SkyRaw {
+            perez: model.perez.map(|e| e.extend(0.0).into()),
+            zenith: model.zenith.extend(model.day).into(),
+            sun: model.sun.extend(settings.sun_angular_radius.cos()).into(),
+            sun_color: sun_color.extend(1.0).into(),
+            ground_color: settings.ground_color,
+            night_color: settings.night_color,
+            exposure: settings.exposure,
+            _pad: [0.0; 3],
+        }
+    }
+}
+
+fn uniform_bind_group_layout(device: &wgpu::Device, label: &str) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some(&format!("{label} BindGroupLayout")),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    })
+}
+
+fn create_pipeline(
+    ctx: &GraphicsContext,
+    camera_layout: &wgpu::BindGroupLayout,
+    sky_layout: &wgpu::BindGroupLayout,
+) -> wgpu::RenderPipeline {
+    let label = "Sky";
+    let device = &ctx.device;
+    let wgsl = include_str!("sky.wgsl");
+    UniformBuffer::<SkyRaw>::debug_check_layout(wgsl, 1, 0);
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(&format!("{label} ShaderModule")),
+        source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+    });
+
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(&format!("{label} PipelineLayout")),
+        bind_group_layouts: &[camera_layout, sky_layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("{label} Pipeline")),
+        layout: Some(&layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: HDR_COLOR_FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // the camera is inside the cube.
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        // drawn on the far plane, without writing depth, such that all geometry is drawn in front of it.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: ctx.msaa_sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sky_is_blue_at_noon_and_dark_at_night() {
+        let mut sky = Sky::new(SkySettings {
+            sun_direction: Vec3::Y,
+            ..Default::default()
+        });
+        let zenith = sky.radiance(Vec3::Y);
+        assert!(zenith.b > zenith.r, "{zenith:?}");
+        let horizon = sky.horizon_color();
+        assert!(horizon.r + horizon.g + horizon.b > 0.3, "{horizon:?}");
+
+        sky.settings.sun_direction = -Vec3::Y;
+        let night = sky.radiance(Vec3::Y);
+        assert_eq!(night, sky.settings.night_color.alpha(1.0));
+    }
+
+    #[test]
+    fn sun_rises_in_the_east() {
+        let mut cycle = DayNightCycle::new(6.0, 60.0);
+        assert!(cycle.sun_direction().abs_diff_eq(Vec3::X, 1e-5));
+        cycle.time_of_day = 12.0;
+        assert!(cycle.is_day() && cycle.sun_direction().y > 0.9);
+        assert!(cycle.light().ambient.r > 0.5);
+        cycle.time_of_day = 0.0;
+        assert!(!cycle.is_day());
+        // only the dim ambient is left at night.
+        let night = cycle.light();
+        assert_eq!(night.color, Color::new(0.0, 0.0, 0.0));
+        assert!(night.ambient.r < 0.2);
+    }
+}
@@ -0,0 +1,97 @@
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+
+struct Sky {
+    perez: array<vec4<f32>, 5>,
+    zenith: vec4<f32>,
+    sun: vec4<f32>,
+    sun_color: vec4<f32>,
+    ground_color: vec4<f32>,
+    night_color: vec4<f32>,
+    exposure: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@group(1) @binding(0)
+var<uniform> sky: Sky;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) direction: vec3<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var corners = array<vec3<f32>, 8>(
+        vec3<f32>(-1.0, -1.0, -1.0),
+        vec3<f32>(1.0, -1.0, -1.0),
+        vec3<f32>(1.0, 1.0, -1.0),
+        vec3<f32>(-1.0, 1.0, -1.0),
+        vec3<f32>(-1.0, -1.0, 1.0),
+        vec3<f32>(1.0, -1.0, 1.0),
+        vec3<f32>(1.0, 1.0, 1.0),
+        vec3<f32>(-1.0, 1.0, 1.0),
+    );
+    var indices = array<u32, 36>(
+        0u, 1u, 2u, 0u, 2u, 3u,
+        4u, 6u, 5u, 4u, 7u, 6u,
+        0u, 4u, 5u, 0u, 5u, 1u,
+        3u, 2u, 6u, 3u, 6u, 7u,
+        0u, 3u, 7u, 0u, 7u, 4u,
+        1u, 5u, 6u, 1u, 6u, 2u,
+    );
+    let corner = corners[indices[vertex_index]];
+    // big enough to cover the screen of orthographic cameras.
+    let world_position = vec4<f32>(camera.view_pos.xyz + corner * 1000.0, 1.0);
+    let clip = camera.view_proj * world_position;
+    var out: VertexOutput;
+    // z = w puts the cube on the far plane.
+    out.clip_position = clip.xyww;
+    out.direction = corner;
+    return out;
+}
+
+fn perez_distribution(cos_theta: f32, gamma: f32) -> vec3<f32> {
+    let a = sky.perez[0].xyz;
+    let b = sky.perez[1].xyz;
+    let c = sky.perez[2].xyz;
+    let d = sky.perez[3].xyz;
+    let e = sky.perez[4].xyz;
+    let cos_gamma = cos(gamma);
+    return (1.0 + a * exp(b / cos_theta)) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+fn yxy_to_rgb(yxy: vec3<f32>) -> vec3<f32> {
+    let lum = yxy.x;
+    let x = yxy.y;
+    let y = max(yxy.z, 0.0001);
+    let xyz = vec3<f32>(x / y * lum, lum, (1.0 - x - y) / y * lum);
+    return vec3<f32>(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    );
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = normalize(in.direction);
+    let day = sky.zenith.w;
+    let cos_theta = max(dir.y, 0.01);
+    let cos_sun = dot(dir, sky.sun.xyz);
+    let gamma = acos(clamp(cos_sun, -1.0, 1.0));
+    let yxy = sky.zenith.xyz * perez_distribution(cos_theta, gamma);
+    var color = max(yxy_to_rgb(yxy), vec3<f32>(0.0)) * sky.exposure * day + sky.night_color.rgb;
+
+    // the sun disc, with a soft edge.
+    let edge = (1.0 - sky.sun.w) * 0.2;
+    color += sky.sun_color.rgb * smoothstep(sky.sun.w - edge, sky.sun.w + edge, cos_sun);
+
+    let ground = sky.ground_color.rgb * (0.05 + 0.95 * day);
+    color = mix(color, ground, 1.0 - smoothstep(-0.05, 0.0, dir.y));
+    return vec4<f32>(color, 1.0);
+}
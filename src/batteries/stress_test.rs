@@ -0,0 +1,266 @@
+use std::{io::Write, path::Path};
+
+use glam::{vec3, Vec3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    elements::{Color, Rect, Transform},
+    modules::{
+        renderer::ui_rect::UiRect,
+        ui::{Axis, Board, BoardInput, Id, Len},
+        DefaultModules,
+    },
+};
+
+/// What the `StressTest` spawns. The same seed always spawns the same scene, so runs across engine changes are
+/// comparable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StressTestConfig {
+    pub cubes: usize,
+    pub ui_divs: usize,
+    pub particles: usize,
+    pub seed: u64,
+    /// frames that are not measured at the start, while pipelines and caches warm up.
+    pub warmup_frames: usize,
+    /// measured frames.
+    pub frames: usize,
+}
+
+impl Default for StressTestConfig {
+    fn default() -> Self {
+        StressTestConfig {
+            cubes: 10_000,
+            ui_divs: 1_000,
+            particles: 10_000,
+            seed: 42,
+            warmup_frames: 60,
+            frames: 600,
+        }
+    }
+}
+
+/// Renders a scene with many cubes, ui divs and particles, measures the frame times and writes them to a csv file.
+///
+/// Call `update` every frame instead of the game logic, it returns the frame time stats once all frames ran:
+/// ```rust,ignore
+/// if let Some(stats) = self.stress_test.update(&mut self.mods) {
+///     stats.append_csv("stress_test.csv", self.stress_test.config())?;
+///     return UpdateFlow::Exit("stress test finished".into());
+/// }
+/// ```
+pub struct StressTest {
+    config: StressTestConfig,
+    cubes: Vec<Transform>,
+    particles: Vec<Particle>,
+    board: Board,
+    rng: StdRng,
+    frame: usize,
+    frame_times_ms: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Particle {
+    pos: Vec3,
+    velocity: Vec3,
+    color: Color,
+}
+
+impl StressTest {
+    pub fn new(config: StressTestConfig) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        // a cube of cubes around the origin.
+        let side = (config.cubes as f32).cbrt().ceil().max(1.0) as usize;
+        let cubes = (0..config.cubes)
+            .map(|i| {
+                let (x, y, z) = (i % side, (i / side) % side, i / (side * side));
+                let jitter = vec3(rng.gen(), rng.gen(), rng.gen()) * 0.5;
+                let pos = vec3(x as f32, y as f32, z as f32) * 2.0 - side as f32 + jitter;
+                Transform::new(pos.x, pos.y, pos.z).with_scale(0.5)
+            })
+            .collect();
+        let particles = (0..config.particles)
+            .map(|_| spawn_particle(&mut rng))
+            .collect();
+        StressTest {
+            board: Board::new(glam::dvec2(800.0, 600.0)),
+            config,
+            cubes,
+            particles,
+            rng,
+            frame: 0,
+            frame_times_ms: vec![],
+        }
+    }
+
+    pub fn config(&self) -> &StressTestConfig {
+        &self.config
+    }
+
+    /// Draws the scene, returns the stats after the last measured frame.
+    pub fn update(&mut self, mods: &mut DefaultModules) -> Option<FrameTimeStats> {
+        if self.frame > self.config.warmup_frames {
+            // the delta of this frame is the time the last frame took.
+            let ms = mods.time.real_delta().as_secs_f64() * 1000.0;
+            self.frame_times_ms.push(ms);
+        }
+        self.frame += 1;
+
+        mods.color_mesh.draw_cubes(&self.cubes, None);
+
+        // fixed steps, such that the particles move the same in every run.
+        const DT: f32 = 1.0 / 60.0;
+        for p in self.particles.iter_mut() {
+            p.velocity.y -= 9.81 * DT;
+            p.pos += p.velocity * DT;
+            if p.pos.y < 0.0 {
+                *p = spawn_particle(&mut self.rng);
+            }
+            mods.world_rect.draw_rect(
+                UiRect {
+                    pos: Rect::new(-5.0, -5.0, 10.0, 10.0),
+                    uv: Rect::UNIT,
+                    color: p.color,
+                    border_radius: [5.0; 4],
+                },
+                Transform::new(p.pos.x, p.pos.y, p.pos.z),
+            );
+        }
+
+        let size = mods.screen.ui_size();
+        self.board.start_frame(
+            BoardInput::from_input_and_screen(&mods.input, &mods.screen),
+            glam::dvec2(size.x as f64, size.y as f64),
+        );
+        add_stress_divs(&mut self.board, self.config.ui_divs, self.config.seed);
+        self.board.end_frame(&mut mods.fonts);
+        mods.ui.draw_ui_board(&self.board);
+
+        if self.frame_times_ms.len() < self.config.frames {
+            return None;
+        }
+        let stats = FrameTimeStats::new(&self.frame_times_ms);
+        self.frame_times_ms.clear();
+        Some(stats)
+    }
+}
+
+fn spawn_particle(rng: &mut StdRng) -> Particle {
+    let angle = rng.gen::<f32>() * std::f32::consts::TAU;
+    let speed = rng.gen_range(1.0..4.0);
+    Particle {
+        pos: vec3(0.0, rng.gen_range(0.0..10.0), 0.0),
+        velocity: vec3(
+            angle.cos() * speed,
+            rng.gen_range(2.0..8.0),
+            angle.sin() * speed,
+        ),
+        color: Color::from_hsv(rng.gen_range(0.0..360.0), 0.8, 1.0),
+    }
+}
+
+/// Adds `count` colored divs in rows to the board, the same ones for the same seed. Also used by the ui benches.
+pub fn add_stress_divs(board: &mut Board, count: usize, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let per_row = (count as f64).sqrt().ceil().max(1.0) as usize;
+    let mut column = board.add_div("stress column", None);
+    column.axis = Axis::Y;
+    let column = Some(column.id);
+    for row in 0..count.div_ceil(per_row) {
+        let mut row_div = board.add_div(Id::from("stress row") + row as u64, column);
+        row_div.axis = Axis::X;
+        let row_div = Some(row_div.id);
+        for i in row * per_row..((row + 1) * per_row).min(count) {
+            let mut div = board.add_div(Id::from("stress div") + i as u64, row_div);
+            div.width(Len::px(rng.gen_range(4.0..16.0)));
+            div.height(Len::px(rng.gen_range(4.0..16.0)));
+            div.color = Color::from_hsv(rng.gen_range(0.0..360.0), 0.6, 0.9);
+        }
+    }
+}
+
+/// Frame times in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameTimeStats {
+    pub frames: usize,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl FrameTimeStats {
+    pub fn new(frame_times_ms: &[f64]) -> Self {
+        let mut sorted = frame_times_ms.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        // nearest rank percentile.
+        let percentile = |p: f64| {
+            if sorted.is_empty() {
+                return 0.0;
+            }
+            let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+        FrameTimeStats {
+            frames: sorted.len(),
+            mean: sorted.iter().sum::<f64>() / sorted.len().max(1) as f64,
+            p50: percentile(50.0),
+            p90: percentile(90.0),
+            p99: percentile(99.0),
+            max: sorted.last().copied().unwrap_or(0.0),
+        }
+    }
+
+    /// Appends one row with the config and the stats to a csv file, with a header if the file is new, such that
+    /// one file collects the runs across engine changes.
+    pub fn append_csv(
+        &self,
+        path: impl AsRef<Path>,
+        config: &StressTestConfig,
+    ) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let is_new = !path.exists();
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        if is_new {
+            writeln!(
+                file,
+                "cubes,ui_divs,particles,seed,frames,mean_ms,p50_ms,p90_ms,p99_ms,max_ms"
+            )?;
+        }
+        writeln!(
+            file,
+            "{},{},{},{},{},{:.3},{:.3},{:.3},{:.3},{:.3}",
+            config.cubes,
+            config.ui_divs,
+            config.particles,
+            config.seed,
+            self.frames,
+            self.mean,
+            self.p50,
+            self.p90,
+            self.p99,
+            self.max
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_time_percentiles() {
+        let times: Vec<f64> = (1..=100).rev().map(|e| e as f64).collect();
+        let stats = FrameTimeStats::new(&times);
+        assert_eq!(stats.frames, 100);
+        assert_eq!(stats.mean, 50.5);
+        assert_eq!(
+            (stats.p50, stats.p90, stats.p99, stats.max),
+            (50.0, 90.0, 99.0, 100.0)
+        );
+    }
+}
@@ -1,14 +1,147 @@
-use std::{borrow::Cow, marker::PhantomData};
+use std::{borrow::Cow, marker::PhantomData, ops::Range};
 
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 
-use crate::utils::next_pow2_number;
+use anyhow::{anyhow, bail};
+
+use crate::{
+    modules::renderer::material::{BindingReflectionType, ShaderReflection},
+    utils::next_pow2_number,
+};
+
+pub use vert_macros::{ToRaw, WgslLayout};
 
 pub trait ToRaw {
     type Raw: Copy + bytemuck::Pod + bytemuck::Zeroable + PartialEq;
     fn to_raw(&self) -> Self::Raw;
 }
 
+/// Types that are already Pod are their own raw representation.
+macro_rules! impl_to_raw_identity {
+    ($($ty:ty),*) => {
+        $(
+            impl ToRaw for $ty {
+                type Raw = $ty;
+                #[inline(always)]
+                fn to_raw(&self) -> Self::Raw {
+                    *self
+                }
+            }
+        )*
+    };
+}
+
+impl_to_raw_identity!(
+    f32,
+    u32,
+    i32,
+    [f32; 2],
+    [f32; 3],
+    [f32; 4],
+    [[f32; 4]; 4],
+    super::Color
+);
+
+/// glam types are converted to arrays, to make the layout explicit.
+macro_rules! impl_to_raw_glam {
+    ($($ty:ty => $raw:ty),*) => {
+        $(
+            impl ToRaw for $ty {
+                type Raw = $raw;
+                #[inline(always)]
+                fn to_raw(&self) -> Self::Raw {
+                    self.to_array()
+                }
+            }
+        )*
+    };
+}
+
+impl_to_raw_glam!(
+    glam::Vec2 => [f32; 2],
+    glam::Vec3 => [f32; 3],
+    glam::Vec4 => [f32; 4],
+    glam::Quat => [f32; 4]
+);
+
+impl ToRaw for glam::Mat4 {
+    type Raw = [[f32; 4]; 4];
+
+    #[inline(always)]
+    fn to_raw(&self) -> Self::Raw {
+        self.to_cols_array_2d()
+    }
+}
+
+/// Alignment and size of a type in a wgsl uniform buffer. Derive it for uniform structs (see the `WgslLayout`
+/// derive macro), which checks at compile time that the offsets of the rust fields follow the wgsl layout rules.
+///
+/// Arrays are supported for elements that implement `WgslArrayElement`.
+pub trait WgslLayout {
+    const ALIGN: u32;
+    const SIZE: u32;
+    /// name, offset and size of the fields without padding, empty for scalars, vectors and matrices.
+    const FIELDS: &'static [(&'static str, u32, u32)] = &[];
+}
+
+macro_rules! impl_wgsl_layout {
+    ($($ty:ty => ($align:expr, $size:expr)),*) => {
+        $(
+            impl WgslLayout for $ty {
+                const ALIGN: u32 = $align;
+                const SIZE: u32 = $size;
+            }
+        )*
+    };
+}
+
+impl_wgsl_layout!(
+    f32 => (4, 4),
+    u32 => (4, 4),
+    i32 => (4, 4),
+    [f32; 2] => (8, 8),
+    [u32; 2] => (8, 8),
+    [i32; 2] => (8, 8),
+    // a vec3 is aligned like a vec4, but a scalar can follow it directly.
+    [f32; 3] => (16, 12),
+    [u32; 3] => (16, 12),
+    [i32; 3] => (16, 12),
+    [f32; 4] => (16, 16),
+    [u32; 4] => (16, 16),
+    [i32; 4] => (16, 16),
+    glam::Vec2 => (8, 8),
+    glam::Vec3 => (16, 12),
+    glam::Vec4 => (16, 16),
+    glam::Mat4 => (16, 64),
+    super::Color => (16, 16)
+);
+
+/// Types that can be elements of `[T; N]` in a uniform buffer. The array stride in a uniform buffer is the size
+/// rounded up to 16 bytes, so only types whose rust size already is a multiple of 16 qualify: vec4s, matrices and
+/// structs with trailing padding. Implement it by hand for derived structs that are used in arrays.
+pub trait WgslArrayElement: WgslLayout {}
+
+impl WgslArrayElement for [f32; 4] {}
+impl WgslArrayElement for [u32; 4] {}
+impl WgslArrayElement for [i32; 4] {}
+impl WgslArrayElement for glam::Vec4 {}
+impl WgslArrayElement for glam::Mat4 {}
+impl WgslArrayElement for super::Color {}
+impl<T: WgslArrayElement, const N: usize> WgslArrayElement for [T; N] {}
+
+/// Also covers `mat4x4<f32>` as `[[f32; 4]; 4]`.
+impl<T: WgslArrayElement, const N: usize> WgslLayout for [T; N] {
+    const ALIGN: u32 = if T::ALIGN > 16 { T::ALIGN } else { 16 };
+    const SIZE: u32 = {
+        let stride = T::SIZE.div_ceil(Self::ALIGN) * Self::ALIGN;
+        assert!(
+            stride == std::mem::size_of::<T>() as u32,
+            "the rust size of an array element differs from its wgsl array stride, add padding to a multiple of 16 bytes."
+        );
+        stride * N as u32
+    };
+}
+
 pub trait BufferT {}
 
 pub struct UniformBuffer<U: Copy + bytemuck::Pod + bytemuck::Zeroable + PartialEq> {
@@ -49,6 +182,285 @@ impl<U: Copy + bytemuck::Pod + bytemuck::Zeroable + PartialEq> UniformBuffer<U>
     }
 }
 
+impl<U: WgslLayout + bytemuck::Pod + PartialEq> UniformBuffer<U> {
+    /// Compares the fields of `U` with the uniform struct the shader declares at `group` and `binding`, to catch
+    /// a rust struct that does not match the wgsl one, e.g. in debug builds after loading a shader.
+    pub fn check_layout(
+        reflection: &ShaderReflection,
+        group: u32,
+        binding: u32,
+    ) -> anyhow::Result<()> {
+        let entry = reflection
+            .bind_group(group)
+            .and_then(|g| g.entries.iter().find(|e| e.binding == binding))
+            .ok_or_else(|| anyhow!("shader has no binding @group({group}) @binding({binding})"))?;
+        let BindingReflectionType::Uniform { size, members } = &entry.ty else {
+            bail!("@group({group}) @binding({binding}) is not a uniform");
+        };
+        let name = &entry.name;
+        if *size != U::SIZE {
+            bail!(
+                "uniform {name} has {size} bytes in the shader, but {} in rust",
+                U::SIZE
+            );
+        }
+        // padding fields can be left out in the shader.
+        let members: Vec<_> = members
+            .iter()
+            .filter(|m| !m.name.starts_with('_'))
+            .collect();
+        if members.len() != U::FIELDS.len() && !U::FIELDS.is_empty() {
+            bail!(
+                "uniform {name} has {} fields in the shader, but {} in rust",
+                members.len(),
+                U::FIELDS.len()
+            );
+        }
+        for (member, (field, offset, field_size)) in members.iter().zip(U::FIELDS) {
+            if member.offset != *offset || member.size != *field_size {
+                bail!(
+                    "field {} of uniform {name} is at {}..{} in the shader, but rust field {field} at {offset}..{}",
+                    member.name,
+                    member.offset,
+                    member.offset + member.size,
+                    offset + field_size
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `check_layout` on the wgsl source in debug builds and panics on a mismatch. Call it where the shader
+    /// module is created.
+    pub fn debug_check_layout(wgsl: &str, group: u32, binding: u32) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let result = ShaderReflection::from_wgsl(wgsl)
+            .and_then(|reflection| Self::check_layout(&reflection, group, binding));
+        if let Err(err) = result {
+            panic!("uniform does not match the shader: {err:#}");
+        }
+    }
+}
+
+/// Offset into a `DynamicUniformBuffer`, passed to `RenderPass::set_bind_group`.
+pub type DynamicOffset = u32;
+
+/// Many values of `U` in one uniform buffer, bound with a different offset per draw. An alternative to instance
+/// buffers for per-object data that does not fit into vertex attributes, e.g. the joints of skinned meshes.
+///
+/// ```rust,ignore
+/// // prepare:
+/// let offset = self.uniforms.push(ObjectRaw { .. });
+/// self.uniforms.prepare(device, queue);
+/// // render:
+/// render_pass.set_bind_group(1, self.uniforms.bind_group(), &[offset]);
+/// ```
+///
+/// The slots are allocated from a ring, so a frame does not overwrite the slots of the frame before. The buffer
+/// grows if one frame pushes more values than it has slots, which invalidates the old `bind_group`.
+pub struct DynamicUniformBuffer<U: bytemuck::Pod> {
+    /// bytes between two slots, the size of `U` rounded up to `min_uniform_buffer_offset_alignment`.
+    stride: u32,
+    /// cpu copy of the whole buffer.
+    data: Vec<u8>,
+    ring: SlotRing,
+    /// the whole buffer has to be written, not just the slots of this frame.
+    grown: bool,
+    buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    phantom: PhantomData<U>,
+}
+
+impl<U: bytemuck::Pod> DynamicUniformBuffer<U> {
+    pub fn new(device: &wgpu::Device, visibility: wgpu::ShaderStages, capacity: usize) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment;
+        let stride = dynamic_stride(std::mem::size_of::<U>() as u32, alignment);
+        let capacity = capacity.max(1);
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("DynamicUniformBuffer BindGroupLayout"),
+            entries: &[Self::layout_entry(0, visibility)],
+        });
+        let (buffer, bind_group) = Self::create_buffer(device, &layout, stride, capacity);
+        DynamicUniformBuffer {
+            stride,
+            data: vec![0; stride as usize * capacity],
+            ring: SlotRing::new(capacity),
+            grown: false,
+            buffer,
+            layout,
+            bind_group,
+            phantom: PhantomData,
+        }
+    }
+
+    /// For bind group layouts with more entries than the `DynamicUniformBuffer::bind_group_layout`.
+    pub fn layout_entry(
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<U>() as u64),
+            },
+            count: None,
+        }
+    }
+
+    /// A single uniform at binding 0, with a dynamic offset.
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Binds one slot, for a bind group with a `layout_entry`.
+    pub fn binding(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: wgpu::BufferSize::new(std::mem::size_of::<U>() as u64),
+        })
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn stride(&self) -> u32 {
+        self.stride
+    }
+
+    /// Stores the value in the next free slot and returns its offset, valid until the slot comes around again.
+    pub fn push(&mut self, value: U) -> DynamicOffset {
+        let slot = match self.ring.alloc() {
+            Some(slot) => slot,
+            None => {
+                self.data.resize(self.data.len() * 2, 0);
+                self.grown = true;
+                self.ring.grow()
+            }
+        };
+        let start = slot * self.stride as usize;
+        let bytes = bytemuck::bytes_of(&value);
+        self.data[start..start + bytes.len()].copy_from_slice(bytes);
+        (slot * self.stride as usize) as DynamicOffset
+    }
+
+    /// Uploads the values pushed since the last call.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let stride = self.stride as usize;
+        if self.grown {
+            let capacity = self.data.len() / stride;
+            (self.buffer, self.bind_group) =
+                Self::create_buffer(device, &self.layout, self.stride, capacity);
+            queue.write_buffer(&self.buffer, 0, &self.data);
+            self.grown = false;
+        } else {
+            for slots in self.ring.frame_ranges() {
+                let bytes = slots.start * stride..slots.end * stride;
+                queue.write_buffer(&self.buffer, bytes.start as u64, &self.data[bytes]);
+            }
+        }
+        self.ring.end_frame();
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        stride: u32,
+        capacity: usize,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("DynamicUniformBuffer"),
+            size: stride as u64 * capacity as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("DynamicUniformBuffer BindGroup"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<U>() as u64),
+                }),
+            }],
+        });
+        (buffer, bind_group)
+    }
+}
+
+/// Size of `U` rounded up to the offset alignment.
+fn dynamic_stride(size: u32, alignment: u32) -> u32 {
+    size.div_ceil(alignment) * alignment
+}
+
+/// Hands out slots in a ring, such that the slots of the last frame are reused last.
+#[derive(Debug, Clone)]
+struct SlotRing {
+    capacity: usize,
+    /// first slot of the current frame.
+    frame_start: usize,
+    /// slots allocated in the current frame.
+    used: usize,
+}
+
+impl SlotRing {
+    fn new(capacity: usize) -> Self {
+        SlotRing {
+            capacity,
+            frame_start: 0,
+            used: 0,
+        }
+    }
+
+    /// None if the current frame used up all slots.
+    fn alloc(&mut self) -> Option<usize> {
+        if self.used == self.capacity {
+            return None;
+        }
+        let slot = (self.frame_start + self.used) % self.capacity;
+        self.used += 1;
+        Some(slot)
+    }
+
+    /// Doubles the capacity and returns the first of the new slots. The old slots all count as used in this frame,
+    /// so the slots handed out before stay valid.
+    fn grow(&mut self) -> usize {
+        let slot = self.capacity;
+        self.frame_start = 0;
+        self.used = self.capacity + 1;
+        self.capacity *= 2;
+        slot
+    }
+
+    /// The slots used in the current frame, two ranges if they wrap around.
+    fn frame_ranges(&self) -> impl Iterator<Item = Range<usize>> {
+        let end = self.frame_start + self.used;
+        let (first, second) = match end > self.capacity {
+            true => (self.frame_start..self.capacity, 0..end - self.capacity),
+            false => (self.frame_start..end, 0..0),
+        };
+        [first, second].into_iter().filter(|r| !r.is_empty())
+    }
+
+    fn end_frame(&mut self) {
+        self.frame_start = (self.frame_start + self.used) % self.capacity;
+        self.used = 0;
+    }
+}
+
 pub struct InstanceBuffer<U: ToRaw> {
     values: Vec<U>,
     raw_values: Vec<U::Raw>,
@@ -268,3 +680,100 @@ impl<T: bytemuck::Pod + bytemuck::Zeroable> GrowableBuffer<T> {
         &self.buffer
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable, WgslLayout)]
+    struct LightRaw {
+        position: [f32; 3],
+        radius: f32,
+        color: [f32; 3],
+        _pad: f32,
+        direction: [f32; 2],
+        _pad2: [f32; 2],
+    }
+
+    #[test]
+    fn uniform_layout_matches_shader() {
+        assert_eq!(LightRaw::SIZE, 48);
+        assert_eq!(
+            LightRaw::FIELDS,
+            &[
+                ("position", 0, 12),
+                ("radius", 12, 4),
+                ("color", 16, 12),
+                ("direction", 32, 8)
+            ]
+        );
+
+        let shader = |light: &str| {
+            let wgsl = format!(
+                "struct Light {{ {light} }}
+                @group(0) @binding(0) var<uniform> light: Light;
+                @vertex fn vs_main() -> @builtin(position) vec4<f32> {{ return vec4<f32>(light.position, 1.0); }}"
+            );
+            ShaderReflection::from_wgsl(&wgsl).unwrap()
+        };
+        let matching =
+            shader("position: vec3<f32>, radius: f32, color: vec3<f32>, direction: vec2<f32>");
+        UniformBuffer::<LightRaw>::check_layout(&matching, 0, 0).unwrap();
+
+        // the direction is a vec3 in the shader, so everything after is garbage.
+        let mismatching =
+            shader("position: vec3<f32>, radius: f32, color: vec3<f32>, direction: vec3<f32>");
+        let err = UniformBuffer::<LightRaw>::check_layout(&mismatching, 0, 0).unwrap_err();
+        assert!(err.to_string().contains("direction"), "{err}");
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable, WgslLayout)]
+    struct LightsRaw {
+        count: u32,
+        _pad: [u32; 3],
+        lights: [LightRaw; 4],
+        colors: [[f32; 4]; 2],
+    }
+
+    impl WgslArrayElement for LightRaw {}
+
+    #[test]
+    fn uniform_arrays() {
+        assert_eq!(<[LightRaw; 4]>::SIZE, 4 * 48);
+        assert_eq!(LightsRaw::SIZE, 16 + 4 * 48 + 2 * 16);
+        let wgsl = "struct Light { position: vec3<f32>, radius: f32, color: vec3<f32>, direction: vec2<f32> }
+            struct Lights { count: u32, lights: array<Light, 4>, colors: array<vec4<f32>, 2> }
+            @group(0) @binding(0) var<uniform> lights: Lights;
+            @vertex fn vs_main() -> @builtin(position) vec4<f32> { return lights.colors[0]; }";
+        UniformBuffer::<LightsRaw>::debug_check_layout(wgsl, 0, 0);
+    }
+
+    #[test]
+    fn dynamic_uniform_slots() {
+        assert_eq!(dynamic_stride(80, 256), 256);
+        assert_eq!(dynamic_stride(300, 256), 512);
+
+        let mut ring = SlotRing::new(4);
+        assert_eq!(
+            (ring.alloc(), ring.alloc(), ring.alloc()),
+            (Some(0), Some(1), Some(2))
+        );
+        ring.end_frame();
+        // the next frame continues after the slots of the last one and wraps around.
+        assert_eq!((ring.alloc(), ring.alloc()), (Some(3), Some(0)));
+        let ranges: Vec<_> = ring.frame_ranges().collect();
+        assert_eq!(ranges, vec![3..4, 0..1]);
+        ring.end_frame();
+
+        for _ in 0..4 {
+            ring.alloc().unwrap();
+        }
+        assert_eq!(ring.alloc(), None);
+        assert_eq!(ring.grow(), 4);
+        assert_eq!(ring.alloc(), Some(5));
+        ring.end_frame();
+        assert_eq!(ring.alloc(), Some(6));
+    }
+}
@@ -1,8 +1,15 @@
 use glam::{vec2, vec3, Mat4, Vec2, Vec3};
 
-use crate::{elements::ToRaw, modules::GraphicsContext, Resize};
+use crate::{
+    elements::{ToRaw, WgslLayout},
+    modules::GraphicsContext,
+    Resize,
+};
 
-use super::UniformBuffer;
+use super::{
+    geometry::{Frustum, Ray},
+    Rect, Screen, UniformBuffer,
+};
 
 pub struct Camera3dGR {
     uniform: UniformBuffer<Camera3dRaw>,
@@ -54,12 +61,27 @@ impl Camera3dGR {
     pub fn prepare(&mut self, queue: &wgpu::Queue, camera: &Camera3d) {
         self.uniform.update_and_prepare(camera.to_raw(), queue)
     }
+
+    /// For views that a `Camera3d` can not describe, e.g. the faces of a cubemap.
+    pub fn prepare_view_proj(&mut self, queue: &wgpu::Queue, view_position: Vec3, view_proj: Mat4) {
+        let raw = Camera3dRaw {
+            view_position: view_position.extend(1.0).into(),
+            view_proj: view_proj.to_cols_array_2d(),
+        };
+        self.uniform.update_and_prepare(raw, queue)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Camera3d {
     pub transform: Camera3DTransform,
     pub projection: Projection,
+    /// Region of the window (physical pixels) this camera renders to. None means the viewport of the `Screen`.
+    pub viewport: Option<Rect>,
+    /// Scales the hdr colors before tone mapping, see `Exposure`.
+    pub exposure: Exposure,
+    /// In stops, added on top of the exposure: +1 is twice as bright.
+    pub exposure_compensation: f32,
 }
 
 impl Camera3d {
@@ -70,18 +92,99 @@ impl Camera3d {
         Self {
             transform,
             projection,
+            viewport: None,
+            exposure: Exposure::default(),
+            exposure_compensation: 0.0,
         }
     }
 
-    pub fn ray_from_screen_pos(&self, mut screen_pos: Vec2) -> Ray {
-        let projection = &self.projection;
-        let transform = &self.transform;
+    /// The factor the hdr colors are multiplied with before tone mapping.
+    pub fn exposure_scale(&self) -> f32 {
+        self.exposure.scale() * 2f32.powf(self.exposure_compensation)
+    }
+
+    pub fn viewport_or_screen(&self, screen: &Screen) -> Rect {
+        self.viewport.unwrap_or_else(|| screen.viewport())
+    }
+
+    /// Sets the projection size to the size of the viewport, such that the aspect ratio is right.
+    pub fn fit_to_viewport(&mut self, screen: &Screen) {
+        let viewport = self.viewport_or_screen(screen);
+        let (width, height) = (viewport.width as u32, viewport.height as u32);
+        if width != self.projection.width || height != self.projection.height {
+            self.projection.resize(width.max(1), height.max(1));
+        }
+    }
+
+    /// For orthographic cameras in `AspectMode::PixelPerfect`: moves the camera to the closest multiple of a
+    /// virtual pixel in its view plane, such that sprites do not shimmer when the camera moves. Call after moving
+    /// the camera each frame.
+    pub fn snap_to_pixel_grid(&mut self, virtual_height: u32) {
+        let ProjectionKind::Orthographic { y_height } = self.projection.kind else {
+            return;
+        };
+        let units_per_pixel = y_height / virtual_height.max(1) as f32;
+        let right = self.transform.right();
+        let up = right.cross(self.transform.forward()).normalize_or_zero();
+        for axis in [right, up] {
+            let along = self.transform.pos.dot(axis);
+            let snapped = (along / units_per_pixel).round() * units_per_pixel;
+            self.transform.pos += axis * (snapped - along);
+        }
+    }
+
+    /// Projection * view, maps world positions into clip space.
+    pub fn view_proj(&self) -> Mat4 {
+        self.projection.calc_matrix() * self.transform.calc_matrix()
+    }
+
+    /// For culling, everything outside of it is not visible.
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_proj(self.view_proj())
+    }
 
-        let screen_size = vec2(projection.width as f32, projection.height as f32);
-        // flip the y:
-        screen_pos.y = screen_size.y - screen_pos.y;
-        let ndc = screen_pos * 2.0 / screen_size - Vec2::ONE;
-        let ndc_to_world = transform.calc_matrix().inverse() * projection.calc_matrix().inverse();
+    // Coordinate spaces: "screen" positions are in pixels relative to the top left corner of the viewport of this
+    // camera, "window" positions relative to the window (like the cursor position) and "ui" positions are in the
+    // coordinates of the ui board (see `Screen::window_to_ui`). NDC are -1 to 1 with y up.
+
+    /// `screen_pos` in pixels relative to the top left corner of the viewport, to normalized device coordinates.
+    pub fn viewport_to_ndc(&self, screen_pos: Vec2) -> Vec2 {
+        let size = self.projection_size();
+        vec2(screen_pos.x / size.x, 1.0 - screen_pos.y / size.y) * 2.0 - Vec2::ONE
+    }
+
+    pub fn ndc_to_viewport(&self, ndc: Vec2) -> Vec2 {
+        let relative = (ndc + Vec2::ONE) * 0.5;
+        vec2(relative.x, 1.0 - relative.y) * self.projection_size()
+    }
+
+    /// x and y in NDC, z is the depth (0 at the near plane, 1 at the far plane). None if behind the camera.
+    pub fn world_to_ndc(&self, world_pos: Vec3) -> Option<Vec3> {
+        let clip = self.view_proj() * world_pos.extend(1.0);
+        (clip.w > 0.0).then(|| clip.truncate() / clip.w)
+    }
+
+    /// Pixel position relative to the viewport, e.g. for a health bar above a head. Can be outside of the viewport,
+    /// e.g. for arrows pointing to off-screen targets. None if behind the camera.
+    pub fn world_to_screen(&self, world_pos: Vec3) -> Option<Vec2> {
+        Some(self.ndc_to_viewport(self.world_to_ndc(world_pos)?.truncate()))
+    }
+
+    /// Like `world_to_screen`, but relative to the window.
+    pub fn world_to_window(&self, world_pos: Vec3, screen: &Screen) -> Option<Vec2> {
+        let viewport = self.viewport_or_screen(screen);
+        Some(self.world_to_screen(world_pos)? + vec2(viewport.min_x, viewport.min_y))
+    }
+
+    /// Position in the coordinates of the ui board. None if behind the camera or outside of the viewport of the screen.
+    pub fn world_to_ui(&self, world_pos: Vec3, screen: &Screen) -> Option<Vec2> {
+        screen.window_to_ui(self.world_to_window(world_pos, screen)?)
+    }
+
+    /// `screen_pos` is relative to the top left corner of the viewport of this camera.
+    pub fn screen_to_world_ray(&self, screen_pos: Vec2) -> Ray {
+        let ndc = self.viewport_to_ndc(screen_pos);
+        let ndc_to_world = self.view_proj().inverse();
         let world_far_plane = ndc_to_world.project_point3(ndc.extend(1.));
         let world_near_plane = ndc_to_world.project_point3(ndc.extend(f32::EPSILON));
 
@@ -95,6 +198,72 @@ impl Camera3d {
             direction,
         }
     }
+
+    /// Like `screen_to_world_ray`, but `window_pos` is relative to the window, e.g. the cursor position for click to move.
+    pub fn window_to_world_ray(&self, window_pos: Vec2, screen: &Screen) -> Ray {
+        let viewport = self.viewport_or_screen(screen);
+        self.screen_to_world_ray(window_pos - vec2(viewport.min_x, viewport.min_y))
+    }
+
+    /// Like `screen_to_world_ray`, but `ui_pos` is in the coordinates of the ui board.
+    pub fn ui_to_world_ray(&self, ui_pos: Vec2, screen: &Screen) -> Ray {
+        self.window_to_world_ray(screen.ui_to_window(ui_pos), screen)
+    }
+
+    fn projection_size(&self) -> Vec2 {
+        vec2(self.projection.width as f32, self.projection.height as f32)
+    }
+}
+
+/// Exposure of a camera, such that light values can be given in physical units (cd/m²) and a scene looks the same
+/// with any tone mapper. The default is neutral: hdr colors are passed to tone mapping unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exposure {
+    /// Exposure value at ISO 100. Higher values are for brighter scenes, e.g. 15 for a sunny day, 7 indoors.
+    Ev100(f32),
+    /// Settings of a physical camera.
+    Physical {
+        /// f-number, e.g. 16 for f/16.
+        aperture: f32,
+        /// in seconds, e.g. 0.01 for 1/100 s.
+        shutter_speed: f32,
+        iso: f32,
+    },
+}
+
+impl Exposure {
+    /// EV100 at which a luminance of 1 is mapped to 1.
+    pub const NEUTRAL_EV100: f32 = -0.2630344;
+    pub const SUNNY: Exposure = Exposure::Ev100(15.0);
+    pub const OVERCAST: Exposure = Exposure::Ev100(12.0);
+    pub const INDOOR: Exposure = Exposure::Ev100(7.0);
+    pub const NIGHT: Exposure = Exposure::Ev100(0.0);
+
+    pub fn ev100(&self) -> f32 {
+        match *self {
+            Exposure::Ev100(ev100) => ev100,
+            Exposure::Physical {
+                aperture,
+                shutter_speed,
+                iso,
+            } => {
+                (aperture * aperture / shutter_speed.max(f32::EPSILON)).log2()
+                    - (iso / 100.0).log2()
+            }
+        }
+    }
+
+    /// The factor for luminance values: 1 / maximum luminance that does not saturate the sensor, which is
+    /// `1.2 * 2^EV100` (with the usual calibration constant of 12.5 and a lens attenuation of 0.65).
+    pub fn scale(&self) -> f32 {
+        1.0 / (1.2 * 2f32.powf(self.ev100()))
+    }
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Exposure::Ev100(Exposure::NEUTRAL_EV100)
+    }
 }
 
 impl Resize for Camera3d {
@@ -219,31 +388,6 @@ impl Projection {
     }
 }
 
-pub struct Ray {
-    pub origin: Vec3,
-    pub direction: Vec3,
-}
-
-impl Ray {
-    /// Shout out to bevy_math
-    pub fn intersect_plane(&self, plane_origin: Vec3, plane_normal: Vec3) -> Option<f32> {
-        let denominator = plane_normal.dot(self.direction);
-        if denominator.abs() > f32::EPSILON {
-            let distance = (plane_origin - self.origin).dot(plane_normal) / denominator;
-            if distance > f32::EPSILON {
-                return Some(distance);
-            }
-        }
-        None
-    }
-
-    /// Shout out to bevy_math
-    #[inline]
-    pub fn get_point(&self, distance: f32) -> Vec3 {
-        self.origin + self.direction * distance
-    }
-}
-
 impl ToRaw for Camera3d {
     type Raw = Camera3dRaw;
 
@@ -253,7 +397,7 @@ impl ToRaw for Camera3d {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable, WgslLayout)]
 pub struct Camera3dRaw {
     view_position: [f32; 4],
     view_proj: [[f32; 4]; 4],
@@ -275,3 +419,41 @@ impl Camera3dRaw {
         self.view_proj = (projection.calc_matrix() * camera.calc_matrix()).to_cols_array_2d();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exposure_units() {
+        assert!((Exposure::default().scale() - 1.0).abs() < 1e-5);
+        // sunny 16: f/16, 1/100 s at ISO 100.
+        let sunny_16 = Exposure::Physical {
+            aperture: 16.0,
+            shutter_speed: 0.01,
+            iso: 100.0,
+        };
+        assert!((sunny_16.ev100() - 14.64).abs() < 0.01);
+        // doubling the iso is one stop brighter.
+        let iso_200 = Exposure::Physical {
+            aperture: 16.0,
+            shutter_speed: 0.01,
+            iso: 200.0,
+        };
+        assert!((iso_200.scale() / sunny_16.scale() - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn world_screen_round_trip() {
+        let camera = Camera3d::new(800, 600);
+        let target = vec3(3.0, 2.0, 1.5);
+        let screen_pos = camera.world_to_screen(target).unwrap();
+        let ray = camera.screen_to_world_ray(screen_pos);
+        let closest = ray.origin + ray.direction * (target - ray.origin).dot(ray.direction);
+        assert!(closest.distance(target) < 1e-3);
+        // the camera looks along +x.
+        assert!(camera.world_to_screen(vec3(-10.0, 1.0, 0.0)).is_none());
+        let center = camera.world_to_screen(vec3(5.0, 1.0, 0.0)).unwrap();
+        assert!(center.distance(vec2(400.0, 300.0)) < 1e-3);
+    }
+}
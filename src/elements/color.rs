@@ -41,33 +41,33 @@ impl Color {
         Color { r, g, b, a: 1.0 }
     }
 
+    /// Parses "#rgb", "#rrggbb" or "#rrggbbaa" (the # is optional). Panics if the string is not valid hex.
     pub fn from_hex(hex: &str) -> Color {
-        const fn hex_digit_value(c: char) -> u8 {
-            match c {
-                '0'..='9' => c as u8 - b'0',
-                'a'..='f' => c as u8 - b'a' + 10,
-                'A'..='F' => c as u8 - b'A' + 10,
-                _ => 0,
-            }
-        }
-
-        const fn parse_hex_pair(s: &str, start: usize) -> u8 {
-            16 * hex_digit_value(s.as_bytes()[start] as char)
-                + hex_digit_value(s.as_bytes()[start + 1] as char)
-        }
+        Self::try_from_hex(hex)
+            .unwrap_or_else(|| panic!("Cannot create Color from hex string {hex}"))
+    }
 
-        if hex.as_bytes()[0] != "#".as_bytes()[0] {
-            panic!("Hex string needs to start with #")
-        }
+    /// Parses "#rgb", "#rrggbb" or "#rrggbbaa" (the # is optional).
+    pub fn try_from_hex(hex: &str) -> Option<Color> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        let digit = |i: usize| -> Option<u8> {
+            let c = *hex.as_bytes().get(i)? as char;
+            c.to_digit(16).map(|d| d as u8)
+        };
+        let pair = |i: usize| -> Option<u8> { Some(digit(i)? * 16 + digit(i + 1)?) };
+        let [r, g, b, a] = match hex.len() {
+            3 => [digit(0)? * 17, digit(1)? * 17, digit(2)? * 17, 255],
+            6 => [pair(0)?, pair(2)?, pair(4)?, 255],
+            8 => [pair(0)?, pair(2)?, pair(4)?, pair(6)?],
+            _ => return None,
+        };
+        Some(Self::u8_srgb(r, g, b).alpha(a as f32 / 255.0))
+    }
 
-        if hex.len() == 7 {
-            let r = color_map_to_srgb(parse_hex_pair(hex, 1));
-            let g = color_map_to_srgb(parse_hex_pair(hex, 3));
-            let b = color_map_to_srgb(parse_hex_pair(hex, 5));
-            Color { r, g, b, a: 1.0 }
-        } else {
-            panic!("Cannot create Color! Expects a hex string")
-        }
+    /// "#rrggbbaa"
+    pub fn to_hex(&self) -> String {
+        let [r, g, b, a] = self.to_u8_srgb();
+        format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
     }
 
     /// creates colors from rgb and maps them into srgb space
@@ -85,6 +85,291 @@ impl Color {
     pub const fn alpha(self, a: f32) -> Self {
         Self { a, ..self }
     }
+
+    /// Inverse of `u8_srgb`, alpha is mapped linearly.
+    pub fn to_u8_srgb(&self) -> [u8; 4] {
+        [
+            color_map_from_srgb(self.r),
+            color_map_from_srgb(self.g),
+            color_map_from_srgb(self.b),
+            (self.a.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ]
+    }
+
+    /// Components mapped to the non-linear srgb curve, in range 0.0 to 1.0. Hsv and Hsl are defined on these.
+    fn to_gamma(self) -> [f32; 3] {
+        [self.r, self.g, self.b].map(|c| {
+            let c = c.clamp(0.0, 1.0);
+            if c <= 0.0031308 {
+                12.92 * c
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            }
+        })
+    }
+
+    /// Inverse of `to_gamma`.
+    fn from_gamma(rgb: [f32; 3], a: f32) -> Self {
+        let [r, g, b] = rgb.map(|c| {
+            let c = c.clamp(0.0, 1.0);
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        Color { r, g, b, a }
+    }
+
+    /// `hue` in degrees (0.0 to 360.0), saturation and value in range 0.0 to 1.0.
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+        let chroma = value * saturation;
+        Self::from_gamma(hue_to_rgb(hue, chroma, value - chroma), 1.0)
+    }
+
+    /// Returns [hue, saturation, value], see `from_hsv`.
+    pub fn to_hsv(&self) -> [f32; 3] {
+        let [r, g, b] = self.to_gamma();
+        let max = r.max(g).max(b);
+        let chroma = max - r.min(g).min(b);
+        let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+        [
+            rgb_to_hue(r, g, b, max, chroma),
+            saturation.clamp(0.0, 1.0),
+            max,
+        ]
+    }
+
+    /// `hue` in degrees (0.0 to 360.0), saturation and lightness in range 0.0 to 1.0.
+    pub fn from_hsl(hue: f32, saturation: f32, lightness: f32) -> Self {
+        let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+        Self::from_gamma(hue_to_rgb(hue, chroma, lightness - chroma * 0.5), 1.0)
+    }
+
+    /// Returns [hue, saturation, lightness], see `from_hsl`.
+    pub fn to_hsl(&self) -> [f32; 3] {
+        let [r, g, b] = self.to_gamma();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let chroma = max - min;
+        let lightness = (max + min) * 0.5;
+        let saturation = if lightness == 0.0 || lightness == 1.0 {
+            0.0
+        } else {
+            chroma / (1.0 - (2.0 * lightness - 1.0).abs())
+        };
+        [
+            rgb_to_hue(r, g, b, max, chroma),
+            saturation.clamp(0.0, 1.0),
+            lightness,
+        ]
+    }
+
+    /// Returns [L, a, b] in the perceptual OKLab color space.
+    #[allow(clippy::excessive_precision)]
+    pub fn to_oklab(&self) -> [f32; 3] {
+        let l = 0.4122214708 * self.r + 0.5363325363 * self.g + 0.0514459929 * self.b;
+        let m = 0.2119034982 * self.r + 0.6806995451 * self.g + 0.1073969566 * self.b;
+        let s = 0.0883024619 * self.r + 0.2817188376 * self.g + 0.6299787005 * self.b;
+        let (l, m, s) = (l.cbrt(), m.cbrt(), s.cbrt());
+        [
+            0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+        ]
+    }
+
+    #[allow(clippy::excessive_precision)]
+    pub fn from_oklab(lab: [f32; 3]) -> Self {
+        let [l, a, b] = lab;
+        let l_ = l + 0.3963377774 * a + 0.2158037573 * b;
+        let m_ = l - 0.1055613458 * a - 0.0638541728 * b;
+        let s_ = l - 0.0894841775 * a - 1.2914855480 * b;
+        let (l, m, s) = (l_ * l_ * l_, m_ * m_ * m_, s_ * s_ * s_);
+        Color {
+            r: 4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+            g: -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+            b: -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+            a: 1.0,
+        }
+    }
+
+    /// Returns [lightness, chroma, hue in degrees] in the OKLCH color space (polar form of OKLab).
+    pub fn to_oklch(&self) -> [f32; 3] {
+        let [l, a, b] = self.to_oklab();
+        let hue = b.atan2(a).to_degrees().rem_euclid(360.0);
+        [l, (a * a + b * b).sqrt(), hue]
+    }
+
+    pub fn from_oklch(lch: [f32; 3]) -> Self {
+        let [l, chroma, hue] = lch;
+        let (sin, cos) = hue.to_radians().sin_cos();
+        Self::from_oklab([l, chroma * cos, chroma * sin])
+    }
+
+    /// Interpolates in OKLab space, which looks more even to the eye than the component wise `Lerp`.
+    pub fn lerp_perceptual(&self, other: &Self, factor: f32) -> Self {
+        let a = self.to_oklab();
+        let b = other.to_oklab();
+        let lab = [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * factor);
+        Self::from_oklab(lab).alpha(self.a + (other.a - self.a) * factor)
+    }
+}
+
+/// The 16 colors of the PICO-8 palette.
+pub mod palette {
+    use super::Color;
+
+    /// #000000
+    pub const BLACK: Color = Color::new(0.0008338, 0.0008338, 0.0008338);
+    /// #1D2B53
+    pub const DARK_BLUE: Color = Color::new(0.01229, 0.02416, 0.0865);
+    /// #7E2553
+    pub const DARK_PURPLE: Color = Color::new(0.2086, 0.0185, 0.0865);
+    /// #008751
+    pub const DARK_GREEN: Color = Color::new(0.0008338, 0.2423, 0.08228);
+    /// #AB5236
+    pub const BROWN: Color = Color::new(0.4072, 0.08438, 0.03689);
+    /// #5F574F
+    pub const DARK_GREY: Color = Color::new(0.1144, 0.09531, 0.07819);
+    /// #C2C3C7
+    pub const LIGHT_GREY: Color = Color::new(0.5395, 0.5457, 0.5711);
+    /// #FFF1E8
+    pub const WHITE: Color = Color::new(1.0, 0.8796, 0.807);
+    /// #FF004D
+    pub const RED: Color = Color::new(1.0, 0.0008338, 0.07421);
+    /// #FFA300
+    pub const ORANGE: Color = Color::new(1.0, 0.3663, 0.0008338);
+    /// #FFEC27
+    pub const YELLOW: Color = Color::new(1.0, 0.8388, 0.02029);
+    /// #00E436
+    pub const GREEN: Color = Color::new(0.0008338, 0.7758, 0.03689);
+    /// #29ADFF
+    pub const BLUE: Color = Color::new(0.02217, 0.4179, 1.0);
+    /// #83769C
+    pub const LAVENDER: Color = Color::new(0.227, 0.1812, 0.3325);
+    /// #FF77A8
+    pub const PINK: Color = Color::new(1.0, 0.1845, 0.3916);
+    /// #FFCCAA
+    pub const PEACH: Color = Color::new(1.0, 0.6038, 0.402);
+
+    pub const ALL: [Color; 16] = [
+        BLACK,
+        DARK_BLUE,
+        DARK_PURPLE,
+        DARK_GREEN,
+        BROWN,
+        DARK_GREY,
+        LIGHT_GREY,
+        WHITE,
+        RED,
+        ORANGE,
+        YELLOW,
+        GREEN,
+        BLUE,
+        LAVENDER,
+        PINK,
+        PEACH,
+    ];
+}
+
+/// How a `Gradient` blends between its stops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GradientInterpolation {
+    /// Component wise in linear color space.
+    #[default]
+    Linear,
+    /// In OKLab space, see `Color::lerp_perceptual`.
+    Perceptual,
+}
+
+/// Multiple colors at positions (usually 0.0 to 1.0), e.g. for the color of particles over their lifetime.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    /// sorted by position
+    stops: Vec<(f32, Color)>,
+    pub interpolation: GradientInterpolation,
+}
+
+impl Gradient {
+    /// `stops` do not need to be sorted. Panics if there are no stops.
+    pub fn new(mut stops: Vec<(f32, Color)>) -> Self {
+        assert!(!stops.is_empty(), "Gradient needs at least one stop");
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Gradient {
+            stops,
+            interpolation: GradientInterpolation::default(),
+        }
+    }
+
+    /// Stops evenly spaced between 0.0 and 1.0.
+    pub fn even(colors: &[Color]) -> Self {
+        let step = 1.0 / (colors.len().max(2) - 1) as f32;
+        Self::new(
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (i as f32 * step, *c))
+                .collect(),
+        )
+    }
+
+    pub fn perceptual(mut self) -> Self {
+        self.interpolation = GradientInterpolation::Perceptual;
+        self
+    }
+
+    pub fn stops(&self) -> &[(f32, Color)] {
+        &self.stops
+    }
+
+    /// Color at position `t`, clamped to the first and last stop.
+    pub fn sample(&self, t: f32) -> Color {
+        let first = self.stops[0];
+        if t <= first.0 {
+            return first.1;
+        }
+        for window in self.stops.windows(2) {
+            let (start, end) = (window[0], window[1]);
+            if t <= end.0 {
+                let factor = (t - start.0) / (end.0 - start.0).max(f32::EPSILON);
+                return match self.interpolation {
+                    GradientInterpolation::Linear => start.1.lerp(&end.1, factor),
+                    GradientInterpolation::Perceptual => start.1.lerp_perceptual(&end.1, factor),
+                };
+            }
+        }
+        self.stops.last().unwrap().1
+    }
+}
+
+/// `offset` is added to every component, to shift by the lightness/value.
+fn hue_to_rgb(hue: f32, chroma: f32, offset: f32) -> [f32; 3] {
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let [r, g, b] = match h as u32 {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    };
+    [r + offset, g + offset, b + offset]
+}
+
+fn rgb_to_hue(r: f32, g: f32, b: f32, max: f32, chroma: f32) -> f32 {
+    if chroma == 0.0 {
+        return 0.0;
+    }
+    let h = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    h * 60.0
 }
 
 /// srgb_color = ((rgb_color / 255 + 0.055) / 1.055) ^ 2.4
@@ -93,6 +378,14 @@ pub fn color_map_to_srgb(u: u8) -> f32 {
     ((u as f32 / 255.0 + 0.055) / 1.055).powf(2.4)
 }
 
+/// Inverse of `color_map_to_srgb`.
+#[inline]
+pub fn color_map_from_srgb(c: f32) -> u8 {
+    ((c.clamp(0.0, 1.0).powf(1.0 / 2.4) * 1.055 - 0.055) * 255.0)
+        .round()
+        .clamp(0.0, 255.0) as u8
+}
+
 impl From<Color> for wgpu::Color {
     fn from(value: Color) -> Self {
         wgpu::Color {
@@ -136,3 +429,36 @@ impl From<Vec4> for Color {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < 1e-3, "{a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn hsv_hsl_round_trip() {
+        assert_eq!(Color::BLACK.to_hsv(), [0.0, 0.0, 0.0]);
+        assert_close(Color::WHITE.to_hsl(), [0.0, 0.0, 1.0]);
+        for hsv in [
+            [0.0, 1.0, 1.0],
+            [120.0, 0.5, 0.25],
+            [210.0, 0.2, 0.9],
+            [300.0, 1.0, 0.01],
+        ] {
+            let [h, s, v] = hsv;
+            assert_close(Color::from_hsv(h, s, v).to_hsv(), hsv);
+        }
+        for hsl in [[30.0, 1.0, 0.5], [180.0, 0.3, 0.2], [270.0, 0.8, 0.95]] {
+            let [h, s, l] = hsl;
+            assert_close(Color::from_hsl(h, s, l).to_hsl(), hsl);
+        }
+        // hdr and negative components are clamped.
+        let [_, saturation, value] = Color::new(4.0, -1.0, 0.5).to_hsv();
+        assert!(saturation <= 1.0 && value <= 1.0);
+    }
+}
@@ -0,0 +1,290 @@
+//! Splines for camera rails, moving platforms and road or track generation.
+//!
+//! The parameter of a spline does not move at constant speed along it: control points that are close together are
+//! passed faster. `Curve3d` keeps a table of arc lengths, such that it can also be sampled by distance.
+
+use glam::{Mat3, Quat, Vec3};
+
+use super::Transform;
+
+/// Samples per segment for the arc length table.
+const SAMPLES_PER_SEGMENT: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurveKind {
+    /// Cubic bezier segments: the points are `start, control, control, end, control, control, end, ...`,
+    /// the end of a segment is the start of the next one.
+    Bezier,
+    /// Passes through all points. If closed, the last point connects back to the first one.
+    CatmullRom { closed: bool },
+}
+
+/// Position and orientation on a curve. `tangent`, `normal` and `binormal` are orthonormal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurveFrame {
+    pub position: Vec3,
+    /// direction of the curve.
+    pub tangent: Vec3,
+    /// as close to the up vector as possible, e.g. the up direction of a road.
+    pub normal: Vec3,
+    /// `tangent.cross(normal)`, to the right of the curve when looking along it with the normal up.
+    pub binormal: Vec3,
+}
+
+impl CurveFrame {
+    /// Looking along the tangent (-z forward) with the normal up, e.g. for a camera on a rail.
+    pub fn transform(&self) -> Transform {
+        let rotation = Quat::from_mat3(&Mat3::from_cols(self.binormal, self.normal, -self.tangent));
+        Transform::from_translation(self.position).with_rotation(rotation)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Curve3d {
+    kind: CurveKind,
+    points: Vec<Vec3>,
+    /// arc length from the start to each sample, `SAMPLES_PER_SEGMENT` per segment plus the end.
+    lengths: Vec<f32>,
+}
+
+impl Curve3d {
+    /// `points.len()` needs to be `3 * segments + 1`.
+    pub fn bezier(points: Vec<Vec3>) -> Self {
+        assert!(
+            points.len() >= 4 && (points.len() - 1).is_multiple_of(3),
+            "a bezier curve needs 3 * segments + 1 points, got {}",
+            points.len()
+        );
+        Self::new(CurveKind::Bezier, points)
+    }
+
+    /// Needs at least 2 points.
+    pub fn catmull_rom(points: Vec<Vec3>, closed: bool) -> Self {
+        assert!(
+            points.len() >= 2,
+            "a catmull-rom curve needs at least 2 points"
+        );
+        Self::new(CurveKind::CatmullRom { closed }, points)
+    }
+
+    fn new(kind: CurveKind, points: Vec<Vec3>) -> Self {
+        let mut curve = Curve3d {
+            kind,
+            points,
+            lengths: vec![],
+        };
+        curve.compute_lengths();
+        curve
+    }
+
+    pub fn kind(&self) -> CurveKind {
+        self.kind
+    }
+
+    pub fn points(&self) -> &[Vec3] {
+        &self.points
+    }
+
+    /// Moves a point, e.g. while editing a track.
+    pub fn set_point(&mut self, index: usize, point: Vec3) {
+        self.points[index] = point;
+        self.compute_lengths();
+    }
+
+    pub fn segment_count(&self) -> usize {
+        match self.kind {
+            CurveKind::Bezier => (self.points.len() - 1) / 3,
+            CurveKind::CatmullRom { closed: false } => self.points.len() - 1,
+            CurveKind::CatmullRom { closed: true } => self.points.len(),
+        }
+    }
+
+    /// Approximated with straight lines between `SAMPLES_PER_SEGMENT` samples per segment.
+    pub fn length(&self) -> f32 {
+        *self.lengths.last().unwrap()
+    }
+
+    /// `t` from 0 to 1 over the whole curve, not at constant speed. See `position_at_distance`.
+    pub fn position(&self, t: f32) -> Vec3 {
+        let (segment, t) = self.segment_t(t);
+        let [p0, p1, p2, p3] = self.segment_points(segment);
+        match self.kind {
+            CurveKind::Bezier => {
+                let s = 1.0 - t;
+                p0 * (s * s * s)
+                    + p1 * (3.0 * s * s * t)
+                    + p2 * (3.0 * s * t * t)
+                    + p3 * (t * t * t)
+            }
+            CurveKind::CatmullRom { .. } => {
+                let (t2, t3) = (t * t, t * t * t);
+                0.5 * (2.0 * p1
+                    + (p2 - p0) * t
+                    + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+                    + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+            }
+        }
+    }
+
+    /// Normalized direction at `t`. Zero where the curve stops, e.g. at coinciding control points.
+    pub fn tangent(&self, t: f32) -> Vec3 {
+        let (segment, t) = self.segment_t(t);
+        let [p0, p1, p2, p3] = self.segment_points(segment);
+        let derivative = match self.kind {
+            CurveKind::Bezier => {
+                let s = 1.0 - t;
+                3.0 * s * s * (p1 - p0) + 6.0 * s * t * (p2 - p1) + 3.0 * t * t * (p3 - p2)
+            }
+            CurveKind::CatmullRom { .. } => {
+                0.5 * ((p2 - p0)
+                    + 2.0 * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t
+                    + 3.0 * (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t)
+            }
+        };
+        derivative.normalize_or_zero()
+    }
+
+    /// The `t` at which the curve is `distance` long, clamped to the curve.
+    pub fn t_at_distance(&self, distance: f32) -> f32 {
+        let samples = self.lengths.len() - 1;
+        let distance = distance.clamp(0.0, self.length());
+        // index of the first sample at or after the distance.
+        let i = self
+            .lengths
+            .partition_point(|l| *l < distance)
+            .clamp(1, samples);
+        let (before, after) = (self.lengths[i - 1], self.lengths[i]);
+        let fraction = match after > before {
+            true => (distance - before) / (after - before),
+            false => 0.0,
+        };
+        (i - 1) as f32 / samples as f32 + fraction / samples as f32
+    }
+
+    pub fn position_at_distance(&self, distance: f32) -> Vec3 {
+        self.position(self.t_at_distance(distance))
+    }
+
+    /// Frame with the normal as close to `up` as possible. For a closed loop like a rollercoaster, pick `up` such
+    /// that the curve is never parallel to it.
+    pub fn frame_at_distance(&self, distance: f32, up: Vec3) -> CurveFrame {
+        let t = self.t_at_distance(distance);
+        let mut tangent = self.tangent(t);
+        if tangent == Vec3::ZERO {
+            // a stop, look a little further.
+            let step = 1.0 / self.lengths.len() as f32;
+            tangent = (self.position((t + step).min(1.0)) - self.position((t - step).max(0.0)))
+                .normalize_or_zero();
+        }
+        let mut binormal = tangent.cross(up).normalize_or_zero();
+        if binormal == Vec3::ZERO {
+            binormal = tangent.any_orthonormal_vector();
+        }
+        CurveFrame {
+            position: self.position(t),
+            tangent,
+            normal: binormal.cross(tangent),
+            binormal,
+        }
+    }
+
+    /// `count` points at equal distances along the curve, including both ends. E.g. for placing fence posts.
+    pub fn equidistant_points(&self, count: usize) -> Vec<Vec3> {
+        let step = self.length() / (count.max(2) - 1) as f32;
+        (0..count)
+            .map(|i| self.position_at_distance(i as f32 * step))
+            .collect()
+    }
+
+    /// Points for drawing the curve as a line strip, `SAMPLES_PER_SEGMENT` per segment.
+    pub fn polyline(&self) -> Vec<Vec3> {
+        let samples = self.lengths.len() - 1;
+        (0..=samples)
+            .map(|i| self.position(i as f32 / samples as f32))
+            .collect()
+    }
+
+    /// Segment index and `t` within the segment.
+    fn segment_t(&self, t: f32) -> (usize, f32) {
+        let segments = self.segment_count();
+        let global = t.clamp(0.0, 1.0) * segments as f32;
+        let segment = (global as usize).min(segments - 1);
+        (segment, global - segment as f32)
+    }
+
+    fn segment_points(&self, segment: usize) -> [Vec3; 4] {
+        let points = &self.points;
+        match self.kind {
+            CurveKind::Bezier => {
+                let i = segment * 3;
+                [points[i], points[i + 1], points[i + 2], points[i + 3]]
+            }
+            CurveKind::CatmullRom { closed } => {
+                let n = points.len() as isize;
+                let point = |i: isize| match closed {
+                    true => points[i.rem_euclid(n) as usize],
+                    // the end points are repeated.
+                    false => points[i.clamp(0, n - 1) as usize],
+                };
+                let i = segment as isize;
+                [point(i - 1), point(i), point(i + 1), point(i + 2)]
+            }
+        }
+    }
+
+    fn compute_lengths(&mut self) {
+        let samples = self.segment_count() * SAMPLES_PER_SEGMENT;
+        self.lengths.clear();
+        self.lengths.push(0.0);
+        let mut previous = self.position(0.0);
+        let mut length = 0.0;
+        for i in 1..=samples {
+            let position = self.position(i as f32 / samples as f32);
+            length += position.distance(previous);
+            self.lengths.push(length);
+            previous = position;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+
+    #[test]
+    fn constant_speed_sampling() {
+        // a straight line, but the control points bunch up at the start: the parameter is slow there.
+        let line = Curve3d::bezier(vec![
+            Vec3::ZERO,
+            vec3(0.1, 0.0, 0.0),
+            vec3(0.2, 0.0, 0.0),
+            vec3(10.0, 0.0, 0.0),
+        ]);
+        assert!((line.length() - 10.0).abs() < 1e-3);
+        assert!(line.position(0.5).x < 4.0);
+        for (i, p) in line.equidistant_points(6).iter().enumerate() {
+            assert!((p.x - 2.0 * i as f32).abs() < 0.05, "{i}: {p}");
+        }
+
+        let points = vec![
+            Vec3::ZERO,
+            vec3(4.0, 0.0, 0.0),
+            vec3(4.0, 0.0, -4.0),
+            vec3(0.0, 0.0, -4.0),
+        ];
+        let loop_curve = Curve3d::catmull_rom(points.clone(), true);
+        assert_eq!(loop_curve.segment_count(), 4);
+        // passes through its points and ends where it started.
+        assert!(loop_curve.position(0.25).distance(points[1]) < 1e-5);
+        assert!(loop_curve.position(1.0).distance(points[0]) < 1e-5);
+
+        let frame = loop_curve.frame_at_distance(0.0, Vec3::Y);
+        assert!(frame.normal.dot(Vec3::Y) > 0.99);
+        assert!(frame.tangent.x > 0.7);
+        let transform = frame.transform();
+        assert!(transform.forward().distance(frame.tangent) < 1e-4);
+        assert!(transform.up().distance(frame.normal) < 1e-4);
+    }
+}
@@ -0,0 +1,364 @@
+//! 3d primitives with intersection and containment tests, for culling, picking, broad-phase collision and gizmos.
+//! 2d rects for the ui are `rect::Aabb` and `Rect`.
+
+use glam::{Affine3A, Mat3, Mat4, Quat, Vec3, Vec4};
+
+use super::Transform;
+
+/// All points `p` with `normal.dot(p) + d == 0`. The normal points to the positive side.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub d: f32,
+}
+
+impl Plane {
+    /// `normal` needs to be normalized.
+    pub fn from_point_normal(point: Vec3, normal: Vec3) -> Self {
+        Plane {
+            normal,
+            d: -normal.dot(point),
+        }
+    }
+
+    /// From the coefficients `(a, b, c, d)` of `ax + by + cz + d = 0`, normalized.
+    pub fn from_coefficients(coefficients: Vec4) -> Self {
+        let len = coefficients.truncate().length();
+        Plane {
+            normal: coefficients.truncate() / len,
+            d: coefficients.w / len,
+        }
+    }
+
+    /// Positive on the side the normal points to.
+    #[inline]
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.d
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// normalized.
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Ray {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    /// Like `intersect` with `Plane::from_point_normal`.
+    pub fn intersect_plane(&self, plane_origin: Vec3, plane_normal: Vec3) -> Option<f32> {
+        self.intersect(&Plane::from_point_normal(plane_origin, plane_normal))
+    }
+
+    /// Shout out to bevy_math
+    #[inline]
+    pub fn get_point(&self, distance: f32) -> Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    /// Distance to the plane along the ray, None if the ray points away from it or is parallel.
+    /// 0 if the ray starts on the plane, like the other `intersect_*` functions for rays that start inside.
+    pub fn intersect(&self, plane: &Plane) -> Option<f32> {
+        let denominator = plane.normal.dot(self.direction);
+        if denominator.abs() <= f32::EPSILON {
+            return None;
+        }
+        let distance = -plane.signed_distance(self.origin) / denominator;
+        (distance >= 0.0).then_some(distance)
+    }
+
+    /// Distance to where the ray enters the box, 0 if it starts inside. Slab test.
+    pub fn intersect_aabb(&self, aabb: &Aabb3d) -> Option<f32> {
+        let inv = self.direction.recip();
+        let t1 = (aabb.min - self.origin) * inv;
+        let t2 = (aabb.max - self.origin) * inv;
+        let enter = t1.min(t2).max_element().max(0.0);
+        let exit = t1.max(t2).min_element();
+        (enter <= exit).then_some(enter)
+    }
+
+    /// Like `intersect_aabb`, in the local space of the box.
+    pub fn intersect_obb(&self, obb: &Obb) -> Option<f32> {
+        let to_local = obb.rotation.inverse();
+        let local = Ray {
+            origin: to_local * (self.origin - obb.center),
+            direction: to_local * self.direction,
+        };
+        local.intersect_aabb(&Aabb3d::from_center_half_extents(
+            Vec3::ZERO,
+            obb.half_extents,
+        ))
+    }
+
+    /// Distance to where the ray enters the sphere, 0 if it starts inside.
+    pub fn intersect_sphere(&self, sphere: &Sphere) -> Option<f32> {
+        let to_center = sphere.center - self.origin;
+        let along = to_center.dot(self.direction);
+        let distance_sq = to_center.length_squared() - along * along;
+        let radius_sq = sphere.radius * sphere.radius;
+        if distance_sq > radius_sq {
+            return None;
+        }
+        let half_chord = (radius_sq - distance_sq).sqrt();
+        let exit = along + half_chord;
+        (exit >= 0.0).then_some((along - half_chord).max(0.0))
+    }
+}
+
+/// Axis aligned box in 3d.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb3d {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb3d {
+    pub const fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb3d { min, max }
+    }
+
+    pub fn from_center_half_extents(center: Vec3, half_extents: Vec3) -> Self {
+        Aabb3d {
+            min: center - half_extents,
+            max: center + half_extents,
+        }
+    }
+
+    /// None for no points.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Aabb3d::new(first, first), |aabb, p| Aabb3d {
+            min: aabb.min.min(p),
+            max: aabb.max.max(p),
+        }))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Corner `i` has the max x if bit 0 of `i` is set, the max y for bit 1 and the max z for bit 2.
+    pub fn corners(&self) -> [Vec3; 8] {
+        std::array::from_fn(|i| {
+            Vec3::select(
+                glam::BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+                self.max,
+                self.min,
+            )
+        })
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    /// Touching boxes intersect.
+    pub fn intersects(&self, other: &Aabb3d) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    pub fn union(&self, other: &Aabb3d) -> Aabb3d {
+        Aabb3d {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// The axis aligned box around the transformed box, e.g. the world bounds of a mesh.
+    pub fn transformed(&self, affine: &Affine3A) -> Aabb3d {
+        let center = affine.transform_point3(self.center());
+        let matrix = Mat3::from(affine.matrix3);
+        let half = self.half_extents();
+        let half_extents = matrix.x_axis.abs() * half.x
+            + matrix.y_axis.abs() * half.y
+            + matrix.z_axis.abs() * half.z;
+        Aabb3d::from_center_half_extents(center, half_extents)
+    }
+}
+
+/// Oriented box in 3d.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Obb {
+    pub center: Vec3,
+    pub half_extents: Vec3,
+    pub rotation: Quat,
+}
+
+impl Obb {
+    /// The box of a mesh with local bounds `aabb`, placed with `transform`.
+    pub fn from_transformed_aabb(aabb: &Aabb3d, transform: &Transform) -> Self {
+        Obb {
+            center: transform.transform_point(aabb.center()),
+            half_extents: aabb.half_extents() * transform.scale.abs(),
+            rotation: transform.rotation,
+        }
+    }
+
+    /// Same order as `Aabb3d::corners`, with the axes of the box.
+    pub fn corners(&self) -> [Vec3; 8] {
+        let local = Aabb3d::from_center_half_extents(Vec3::ZERO, self.half_extents);
+        local.corners().map(|c| self.center + self.rotation * c)
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        let local = self.rotation.inverse() * (point - self.center);
+        local.abs().cmple(self.half_extents).all()
+    }
+
+    pub fn aabb(&self) -> Aabb3d {
+        let affine = Affine3A::from_rotation_translation(self.rotation, self.center);
+        Aabb3d::from_center_half_extents(Vec3::ZERO, self.half_extents).transformed(&affine)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Sphere {
+    pub const fn new(center: Vec3, radius: f32) -> Self {
+        Sphere { center, radius }
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        self.center.distance_squared(point) <= self.radius * self.radius
+    }
+
+    pub fn intersects(&self, other: &Sphere) -> bool {
+        let radii = self.radius + other.radius;
+        self.center.distance_squared(other.center) <= radii * radii
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb3d) -> bool {
+        let closest = self.center.clamp(aabb.min, aabb.max);
+        self.contains(closest)
+    }
+}
+
+/// The volume a camera sees, as six planes with normals pointing inwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// left, right, bottom, top, near, far.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// From a projection times view matrix with depth from 0 to 1 (like `Mat4::perspective_rh`), see
+    /// `Camera3d::frustum`.
+    pub fn from_view_proj(view_proj: Mat4) -> Self {
+        let rows = [
+            view_proj.row(0),
+            view_proj.row(1),
+            view_proj.row(2),
+            view_proj.row(3),
+        ];
+        Frustum {
+            planes: [
+                Plane::from_coefficients(rows[3] + rows[0]),
+                Plane::from_coefficients(rows[3] - rows[0]),
+                Plane::from_coefficients(rows[3] + rows[1]),
+                Plane::from_coefficients(rows[3] - rows[1]),
+                Plane::from_coefficients(rows[2]),
+                Plane::from_coefficients(rows[3] - rows[2]),
+            ],
+        }
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        self.planes.iter().all(|p| p.signed_distance(point) >= 0.0)
+    }
+
+    /// Conservative: boxes near the corners of the frustum can be reported as visible, but no visible box is culled.
+    pub fn intersects_aabb(&self, aabb: &Aabb3d) -> bool {
+        let center = aabb.center();
+        let half = aabb.half_extents();
+        self.planes.iter().all(|p| {
+            // the corner furthest along the normal.
+            let radius = half.dot(p.normal.abs());
+            p.signed_distance(center) >= -radius
+        })
+    }
+
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes
+            .iter()
+            .all(|p| p.signed_distance(sphere.center) >= -sphere.radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+
+    #[test]
+    fn intersections() {
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+        let proj = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let frustum = Frustum::from_view_proj(proj * view);
+        let unit = |center: Vec3| Aabb3d::from_center_half_extents(center, Vec3::splat(0.5));
+        assert!(frustum.contains(vec3(0.0, 0.0, -10.0)));
+        assert!(frustum.intersects_aabb(&unit(vec3(0.0, 0.0, -10.0))));
+        // behind the camera, beyond the far plane, and outside the 90 degree field of view.
+        assert!(!frustum.intersects_aabb(&unit(vec3(0.0, 0.0, 5.0))));
+        assert!(!frustum.intersects_aabb(&unit(vec3(0.0, 0.0, -102.0))));
+        assert!(!frustum.intersects_aabb(&unit(vec3(12.0, 0.0, -10.0))));
+        // straddling the left plane.
+        assert!(frustum.intersects_aabb(&unit(vec3(-10.2, 0.0, -10.0))));
+        assert!(!frustum.intersects_sphere(&Sphere::new(vec3(0.0, 15.0, -10.0), 2.0)));
+
+        let obb = Obb {
+            center: vec3(0.0, 0.0, -5.0),
+            half_extents: vec3(2.0, 0.5, 0.5),
+            rotation: Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+        };
+        // rotated, the long side points along z.
+        assert!(obb.contains(vec3(0.0, 0.0, -6.5)));
+        assert!(!obb.contains(vec3(1.5, 0.0, -5.0)));
+        let ray = Ray::new(Vec3::ZERO, Vec3::NEG_Z);
+        assert!((ray.intersect_obb(&obb).unwrap() - 3.0).abs() < 1e-5);
+        assert_eq!(
+            Ray::new(vec3(1.0, 0.0, 0.0), Vec3::NEG_Z).intersect_obb(&obb),
+            None
+        );
+        assert!((ray.intersect_aabb(&unit(vec3(0.0, 0.0, -5.0))).unwrap() - 4.5).abs() < 1e-5);
+        assert!((obb.aabb().half_extents() - vec3(0.5, 0.5, 2.0)).length() < 1e-5);
+
+        let a = Sphere::new(Vec3::ZERO, 1.0);
+        assert!(a.intersects(&Sphere::new(vec3(1.5, 0.0, 0.0), 0.5)));
+        assert!(!a.intersects(&Sphere::new(vec3(1.6, 0.0, 0.0), 0.5)));
+        assert!(
+            (ray.intersect_sphere(&Sphere::new(vec3(0.0, 0.0, -5.0), 1.0))
+                .unwrap()
+                - 4.0)
+                .abs()
+                < 1e-5
+        );
+        assert_eq!(
+            ray.intersect(&Plane::from_point_normal(vec3(0.0, 0.0, -2.0), Vec3::Z)),
+            Some(2.0)
+        );
+        // the normal does not need to be normalized, the plane behind the ray is not hit.
+        assert_eq!(
+            ray.intersect_plane(vec3(0.0, 0.0, -2.0), vec3(0.0, 0.0, 3.0)),
+            Some(2.0)
+        );
+        assert_eq!(ray.intersect_plane(vec3(0.0, 0.0, 2.0), Vec3::Z), None);
+        assert_eq!(ray.intersect_plane(Vec3::ZERO, Vec3::Z), Some(0.0));
+    }
+}
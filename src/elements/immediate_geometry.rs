@@ -42,15 +42,25 @@ impl<V: Copy, I: ToRaw> Default for ImmediateMeshQueue<V, I> {
 
 impl<V: Copy, I: ToRaw> ImmediateMeshQueue<V, I> {
     pub fn add_mesh(&mut self, vertices: &[V], indices: &[u32], transforms: &[I]) {
+        self.add_mesh_raw(vertices, indices, transforms.iter().map(|e| e.to_raw()))
+    }
+
+    /// Like `add_mesh`, but the instances are written into the queue as they are computed, without collecting them first.
+    pub fn add_mesh_raw(
+        &mut self,
+        vertices: &[V],
+        indices: &[u32],
+        instances: impl IntoIterator<Item = I::Raw>,
+    ) {
         let v_count = self.vertices.len() as u32;
         let i_count = self.indices.len() as u32;
         let t_count = self.instances.len() as u32;
         self.vertices.extend(vertices.iter().copied());
         self.indices.extend(indices.iter().map(|e| *e + v_count));
-        self.instances.extend(transforms.iter().map(|e| e.to_raw()));
+        self.instances.extend(instances);
         self.immediate_meshes.push(ImmediateMeshRanges {
             index_range: i_count..(i_count + indices.len() as u32),
-            instance_range: t_count..(t_count + transforms.len() as u32),
+            instance_range: t_count..self.instances.len() as u32,
         });
     }
 
@@ -1,4 +1,4 @@
-use glam::{DVec2, DVec3, Quat, Vec2, Vec3};
+use glam::{DVec2, DVec3, DVec4, Mat4, Quat, Vec2, Vec3, Vec4};
 pub use vert_macros::Lerp;
 
 pub trait Lerp {
@@ -67,9 +67,54 @@ impl Lerp for DVec3 {
     }
 }
 
+impl Lerp for Vec4 {
+    #[inline(always)]
+    fn lerp(&self, other: &Self, factor: f32) -> Self {
+        Vec4::lerp(*self, *other, factor)
+    }
+}
+
+impl Lerp for DVec4 {
+    #[inline(always)]
+    fn lerp(&self, other: &Self, factor: f32) -> Self {
+        DVec4::lerp(*self, *other, factor as f64)
+    }
+}
+
+/// Spherical interpolation, such that the rotation speed is constant.
 impl Lerp for Quat {
     #[inline(always)]
     fn lerp(&self, other: &Self, factor: f32) -> Self {
-        Quat::lerp(*self, *other, factor)
+        Quat::slerp(*self, *other, factor)
+    }
+}
+
+/// Decomposes both matrices into scale, rotation and translation and interpolates those.
+/// Only meaningful for affine transformation matrices, not for projections.
+impl Lerp for Mat4 {
+    fn lerp(&self, other: &Self, factor: f32) -> Self {
+        let (scale_a, rot_a, pos_a) = self.to_scale_rotation_translation();
+        let (scale_b, rot_b, pos_b) = other.to_scale_rotation_translation();
+        Mat4::from_scale_rotation_translation(
+            scale_a.lerp(scale_b, factor),
+            rot_a.slerp(rot_b, factor),
+            pos_a.lerp(pos_b, factor),
+        )
+    }
+}
+
+/// Two `Some` values are interpolated, otherwise the value switches at factor 0.5.
+impl<T: Lerp + Clone> Lerp for Option<T> {
+    fn lerp(&self, other: &Self, factor: f32) -> Self {
+        match (self, other) {
+            (Some(a), Some(b)) => Some(a.lerp(b, factor)),
+            _ => {
+                if factor < 0.5 {
+                    self.clone()
+                } else {
+                    other.clone()
+                }
+            }
+        }
     }
 }
@@ -1,25 +1,40 @@
 pub mod color;
-pub use color::Color;
+pub use color::{Color, Gradient};
 
 pub mod texture;
 pub use texture::{BindableTexture, Texture};
 
 pub mod buffer;
-pub use buffer::{GrowableBuffer, IndexBuffer, ToRaw, UniformBuffer, VertexBuffer};
+pub use buffer::{
+    DynamicOffset, DynamicUniformBuffer, GrowableBuffer, IndexBuffer, ToRaw, UniformBuffer,
+    VertexBuffer, WgslArrayElement, WgslLayout,
+};
 
 pub mod camera3d;
-pub use camera3d::Camera3d;
+pub use camera3d::{Camera3d, Exposure};
 
 pub mod immediate_geometry;
 pub use immediate_geometry::{ImmediateMeshQueue, ImmediateMeshRanges};
 
 pub mod transform;
-pub use transform::{Transform, TransformRaw};
+pub use transform::{CachedTransform, InterpolatedTransform, Transform, TransformRaw};
 
 pub mod rect;
 pub use rect::Rect;
 
+pub mod geometry;
+pub use geometry::{Aabb3d, Frustum, Obb, Plane, Ray, Sphere};
+
+pub mod curve;
+pub use curve::{Curve3d, CurveFrame, CurveKind};
+
 pub mod lerp;
 
+pub mod shapes;
+pub use shapes::MeshData;
+
 pub mod screen;
-pub use screen::{Screen, ScreenGR, ScreenRaw};
+pub use screen::{AspectMode, Screen, ScreenGR, ScreenRaw};
+
+pub mod tilemap;
+pub use tilemap::{Tile, TileLayer, Tilemap, Tileset};
@@ -5,7 +5,7 @@ use glam::{dvec2, vec2, DVec2, Vec2};
 
 ///  min_x, min_y form the top left corner.
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Lerp)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable, Lerp)]
 pub struct Rect {
     pub min_x: f32,
     pub min_y: f32,
@@ -128,6 +128,24 @@ impl Aabb {
         pos.x >= self.min_x && pos.y >= self.min_y && pos.x <= self.max_x && pos.y <= self.max_y
     }
 
+    /// true if the two overlap with a non zero area.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min_x < other.max_x
+            && other.min_x < self.max_x
+            && self.min_y < other.max_y
+            && other.min_y < self.max_y
+    }
+
+    /// The smallest Aabb containing both.
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min_x: self.min_x.min(other.min_x),
+            min_y: self.min_y.min(other.min_y),
+            max_x: self.max_x.max(other.max_x),
+            max_y: self.max_y.max(other.max_y),
+        }
+    }
+
     pub const UNIT: Aabb = Aabb {
         min_x: 0.0,
         min_y: 0.0,
@@ -1,5 +1,8 @@
+use glam::{vec2, Vec2};
+use winit::dpi::PhysicalSize;
+
 use crate::{
-    elements::{buffer::ToRaw, UniformBuffer},
+    elements::{buffer::ToRaw, Rect, UniformBuffer, WgslLayout},
     modules::GraphicsContext,
     Resize, Resized,
 };
@@ -74,7 +77,29 @@ impl Resize for Screen {
 pub struct Screen {
     pub width: u32,
     pub height: u32,
+    /// Scale factor of the window (physical pixels per logical pixel), updated on `WindowEvent::ScaleFactorChanged`.
     pub scale_factor: f64,
+    /// User settable multiplier on top of the `scale_factor`, e.g. for a ui size setting. Also applies to egui.
+    pub ui_scale: f32,
+    pub aspect_mode: AspectMode,
+}
+
+/// How the rendered image is fit into the window.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AspectMode {
+    /// The viewport covers the entire window.
+    #[default]
+    Stretch,
+    /// Keeps a fixed aspect ratio (width / height) by adding black bars at the top/bottom (letterbox) or left/right (pillarbox).
+    /// Aspects that are not positive and finite are ignored, like `Stretch`.
+    FixedAspect(f32),
+    /// Like `FixedAspect`, but the ui is laid out in a fixed virtual resolution that is scaled to fit the viewport.
+    FixedResolution { width: u32, height: u32 },
+    /// For pixel art: the main pass renders at this virtual resolution, which is scaled up by the largest integer
+    /// factor that fits into the window, with nearest neighbor sampling. The ui is laid out in the virtual resolution.
+    /// Load sprite textures with `FilterMode::Nearest` and snap the camera with `Camera3d::snap_to_pixel_grid`
+    /// to avoid sub-pixel jitter.
+    PixelPerfect { width: u32, height: u32 },
 }
 
 impl Screen {
@@ -83,6 +108,8 @@ impl Screen {
             width: window.inner_size().width,
             height: window.inner_size().height,
             scale_factor: window.scale_factor(),
+            ui_scale: 1.0,
+            aspect_mode: AspectMode::Stretch,
         }
     }
 
@@ -90,11 +117,137 @@ impl Screen {
     pub fn aspect(&self) -> f32 {
         self.width as f32 / self.height as f32
     }
+
+    /// The region of the window (in physical pixels) that is rendered to, respecting the `aspect_mode`.
+    pub fn viewport(&self) -> Rect {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let target_aspect = match self.aspect_mode {
+            AspectMode::Stretch => return Rect::new(0.0, 0.0, width, height),
+            AspectMode::FixedAspect(aspect) => {
+                if !(aspect > 0.0 && aspect.is_finite()) {
+                    return Rect::new(0.0, 0.0, width, height);
+                }
+                aspect
+            }
+            AspectMode::FixedResolution { width, height } => {
+                width.max(1) as f32 / height.max(1) as f32
+            }
+            AspectMode::PixelPerfect { width, height } => {
+                if let Some(scale) = self.pixel_scale() {
+                    let (w, h) = ((width * scale) as f32, (height * scale) as f32);
+                    // rounded, such that the pixels of the virtual resolution hit whole window pixels.
+                    let (x, y) = (
+                        ((self.width as f32 - w) * 0.5).floor(),
+                        ((self.height as f32 - h) * 0.5).floor(),
+                    );
+                    return Rect::new(x, y, w, h);
+                }
+                // the window is smaller than the virtual resolution, it is scaled down instead.
+                width.max(1) as f32 / height.max(1) as f32
+            }
+        };
+        if self.aspect() > target_aspect {
+            // window too wide => pillarbox
+            let viewport_width = height * target_aspect;
+            Rect::new((width - viewport_width) * 0.5, 0.0, viewport_width, height)
+        } else {
+            // window too tall => letterbox
+            let viewport_height = width / target_aspect;
+            Rect::new(
+                0.0,
+                (height - viewport_height) * 0.5,
+                width,
+                viewport_height,
+            )
+        }
+    }
+
+    /// The virtual resolution in `AspectMode::PixelPerfect`.
+    pub fn pixel_perfect_size(&self) -> Option<(u32, u32)> {
+        match self.aspect_mode {
+            AspectMode::PixelPerfect { width, height } => Some((width.max(1), height.max(1))),
+            _ => None,
+        }
+    }
+
+    /// In `AspectMode::PixelPerfect`, the number of window pixels per virtual pixel (in each direction).
+    /// None if the window is smaller than the virtual resolution.
+    pub fn pixel_scale(&self) -> Option<u32> {
+        let (width, height) = self.pixel_perfect_size()?;
+        let scale = (self.width / width).min(self.height / height);
+        (scale >= 1).then_some(scale)
+    }
+
+    /// Physical pixels per ui layout unit: `scale_factor * ui_scale`.
+    pub fn ui_scale_factor(&self) -> f32 {
+        self.scale_factor as f32 * self.ui_scale
+    }
+
+    /// The size of the coordinate space that ui is laid out in.
+    /// The viewport size in logical pixels (divided by `ui_scale_factor`), except for the fixed and pixel perfect modes.
+    pub fn ui_size(&self) -> Vec2 {
+        match self.aspect_mode {
+            AspectMode::FixedResolution { width, height }
+            | AspectMode::PixelPerfect { width, height } => vec2(width as f32, height as f32),
+            _ => self.viewport().size() / self.ui_scale_factor(),
+        }
+    }
+
+    /// Maps a position in the window (e.g. the cursor position) into ui coordinates.
+    /// Returns None if the position is in the black bars outside of the viewport.
+    pub fn window_to_ui(&self, pos: Vec2) -> Option<Vec2> {
+        let viewport = self.viewport();
+        if !viewport.contains(pos) {
+            return None;
+        }
+        let relative = (pos - vec2(viewport.min_x, viewport.min_y)) / viewport.size();
+        Some(relative * self.ui_size())
+    }
+
+    /// Inverse of `window_to_ui`: maps a position in ui coordinates into the window.
+    pub fn ui_to_window(&self, pos: Vec2) -> Vec2 {
+        let viewport = self.viewport();
+        vec2(viewport.min_x, viewport.min_y) + pos / self.ui_size() * viewport.size()
+    }
+}
+
+/// Restricts `viewport` to a render target of `size`. wgpu panics if a viewport or scissor rect reaches outside of
+/// the target, e.g. for a camera viewport that is partly off screen. The result is at least 1x1 pixels.
+pub fn clamp_viewport(viewport: Rect, size: PhysicalSize<u32>) -> Rect {
+    let (width, height) = (size.width.max(1) as f32, size.height.max(1) as f32);
+    let min_x = viewport.min_x.clamp(0.0, width - 1.0);
+    let min_y = viewport.min_y.clamp(0.0, height - 1.0);
+    let max_x = (viewport.min_x + viewport.width).clamp(min_x + 1.0, width);
+    let max_y = (viewport.min_y + viewport.height).clamp(min_y + 1.0, height);
+    Rect::new(min_x, min_y, max_x - min_x, max_y - min_y)
+}
+
+/// Restricts all following draw calls of the render pass to the `viewport`. NDC coordinates are mapped into the viewport.
+pub fn set_viewport_and_scissor(render_pass: &mut wgpu::RenderPass, viewport: Rect) {
+    render_pass.set_viewport(
+        viewport.min_x,
+        viewport.min_y,
+        viewport.width,
+        viewport.height,
+        0.0,
+        1.0,
+    );
+    set_scissor(render_pass, viewport);
+}
+
+/// Clips all following draw calls of the render pass to the `rect`, without changing the NDC mapping.
+pub fn set_scissor(render_pass: &mut wgpu::RenderPass, rect: Rect) {
+    render_pass.set_scissor_rect(
+        rect.min_x.max(0.0) as u32,
+        rect.min_y.max(0.0) as u32,
+        rect.width.max(1.0) as u32,
+        rect.height.max(1.0) as u32,
+    );
 }
 
 /// the stuff that gets sent to the shader
 #[repr(C)]
-#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, PartialEq)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, PartialEq, WgslLayout)]
 pub struct ScreenRaw {
     width: f32,
     height: f32,
@@ -106,11 +259,64 @@ impl ToRaw for Screen {
     type Raw = ScreenRaw;
 
     fn to_raw(&self) -> Self::Raw {
+        let ui_size = self.ui_size();
         ScreenRaw {
-            width: self.width as f32,
-            height: self.height as f32,
-            aspect: self.aspect(),
-            scale_factor: self.scale_factor as f32,
+            width: ui_size.x,
+            height: ui_size.y,
+            // of the viewport, not the window, such that letterboxed content is not stretched.
+            aspect: ui_size.x / ui_size.y.max(1.0),
+            scale_factor: self.ui_scale_factor(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(width: u32, height: u32, aspect_mode: AspectMode) -> Screen {
+        Screen {
+            width,
+            height,
+            scale_factor: 1.0,
+            ui_scale: 1.0,
+            aspect_mode,
+        }
+    }
+
+    #[test]
+    fn letterboxed_viewport() {
+        let s = screen(1000, 1000, AspectMode::FixedAspect(2.0));
+        assert_eq!(s.viewport(), Rect::new(0.0, 250.0, 1000.0, 500.0));
+        assert_eq!(s.to_raw().aspect, 2.0);
+        assert_eq!(s.window_to_ui(vec2(500.0, 100.0)), None);
+
+        for invalid in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let s = screen(800, 600, AspectMode::FixedAspect(invalid));
+            assert_eq!(s.viewport(), Rect::new(0.0, 0.0, 800.0, 600.0));
+        }
+        let s = screen(
+            800,
+            600,
+            AspectMode::FixedResolution {
+                width: 320,
+                height: 0,
+            },
+        );
+        assert!(s.viewport().width.is_finite() && s.viewport().height.is_finite());
+    }
+
+    #[test]
+    fn viewport_is_clamped_to_target() {
+        let size = PhysicalSize::new(800, 600);
+        let inside = Rect::new(10.0, 20.0, 100.0, 100.0);
+        assert_eq!(clamp_viewport(inside, size), inside);
+        assert_eq!(
+            clamp_viewport(Rect::new(-100.0, 500.0, 400.0, 400.0), size),
+            Rect::new(0.0, 500.0, 300.0, 100.0)
+        );
+        // entirely outside, still a valid rect inside of the target.
+        let outside = clamp_viewport(Rect::new(900.0, 700.0, 50.0, 50.0), size);
+        assert_eq!(outside, Rect::new(799.0, 599.0, 1.0, 1.0));
+    }
+}
@@ -0,0 +1,355 @@
+//! Generators for primitive meshes (cube, spheres, cylinder, cone, capsule, torus and plane), such that examples and
+//! prototypes do not have to write vertex arrays by hand.
+//!
+//! All shapes are centered at the origin with y up. Triangles are counter-clockwise seen from the outside, matching
+//! the back face culling of the renderers.
+
+use std::{
+    collections::HashMap,
+    f32::consts::{PI, TAU},
+};
+
+use glam::{vec2, vec3, Vec2, Vec3};
+
+/// Positions, normals and uvs of a mesh, one entry per vertex, and the triangle indices into them.
+///
+/// Use `map_vertices` to convert it to the vertex type of a renderer, e.g. `ColorMeshRenderer::draw_mesh`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Builds one vertex from the position, normal and uv of each vertex.
+    pub fn map_vertices<V>(&self, f: impl Fn(Vec3, Vec3, Vec2) -> V) -> Vec<V> {
+        (0..self.positions.len())
+            .map(|i| f(self.positions[i], self.normals[i], self.uvs[i]))
+            .collect()
+    }
+
+    fn push_vertex(&mut self, position: Vec3, normal: Vec3, uv: Vec2) -> u32 {
+        let index = self.positions.len() as u32;
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.uvs.push(uv);
+        index
+    }
+
+    /// Skips triangles with two equal corners, e.g. at the poles of a sphere or the tip of a cone.
+    fn push_triangle(&mut self, a: u32, b: u32, c: u32) {
+        let pos = |i: u32| self.positions[i as usize];
+        let (pa, pb, pc) = (pos(a), pos(b), pos(c));
+        if pa.distance_squared(pb) < 1e-12
+            || pb.distance_squared(pc) < 1e-12
+            || pc.distance_squared(pa) < 1e-12
+        {
+            return;
+        }
+        self.indices.extend([a, b, c]);
+    }
+
+    /// Adds a grid of `cols` x `rows` quads. `f` returns position, normal and uv for `u` (along the columns) and
+    /// `v` (along the rows), both from 0 to 1. The outside is the side `dP/du x dP/dv` points to.
+    fn push_grid(&mut self, cols: u32, rows: u32, f: impl Fn(f32, f32) -> (Vec3, Vec3, Vec2)) {
+        let base = self.positions.len() as u32;
+        for row in 0..=rows {
+            for col in 0..=cols {
+                let (position, normal, uv) = f(col as f32 / cols as f32, row as f32 / rows as f32);
+                self.push_vertex(position, normal, uv);
+            }
+        }
+        let index = |col: u32, row: u32| base + row * (cols + 1) + col;
+        for row in 0..rows {
+            for col in 0..cols {
+                let (a, b) = (index(col, row), index(col + 1, row));
+                let (c, d) = (index(col, row + 1), index(col + 1, row + 1));
+                self.push_triangle(a, b, d);
+                self.push_triangle(a, d, c);
+            }
+        }
+    }
+
+    /// A disc in the xz plane at height `y`, facing up or down.
+    fn push_disc(&mut self, y: f32, radius: f32, segments: u32, up: bool) {
+        let sign = if up { 1.0 } else { -1.0 };
+        let normal = vec3(0.0, sign, 0.0);
+        self.push_grid(segments, 1, |u, v| {
+            let (sin, cos) = (sign * u * TAU).sin_cos();
+            let position = vec3(cos * radius * v, y, sin * radius * v);
+            (
+                position,
+                normal,
+                vec2(0.5 + cos * v * 0.5, 0.5 + sin * v * 0.5),
+            )
+        });
+    }
+}
+
+/// A cube with side length `size`. Each face has its own vertices, such that the normals are flat.
+pub fn cube(size: f32) -> MeshData {
+    let h = size * 0.5;
+    let mut mesh = MeshData::default();
+    // normal, u and v axis of each face, with u x v = normal:
+    let faces = [
+        (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+        (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+        (Vec3::Y, Vec3::X, Vec3::NEG_Z),
+        (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+        (Vec3::Z, Vec3::X, Vec3::Y),
+        (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+    ];
+    for (normal, u, v) in faces {
+        let center = normal * h;
+        let a = mesh.push_vertex(center - u * h - v * h, normal, vec2(0.0, 1.0));
+        let b = mesh.push_vertex(center + u * h - v * h, normal, vec2(1.0, 1.0));
+        let c = mesh.push_vertex(center + u * h + v * h, normal, vec2(1.0, 0.0));
+        let d = mesh.push_vertex(center - u * h + v * h, normal, vec2(0.0, 0.0));
+        mesh.push_triangle(a, b, c);
+        mesh.push_triangle(a, c, d);
+    }
+    mesh
+}
+
+/// A sphere made of `sectors` slices around the y axis and `stacks` rings from top to bottom.
+/// The uvs are an equirectangular mapping.
+pub fn uv_sphere(radius: f32, sectors: u32, stacks: u32) -> MeshData {
+    let mut mesh = MeshData::default();
+    mesh.push_grid(sectors.max(3), stacks.max(2), |u, v| {
+        let normal = sphere_normal(u * TAU, v * PI);
+        (normal * radius, normal, vec2(u, v))
+    });
+    mesh
+}
+
+/// A sphere made of equally sized triangles: an icosahedron whose triangles are split into 4 `subdivisions` times.
+/// The uvs are an equirectangular mapping that is distorted at the seam.
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut positions: Vec<Vec3> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .into_iter()
+    .map(|p| Vec3::from(p).normalize())
+    .collect();
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // edges are shared by two triangles, the midpoint is only added once:
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let p = (positions[a as usize] + positions[b as usize]).normalize();
+                positions.push(p);
+                positions.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .into_iter()
+            .flat_map(|[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let mut mesh = MeshData::default();
+    for normal in positions {
+        let u = 0.5 + normal.z.atan2(normal.x) / TAU;
+        let v = normal.y.clamp(-1.0, 1.0).acos() / PI;
+        mesh.push_vertex(normal * radius, normal, vec2(u, v));
+    }
+    for [a, b, c] in triangles {
+        mesh.push_triangle(a, b, c);
+    }
+    mesh
+}
+
+/// A cylinder along the y axis with caps on both ends.
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> MeshData {
+    let segments = segments.max(3);
+    let h = height * 0.5;
+    let mut mesh = MeshData::default();
+    mesh.push_grid(segments, 1, |u, v| {
+        let (sin, cos) = (u * TAU).sin_cos();
+        let normal = vec3(cos, 0.0, sin);
+        (
+            vec3(cos * radius, h - v * height, sin * radius),
+            normal,
+            vec2(u, v),
+        )
+    });
+    mesh.push_disc(h, radius, segments, true);
+    mesh.push_disc(-h, radius, segments, false);
+    mesh
+}
+
+/// A cone along the y axis with the tip at the top and a cap at the bottom.
+pub fn cone(radius: f32, height: f32, segments: u32) -> MeshData {
+    let segments = segments.max(3);
+    let h = height * 0.5;
+    let mut mesh = MeshData::default();
+    mesh.push_grid(segments, 1, |u, v| {
+        let (sin, cos) = (u * TAU).sin_cos();
+        let normal = vec3(cos * height, radius, sin * height).normalize();
+        (
+            vec3(cos * radius * v, h - v * height, sin * radius * v),
+            normal,
+            vec2(u, v),
+        )
+    });
+    mesh.push_disc(-h, radius, segments, false);
+    mesh
+}
+
+/// A cylinder of `length` along the y axis with half spheres on both ends, the total height is `length + 2 * radius`.
+/// `rings` is the number of rings of each half sphere.
+pub fn capsule(radius: f32, length: f32, segments: u32, rings: u32) -> MeshData {
+    let (segments, rings) = (segments.max(3), rings.max(1));
+    let h = length * 0.5;
+    // the uvs go from top to bottom over the whole capsule, proportional to the arc length.
+    let cap_v = (PI * 0.5 * radius) / (PI * radius + length);
+    let mut mesh = MeshData::default();
+    mesh.push_grid(segments, rings, |u, v| {
+        let normal = sphere_normal(u * TAU, v * PI * 0.5);
+        (
+            normal * radius + vec3(0.0, h, 0.0),
+            normal,
+            vec2(u, v * cap_v),
+        )
+    });
+    mesh.push_grid(segments, 1, |u, v| {
+        let (sin, cos) = (u * TAU).sin_cos();
+        (
+            vec3(cos * radius, h - v * length, sin * radius),
+            vec3(cos, 0.0, sin),
+            vec2(u, cap_v + v * (1.0 - 2.0 * cap_v)),
+        )
+    });
+    mesh.push_grid(segments, rings, |u, v| {
+        let normal = sphere_normal(u * TAU, PI * 0.5 + v * PI * 0.5);
+        (
+            normal * radius - vec3(0.0, h, 0.0),
+            normal,
+            vec2(u, 1.0 - cap_v + v * cap_v),
+        )
+    });
+    mesh
+}
+
+/// A torus around the y axis. `major_radius` is the distance from the center to the middle of the tube.
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: u32,
+    minor_segments: u32,
+) -> MeshData {
+    let mut mesh = MeshData::default();
+    mesh.push_grid(major_segments.max(3), minor_segments.max(3), |u, v| {
+        let (sin_major, cos_major) = (u * TAU).sin_cos();
+        let (sin_minor, cos_minor) = (-v * TAU).sin_cos();
+        let normal = vec3(cos_minor * cos_major, sin_minor, cos_minor * sin_major);
+        let ring = major_radius + minor_radius * cos_minor;
+        (
+            vec3(ring * cos_major, minor_radius * sin_minor, ring * sin_major),
+            normal,
+            vec2(u, v),
+        )
+    });
+    mesh
+}
+
+/// A square in the xz plane facing up, split into `subdivisions + 1` quads along each side.
+pub fn plane(size: f32, subdivisions: u32) -> MeshData {
+    let quads = subdivisions + 1;
+    let h = size * 0.5;
+    let mut mesh = MeshData::default();
+    mesh.push_grid(quads, quads, |u, v| {
+        (
+            vec3(-h + u * size, 0.0, h - v * size),
+            Vec3::Y,
+            vec2(u, 1.0 - v),
+        )
+    });
+    mesh
+}
+
+/// Unit vector for the angle `theta` around the y axis and `phi` from the top.
+fn sphere_normal(theta: f32, phi: f32) -> Vec3 {
+    let (sin_theta, cos_theta) = theta.sin_cos();
+    let (sin_phi, cos_phi) = phi.sin_cos();
+    vec3(sin_phi * cos_theta, cos_phi, sin_phi * sin_theta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triangles_face_outwards() {
+        let shapes = [
+            ("cube", cube(1.0)),
+            ("uv_sphere", uv_sphere(1.0, 16, 8)),
+            ("icosphere", icosphere(1.0, 2)),
+            ("cylinder", cylinder(0.5, 2.0, 12)),
+            ("cone", cone(0.5, 1.0, 12)),
+            ("capsule", capsule(0.5, 1.0, 12, 4)),
+            ("torus", torus(1.0, 0.25, 16, 8)),
+            ("plane", plane(2.0, 3)),
+        ];
+        for (name, mesh) in shapes {
+            assert!(!mesh.indices.is_empty(), "{name}");
+            assert_eq!(mesh.indices.len() % 3, 0, "{name}");
+            for normal in mesh.normals.iter() {
+                assert!((normal.length() - 1.0).abs() < 1e-4, "{name}");
+            }
+            for tri in mesh.indices.chunks(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| tri[i] as usize);
+                let face_normal = (mesh.positions[b] - mesh.positions[a])
+                    .cross(mesh.positions[c] - mesh.positions[a]);
+                let vertex_normals = mesh.normals[a] + mesh.normals[b] + mesh.normals[c];
+                assert!(face_normal.dot(vertex_normals) > 0.0, "{name}: {tri:?}");
+            }
+        }
+        assert_eq!(cube(1.0).vertex_count(), 24);
+        assert_eq!(icosphere(1.0, 1).indices.len(), 80 * 3);
+    }
+}
@@ -1,6 +1,9 @@
 // use image::GenericImageView;
 
-use std::{borrow::Cow, sync::OnceLock};
+use std::{
+    borrow::Cow,
+    sync::{Arc, Mutex},
+};
 
 use image::RgbaImage;
 use rand::{thread_rng, Rng};
@@ -13,11 +16,57 @@ pub struct BindableTexture {
     pub bind_group: wgpu::BindGroup,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SharedLayout {
+    Rgba,
+    RgbaMsaa4,
+    Cube,
+}
+
+/// Bind group layouts used by many pipelines, cached per device. The layouts of a lost device are released in
+/// `GraphicsContext::recreate_device`, see `release_shared_layouts`.
+static SHARED_LAYOUTS: Mutex<Vec<SharedLayoutEntry>> = Mutex::new(vec![]);
+
+struct SharedLayoutEntry {
+    device: wgpu::Id<wgpu::Device>,
+    kind: SharedLayout,
+    layout: Arc<BindGroupLayout>,
+}
+
+fn shared_layout(
+    device: &wgpu::Device,
+    kind: SharedLayout,
+    create: impl FnOnce() -> BindGroupLayout,
+) -> Arc<BindGroupLayout> {
+    let device_id = device.global_id();
+    let mut layouts = SHARED_LAYOUTS.lock().unwrap();
+    if let Some(entry) = layouts
+        .iter()
+        .find(|e| e.device == device_id && e.kind == kind)
+    {
+        return entry.layout.clone();
+    }
+    let layout = Arc::new(create());
+    layouts.push(SharedLayoutEntry {
+        device: device_id,
+        kind,
+        layout: layout.clone(),
+    });
+    layout
+}
+
+/// Drops the cached layouts of a device, call before it is replaced.
+pub(crate) fn release_shared_layouts(device: &wgpu::Device) {
+    let device_id = device.global_id();
+    SHARED_LAYOUTS
+        .lock()
+        .unwrap()
+        .retain(|e| e.device != device_id);
+}
+
 /// cached bind group layout for rgba images
-pub fn rgba_bind_group_layout(device: &wgpu::Device) -> &'static BindGroupLayout {
-    /// ugly, use resources cache in the future.
-    static _RGBA_BIND_GROUP_LAYOUT: OnceLock<BindGroupLayout> = OnceLock::new();
-    _RGBA_BIND_GROUP_LAYOUT.get_or_init(|| {
+pub fn rgba_bind_group_layout(device: &wgpu::Device) -> Arc<BindGroupLayout> {
+    shared_layout(device, SharedLayout::Rgba, || {
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
             entries: &[
@@ -42,10 +91,36 @@ pub fn rgba_bind_group_layout(device: &wgpu::Device) -> &'static BindGroupLayout
     })
 }
 
+/// cached bind group layout for hdr cubemaps, e.g. `HdrCubemap`
+pub fn cube_bind_group_layout(device: &wgpu::Device) -> Arc<BindGroupLayout> {
+    shared_layout(device, SharedLayout::Cube, || {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cube BindGroupLayout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+    })
+}
+
 /// cached bind group layout for rgba images, with msaa 4x
-pub fn rgba_bind_group_layout_msaa4(device: &wgpu::Device) -> &'static BindGroupLayout {
-    static _RGBA_BIND_GROUP_LAYOUT_MSAA4: OnceLock<BindGroupLayout> = OnceLock::new();
-    _RGBA_BIND_GROUP_LAYOUT_MSAA4.get_or_init(|| {
+pub fn rgba_bind_group_layout_msaa4(device: &wgpu::Device) -> Arc<BindGroupLayout> {
+    shared_layout(device, SharedLayout::RgbaMsaa4, || {
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
             entries: &[
@@ -77,7 +152,7 @@ impl BindableTexture {
     pub fn new(device: &wgpu::Device, texture: Texture) -> Self {
         let bind_group = device.create_bind_group(&BindGroupDescriptor {
             label: None,
-            layout: rgba_bind_group_layout(device),
+            layout: &rgba_bind_group_layout(device),
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -132,23 +207,49 @@ impl Texture {
     }
 
     pub fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, rgba: &RgbaImage) -> Self {
-        let dimensions = rgba.dimensions();
+        Self::from_image_with_filter(device, queue, rgba, wgpu::FilterMode::Linear)
+    }
 
+    /// Like `from_image`, `FilterMode::Nearest` keeps the pixels of pixel art crisp when scaled up.
+    pub fn from_image_with_filter(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: &RgbaImage,
+        mag_filter: wgpu::FilterMode,
+    ) -> Self {
         let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        Self::from_image_with_format(device, queue, rgba, format, mag_filter)
+    }
+
+    /// Normal maps store directions, not colors, so unlike `from_image` they are not converted from srgb.
+    pub fn from_normal_map(device: &wgpu::Device, queue: &wgpu::Queue, rgba: &RgbaImage) -> Self {
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        Self::from_image_with_format(device, queue, rgba, format, wgpu::FilterMode::Linear)
+    }
+
+    /// For data that is not a color (e.g. distance fields), not converted from srgb.
+    pub fn from_linear_image(device: &wgpu::Device, queue: &wgpu::Queue, rgba: &RgbaImage) -> Self {
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        Self::from_image_with_format(device, queue, rgba, format, wgpu::FilterMode::Linear)
+    }
+
+    fn from_image_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: &RgbaImage,
+        format: wgpu::TextureFormat,
+        mag_filter: wgpu::FilterMode,
+    ) -> Self {
+        let dimensions = rgba.dimensions();
+
         let usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
         let size = wgpu::Extent3d {
             width: rgba.width(),
             height: rgba.height(),
             depth_or_array_layers: 1,
         };
-        let texture = Self::create_2d_texture(
-            device,
-            size.width,
-            size.height,
-            format,
-            usage,
-            wgpu::FilterMode::Linear,
-        );
+        let texture =
+            Self::create_2d_texture(device, size.width, size.height, format, usage, mag_filter);
 
         queue.write_texture(
             wgpu::ImageCopyTexture {
@@ -0,0 +1,257 @@
+//! Tile maps made of layers of tile ids, with the tilesets they reference. Can be loaded from Tiled (.tmx / .tsx)
+//! files, see `Tilemap::load_tmx`, and drawn with the `TilemapRenderer`.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use super::Rect;
+
+mod tmx;
+
+/// Tiled stores flips in the highest bits of the tile ids.
+const FLIP_H: u32 = 0x8000_0000;
+const FLIP_V: u32 = 0x4000_0000;
+const FLIP_D: u32 = 0x2000_0000;
+const GID_MASK: u32 = 0x1FFF_FFFF;
+
+#[derive(Debug, Clone)]
+pub struct Tilemap {
+    /// in tiles.
+    pub width: u32,
+    pub height: u32,
+    /// in pixels.
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// sorted by `first_gid`.
+    pub tilesets: Vec<Tileset>,
+    /// from bottom to top.
+    pub layers: Vec<TileLayer>,
+    pub properties: HashMap<String, String>,
+}
+
+/// A tile atlas image and the metadata of its tiles.
+#[derive(Debug, Clone)]
+pub struct Tileset {
+    pub name: String,
+    /// the gid of the first tile in this tileset, gids of the map are global across all tilesets.
+    pub first_gid: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tile_count: u32,
+    pub columns: u32,
+    /// pixels between the tiles in the image.
+    pub spacing: u32,
+    /// pixels around the tiles in the image.
+    pub margin: u32,
+    /// relative to the map file if loaded with `Tilemap::load_tmx`.
+    pub image: PathBuf,
+    pub image_width: u32,
+    pub image_height: u32,
+    /// metadata of the tiles that have some, by local tile id.
+    pub tiles: HashMap<u32, TileData>,
+}
+
+/// Metadata of a tile in a `Tileset`.
+#[derive(Debug, Clone, Default)]
+pub struct TileData {
+    pub properties: HashMap<String, String>,
+    /// collision shapes in pixels relative to the top left of the tile, from the Tiled collision editor.
+    /// A tile with a `collision` or `solid` property set to true collides with its whole area.
+    pub collision: Vec<Rect>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileLayer {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub visible: bool,
+    pub opacity: f32,
+    /// row by row from the top left, 0 is empty. Includes the flip flags, see `Tile`.
+    pub tiles: Vec<u32>,
+    pub properties: HashMap<String, String>,
+}
+
+/// A tile id of a layer with its flip flags decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Tile {
+    /// global tile id, 0 is empty.
+    pub gid: u32,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    /// flipped along the diagonal from top left to bottom right, combined with the other flips for rotations.
+    pub flip_d: bool,
+}
+
+impl Tile {
+    pub fn from_raw(raw: u32) -> Self {
+        Tile {
+            gid: raw & GID_MASK,
+            flip_h: raw & FLIP_H != 0,
+            flip_v: raw & FLIP_V != 0,
+            flip_d: raw & FLIP_D != 0,
+        }
+    }
+
+    pub fn to_raw(self) -> u32 {
+        let mut raw = self.gid & GID_MASK;
+        if self.flip_h {
+            raw |= FLIP_H;
+        }
+        if self.flip_v {
+            raw |= FLIP_V;
+        }
+        if self.flip_d {
+            raw |= FLIP_D;
+        }
+        raw
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.gid == 0
+    }
+}
+
+impl TileLayer {
+    pub fn new(name: impl Into<String>, width: u32, height: u32) -> Self {
+        TileLayer {
+            name: name.into(),
+            width,
+            height,
+            visible: true,
+            opacity: 1.0,
+            tiles: vec![0; (width * height) as usize],
+            properties: HashMap::new(),
+        }
+    }
+
+    /// None outside of the layer.
+    pub fn get(&self, x: i32, y: i32) -> Option<Tile> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return None;
+        }
+        Some(Tile::from_raw(
+            self.tiles[(y as u32 * self.width + x as u32) as usize],
+        ))
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, tile: Tile) {
+        if x < self.width && y < self.height {
+            self.tiles[(y * self.width + x) as usize] = tile.to_raw();
+        }
+    }
+
+    /// Replaces every tile for which `is_terrain` is true with one of 16 `variants`, chosen by which of its 4
+    /// neighbours are terrain too: the index is the sum of 1 (up), 2 (right), 4 (down) and 8 (left).
+    /// E.g. `variants[0]` is a single island tile and `variants[15]` a tile surrounded on all sides.
+    pub fn autotile(&mut self, is_terrain: impl Fn(u32) -> bool, variants: &[u32; 16]) {
+        let terrain: Vec<bool> = self
+            .tiles
+            .iter()
+            .map(|raw| {
+                let gid = raw & GID_MASK;
+                gid != 0 && is_terrain(gid)
+            })
+            .collect();
+        let is = |x: i32, y: i32| {
+            x >= 0
+                && y >= 0
+                && x < self.width as i32
+                && y < self.height as i32
+                && terrain[(y * self.width as i32 + x) as usize]
+        };
+        let mut tiles = self.tiles.clone();
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                if !is(x, y) {
+                    continue;
+                }
+                let mask = is(x, y - 1) as usize
+                    | (is(x + 1, y) as usize) << 1
+                    | (is(x, y + 1) as usize) << 2
+                    | (is(x - 1, y) as usize) << 3;
+                tiles[(y * self.width as i32 + x) as usize] = variants[mask];
+            }
+        }
+        self.tiles = tiles;
+    }
+}
+
+impl Tilemap {
+    /// The tileset a gid belongs to.
+    pub fn tileset_of(&self, gid: u32) -> Option<&Tileset> {
+        if gid == 0 {
+            return None;
+        }
+        self.tilesets.iter().rev().find(|t| t.first_gid <= gid)
+    }
+
+    /// The metadata of a tile, None if it has none.
+    pub fn tile_data(&self, gid: u32) -> Option<&TileData> {
+        let tileset = self.tileset_of(gid)?;
+        tileset.tiles.get(&(gid - tileset.first_gid))
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut TileLayer> {
+        self.layers.iter_mut().find(|l| l.name == name)
+    }
+
+    /// The collision shapes of all tiles in a layer, in tile units (1.0 is one tile of the map, y pointing down),
+    /// to be handed to a physics engine. Horizontal runs of fully solid tiles are merged into one rect.
+    pub fn collision_rects(&self, layer: &TileLayer) -> Vec<Rect> {
+        let (tw, th) = (self.tile_width as f32, self.tile_height as f32);
+        let mut rects = vec![];
+        for y in 0..layer.height {
+            let mut run: Option<Rect> = None;
+            for x in 0..layer.width {
+                let tile = Tile::from_raw(layer.tiles[(y * layer.width + x) as usize]);
+                let data = self.tile_data(tile.gid);
+                let full = data.is_some_and(|d| {
+                    ["collision", "solid"]
+                        .iter()
+                        .any(|p| d.properties.get(*p).is_some_and(|v| v == "true"))
+                });
+                if full {
+                    match &mut run {
+                        Some(rect) => rect.width += 1.0,
+                        None => run = Some(Rect::new(x as f32, y as f32, 1.0, 1.0)),
+                    }
+                    continue;
+                }
+                rects.extend(run.take());
+                for shape in data.map(|d| d.collision.as_slice()).unwrap_or(&[]) {
+                    rects.push(Rect::new(
+                        x as f32 + shape.min_x / tw,
+                        y as f32 + shape.min_y / th,
+                        shape.width / tw,
+                        shape.height / th,
+                    ));
+                }
+            }
+            rects.extend(run);
+        }
+        rects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn autotile_picks_variants_by_neighbours() {
+        let mut layer = TileLayer::new("ground", 3, 2);
+        // a horizontal bar of 3 tiles in the first row:
+        for x in 0..3 {
+            layer.set(x, 0, Tile::from_raw(1));
+        }
+        let variants: [u32; 16] = std::array::from_fn(|i| 100 + i as u32);
+        layer.autotile(|gid| gid == 1, &variants);
+        // left end has a right neighbour (2), the middle has both (2 + 8), the right end has a left one (8).
+        assert_eq!(&layer.tiles[0..3], &[102, 110, 108]);
+        assert_eq!(&layer.tiles[3..6], &[0, 0, 0]);
+    }
+}
@@ -0,0 +1,456 @@
+//! Loading of Tiled maps. Supports orthogonal maps with tile layers in csv or uncompressed base64 encoding, and
+//! embedded or external (.tsx) tilesets with a single atlas image each. Object layers and image layers are ignored.
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::{
+    assets::{AssetSource, AssetT},
+    elements::Rect,
+};
+
+use super::{TileData, TileLayer, Tilemap, Tileset};
+
+impl Tilemap {
+    /// Loads a .tmx file and the .tsx files it references. Image paths are made relative to the working directory.
+    pub fn load_tmx(path: impl AsRef<Path>) -> anyhow::Result<Tilemap> {
+        let path = path.as_ref();
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let xml = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read tilemap {path:?}"))?;
+        let mut map = Tilemap::from_tmx(&xml, |source| {
+            let tsx_path = dir.join(source);
+            std::fs::read_to_string(&tsx_path)
+                .with_context(|| format!("could not read tileset {tsx_path:?}"))
+        })?;
+        for tileset in map.tilesets.iter_mut() {
+            tileset.image = dir.join(&tileset.image);
+        }
+        Ok(map)
+    }
+
+    /// Parses the xml of a .tmx file. `load_tsx` is called with the `source` of external tilesets.
+    /// Image paths of tilesets are left as they are in the files.
+    pub fn from_tmx(
+        xml: &str,
+        mut load_tsx: impl FnMut(&str) -> anyhow::Result<String>,
+    ) -> anyhow::Result<Tilemap> {
+        let root = parse_xml(xml)?;
+        if root.name != "map" {
+            bail!("expected a <map> element, got <{}>", root.name);
+        }
+        let orientation = root.attr("orientation").unwrap_or("orthogonal");
+        if orientation != "orthogonal" {
+            bail!("only orthogonal maps are supported, not {orientation}");
+        }
+        if root.attr("infinite") == Some("1") {
+            bail!("infinite maps are not supported");
+        }
+
+        let mut tilesets = vec![];
+        for element in root.children("tileset") {
+            let first_gid: u32 = element.parse_attr("firstgid")?;
+            let tileset = match element.attr("source") {
+                Some(source) => {
+                    let tsx = load_tsx(source)?;
+                    let tsx_root = parse_xml(&tsx)?;
+                    let tileset = parse_tileset(&tsx_root, first_gid);
+                    tileset.with_context(|| format!("in tileset {source}"))?
+                }
+                None => parse_tileset(element, first_gid)?,
+            };
+            tilesets.push(tileset);
+        }
+        tilesets.sort_by_key(|t| t.first_gid);
+
+        let mut layers = vec![];
+        for element in root.children("layer") {
+            let name = element.attr("name").unwrap_or_default();
+            layers.push(parse_layer(element).with_context(|| format!("in layer {name}"))?);
+        }
+
+        Ok(Tilemap {
+            width: root.parse_attr("width")?,
+            height: root.parse_attr("height")?,
+            tile_width: root.parse_attr("tilewidth")?,
+            tile_height: root.parse_attr("tileheight")?,
+            tilesets,
+            layers,
+            properties: parse_properties(&root),
+        })
+    }
+}
+
+/// Only maps with embedded tilesets can be loaded from bytes, use `Tilemap::load_tmx` for external tilesets.
+impl AssetT for Tilemap {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let xml = std::str::from_utf8(bytes)?;
+        Tilemap::from_tmx(xml, |source| {
+            Err(anyhow!(
+                "external tileset {source} cannot be loaded from bytes, use Tilemap::load_tmx"
+            ))
+        })
+    }
+
+    fn dependencies(&self) -> Vec<AssetSource> {
+        self.tilesets
+            .iter()
+            .map(|t| AssetSource::File(t.image.clone()))
+            .collect()
+    }
+}
+
+fn parse_tileset(element: &XmlElement, first_gid: u32) -> anyhow::Result<Tileset> {
+    let image = element
+        .child("image")
+        .ok_or_else(|| anyhow!("tilesets without a single atlas image are not supported"))?;
+    let mut tiles = HashMap::new();
+    for tile in element.children("tile") {
+        let id: u32 = tile.parse_attr("id")?;
+        let mut collision = vec![];
+        for object in tile
+            .children("objectgroup")
+            .flat_map(|g| g.children("object"))
+        {
+            collision.push(Rect::new(
+                object.parse_attr_or("x", 0.0)?,
+                object.parse_attr_or("y", 0.0)?,
+                object.parse_attr_or("width", 0.0)?,
+                object.parse_attr_or("height", 0.0)?,
+            ));
+        }
+        let data = TileData {
+            properties: parse_properties(tile),
+            collision,
+        };
+        tiles.insert(id, data);
+    }
+    let tile_width: u32 = element.parse_attr("tilewidth")?;
+    let image_width: u32 = image.parse_attr("width")?;
+    Ok(Tileset {
+        name: element.attr("name").unwrap_or_default().to_string(),
+        first_gid,
+        tile_width,
+        tile_height: element.parse_attr("tileheight")?,
+        tile_count: element.parse_attr("tilecount")?,
+        columns: element.parse_attr_or("columns", image_width / tile_width.max(1))?,
+        spacing: element.parse_attr_or("spacing", 0)?,
+        margin: element.parse_attr_or("margin", 0)?,
+        image: image.attr("source").unwrap_or_default().into(),
+        image_width,
+        image_height: image.parse_attr("height")?,
+        tiles,
+    })
+}
+
+fn parse_layer(element: &XmlElement) -> anyhow::Result<TileLayer> {
+    let width: u32 = element.parse_attr("width")?;
+    let height: u32 = element.parse_attr("height")?;
+    let data = element
+        .child("data")
+        .ok_or_else(|| anyhow!("layer has no data"))?;
+    if let Some(compression) = data.attr("compression") {
+        bail!("{compression} compressed layers are not supported, save the map with csv or base64 encoding");
+    }
+    let tiles: Vec<u32> = match data.attr("encoding") {
+        Some("csv") => data
+            .text
+            .split(',')
+            .map(|e| e.trim())
+            .filter(|e| !e.is_empty())
+            .map(|e| e.parse::<u32>())
+            .collect::<Result<_, _>>()?,
+        Some("base64") => decode_base64(data.text.trim())?
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        None => data
+            .children("tile")
+            .map(|t| t.parse_attr_or("gid", 0))
+            .collect::<Result<_, _>>()?,
+        Some(encoding) => bail!("unknown layer encoding {encoding}"),
+    };
+    if tiles.len() != (width * height) as usize {
+        bail!("expected {} tiles, got {}", width * height, tiles.len());
+    }
+    Ok(TileLayer {
+        name: element.attr("name").unwrap_or_default().to_string(),
+        width,
+        height,
+        visible: element.attr("visible") != Some("0"),
+        opacity: element.parse_attr_or("opacity", 1.0)?,
+        tiles,
+        properties: parse_properties(element),
+    })
+}
+
+fn parse_properties(element: &XmlElement) -> HashMap<String, String> {
+    element
+        .children("properties")
+        .flat_map(|p| p.children("property"))
+        .filter_map(|p| {
+            let name = p.attr("name")?;
+            // multiline strings are stored as text instead of a value.
+            let value = p.attr("value").unwrap_or(p.text.as_str());
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+fn decode_base64(text: &str) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            c if c.is_ascii_whitespace() => continue,
+            c => bail!("invalid base64 character {:?}", c as char),
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Xml
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Just enough xml for the Tiled formats: elements, attributes and text. No namespaces, doctypes or cdata.
+#[derive(Debug, Default)]
+struct XmlElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn parse_attr<T: std::str::FromStr>(&self, name: &str) -> anyhow::Result<T> {
+        let value = self
+            .attr(name)
+            .ok_or_else(|| anyhow!("<{}> has no attribute {name}", self.name))?;
+        value
+            .parse()
+            .map_err(|_| anyhow!("invalid value {value:?} of attribute {name}"))
+    }
+
+    fn parse_attr_or<T: std::str::FromStr>(&self, name: &str, default: T) -> anyhow::Result<T> {
+        match self.attr(name) {
+            Some(_) => self.parse_attr(name),
+            None => Ok(default),
+        }
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find(|c| c.name == name)
+    }
+}
+
+fn parse_xml(src: &str) -> anyhow::Result<XmlElement> {
+    let mut parser = XmlParser { src, pos: 0 };
+    parser.skip_misc();
+    let root = parser.element()?;
+    parser.skip_misc();
+    if parser.pos < src.len() {
+        bail!(
+            "unexpected content after the root element at {}",
+            parser.pos
+        );
+    }
+    Ok(root)
+}
+
+struct XmlParser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> XmlParser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skips whitespace, the xml declaration and comments.
+    fn skip_misc(&mut self) {
+        loop {
+            self.skip_whitespace();
+            let end = if self.rest().starts_with("<?") {
+                "?>"
+            } else if self.rest().starts_with("<!--") {
+                "-->"
+            } else {
+                return;
+            };
+            match self.rest().find(end) {
+                Some(i) => self.pos += i + end.len(),
+                None => self.pos = self.src.len(),
+            }
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> anyhow::Result<()> {
+        if !self.rest().starts_with(s) {
+            bail!("expected {s:?} at {}", self.pos);
+        }
+        self.pos += s.len();
+        Ok(())
+    }
+
+    fn name(&mut self) -> anyhow::Result<&'a str> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            bail!("expected a name at {}", self.pos);
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn element(&mut self) -> anyhow::Result<XmlElement> {
+        self.expect("<")?;
+        let mut element = XmlElement {
+            name: self.name()?.to_string(),
+            ..Default::default()
+        };
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            let name = self.name()?.to_string();
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = if self.rest().starts_with('\'') {
+                "'"
+            } else {
+                "\""
+            };
+            self.expect(quote)?;
+            let len = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| anyhow!("unterminated attribute {name}"))?;
+            let value = unescape(&self.rest()[..len]);
+            self.pos += len + 1;
+            element.attributes.push((name, value));
+        }
+        loop {
+            let rest = self.rest();
+            let text_len = rest.find('<').unwrap_or(rest.len());
+            element.text.push_str(&unescape(&rest[..text_len]));
+            self.pos += text_len;
+            if self.rest().starts_with("<!--") || self.rest().starts_with("<?") {
+                self.skip_misc();
+            } else if self.rest().starts_with("</") {
+                self.pos += 2;
+                let name = self.name()?;
+                if name != element.name {
+                    bail!("expected </{}>, got </{name}>", element.name);
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(element);
+            } else if self.rest().is_empty() {
+                bail!("unclosed element <{}>", element.name);
+            } else {
+                element.children.push(self.element()?);
+            }
+        }
+    }
+}
+
+fn unescape(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_tmx_with_external_tileset() {
+        let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16" infinite="0">
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <layer id="1" name="ground" width="3" height="2">
+  <properties><property name="music" value="forest &amp; river"/></properties>
+  <data encoding="csv">
+1,2,0,
+3,2147483649,1
+</data>
+ </layer>
+ <layer id="2" name="deco" width="3" height="2" visible="0">
+  <data encoding="base64">AQAAAAAAAAAAAAAAAAAAAAAAAAACAAAA</data>
+ </layer>
+</map>"#;
+        let tsx = r#"<tileset name="terrain" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+ <image source="terrain.png" width="32" height="32"/>
+ <tile id="0"><properties><property name="solid" type="bool" value="true"/></properties></tile>
+ <tile id="2">
+  <objectgroup draworder="index"><object id="1" x="0" y="8" width="16" height="8"/></objectgroup>
+ </tile>
+</tileset>"#;
+        let map = Tilemap::from_tmx(tmx, |source| {
+            assert_eq!(source, "terrain.tsx");
+            Ok(tsx.to_string())
+        })
+        .unwrap();
+        assert_eq!(map.tilesets[0].columns, 2);
+        let ground = map.layer("ground").unwrap();
+        assert_eq!(ground.properties["music"], "forest & river");
+        let flipped = ground.get(1, 1).unwrap();
+        assert!(flipped.flip_h && flipped.gid == 1);
+        let deco = map.layer("deco").unwrap();
+        assert!(!deco.visible);
+        assert_eq!(deco.tiles, vec![1, 0, 0, 0, 0, 2]);
+
+        let rects = map.collision_rects(ground);
+        // the solid tile 1 (top left), the half tile 3 and the flipped solid tile 1 next to a solid tile 1.
+        assert_eq!(rects.len(), 3);
+        assert_eq!((rects[1].min_y, rects[1].height), (1.5, 0.5));
+        assert_eq!((rects[2].min_x, rects[2].width), (1.0, 2.0));
+    }
+}
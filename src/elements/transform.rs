@@ -1,12 +1,14 @@
-use std::f32::consts::PI;
+use std::{cell::Cell, f32::consts::PI, ops::Deref};
 
 use glam::{vec3, Affine3A, Mat4, Quat, Vec3};
 
 use crate::modules::{Attribute, VertexT};
 
-use super::buffer::ToRaw;
+use super::{buffer::ToRaw, lerp::Lerp};
 
-#[derive(Debug, Clone, Copy)]
+/// Translation, rotation and scale of an object, applied in the order scale, rotation, translation.
+/// The only transform type of vert: the renderers take it for their instances, see `ToRaw`.
+#[derive(Debug, Clone, Copy, PartialEq, Lerp)]
 pub struct Transform {
     pub position: Vec3,
     pub rotation: Quat,
@@ -14,12 +16,15 @@ pub struct Transform {
 }
 
 impl Transform {
-    pub const ZERO: Transform = Transform {
+    pub const IDENTITY: Transform = Transform {
         position: Vec3::ZERO,
         rotation: Quat::IDENTITY,
         scale: Vec3::ONE,
     };
 
+    /// Same as `IDENTITY`, at position zero.
+    pub const ZERO: Transform = Transform::IDENTITY;
+
     /// New Transform from Position
     pub fn new(x: f32, y: f32, z: f32) -> Self {
         Transform {
@@ -29,6 +34,112 @@ impl Transform {
         }
     }
 
+    pub const fn from_translation(position: Vec3) -> Self {
+        Transform {
+            position,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+
+    pub const fn from_rotation(rotation: Quat) -> Self {
+        Transform {
+            position: Vec3::ZERO,
+            rotation,
+            scale: Vec3::ONE,
+        }
+    }
+
+    pub const fn from_scale(scale: Vec3) -> Self {
+        Transform {
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale,
+        }
+    }
+
+    /// Decomposes the matrix, which should not contain shear or a projection.
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let (scale, rotation, position) = matrix.to_scale_rotation_translation();
+        Transform {
+            position,
+            rotation,
+            scale,
+        }
+    }
+
+    /// At `position`, with the forward direction (-z) pointing to `target`.
+    pub fn looking_at(position: Vec3, target: Vec3, up: Vec3) -> Self {
+        Transform::from_translation(position).look_at(target, up)
+    }
+
+    /// Rotates such that the forward direction (-z) points to `target`, keeping the position and scale.
+    /// Does nothing if `target` is the position.
+    pub fn look_at(mut self, target: Vec3, up: Vec3) -> Self {
+        let forward = (target - self.position).normalize_or_zero();
+        if forward == Vec3::ZERO {
+            return self;
+        }
+        // fall back to another up vector, if looking straight along it.
+        let up = match forward.cross(up).length_squared() < 1e-8 {
+            true => forward.any_orthonormal_vector(),
+            false => up,
+        };
+        self.rotation = Quat::from_mat4(&Mat4::look_to_rh(Vec3::ZERO, forward, up).inverse());
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_position(mut self, position: Vec3) -> Self {
+        self.position = position;
+        self
+    }
+
+    #[inline]
+    pub fn affine(&self) -> Affine3A {
+        Affine3A::from_scale_rotation_translation(self.scale, self.rotation, self.position)
+    }
+
+    #[inline]
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position)
+    }
+
+    #[inline]
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.position + self.rotation * (self.scale * point)
+    }
+
+    /// The -z axis after rotation.
+    #[inline]
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * Vec3::NEG_Z
+    }
+
+    #[inline]
+    pub fn right(&self) -> Vec3 {
+        self.rotation * Vec3::X
+    }
+
+    #[inline]
+    pub fn up(&self) -> Vec3 {
+        self.rotation * Vec3::Y
+    }
+
+    /// `child` relative to `self`, e.g. a weapon relative to the hand holding it. With non uniform scale,
+    /// the result is only exact if the child is not rotated.
+    pub fn mul_transform(&self, child: &Transform) -> Transform {
+        Transform {
+            position: self.transform_point(child.position),
+            rotation: self.rotation * child.rotation,
+            scale: self.scale * child.scale,
+        }
+    }
+
     pub fn face_minus_z(mut self) -> Self {
         self.rotate_y(-PI / 2.0);
         self
@@ -69,23 +180,50 @@ impl Transform {
     }
 }
 
+/// The transforms of the last two fixed steps, such that rendering (at a higher frame rate than the fixed update)
+/// can blend between them with `Time::fixed_alpha` and look smooth. `ColorMeshRenderer::draw_cubes_interpolated`
+/// blends them with the alpha of the current frame.
+#[derive(Debug, Clone, Copy)]
+pub struct InterpolatedTransform {
+    pub previous: Transform,
+    pub current: Transform,
+}
+
+impl InterpolatedTransform {
+    pub fn new(transform: Transform) -> Self {
+        InterpolatedTransform {
+            previous: transform,
+            current: transform,
+        }
+    }
+
+    /// Call once per fixed step with the new simulated transform.
+    pub fn push(&mut self, next: Transform) {
+        self.previous = self.current;
+        self.current = next;
+    }
+
+    /// Sets both transforms, such that the object does not visibly slide to its new position.
+    pub fn teleport(&mut self, transform: Transform) {
+        self.previous = transform;
+        self.current = transform;
+    }
+
+    /// `alpha` should be `Time::fixed_alpha`.
+    pub fn interpolate(&self, alpha: f32) -> Transform {
+        self.previous.lerp(&self.current, alpha)
+    }
+}
+
 impl From<Vec3> for Transform {
     fn from(translation: Vec3) -> Self {
-        Transform {
-            position: translation,
-            rotation: Default::default(),
-            scale: Vec3::ONE,
-        }
+        Transform::from_translation(translation)
     }
 }
 
 impl Default for Transform {
     fn default() -> Self {
-        Self {
-            position: Vec3::ZERO,
-            rotation: Quat::IDENTITY,
-            scale: Vec3::ONE,
-        }
+        Transform::IDENTITY
     }
 }
 
@@ -94,16 +232,87 @@ impl ToRaw for Transform {
 
     fn to_raw(&self) -> Self::Raw {
         TransformRaw {
-            affine: Affine3A::from_scale_rotation_translation(
-                self.scale,
-                self.rotation,
-                self.position,
-            )
-            .into(),
+            affine: self.affine().into(),
+        }
+    }
+}
+
+/// A `Transform` that keeps its matrix until it is changed, for objects that are drawn every frame but rarely move,
+/// e.g. many static instances. Read it through `Deref`, change it with `set` or `update`.
+#[derive(Debug, Clone)]
+pub struct CachedTransform {
+    transform: Transform,
+    /// None after a change, computed on the next access.
+    affine: Cell<Option<Affine3A>>,
+}
+
+impl CachedTransform {
+    pub fn new(transform: Transform) -> Self {
+        CachedTransform {
+            transform,
+            affine: Cell::new(None),
+        }
+    }
+
+    pub fn set(&mut self, transform: Transform) {
+        self.transform = transform;
+        self.affine.set(None);
+    }
+
+    pub fn update(&mut self, f: impl FnOnce(&mut Transform)) {
+        f(&mut self.transform);
+        self.affine.set(None);
+    }
+
+    pub fn affine(&self) -> Affine3A {
+        match self.affine.get() {
+            Some(affine) => affine,
+            None => {
+                let affine = self.transform.affine();
+                self.affine.set(Some(affine));
+                affine
+            }
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        self.affine().into()
+    }
+}
+
+impl Deref for CachedTransform {
+    type Target = Transform;
+
+    fn deref(&self) -> &Transform {
+        &self.transform
+    }
+}
+
+impl From<Transform> for CachedTransform {
+    fn from(transform: Transform) -> Self {
+        CachedTransform::new(transform)
+    }
+}
+
+impl ToRaw for CachedTransform {
+    type Raw = TransformRaw;
+
+    fn to_raw(&self) -> Self::Raw {
+        TransformRaw {
+            affine: self.affine().into(),
         }
     }
 }
 
+impl ToRaw for TransformRaw {
+    type Raw = TransformRaw;
+
+    #[inline(always)]
+    fn to_raw(&self) -> Self::Raw {
+        *self
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy, bytemuck::Zeroable)]
 #[repr(C)]
 pub struct TransformRaw {
@@ -115,12 +324,7 @@ unsafe impl bytemuck::Pod for TransformRaw {}
 impl TransformRaw {
     #[inline]
     pub fn compute_transform(&self) -> Transform {
-        let (scale, rotation, translation) = self.affine.to_scale_rotation_translation();
-        Transform {
-            position: translation,
-            rotation,
-            scale,
-        }
+        Transform::from_matrix(self.affine)
     }
 }
 
@@ -132,3 +336,35 @@ impl VertexT for TransformRaw {
         Attribute::new("translation", wgpu::VertexFormat::Float32x4),
     ];
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+
+    #[test]
+    fn look_at_and_cached_matrix() {
+        let eye = vec3(1.0, 2.0, 3.0);
+        let target = vec3(4.0, 2.0, -1.0);
+        let transform = Transform::looking_at(eye, target, Vec3::Y);
+        assert!(transform.forward().distance((target - eye).normalize()) < 1e-5);
+        assert!(transform.up().dot(Vec3::Y) > 0.99);
+        // straight down, with the up vector along the view direction.
+        let down = Transform::looking_at(eye, eye - Vec3::Y, Vec3::Y);
+        assert!(down.forward().distance(Vec3::NEG_Y) < 1e-5);
+
+        let mut cached = CachedTransform::new(transform.with_scale(2.0));
+        let point = vec3(0.5, -1.0, 2.0);
+        assert!(
+            cached
+                .affine()
+                .transform_point3(point)
+                .distance(cached.transform_point(point))
+                < 1e-5
+        );
+        cached.update(|t| t.position = Vec3::ZERO);
+        assert_eq!(cached.to_raw(), cached.with_scale(2.0).to_raw());
+        assert_eq!(cached.matrix().w_axis.truncate(), Vec3::ZERO);
+    }
+}
@@ -7,7 +7,7 @@
 #![feature(sync_unsafe_cell)]
 
 pub mod app;
-pub use app::{App, UpdateFlow, WinitConfig, WinitRunner};
+pub use app::{App, ExitReason, UpdateFlow, WinitConfig, WinitRunner};
 
 pub mod assets;
 pub use assets::{OwnedPtr, Ptr};
@@ -18,7 +18,7 @@ pub mod lifecycle;
 pub mod modules;
 pub mod utils;
 
-pub use lifecycle::{Prepare, ReceiveWindowEvent, Resize, Resized};
+pub use lifecycle::{GpuRecreate, GpuRecreated, Prepare, ReceiveWindowEvent, Resize, Resized};
 
 pub mod ext {
     pub use anyhow;
@@ -1,5 +1,7 @@
 use winit::{dpi::PhysicalSize, event::WindowEvent};
 
+use crate::modules::GraphicsContext;
+
 pub trait Prepare {
     fn prepare(
         &mut self,
@@ -17,6 +19,17 @@ pub trait Resize {
     fn resize(&mut self, resized: Resized);
 }
 
+/// Sent after the surface was reconfigured or the gpu device was lost and recreated.
+#[derive(Debug, Clone, Copy)]
+pub struct GpuRecreated {
+    /// If true, the old device is gone and all gpu resources (buffers, textures, pipelines) need to be recreated.
+    /// Otherwise only the surface was reconfigured and resources that depend on it (e.g. screen textures) are affected.
+    pub device_lost: bool,
+}
+pub trait GpuRecreate {
+    fn gpu_recreated(&mut self, ctx: &GraphicsContext, event: GpuRecreated);
+}
+
 pub trait ReceiveWindowEvent {
     fn receive_window_event(&mut self, event: &WindowEvent);
 }
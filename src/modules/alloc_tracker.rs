@@ -0,0 +1,290 @@
+//! Attributes heap allocations to the module or system that made them, to find out who is thrashing the
+//! allocator each frame.
+//!
+//! Opt in by installing the `TrackingAllocator` as the global allocator of the binary:
+//! ```rust,ignore
+//! #[global_allocator]
+//! static ALLOC: vert::modules::alloc_tracker::TrackingAllocator = TrackingAllocator::system();
+//! ```
+//! Allocations (and reallocations, e.g. a growing `Vec`) are attributed to the innermost `alloc_scope` of the
+//! thread they are made on, and deallocations to the scope of their allocation, such that live bytes stay correct
+//! when memory is freed somewhere else.
+//! Without the allocator installed, everything reports zero.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::{Cell, RefCell},
+    sync::{
+        atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// Tags beyond this number are counted as untagged.
+pub const MAX_ALLOC_TAGS: usize = 64;
+const UNTAGGED: &str = "untagged";
+
+/// Every allocation is prefixed with a header that stores its tag index.
+const HEADER: usize = 16;
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+/// names of the tags by index, index 0 is `UNTAGGED`.
+static TAGS: Mutex<Vec<&'static str>> = Mutex::new(vec![]);
+static COUNTERS: [TagCounters; MAX_ALLOC_TAGS] = [TagCounters::ZERO; MAX_ALLOC_TAGS];
+
+thread_local! {
+    static CURRENT_TAG: Cell<u32> = const { Cell::new(0) };
+    /// the indices of the tags this thread used before, such that `alloc_scope` does not lock `TAGS` every time.
+    static TAG_CACHE: RefCell<Vec<(&'static str, u32)>> = const { RefCell::new(Vec::new()) };
+}
+
+struct TagCounters {
+    live_bytes: AtomicIsize,
+    allocations: AtomicUsize,
+    allocated_bytes: AtomicUsize,
+}
+
+impl TagCounters {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: TagCounters = TagCounters {
+        live_bytes: AtomicIsize::new(0),
+        allocations: AtomicUsize::new(0),
+        allocated_bytes: AtomicUsize::new(0),
+    };
+}
+
+/// A global allocator that forwards to `A` and counts allocations per tag, see the module docs.
+pub struct TrackingAllocator<A: GlobalAlloc = System> {
+    inner: A,
+}
+
+impl TrackingAllocator<System> {
+    pub const fn system() -> Self {
+        TrackingAllocator { inner: System }
+    }
+}
+
+impl<A: GlobalAlloc> TrackingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        TrackingAllocator { inner }
+    }
+
+    /// True once a `TrackingAllocator` allocated something, i.e. it is most likely the global allocator.
+    pub fn is_installed() -> bool {
+        INSTALLED.load(Ordering::Relaxed)
+    }
+
+    fn outer_layout(layout: Layout) -> Option<(Layout, usize)> {
+        let offset = HEADER.max(layout.align());
+        let size = layout.size().checked_add(offset)?;
+        let outer = Layout::from_size_align(size, offset).ok()?;
+        Some((outer, offset))
+    }
+
+    /// Writes the tag in front of the allocation, returns the pointer handed out to the caller.
+    unsafe fn register(ptr: *mut u8, offset: usize, size: usize) -> *mut u8 {
+        if ptr.is_null() {
+            return ptr;
+        }
+        let tag = CURRENT_TAG.try_with(|t| t.get()).unwrap_or(0);
+        let user = ptr.add(offset);
+        (user.sub(4) as *mut u32).write(tag);
+        let counters = &COUNTERS[tag as usize];
+        counters
+            .live_bytes
+            .fetch_add(size as isize, Ordering::Relaxed);
+        counters.allocations.fetch_add(1, Ordering::Relaxed);
+        counters.allocated_bytes.fetch_add(size, Ordering::Relaxed);
+        INSTALLED.store(true, Ordering::Relaxed);
+        user
+    }
+
+    unsafe fn unregister(user: *mut u8, offset: usize, size: usize) -> *mut u8 {
+        let tag = (user.sub(4) as *const u32).read();
+        COUNTERS[tag as usize]
+            .live_bytes
+            .fetch_sub(size as isize, Ordering::Relaxed);
+        user.sub(offset)
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some((outer, offset)) = Self::outer_layout(layout) else {
+            return std::ptr::null_mut();
+        };
+        Self::register(self.inner.alloc(outer), offset, layout.size())
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let Some((outer, offset)) = Self::outer_layout(layout) else {
+            return std::ptr::null_mut();
+        };
+        Self::register(self.inner.alloc_zeroed(outer), offset, layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let (outer, offset) = Self::outer_layout(layout).unwrap();
+        let ptr = Self::unregister(ptr, offset, layout.size());
+        self.inner.dealloc(ptr, outer);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let (outer, offset) = Self::outer_layout(layout).unwrap();
+        let Some(new_outer_size) = new_size.checked_add(offset) else {
+            return std::ptr::null_mut();
+        };
+        let new = self.inner.realloc(ptr.sub(offset), outer, new_outer_size);
+        if new.is_null() {
+            return new;
+        }
+        // counted as a new allocation in the current scope, the old one is freed.
+        Self::unregister(new.add(offset), offset, layout.size());
+        Self::register(new, offset, new_size)
+    }
+}
+
+/// Attributes the allocations of this thread to `tag` until the returned guard is dropped. Scopes can be nested,
+/// the innermost one wins.
+pub fn alloc_scope(tag: &'static str) -> AllocScope {
+    let index = tag_index(tag);
+    let previous = CURRENT_TAG.with(|t| t.replace(index));
+    AllocScope { previous }
+}
+
+#[must_use = "allocations are only attributed to the tag while the scope is alive"]
+pub struct AllocScope {
+    previous: u32,
+}
+
+impl Drop for AllocScope {
+    fn drop(&mut self) {
+        CURRENT_TAG.with(|t| t.set(self.previous));
+    }
+}
+
+fn tag_index(tag: &'static str) -> u32 {
+    TAG_CACHE.with_borrow_mut(|cache| {
+        if let Some((_, index)) = cache.iter().find(|(t, _)| *t == tag) {
+            return *index;
+        }
+        let index = register_tag(tag);
+        cache.push((tag, index));
+        index
+    })
+}
+
+fn register_tag(tag: &'static str) -> u32 {
+    let mut tags = TAGS.lock().unwrap();
+    if tags.is_empty() {
+        tags.push(UNTAGGED);
+    }
+    if let Some(i) = tags.iter().position(|t| *t == tag) {
+        return i as u32;
+    }
+    if tags.len() == MAX_ALLOC_TAGS {
+        return 0;
+    }
+    tags.push(tag);
+    tags.len() as u32 - 1
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagAllocStats {
+    pub tag: &'static str,
+    /// bytes allocated in this scope that are not freed yet.
+    pub live_bytes: isize,
+    /// number of allocations since the last `AllocTracker::update`.
+    pub frame_allocations: usize,
+    pub frame_bytes: usize,
+    pub total_allocations: usize,
+}
+
+/// Turns the counters of the `TrackingAllocator` into per frame stats, call `update` once per frame.
+#[derive(Debug, Default)]
+pub struct AllocTracker {
+    /// allocations and allocated bytes per tag at the last update.
+    last: Vec<(usize, usize)>,
+    stats: Vec<TagAllocStats>,
+}
+
+impl AllocTracker {
+    pub fn new() -> Self {
+        AllocTracker::default()
+    }
+
+    pub fn update(&mut self) {
+        let tags: Vec<&'static str> = TAGS.lock().unwrap().clone();
+        self.last.resize(tags.len().max(1), (0, 0));
+        self.stats.clear();
+        for (i, last) in self.last.iter_mut().enumerate() {
+            let counters = &COUNTERS[i];
+            let allocations = counters.allocations.load(Ordering::Relaxed);
+            let bytes = counters.allocated_bytes.load(Ordering::Relaxed);
+            self.stats.push(TagAllocStats {
+                tag: tags.get(i).copied().unwrap_or(UNTAGGED),
+                live_bytes: counters.live_bytes.load(Ordering::Relaxed),
+                frame_allocations: allocations - last.0,
+                frame_bytes: bytes - last.1,
+                total_allocations: allocations,
+            });
+            *last = (allocations, bytes);
+        }
+        self.stats
+            .sort_by_key(|s| std::cmp::Reverse(s.frame_allocations));
+    }
+
+    /// Per tag, most allocations in the last frame first.
+    pub fn stats(&self) -> &[TagAllocStats] {
+        &self.stats
+    }
+
+    pub fn egui_alloc_stats(&self, egui_ctx: egui::Context) {
+        egui::Window::new("Allocations").show(&egui_ctx, |ui| {
+            if !TrackingAllocator::<System>::is_installed() {
+                ui.label("TrackingAllocator is not the global allocator");
+                return;
+            }
+            for s in self.stats.iter() {
+                ui.label(format!(
+                    "{}: {} allocs / {} bytes this frame, {} bytes live",
+                    s.tag, s.frame_allocations, s.frame_bytes, s.live_bytes
+                ));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_are_attributed_to_their_scope() {
+        let alloc = TrackingAllocator::system();
+        let mut tracker = AllocTracker::new();
+        let layout = Layout::from_size_align(100, 32).unwrap();
+        let live = |tracker: &AllocTracker| {
+            tracker
+                .stats()
+                .iter()
+                .find(|s| s.tag == "test scope")
+                .map(|s| (s.live_bytes, s.frame_allocations))
+        };
+        unsafe {
+            let scope = alloc_scope("test scope");
+            let ptr = alloc.alloc(layout);
+            assert_eq!(ptr as usize % 32, 0);
+            drop(scope);
+            tracker.update();
+            assert_eq!(live(&tracker), Some((100, 1)));
+
+            // freed in another scope, still subtracted from the scope it was allocated in.
+            let other = alloc_scope("other scope");
+            alloc.dealloc(ptr, layout);
+            drop(other);
+            tracker.update();
+            assert_eq!(live(&tracker), Some((0, 0)));
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::{any::TypeId, collections::HashMap, sync::Arc};
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use slotmap::{Key as KeyT, KeyData};
+
+use super::{
+    key::{DropQueue, Key, OwnedKey},
+    Arenas,
+};
+
+/// Bump this whenever the layout of `ArenasDump` changes. `Arenas::restore` refuses dumps of other versions.
+pub const DUMP_VERSION: u32 = 1;
+
+/// Snapshot of all arenas whose type is registered with `Arenas::register_serializer`.
+///
+/// Serialize it with any serde format. The values are already turned into strings by the registered serializers,
+/// so the dump itself does not need to know the types.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArenasDump {
+    pub version: u32,
+    pub arenas: Vec<ArenaDump>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArenaDump {
+    /// the name given in `Arenas::register_serializer`, not the rust type name, such that renaming a type does not break saves.
+    pub name: String,
+    pub entries: Vec<EntryDump>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryDump {
+    /// the key the value had when it was dumped.
+    pub key: u64,
+    pub stable_key: Option<String>,
+    pub data: String,
+}
+
+pub(super) struct ArenaSerializer {
+    pub name: &'static str,
+    pub type_id: TypeId,
+    pub dump: Box<dyn Fn(&Arenas) -> anyhow::Result<Vec<EntryDump>> + Send + Sync>,
+    /// inserts one value and returns its new key.
+    pub restore_entry: Box<dyn Fn(&mut Arenas, &str) -> anyhow::Result<KeyData> + Send + Sync>,
+}
+
+impl ArenaSerializer {
+    pub fn new<A: 'static + Sized + Send + Sync>(
+        name: &'static str,
+        to_string: impl Fn(&A) -> anyhow::Result<String> + Send + Sync + 'static,
+        from_str: impl Fn(&str) -> anyhow::Result<A> + Send + Sync + 'static,
+    ) -> Self {
+        ArenaSerializer {
+            name,
+            type_id: TypeId::of::<A>(),
+            dump: Box::new(move |arenas| {
+                let arena = arenas.read::<A>();
+                arena
+                    .iter()
+                    .map(|(key, value)| {
+                        Ok(EntryDump {
+                            key: key.data().as_ffi(),
+                            stable_key: arenas.stable_key_of(key).map(|s| s.name().to_string()),
+                            data: to_string(value)?,
+                        })
+                    })
+                    .collect()
+            }),
+            restore_entry: Box::new(move |arenas, data| {
+                let value = from_str(data)?;
+                // through `insert`, such that component hooks run.
+                Ok(arenas.insert(value).leak().data())
+            }),
+        }
+    }
+}
+
+/// Restored values get new keys. Maps the keys saved in the dump to the new ones.
+#[derive(Debug)]
+pub struct KeyRemap {
+    keys: HashMap<(TypeId, u64), KeyData>,
+    drop_queue: Arc<DropQueue>,
+}
+
+impl KeyRemap {
+    pub(super) fn insert(&mut self, type_id: TypeId, old: u64, new: KeyData) {
+        self.keys.insert((type_id, old), new);
+    }
+
+    /// None if there was no value with this key in the dump.
+    pub fn key<A: 'static + Sized>(&self, old: Key<A>) -> Option<Key<A>> {
+        let new = self.keys.get(&(TypeId::of::<A>(), old.data().as_ffi()))?;
+        Some((*new).into())
+    }
+
+    /// Turns an owned key that was saved together with the dump into the owned key of the restored value.
+    ///
+    /// ### Panics
+    ///
+    /// If there was no value with this key in the dump.
+    pub fn owned<A: 'static + Sized>(&self, old: OwnedKey<A>) -> OwnedKey<A> {
+        let new = self
+            .key(old.key())
+            .expect("owned key not part of the restored dump");
+        // the saved key does not own anything.
+        old.leak();
+        OwnedKey::new(new, self.drop_queue.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl Arenas {
+    /// Makes values of type `A` part of `dump` and `restore`. `name` identifies the arena in the dump.
+    ///
+    /// The serializers turn single values into strings and back, e.g. via `serde_json::to_string`.
+    pub fn register_serializer<A: 'static + Sized + Send + Sync>(
+        &mut self,
+        name: &'static str,
+        to_string: impl Fn(&A) -> anyhow::Result<String> + Send + Sync + 'static,
+        from_str: impl Fn(&str) -> anyhow::Result<A> + Send + Sync + 'static,
+    ) {
+        assert!(
+            self.serializers.iter().all(|s| s.name != name),
+            "arena serializer {name} registered twice"
+        );
+        self.serializers
+            .push(ArenaSerializer::new(name, to_string, from_str));
+    }
+
+    pub fn dump(&self) -> anyhow::Result<ArenasDump> {
+        let arenas = self
+            .serializers
+            .iter()
+            .map(|s| {
+                Ok(ArenaDump {
+                    name: s.name.to_string(),
+                    entries: (s.dump)(self)?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(ArenasDump {
+            version: DUMP_VERSION,
+            arenas,
+        })
+    }
+
+    /// Inserts all values of the dump, in addition to the values that are already in the arenas.
+    /// The restored values get new keys (see `KeyRemap`), their stable keys are assigned to them again.
+    pub fn restore(&mut self, dump: &ArenasDump) -> anyhow::Result<KeyRemap> {
+        if dump.version != DUMP_VERSION {
+            bail!(
+                "arenas dump has version {}, expected {DUMP_VERSION}",
+                dump.version
+            );
+        }
+        let serializers = std::mem::take(&mut self.serializers);
+        let result = self.restore_with(&serializers, dump);
+        self.serializers = serializers;
+        result
+    }
+
+    fn restore_with(
+        &mut self,
+        serializers: &[ArenaSerializer],
+        dump: &ArenasDump,
+    ) -> anyhow::Result<KeyRemap> {
+        let mut remap = KeyRemap {
+            keys: HashMap::new(),
+            drop_queue: self.drop_queue.clone(),
+        };
+        for arena in dump.arenas.iter() {
+            let Some(serializer) = serializers.iter().find(|s| s.name == arena.name) else {
+                bail!("no serializer registered for arena {}", arena.name);
+            };
+            for entry in arena.entries.iter() {
+                let key = (serializer.restore_entry)(self, &entry.data)?;
+                remap.insert(serializer.type_id, entry.key, key);
+                if let Some(stable_key) = &entry.stable_key {
+                    self.stable_keys
+                        .get_mut()
+                        .unwrap()
+                        .entry(serializer.type_id)
+                        .or_default()
+                        .insert(stable_key.clone(), key);
+                }
+            }
+        }
+        Ok(remap)
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use crate::modules::GraphicsContext;
+
+use super::Arenas;
+
+/// Values that want to be notified when they enter or leave the `Arenas`, e.g. to create and destroy
+/// the gpu buffers and bind groups they own. Opt-in per type with `Arenas::register_component`.
+///
+/// Hooks only run for values inserted and removed through `Arenas` (`insert`, `remove`, `remove_dropped`, `restore`),
+/// not for direct access via `write` or `arena_mut`, and not when the `Arenas` themselves are dropped.
+pub trait Component: 'static + Sized + Send + Sync {
+    /// Called right before the value is inserted.
+    fn on_insert(&mut self, _ctx: &HookContext) {}
+    /// Called right after the value was removed.
+    fn on_remove(&mut self, _ctx: &HookContext) {}
+}
+
+/// What hooks get to work with, set with `Arenas::set_hook_context`.
+/// Without a graphics context (e.g. in tests or on a server) device and queue are None.
+#[derive(Debug, Clone, Default)]
+pub struct HookContext {
+    pub device: Option<Arc<wgpu::Device>>,
+    pub queue: Option<Arc<wgpu::Queue>>,
+}
+
+impl HookContext {
+    pub fn new(ctx: &GraphicsContext) -> Self {
+        HookContext {
+            device: Some(ctx.device.clone()),
+            queue: Some(ctx.queue.clone()),
+        }
+    }
+}
+
+/// The hooks of a registered component type, stored in its arena.
+pub(super) struct Hooks<T> {
+    pub on_insert: fn(&mut T, &HookContext),
+    pub on_remove: fn(&mut T, &HookContext),
+}
+
+impl<T> Clone for Hooks<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Hooks<T> {}
+
+impl Arenas {
+    /// Lets the arena of `C` call the hooks of `C` from now on.
+    pub fn register_component<C: Component>(&mut self) {
+        self.arena_mut::<C>().hooks = Some(Hooks {
+            on_insert: C::on_insert,
+            on_remove: C::on_remove,
+        });
+    }
+
+    pub fn set_hook_context(&mut self, ctx: HookContext) {
+        self.hook_context = ctx;
+    }
+
+    pub fn hook_context(&self) -> &HookContext {
+        &self.hook_context
+    }
+}
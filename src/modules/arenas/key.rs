@@ -1,23 +1,126 @@
-use std::{any::TypeId, fmt::Display, marker::PhantomData};
+use std::{
+    any::TypeId,
+    borrow::Cow,
+    fmt::Display,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
 
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use slotmap::{Key as KeyT, KeyData};
 
 /// An owned key cannot be cloned or in any way duplicated (except with unsafe of course). it is unique.
 ///
 /// But it can be converted into any number of normal keys that can be passed around.
-#[repr(C)]
-pub struct OwnedKey<T: 'static + Sized>(pub(super) Key<T>);
+///
+/// Dropping an owned key removes its value from the arena: the removal is queued and carried out
+/// by `Arenas::remove_dropped`, which should be called once per frame. Use `Arenas::remove` to get the value back instead.
+pub struct OwnedKey<T: 'static + Sized> {
+    pub(super) key: Key<T>,
+    /// None for keys that were deserialized but not yet remapped, see `KeyRemap::owned`.
+    pub(super) drop_queue: Option<Arc<DropQueue>>,
+}
 
 impl<T: 'static + Sized> OwnedKey<T> {
+    pub(super) fn new(key: Key<T>, drop_queue: Arc<DropQueue>) -> Self {
+        OwnedKey {
+            key,
+            drop_queue: Some(drop_queue),
+        }
+    }
+
     pub fn key(&self) -> Key<T> {
-        self.0
+        self.key
+    }
+
+    pub fn downgrade(&self) -> WeakKey<T> {
+        WeakKey { key: self.key }
+    }
+
+    /// Gives up ownership without removing the value, it stays in the arena forever.
+    pub fn leak(mut self) -> Key<T> {
+        self.drop_queue = None;
+        self.key
+    }
+}
+
+impl<T: 'static + Sized> Drop for OwnedKey<T> {
+    fn drop(&mut self) {
+        if let Some(drop_queue) = self.drop_queue.take() {
+            drop_queue.push(TypeId::of::<T>(), self.key.data());
+        }
     }
 }
 
-// only from Owned -> Normal is allowed!
+impl<T: 'static + Sized> std::fmt::Debug for OwnedKey<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("OwnedKey").field(&self.key).finish()
+    }
+}
+
+/// Keys of values whose `OwnedKey` was dropped, shared by all owned keys of one `Arenas`.
+#[derive(Debug, Default)]
+pub(super) struct DropQueue {
+    pending: Mutex<Vec<(TypeId, KeyData)>>,
+}
+
+impl DropQueue {
+    fn push(&self, type_id: TypeId, key: KeyData) {
+        self.pending.lock().unwrap().push((type_id, key));
+    }
+
+    pub(super) fn take(&self) -> Vec<(TypeId, KeyData)> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+
+    pub(super) fn contains(&self, type_id: TypeId, key: KeyData) -> bool {
+        self.pending.lock().unwrap().contains(&(type_id, key))
+    }
+}
+
+/// A key that does not keep its value alive, created with `OwnedKey::downgrade`.
+///
+/// Unlike a plain `Key`, it does not upgrade once the `OwnedKey` is dropped, even if the value is
+/// still in the arena because `Arenas::remove_dropped` did not run yet. See `Arenas::upgrade`.
+pub struct WeakKey<T: 'static + Sized> {
+    pub(super) key: Key<T>,
+}
+
+impl<T: 'static + Sized> Clone for WeakKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: 'static + Sized> Copy for WeakKey<T> {}
+
+impl<T: 'static + Sized> std::fmt::Debug for WeakKey<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("WeakKey").field(&self.key).finish()
+    }
+}
+
+/// Deserializing an owned key duplicates it, if the original is still around.
+/// Only do that for keys saved together with the arenas, and remap them with `KeyRemap::owned` after `Arenas::restore`.
+impl<T: 'static + Sized> Serialize for OwnedKey<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.key.serialize(serializer)
+    }
+}
+
+impl<'de, T: 'static + Sized> Deserialize<'de> for OwnedKey<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Key::deserialize(deserializer).map(|key| OwnedKey {
+            key,
+            drop_queue: None,
+        })
+    }
+}
+
+// only from Owned -> Normal is allowed! The value is leaked, see `OwnedKey::leak`.
 impl<T: 'static + Sized> From<OwnedKey<T>> for Key<T> {
     fn from(value: OwnedKey<T>) -> Self {
-        value.0
+        value.leak()
     }
 }
 
@@ -114,3 +217,77 @@ impl<T: 'static + Sized> From<KeyData> for Key<T> {
         }
     }
 }
+
+/// Keys are serialized as a single u64. They are only meaningful for the arena they were created in,
+/// use a `StableKey` for references that should survive saving and loading.
+impl<T: 'static + Sized> Serialize for Key<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.value.as_ffi())
+    }
+}
+
+impl<'de, T: 'static + Sized> Deserialize<'de> for Key<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(|ffi| KeyData::from_ffi(ffi).into())
+    }
+}
+
+/// A name for a value in an arena, see `Arenas::set_stable_key`.
+///
+/// Unlike a `Key`, the name stays the same across runs, because it is saved in `Arenas::dump`
+/// and assigned to the restored value again in `Arenas::restore`. Resolve it with `Arenas::resolve`.
+pub struct StableKey<T: 'static + Sized> {
+    name: Cow<'static, str>,
+    phantom: PhantomData<T>,
+}
+
+impl<T: 'static + Sized> StableKey<T> {
+    pub fn new(name: impl Into<Cow<'static, str>>) -> Self {
+        StableKey {
+            name: name.into(),
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<T: 'static + Sized> Clone for StableKey<T> {
+    fn clone(&self) -> Self {
+        StableKey::new(self.name.clone())
+    }
+}
+
+impl<T: 'static + Sized> std::fmt::Debug for StableKey<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("StableKey").field(&self.name).finish()
+    }
+}
+
+impl<T: 'static + Sized> PartialEq for StableKey<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl<T: 'static + Sized> Eq for StableKey<T> {}
+
+impl<T: 'static + Sized> std::hash::Hash for StableKey<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+impl<T: 'static + Sized> Serialize for StableKey<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name)
+    }
+}
+
+impl<'de, T: 'static + Sized> Deserialize<'de> for StableKey<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(StableKey::new)
+    }
+}
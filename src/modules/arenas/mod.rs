@@ -1,19 +1,52 @@
-use crate::utils::ChillCell;
-use slotmap::SlotMap;
+use slotmap::{Key as KeyT, KeyData, SlotMap};
 use std::{
-    any::TypeId,
+    any::{Any, TypeId},
     collections::HashMap,
-    ops::{DerefMut, Index, IndexMut},
+    ops::DerefMut,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
 };
 
-mod key;
-use key::{Key, OwnedKey};
+pub mod key;
+use key::{DropQueue, Key, OwnedKey, StableKey, WeakKey};
 
-struct Arenas {
-    /// Todo! doing ChillCell + HashMap lookup is absolutely disgusting.
+mod dump;
+pub use dump::{ArenaDump, ArenasDump, EntryDump, KeyRemap, DUMP_VERSION};
+
+mod stats;
+pub use stats::{ArenaStats, LeakDetector, MemoryReport};
+
+mod hooks;
+pub use hooks::{Component, HookContext};
+
+mod resources;
+
+/// One `Arena<A>` per type `A`, created lazily on first access.
+///
+/// Every arena sits behind its own `RwLock`, so `Arenas` can be shared between threads (e.g. tokio tasks loading assets):
+/// through `&self`, arenas are accessed via the `read` and `write` guards, and different arenas do not block each other.
+/// Through `&mut self`, no locking is needed. To mutate two arenas at once, use `arena_pair_mut`,
+/// which panics if both are the same arena.
+///
+/// Types registered with `register_serializer` can be saved with `dump` and loaded again with `restore`.
+///
+/// Values are owned by their `OwnedKey`: dropping it queues the removal of the value, call `remove_dropped` once per frame.
+/// Types registered with `register_component` get their `Component` hooks called on insert and remove.
+///
+/// Besides the arenas, there is at most one resource per type, see `insert_resource`. Resources have change ticks,
+/// such that systems can tell if e.g. the settings changed since they last looked.
+pub struct Arenas {
+    /// Todo! doing a HashMap lookup + downcast on every access is not great.
     /// It would be better if could construct something at compile time.
     /// This is just an intermediate solution, to get something working.
-    any: ChillCell<HashMap<TypeId, UntypedArena>>,
+    ///
+    /// Append only: shards (`RwLock<Arena<A>>`) are never removed or replaced, and they are boxed,
+    /// such that references to them stay valid while the map grows. See `Arenas::shard`.
+    shards: RwLock<HashMap<TypeId, Box<dyn Shard>>>,
+    stable_keys: RwLock<HashMap<TypeId, StableKeys>>,
+    serializers: Vec<dump::ArenaSerializer>,
+    drop_queue: Arc<DropQueue>,
+    hook_context: HookContext,
+    resources: resources::Resources,
 }
 
 impl Default for Arenas {
@@ -25,77 +58,276 @@ impl Default for Arenas {
 impl Arenas {
     pub fn new() -> Self {
         Arenas {
-            any: ChillCell::new(HashMap::new()),
+            shards: RwLock::new(HashMap::new()),
+            stable_keys: RwLock::new(HashMap::new()),
+            serializers: vec![],
+            drop_queue: Arc::new(DropQueue::default()),
+            hook_context: HookContext::default(),
+            resources: Default::default(),
         }
     }
 
-    pub fn arena<A: 'static + Sized>(&self) -> &Arena<A> {
-        self._any_arena_internal::<A>()
+    /// The lock of the arena for `A`, creating the arena if needed.
+    fn shard<A: 'static + Sized + Send + Sync>(&self) -> &RwLock<Arena<A>> {
+        let type_key = TypeId::of::<A>();
+        let shard_ptr: *const RwLock<Arena<A>> = {
+            let shards = self.shards.read().unwrap();
+            match shards.get(&type_key) {
+                Some(shard) => shard.as_any().downcast_ref().expect("arena of wrong type"),
+                None => {
+                    drop(shards);
+                    let mut shards = self.shards.write().unwrap();
+                    let shard = shards
+                        .entry(type_key)
+                        .or_insert_with(|| Box::new(RwLock::new(Arena::<A>::new())));
+                    shard.as_any().downcast_ref().expect("arena of wrong type")
+                }
+            }
+        };
+        // Safety: the shard is boxed and never removed from the map while `self` is borrowed,
+        // so the reference is valid for the lifetime of `&self` even after the map lock is released.
+        unsafe { &*shard_ptr }
+    }
+
+    /// Shared access to the arena of `A`. Blocks while another thread holds the `write` guard of this arena.
+    pub fn read<A: 'static + Sized + Send + Sync>(&self) -> RwLockReadGuard<'_, Arena<A>> {
+        self.shard::<A>().read().unwrap()
     }
 
-    pub fn any_arena_mut<A: 'static + Sized>(&mut self) -> &mut Arena<A> {
-        self._any_arena_internal::<A>()
+    /// Exclusive access to the arena of `A`, from any thread.
+    pub fn write<A: 'static + Sized + Send + Sync>(&self) -> RwLockWriteGuard<'_, Arena<A>> {
+        self.shard::<A>().write().unwrap()
     }
 
-    #[inline]
-    pub fn _any_arena_internal<A: 'static + Sized>(&self) -> &mut Arena<A> {
-        let type_key = TypeId::of::<A>();
-        let arena = self
-            .any
+    /// Like `write`, but without locking, because `&mut self` already guarantees exclusive access.
+    pub fn arena_mut<A: 'static + Sized + Send + Sync>(&mut self) -> &mut Arena<A> {
+        self.shards
             .get_mut()
-            .entry(type_key)
-            .or_insert_with(|| Arena::<A>::new().into_untyped())
-            .typed_mut::<A>();
-        arena
+            .unwrap()
+            .entry(TypeId::of::<A>())
+            .or_insert_with(|| Box::new(RwLock::new(Arena::<A>::new())))
+            .as_any_mut()
+            .downcast_mut::<RwLock<Arena<A>>>()
+            .expect("arena of wrong type")
+            .get_mut()
+            .unwrap()
+    }
+
+    /// Mutable access to two different arenas at the same time.
+    ///
+    /// ### Panics
+    ///
+    /// If `A` and `B` are the same type, because that would hand out two `&mut` to the same arena.
+    pub fn arena_pair_mut<A: 'static + Sized + Send + Sync, B: 'static + Sized + Send + Sync>(
+        &mut self,
+    ) -> (&mut Arena<A>, &mut Arena<B>) {
+        let (a_id, b_id) = (TypeId::of::<A>(), TypeId::of::<B>());
+        assert!(
+            a_id != b_id,
+            "aliased access: arena of {} borrowed mutably twice",
+            std::any::type_name::<A>()
+        );
+        self.arena_mut::<A>();
+        self.arena_mut::<B>();
+        let mut a = None;
+        let mut b = None;
+        for (id, shard) in self.shards.get_mut().unwrap().iter_mut() {
+            if *id == a_id {
+                a = shard.as_any_mut().downcast_mut::<RwLock<Arena<A>>>();
+            } else if *id == b_id {
+                b = shard.as_any_mut().downcast_mut::<RwLock<Arena<B>>>();
+            }
+        }
+        (a.unwrap().get_mut().unwrap(), b.unwrap().get_mut().unwrap())
     }
 
-    pub fn insert<A: 'static + Sized>(&mut self, value: A) -> OwnedKey<A> {
-        let key = self._any_arena_internal::<A>().insert(value);
-        OwnedKey(key)
+    pub fn insert<A: 'static + Sized + Send + Sync>(&self, mut value: A) -> OwnedKey<A> {
+        // the hook runs without holding the lock.
+        let hooks = self.read::<A>().hooks;
+        if let Some(hooks) = hooks {
+            (hooks.on_insert)(&mut value, &self.hook_context);
+        }
+        let key = self.write::<A>().insert(value);
+        OwnedKey::new(key, self.drop_queue.clone())
     }
 
     /// This consumes the OwnedKey, to make it impossible to use it later.
-    pub fn remove<A: 'static + Sized>(&mut self, key: OwnedKey<A>) -> Option<A> {
-        self._any_arena_internal::<A>().remove(key.0)
+    pub fn remove<A: 'static + Sized + Send + Sync>(&self, key: OwnedKey<A>) -> Option<A> {
+        if let Some(stable_keys) = self
+            .stable_keys
+            .write()
+            .unwrap()
+            .get_mut(&TypeId::of::<A>())
+        {
+            stable_keys.remove_key(key.key.data());
+        }
+        let mut arena = self.write::<A>();
+        let hooks = arena.hooks;
+        let mut value = arena.remove(key.leak());
+        drop(arena);
+        if let (Some(hooks), Some(value)) = (hooks, &mut value) {
+            (hooks.on_remove)(value, &self.hook_context);
+        }
+        value
+    }
+
+    /// Removes the values whose `OwnedKey` was dropped. Call this once per frame.
+    /// Returns the number of values removed.
+    pub fn remove_dropped(&mut self) -> usize {
+        let mut removed = 0;
+        // dropping a value can drop owned keys it holds, which queues more removals.
+        loop {
+            let dropped = self.drop_queue.take();
+            if dropped.is_empty() {
+                return removed;
+            }
+            let shards = self.shards.get_mut().unwrap();
+            let stable_keys = self.stable_keys.get_mut().unwrap();
+            for (type_id, key) in dropped {
+                if let Some(stable_keys) = stable_keys.get_mut(&type_id) {
+                    stable_keys.remove_key(key);
+                }
+                if let Some(shard) = shards.get_mut(&type_id) {
+                    removed += shard.remove(key, &self.hook_context) as usize;
+                }
+            }
+        }
+    }
+
+    /// The key, if the value is still there and its `OwnedKey` was not dropped.
+    pub fn upgrade<A: 'static + Sized + Send + Sync>(&self, weak: &WeakKey<A>) -> Option<Key<A>> {
+        let alive = self.read::<A>().contains_key(weak.key)
+            && !self.drop_queue.contains(TypeId::of::<A>(), weak.key.data());
+        alive.then_some(weak.key)
     }
 
-    pub fn get_mut<A: 'static + Sized>(&self, key: &OwnedKey<A>) -> &mut A {
-        self._any_arena_internal::<A>()
-            .get_mut(key.0)
+    pub fn get_mut<A: 'static + Sized + Send + Sync>(&mut self, key: &OwnedKey<A>) -> &mut A {
+        self.arena_mut::<A>()
+            .get_mut(key.key)
             .expect("owned key resource always present")
     }
 
-    pub fn get<A: 'static + Sized>(&self, key: Key<A>) -> Option<&A> {
-        self._any_arena_internal::<A>().get(key)
+    /// Copies the value out, to not hold the lock. Use `read` to borrow values instead.
+    pub fn get<A: 'static + Sized + Send + Sync + Clone>(&self, key: Key<A>) -> Option<A> {
+        self.read::<A>().get(key).cloned()
     }
-}
 
-impl<T: 'static + Sized> Index<Key<T>> for Arenas {
-    type Output = T;
+    /// Gives the value a name that survives `dump` and `restore`. A name refers to one value at a time,
+    /// so setting it again moves it to the new key. The name is dropped when the value is removed.
+    pub fn set_stable_key<A: 'static + Sized + Send + Sync>(
+        &self,
+        key: Key<A>,
+        name: impl Into<String>,
+    ) -> StableKey<A> {
+        let name: String = name.into();
+        self.stable_keys
+            .write()
+            .unwrap()
+            .entry(TypeId::of::<A>())
+            .or_default()
+            .insert(name.clone(), key.data());
+        StableKey::new(name)
+    }
 
-    fn index(&self, key: Key<T>) -> &Self::Output {
-        self._any_arena_internal().get(key).unwrap()
+    /// None if no value has this stable key (anymore).
+    pub fn resolve<A: 'static + Sized + Send + Sync>(
+        &self,
+        stable_key: &StableKey<A>,
+    ) -> Option<Key<A>> {
+        let key: Key<A> = {
+            let stable_keys = self.stable_keys.read().unwrap();
+            (*stable_keys
+                .get(&TypeId::of::<A>())?
+                .by_name
+                .get(stable_key.name())?)
+            .into()
+        };
+        self.read::<A>().contains_key(key).then_some(key)
     }
+
+    pub fn stable_key_of<A: 'static + Sized + Send + Sync>(
+        &self,
+        key: Key<A>,
+    ) -> Option<StableKey<A>> {
+        let stable_keys = self.stable_keys.read().unwrap();
+        let name = stable_keys
+            .get(&TypeId::of::<A>())?
+            .by_key
+            .get(&key.data())?;
+        Some(StableKey::new(name.clone()))
+    }
+}
+
+/// An arena behind its lock, with the operations that are needed without knowing its type.
+trait Shard: Any + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// true if there was a value with this key.
+    fn remove(&mut self, key: KeyData, hook_context: &HookContext) -> bool;
+    fn stats(&self) -> ArenaStats;
 }
 
-// Note: no IndexMut implementation for Key<A> only for OwnedKey<A>
+impl<A: 'static + Sized + Send + Sync> Shard for RwLock<Arena<A>> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 
-impl<T: 'static + Sized> Index<&OwnedKey<T>> for Arenas {
-    type Output = T;
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn remove(&mut self, key: KeyData, hook_context: &HookContext) -> bool {
+        let arena = self.get_mut().unwrap();
+        let Some(mut value) = arena.remove(key.into()) else {
+            return false;
+        };
+        if let Some(hooks) = arena.hooks {
+            (hooks.on_remove)(&mut value, hook_context);
+        }
+        true
+    }
 
-    fn index(&self, key: &OwnedKey<T>) -> &Self::Output {
-        self._any_arena_internal().get(key.0).unwrap()
+    fn stats(&self) -> ArenaStats {
+        let arena = self.read().unwrap();
+        // a slot is the value (or the next free slot) and a u32 version.
+        let slot_size = std::mem::size_of::<A>().max(4) + 4;
+        ArenaStats {
+            type_name: std::any::type_name::<A>(),
+            len: arena.len(),
+            capacity: arena.capacity(),
+            free: arena.capacity() - arena.len(),
+            bytes: arena.capacity() * slot_size,
+        }
     }
 }
 
-impl<T: 'static + Sized> IndexMut<&OwnedKey<T>> for Arenas {
-    fn index_mut(&mut self, key: &OwnedKey<T>) -> &mut Self::Output {
-        self._any_arena_internal().get_mut(key.0).unwrap()
+/// Stable key names of one arena, in both directions.
+#[derive(Debug, Default)]
+struct StableKeys {
+    by_name: HashMap<String, KeyData>,
+    by_key: HashMap<KeyData, String>,
+}
+
+impl StableKeys {
+    fn insert(&mut self, name: String, key: KeyData) {
+        if let Some(old_key) = self.by_name.insert(name.clone(), key) {
+            self.by_key.remove(&old_key);
+        }
+        if let Some(old_name) = self.by_key.insert(key, name) {
+            self.by_name.remove(&old_name);
+        }
+    }
+
+    fn remove_key(&mut self, key: KeyData) {
+        if let Some(name) = self.by_key.remove(&key) {
+            self.by_name.remove(&name);
+        }
     }
 }
 
 pub struct Arena<T: 'static + Sized> {
     inner: SlotMap<Key<T>, T>,
+    hooks: Option<hooks::Hooks<T>>,
 }
 
 /// This is a bit lazy, we want to use our own functions in the future to supports,
@@ -118,37 +350,252 @@ impl<T: 'static + Sized> Arena<T> {
     pub fn new() -> Self {
         Arena {
             inner: Default::default(),
+            hooks: None,
         }
     }
-
-    fn into_untyped(self) -> UntypedArena {
-        unsafe { std::mem::transmute(self) }
-    }
 }
 
 impl<T: 'static + Sized> Default for Arena<T> {
     fn default() -> Self {
         Self {
             inner: Default::default(),
+            hooks: None,
         }
     }
 }
 
-enum Never {}
-struct UntypedArena {
-    _inner: SlotMap<Key<Never>, Never>,
-}
+#[cfg(test)]
+mod tests {
+    //! No ffi involved, so these also run under miri: `cargo +nightly miri test --lib arenas`.
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::{Arenas, Component, HookContext, LeakDetector, OwnedKey};
+
+    #[test]
+    fn insert_get_remove() {
+        let mut arenas = Arenas::new();
+        assert!(arenas.read::<String>().is_empty());
+        let a = arenas.insert(String::from("a"));
+        let one = arenas.insert(1u64);
+        arenas.get_mut(&a).push('b');
+        *arenas.get_mut(&one) += 1;
+        assert_eq!(arenas.read::<String>()[a.key()], "ab");
+        assert_eq!(arenas.get(one.key()), Some(2));
+        assert_eq!(arenas.remove(a).as_deref(), Some("ab"));
+        assert_eq!(arenas.read::<String>().len(), 0);
+    }
+
+    #[test]
+    fn pair_mut() {
+        let mut arenas = Arenas::new();
+        let a = arenas.insert(1u32);
+        let b = arenas.insert(2u64);
+        let (a_arena, b_arena) = arenas.arena_pair_mut::<u32, u64>();
+        a_arena[a.key()] += 1;
+        b_arena[b.key()] += a_arena[a.key()] as u64;
+        assert_eq!(arenas.get(b.key()), Some(4));
+    }
+
+    #[test]
+    fn dump_restore() {
+        let mut arenas = Arenas::new();
+        arenas.register_serializer::<i32>("numbers", |n| Ok(n.to_string()), |s| Ok(s.parse()?));
+        let one = arenas.insert(1);
+        let two = arenas.insert(2);
+        let stable = arenas.set_stable_key(two.key(), "two");
+
+        let dump = arenas.dump().unwrap();
+        let mut restored = Arenas::new();
+        restored.register_serializer::<i32>("numbers", |n| Ok(n.to_string()), |s| Ok(s.parse()?));
+        restored.insert(100);
+        let remap = restored.restore(&dump).unwrap();
+
+        assert_eq!(remap.len(), 2);
+        assert_eq!(restored.get(remap.key(one.key()).unwrap()), Some(1));
+        assert_eq!(*restored.get_mut(&remap.owned(two)), 2);
+        assert_eq!(restored.get(restored.resolve(&stable).unwrap()), Some(2));
+
+        let mut outdated = dump.clone();
+        outdated.version += 1;
+        assert!(restored.restore(&outdated).is_err());
+    }
+
+    #[test]
+    fn drop_owned_key() {
+        struct Node {
+            _child: Option<OwnedKey<Node>>,
+        }
+        let mut arenas = Arenas::new();
+        let leaf = arenas.insert(Node { _child: None });
+        let weak_leaf = leaf.downgrade();
+        let root = arenas.insert(Node { _child: Some(leaf) });
+        let kept = arenas.insert(Node { _child: None });
+
+        drop(root);
+        // the child is removed in a second round, after its parent was dropped:
+        assert!(arenas.upgrade(&weak_leaf).is_some());
+        assert_eq!(arenas.remove_dropped(), 2);
+        assert!(arenas.upgrade(&weak_leaf).is_none());
+        assert_eq!(arenas.read::<Node>().len(), 1);
+
+        let weak_kept = kept.downgrade();
+        let leaked = kept.leak();
+        assert_eq!(arenas.remove_dropped(), 0);
+        assert_eq!(arenas.upgrade(&weak_kept), Some(leaked));
+    }
+
+    #[test]
+    fn memory_report_and_leaks() {
+        let mut arenas = Arenas::new();
+        let mut detector = LeakDetector::new(3);
+        let mut keys = vec![];
+        for i in 0..2 {
+            keys.push(arenas.insert(i as u64));
+            assert!(detector.check(&arenas.memory_report()).is_empty());
+        }
+        keys.push(arenas.insert(2u64));
+        assert_eq!(detector.check(&arenas.memory_report()), vec!["u64"]);
+
+        keys.truncate(1);
+        arenas.remove_dropped();
+        let report = arenas.memory_report();
+        let stats = report.get("u64").unwrap();
+        assert_eq!(stats.len, 1);
+        assert_eq!(stats.free, stats.capacity - 1);
+        assert!(detector.check(&report).is_empty());
+    }
+
+    #[test]
+    fn component_hooks() {
+        static REMOVED: AtomicUsize = AtomicUsize::new(0);
+        struct Buffer {
+            created: bool,
+        }
+        impl Component for Buffer {
+            fn on_insert(&mut self, _ctx: &HookContext) {
+                self.created = true;
+            }
+            fn on_remove(&mut self, _ctx: &HookContext) {
+                REMOVED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut arenas = Arenas::new();
+        arenas.register_component::<Buffer>();
+        let a = arenas.insert(Buffer { created: false });
+        let b = arenas.insert(Buffer { created: false });
+        assert!(arenas.get_mut(&a).created);
+        assert!(arenas.remove(a).unwrap().created);
+        drop(b);
+        arenas.remove_dropped();
+        assert_eq!(REMOVED.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let arenas = Arenas::new();
+        std::thread::scope(|scope| {
+            for t in 0..4u64 {
+                let arenas = &arenas;
+                scope.spawn(move || {
+                    for i in 0..100 {
+                        let key = arenas.insert(t * 1000 + i);
+                        assert_eq!(arenas.get(key.key()), Some(t * 1000 + i));
+                        arenas.insert(format!("{t} {i}"));
+                    }
+                });
+            }
+        });
+        assert_eq!(arenas.read::<u64>().len(), 400);
+        assert_eq!(arenas.read::<String>().len(), 400);
+    }
+
+    #[test]
+    fn resources() {
+        struct Score(u32);
+
+        let mut arenas = Arenas::new();
+        assert!(arenas.resource::<Score>().is_none());
+        assert!(arenas.insert_resource(Score(1)).is_none());
+        let tick = arenas.change_tick();
+        assert!(!arenas.resource_changed_since::<Score>(tick));
 
-impl UntypedArena {
-    fn _into_typed<T: 'static + Sized>(self) -> Arena<T> {
-        unsafe { std::mem::transmute(self) }
+        arenas.resource_mut::<Score>().unwrap().0 += 1;
+        assert!(arenas.resource_changed_since::<Score>(tick));
+        assert!(!arenas.resource_added_since::<Score>(tick));
+        assert_eq!(arenas.resource::<Score>().unwrap().0, 2);
+        // resources are not part of the arenas:
+        assert!(arenas.read::<Score>().is_empty());
+
+        assert_eq!(arenas.insert_resource(Score(7)).unwrap().0, 2);
+        assert!(arenas.resource_added_since::<Score>(tick));
+        assert_eq!(arenas.remove_resource::<Score>().unwrap().0, 7);
+        assert!(!arenas.resource_changed_since::<Score>(tick));
+    }
+
+    /// Keys are versioned per slot: reusing one slot makes only the keys to that slot stale.
+    #[test]
+    fn slot_reuse() {
+        let arenas = Arenas::new();
+        let a = arenas.insert("a");
+        let a_key = a.key();
+        let mut b = arenas.insert("b");
+        for _ in 0..5 {
+            let old_b = b.key();
+            arenas.remove(b);
+            b = arenas.insert("b");
+            assert!(arenas.get(old_b).is_none());
+            assert_eq!(arenas.get(a_key), Some("a"));
+        }
+
+        arenas.remove(a);
+        let c = arenas.insert("c");
+        // the stale key does not reach the new value in the same slot (ABA):
+        assert!(arenas.get(a_key).is_none());
+        assert!(arenas.write::<&str>().remove(a_key).is_none());
+        assert_eq!(arenas.get(c.key()), Some("c"));
+        assert_eq!(arenas.get(b.key()), Some("b"));
     }
 
-    fn _typed<T: 'static + Sized>(&self) -> &Arena<T> {
-        unsafe { std::mem::transmute(self) }
+    /// Dropping the `Arenas` drops all values, also of arenas only known by their `TypeId`.
+    #[test]
+    fn drop_all_values() {
+        let counter = Arc::new(());
+        let arenas = Arenas::new();
+        let keys: Vec<_> = (0..10).map(|_| arenas.insert(counter.clone())).collect();
+        for key in keys {
+            key.leak();
+        }
+        assert_eq!(Arc::strong_count(&counter), 11);
+        drop(arenas);
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    /// Values are stored typed in the slots, so types with high alignment need no offset computation.
+    #[test]
+    fn high_alignment() {
+        #[derive(Debug, Clone, PartialEq)]
+        #[repr(align(64))]
+        struct Align64([u64; 3]);
+
+        let arenas = Arenas::new();
+        let mut keys: Vec<_> = (0..3).map(|i| arenas.insert(Align64([i; 3]))).collect();
+        arenas.remove(keys.remove(1));
+        for (_, value) in arenas.read::<Align64>().iter() {
+            assert_eq!(value as *const Align64 as usize % 64, 0);
+            assert_ne!(value, &Align64([1; 3]));
+        }
+        assert_eq!(arenas.get(keys[1].key()), Some(Align64([2; 3])));
     }
 
-    fn typed_mut<T: 'static + Sized>(&mut self) -> &mut Arena<T> {
-        unsafe { std::mem::transmute(self) }
+    #[test]
+    #[should_panic(expected = "aliased access")]
+    fn pair_mut_aliased() {
+        let mut arenas = Arenas::new();
+        arenas.insert(1u32);
+        let _ = arenas.arena_pair_mut::<u32, u32>();
     }
 }
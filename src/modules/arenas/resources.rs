@@ -0,0 +1,105 @@
+use std::{
+    any::{Any, TypeId},
+    collections::{hash_map::Entry, HashMap},
+};
+
+use super::Arenas;
+
+/// Values that exist at most once per type, e.g. settings or the score of a game. See `Arenas::insert_resource`.
+#[derive(Default)]
+pub(super) struct Resources {
+    values: HashMap<TypeId, Resource>,
+    /// incremented on every insert of or mutable access to a resource.
+    change_tick: u32,
+}
+
+struct Resource {
+    value: Box<dyn Any + Send + Sync>,
+    added_tick: u32,
+    changed_tick: u32,
+}
+
+impl Resources {
+    fn next_tick(&mut self) -> u32 {
+        self.change_tick = self.change_tick.wrapping_add(1);
+        self.change_tick
+    }
+}
+
+impl Arenas {
+    /// Inserts the resource of type `R`, returning the previous one.
+    pub fn insert_resource<R: 'static + Send + Sync>(&mut self, resource: R) -> Option<R> {
+        let tick = self.resources.next_tick();
+        let resource = Resource {
+            value: Box::new(resource),
+            added_tick: tick,
+            changed_tick: tick,
+        };
+        match self.resources.values.entry(TypeId::of::<R>()) {
+            Entry::Occupied(mut e) => {
+                let old = std::mem::replace(e.get_mut(), resource);
+                Some(*old.value.downcast().expect("resource of wrong type"))
+            }
+            Entry::Vacant(e) => {
+                e.insert(resource);
+                None
+            }
+        }
+    }
+
+    pub fn remove_resource<R: 'static + Send + Sync>(&mut self) -> Option<R> {
+        let resource = self.resources.values.remove(&TypeId::of::<R>())?;
+        Some(*resource.value.downcast().expect("resource of wrong type"))
+    }
+
+    pub fn resource<R: 'static + Send + Sync>(&self) -> Option<&R> {
+        let resource = self.resources.values.get(&TypeId::of::<R>())?;
+        Some(
+            resource
+                .value
+                .downcast_ref()
+                .expect("resource of wrong type"),
+        )
+    }
+
+    /// Marks the resource as changed, even if it is not written to.
+    pub fn resource_mut<R: 'static + Send + Sync>(&mut self) -> Option<&mut R> {
+        let tick = self.resources.next_tick();
+        let resource = self.resources.values.get_mut(&TypeId::of::<R>())?;
+        resource.changed_tick = tick;
+        Some(
+            resource
+                .value
+                .downcast_mut()
+                .expect("resource of wrong type"),
+        )
+    }
+
+    /// The current tick. Store it and pass it to `resource_changed_since` later, to find out if a resource changed
+    /// in between.
+    pub fn change_tick(&self) -> u32 {
+        self.resources.change_tick
+    }
+
+    /// True if the resource was inserted or accessed mutably after `tick`. False if there is no such resource.
+    pub fn resource_changed_since<R: 'static + Send + Sync>(&self, tick: u32) -> bool {
+        self.resources
+            .values
+            .get(&TypeId::of::<R>())
+            .is_some_and(|r| tick_is_newer(r.changed_tick, tick))
+    }
+
+    /// True if the resource was inserted after `tick`. False if there is no such resource.
+    pub fn resource_added_since<R: 'static + Send + Sync>(&self, tick: u32) -> bool {
+        self.resources
+            .values
+            .get(&TypeId::of::<R>())
+            .is_some_and(|r| tick_is_newer(r.added_tick, tick))
+    }
+}
+
+/// Ticks wrap around, so a tick is newer if it is less than half the range ahead.
+fn tick_is_newer(tick: u32, than: u32) -> bool {
+    let diff = tick.wrapping_sub(than);
+    diff != 0 && diff < u32::MAX / 2
+}
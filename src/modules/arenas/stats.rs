@@ -0,0 +1,145 @@
+use std::{collections::HashMap, fmt::Display};
+
+use super::Arenas;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArenaStats {
+    pub type_name: &'static str,
+    /// number of values in the arena.
+    pub len: usize,
+    /// number of values the arena can hold without reallocating.
+    pub capacity: usize,
+    /// slots that are free for new values, `capacity - len`.
+    pub free: usize,
+    /// memory reserved for the slots, approximately, not counting heap memory owned by the values.
+    pub bytes: usize,
+}
+
+/// Stats of all arenas, largest first. See `Arenas::memory_report`.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryReport {
+    pub arenas: Vec<ArenaStats>,
+}
+
+impl MemoryReport {
+    pub fn total_bytes(&self) -> usize {
+        self.arenas.iter().map(|a| a.bytes).sum()
+    }
+
+    pub fn total_len(&self) -> usize {
+        self.arenas.iter().map(|a| a.len).sum()
+    }
+
+    pub fn get(&self, type_name: &str) -> Option<&ArenaStats> {
+        self.arenas.iter().find(|a| a.type_name == type_name)
+    }
+}
+
+impl Display for MemoryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} values in {} arenas, {} bytes",
+            self.total_len(),
+            self.arenas.len(),
+            self.total_bytes()
+        )?;
+        for a in self.arenas.iter() {
+            writeln!(
+                f,
+                "  {}: {} / {} ({} free), {} bytes",
+                a.type_name, a.len, a.capacity, a.free, a.bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Warns about arenas whose number of values only ever grows, which usually means `OwnedKey`s are leaked
+/// or `Arenas::remove_dropped` is not called.
+///
+/// Feed it a report every now and then (e.g. once per second, not every frame): if the count of an arena
+/// did not shrink for `max_growing_reports` reports in a row and grew in that time, a warning is logged.
+#[derive(Debug)]
+pub struct LeakDetector {
+    pub max_growing_reports: usize,
+    /// per type: count at the start of the streak, last count, reports in the streak.
+    streaks: HashMap<&'static str, (usize, usize, usize)>,
+}
+
+impl LeakDetector {
+    pub fn new(max_growing_reports: usize) -> Self {
+        LeakDetector {
+            max_growing_reports,
+            streaks: HashMap::new(),
+        }
+    }
+
+    /// Returns the type names of the arenas that look like they are leaking, a warning is logged for each.
+    pub fn check(&mut self, report: &MemoryReport) -> Vec<&'static str> {
+        let mut leaking = vec![];
+        for stats in report.arenas.iter() {
+            let streak = self
+                .streaks
+                .entry(stats.type_name)
+                .or_insert((stats.len, stats.len, 0));
+            let (start, last, reports) = streak;
+            if stats.len < *last {
+                *streak = (stats.len, stats.len, 0);
+                continue;
+            }
+            *last = stats.len;
+            *reports += 1;
+            if *reports >= self.max_growing_reports {
+                if stats.len > *start {
+                    log::warn!(
+                        "arena of {} only grew in the last {} reports: {} -> {} values",
+                        stats.type_name,
+                        reports,
+                        start,
+                        stats.len
+                    );
+                    leaking.push(stats.type_name);
+                }
+                *streak = (stats.len, stats.len, 0);
+            }
+        }
+        leaking
+    }
+}
+
+impl Arenas {
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut arenas: Vec<ArenaStats> = self
+            .shards
+            .read()
+            .unwrap()
+            .values()
+            .map(|shard| shard.stats())
+            .collect();
+        // ties by name, the shards are in a hash map.
+        arenas.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.type_name.cmp(b.type_name)));
+        MemoryReport { arenas }
+    }
+
+    pub fn egui_memory_report(&self, mut egui_ctx: egui::Context) {
+        let report = self.memory_report();
+        egui::Window::new("Arenas").show(&mut egui_ctx, |ui| {
+            ui.label(format!(
+                "{} values in {} arenas, {} bytes",
+                report.total_len(),
+                report.arenas.len(),
+                report.total_bytes()
+            ));
+            for a in report.arenas.iter() {
+                ui.label(format!(
+                    "{}: {} / {} ({} free), {} bytes",
+                    a.type_name, a.len, a.capacity, a.free, a.bytes
+                ));
+            }
+            if ui.button("Log Memory Report").clicked() {
+                log::info!("{report}");
+            }
+        });
+    }
+}
@@ -0,0 +1,531 @@
+//! Mixer buses with volume, low-pass and reverb. Every sound is routed through the bus of its `SoundKind` and then
+//! through the master bus. The effects run on the audio thread and read the bus settings from atomics, such that
+//! gameplay code can change them every frame:
+//!
+//! ```rust,ignore
+//! let underwater = MixerState::default().with_bus(Bus::Master, BusSettings { low_pass_hz: 600.0, ..Default::default() });
+//! mods.audio.mixer.transition_to(underwater, Duration::from_millis(400));
+//! ```
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use glam::Vec3;
+use rodio::{cpal::FromSample, source::SamplesConverter, Sample, Source};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Bus {
+    Master,
+    Music,
+    Sfx,
+    Ui,
+}
+
+impl Bus {
+    pub const ALL: [Bus; 4] = [Bus::Master, Bus::Music, Bus::Sfx, Bus::Ui];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Above this cutoff the low-pass is skipped.
+pub const LOW_PASS_OFF: f32 = 20_000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BusSettings {
+    pub volume: f32,
+    /// cutoff frequency of the low-pass in hz, e.g. 500 for muffled sounds underwater or behind walls.
+    pub low_pass_hz: f32,
+    /// 0.0 is dry, 1.0 only reverb.
+    pub reverb_mix: f32,
+    /// 0.0 is a small room, 1.0 a cathedral.
+    pub reverb_room_size: f32,
+}
+
+impl Default for BusSettings {
+    fn default() -> Self {
+        BusSettings {
+            volume: 1.0,
+            low_pass_hz: LOW_PASS_OFF,
+            reverb_mix: 0.0,
+            reverb_room_size: 0.5,
+        }
+    }
+}
+
+impl BusSettings {
+    fn lerp(&self, other: &BusSettings, t: f32) -> BusSettings {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        BusSettings {
+            volume: lerp(self.volume, other.volume),
+            // in octaves, such that the sweep sounds even.
+            low_pass_hz: match self.low_pass_hz == other.low_pass_hz {
+                true => self.low_pass_hz,
+                false => lerp(self.low_pass_hz.log2(), other.low_pass_hz.log2()).exp2(),
+            },
+            reverb_mix: lerp(self.reverb_mix, other.reverb_mix),
+            reverb_room_size: lerp(self.reverb_room_size, other.reverb_room_size),
+        }
+    }
+}
+
+/// Settings of all buses, e.g. "underwater" or "pause menu". Transition between them with `Mixer::transition_to`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MixerState {
+    pub buses: [BusSettings; 4],
+}
+
+impl MixerState {
+    pub fn bus(&self, bus: Bus) -> &BusSettings {
+        &self.buses[bus.index()]
+    }
+
+    pub fn bus_mut(&mut self, bus: Bus) -> &mut BusSettings {
+        &mut self.buses[bus.index()]
+    }
+
+    pub fn with_bus(mut self, bus: Bus, settings: BusSettings) -> Self {
+        self.buses[bus.index()] = settings;
+        self
+    }
+
+    fn lerp(&self, other: &MixerState, t: f32) -> MixerState {
+        MixerState {
+            buses: std::array::from_fn(|i| self.buses[i].lerp(&other.buses[i], t)),
+        }
+    }
+}
+
+/// Adds reverb to the sfx bus while the listener is inside, fading in over `falloff` at the border.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReverbZone {
+    pub center: Vec3,
+    pub radius: f32,
+    pub falloff: f32,
+    pub mix: f32,
+    pub room_size: f32,
+}
+
+impl ReverbZone {
+    fn weight(&self, listener: Vec3) -> f32 {
+        let distance = listener.distance(self.center);
+        let t = ((self.radius + self.falloff - distance) / self.falloff.max(0.001)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+/// The settings of one bus as the audio thread sees them.
+#[derive(Debug)]
+pub struct BusParams {
+    volume: AtomicU32,
+    low_pass_hz: AtomicU32,
+    reverb_mix: AtomicU32,
+    reverb_room_size: AtomicU32,
+}
+
+impl BusParams {
+    fn new(settings: &BusSettings) -> Self {
+        let params = BusParams {
+            volume: AtomicU32::new(0),
+            low_pass_hz: AtomicU32::new(0),
+            reverb_mix: AtomicU32::new(0),
+            reverb_room_size: AtomicU32::new(0),
+        };
+        params.store(settings);
+        params
+    }
+
+    fn store(&self, settings: &BusSettings) {
+        let store = |a: &AtomicU32, v: f32| a.store(v.to_bits(), Ordering::Relaxed);
+        store(&self.volume, settings.volume);
+        store(&self.low_pass_hz, settings.low_pass_hz);
+        store(&self.reverb_mix, settings.reverb_mix);
+        store(&self.reverb_room_size, settings.reverb_room_size);
+    }
+
+    fn load(&self) -> BusSettings {
+        let load = |a: &AtomicU32| f32::from_bits(a.load(Ordering::Relaxed));
+        BusSettings {
+            volume: load(&self.volume),
+            low_pass_hz: load(&self.low_pass_hz),
+            reverb_mix: load(&self.reverb_mix),
+            reverb_room_size: load(&self.reverb_room_size),
+        }
+    }
+}
+
+struct Transition {
+    from: MixerState,
+    to: MixerState,
+    elapsed: Duration,
+    duration: Duration,
+}
+
+pub struct Mixer {
+    params: [Arc<BusParams>; 4],
+    /// the state without reverb zones, interpolated during a transition.
+    state: MixerState,
+    transition: Option<Transition>,
+    pub reverb_zones: Vec<ReverbZone>,
+    /// position of the listener for the reverb zones, e.g. the camera.
+    pub listener: Vec3,
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        let state = MixerState::default();
+        Mixer {
+            params: std::array::from_fn(|i| Arc::new(BusParams::new(&state.buses[i]))),
+            state,
+            transition: None,
+            reverb_zones: vec![],
+            listener: Vec3::ZERO,
+        }
+    }
+
+    /// The current state, during a transition somewhere in between.
+    pub fn state(&self) -> &MixerState {
+        &self.state
+    }
+
+    /// Changes one bus right away, cancels a running transition.
+    pub fn set_bus(&mut self, bus: Bus, settings: BusSettings) {
+        self.transition = None;
+        *self.state.bus_mut(bus) = settings;
+    }
+
+    pub fn set_state(&mut self, state: MixerState) {
+        self.transition = None;
+        self.state = state;
+    }
+
+    /// Blends from the current state to `state` over `duration`.
+    pub fn transition_to(&mut self, state: MixerState, duration: Duration) {
+        self.transition = Some(Transition {
+            from: self.state,
+            to: state,
+            elapsed: Duration::ZERO,
+            duration,
+        });
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    /// Wraps a source, such that it plays through `bus` and the master bus.
+    pub fn route<S>(&self, source: S, bus: Bus) -> BusSource<S>
+    where
+        S: Source,
+        S::Item: Sample,
+        f32: FromSample<S::Item>,
+    {
+        BusSource::new(source, self.chain(bus))
+    }
+
+    /// The settings that a source on `bus` goes through, in order.
+    pub(super) fn chain(&self, bus: Bus) -> Vec<Arc<BusParams>> {
+        let mut chain = vec![self.params[bus.index()].clone()];
+        if bus != Bus::Master {
+            chain.push(self.params[Bus::Master.index()].clone());
+        }
+        chain
+    }
+
+    /// Advances the transition and passes the settings to the audio thread, call once per frame.
+    pub fn update(&mut self, delta: Duration) {
+        if let Some(transition) = &mut self.transition {
+            transition.elapsed += delta;
+            let t = match transition.duration.is_zero() {
+                true => 1.0,
+                false => transition.elapsed.as_secs_f32() / transition.duration.as_secs_f32(),
+            };
+            if t >= 1.0 {
+                self.state = transition.to;
+                self.transition = None;
+            } else {
+                self.state = transition.from.lerp(&transition.to, t);
+            }
+        }
+
+        let mut applied = self.state;
+        let sfx = applied.bus_mut(Bus::Sfx);
+        for zone in self.reverb_zones.iter() {
+            let mix = zone.mix * zone.weight(self.listener);
+            if mix > sfx.reverb_mix {
+                sfx.reverb_mix = mix;
+                sfx.reverb_room_size = zone.room_size;
+            }
+        }
+        for (params, settings) in self.params.iter().zip(applied.buses.iter()) {
+            params.store(settings);
+        }
+    }
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Mixer::new()
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// DSP
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Samples between reading the bus settings again.
+const PARAMS_INTERVAL: usize = 256;
+/// Played after the source ended, such that the reverb can ring out.
+const REVERB_TAIL: Duration = Duration::from_millis(1500);
+
+/// A source played through a chain of buses, see `Mixer::route`.
+pub struct BusSource<S> {
+    source: SamplesConverter<S, f32>,
+    channels: u16,
+    sample_rate: u32,
+    stages: Vec<Stage>,
+    /// index of the next sample, the channel is `pos % channels`.
+    pos: usize,
+    /// samples of silence left to play through the reverb after the source ended.
+    tail: Option<usize>,
+}
+
+struct Stage {
+    params: Arc<BusParams>,
+    settings: BusSettings,
+    /// per channel.
+    low_pass: Vec<f32>,
+    reverb: Vec<Reverb>,
+}
+
+impl<S> BusSource<S>
+where
+    S: Source,
+    S::Item: Sample,
+    f32: FromSample<S::Item>,
+{
+    pub(super) fn new(source: S, chain: Vec<Arc<BusParams>>) -> Self {
+        let channels = source.channels().max(1);
+        let sample_rate = source.sample_rate();
+        let stages = chain
+            .into_iter()
+            .map(|params| Stage {
+                settings: params.load(),
+                params,
+                low_pass: vec![0.0; channels as usize],
+                reverb: (0..channels)
+                    .map(|c| Reverb::new(sample_rate, c as usize))
+                    .collect(),
+            })
+            .collect();
+        BusSource {
+            source: source.convert_samples(),
+            channels,
+            sample_rate,
+            stages,
+            pos: 0,
+            tail: None,
+        }
+    }
+
+    fn process(&mut self, mut x: f32) -> f32 {
+        if self.pos.is_multiple_of(PARAMS_INTERVAL) {
+            for stage in self.stages.iter_mut() {
+                stage.settings = stage.params.load();
+            }
+        }
+        let channel = self.pos % self.channels as usize;
+        self.pos += 1;
+        for stage in self.stages.iter_mut() {
+            let s = &stage.settings;
+            if s.low_pass_hz < LOW_PASS_OFF {
+                // one pole low-pass.
+                let a = 1.0
+                    - (-2.0 * std::f32::consts::PI * s.low_pass_hz / self.sample_rate as f32).exp();
+                let y = &mut stage.low_pass[channel];
+                *y += a * (x - *y);
+                x = *y;
+            }
+            if s.reverb_mix > 0.0 {
+                let wet = stage.reverb[channel].process(x, s.reverb_room_size);
+                x = x * (1.0 - s.reverb_mix) + wet * s.reverb_mix;
+            }
+            x *= s.volume;
+        }
+        x
+    }
+}
+
+impl<S> Iterator for BusSource<S>
+where
+    S: Source,
+    S::Item: Sample,
+    f32: FromSample<S::Item>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(x) = self.source.next() {
+            return Some(self.process(x));
+        }
+        let has_reverb = self.stages.iter().any(|s| s.settings.reverb_mix > 0.0);
+        let tail = self.tail.get_or_insert_with(|| match has_reverb {
+            true => {
+                let frames = (REVERB_TAIL.as_secs_f32() * self.sample_rate as f32) as usize;
+                frames * self.channels as usize
+            }
+            false => 0,
+        });
+        if *tail == 0 {
+            return None;
+        }
+        *tail -= 1;
+        Some(self.process(0.0))
+    }
+}
+
+impl<S> Source for BusSource<S>
+where
+    S: Source,
+    S::Item: Sample,
+    f32: FromSample<S::Item>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        // the tail has no frame of the source.
+        match self.tail {
+            Some(tail) => Some(tail),
+            None => self.source.current_frame_len(),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Schroeder reverb: parallel feedback combs into allpasses. The delays of the channels differ a bit, such that
+/// stereo sounds wide.
+struct Reverb {
+    combs: Vec<Delay>,
+    allpasses: Vec<Delay>,
+}
+
+struct Delay {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl Delay {
+    fn new(samples: usize) -> Self {
+        Delay {
+            buffer: vec![0.0; samples.max(1)],
+            pos: 0,
+        }
+    }
+
+    fn read(&self) -> f32 {
+        self.buffer[self.pos]
+    }
+
+    fn write(&mut self, value: f32) {
+        self.buffer[self.pos] = value;
+        self.pos = (self.pos + 1) % self.buffer.len();
+    }
+}
+
+impl Reverb {
+    /// delays in samples at 44.1 khz, from freeverb.
+    const COMBS: [usize; 4] = [1116, 1188, 1277, 1356];
+    const ALLPASSES: [usize; 2] = [556, 441];
+    const STEREO_SPREAD: usize = 23;
+
+    fn new(sample_rate: u32, channel: usize) -> Self {
+        let scale = |samples: usize| {
+            (samples + channel * Self::STEREO_SPREAD) * sample_rate as usize / 44_100
+        };
+        Reverb {
+            combs: Self::COMBS.iter().map(|s| Delay::new(scale(*s))).collect(),
+            allpasses: Self::ALLPASSES
+                .iter()
+                .map(|s| Delay::new(scale(*s)))
+                .collect(),
+        }
+    }
+
+    fn process(&mut self, x: f32, room_size: f32) -> f32 {
+        let feedback = 0.7 + 0.28 * room_size.clamp(0.0, 1.0);
+        let mut out = 0.0;
+        for comb in self.combs.iter_mut() {
+            let y = comb.read();
+            comb.write(x + y * feedback);
+            out += y;
+        }
+        out /= self.combs.len() as f32;
+        for allpass in self.allpasses.iter_mut() {
+            let delayed = allpass.read();
+            allpass.write(out + delayed * 0.5);
+            out = delayed - out;
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    #[test]
+    fn snapshot_transition_and_bus_routing() {
+        let mut mixer = Mixer::new();
+        let quiet = MixerState::default().with_bus(
+            Bus::Sfx,
+            BusSettings {
+                volume: 0.5,
+                ..Default::default()
+            },
+        );
+        mixer.transition_to(quiet, Duration::from_secs(1));
+        mixer.update(Duration::from_millis(500));
+        assert_eq!(mixer.state().bus(Bus::Sfx).volume, 0.75);
+        mixer.update(Duration::from_millis(500));
+        assert!(!mixer.is_transitioning());
+
+        let mut master = *mixer.state().bus(Bus::Master);
+        master.volume = 0.5;
+        mixer.set_bus(Bus::Master, master);
+        mixer.update(Duration::ZERO);
+        // sfx and master volume multiply, the music bus is untouched.
+        let source = || SamplesBuffer::new(1, 44_100, vec![1.0f32; 4]);
+        let sfx: Vec<f32> = mixer.route(source(), Bus::Sfx).collect();
+        assert_eq!(sfx, [0.25; 4]);
+        let music: Vec<f32> = mixer.route(source(), Bus::Music).collect();
+        assert_eq!(music, [0.5; 4]);
+
+        // a reverb zone rings out after the sound ended.
+        mixer.reverb_zones.push(ReverbZone {
+            center: Vec3::ZERO,
+            radius: 10.0,
+            falloff: 5.0,
+            mix: 0.5,
+            room_size: 0.8,
+        });
+        mixer.update(Duration::ZERO);
+        assert_eq!(mixer.route(source(), Bus::Sfx).count(), 4 + 66_150);
+    }
+}
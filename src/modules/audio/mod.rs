@@ -0,0 +1,243 @@
+//! Audio playback with rodio. Sound effects are decoded into memory once and can be played many times,
+//! music is decoded while it plays, see `MusicPlayer`.
+//!
+//! If there is no audio device (e.g. on a ci machine), everything still works but nothing is played.
+
+use std::{io::Cursor, sync::Arc, time::Duration};
+
+use rodio::{OutputStream, OutputStreamHandle, Sink, Source};
+
+use crate::{assets::AssetT, modules::ui::UiSoundEvent};
+
+pub mod music;
+pub use music::{LoopRegion, MusicPlayer, MusicTrack};
+pub mod mixer;
+pub use mixer::{Bus, BusSettings, Mixer, MixerState, ReverbZone};
+
+/// Sounds of some kinds duck the music while they play, such that they can be heard over it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SoundKind {
+    Effect,
+    Dialogue,
+    Ui,
+}
+
+impl SoundKind {
+    pub fn bus(self) -> Bus {
+        match self {
+            SoundKind::Effect | SoundKind::Dialogue => Bus::Sfx,
+            SoundKind::Ui => Bus::Ui,
+        }
+    }
+}
+
+/// Decoded samples of a short sound, cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Sound {
+    samples: Arc<[i16]>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl Sound {
+    pub fn duration(&self) -> Duration {
+        let frames = self.samples.len() / self.channels.max(1) as usize;
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+
+    /// Interleaved samples of all channels, e.g. for generated sounds.
+    pub fn from_samples(samples: impl Into<Arc<[i16]>>, channels: u16, sample_rate: u32) -> Self {
+        Sound {
+            samples: samples.into(),
+            channels,
+            sample_rate,
+        }
+    }
+
+    fn source(&self) -> SoundSource {
+        SoundSource {
+            sound: self.clone(),
+            pos: 0,
+        }
+    }
+}
+
+/// ogg, mp3 or wav bytes, decoded completely.
+impl AssetT for Sound {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let decoder = rodio::Decoder::new(Cursor::new(bytes.to_vec()))?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        Ok(Sound {
+            samples: decoder.collect(),
+            channels,
+            sample_rate,
+        })
+    }
+}
+
+struct SoundSource {
+    sound: Sound,
+    pos: usize,
+}
+
+impl Iterator for SoundSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.sound.samples.get(self.pos).copied();
+        self.pos += 1;
+        sample
+    }
+}
+
+impl Source for SoundSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.sound.samples.len().saturating_sub(self.pos))
+    }
+
+    fn channels(&self) -> u16 {
+        self.sound.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sound.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.sound.duration())
+    }
+}
+
+/// How much the music is turned down while sounds of a kind play, 1.0 means not at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ducking {
+    pub effect: f32,
+    pub dialogue: f32,
+    pub ui: f32,
+    /// time to turn the music down or up again.
+    pub fade: Duration,
+}
+
+impl Default for Ducking {
+    fn default() -> Self {
+        Ducking {
+            effect: 0.8,
+            dialogue: 0.35,
+            ui: 1.0,
+            fade: Duration::from_millis(250),
+        }
+    }
+}
+
+impl Ducking {
+    pub fn of(&self, kind: SoundKind) -> f32 {
+        match kind {
+            SoundKind::Effect => self.effect,
+            SoundKind::Dialogue => self.dialogue,
+            SoundKind::Ui => self.ui,
+        }
+    }
+}
+
+pub struct Audio {
+    /// None if there is no audio device. The stream has to be kept alive for the handle to work.
+    output: Option<(OutputStream, OutputStreamHandle)>,
+    pub music: MusicPlayer,
+    pub mixer: Mixer,
+    pub ducking: Ducking,
+    playing: Vec<(Sink, SoundKind)>,
+}
+
+impl Audio {
+    pub fn new() -> Self {
+        let output = match OutputStream::try_default() {
+            Ok(output) => Some(output),
+            Err(err) => {
+                log::warn!("no audio output, sounds are not played: {err}");
+                None
+            }
+        };
+        let handle = output.as_ref().map(|(_, handle)| handle.clone());
+        let mixer = Mixer::new();
+        Audio {
+            output,
+            music: MusicPlayer::new(handle, mixer.chain(Bus::Music)),
+            mixer,
+            ducking: Ducking::default(),
+            playing: vec![],
+        }
+    }
+
+    pub fn has_output(&self) -> bool {
+        self.output.is_some()
+    }
+
+    pub fn play(&mut self, sound: &Sound, kind: SoundKind) {
+        self.play_with_volume(sound, kind, 1.0);
+    }
+
+    pub fn play_with_volume(&mut self, sound: &Sound, kind: SoundKind, volume: f32) {
+        let Some((_, handle)) = &self.output else {
+            return;
+        };
+        match Sink::try_new(handle) {
+            Ok(sink) => {
+                sink.set_volume(volume);
+                sink.append(self.mixer.route(sound.source(), kind.bus()));
+                self.playing.push((sink, kind));
+            }
+            Err(err) => log::error!("could not play sound: {err}"),
+        }
+    }
+
+    /// Plays the sounds of the widgets of a board (see `Board::sound_events`) on the ui bus.
+    pub fn play_ui_sounds(&mut self, events: &[UiSoundEvent]) {
+        for event in events {
+            self.play(&event.sound, SoundKind::Ui);
+        }
+    }
+
+    /// Plays a source on its own sink through a mixer bus, for sounds that are paused or stopped by their owner,
+    /// like the sound track of a video. None if there is no audio output.
+    pub fn play_source(
+        &self,
+        source: impl Source<Item = i16> + Send + 'static,
+        bus: Bus,
+    ) -> Option<Sink> {
+        let (_, handle) = self.output.as_ref()?;
+        match Sink::try_new(handle) {
+            Ok(sink) => {
+                sink.append(self.mixer.route(source, bus));
+                Some(sink)
+            }
+            Err(err) => {
+                log::error!("could not play sound: {err}");
+                None
+            }
+        }
+    }
+
+    /// Number of sounds (without music) that are playing right now.
+    pub fn playing_count(&self) -> usize {
+        self.playing.len()
+    }
+
+    /// Drops finished sounds, fades the music and applies the mixer settings, call once per frame.
+    pub fn update(&mut self, delta: Duration) {
+        self.mixer.update(delta);
+        self.playing.retain(|(sink, _)| !sink.empty());
+        let duck = self
+            .playing
+            .iter()
+            .map(|(_, kind)| self.ducking.of(*kind))
+            .fold(1.0, f32::min);
+        self.music.update(delta, duck, self.ducking.fade);
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Audio::new()
+    }
+}
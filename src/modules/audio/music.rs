@@ -0,0 +1,343 @@
+use std::{fs::File, io::BufReader, path::PathBuf, time::Duration};
+
+use std::sync::{mpsc, Arc};
+
+use rodio::{Decoder, OutputStreamHandle, Sink, Source};
+
+use super::mixer::{BusParams, BusSource};
+
+/// A part of a track that repeats, e.g. after an intro that is only played once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopRegion {
+    pub start: Duration,
+    /// None loops at the end of the track.
+    pub end: Option<Duration>,
+}
+
+impl LoopRegion {
+    pub const WHOLE_TRACK: LoopRegion = LoopRegion {
+        start: Duration::ZERO,
+        end: None,
+    };
+}
+
+/// A music file (ogg, mp3 or wav) that is decoded while it plays, instead of decoding it into memory up front.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MusicTrack {
+    pub path: PathBuf,
+    /// None plays the track once.
+    pub loop_region: Option<LoopRegion>,
+}
+
+impl MusicTrack {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        MusicTrack {
+            path: path.into(),
+            loop_region: None,
+        }
+    }
+
+    pub fn looping(mut self) -> Self {
+        self.loop_region = Some(LoopRegion::WHOLE_TRACK);
+        self
+    }
+
+    pub fn with_loop_region(mut self, region: LoopRegion) -> Self {
+        self.loop_region = Some(region);
+        self
+    }
+
+    fn open(&self) -> anyhow::Result<Decoder<BufReader<File>>> {
+        let file = File::open(&self.path)?;
+        Ok(Decoder::new(BufReader::new(file))?)
+    }
+}
+
+/// Plays one track at a time and crossfades to the next one. Ducked by `Audio` while sound effects or dialogue play.
+///
+/// ```rust,ignore
+/// let intro_then_loop = LoopRegion { start: Duration::from_secs(12), end: None };
+/// mods.audio.music.play(MusicTrack::new("assets/battle.ogg").with_loop_region(intro_then_loop), Duration::from_secs(2));
+/// ```
+pub struct MusicPlayer {
+    handle: Option<OutputStreamHandle>,
+    /// the music bus and the master bus.
+    bus_chain: Vec<Arc<BusParams>>,
+    pub volume: f32,
+    current: Option<Channel>,
+    /// fading out.
+    previous: Vec<Channel>,
+    /// current ducking factor, moves towards the target of `Audio::update`.
+    duck: f32,
+}
+
+struct Channel {
+    sink: Sink,
+    track: MusicTrack,
+    /// 0.0 to 1.0
+    fade: f32,
+    /// change of `fade` per second, negative while fading out.
+    fade_speed: f32,
+}
+
+impl Channel {
+    fn advance(&mut self, delta: Duration) {
+        self.fade = (self.fade + self.fade_speed * delta.as_secs_f32()).clamp(0.0, 1.0);
+    }
+}
+
+fn fade_speed(duration: Duration) -> f32 {
+    match duration.is_zero() {
+        true => f32::INFINITY,
+        false => 1.0 / duration.as_secs_f32(),
+    }
+}
+
+impl MusicPlayer {
+    pub(super) fn new(handle: Option<OutputStreamHandle>, bus_chain: Vec<Arc<BusParams>>) -> Self {
+        MusicPlayer {
+            handle,
+            bus_chain,
+            volume: 1.0,
+            current: None,
+            previous: vec![],
+            duck: 1.0,
+        }
+    }
+
+    /// Fades the current track out and `track` in over `crossfade`. Does nothing if `track` is already playing.
+    pub fn play(&mut self, track: MusicTrack, crossfade: Duration) {
+        if self.current.as_ref().is_some_and(|c| c.track == track) {
+            return;
+        }
+        self.stop(crossfade);
+        let Some(handle) = &self.handle else {
+            return;
+        };
+        let sink = match Sink::try_new(handle) {
+            Ok(sink) => sink,
+            Err(err) => {
+                log::error!("could not play music: {err}");
+                return;
+            }
+        };
+        let source = match track.loop_region {
+            None => track
+                .open()
+                .map(|d| Box::new(d) as Box<dyn Source<Item = i16> + Send>),
+            Some(region) => {
+                let looping_track = track.clone();
+                LoopingSource::new(move || looping_track.open(), region)
+                    .map(|s| Box::new(s) as Box<dyn Source<Item = i16> + Send>)
+            }
+        };
+        match source {
+            Ok(source) => sink.append(BusSource::new(source, self.bus_chain.clone())),
+            Err(err) => {
+                log::error!("could not open music track {:?}: {err}", track.path);
+                return;
+            }
+        }
+        sink.set_volume(0.0);
+        self.current = Some(Channel {
+            sink,
+            track,
+            fade: 0.0,
+            fade_speed: fade_speed(crossfade),
+        });
+    }
+
+    /// Fades out the current track.
+    pub fn stop(&mut self, fade_out: Duration) {
+        if let Some(mut current) = self.current.take() {
+            current.fade_speed = -fade_speed(fade_out);
+            self.previous.push(current);
+        }
+    }
+
+    pub fn current(&self) -> Option<&MusicTrack> {
+        self.current.as_ref().map(|c| &c.track)
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.current.as_ref().is_some_and(|c| !c.sink.empty())
+    }
+
+    pub(super) fn update(&mut self, delta: Duration, duck_target: f32, duck_fade: Duration) {
+        let max_step = delta.as_secs_f32() * fade_speed(duck_fade);
+        self.duck += (duck_target - self.duck).clamp(-max_step, max_step);
+        let volume = self.volume * self.duck;
+        if let Some(current) = &mut self.current {
+            current.advance(delta);
+            current.sink.set_volume(current.fade * volume);
+        }
+        self.previous.retain_mut(|channel| {
+            channel.advance(delta);
+            channel.sink.set_volume(channel.fade * volume);
+            channel.fade > 0.0 && !channel.sink.empty()
+        });
+    }
+}
+
+/// Plays a source and jumps back to the start of the loop region whenever its end is reached. Decoders cannot seek,
+/// so a spare source is opened and decoded up to the start of the region on a background thread, while the loop
+/// plays. On restart the spare is swapped in and the next one is prepared.
+struct LoopingSource<S, F> {
+    open: F,
+    source: S,
+    /// the next source, positioned at the start of the region. None if the track cannot be opened again.
+    spare: Option<mpsc::Receiver<Option<S>>>,
+    channels: u16,
+    sample_rate: u32,
+    /// samples (not frames) since the start of the track.
+    pos: u64,
+    start: u64,
+    end: Option<u64>,
+}
+
+impl<S, F> LoopingSource<S, F>
+where
+    S: Source<Item = i16> + Send + 'static,
+    F: Fn() -> anyhow::Result<S> + Clone + Send + 'static,
+{
+    fn new(open: F, region: LoopRegion) -> anyhow::Result<Self> {
+        let source = open()?;
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+        let to_samples = |d: Duration| {
+            // whole frames, such that the channels stay in order.
+            (d.as_secs_f64() * sample_rate as f64) as u64 * channels as u64
+        };
+        let mut looping = LoopingSource {
+            open,
+            source,
+            spare: None,
+            channels,
+            sample_rate,
+            pos: 0,
+            start: to_samples(region.start),
+            end: region.end.map(to_samples),
+        };
+        looping.prepare_spare();
+        Ok(looping)
+    }
+
+    fn prepare_spare(&mut self) {
+        let (sender, receiver) = mpsc::channel();
+        let open = self.open.clone();
+        let start = self.start;
+        let spawned = std::thread::Builder::new()
+            .name("music loop".into())
+            .spawn(move || {
+                let spare = match open() {
+                    Ok(mut source) => (0..start)
+                        .try_for_each(|_| source.next().map(|_| ()))
+                        .map(|_| source),
+                    Err(err) => {
+                        log::error!("could not reopen looping music track: {err}");
+                        None
+                    }
+                };
+                _ = sender.send(spare);
+            });
+        self.spare = spawned.ok().map(|_| receiver);
+    }
+
+    /// None if the track cannot be opened again or the region is empty.
+    fn restart(&mut self) -> Option<()> {
+        // usually ready long ago, unless the loop is shorter than decoding up to its start.
+        self.source = self.spare.take()?.recv().ok()??;
+        self.pos = self.start;
+        self.prepare_spare();
+        Some(())
+    }
+}
+
+impl<S, F> Iterator for LoopingSource<S, F>
+where
+    S: Source<Item = i16> + Send + 'static,
+    F: Fn() -> anyhow::Result<S> + Clone + Send + 'static,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.end.is_some_and(|end| self.pos >= end) {
+            self.restart()?;
+        }
+        let sample = match self.source.next() {
+            Some(sample) => sample,
+            None => {
+                if self.pos <= self.start {
+                    // nothing to loop.
+                    return None;
+                }
+                self.restart()?;
+                self.source.next()?
+            }
+        };
+        self.pos += 1;
+        Some(sample)
+    }
+}
+
+impl<S, F> Source for LoopingSource<S, F>
+where
+    S: Source<Item = i16> + Send + 'static,
+    F: Fn() -> anyhow::Result<S> + Clone + Send + 'static,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        // a decoder can change the sample rate between frames, the loop assumes it does not.
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    #[test]
+    fn loop_region_repeats() {
+        // 10 stereo frames per second, the sample value is the frame index.
+        let open = || {
+            let samples: Vec<i16> = (0..20).flat_map(|i| [i, i]).collect();
+            Ok(SamplesBuffer::new(2, 10, samples))
+        };
+        let region = LoopRegion {
+            start: Duration::from_millis(500),
+            end: Some(Duration::from_millis(800)),
+        };
+        let frames: Vec<i16> = LoopingSource::new(open, region)
+            .unwrap()
+            .step_by(2)
+            .take(14)
+            .collect();
+        assert_eq!(frames, [0, 1, 2, 3, 4, 5, 6, 7, 5, 6, 7, 5, 6, 7]);
+
+        // without an end, the whole rest of the track repeats.
+        let region = LoopRegion {
+            start: Duration::from_millis(1500),
+            end: None,
+        };
+        let frames: Vec<i16> = LoopingSource::new(open, region)
+            .unwrap()
+            .step_by(2)
+            .skip(18)
+            .take(4)
+            .collect();
+        assert_eq!(frames, [18, 19, 15, 16]);
+    }
+}
@@ -0,0 +1,72 @@
+use std::borrow::Cow;
+
+use image::RgbaImage;
+
+/// Read and write access to the system clipboard.
+///
+/// If the clipboard is not available (e.g. no display server), a warning is logged once and all reads return None.
+/// Copy/cut/paste shortcuts are detected by the `Input`, see `Input::clipboard_events`.
+pub struct Clipboard {
+    inner: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        let inner = match arboard::Clipboard::new() {
+            Ok(inner) => Some(inner),
+            Err(err) => {
+                log::warn!("clipboard not available: {err}");
+                None
+            }
+        };
+        Clipboard { inner }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// None if the clipboard is empty, does not contain text or is not available.
+    pub fn get_text(&mut self) -> Option<String> {
+        self.inner.as_mut()?.get_text().ok()
+    }
+
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        let Some(inner) = self.inner.as_mut() else {
+            return;
+        };
+        if let Err(err) = inner.set_text(text.into()) {
+            log::warn!("could not write text to clipboard: {err}");
+        }
+    }
+
+    /// None if the clipboard does not contain an image or is not available.
+    pub fn get_image(&mut self) -> Option<RgbaImage> {
+        let image = self.inner.as_mut()?.get_image().ok()?;
+        RgbaImage::from_raw(
+            image.width as u32,
+            image.height as u32,
+            image.bytes.into_owned(),
+        )
+    }
+
+    pub fn set_image(&mut self, image: &RgbaImage) {
+        let Some(inner) = self.inner.as_mut() else {
+            return;
+        };
+        let image = arboard::ImageData {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            bytes: Cow::Borrowed(image.as_raw()),
+        };
+        if let Err(err) = inner.set_image(image) {
+            log::warn!("could not write image to clipboard: {err}");
+        }
+    }
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
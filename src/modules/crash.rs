@@ -0,0 +1,207 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{Log, Metadata, Record};
+
+use super::Time;
+
+/// number of log lines kept for crash reports.
+const LOG_RING_CAPACITY: usize = 200;
+
+static STATE: Mutex<CrashState> = Mutex::new(CrashState {
+    log_lines: VecDeque::new(),
+    frame: None,
+    report_dir: None,
+    last_report: None,
+});
+
+struct CrashState {
+    log_lines: VecDeque<String>,
+    frame: Option<FrameStats>,
+    /// set by `install_panic_hook`.
+    report_dir: Option<PathBuf>,
+    last_report: Option<CrashReport>,
+}
+
+/// Snapshot of `Time` at the start of the latest frame, see `record_frame`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    pub frame_count: usize,
+    pub fps: f64,
+    pub delta: Duration,
+    pub total: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    pub message: String,
+    /// file:line:column of the panic.
+    pub location: Option<String>,
+    pub thread: String,
+    pub backtrace: String,
+    /// the latest log lines, oldest first. Empty if the logger was not installed with `init_logger`.
+    pub log_lines: Vec<String>,
+    pub frame: Option<FrameStats>,
+    /// where the report was written to, None if writing failed or no panic hook was installed.
+    pub file: Option<PathBuf>,
+}
+
+impl CrashReport {
+    /// Report without backtrace and logs, for panics that happened without the panic hook installed.
+    pub fn from_message(message: String) -> Self {
+        CrashReport {
+            message,
+            location: None,
+            thread: std::thread::current()
+                .name()
+                .unwrap_or("unnamed")
+                .to_string(),
+            backtrace: String::new(),
+            log_lines: vec![],
+            frame: None,
+            file: None,
+        }
+    }
+}
+
+impl Display for CrashReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "thread '{}' panicked: {}", self.thread, self.message)?;
+        if let Some(location) = &self.location {
+            writeln!(f, "at {location}")?;
+        }
+        if let Some(frame) = &self.frame {
+            writeln!(
+                f,
+                "\nframe {}, {:.1} fps, last delta {:.2} ms, running for {:.1} s",
+                frame.frame_count,
+                frame.fps,
+                frame.delta.as_secs_f64() * 1000.0,
+                frame.total.as_secs_f64()
+            )?;
+        }
+        writeln!(f, "\nbacktrace:\n{}", self.backtrace)?;
+        writeln!(f, "latest log lines:")?;
+        for line in self.log_lines.iter() {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Installs `pretty_env_logger` (configured with `RUST_LOG` as usual) and keeps the latest log lines for crash reports.
+pub fn init_logger() -> Result<(), log::SetLoggerError> {
+    let inner = pretty_env_logger::formatted_builder()
+        .parse_default_env()
+        .build();
+    let max_level = inner.filter();
+    log::set_boxed_logger(Box::new(RingLogger { inner }))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+struct RingLogger {
+    inner: pretty_env_logger::env_logger::Logger,
+}
+
+impl Log for RingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        self.inner.log(record);
+        let line = format!("[{} {}] {}", record.level(), record.target(), record.args());
+        // never block or panic inside of the logger, e.g. when logging from the panic hook.
+        if let Ok(mut state) = STATE.try_lock() {
+            if state.log_lines.len() >= LOG_RING_CAPACITY {
+                state.log_lines.pop_front();
+            }
+            state.log_lines.push_back(line);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Sets a panic hook that writes a crash report (backtrace, latest log lines and frame stats) to a file in `dir`.
+/// The previous hook still runs afterwards, so the panic is printed to the console as before.
+///
+/// Only panics on the thread that installs the hook (the main thread) are reported. Panics of jobs or tasks are
+/// caught and handled by their handles, they do not crash the app.
+///
+/// The runner catches panics in `App::update` and passes the report to `App::crashed`, see `take_report`.
+pub fn install_panic_hook(dir: impl Into<PathBuf>) {
+    STATE.lock().unwrap().report_dir = Some(dir.into());
+    let previous_hook = std::panic::take_hook();
+    let main_thread = std::thread::current().id();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().id() != main_thread {
+            previous_hook(info);
+            return;
+        }
+        let message = if let Some(s) = info.payload().downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = info.payload().downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown panic payload".to_string()
+        };
+        write_report(message, info.location().map(|l| l.to_string()));
+        previous_hook(info);
+    }));
+}
+
+fn write_report(message: String, location: Option<String>) {
+    let mut report = CrashReport::from_message(message);
+    report.location = location;
+    report.backtrace = std::backtrace::Backtrace::force_capture().to_string();
+
+    // the panic could have happened while the state was locked, do not deadlock then.
+    let Ok(mut state) = STATE.try_lock() else {
+        return;
+    };
+    report.log_lines = state.log_lines.iter().cloned().collect();
+    report.frame = state.frame;
+    if let Some(dir) = &state.report_dir {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let file = dir.join(format!("crash_report_{secs}.txt"));
+        let written =
+            std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&file, report.to_string()));
+        match written {
+            Ok(()) => report.file = Some(file),
+            Err(err) => eprintln!("could not write crash report to {}: {err}", file.display()),
+        }
+    }
+    state.last_report = Some(report);
+}
+
+/// Called by `DefaultModules::begin_frame`, such that crash reports contain the latest frame stats.
+pub fn record_frame(time: &Time) {
+    if let Ok(mut state) = STATE.try_lock() {
+        state.frame = Some(FrameStats {
+            frame_count: time.frame_count(),
+            fps: time.fps(),
+            delta: *time.real_delta(),
+            total: *time.real_total(),
+        });
+    }
+}
+
+/// The report of the latest panic on the main thread, if the panic hook is installed.
+pub fn take_report() -> Option<CrashReport> {
+    STATE.lock().ok()?.last_report.take()
+}
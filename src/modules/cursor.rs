@@ -0,0 +1,196 @@
+use glam::{vec2, Vec2};
+use image::RgbaImage;
+use winit::{
+    event::WindowEvent,
+    window::{CursorIcon, Window},
+};
+
+use crate::{
+    assets::AssetT,
+    elements::{rect::Aabb, BindableTexture, Color, Screen, Texture},
+    GpuRecreate, GpuRecreated, OwnedPtr, ReceiveWindowEvent,
+};
+
+use super::{GraphicsContext, UiRenderer};
+
+/// Sets the cursor of the window. The cursor is requested anew every frame (e.g. by the ui, depending on what
+/// is hovered) and falls back to `CursorIcon::Default` if nothing requested another one. The window is only
+/// updated if the requested cursor differs from the last frame.
+///
+/// Custom cursors are software cursors: winit 0.29 cannot set images as hardware cursors, so the os cursor is hidden
+/// and the image is drawn with the `UiRenderer` at the cursor position instead, after tone mapping and on top of the ui.
+pub struct Cursor {
+    requested: Option<CursorKind>,
+    applied: Option<CursorKind>,
+    custom: Vec<CustomCursor>,
+    /// physical pixels, relative to the window.
+    pos: Vec2,
+    in_window: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorKind {
+    Icon(CursorIcon),
+    Custom(CustomCursorId),
+    Hidden,
+}
+
+/// Handle to a custom cursor image, see `Cursor::add_custom`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CustomCursorId(usize);
+
+struct CustomCursor {
+    /// kept to recreate the texture if the device is lost.
+    image: RgbaImage,
+    texture: OwnedPtr<BindableTexture>,
+    /// in pixels of the image, the point that is placed at the cursor position.
+    hotspot: Vec2,
+}
+
+impl Cursor {
+    pub fn new() -> Self {
+        Cursor {
+            requested: None,
+            applied: None,
+            custom: vec![],
+            pos: Vec2::ZERO,
+            in_window: false,
+        }
+    }
+
+    /// Requests an os cursor icon for this frame. Later requests in the same frame override earlier ones.
+    pub fn set_icon(&mut self, icon: CursorIcon) {
+        self.requested = Some(CursorKind::Icon(icon));
+    }
+
+    /// Requests a custom cursor image for this frame.
+    pub fn set_custom(&mut self, id: CustomCursorId) {
+        self.requested = Some(CursorKind::Custom(id));
+    }
+
+    /// Hides the cursor for this frame.
+    pub fn hide(&mut self) {
+        self.requested = Some(CursorKind::Hidden);
+    }
+
+    pub fn set(&mut self, kind: CursorKind) {
+        self.requested = Some(kind);
+    }
+
+    /// The cursor requested so far in this frame, if any.
+    pub fn requested(&self) -> Option<CursorKind> {
+        self.requested
+    }
+
+    /// Registers an image as a custom cursor. The `hotspot` is the point in the image (in pixels) that is
+    /// placed at the cursor position, e.g. the tip of an arrow. One pixel of the image covers one physical pixel of the
+    /// viewport, like the os cursor. In the fixed resolution modes it is scaled with the ui.
+    pub fn add_custom(
+        &mut self,
+        ctx: &GraphicsContext,
+        image: RgbaImage,
+        hotspot: Vec2,
+    ) -> CustomCursorId {
+        let texture = OwnedPtr::new(BindableTexture::new(
+            &ctx.device,
+            Texture::from_image(&ctx.device, &ctx.queue, &image),
+        ));
+        self.custom.push(CustomCursor {
+            image,
+            texture,
+            hotspot,
+        });
+        CustomCursorId(self.custom.len() - 1)
+    }
+
+    /// Loads an image from a file path or url (blocking) and registers it as a custom cursor, see `add_custom`.
+    pub fn load_custom(
+        &mut self,
+        ctx: &GraphicsContext,
+        rt: &tokio::runtime::Runtime,
+        src: &str,
+        hotspot: Vec2,
+    ) -> anyhow::Result<CustomCursorId> {
+        let image = rt.block_on(RgbaImage::load(src))?;
+        Ok(self.add_custom(ctx, image, hotspot))
+    }
+
+    /// Updates the window cursor with the cursor requested in this frame and draws the custom cursor image if there
+    /// is one. Resets the request for the next frame. Call once per frame, after the ui boards were drawn and before
+    /// the `UiRenderer` is prepared.
+    pub fn apply(&mut self, window: &Window, screen: &Screen, ui: &mut UiRenderer) {
+        let kind = self
+            .requested
+            .take()
+            .unwrap_or(CursorKind::Icon(CursorIcon::Default));
+
+        if self.applied != Some(kind) {
+            match kind {
+                CursorKind::Icon(icon) => {
+                    window.set_cursor_icon(icon);
+                    window.set_cursor_visible(true);
+                }
+                CursorKind::Custom(_) | CursorKind::Hidden => window.set_cursor_visible(false),
+            }
+            self.applied = Some(kind);
+        }
+
+        let CursorKind::Custom(CustomCursorId(index)) = kind else {
+            return;
+        };
+        if !self.in_window {
+            return;
+        }
+        let Some(pos) = screen.window_to_ui(self.pos) else {
+            return;
+        };
+        let custom = &self.custom[index];
+        let size = custom.texture.texture.size;
+        // ui units per physical pixel.
+        let scale = screen.ui_size() / screen.viewport().size();
+        let min = pos - custom.hotspot * scale;
+        let max = min + vec2(size.width as f32, size.height as f32) * scale;
+        ui.draw_textured_rect(
+            Aabb::new(min.x, min.y, max.x, max.y),
+            Aabb::new(0.0, 0.0, 1.0, 1.0),
+            Color::WHITE,
+            custom.texture.ptr(),
+        );
+    }
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReceiveWindowEvent for Cursor {
+    fn receive_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pos = vec2(position.x as f32, position.y as f32);
+                self.in_window = true;
+            }
+            WindowEvent::CursorEntered { .. } => self.in_window = true,
+            WindowEvent::CursorLeft { .. } => self.in_window = false,
+            _ => {}
+        }
+    }
+}
+
+impl GpuRecreate for Cursor {
+    fn gpu_recreated(&mut self, ctx: &GraphicsContext, event: GpuRecreated) {
+        if !event.device_lost {
+            return;
+        }
+        for custom in self.custom.iter_mut() {
+            custom.texture = OwnedPtr::new(BindableTexture::new(
+                &ctx.device,
+                Texture::from_image(&ctx.device, &ctx.queue, &custom.image),
+            ));
+        }
+        // the window keeps its cursor, but set it again to be safe.
+        self.applied = None;
+    }
+}
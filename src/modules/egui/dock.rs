@@ -0,0 +1,178 @@
+//! Dockable egui panels for tool-style apps, e.g. an inspector on the right and a console at the bottom.
+//!
+//! Each side of the screen holds a resizable egui panel with one tab per docked panel. Panels can be moved to another
+//! side, floated into an `egui::Window` or closed from the menu of their tab, and reopened with `Dock::panels_menu`.
+//!
+//! This is a small dock inside the main window, not `egui_dock`: there is no drag and drop of tabs and no splitting
+//! of a side. Floating panels stay in the main window, see the note on viewports in `Egui::new`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DockSide {
+    Left,
+    Right,
+    Bottom,
+    /// shown in its own `egui::Window`.
+    Floating,
+}
+
+impl DockSide {
+    pub const ALL: [DockSide; 4] = [
+        DockSide::Left,
+        DockSide::Right,
+        DockSide::Bottom,
+        DockSide::Floating,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            DockSide::Left => "Left",
+            DockSide::Right => "Right",
+            DockSide::Bottom => "Bottom",
+            DockSide::Floating => "Floating",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DockPanel {
+    /// shown on the tab, also identifies the panel in the callback of `Dock::show`.
+    pub title: String,
+    pub side: DockSide,
+    pub open: bool,
+}
+
+/// The docked panels and which tab is selected on each side. Keep it in the app state between frames.
+#[derive(Debug, Clone, Default)]
+pub struct Dock {
+    pub panels: Vec<DockPanel>,
+    /// selected tab per side (Left, Right, Bottom), as an index into `panels`.
+    selected: [Option<usize>; 3],
+}
+
+impl Dock {
+    pub fn new() -> Self {
+        Dock::default()
+    }
+
+    /// Adds an open panel. Titles should be unique, they are used as egui ids.
+    pub fn add(&mut self, title: impl Into<String>, side: DockSide) -> &mut Self {
+        self.panels.push(DockPanel {
+            title: title.into(),
+            side,
+            open: true,
+        });
+        self
+    }
+
+    /// Shows all open panels. `panel_ui` is called with the title and ui of each visible panel: the selected tab of
+    /// each side and all floating panels.
+    ///
+    /// Call before any `egui::CentralPanel`, such that the panels take their space first.
+    pub fn show(&mut self, ctx: &egui::Context, mut panel_ui: impl FnMut(&str, &mut egui::Ui)) {
+        let mut moves: Vec<(usize, Option<DockSide>)> = vec![];
+        for (slot, side) in [DockSide::Left, DockSide::Right, DockSide::Bottom]
+            .into_iter()
+            .enumerate()
+        {
+            let docked: Vec<usize> = (0..self.panels.len())
+                .filter(|i| self.panels[*i].open && self.panels[*i].side == side)
+                .collect();
+            if docked.is_empty() {
+                continue;
+            }
+            let selected = match self.selected[slot] {
+                Some(i) if docked.contains(&i) => i,
+                _ => docked[0],
+            };
+            self.selected[slot] = Some(selected);
+
+            let id = format!("vert_dock_{}", side.name());
+            let mut contents = |ui: &mut egui::Ui| {
+                ui.horizontal(|ui| {
+                    for &i in docked.iter() {
+                        let title = &self.panels[i].title;
+                        ui.selectable_value(&mut self.selected[slot], Some(i), title.as_str());
+                        ui.menu_button("⏷", |ui| {
+                            if let Some(m) = move_menu(ui, side) {
+                                moves.push((i, m));
+                            }
+                        });
+                    }
+                });
+                ui.separator();
+                panel_ui(&self.panels[selected].title, ui);
+            };
+            match side {
+                DockSide::Left => {
+                    egui::SidePanel::left(id.as_str())
+                        .resizable(true)
+                        .default_width(250.0)
+                        .show(ctx, |ui| contents(ui));
+                }
+                DockSide::Right => {
+                    egui::SidePanel::right(id.as_str())
+                        .resizable(true)
+                        .default_width(250.0)
+                        .show(ctx, |ui| contents(ui));
+                }
+                DockSide::Bottom => {
+                    egui::TopBottomPanel::bottom(id.as_str())
+                        .resizable(true)
+                        .default_height(200.0)
+                        .show(ctx, |ui| contents(ui));
+                }
+                DockSide::Floating => unreachable!(),
+            }
+        }
+
+        for (i, panel) in self.panels.iter_mut().enumerate() {
+            if !panel.open || panel.side != DockSide::Floating {
+                continue;
+            }
+            let title = panel.title.clone();
+            egui::Window::new(title.as_str())
+                .open(&mut panel.open)
+                .default_size([300.0, 200.0])
+                .show(ctx, |ui| {
+                    ui.menu_button("Dock", |ui| {
+                        if let Some(m) = move_menu(ui, DockSide::Floating) {
+                            moves.push((i, m));
+                        }
+                    });
+                    ui.separator();
+                    panel_ui(&title, ui);
+                });
+        }
+
+        for (i, m) in moves {
+            match m {
+                Some(side) => self.panels[i].side = side,
+                None => self.panels[i].open = false,
+            }
+        }
+    }
+
+    /// Checkboxes to open and close the panels, e.g. for a "View" menu.
+    pub fn panels_menu(&mut self, ui: &mut egui::Ui) {
+        for panel in self.panels.iter_mut() {
+            ui.checkbox(&mut panel.open, panel.title.as_str());
+        }
+    }
+}
+
+/// Buttons to move a panel to another side or to close it. Returns `Some(None)` for close.
+fn move_menu(ui: &mut egui::Ui, current: DockSide) -> Option<Option<DockSide>> {
+    let mut result = None;
+    for side in DockSide::ALL {
+        if side != current && ui.button(side.name()).clicked() {
+            result = Some(Some(side));
+        }
+    }
+    if ui.button("Close").clicked() {
+        result = Some(None);
+    }
+    if result.is_some() {
+        ui.close_menu();
+    }
+    result
+}
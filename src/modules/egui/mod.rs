@@ -4,12 +4,14 @@ use egui::ClippedPrimitive;
 
 use winit::{event::WindowEvent, window::Window};
 
-use crate::{Prepare, ReceiveWindowEvent};
+use crate::{GpuRecreate, GpuRecreated, Prepare, ReceiveWindowEvent};
 
 use self::platform::{Platform, PlatformDescriptor};
 
-use super::GraphicsContext;
+use super::{cursor::CursorKind, GraphicsContext};
+use crate::elements::BindableTexture;
 
+pub mod dock;
 pub mod platform;
 
 /// ## How to use the functions exposed by EguiState:
@@ -32,27 +34,44 @@ pub struct Egui {
     pub renderer: egui_wgpu::Renderer,
     paint_jobs: Vec<ClippedPrimitive>,
     textures_delta: egui::TexturesDelta,
+    /// of a frame that was begun but never prepared, e.g. because the app panicked mid frame.
+    unprepared_textures_delta: egui::TexturesDelta,
+    /// between `begin_frame` and `prepare`.
+    frame_running: bool,
+    /// requested in the last `prepare`.
+    cursor_icon: egui::CursorIcon,
+    /// copied or cut in the last `prepare`, written to the `Clipboard` by the `DefaultModules`.
+    copied_text: Option<String>,
     pub start_time: Instant,
 }
 
 impl Egui {
     pub fn new(ctx: &GraphicsContext, window: &Window) -> Self {
-        // Important note: pixels_per_point is the inverse of the devices scale_factor.
+        // pixels_per_point is the scale factor (physical pixels per logical point), see `Platform::set_pixels_per_point`.
 
         let platform = Platform::new(PlatformDescriptor {
             physical_size: ctx.size,
-            pixels_per_point: 1.0 / window.scale_factor() as f32, // ??? is this updated properly?
+            pixels_per_point: window.scale_factor() as f32,
             font_definitions: Default::default(),
             style: Default::default(),
         });
 
+        // The renderer draws into the one surface of the engine, so extra viewports (e.g. from
+        // `Context::show_viewport_immediate`) are embedded as egui windows instead of opening OS windows.
+        // Multi-viewport support needs a window and surface per viewport, see todo.md.
+        platform.context().set_embed_viewports(true);
+
         let renderer = egui_wgpu::Renderer::new(&ctx.device, ctx.surface_format, None, 1);
         // renderer.render(render_pass, paint_jobs, self.platform);
         Egui {
             platform,
             renderer,
             textures_delta: Default::default(),
+            unprepared_textures_delta: Default::default(),
+            frame_running: false,
             paint_jobs: Vec::new(),
+            cursor_icon: egui::CursorIcon::Default,
+            copied_text: None,
             start_time: Instant::now(),
             // demo_windows: DemoWindows::default(),
         }
@@ -62,7 +81,85 @@ impl Egui {
         self.platform.context()
     }
 
+    /// The cursor egui wants to show, if the pointer is over an egui area. `Some(CursorKind::Hidden)` if egui wants to hide it.
+    pub fn cursor(&self) -> Option<CursorKind> {
+        if !self.context().is_pointer_over_area() && !self.context().is_using_pointer() {
+            return None;
+        }
+        match platform::egui_to_winit_cursor_icon(self.cursor_icon) {
+            Some(icon) => Some(CursorKind::Icon(icon)),
+            None => Some(CursorKind::Hidden),
+        }
+    }
+
+    /// true if the pointer is over an egui window or egui is dragging, then the vert ui and the game should ignore
+    /// clicks. See `InputCapture`.
+    pub fn wants_pointer_input(&self) -> bool {
+        self.context().wants_pointer_input()
+    }
+
+    /// true if an egui text field is focused.
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.context().wants_keyboard_input()
+    }
+
+    /// Makes an engine texture (e.g. a render target or a loaded image) drawable in egui, e.g. with `ui.image((id, size))`.
+    /// The texture is not updated automatically: if it is recreated (e.g. on resize), call `update_texture`.
+    ///
+    /// Registered textures are lost when the device is lost and need to be registered again.
+    pub fn register_texture(
+        &mut self,
+        ctx: &GraphicsContext,
+        texture: &BindableTexture,
+        filter: wgpu::FilterMode,
+    ) -> egui::TextureId {
+        self.renderer
+            .register_native_texture(&ctx.device, &texture.texture.view, filter)
+    }
+
+    /// Points a registered texture id to another texture.
+    pub fn update_texture(
+        &mut self,
+        ctx: &GraphicsContext,
+        id: egui::TextureId,
+        texture: &BindableTexture,
+        filter: wgpu::FilterMode,
+    ) {
+        self.renderer.update_egui_texture_from_wgpu_texture(
+            &ctx.device,
+            &texture.texture.view,
+            filter,
+            id,
+        );
+    }
+
+    pub fn unregister_texture(&mut self, id: egui::TextureId) {
+        self.renderer.free_texture(&id);
+    }
+
+    /// Text that egui copied to the clipboard in the last frame.
+    pub fn take_copied_text(&mut self) -> Option<String> {
+        self.copied_text.take()
+    }
+
+    /// Pastes text into the focused egui text field. Call before `begin_frame`.
+    pub fn paste(&mut self, text: String) {
+        self.platform
+            .raw_input_mut()
+            .events
+            .push(egui::Event::Paste(text));
+    }
+
+    /// Ends a frame that is still running without drawing it, e.g. after the app panicked mid frame.
     pub fn begin_frame(&mut self) {
+        if self.frame_running {
+            let output = self.platform.end_frame();
+            // the textures are only sent once, so they are uploaded with the next frame.
+            let delta = &mut self.unprepared_textures_delta;
+            delta.set.extend(output.textures_delta.set);
+            delta.free.extend(output.textures_delta.free);
+        }
+        self.frame_running = true;
         let total_time = Instant::now() - self.start_time;
         let total_elapsed_seconds = total_time.as_secs_f64();
         self.platform.begin_frame(total_elapsed_seconds);
@@ -100,11 +197,18 @@ impl Prepare for Egui {
         encoder: &mut wgpu::CommandEncoder,
     ) {
         let output = self.platform.end_frame();
+        self.frame_running = false;
+        self.cursor_icon = output.platform_output.cursor_icon;
+        if !output.platform_output.copied_text.is_empty() {
+            self.copied_text = Some(output.platform_output.copied_text);
+        }
         self.paint_jobs.clear();
         for id in self.textures_delta.free.drain(..) {
             self.renderer.free_texture(&id)
         }
-        self.textures_delta = output.textures_delta;
+        self.textures_delta = std::mem::take(&mut self.unprepared_textures_delta);
+        self.textures_delta.set.extend(output.textures_delta.set);
+        self.textures_delta.free.extend(output.textures_delta.free);
         for (id, image_delta) in self.textures_delta.set.iter() {
             self.renderer
                 .update_texture(device, queue, *id, image_delta);
@@ -121,6 +225,21 @@ impl Prepare for Egui {
     }
 }
 
+impl GpuRecreate for Egui {
+    fn gpu_recreated(&mut self, ctx: &GraphicsContext, event: GpuRecreated) {
+        if !event.device_lost {
+            return;
+        }
+        self.renderer = egui_wgpu::Renderer::new(&ctx.device, ctx.surface_format, None, 1);
+        self.paint_jobs.clear();
+        self.textures_delta = Default::default();
+        self.unprepared_textures_delta = Default::default();
+        // egui only sends textures once. Setting the fonts makes it send the font atlas again,
+        // images registered by the user need to be registered again.
+        self.context().set_fonts(egui::FontDefinitions::default());
+    }
+}
+
 impl ReceiveWindowEvent for Egui {
     fn receive_window_event(&mut self, event: &WindowEvent) {
         self.platform.handle_event(event);
@@ -37,6 +37,8 @@ pub struct Platform {
     raw_input: egui::RawInput,
     modifier_state: ModifiersState,
     pointer_pos: Option<egui::Pos2>,
+    /// true between the first ime preedit and the commit.
+    composing: bool,
 
     // For emulating pointer events from touch events we merge multi-touch
     // pointers, and ref-count the press state.
@@ -62,6 +64,7 @@ impl Platform {
 
         context.set_fonts(descriptor.font_definitions.clone());
         context.set_style(descriptor.style);
+        context.set_pixels_per_point(descriptor.pixels_per_point);
         let raw_input = egui::RawInput {
             // pixels_per_point: Some(descriptor.scale_factor as f32),
             screen_rect: Some(screen_rect(
@@ -78,12 +81,27 @@ impl Platform {
             raw_input,
             modifier_state: ModifiersState::empty(),
             pointer_pos: Some(Pos2::default()),
+            composing: false,
             touch_pointer_pressed: 0,
             device_indices: HashMap::new(),
             next_device_index: 1,
         }
     }
 
+    /// Physical pixels per egui point, usually the scale factor of the window.
+    pub fn set_pixels_per_point(&mut self, pixels_per_point: f32) {
+        if self.pixels_per_point == pixels_per_point {
+            return;
+        }
+        self.pixels_per_point = pixels_per_point;
+        self.raw_input.screen_rect = Some(screen_rect(self.physical_size, pixels_per_point));
+        self.context.set_pixels_per_point(pixels_per_point);
+    }
+
+    pub fn pixels_per_point(&self) -> f32 {
+        self.pixels_per_point
+    }
+
     pub fn screen_descriptor(&self) -> ScreenDescriptor {
         ScreenDescriptor {
             size_in_pixels: [self.physical_size.width, self.physical_size.height],
@@ -108,15 +126,8 @@ impl Platform {
                     Some(screen_rect(self.physical_size, self.pixels_per_point));
             }
 
-            ScaleFactorChanged {
-                scale_factor,
-                inner_size_writer: _,
-            } => {
-                self.pixels_per_point = 1.0 / *scale_factor as f32;
-
-                self.raw_input.screen_rect =
-                    Some(screen_rect(self.physical_size, self.pixels_per_point));
-            }
+            // pixels_per_point is set from the outside with `set_pixels_per_point`, because it includes the ui scale.
+            ScaleFactorChanged { .. } => {}
             MouseInput { state, button, .. } => {
                 if let winit::event::MouseButton::Other(..) = button {
                 } else {
@@ -308,6 +319,32 @@ impl Platform {
                     }
                 }
             }
+            Ime(ime) => match ime {
+                winit::event::Ime::Enabled => {}
+                winit::event::Ime::Preedit(text, _) => {
+                    if !self.composing {
+                        self.composing = true;
+                        self.raw_input.events.push(egui::Event::CompositionStart);
+                    }
+                    self.raw_input
+                        .events
+                        .push(egui::Event::CompositionUpdate(text.clone()));
+                }
+                winit::event::Ime::Commit(text) => {
+                    self.composing = false;
+                    self.raw_input
+                        .events
+                        .push(egui::Event::CompositionEnd(text.clone()));
+                }
+                winit::event::Ime::Disabled => {
+                    if self.composing {
+                        self.composing = false;
+                        self.raw_input
+                            .events
+                            .push(egui::Event::CompositionEnd(String::new()));
+                    }
+                }
+            },
             _ => {}
         }
     }
@@ -342,27 +379,19 @@ impl Platform {
     }
 
     /// Ends the frame. Returns what has happened as `Output` and gives you the draw instructions
-    /// as `PaintJobs`. If the optional `window` is set, it will set the cursor key based on
-    /// egui's instructions.
-    ///
-    ///  window: Option<&winit::window::Window>
+    /// as `PaintJobs`. The cursor icon requested by egui is applied by the `Cursor` module, see `Egui::cursor_icon`.
     pub fn end_frame(&mut self) -> egui::FullOutput {
-        // otherwise the below line gets flagged by clippy if both clipboard and webbrowser features are disabled
-        #[allow(clippy::let_and_return)]
-        let output = self.context.end_frame();
-        // if let Some(window) = window {
-        //     if let Some(cursor_icon) = egui_to_winit_cursor_icon(output.platform_output.cursor_icon)
-        //     {
-        //         window.set_cursor_visible(true);
-        //         // if the pointer is located inside the window, set cursor icon
-        //         if self.pointer_pos.is_some() {
-        //             window.set_cursor_icon(cursor_icon);
-        //         }
-        //     } else {
-        //         window.set_cursor_visible(false);
-        //     }
-        // }
-        output
+        self.context.end_frame()
+    }
+
+    /// Removes the key events of the key that egui did not process yet, e.g. because a shortcut already handled it.
+    pub fn remove_key_events(&mut self, key: KeyCode) {
+        let Some(key) = winit_to_egui_key_code(key) else {
+            return;
+        };
+        self.raw_input
+            .events
+            .retain(|e| !matches!(e, egui::Event::Key { key: k, .. } if *k == key));
     }
 
     /// Returns the internal egui context.
@@ -504,7 +533,7 @@ fn winit_to_egui_modifiers(modifiers: ModifiersState) -> egui::Modifiers {
 }
 
 #[inline]
-fn _unused_egui_to_winit_cursor_icon(icon: egui::CursorIcon) -> Option<winit::window::CursorIcon> {
+pub fn egui_to_winit_cursor_icon(icon: egui::CursorIcon) -> Option<winit::window::CursorIcon> {
     use egui::CursorIcon::*;
 
     match icon {
@@ -0,0 +1,97 @@
+use std::sync::{mpsc, Arc, Mutex};
+
+/// Recorded on the main thread into an encoder that is submitted before the next frame.
+pub type GpuCommand = Box<dyn FnOnce(&wgpu::Device, &mut wgpu::CommandEncoder) + Send>;
+
+/// Lets background threads (asset loaders, `Jobs`) create gpu resources and record commands for them, e.g.
+/// copies or mip generation, without blocking the frame. Get one with `GraphicsContext::uploader`.
+///
+/// ```rust,ignore
+/// let uploader = modules.ctx.uploader();
+/// modules.jobs.spawn(move || {
+///     let image = image::open("big.png").unwrap().to_rgba8();
+///     // `write_texture` is staged and happens with the next submit of the main thread.
+///     let texture = Texture::from_image(uploader.device(), uploader.queue(), &image);
+///     uploader.enqueue(move |_device, encoder| { /* copies into an atlas, ... */ })?;
+///     anyhow::Ok(texture)
+/// });
+/// ```
+#[derive(Clone)]
+pub struct GpuUploader {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    sender: mpsc::Sender<GpuCommand>,
+}
+
+impl std::fmt::Debug for GpuUploader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GpuUploader").finish_non_exhaustive()
+    }
+}
+
+impl GpuUploader {
+    /// The device and queue are thread safe, resources can be created on any thread.
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    /// Queues commands, which are submitted before the next frame is prepared. Fails if the device was recreated
+    /// since the uploader was created, then the resources of this uploader are invalid and need to be loaded again.
+    pub fn enqueue(
+        &self,
+        command: impl FnOnce(&wgpu::Device, &mut wgpu::CommandEncoder) + Send + 'static,
+    ) -> anyhow::Result<()> {
+        if self.sender.send(Box::new(command)).is_err() {
+            anyhow::bail!("the gpu device was recreated, the uploader is invalid");
+        }
+        Ok(())
+    }
+}
+
+/// The receiving end in the `GraphicsContext`, replaced when the device is recreated.
+#[derive(Debug)]
+pub(crate) struct GpuCommandQueue {
+    sender: mpsc::Sender<GpuCommand>,
+    receiver: Mutex<mpsc::Receiver<GpuCommand>>,
+}
+
+impl GpuCommandQueue {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        GpuCommandQueue {
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    pub fn uploader(&self, device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> GpuUploader {
+        GpuUploader {
+            device,
+            queue,
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Records all queued commands and submits them, returns how many there were.
+    pub fn submit(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> usize {
+        let receiver = self.receiver.lock().unwrap();
+        let mut commands = receiver.try_iter().peekable();
+        if commands.peek().is_none() {
+            return 0;
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Queued Gpu Commands"),
+        });
+        let mut count = 0;
+        for command in commands {
+            command(device, &mut encoder);
+            count += 1;
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        count
+    }
+}
@@ -1,10 +1,19 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 use glam::DVec2;
 use wgpu::SurfaceTexture;
 use winit::{dpi::PhysicalSize, window::Window};
 
-use crate::{Resize, Resized};
+use crate::{elements::texture::release_shared_layouts, Resize, Resized};
+
+use super::{
+    gpu_commands::{GpuCommandQueue, GpuUploader},
+    readback::Readback,
+    renderer::{PipelineSettings, DEPTH_FORMAT, HDR_COLOR_FORMAT, MSAA_SAMPLE_COUNT},
+};
 
 #[derive(Debug)]
 pub struct GraphicsContext {
@@ -16,24 +25,178 @@ pub struct GraphicsContext {
     pub surface_format: wgpu::TextureFormat,
     pub surface_config: wgpu::SurfaceConfiguration,
     pub size: PhysicalSize<u32>,
+    /// 1 if msaa is not supported, otherwise `GraphicsContextConfig::msaa_sample_count`.
+    pub msaa_sample_count: u32,
+    /// What was chosen during initialization.
+    pub report: GraphicsContextReport,
+    /// copies from the gpu to the cpu, resolved a few frames later.
+    pub readback: Readback,
+    /// set from the uncaptured error handler of the device.
+    device_lost: Arc<AtomicBool>,
+    /// commands from background threads, see `GraphicsContext::uploader`.
+    commands: GpuCommandQueue,
+}
+
+/// Result of `GraphicsContext::acquire_surface_texture`.
+pub enum SurfaceAcquire {
+    Ready(SurfaceTexture, wgpu::TextureView),
+    /// The surface was lost or outdated and has been reconfigured. The frame should be skipped.
+    Reconfigured,
+    /// Timeout or minimized window. The frame should be skipped.
+    Skip,
+    /// The device needs to be recreated with `GraphicsContext::recreate_device`.
+    DeviceLost,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct GraphicsContextConfig {
+    /// Backends to pick the adapter from. If none of them has a suitable adapter, all other backends are tried.
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    /// Features the device needs. Initialization fails if the adapter does not support them.
     pub features: wgpu::Features,
+    /// Features that are enabled if the adapter supports them. Check `GraphicsContext::device.features()`.
+    pub optional_features: wgpu::Features,
+    /// Limits the adapter does not support are lowered (or for alignments raised) to the ones of the adapter.
+    pub limits: wgpu::Limits,
     pub present_mode: wgpu::PresentMode,
-    pub max_push_constant_size: u32,
+    pub surface_format: SurfaceFormatPreference,
+    /// Falls back to the next lower count the adapter supports for the hdr and depth formats, down to 1 (no msaa).
+    pub msaa_sample_count: u32,
+}
+
+/// Summary of the adapter, features, limits and formats picked in `initialize_graphics_context`.
+#[derive(Debug, Clone)]
+pub struct GraphicsContextReport {
+    pub adapter: wgpu::AdapterInfo,
+    /// true if none of the `GraphicsContextConfig::backends` had an adapter.
+    pub backend_fallback: bool,
+    pub features: wgpu::Features,
+    pub missing_optional_features: wgpu::Features,
+    /// Names of the limits in `GraphicsContextConfig::limits` the adapter did not support.
+    pub unmet_limits: Vec<&'static str>,
+    pub surface_format: wgpu::TextureFormat,
+    pub msaa_sample_count: u32,
+}
+
+impl std::fmt::Display for GraphicsContextReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let a = &self.adapter;
+        writeln!(
+            f,
+            "adapter: {} ({:?}, {:?})",
+            a.name, a.backend, a.device_type
+        )?;
+        if self.backend_fallback {
+            writeln!(
+                f,
+                "  no adapter found for the configured backends, fell back"
+            )?;
+        }
+        writeln!(f, "  features: {:?}", self.features)?;
+        if !self.missing_optional_features.is_empty() {
+            writeln!(
+                f,
+                "  missing optional features: {:?}",
+                self.missing_optional_features
+            )?;
+        }
+        if !self.unmet_limits.is_empty() {
+            writeln!(
+                f,
+                "  unmet limits (using adapter limits): {}",
+                self.unmet_limits.join(", ")
+            )?;
+        }
+        writeln!(f, "  surface format: {:?}", self.surface_format)?;
+        write!(f, "  msaa sample count: {}", self.msaa_sample_count)
+    }
+}
+
+/// Which kind of surface format to prefer. The actual format is picked from what the surface supports
+/// and stored in `GraphicsContext::surface_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SurfaceFormatPreference {
+    /// 8 bit srgb, e.g. `Bgra8UnormSrgb`.
+    #[default]
+    Sdr,
+    /// `Rgba16Float` (extended linear) if available, otherwise falls back to sdr. `Rgb10a2Unorm` is not picked,
+    /// it has more precision but no values above 1.0, so it is not hdr.
+    Hdr,
+}
+
+/// How the tonemapping has to encode colors for the chosen surface format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SurfaceEncoding {
+    /// The hardware converts linear colors to srgb when writing.
+    Srgb,
+    /// Unorm format without srgb conversion, the shader needs to apply the srgb transfer function.
+    LinearUnorm,
+    /// Float format that takes linear values, including values above 1.0.
+    Hdr,
+}
+
+impl SurfaceEncoding {
+    pub fn of(format: wgpu::TextureFormat) -> Self {
+        if format.is_srgb() {
+            SurfaceEncoding::Srgb
+        } else if format == wgpu::TextureFormat::Rgba16Float {
+            SurfaceEncoding::Hdr
+        } else {
+            SurfaceEncoding::LinearUnorm
+        }
+    }
+
+    /// How shaders are told the encoding: 0 for srgb, 1 for linear unorm and 2 for hdr.
+    pub fn shader_index(self) -> u32 {
+        match self {
+            SurfaceEncoding::Srgb => 0,
+            SurfaceEncoding::LinearUnorm => 1,
+            SurfaceEncoding::Hdr => 2,
+        }
+    }
+}
+
+/// Picks the best format out of the formats supported by the surface.
+pub fn pick_surface_format(
+    supported: &[wgpu::TextureFormat],
+    preference: SurfaceFormatPreference,
+) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as F;
+    const HDR: &[F] = &[F::Rgba16Float];
+    const SDR: &[F] = &[F::Bgra8UnormSrgb, F::Rgba8UnormSrgb];
+    let preferred: &[&[F]] = match preference {
+        SurfaceFormatPreference::Sdr => &[SDR],
+        SurfaceFormatPreference::Hdr => &[HDR, SDR],
+    };
+    preferred
+        .iter()
+        .flat_map(|formats| formats.iter())
+        .find(|f| supported.contains(f))
+        .copied()
+        // any srgb format, then whatever comes first.
+        .or_else(|| supported.iter().copied().find(|f| f.is_srgb()))
+        .or_else(|| supported.first().copied())
 }
 
 impl Default for GraphicsContextConfig {
     fn default() -> Self {
         Self {
-            features: wgpu::Features::MULTIVIEW
-                | wgpu::Features::PUSH_CONSTANTS
+            backends: wgpu::Backends::PRIMARY,
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            features: wgpu::Features::empty(),
+            // without push constants, renderers fall back to uniforms, see `PushConstantBlock`.
+            optional_features: wgpu::Features::PUSH_CONSTANTS
+                | wgpu::Features::MULTIVIEW
                 | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
                 | wgpu::Features::TEXTURE_BINDING_ARRAY,
+            limits: wgpu::Limits {
+                max_push_constant_size: 64,
+                ..Default::default()
+            },
             present_mode: wgpu::PresentMode::AutoNoVsync,
-            max_push_constant_size: 64,
+            surface_format: SurfaceFormatPreference::Sdr,
+            msaa_sample_count: MSAA_SAMPLE_COUNT,
         }
     }
 }
@@ -45,7 +208,10 @@ impl Resize for GraphicsContext {
         self.surface_config.width = event.new_size.width;
         self.surface_config.height = event.new_size.height;
         self.size = event.new_size;
-        self.surface.configure(&self.device, &self.surface_config);
+        // a surface with zero size cannot be configured, e.g. while the window is minimized.
+        if self.size.width != 0 && self.size.height != 0 {
+            self.surface.configure(&self.device, &self.surface_config);
+        }
     }
 }
 
@@ -60,6 +226,17 @@ impl GraphicsContext {
         Ok(graphics_context)
     }
 
+    /// Reconfigures the surface, e.g. to turn vsync on or off.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        if self.surface_config.present_mode == present_mode {
+            return;
+        }
+        self.surface_config.present_mode = present_mode;
+        if self.size.width != 0 && self.size.height != 0 {
+            self.surface.configure(&self.device, &self.surface_config);
+        }
+    }
+
     pub fn size(&self) -> PhysicalSize<u32> {
         self.size
     }
@@ -71,7 +248,14 @@ impl GraphicsContext {
         }
     }
 
-    pub const SURFACE_COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+    pub fn surface_encoding(&self) -> SurfaceEncoding {
+        SurfaceEncoding::of(self.surface_format)
+    }
+
+    /// Whether renderers can use push constants, see `PushConstantBlock`.
+    pub fn pipeline_settings(&self) -> PipelineSettings {
+        PipelineSettings::new(&self.device)
+    }
 
     pub fn new_encoder(&self) -> wgpu::CommandEncoder {
         self.device
@@ -88,49 +272,185 @@ impl GraphicsContext {
         let view = output.texture.create_view(&Default::default());
         (output, view)
     }
+
+    /// Like `new_surface_texture_and_view`, but recovers from surface errors instead of panicking.
+    pub fn acquire_surface_texture(&self) -> SurfaceAcquire {
+        if self.is_device_lost() {
+            return SurfaceAcquire::DeviceLost;
+        }
+        if self.size.width == 0 || self.size.height == 0 {
+            return SurfaceAcquire::Skip;
+        }
+        match self.surface.get_current_texture() {
+            Ok(output) => {
+                let view = output.texture.create_view(&Default::default());
+                SurfaceAcquire::Ready(output, view)
+            }
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                log::warn!("surface lost or outdated, reconfiguring");
+                self.surface.configure(&self.device, &self.surface_config);
+                SurfaceAcquire::Reconfigured
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                log::warn!("timeout while acquiring surface texture, skipping frame");
+                SurfaceAcquire::Skip
+            }
+            Err(wgpu::SurfaceError::OutOfMemory) => {
+                log::error!("out of memory while acquiring surface texture");
+                self.device_lost.store(true, Ordering::Relaxed);
+                SurfaceAcquire::DeviceLost
+            }
+        }
+    }
+
+    /// A handle for background threads to create resources and queue commands, see `GpuUploader`.
+    pub fn uploader(&self) -> GpuUploader {
+        self.commands
+            .uploader(self.device.clone(), self.queue.clone())
+    }
+
+    /// Submits the commands queued by `GpuUploader`s, before the frame is prepared. Returns how many there were.
+    pub fn submit_queued_commands(&self) -> usize {
+        self.commands.submit(&self.device, &self.queue)
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Relaxed)
+    }
+
+    /// Requests a new device and queue from the adapter, with the same features and limits as the lost one,
+    /// and reconfigures the surface. All resources created on the old device are invalid afterwards.
+    pub fn recreate_device(&mut self, rt: &tokio::runtime::Runtime) -> anyhow::Result<()> {
+        // layouts of the old device must not be used for pipelines of the new one.
+        release_shared_layouts(&self.device);
+        let features = self.device.features();
+        let limits = self.device.limits();
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let (device, queue) = rt.block_on(request_device(
+            &self.adapter,
+            features,
+            limits,
+            device_lost.clone(),
+        ))?;
+        self.device = Arc::new(device);
+        self.queue = Arc::new(queue);
+        // pending readbacks of the old device resolve with an error.
+        self.readback = Readback::new(self.device.clone());
+        // uploaders of the old device fail to enqueue.
+        self.commands = GpuCommandQueue::new();
+        self.device_lost = device_lost;
+        if self.size.width != 0 && self.size.height != 0 {
+            self.surface.configure(&self.device, &self.surface_config);
+        }
+        Ok(())
+    }
+}
+
+/// The `requested` limits, with each limit the adapter does not support replaced by the adapter's.
+fn fall_back_limits(requested: &wgpu::Limits, adapter: &wgpu::Limits) -> wgpu::Limits {
+    let mut limits = requested.clone();
+    macro_rules! fall_back {
+        (max: $($max:ident),*; min: $($min:ident),*) => {
+            $(limits.$max = limits.$max.min(adapter.$max);)*
+            $(limits.$min = limits.$min.max(adapter.$min);)*
+        };
+    }
+    fall_back!(
+        max: max_texture_dimension_1d,
+        max_texture_dimension_2d,
+        max_texture_dimension_3d,
+        max_texture_array_layers,
+        max_bind_groups,
+        max_bindings_per_bind_group,
+        max_dynamic_uniform_buffers_per_pipeline_layout,
+        max_dynamic_storage_buffers_per_pipeline_layout,
+        max_sampled_textures_per_shader_stage,
+        max_samplers_per_shader_stage,
+        max_storage_buffers_per_shader_stage,
+        max_storage_textures_per_shader_stage,
+        max_uniform_buffers_per_shader_stage,
+        max_uniform_buffer_binding_size,
+        max_storage_buffer_binding_size,
+        max_vertex_buffers,
+        max_buffer_size,
+        max_vertex_attributes,
+        max_vertex_buffer_array_stride,
+        max_inter_stage_shader_components,
+        max_compute_workgroup_storage_size,
+        max_compute_invocations_per_workgroup,
+        max_compute_workgroup_size_x,
+        max_compute_workgroup_size_y,
+        max_compute_workgroup_size_z,
+        max_compute_workgroups_per_dimension,
+        max_push_constant_size,
+        max_non_sampler_bindings;
+        min: min_uniform_buffer_offset_alignment,
+        min_storage_buffer_offset_alignment
+    );
+    limits
+}
+
+/// The highest sample count up to `requested` that is `supported`, 1 if there is none.
+fn pick_msaa_sample_count(requested: u32, supported: impl Fn(u32) -> bool) -> u32 {
+    [16, 8, 4, 2]
+        .into_iter()
+        .filter(|count| *count <= requested)
+        .find(|count| supported(*count))
+        .unwrap_or(1)
 }
 
 pub async fn initialize_graphics_context(
     config: GraphicsContextConfig,
     window: &Window,
 ) -> anyhow::Result<GraphicsContext> {
-    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::all(),
-        ..Default::default()
-    });
+    let mut backend_fallback = false;
+    let mut found = request_adapter(&config, config.backends, window).await;
+    if found.is_none() && config.backends != wgpu::Backends::all() {
+        backend_fallback = true;
+        let other_backends = wgpu::Backends::all().difference(config.backends);
+        found = request_adapter(&config, other_backends, window).await;
+    }
+    let Some((instance, surface, adapter)) = found else {
+        anyhow::bail!(
+            "no adapter supports the required features {:?}",
+            config.features
+        );
+    };
 
-    let surface = unsafe { instance.create_surface(&window) }.unwrap();
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::default(),
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
-        })
-        .await
-        .unwrap();
+    let supported_features = adapter.features();
+    let features = config.features | (config.optional_features & supported_features);
+    let missing_optional_features = config.optional_features.difference(supported_features);
 
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: config.features,
-                limits: wgpu::Limits {
-                    max_push_constant_size: config.max_push_constant_size,
-                    ..Default::default()
-                },
-            },
-            None,
-        )
-        .await
-        .unwrap();
+    let adapter_limits = adapter.limits();
+    let mut unmet_limits: Vec<&'static str> = vec![];
+    config
+        .limits
+        .check_limits_with_fail_fn(&adapter_limits, false, |name, _, _| unmet_limits.push(name));
+    let limits = fall_back_limits(&config.limits, &adapter_limits);
+
+    let device_lost = Arc::new(AtomicBool::new(false));
+    let (device, queue) = request_device(&adapter, features, limits, device_lost.clone()).await?;
+
+    let msaa_sample_count = pick_msaa_sample_count(config.msaa_sample_count, |count| {
+        [HDR_COLOR_FORMAT, DEPTH_FORMAT].iter().all(|format| {
+            adapter
+                .get_texture_format_features(*format)
+                .flags
+                .sample_count_supported(count)
+        })
+    });
+    if msaa_sample_count != config.msaa_sample_count {
+        log::warn!(
+            "msaa sample count {} is not supported, using {msaa_sample_count}",
+            config.msaa_sample_count
+        );
+    }
 
     let surface_caps = surface.get_capabilities(&adapter);
-    let surface_format = surface_caps
-        .formats
-        .iter()
-        .copied()
-        .find(|f| *f == GraphicsContext::SURFACE_COLOR_FORMAT)
-        .expect("SURFACE_FORMAT not found in surface caps ");
+    let Some(surface_format) = pick_surface_format(&surface_caps.formats, config.surface_format)
+    else {
+        anyhow::bail!("surface does not support any texture format");
+    };
 
     let size = window.inner_size();
     let surface_config = wgpu::SurfaceConfiguration {
@@ -140,20 +460,162 @@ pub async fn initialize_graphics_context(
         height: size.height,
         present_mode: config.present_mode,
         alpha_mode: surface_caps.alpha_modes[0],
-        view_formats: vec![wgpu::TextureFormat::Bgra8UnormSrgb],
+        view_formats: vec![surface_format],
     };
     surface.configure(&device, &surface_config);
 
+    let report = GraphicsContextReport {
+        adapter: adapter.get_info(),
+        backend_fallback,
+        features,
+        missing_optional_features,
+        unmet_limits,
+        surface_format,
+        msaa_sample_count,
+    };
+    log::info!("{report}");
+
+    let device = Arc::new(device);
     let context = GraphicsContext {
         instance,
         adapter,
-        device: Arc::new(device),
+        readback: Readback::new(device.clone()),
+        device,
         queue: Arc::new(queue),
         surface,
         surface_format,
         surface_config,
         size,
+        msaa_sample_count,
+        report,
+        device_lost,
+        commands: GpuCommandQueue::new(),
     };
 
     Ok(context)
 }
+
+/// Requests a device that sets `device_lost` if it is lost or runs out of memory.
+async fn request_device(
+    adapter: &wgpu::Adapter,
+    features: wgpu::Features,
+    limits: wgpu::Limits,
+    device_lost: Arc<AtomicBool>,
+) -> anyhow::Result<(wgpu::Device, wgpu::Queue)> {
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features,
+                limits,
+            },
+            None,
+        )
+        .await?;
+    device.on_uncaptured_error(Box::new(move |err| {
+        let lost = match &err {
+            wgpu::Error::OutOfMemory { .. } => true,
+            wgpu::Error::Validation { source, .. } => is_device_lost_error(source.as_ref()),
+        };
+        if lost {
+            log::error!("gpu device lost: {err}");
+            device_lost.store(true, Ordering::Relaxed);
+        } else {
+            // same as the default handler, validation errors are bugs.
+            panic!("wgpu error: {err}");
+        }
+    }));
+    Ok((device, queue))
+}
+
+/// Whether `DeviceError::Lost` is in the source chain of an error, wgpu reports it as a validation error.
+fn is_device_lost_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(wgpu::core::device::DeviceError::Lost) =
+            err.downcast_ref::<wgpu::core::device::DeviceError>()
+        {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Finds an adapter for one of the `backends` that can present to the window and has the required features.
+async fn request_adapter(
+    config: &GraphicsContextConfig,
+    backends: wgpu::Backends,
+    window: &Window,
+) -> Option<(wgpu::Instance, wgpu::Surface, wgpu::Adapter)> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    });
+    let surface = unsafe { instance.create_surface(&window) }.ok()?;
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: config.power_preference,
+            compatible_surface: Some(&surface),
+            force_fallback_adapter: false,
+        })
+        .await?;
+    if !adapter.features().contains(config.features) {
+        log::warn!(
+            "adapter {} is missing required features {:?}",
+            adapter.get_info().name,
+            config.features.difference(adapter.features())
+        );
+        return None;
+    }
+    Some((instance, surface, adapter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_fall_back_per_limit() {
+        let requested = wgpu::Limits {
+            max_texture_dimension_2d: 16384,
+            max_push_constant_size: 128,
+            min_uniform_buffer_offset_alignment: 64,
+            ..wgpu::Limits::default()
+        };
+        let adapter = wgpu::Limits {
+            max_texture_dimension_2d: 8192,
+            max_push_constant_size: 256,
+            ..wgpu::Limits::default()
+        };
+        let limits = fall_back_limits(&requested, &adapter);
+        assert_eq!(limits.max_texture_dimension_2d, 8192);
+        // supported, so not raised to the adapter limit.
+        assert_eq!(limits.max_push_constant_size, 128);
+        assert_eq!(limits.min_uniform_buffer_offset_alignment, 256);
+        assert!(limits.check_limits(&adapter));
+    }
+
+    #[test]
+    fn ten_bit_surfaces_are_not_hdr() {
+        use wgpu::TextureFormat as F;
+        let hdr = SurfaceFormatPreference::Hdr;
+        let supported = [F::Rgb10a2Unorm, F::Bgra8UnormSrgb];
+        assert_eq!(
+            pick_surface_format(&supported, hdr),
+            Some(F::Bgra8UnormSrgb)
+        );
+        let supported = [F::Rgb10a2Unorm, F::Bgra8UnormSrgb, F::Rgba16Float];
+        assert_eq!(pick_surface_format(&supported, hdr), Some(F::Rgba16Float));
+    }
+
+    #[test]
+    fn msaa_falls_back_to_supported_count() {
+        let supported = |count| count == 2 || count == 4;
+        assert_eq!(pick_msaa_sample_count(4, supported), 4);
+        assert_eq!(pick_msaa_sample_count(8, supported), 4);
+        assert_eq!(pick_msaa_sample_count(3, supported), 2);
+        assert_eq!(pick_msaa_sample_count(1, supported), 1);
+        assert_eq!(pick_msaa_sample_count(16, |_| false), 1);
+    }
+}
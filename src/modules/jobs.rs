@@ -0,0 +1,140 @@
+use std::{
+    num::NonZeroUsize,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    sync::mpsc,
+    thread,
+};
+
+use tokio::{runtime::Handle, task::JoinHandle};
+
+/// below this many items per thread, parallel loops just run on the calling thread.
+const MIN_ITEMS_PER_THREAD: usize = 64;
+
+/// Runs work in parallel, either as background jobs on the blocking pool of the tokio runtime,
+/// or as scoped parallel loops that finish before the call returns.
+pub struct Jobs {
+    handle: Handle,
+    parallelism: usize,
+}
+
+impl Jobs {
+    pub fn new(handle: Handle) -> Self {
+        let parallelism = thread::available_parallelism()
+            .map(NonZeroUsize::get)
+            .unwrap_or(1);
+        Jobs {
+            handle,
+            parallelism,
+        }
+    }
+
+    pub fn parallelism(&self) -> usize {
+        self.parallelism
+    }
+
+    /// Runs `f` in the background. Use the returned handle to wait for the result.
+    pub fn spawn<T: Send + 'static>(&self, f: impl FnOnce() -> T + Send + 'static) -> JobHandle<T> {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let inner = self.handle.spawn_blocking(move || {
+            _ = tx.send(catch_unwind(AssertUnwindSafe(f)));
+        });
+        JobHandle { inner, rx }
+    }
+
+    /// Runs `f` in the background, as soon as the `dependency` job is finished, with the result of it.
+    pub fn spawn_after<T: Send + 'static, U: Send + 'static>(
+        &self,
+        dependency: JobHandle<T>,
+        f: impl FnOnce(T) -> U + Send + 'static,
+    ) -> JobHandle<U> {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let inner = self.handle.spawn(async move {
+            // the dependency sends its result before its task finishes.
+            _ = dependency.inner.await;
+            let Ok(input) = dependency.rx.try_recv() else {
+                return;
+            };
+            _ = tokio::task::spawn_blocking(move || {
+                // a panic of the dependency is passed on to whoever joins this job.
+                let result = input.and_then(|input| catch_unwind(AssertUnwindSafe(|| f(input))));
+                _ = tx.send(result);
+            })
+            .await;
+        });
+        JobHandle { inner, rx }
+    }
+
+    /// Calls `f` on every item, split across threads. Returns once all items are processed.
+    pub fn par_for_each_mut<T: Send>(&self, items: &mut [T], f: impl Fn(&mut T) + Sync) {
+        let threads = self
+            .parallelism
+            .min(items.len() / MIN_ITEMS_PER_THREAD)
+            .max(1);
+        if threads == 1 {
+            items.iter_mut().for_each(f);
+            return;
+        }
+        let chunk_size = items.len().div_ceil(threads);
+        let f = &f;
+        thread::scope(|scope| {
+            for chunk in items.chunks_mut(chunk_size) {
+                scope.spawn(move || chunk.iter_mut().for_each(f));
+            }
+        });
+    }
+
+    /// Like `par_for_each_mut`, but for anything that yields mutable references, e.g. `arena.values_mut()`.
+    pub fn par_for_each_mut_iter<'a, T: Send + 'a>(
+        &self,
+        items: impl Iterator<Item = &'a mut T>,
+        f: impl Fn(&mut T) + Sync,
+    ) {
+        let mut items: Vec<&'a mut T> = items.collect();
+        self.par_for_each_mut(&mut items, |item| f(item));
+    }
+}
+
+/// Result of a background job.
+pub struct JobHandle<T> {
+    /// finishes after the result is sent.
+    inner: JoinHandle<()>,
+    rx: mpsc::Receiver<thread::Result<T>>,
+}
+
+impl<T> JobHandle<T> {
+    pub fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+    }
+
+    /// Blocks the calling thread until the job is done. Panics if the job panicked.
+    /// Can also be called from jobs and tasks on the (multi threaded) tokio runtime of the `Jobs`.
+    pub fn join(self) -> T {
+        // on a worker of the runtime, its other tasks (e.g. the one starting a `spawn_after` job) move to another
+        // thread while this one blocks. Elsewhere this just calls the closure.
+        let result = tokio::task::block_in_place(|| self.rx.recv());
+        match result {
+            Ok(Ok(value)) => value,
+            Ok(Err(panic)) => resume_unwind(panic),
+            Err(_) => panic!("job was cancelled, because the runtime shut down"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_inside_of_the_runtime() {
+        let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+        let jobs = Jobs::new(runtime.handle().clone());
+        let first = jobs.spawn(|| 20);
+        let second = jobs.spawn_after(first, |x| x + 1);
+        let joined_in_task = runtime.spawn(async move { second.join() * 2 });
+        assert_eq!(runtime.block_on(joined_in_task).unwrap(), 42);
+
+        let panicking = jobs.spawn(|| -> u32 { panic!("job failed") });
+        let after_panic = jobs.spawn_after(panicking, |x| x + 1);
+        assert!(catch_unwind(AssertUnwindSafe(|| after_panic.join())).is_err());
+    }
+}
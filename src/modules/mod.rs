@@ -1,58 +1,168 @@
 pub mod renderer;
-use std::sync::Arc;
+use std::{
+    mem::ManuallyDrop,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 pub use renderer::{AcesToneMapping, Attribute, Bloom, BloomSettings, VertexT};
 
-use winit::{event::WindowEvent, window::Window};
+use glam::Vec3;
+use smallvec::{smallvec, SmallVec};
+use winit::{
+    dpi::PhysicalSize,
+    event::{DeviceEvent, WindowEvent},
+    window::Window,
+};
+
+pub mod readback;
+pub use readback::{Readback, ReadbackHandle, TextureReadback};
+
+pub mod gpu_commands;
+pub use gpu_commands::{GpuCommand, GpuUploader};
 
 pub mod graphics_context;
-pub use graphics_context::{GraphicsContext, GraphicsContextConfig};
+pub use graphics_context::{
+    GraphicsContext, GraphicsContextConfig, GraphicsContextReport, SurfaceAcquire, SurfaceEncoding,
+    SurfaceFormatPreference,
+};
 
 pub mod input;
 pub use input::Input;
 
+pub mod touch;
+pub use touch::{Gesture, Touches};
+
+pub mod shortcuts;
+pub use shortcuts::{KeyChord, Shortcuts};
+
+pub mod clipboard;
+pub use clipboard::Clipboard;
+
+pub mod settings;
+pub use settings::{Setting, Settings};
+
 pub mod time;
-pub use time::Time;
+pub use time::{Stopwatch, Time, Timer, TimerMode};
+
+pub mod random;
+pub use random::Random;
+
+pub mod cursor;
+pub use cursor::Cursor;
+
+pub mod audio;
+pub use audio::{Audio, MusicPlayer, MusicTrack, Sound, SoundKind};
+
+pub mod video;
+pub use video::{VideoDecoder, VideoPlayer, Y4mDecoder};
+
+#[cfg(feature = "presence")]
+pub mod presence;
+#[cfg(feature = "presence")]
+pub use presence::{Presence, PresenceBackend};
 
 pub mod arenas;
 
 pub mod egui;
-pub use egui::Egui;
+pub use egui::{
+    dock::{Dock, DockPanel, DockSide},
+    Egui,
+};
 
 pub mod ui;
 
+pub mod split_screen;
+pub use split_screen::SplitScreen;
+
+pub mod jobs;
+pub use jobs::Jobs;
+
+pub mod states;
+pub use states::States;
+
+pub mod plugins;
+use plugins::PluginSystems;
+pub use plugins::{Plugin, Plugins};
+
+pub mod post_effects;
+pub use post_effects::{PostEffectKey, PostEffects};
+
+pub mod crash;
+pub use crash::CrashReport;
+
+pub mod streaming;
+pub use streaming::{MeshLod, Streaming, StreamingSettings};
+
+pub mod alloc_tracker;
+pub use alloc_tracker::{alloc_scope, AllocTracker, TrackingAllocator};
+
 use crate::{
-    elements::{camera3d::Camera3dGR, Camera3d, Color, Screen, ScreenGR},
-    App, Prepare, ReceiveWindowEvent, Resize, UpdateFlow,
+    assets::AssetGraph,
+    elements::{
+        camera3d::Camera3dGR,
+        screen::{clamp_viewport, set_viewport_and_scissor},
+        Camera3d, Color, Rect, Screen, ScreenGR,
+    },
+    utils::{Scheduler, System},
+    App, ExitReason, GpuRecreate, GpuRecreated, Prepare, ReceiveWindowEvent, Resize, Resized,
+    UpdateFlow,
 };
 
 use self::{
+    audio::Bus,
+    input::{ClipboardEvent, InputCapture},
     renderer::{
-        ColorMeshRenderer, Gizmos, ScreenTextures, TextRenderer, UiRectRenderer, WorldRectRenderer,
+        CaptureScene, ColorMeshRenderer, EnvironmentCapture, Gizmos, HdrCubemap, RenderScale,
+        RenderTargets, ScreenTextures, TextRenderer, TextureDebugView, TilemapRenderer,
+        TrailRenderer, UiRectRenderer, WorldRectRenderer, WorldTextRenderer,
     },
     ui::{FontCache, UiRenderer},
 };
 
+/// Fields are dropped in declaration order, so everything holding gpu resources comes before the `GraphicsContext`.
+/// See `shutdown` for the whole teardown.
 pub struct DefaultModules {
-    pub tokio: tokio::runtime::Runtime,
-    pub ctx: GraphicsContext,
-    pub window: Arc<Window>,
+    /// shut down with `shutdown_timeout` when the modules are dropped.
+    pub tokio: ManuallyDrop<tokio::runtime::Runtime>,
+    pub jobs: Jobs,
     pub input: Input,
     pub time: Time,
+    /// allocations per `alloc_scope` in the last frame, if the `TrackingAllocator` is installed.
+    pub allocs: AllocTracker,
+    /// seeded rng for everything that affects the simulation, see `set_deterministic`.
+    pub random: Random,
+    pub audio: Audio,
+    pub cursor: Cursor,
+    pub clipboard: Clipboard,
+    pub shortcuts: Shortcuts,
+    /// persisted user options, loaded before the other modules are created.
+    pub settings: Settings,
+    /// dependencies between loaded assets, polled for file changes in `begin_frame`.
+    pub assets: AssetGraph,
 
     pub screen: Screen,
     pub screen_gr: ScreenGR,
     pub camera: Camera3d,
     pub camera_gr: Camera3dGR,
+    pub split_screen: SplitScreen,
 
     pub egui: Egui,
 
     pub screen_textures: ScreenTextures,
+    /// textures shared between passes by name, e.g. the depth buffer.
+    pub render_targets: RenderTargets,
     pub color_mesh: ColorMeshRenderer,
     pub gizmos: Gizmos,
+    pub trails: TrailRenderer,
+    pub tilemaps: TilemapRenderer,
+    /// textures and meshes that are uploaded progressively, within a vram budget.
+    pub streaming: Streaming,
 
     pub ui_rect: UiRectRenderer,
     pub world_rect: WorldRectRenderer,
+    /// many labels in world space, culled against the cameras.
+    pub world_text: WorldTextRenderer,
 
     #[warn(deprecated)]
     /// deprecated, because uses its own font atlas and it a bit clumsy.
@@ -63,139 +173,885 @@ pub struct DefaultModules {
 
     pub bloom: Bloom,
     pub tone_mapping: AcesToneMapping,
+    /// overlay that shows one of the `render_targets`, see `TextureDebugView::egui_texture_debug`.
+    pub texture_debug: TextureDebugView,
+    /// order of bloom and the `Plugin::post_process` effects, can be changed at runtime.
+    pub post_effects: PostEffects,
+    /// size of the hdr and depth textures relative to the surface.
+    pub render_scale: RenderScale,
+
+    pub plugins: Plugins,
+    /// systems that run at the end of `begin_frame`, after the plugins. Unlike plugins they are ordered by name,
+    /// see `Scheduler`.
+    pub schedule: Scheduler<System<DefaultModules>>,
+    /// systems that run `Time::fixed_steps` times per frame (none while paused), right before the `schedule`.
+    /// For simulation code that should not depend on the frame rate, blend the results with `Time::fixed_alpha`.
+    pub fixed_schedule: Scheduler<System<DefaultModules>>,
+
+    pub ctx: GraphicsContext,
+    pub window: Arc<Window>,
+
+    /// How long to wait for background jobs and tasks on the tokio runtime when the modules are dropped.
+    pub shutdown_timeout: Duration,
+    is_shut_down: bool,
+
+    /// Screen sized textures and modules are only resized once no resize happened for this long.
+    /// Until then, the frame is rendered at the old size and stretched to the window.
+    pub resize_debounce: Duration,
+    /// latest resize that was not applied yet, and when it happened.
+    pending_resize: Option<(Resized, Instant)>,
+    /// set by `request_text_input` in this frame.
+    text_input_requested: bool,
+    ime_allowed: bool,
 }
 
 impl DefaultModules {
+    /// The settings are loaded from the config directory named like the window title.
     pub fn new(window: Arc<Window>) -> anyhow::Result<Self> {
+        let settings = Settings::load_for_app(&window.title());
         let tokio = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .build()?;
-        let ctx = GraphicsContext::new(GraphicsContextConfig::default(), &tokio, &window)?;
+        let jobs = Jobs::new(tokio.handle().clone());
+        let streaming = Streaming::new(tokio.handle().clone());
+        let graphics_config = GraphicsContextConfig {
+            present_mode: settings.present_mode(),
+            ..Default::default()
+        };
+        let ctx = GraphicsContext::new(graphics_config, &tokio, &window)?;
         let input = Input::new();
         let time = Time::new();
+        let cursor = Cursor::new();
+        let clipboard = Clipboard::new();
+        let shortcuts = Shortcuts::new();
 
         let screen = Screen::from_window(&window);
         let screen_gr = ScreenGR::new(&ctx, &screen);
         let camera = Camera3d::new(ctx.size.width, ctx.size.height);
         let camera_gr = Camera3dGR::new(&ctx, &camera);
+        let split_screen = SplitScreen::new();
 
         let egui = Egui::new(&ctx, &window);
 
         let screen_textures = ScreenTextures::new(&ctx);
+        let mut render_targets = RenderTargets::new();
+        screen_textures.publish(&mut render_targets);
         let color_mesh = ColorMeshRenderer::new(&ctx, &camera_gr);
         let gizmos = Gizmos::new(&ctx, &camera_gr);
+        let trails = TrailRenderer::new(&ctx, &camera_gr);
+        let tilemaps = TilemapRenderer::new(&ctx, &camera_gr);
         let ui_rect = UiRectRenderer::new(&ctx, &screen_gr);
         let world_rect = WorldRectRenderer::new(&ctx, &camera_gr);
+        let world_text = WorldTextRenderer::new(&ctx, &camera_gr);
         let text = TextRenderer::new(&ctx);
         let fonts = FontCache::new(&ctx);
         let ui = UiRenderer::new(&ctx, &screen_gr);
         let bloom = Bloom::new(&ctx, &screen_textures.screen_vertex_shader, &screen_gr);
         let tone_mapping = AcesToneMapping::new(&ctx, &screen_textures.screen_vertex_shader);
+        let texture_debug = TextureDebugView::new(&ctx);
 
-        Ok(DefaultModules {
-            tokio,
-            ctx,
-            window,
+        let mut modules = DefaultModules {
+            tokio: ManuallyDrop::new(tokio),
+            jobs,
             input,
             time,
+            allocs: AllocTracker::new(),
+            random: Random::from_entropy(),
+            audio: Audio::new(),
+            cursor,
+            clipboard,
+            shortcuts,
+            settings,
+            assets: AssetGraph::new(),
             screen,
             screen_gr,
             camera,
             camera_gr,
+            split_screen,
             egui,
             screen_textures,
+            render_targets,
             gizmos,
+            trails,
+            tilemaps,
+            streaming,
             color_mesh,
             ui_rect,
             world_rect,
+            world_text,
             text,
             fonts,
             ui,
             bloom,
             tone_mapping,
-        })
+            texture_debug,
+            post_effects: PostEffects::default(),
+            render_scale: RenderScale::default(),
+            plugins: Plugins::default(),
+            schedule: Scheduler::new(),
+            fixed_schedule: Scheduler::new(),
+            ctx,
+            window,
+            shutdown_timeout: Duration::from_secs(2),
+            is_shut_down: false,
+            resize_debounce: Duration::from_millis(150),
+            pending_resize: None,
+            text_input_requested: false,
+            ime_allowed: false,
+        };
+        modules.apply_settings(true);
+        Ok(modules)
+    }
+
+    /// Applies the built-in `Settings` that changed in the last frame, or all of them.
+    fn apply_settings(&mut self, all: bool) {
+        let settings = &self.settings;
+        if all || settings.was_changed(Settings::RESOLUTION) {
+            if let Some([width, height]) = settings.get(Settings::RESOLUTION) {
+                // the surface and screen follow with the resize event.
+                _ = self
+                    .window
+                    .request_inner_size(PhysicalSize::new(width, height));
+            }
+        }
+        if all || settings.was_changed(Settings::VSYNC) {
+            self.ctx.set_present_mode(settings.present_mode());
+        }
+        if all || settings.was_changed(Settings::VOLUME) {
+            let mut master = *self.audio.mixer.state().bus(Bus::Master);
+            master.volume = settings.get(Settings::VOLUME);
+            self.audio.mixer.set_bus(Bus::Master, master);
+        }
+        if all || settings.was_changed(Settings::UI_SCALE) {
+            self.screen.ui_scale = settings.get(Settings::UI_SCALE);
+        }
     }
 
     pub fn begin_frame(&mut self) -> UpdateFlow {
+        let _scope = alloc_scope("modules");
         self.time.update();
+        self.allocs.update();
+        self.settings.update();
+        self.apply_settings(false);
+        self.audio.update(*self.time.real_delta());
+        self.assets.update();
+        // copies of the last frame are often mapped by now, such that the app sees them in this update.
+        self.ctx.readback.poll();
+        crash::record_frame(&self.time);
+        if let Some(scale_factor) = self.input.scale_factor_changed() {
+            self.screen.scale_factor = scale_factor;
+        }
+        // every frame, because the user can change `Screen::ui_scale` at any time.
+        let ui_scale_factor = self.screen.ui_scale_factor();
+        self.fonts.begin_frame();
+        self.fonts.set_raster_scale(ui_scale_factor);
+        self.egui.platform.set_pixels_per_point(ui_scale_factor);
+        // a text field had focus last frame, if the ime is allowed.
+        self.shortcuts.dispatch(&self.input, self.ime_allowed);
+        for key in self.shortcuts.swallowed() {
+            self.input.consume_key(*key);
+            self.egui.platform.remove_key_events(*key);
+        }
+        if self
+            .input
+            .clipboard_events()
+            .contains(&ClipboardEvent::Paste)
+        {
+            if let Some(text) = self.clipboard.get_text() {
+                self.egui.paste(text);
+            }
+        }
+        // egui decides with the state of the last frame, whether the pointer is over one of its windows.
+        self.input.set_capture(InputCapture {
+            pointer: self.egui.wants_pointer_input(),
+            keyboard: self.egui.wants_keyboard_input(),
+        });
         self.egui.begin_frame();
 
         if self.input.close_requested() {
             return UpdateFlow::Exit("Close Requested".into());
         }
         if let Some(resized) = self.input.resized() {
+            // the surface always has to match the window, everything else is debounced.
             self.ctx.resize(resized);
-            self.camera.resize(resized);
-            self.screen_textures.resize(&self.ctx);
-            self.screen.resize(resized);
-            self.bloom.resize(resized);
+            self.pending_resize = Some((resized, Instant::now()));
+        }
+        if let Some((resized, time)) = self.pending_resize {
+            if time.elapsed() >= self.resize_debounce {
+                self.pending_resize = None;
+                self.apply_resize(resized);
+            }
+        }
+        let pixel_perfect_changed = self
+            .render_scale
+            .set_fixed_size(self.screen.pixel_perfect_size());
+        if self.render_scale.update(*self.time.real_delta()) || pixel_perfect_changed {
+            self.apply_render_size();
+        }
+        self.tone_mapping
+            .set_sharpness(self.render_scale.sharpness());
+        self.tone_mapping
+            .set_pixel_perfect(self.render_scale.is_pixel_perfect());
+        self.tone_mapping.set_exposure(self.camera.exposure_scale());
+        self.color_mesh.set_fixed_alpha(self.time.fixed_alpha());
+
+        // each plugin is taken out temporarily, such that it can get mutable access to the other modules.
+        // Plugins added by a hook start next frame, removed ones are skipped.
+        let plugins_scope = alloc_scope("plugins");
+        for id in self.plugins.ids() {
+            if let Some(mut plugin) = self.plugins.take_running(id) {
+                let next_keys = PluginSystems::next_keys(&self.schedule, &self.fixed_schedule);
+                plugin.begin_frame(self);
+                let systems =
+                    PluginSystems::added_since(next_keys, &self.schedule, &self.fixed_schedule);
+                self.plugins.put_back(id, plugin, systems);
+            }
         }
+        drop(plugins_scope);
+
+        let _systems_scope = alloc_scope("systems");
+        let fixed_timestep = self.time.fixed_timestep();
+        for _ in 0..self.time.fixed_steps() {
+            Scheduler::run_on(self, |m| &mut m.fixed_schedule, fixed_timestep)?;
+        }
+        let delta = *self.time.delta();
+        Scheduler::run_on(self, |m| &mut m.schedule, delta)?;
 
         UpdateFlow::Continue
     }
 
+    /// Resizes all modules that depend on the screen size at once.
+    fn apply_resize(&mut self, resized: Resized) {
+        self.camera.resize(resized);
+        self.screen.resize(resized);
+        self.apply_render_size();
+        for plugin in self.plugins.iter_mut() {
+            plugin.resize(resized);
+        }
+    }
+
+    /// Recreates the screen textures (and the bloom mips) with the size given by the `render_scale`.
+    fn apply_render_size(&mut self) {
+        let max_dimension = self.ctx.device.limits().max_texture_dimension_2d;
+        let size = self.render_scale.render_size(self.ctx.size, max_dimension);
+        self.screen_textures.resize_to(&self.ctx, size);
+        self.screen_textures.publish(&mut self.render_targets);
+        self.bloom.resize(Resized { new_size: size });
+    }
+
+    /// Maps a viewport from screen pixels to pixels of the screen textures, which differ by the render scale
+    /// (and while a resize is pending).
+    fn render_viewport(&self, viewport: Rect) -> Rect {
+        let size = self.screen_textures.size();
+        // in pixel perfect mode, the screen textures only cover the viewport of the screen, not the black bars.
+        let area = if self.render_scale.is_pixel_perfect() {
+            self.screen.viewport()
+        } else {
+            Rect::new(
+                0.0,
+                0.0,
+                self.screen.width as f32,
+                self.screen.height as f32,
+            )
+        };
+        let scale_x = size.width as f32 / area.width.max(1.0);
+        let scale_y = size.height as f32 / area.height.max(1.0);
+        let viewport = Rect {
+            min_x: (viewport.min_x - area.min_x) * scale_x,
+            min_y: (viewport.min_y - area.min_y) * scale_y,
+            width: viewport.width * scale_x,
+            height: viewport.height * scale_y,
+        };
+        clamp_viewport(viewport, size)
+    }
+
+    /// true while a resize is debounced, see `resize_debounce`.
+    pub fn is_resizing(&self) -> bool {
+        self.pending_resize.is_some()
+    }
+
+    /// The viewport of the screen in surface pixels. Differs from `Screen::viewport` while a resize is pending,
+    /// because the screen still has the old size then and is stretched over the surface.
+    pub fn surface_viewport(&self) -> Rect {
+        let viewport = self.screen.viewport();
+        let scale_x = self.ctx.size.width as f32 / self.screen.width.max(1) as f32;
+        let scale_y = self.ctx.size.height as f32 / self.screen.height.max(1) as f32;
+        let viewport = Rect {
+            min_x: viewport.min_x * scale_x,
+            min_y: viewport.min_y * scale_y,
+            width: viewport.width * scale_x,
+            height: viewport.height * scale_y,
+        };
+        clamp_viewport(viewport, self.ctx.size)
+    }
+
+    /// With a seed, the simulation becomes reproducible from its inputs: the `random` generator restarts from the
+    /// seed and every frame advances the time by exactly one fixed step (see `Time::set_deterministic`). Arenas and
+    /// the `Scheduler` already iterate in a stable order. Groundwork for lockstep networking and replays.
+    /// `None` goes back to real time and a random seed.
+    pub fn set_deterministic(&mut self, seed: Option<u64>) {
+        self.time.set_deterministic(seed.is_some());
+        match seed {
+            Some(seed) => self.random.reseed(seed),
+            None => self.random = Random::from_entropy(),
+        }
+    }
+
+    /// Enables the ime (input method editor, e.g. for CJK text) for this frame. Call while a text field is focused,
+    /// e.g. if `Board::wants_text_input`. The ime is also enabled while egui wants keyboard input.
+    pub fn request_text_input(&mut self) {
+        self.text_input_requested = true;
+    }
+
+    /// Adds a plugin and initializes it. Panics if a plugin of the same type was already added
+    /// or its dependencies are missing. Can be called at any time, not just at startup.
+    pub fn add_plugin<P: Plugin>(&mut self, plugin: P) -> &mut P {
+        self.try_add_plugin(plugin).unwrap()
+    }
+
+    pub fn try_add_plugin<P: Plugin>(&mut self, plugin: P) -> anyhow::Result<&mut P> {
+        self.plugins.check_can_add(&plugin)?;
+        let mut plugin = Box::new(plugin);
+        self.post_effects
+            .push(PostEffectKey::plugin::<P>(), std::any::type_name::<P>());
+        let next_keys = PluginSystems::next_keys(&self.schedule, &self.fixed_schedule);
+        plugin.initialize(self);
+        let systems = PluginSystems::added_since(next_keys, &self.schedule, &self.fixed_schedule);
+        self.plugins.push(plugin, systems);
+        Ok(self.plugins.get_mut::<P>().unwrap())
+    }
+
+    /// Removes a plugin at runtime, such that none of its hooks are called anymore.
+    /// The systems it added in `Plugin::initialize` or `Plugin::begin_frame` are removed too.
+    /// Errors if the plugin is not present or other plugins depend on it.
+    pub fn remove_plugin<P: Plugin>(&mut self) -> anyhow::Result<P> {
+        let (mut plugin, systems) = self.plugins.take::<P>()?;
+        systems.remove_from(&mut self.schedule, &mut self.fixed_schedule);
+        self.post_effects.remove(PostEffectKey::plugin::<P>());
+        plugin.deinitialize(self);
+        Ok(*plugin)
+    }
+
+    /// Skips the frame if the surface texture cannot be acquired, and recreates gpu resources if the surface
+    /// or the device was lost.
     pub fn prepare_and_render(&mut self, clear_color: Color) {
+        if self.ctx.is_device_lost() {
+            self.recover_from_device_loss();
+            return;
+        }
+        // submitted on their own, such that they are not lost if the frame is skipped.
+        self.ctx.submit_queued_commands();
         let mut encoder = self.ctx.new_encoder();
+        // prepare even if the frame is skipped, to clear the immediate geometry.
         self.prepare(&mut encoder);
+        let _scope = alloc_scope("renderer");
 
-        let (surface_texture, surface_view) = self.ctx.new_surface_texture_and_view();
+        let (surface_texture, surface_view) = match self.ctx.acquire_surface_texture() {
+            SurfaceAcquire::Ready(surface_texture, surface_view) => (surface_texture, surface_view),
+            SurfaceAcquire::Skip => {
+                self.ctx.readback.cancel_recorded();
+                return;
+            }
+            SurfaceAcquire::Reconfigured => {
+                self.ctx.readback.cancel_recorded();
+                self.gpu_recreated(GpuRecreated { device_lost: false });
+                return;
+            }
+            SurfaceAcquire::DeviceLost => {
+                self.recover_from_device_loss();
+                return;
+            }
+        };
+        let screen_viewport = self.render_viewport(self.screen.viewport());
+        let surface_viewport = self.surface_viewport();
 
         // Main Pass Render
         let mut render_pass = self
             .screen_textures
             .new_hdr_target_render_pass(&mut encoder, clear_color);
-        self.color_mesh.render(&mut render_pass, &self.camera_gr);
-        self.world_rect.render(&mut render_pass, &self.camera_gr);
+        let views = if self.split_screen.is_active() {
+            self.split_screen.views(&self.screen)
+        } else {
+            smallvec![(
+                self.camera.viewport_or_screen(&self.screen),
+                &self.camera_gr
+            )]
+        };
+        for (viewport, camera_gr) in views {
+            set_viewport_and_scissor(&mut render_pass, self.render_viewport(viewport));
+            self.render_world(&mut render_pass, camera_gr, true);
+        }
+        set_viewport_and_scissor(&mut render_pass, screen_viewport);
         self.ui_rect.render(&mut render_pass, &self.screen_gr);
-        self.gizmos.render(&mut render_pass, &self.camera_gr);
 
         drop(render_pass);
 
-        // Post processing in Hdr space
-        self.bloom.apply(
+        // Post processing in Hdr space, in the order of the post effects chain.
+        for key in self.post_effects.enabled() {
+            match key {
+                PostEffectKey::Bloom => self.bloom.apply(
+                    &mut encoder,
+                    &self.ctx.queue,
+                    self.screen_textures.hdr_resolve_target.bind_group(),
+                    self.screen_textures.hdr_resolve_target.view(),
+                    &self.screen_gr,
+                ),
+                PostEffectKey::Plugin(id) => {
+                    if let Some(plugin) = self.plugins.get_mut_by_id(id) {
+                        plugin.post_process(&mut encoder, &self.screen_textures.hdr_resolve_target);
+                    }
+                }
+            }
+        }
+        // Tone mapping (also scales the hdr texture to the surface, if they have different sizes)
+        self.tone_mapping.apply(
             &mut encoder,
+            &self.ctx.queue,
             self.screen_textures.hdr_resolve_target.bind_group(),
-            self.screen_textures.hdr_resolve_target.view(),
-            &self.screen_gr,
+            &surface_view,
+            surface_viewport,
         );
-        // Tone mapping
-        self.tone_mapping.apply(
+        self.ui.render(
             &mut encoder,
-            self.screen_textures.hdr_resolve_target.bind_group(),
             &surface_view,
+            &self.screen_gr,
+            &self.fonts,
+            surface_viewport,
         );
-        self.ui
-            .render(&mut encoder, &surface_view, &self.screen_gr, &self.fonts);
+        self.texture_debug
+            .render(&mut encoder, &surface_view, surface_viewport);
         self.egui.render(&mut encoder, &surface_view);
 
         self.ctx.queue.submit(std::iter::once(encoder.finish()));
+        self.ctx.readback.submitted();
         surface_texture.present();
+        self.ctx.readback.poll();
+    }
+
+    /// Everything in the world as seen by `camera_gr`, used by the main pass and `EnvironmentCapture`.
+    fn render_world<'e>(
+        &'e self,
+        render_pass: &mut wgpu::RenderPass<'e>,
+        camera_gr: &'e Camera3dGR,
+        gizmos: bool,
+    ) {
+        for plugin in self.plugins.iter() {
+            plugin.render_background(render_pass, camera_gr);
+        }
+        self.color_mesh.render(render_pass, camera_gr);
+        self.tilemaps.render(render_pass, camera_gr);
+        self.world_rect.render(render_pass, camera_gr);
+        self.world_text.render(render_pass, camera_gr);
+        self.trails.render(render_pass, camera_gr);
+        if gizmos {
+            self.gizmos.render(render_pass, camera_gr);
+        }
+        for plugin in self.plugins.iter() {
+            plugin.render(render_pass, camera_gr);
+        }
+    }
+
+    /// Renders the world (without gizmos) from `position` into the faces of `target`. World text is culled against
+    /// the main cameras, labels only visible from the capture position are missing.
+    pub fn capture_environment(
+        &self,
+        capture: &mut EnvironmentCapture,
+        position: Vec3,
+        target: &HdrCubemap,
+    ) {
+        capture.capture(&self.ctx, position, target, self);
+    }
+
+    /// Requests a new device and recreates all gpu resources of the modules. If that fails, it is tried again
+    /// next frame.
+    fn recover_from_device_loss(&mut self) {
+        log::warn!("gpu device lost, recreating it");
+        if let Err(err) = self.ctx.recreate_device(&self.tokio) {
+            log::error!("could not recreate gpu device: {err}");
+            return;
+        }
+        self.gpu_recreated(GpuRecreated { device_lost: true });
+    }
+
+    /// Recreates the gpu resources of all modules and notifies the plugins. Settings (e.g. of bloom and its lens dirt)
+    /// are kept.
+    fn gpu_recreated(&mut self, event: GpuRecreated) {
+        // the screen textures are recreated with the surface size below.
+        if let Some((resized, _)) = self.pending_resize.take() {
+            self.apply_resize(resized);
+        }
+        let ctx = &self.ctx;
+        let max_dimension = ctx.device.limits().max_texture_dimension_2d;
+        let render_size = self.render_scale.render_size(ctx.size, max_dimension);
+        self.screen_textures = ScreenTextures::new_sized(ctx, render_size);
+        self.screen_textures.publish(&mut self.render_targets);
+        if event.device_lost {
+            self.screen_gr = ScreenGR::new(ctx, &self.screen);
+            self.camera_gr = Camera3dGR::new(ctx, &self.camera);
+            self.split_screen.gpu_recreated(ctx, event);
+            self.egui.gpu_recreated(ctx, event);
+            self.fonts.gpu_recreated(ctx, event);
+            self.cursor.gpu_recreated(ctx, event);
+            self.color_mesh = ColorMeshRenderer::new(ctx, &self.camera_gr);
+            self.gizmos = Gizmos::new(ctx, &self.camera_gr);
+            self.trails = TrailRenderer::new(ctx, &self.camera_gr);
+            self.tilemaps = TilemapRenderer::new(ctx, &self.camera_gr);
+            self.ui_rect = UiRectRenderer::new(ctx, &self.screen_gr);
+            self.world_rect = WorldRectRenderer::new(ctx, &self.camera_gr);
+            self.world_text = WorldTextRenderer::new(ctx, &self.camera_gr);
+            self.text = TextRenderer::new(ctx);
+            self.ui = UiRenderer::new(ctx, &self.screen_gr);
+
+            let bloom_settings = self.bloom.settings_mut().clone();
+            let lens_dirt = self.bloom.take_lens_dirt();
+            self.bloom = Bloom::new(
+                ctx,
+                &self.screen_textures.screen_vertex_shader,
+                &self.screen_gr,
+            );
+            *self.bloom.settings_mut() = bloom_settings;
+            self.bloom.set_lens_dirt(ctx, lens_dirt);
+            self.bloom.resize(Resized {
+                new_size: render_size,
+            });
+            let tone_mapping_enabled = *self.tone_mapping.enabled_mut();
+            self.tone_mapping =
+                AcesToneMapping::new(ctx, &self.screen_textures.screen_vertex_shader);
+            *self.tone_mapping.enabled_mut() = tone_mapping_enabled;
+            let texture_debug_settings = self.texture_debug.settings.clone();
+            self.texture_debug = TextureDebugView::new(ctx);
+            self.texture_debug.settings = texture_debug_settings;
+        } else {
+            self.bloom.resize(Resized {
+                new_size: render_size,
+            });
+        }
+        self.streaming.gpu_recreated(ctx, event);
+        for plugin in self.plugins.iter_mut() {
+            plugin.gpu_recreated(ctx, event);
+        }
     }
 
     pub fn prepare(&mut self, encoder: &mut wgpu::CommandEncoder) {
         let device = &self.ctx.device;
         let queue = &self.ctx.queue;
 
+        let scope = alloc_scope("egui");
         self.egui.prepare(device, queue, encoder);
+        drop(scope);
+        let _scope = alloc_scope("modules");
+        if let Some(text) = self.egui.take_copied_text() {
+            self.clipboard.set_text(text);
+        }
+        // egui decides the cursor while the pointer is over one of its windows.
+        if let Some(cursor) = self.egui.cursor() {
+            self.cursor.set(cursor);
+        }
+        if self.input.apply_cursor_grab(&self.window) {
+            self.cursor.hide();
+        }
+        self.cursor.apply(&self.window, &self.screen, &mut self.ui);
+        let ime_allowed = std::mem::take(&mut self.text_input_requested)
+            || self.egui.context().wants_keyboard_input();
+        if ime_allowed != self.ime_allowed {
+            self.window.set_ime_allowed(ime_allowed);
+            self.ime_allowed = ime_allowed;
+        }
 
+        self.camera.fit_to_viewport(&self.screen);
         self.camera_gr.prepare(queue, &self.camera);
+        self.split_screen.prepare(queue, &self.screen);
         self.screen_gr.prepare(queue, &self.screen);
 
+        let scope = alloc_scope("renderer");
         self.color_mesh.prepare(device, queue, encoder);
         self.gizmos.prepare(device, queue, encoder);
+        self.trails.prepare(device, queue, encoder);
+        self.tilemaps.prepare(device, queue, encoder);
+        self.streaming.prepare(device, queue);
         self.text.prepare(queue);
         self.ui_rect.prepare(device, queue, encoder);
         self.world_rect.prepare(device, queue, encoder);
+        let cameras: SmallVec<[&Camera3d; 4]> = if self.split_screen.is_active() {
+            self.split_screen
+                .players()
+                .iter()
+                .map(|p| &p.camera)
+                .collect()
+        } else {
+            smallvec![&self.camera]
+        };
+        self.world_text.prepare(device, queue, &cameras);
+        drop(scope);
+        let scope = alloc_scope("ui");
         self.ui.prepare(device, queue, encoder);
-        self.fonts.prepare(queue);
+        self.fonts.prepare(&self.ctx);
+        drop(scope);
+        let scope = alloc_scope("plugins");
+        for plugin in self.plugins.iter_mut() {
+            plugin.prepare(device, queue, encoder);
+        }
+        drop(scope);
+        let _scope = alloc_scope("renderer");
+        // after the plugins, which might publish render targets.
+        self.texture_debug.prepare(
+            &self.ctx,
+            encoder,
+            &self.screen_textures.screen_vertex_shader,
+            &self.render_targets,
+        );
     }
 
     pub fn end_frame(&mut self) {
         self.input.end_frame();
     }
 
+    /// Shows the stats overlays: frame times (see `Time::egui_time_stats`) and allocations per scope
+    /// (see `AllocTracker::egui_alloc_stats`).
+    pub fn egui_stats(&mut self) {
+        let egui_ctx = self.egui.context();
+        self.time.egui_time_stats(egui_ctx.clone());
+        self.allocs.egui_alloc_stats(egui_ctx);
+    }
+
+    /// A simple "the game crashed" screen to render instead of the game after a panic, see `App::crashed`.
+    /// Exits when the window is closed or "Quit" is clicked.
+    ///
+    /// Only egui is rendered, on a cleared surface. The world, the ui and the plugins could still be in the state
+    /// that made the app panic, so their hooks are not called.
+    pub fn crash_screen(&mut self, report: &CrashReport) -> UpdateFlow {
+        self.time.update();
+        // also ends the egui frame the panic interrupted.
+        self.egui.begin_frame();
+        if self.input.close_requested() {
+            return UpdateFlow::Exit(ExitReason::Crashed(Box::new(report.clone())));
+        }
+        let mut quit = false;
+        ::egui::Window::new("The game crashed").show(&self.egui.context(), |ui| {
+            ui.label(report.message.clone());
+            if let Some(location) = &report.location {
+                ui.label(format!("at {location}"));
+            }
+            match &report.file {
+                Some(file) => ui.label(format!("A crash report was saved to {}", file.display())),
+                None => ui.label("No crash report was saved."),
+            };
+            ui.collapsing("Backtrace", |ui| ui.label(report.backtrace.clone()));
+            quit = ui.button("Quit").clicked();
+        });
+        if quit {
+            return UpdateFlow::Exit(ExitReason::Crashed(Box::new(report.clone())));
+        }
+        self.render_crash_screen();
+        self.end_frame();
+        UpdateFlow::Continue
+    }
+
+    fn render_crash_screen(&mut self) {
+        if self.ctx.is_device_lost() {
+            self.recover_from_device_loss();
+            return;
+        }
+        let mut encoder = self.ctx.new_encoder();
+        self.egui
+            .prepare(&self.ctx.device, &self.ctx.queue, &mut encoder);
+        let (surface_texture, surface_view) = match self.ctx.acquire_surface_texture() {
+            SurfaceAcquire::Ready(surface_texture, surface_view) => (surface_texture, surface_view),
+            SurfaceAcquire::Skip | SurfaceAcquire::Reconfigured => return,
+            SurfaceAcquire::DeviceLost => {
+                self.recover_from_device_loss();
+                return;
+            }
+        };
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Crash Screen Clear"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &surface_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(Color::DARKGREY.into()),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        self.egui.render(&mut encoder, &surface_view);
+        self.ctx.queue.submit(std::iter::once(encoder.finish()));
+        surface_texture.present();
+    }
+
+    /// Rebuilds the modules in process, as if the app was started again. The tokio runtime, the window,
+    /// the clipboard and the `GraphicsContext` (with its device) are kept, plugins are shut down and removed,
+    /// and the systems and asset dependencies the app registered are dropped.
+    /// Used to handle `ExitReason::Restart` in `App::restart`.
+    pub fn restart(&mut self) {
+        self.shutdown();
+        reset_run_state(
+            &mut self.schedule,
+            &mut self.fixed_schedule,
+            &mut self.assets,
+            &mut self.jobs,
+            self.tokio.handle(),
+        );
+        let ctx = &self.ctx;
+        self.input = Input::new();
+        let deterministic = self.time.is_deterministic();
+        self.time = Time::new();
+        self.time.set_deterministic(deterministic);
+        // a restarted run replays the same random sequence.
+        self.random.reseed(self.random.seed());
+        self.cursor = Cursor::new();
+        self.audio = Audio::new();
+        self.shortcuts = Shortcuts::new();
+
+        self.screen = Screen::from_window(&self.window);
+        self.screen_gr = ScreenGR::new(ctx, &self.screen);
+        self.camera = Camera3d::new(ctx.size.width, ctx.size.height);
+        self.camera_gr = Camera3dGR::new(ctx, &self.camera);
+        self.split_screen = SplitScreen::new();
+
+        self.egui = Egui::new(ctx, &self.window);
+
+        self.screen_textures = ScreenTextures::new(ctx);
+        self.render_targets = RenderTargets::new();
+        self.screen_textures.publish(&mut self.render_targets);
+        self.color_mesh = ColorMeshRenderer::new(ctx, &self.camera_gr);
+        self.gizmos = Gizmos::new(ctx, &self.camera_gr);
+        self.trails = TrailRenderer::new(ctx, &self.camera_gr);
+        self.tilemaps = TilemapRenderer::new(ctx, &self.camera_gr);
+        let streaming_settings = self.streaming.settings;
+        self.streaming = Streaming::new(self.tokio.handle().clone());
+        self.streaming.settings = streaming_settings;
+        self.ui_rect = UiRectRenderer::new(ctx, &self.screen_gr);
+        self.world_rect = WorldRectRenderer::new(ctx, &self.camera_gr);
+        self.world_text = WorldTextRenderer::new(ctx, &self.camera_gr);
+        self.text = TextRenderer::new(ctx);
+        self.fonts = FontCache::new(ctx);
+        self.ui = UiRenderer::new(ctx, &self.screen_gr);
+        self.bloom = Bloom::new(
+            ctx,
+            &self.screen_textures.screen_vertex_shader,
+            &self.screen_gr,
+        );
+        self.tone_mapping = AcesToneMapping::new(ctx, &self.screen_textures.screen_vertex_shader);
+        self.texture_debug = TextureDebugView::new(ctx);
+        self.post_effects = PostEffects::default();
+        self.render_scale = RenderScale::default();
+
+        self.pending_resize = None;
+        self.text_input_requested = false;
+        self.ime_allowed = false;
+        self.window.set_ime_allowed(false);
+        self.is_shut_down = false;
+        // the settings are kept, but the rebuilt modules start with their defaults.
+        self.apply_settings(true);
+    }
+
+    /// Ordered teardown, called on drop if not called before. Call it yourself (e.g. in `App::shutdown`) to shut down
+    /// before your own state is dropped.
+    ///
+    /// Plugins are shut down in reverse order and removed, then all submitted gpu work is waited for.
+    /// Afterwards the modules are dropped in field order, renderers before the `GraphicsContext`,
+    /// and the tokio runtime waits up to `shutdown_timeout` for the remaining jobs.
+    pub fn shutdown(&mut self) {
+        if self.is_shut_down {
+            return;
+        }
+        self.is_shut_down = true;
+        while let Some(mut plugin) = self.plugins.pop() {
+            plugin.shutdown(self);
+        }
+        if self.settings.has_unsaved_changes() {
+            self.settings.save_logged();
+        }
+        // such that no buffer or texture is freed while the gpu still uses it.
+        self.ctx.device.poll(wgpu::Maintain::Wait);
+        log::info!("default modules shut down");
+    }
+
+    pub fn receive_device_event(&mut self, event: &DeviceEvent) {
+        self.input.receive_device_event(event);
+        for plugin in self.plugins.iter_mut() {
+            plugin.receive_device_event(event);
+        }
+    }
+
     pub fn receive_window_event(&mut self, event: &WindowEvent) {
         self.input.receive_window_event(event);
         self.egui.receive_window_event(event);
+        self.cursor.receive_window_event(event);
+        for plugin in self.plugins.iter_mut() {
+            plugin.receive_window_event(event);
+        }
+    }
+}
+
+/// Drops what the app registered while it ran: the systems (and their one-shots), the asset dependencies
+/// and the job pool. See `DefaultModules::restart`.
+fn reset_run_state(
+    schedule: &mut Scheduler<System<DefaultModules>>,
+    fixed_schedule: &mut Scheduler<System<DefaultModules>>,
+    assets: &mut AssetGraph,
+    jobs: &mut Jobs,
+    tokio: &tokio::runtime::Handle,
+) {
+    *schedule = Scheduler::new();
+    *fixed_schedule = Scheduler::new();
+    *assets = AssetGraph::new();
+    *jobs = Jobs::new(tokio.clone());
+}
+
+impl Drop for DefaultModules {
+    fn drop(&mut self) {
+        self.shutdown();
+        // Safety: the runtime is not used after this, no other field needs it to be dropped.
+        let tokio = unsafe { ManuallyDrop::take(&mut self.tokio) };
+        tokio.shutdown_timeout(self.shutdown_timeout);
+    }
+}
+
+impl CaptureScene for DefaultModules {
+    fn render_capture<'e>(
+        &'e self,
+        render_pass: &mut wgpu::RenderPass<'e>,
+        camera: &'e Camera3dGR,
+    ) {
+        self.render_world(render_pass, camera, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::AssetSource;
+
+    #[test]
+    fn restart_drops_the_registered_state() {
+        let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+        let mut jobs = Jobs::new(runtime.handle().clone());
+        let mut schedule: Scheduler<System<DefaultModules>> = Scheduler::new();
+        let mut fixed_schedule: Scheduler<System<DefaultModules>> = Scheduler::new();
+        schedule.add("update", Box::new(|_| {})).unwrap();
+        schedule.add_stage_after("late", Scheduler::<System<DefaultModules>>::UPDATE);
+        fixed_schedule.add("physics", Box::new(|_| {})).unwrap();
+        let mut assets = AssetGraph::new();
+        assets.add_dependency(AssetSource::from("scene"), AssetSource::from("mesh"));
+
+        reset_run_state(
+            &mut schedule,
+            &mut fixed_schedule,
+            &mut assets,
+            &mut jobs,
+            runtime.handle(),
+        );
+        assert_eq!(schedule.iter_mut().unwrap().count(), 0);
+        assert_eq!(fixed_schedule.iter_mut().unwrap().count(), 0);
+        assert!(!schedule.dump().contains("late"));
+        // the names are free again.
+        schedule.add("update", Box::new(|_| {})).unwrap();
+        assert!(assets.dependencies(&AssetSource::from("scene")).is_empty());
+        assert_eq!(jobs.spawn(|| 42).join(), 42);
     }
 }
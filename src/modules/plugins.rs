@@ -0,0 +1,332 @@
+use std::any::{Any, TypeId};
+
+use winit::event::{DeviceEvent, WindowEvent};
+
+use crate::{
+    elements::camera3d::Camera3dGR,
+    utils::{EntryKey, Scheduler, System},
+    GpuRecreated, Resized,
+};
+
+use super::{renderer::HdrTexture, DefaultModules, GraphicsContext};
+
+/// Lets other crates hook into the `DefaultModules` without forking them: add custom modules, renderers that draw into
+/// the main hdr pass, and post effects that run in hdr space before tone mapping.
+///
+/// All methods have empty default implementations, so a plugin only implements what it needs.
+pub trait Plugin: Any {
+    /// Type ids of other plugins that need to be added before this one (and cannot be removed while this one exists).
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![]
+    }
+
+    /// Called once in `DefaultModules::add_plugin`. The post effect of the plugin is already at the end of
+    /// `DefaultModules::post_effects`, so it can move itself, e.g. before bloom.
+    ///
+    /// Systems added to `DefaultModules::schedule` or `fixed_schedule` here or in `begin_frame` belong to the plugin
+    /// and are removed together with it.
+    fn initialize(&mut self, _mods: &mut DefaultModules) {}
+
+    /// Called once in `DefaultModules::remove_plugin`.
+    fn deinitialize(&mut self, _mods: &mut DefaultModules) {}
+
+    /// Called once in `DefaultModules::shutdown`, e.g. to save pending state. Plugins are shut down in reverse order,
+    /// so the plugins this one depends on are still there. Calls `deinitialize` by default.
+    fn shutdown(&mut self, mods: &mut DefaultModules) {
+        self.deinitialize(mods);
+    }
+
+    /// Called at the end of `DefaultModules::begin_frame`.
+    fn begin_frame(&mut self, _mods: &mut DefaultModules) {}
+
+    fn resize(&mut self, _resized: Resized) {}
+
+    /// Called after the surface was reconfigured or the device was recreated, see `GpuRecreated`.
+    fn gpu_recreated(&mut self, _ctx: &GraphicsContext, _event: GpuRecreated) {}
+
+    fn receive_window_event(&mut self, _event: &WindowEvent) {}
+
+    fn receive_device_event(&mut self, _event: &DeviceEvent) {}
+
+    fn prepare(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _encoder: &mut wgpu::CommandEncoder,
+    ) {
+    }
+
+    /// Draw into the main hdr render pass before all other geometry, once per camera view, e.g. a sky or backdrop.
+    fn render_background<'e>(
+        &'e self,
+        _render_pass: &mut wgpu::RenderPass<'e>,
+        _camera: &'e Camera3dGR,
+    ) {
+    }
+
+    /// Draw into the main hdr render pass (msaa, with depth), once per camera view.
+    fn render<'e>(&'e self, _render_pass: &mut wgpu::RenderPass<'e>, _camera: &'e Camera3dGR) {}
+
+    /// Post processing on the resolved hdr texture, before tone mapping. Runs at the plugins position in
+    /// `DefaultModules::post_effects`, after bloom by default.
+    fn post_process(&mut self, _encoder: &mut wgpu::CommandEncoder, _hdr: &HdrTexture) {}
+}
+
+/// The plugins registered in the `DefaultModules`, in the order they were added.
+///
+/// While the hook of a plugin runs with access to the `DefaultModules` (e.g. `Plugin::begin_frame`), the plugin itself
+/// is replaced by a placeholder, such that the other plugins can still be accessed, added and removed.
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<PluginEntry>,
+}
+
+struct PluginEntry {
+    id: TypeId,
+    dependencies: Vec<TypeId>,
+    plugin: Box<dyn Plugin>,
+    systems: PluginSystems,
+}
+
+/// Keys of the systems a plugin added to `DefaultModules::schedule` and `fixed_schedule`.
+#[derive(Debug, Default)]
+pub(super) struct PluginSystems {
+    pub schedule: Vec<EntryKey>,
+    pub fixed_schedule: Vec<EntryKey>,
+}
+
+impl PluginSystems {
+    /// The systems added to the schedules since `next_keys` was called.
+    pub fn added_since<S>(
+        next_keys: (EntryKey, EntryKey),
+        schedule: &Scheduler<System<S>>,
+        fixed_schedule: &Scheduler<System<S>>,
+    ) -> Self {
+        PluginSystems {
+            schedule: schedule.keys_since(next_keys.0),
+            fixed_schedule: fixed_schedule.keys_since(next_keys.1),
+        }
+    }
+
+    pub fn next_keys<S>(
+        schedule: &Scheduler<System<S>>,
+        fixed_schedule: &Scheduler<System<S>>,
+    ) -> (EntryKey, EntryKey) {
+        (schedule.next_key(), fixed_schedule.next_key())
+    }
+
+    fn extend(&mut self, other: PluginSystems) {
+        self.schedule.extend(other.schedule);
+        self.fixed_schedule.extend(other.fixed_schedule);
+    }
+
+    /// Systems that were removed in the meantime are skipped.
+    pub fn remove_from<S>(
+        self,
+        schedule: &mut Scheduler<System<S>>,
+        fixed_schedule: &mut Scheduler<System<S>>,
+    ) {
+        for key in self.schedule {
+            schedule.remove(key);
+        }
+        for key in self.fixed_schedule {
+            fixed_schedule.remove(key);
+        }
+    }
+}
+
+/// Takes the place of a plugin while one of its hooks runs, see `Plugins::take_running`.
+struct Running;
+
+impl Plugin for Running {}
+
+impl Plugins {
+    pub fn len(&self) -> usize {
+        self.plugins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    pub fn contains<P: Plugin>(&self) -> bool {
+        self.contains_id(TypeId::of::<P>())
+    }
+
+    pub fn contains_id(&self, id: TypeId) -> bool {
+        self.plugins.iter().any(|e| e.id == id)
+    }
+
+    /// None if the plugin is not present, or if it is the one whose hook is currently running.
+    pub fn get<P: Plugin>(&self) -> Option<&P> {
+        let entry = self.plugins.iter().find(|e| e.id == TypeId::of::<P>())?;
+        (entry.plugin.as_ref() as &dyn Any).downcast_ref()
+    }
+
+    /// None if the plugin is not present, or if it is the one whose hook is currently running.
+    pub fn get_mut<P: Plugin>(&mut self) -> Option<&mut P> {
+        let entry = self
+            .plugins
+            .iter_mut()
+            .find(|e| e.id == TypeId::of::<P>())?;
+        (entry.plugin.as_mut() as &mut dyn Any).downcast_mut()
+    }
+
+    pub fn get_mut_by_id(&mut self, id: TypeId) -> Option<&mut Box<dyn Plugin>> {
+        self.plugins
+            .iter_mut()
+            .find(|e| e.id == id)
+            .map(|e| &mut e.plugin)
+    }
+
+    /// Errors if the plugin was already added or its dependencies are missing.
+    pub(super) fn check_can_add<P: Plugin>(&self, plugin: &P) -> anyhow::Result<()> {
+        let name = std::any::type_name::<P>();
+        if self.contains::<P>() {
+            anyhow::bail!("plugin {name} was already added");
+        }
+        let missing = plugin
+            .dependencies()
+            .into_iter()
+            .filter(|d| !self.contains_id(*d))
+            .count();
+        if missing > 0 {
+            anyhow::bail!("plugin {name} is missing {missing} of its dependencies");
+        }
+        Ok(())
+    }
+
+    pub(super) fn push<P: Plugin>(&mut self, plugin: Box<P>, systems: PluginSystems) {
+        self.plugins.push(PluginEntry {
+            id: TypeId::of::<P>(),
+            dependencies: plugin.dependencies(),
+            plugin,
+            systems,
+        });
+    }
+
+    /// Errors if the plugin is not present, other plugins depend on it, or one of its hooks is running.
+    /// Returns the plugin together with the systems it added.
+    pub(super) fn take<P: Plugin>(&mut self) -> anyhow::Result<(Box<P>, PluginSystems)> {
+        let name = std::any::type_name::<P>();
+        let id = TypeId::of::<P>();
+        let Some(index) = self.plugins.iter().position(|e| e.id == id) else {
+            anyhow::bail!("plugin {name} is not present");
+        };
+        if self.plugins.iter().any(|e| e.dependencies.contains(&id)) {
+            anyhow::bail!("plugin {name} cannot be removed, because other plugins depend on it");
+        }
+        if (self.plugins[index].plugin.as_ref() as &dyn Any).is::<Running>() {
+            anyhow::bail!("plugin {name} cannot be removed while one of its hooks is running");
+        }
+        let entry = self.plugins.remove(index);
+        let plugin: Box<dyn Any> = entry.plugin;
+        Ok((
+            plugin.downcast().expect("the type id matches"),
+            entry.systems,
+        ))
+    }
+
+    /// Removes the plugin that was added last.
+    pub(super) fn pop(&mut self) -> Option<Box<dyn Plugin>> {
+        self.plugins.pop().map(|e| e.plugin)
+    }
+
+    pub(super) fn ids(&self) -> Vec<TypeId> {
+        self.plugins.iter().map(|e| e.id).collect()
+    }
+
+    /// Takes the plugin out for one of its hooks and leaves a placeholder, see `put_back`.
+    /// None if the plugin was removed in the meantime.
+    pub(super) fn take_running(&mut self, id: TypeId) -> Option<Box<dyn Plugin>> {
+        let entry = self.plugins.iter_mut().find(|e| e.id == id)?;
+        Some(std::mem::replace(&mut entry.plugin, Box::new(Running)))
+    }
+
+    /// Only `shutdown` can remove a running plugin, then it is dropped. `systems` were added by the hook.
+    pub(super) fn put_back(&mut self, id: TypeId, plugin: Box<dyn Plugin>, systems: PluginSystems) {
+        if let Some(entry) = self.plugins.iter_mut().find(|e| e.id == id) {
+            entry.plugin = plugin;
+            entry.systems.extend(systems);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Plugin> {
+        self.plugins.iter().map(|e| e.plugin.as_ref())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Plugin>> {
+        self.plugins.iter_mut().map(|e| &mut e.plugin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    struct A;
+    impl Plugin for A {}
+
+    struct B;
+    impl Plugin for B {
+        fn dependencies(&self) -> Vec<TypeId> {
+            vec![TypeId::of::<A>()]
+        }
+    }
+
+    #[test]
+    fn running_plugin_keeps_its_place() {
+        let mut plugins = Plugins::default();
+        plugins.push(Box::new(A), PluginSystems::default());
+        plugins.push(Box::new(B), PluginSystems::default());
+
+        let running = plugins.take_running(TypeId::of::<A>()).unwrap();
+        // the others are still accessible, the running one is not.
+        assert!(plugins.get::<B>().is_some());
+        assert!(plugins.get::<A>().is_none());
+        assert!(plugins.contains::<A>());
+        // B still depends on the running A.
+        assert!(plugins.take::<A>().is_err());
+        assert!(plugins.take::<B>().is_ok());
+        plugins.put_back(TypeId::of::<A>(), running, PluginSystems::default());
+
+        assert!(plugins.get::<A>().is_some());
+        assert_eq!(plugins.ids(), vec![TypeId::of::<A>()]);
+    }
+
+    #[test]
+    fn systems_are_removed_with_their_plugin() {
+        let mut schedule: Scheduler<System<u32>> = Scheduler::new();
+        let mut fixed_schedule: Scheduler<System<u32>> = Scheduler::new();
+        schedule.add("app", Box::new(|n| *n += 1)).unwrap();
+        let mut plugins = Plugins::default();
+
+        // added in `Plugin::initialize`:
+        let next_keys = PluginSystems::next_keys(&schedule, &fixed_schedule);
+        schedule.add("a", Box::new(|n| *n += 10)).unwrap();
+        fixed_schedule
+            .add("a fixed", Box::new(|n| *n += 100))
+            .unwrap();
+        let systems = PluginSystems::added_since(next_keys, &schedule, &fixed_schedule);
+        plugins.push(Box::new(A), systems);
+
+        // added in `Plugin::begin_frame`:
+        let running = plugins.take_running(TypeId::of::<A>()).unwrap();
+        let next_keys = PluginSystems::next_keys(&schedule, &fixed_schedule);
+        schedule.add("a later", Box::new(|n| *n += 1000)).unwrap();
+        let systems = PluginSystems::added_since(next_keys, &schedule, &fixed_schedule);
+        plugins.put_back(TypeId::of::<A>(), running, systems);
+
+        let (_, systems) = plugins.take::<A>().unwrap();
+        systems.remove_from(&mut schedule, &mut fixed_schedule);
+        let mut n = 0;
+        for schedule in [&mut schedule, &mut fixed_schedule] {
+            schedule
+                .run(&mut n, Duration::ZERO, |system, n| system(n))
+                .unwrap();
+        }
+        assert_eq!(n, 1);
+    }
+}
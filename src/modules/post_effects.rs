@@ -0,0 +1,183 @@
+use std::any::TypeId;
+
+use super::Plugin;
+
+/// Identifies an effect in the `PostEffects` chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PostEffectKey {
+    Bloom,
+    /// `Plugin::post_process` of the plugin with this type id.
+    Plugin(TypeId),
+}
+
+impl PostEffectKey {
+    pub fn plugin<P: Plugin>() -> Self {
+        PostEffectKey::Plugin(TypeId::of::<P>())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PostEffect {
+    pub key: PostEffectKey,
+    /// shown in the graphics settings.
+    pub name: &'static str,
+    pub enabled: bool,
+}
+
+/// Order in which the post effects run on the hdr texture, before tone mapping.
+///
+/// Bloom comes first by default, plugins are appended when they are added and removed with them.
+/// Disabled effects stay in the chain and are skipped by the renderer.
+#[derive(Debug, Clone)]
+pub struct PostEffects {
+    effects: Vec<PostEffect>,
+}
+
+impl Default for PostEffects {
+    fn default() -> Self {
+        PostEffects {
+            effects: vec![PostEffect {
+                key: PostEffectKey::Bloom,
+                name: "Bloom",
+                enabled: true,
+            }],
+        }
+    }
+}
+
+impl PostEffects {
+    pub fn iter(&self) -> impl Iterator<Item = &PostEffect> {
+        self.effects.iter()
+    }
+
+    /// The keys of the enabled effects, in the order they run.
+    pub fn enabled(&self) -> impl Iterator<Item = PostEffectKey> + '_ {
+        self.effects.iter().filter(|e| e.enabled).map(|e| e.key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    pub fn position(&self, key: PostEffectKey) -> Option<usize> {
+        self.effects.iter().position(|e| e.key == key)
+    }
+
+    pub fn contains(&self, key: PostEffectKey) -> bool {
+        self.position(key).is_some()
+    }
+
+    pub fn is_enabled(&self, key: PostEffectKey) -> bool {
+        self.effects.iter().any(|e| e.key == key && e.enabled)
+    }
+
+    /// Returns false if the effect is not in the chain.
+    pub fn set_enabled(&mut self, key: PostEffectKey, enabled: bool) -> bool {
+        match self.effects.iter_mut().find(|e| e.key == key) {
+            Some(effect) => {
+                effect.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Inserts an enabled effect at `index` (clamped to the length). An effect that is already in the chain is
+    /// moved there instead.
+    pub fn insert(&mut self, index: usize, key: PostEffectKey, name: &'static str) {
+        if self.move_to(key, index) {
+            return;
+        }
+        let index = index.min(self.effects.len());
+        self.effects.insert(
+            index,
+            PostEffect {
+                key,
+                name,
+                enabled: true,
+            },
+        );
+    }
+
+    pub fn push(&mut self, key: PostEffectKey, name: &'static str) {
+        self.insert(usize::MAX, key, name);
+    }
+
+    pub fn remove(&mut self, key: PostEffectKey) -> Option<PostEffect> {
+        let index = self.position(key)?;
+        Some(self.effects.remove(index))
+    }
+
+    /// Moves the effect to `index` (clamped to the last index). Returns false if the effect is not in the chain.
+    pub fn move_to(&mut self, key: PostEffectKey, index: usize) -> bool {
+        let Some(old) = self.position(key) else {
+            return false;
+        };
+        let effect = self.effects.remove(old);
+        let index = index.min(self.effects.len());
+        self.effects.insert(index, effect);
+        true
+    }
+
+    /// Moves the effect such that it runs right before `other`. Returns false if one of them is not in the chain.
+    pub fn move_before(&mut self, key: PostEffectKey, other: PostEffectKey) -> bool {
+        if key == other || !self.contains(key) || !self.contains(other) {
+            return false;
+        }
+        let effect = self.effects.remove(self.position(key).unwrap());
+        let index = self.position(other).unwrap();
+        self.effects.insert(index, effect);
+        true
+    }
+
+    /// Swaps the effect with the one before it (`up == true`) or after it.
+    pub fn shift(&mut self, key: PostEffectKey, up: bool) {
+        let Some(index) = self.position(key) else {
+            return;
+        };
+        if up && index > 0 {
+            self.effects.swap(index, index - 1);
+        } else if !up && index + 1 < self.effects.len() {
+            self.effects.swap(index, index + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct A;
+    impl Plugin for A {}
+    struct B;
+    impl Plugin for B {}
+
+    #[test]
+    fn reorder_and_toggle() {
+        let mut effects = PostEffects::default();
+        effects.push(PostEffectKey::plugin::<A>(), "A");
+        effects.insert(0, PostEffectKey::plugin::<B>(), "B");
+        let keys = |e: &PostEffects| e.iter().map(|e| e.name).collect::<Vec<_>>();
+        assert_eq!(keys(&effects), ["B", "Bloom", "A"]);
+
+        assert!(effects.move_before(PostEffectKey::plugin::<A>(), PostEffectKey::Bloom));
+        assert_eq!(keys(&effects), ["B", "A", "Bloom"]);
+        effects.shift(PostEffectKey::plugin::<B>(), false);
+        assert_eq!(keys(&effects), ["A", "B", "Bloom"]);
+        assert!(effects.move_to(PostEffectKey::Bloom, 0));
+        assert_eq!(keys(&effects), ["Bloom", "A", "B"]);
+
+        effects.set_enabled(PostEffectKey::Bloom, false);
+        assert_eq!(
+            effects.enabled().collect::<Vec<_>>(),
+            [PostEffectKey::plugin::<A>(), PostEffectKey::plugin::<B>()]
+        );
+        assert!(effects.remove(PostEffectKey::plugin::<A>()).is_some());
+        assert!(!effects.set_enabled(PostEffectKey::plugin::<A>(), true));
+        assert_eq!(keys(&effects), ["Bloom", "B"]);
+    }
+}
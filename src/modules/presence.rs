@@ -0,0 +1,304 @@
+//! Rich presence (what the player is doing, shown next to their name) and achievements on gaming platforms.
+//! Only compiled with the `presence` feature.
+//!
+//! Discord is supported out of the box. Other platforms (e.g. Steamworks, which needs the Steam SDK to link) can be
+//! added by implementing `PresenceBackend`.
+
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use discord_rich_presence::{activity, DiscordIpc, DiscordIpcClient};
+
+/// What the player is doing right now, e.g. details "Forest, Level 3" and state "Fighting the boss".
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Activity {
+    pub details: String,
+    pub state: String,
+    /// Key of an image uploaded to the platform, e.g. in the Discord developer portal.
+    pub large_image: Option<String>,
+    /// Tooltip of the large image.
+    pub large_text: Option<String>,
+    /// Shows the time elapsed since then.
+    pub started: Option<SystemTime>,
+}
+
+impl Activity {
+    pub fn new(details: impl Into<String>, state: impl Into<String>) -> Self {
+        Activity {
+            details: details.into(),
+            state: state.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_large_image(mut self, key: impl Into<String>, text: impl Into<String>) -> Self {
+        self.large_image = Some(key.into());
+        self.large_text = Some(text.into());
+        self
+    }
+
+    pub fn started_now(mut self) -> Self {
+        self.started = Some(SystemTime::now());
+        self
+    }
+}
+
+pub trait PresenceBackend {
+    fn name(&self) -> &str;
+    fn set_activity(&mut self, activity: &Activity) -> anyhow::Result<()>;
+    fn clear_activity(&mut self) -> anyhow::Result<()>;
+    /// Platforms without achievements ignore this.
+    fn unlock_achievement(&mut self, _id: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Forwards the activity and achievements to all backends. The activity is sent at most once per
+/// `Presence::MIN_UPDATE_INTERVAL`, platforms rate limit it.
+///
+/// ```rust,ignore
+/// let mut presence = Presence::new().with_discord("1234567890");
+/// presence.set_activity(Activity::new("Forest", "Level 3").started_now());
+/// presence.unlock_achievement("FIRST_BOSS");
+/// // every frame:
+/// presence.update();
+/// ```
+pub struct Presence {
+    backends: Vec<Box<dyn PresenceBackend>>,
+    /// None clears the activity.
+    activity: Option<Activity>,
+    /// the activity changed since it was last sent.
+    dirty: bool,
+    last_sent: Option<Instant>,
+    unlocked: HashSet<String>,
+}
+
+impl Presence {
+    /// Discord allows 5 updates per 20 seconds.
+    pub const MIN_UPDATE_INTERVAL: Duration = Duration::from_secs(4);
+
+    pub fn new() -> Self {
+        Presence {
+            backends: vec![],
+            activity: None,
+            dirty: false,
+            last_sent: None,
+            unlocked: HashSet::new(),
+        }
+    }
+
+    pub fn with_backend(mut self, backend: impl PresenceBackend + 'static) -> Self {
+        self.backends.push(Box::new(backend));
+        self
+    }
+
+    /// `client_id` is the application id from the Discord developer portal. Does nothing if Discord is not running.
+    pub fn with_discord(self, client_id: &str) -> Self {
+        self.with_backend(DiscordPresence::new(client_id))
+    }
+
+    pub fn activity(&self) -> Option<&Activity> {
+        self.activity.as_ref()
+    }
+
+    /// Sent with the next `update` that the rate limit allows.
+    pub fn set_activity(&mut self, activity: Activity) {
+        if self.activity.as_ref() != Some(&activity) {
+            self.activity = Some(activity);
+            self.dirty = true;
+        }
+    }
+
+    pub fn clear_activity(&mut self) {
+        if self.activity.is_some() {
+            self.activity = None;
+            self.dirty = true;
+        }
+    }
+
+    /// Unlocks the achievement right away. Achievements that were unlocked before in this session are skipped.
+    pub fn unlock_achievement(&mut self, id: &str) {
+        if !self.unlocked.insert(id.to_string()) {
+            return;
+        }
+        for backend in self.backends.iter_mut() {
+            if let Err(err) = backend.unlock_achievement(id) {
+                log::error!(
+                    "could not unlock achievement {id} on {}: {err}",
+                    backend.name()
+                );
+            }
+        }
+    }
+
+    pub fn is_unlocked(&self, id: &str) -> bool {
+        self.unlocked.contains(id)
+    }
+
+    /// Sends the activity if it changed, call once per frame.
+    pub fn update(&mut self) {
+        if !self.dirty
+            || self
+                .last_sent
+                .is_some_and(|sent| sent.elapsed() < Self::MIN_UPDATE_INTERVAL)
+        {
+            return;
+        }
+        self.dirty = false;
+        self.last_sent = Some(Instant::now());
+        for backend in self.backends.iter_mut() {
+            let result = match &self.activity {
+                Some(activity) => backend.set_activity(activity),
+                None => backend.clear_activity(),
+            };
+            if let Err(err) = result {
+                log::warn!("could not update presence on {}: {err}", backend.name());
+            }
+        }
+    }
+}
+
+impl Default for Presence {
+    fn default() -> Self {
+        Presence::new()
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Discord
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Talks to the Discord client over its local ipc socket. Connects lazily and again after the connection was lost,
+/// e.g. because Discord was started after the game.
+pub struct DiscordPresence {
+    client: DiscordIpcClient,
+    connected: bool,
+}
+
+impl DiscordPresence {
+    pub fn new(client_id: &str) -> Self {
+        DiscordPresence {
+            client: DiscordIpcClient::new(client_id),
+            connected: false,
+        }
+    }
+
+    fn ensure_connected(&mut self) -> anyhow::Result<()> {
+        if !self.connected {
+            self.client.connect()?;
+            self.connected = true;
+        }
+        Ok(())
+    }
+
+    /// Retries once with a new connection, the old one could be stale.
+    fn send(
+        &mut self,
+        mut f: impl FnMut(&mut DiscordIpcClient) -> Result<(), discord_rich_presence::error::Error>,
+    ) -> anyhow::Result<()> {
+        self.ensure_connected()?;
+        if f(&mut self.client).is_ok() {
+            return Ok(());
+        }
+        self.connected = false;
+        self.ensure_connected()?;
+        f(&mut self.client)?;
+        Ok(())
+    }
+}
+
+impl PresenceBackend for DiscordPresence {
+    fn name(&self) -> &str {
+        "Discord"
+    }
+
+    fn set_activity(&mut self, activity: &Activity) -> anyhow::Result<()> {
+        self.send(|client| {
+            let mut payload = activity::Activity::new();
+            if !activity.details.is_empty() {
+                payload = payload.details(activity.details.as_str());
+            }
+            if !activity.state.is_empty() {
+                payload = payload.state(activity.state.as_str());
+            }
+            if let Some(image) = &activity.large_image {
+                let mut assets = activity::Assets::new().large_image(image.as_str());
+                if let Some(text) = &activity.large_text {
+                    assets = assets.large_text(text.as_str());
+                }
+                payload = payload.assets(assets);
+            }
+            if let Some(started) = activity.started {
+                let millis = started
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                payload = payload.timestamps(activity::Timestamps::new().start(millis as i64));
+            }
+            client.set_activity(payload)
+        })
+    }
+
+    fn clear_activity(&mut self) -> anyhow::Result<()> {
+        self.send(|client| client.clear_activity())
+    }
+}
+
+impl Drop for DiscordPresence {
+    fn drop(&mut self) {
+        if self.connected {
+            _ = self.client.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    #[derive(Default, Clone)]
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl PresenceBackend for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn set_activity(&mut self, activity: &Activity) -> anyhow::Result<()> {
+            self.0.borrow_mut().push(activity.state.clone());
+            Ok(())
+        }
+
+        fn clear_activity(&mut self) -> anyhow::Result<()> {
+            self.0.borrow_mut().push("cleared".into());
+            Ok(())
+        }
+
+        fn unlock_achievement(&mut self, id: &str) -> anyhow::Result<()> {
+            self.0.borrow_mut().push(id.into());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rate_limited_activity_and_achievements_once() {
+        let recorder = Recorder::default();
+        let mut presence = Presence::new().with_backend(recorder.clone());
+        presence.set_activity(Activity::new("Forest", "Level 1"));
+        presence.update();
+        // rate limited, only the latest activity is sent later.
+        presence.set_activity(Activity::new("Forest", "Level 2"));
+        presence.set_activity(Activity::new("Forest", "Level 3"));
+        presence.update();
+        presence.unlock_achievement("FIRST_BOSS");
+        presence.unlock_achievement("FIRST_BOSS");
+        presence.last_sent = None;
+        presence.update();
+        presence.update();
+        assert_eq!(*recorder.0.borrow(), ["Level 1", "FIRST_BOSS", "Level 3"]);
+    }
+}
@@ -0,0 +1,88 @@
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+
+/// The seeded random number generator of the engine. Use it (through the `rand::Rng` methods) instead of
+/// `rand::thread_rng` for everything that affects the simulation, such that a run can be reproduced from its seed.
+///
+/// Systems that draw a varying amount of numbers per frame can `fork` their own generator, such that they do not
+/// shift the sequence of the others.
+#[derive(Debug, Clone)]
+pub struct Random {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Seeded randomly, call `seed` to log the seed for reproducing a run.
+    pub fn from_entropy() -> Self {
+        Random::new(rand::thread_rng().gen())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Starts the sequence of `seed` from the beginning.
+    pub fn reseed(&mut self, seed: u64) {
+        *self = Random::new(seed);
+    }
+
+    /// An independent generator for `stream`, only depends on the seed and the stream, not on how many numbers were
+    /// drawn so far.
+    pub fn fork(&self, stream: u64) -> StdRng {
+        // splitmix64 of the stream, such that neighbouring streams get unrelated seeds.
+        let mut z = stream.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        StdRng::seed_from_u64(self.seed ^ z ^ (z >> 31))
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Random::from_entropy()
+    }
+}
+
+impl RngCore for Random {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Random::new(7);
+        let first: Vec<u32> = (0..8).map(|_| a.gen()).collect();
+        a.reseed(7);
+        let again: Vec<u32> = (0..8).map(|_| a.gen()).collect();
+        assert_eq!(first, again);
+
+        // forks do not depend on the numbers drawn before.
+        let fork = a.fork(1).gen::<u64>();
+        assert_eq!(Random::new(7).fork(1).gen::<u64>(), fork);
+        assert_ne!(a.fork(2).gen::<u64>(), fork);
+    }
+}
@@ -0,0 +1,521 @@
+use std::{
+    future::Future,
+    ops::Range,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use anyhow::anyhow;
+
+/// Free staging buffers that are kept around for reuse, larger ones are dropped first.
+const MAX_FREE_STAGING_BUFFERS: usize = 8;
+const MIN_STAGING_SIZE: u64 = 4096;
+
+// /////////////////////////////////////////////////////////////////////////////
+// Interface
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Copies buffers and textures from the gpu to the cpu without stalling, e.g. for picking, screenshots,
+/// histograms or results of compute shaders. Lives in `GraphicsContext::readback`.
+///
+/// The copy is recorded into an encoder and resolved a frame or two after the encoder is submitted:
+///
+/// ```rust,ignore
+/// // in prepare:
+/// self.pick = Some(ctx.readback.read_buffer(encoder, &id_buffer, 0..4));
+/// // in a later update:
+/// if let Some(Ok(bytes)) = self.pick.as_mut().and_then(|p| p.try_take()) { .. }
+/// ```
+///
+/// The handles are also futures, which can be awaited e.g. on the tokio runtime.
+/// Whoever submits the encoder calls `Readback::submitted` afterwards and `Readback::poll` every frame,
+/// the `DefaultModules` do both for the frame encoder and poll again in `begin_frame`.
+pub struct Readback {
+    device: Arc<wgpu::Device>,
+    state: Mutex<ReadbackState>,
+}
+
+impl std::fmt::Debug for Readback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("Readback")
+            .field("recorded", &state.recorded.len())
+            .field("in_flight", &state.in_flight.len())
+            .field("free_staging_buffers", &state.free.len())
+            .finish()
+    }
+}
+
+/// Resolves to the copied data, see `Readback`.
+pub struct ReadbackHandle<T> {
+    slot: Arc<Mutex<Slot>>,
+    convert: Option<Box<dyn FnOnce(Vec<u8>) -> T + Send>>,
+}
+
+/// Pixels of a texture, rows are tightly packed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureReadback {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub data: Vec<u8>,
+}
+
+impl TextureReadback {
+    pub fn bytes_per_pixel(&self) -> u32 {
+        self.data.len() as u32 / (self.width * self.height).max(1)
+    }
+
+    /// The bytes of the pixel at `x`, `y`.
+    pub fn pixel(&self, x: u32, y: u32) -> &[u8] {
+        let bpp = self.bytes_per_pixel() as usize;
+        let start = (y * self.width + x) as usize * bpp;
+        &self.data[start..start + bpp]
+    }
+
+    /// None for formats other than `Rgba8Unorm(Srgb)` and `Bgra8Unorm(Srgb)`, bgra is swizzled to rgba.
+    pub fn to_rgba_image(&self) -> Option<image::RgbaImage> {
+        use wgpu::TextureFormat as F;
+        let mut data = self.data.clone();
+        match self.format {
+            F::Rgba8Unorm | F::Rgba8UnormSrgb => {}
+            F::Bgra8Unorm | F::Bgra8UnormSrgb => {
+                for pixel in data.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+            _ => return None,
+        }
+        image::RgbaImage::from_raw(self.width, self.height, data)
+    }
+}
+
+impl<T> ReadbackHandle<T> {
+    /// A handle that is resolved with `err` already, for copies that cannot be recorded.
+    fn failed(err: anyhow::Error) -> Self {
+        let mut slot = Slot::default();
+        slot.resolve(Err(err));
+        ReadbackHandle {
+            slot: Arc::new(Mutex::new(slot)),
+            convert: None,
+        }
+    }
+
+    /// The result once the copy is mapped, afterwards None again.
+    pub fn try_take(&mut self) -> Option<anyhow::Result<T>> {
+        let result = self.slot.lock().unwrap().result.take()?;
+        match result {
+            Ok(bytes) => Some(Ok((self.convert.take()?)(bytes))),
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.slot.lock().unwrap().result.is_some()
+    }
+}
+
+impl<T> Future for ReadbackHandle<T> {
+    type Output = anyhow::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Some(result) = self.try_take() {
+            return Poll::Ready(result);
+        }
+        self.slot.lock().unwrap().waker = Some(cx.waker().clone());
+        // resolved between the check and storing the waker.
+        match self.try_take() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Readback {
+    pub fn new(device: Arc<wgpu::Device>) -> Self {
+        Readback {
+            device,
+            state: Mutex::new(ReadbackState::default()),
+        }
+    }
+
+    /// Copies `range` of the buffer, which needs `BufferUsages::COPY_SRC`. Start and length need to be multiples of 4,
+    /// otherwise (or for an empty range) the handle resolves with an error right away.
+    pub fn read_buffer(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer,
+        range: Range<u64>,
+    ) -> ReadbackHandle<Vec<u8>> {
+        let size = match check_buffer_range(buffer.size(), &range) {
+            Ok(size) => size,
+            Err(err) => return ReadbackHandle::failed(err),
+        };
+        let staging = self.staging_buffer(size);
+        encoder.copy_buffer_to_buffer(buffer, range.start, &staging, 0, size);
+        self.record(staging, size, None, Box::new(|bytes| bytes))
+    }
+
+    /// Like `read_buffer`, but casts the bytes to `T`, e.g. for results of compute shaders.
+    pub fn read_buffer_as<T: bytemuck::Pod>(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer,
+        range: Range<u64>,
+    ) -> ReadbackHandle<Vec<T>> {
+        let size = match check_buffer_range(buffer.size(), &range) {
+            Ok(size) => size,
+            Err(err) => return ReadbackHandle::failed(err),
+        };
+        let staging = self.staging_buffer(size);
+        encoder.copy_buffer_to_buffer(buffer, range.start, &staging, 0, size);
+        self.record(
+            staging,
+            size,
+            None,
+            Box::new(|bytes| bytemuck::pod_collect_to_vec(&bytes)),
+        )
+    }
+
+    /// Copies a region of a mip level of the texture, which needs `TextureUsages::COPY_SRC`.
+    /// Not supported for depth-stencil formats with both aspects and compressed formats, for those and for empty
+    /// regions the handle resolves with an error right away.
+    pub fn read_texture(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: wgpu::ImageCopyTexture,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> ReadbackHandle<TextureReadback> {
+        if width == 0 || height == 0 {
+            return ReadbackHandle::failed(anyhow!(
+                "cannot read back an empty texture region of {width}x{height}"
+            ));
+        }
+        let Some(layout) = RowLayout::new(format, width, height) else {
+            return ReadbackHandle::failed(anyhow!(
+                "cannot read back textures with format {format:?}"
+            ));
+        };
+        let staging = self.staging_buffer(layout.padded_size());
+        encoder.copy_texture_to_buffer(
+            texture,
+            wgpu::ImageCopyBuffer {
+                buffer: &staging,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(layout.padded_row_bytes),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.record(
+            staging,
+            layout.padded_size(),
+            Some(layout),
+            Box::new(move |data| TextureReadback {
+                width,
+                height,
+                format,
+                data,
+            }),
+        )
+    }
+
+    /// Shorthand for the whole first mip level of a texture.
+    pub fn read_whole_texture(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) -> ReadbackHandle<TextureReadback> {
+        self.read_texture(
+            encoder,
+            texture.as_image_copy(),
+            texture.format(),
+            texture.width(),
+            texture.height(),
+        )
+    }
+
+    /// Starts mapping the copies recorded since the last call. Call it right after submitting the encoders they were
+    /// recorded into, mapping a buffer before its copy is submitted is an error.
+    pub fn submitted(&self) {
+        let mut state = self.state.lock().unwrap();
+        let recorded = std::mem::take(&mut state.recorded);
+        for request in recorded {
+            let mapped = request.mapped.clone();
+            request
+                .staging
+                .slice(..request.size)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    *mapped.lock().unwrap() = Some(result);
+                });
+            state.in_flight.push(request);
+        }
+    }
+
+    /// Resolves the copies recorded since the last `submitted` with an error, if their encoder was dropped
+    /// instead of submitted, e.g. when a frame is skipped.
+    pub fn cancel_recorded(&self) {
+        let mut state = self.state.lock().unwrap();
+        let recorded = std::mem::take(&mut state.recorded);
+        for request in recorded {
+            request.slot.lock().unwrap().resolve(Err(anyhow!(
+                "readback cancelled, the encoder was not submitted"
+            )));
+            state.release(request.staging);
+        }
+    }
+
+    /// Resolves the handles of the copies that are mapped by now, without waiting for the gpu.
+    pub fn poll(&self) {
+        self.device.poll(wgpu::Maintain::Poll);
+        self.resolve_mapped();
+    }
+
+    /// Blocks until all submitted copies are done, e.g. for screenshots in tests or tools.
+    pub fn wait(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
+        self.resolve_mapped();
+    }
+
+    /// Copies that were recorded or submitted but are not resolved yet.
+    pub fn pending(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.recorded.len() + state.in_flight.len()
+    }
+
+    fn record<T>(
+        &self,
+        staging: wgpu::Buffer,
+        size: u64,
+        rows: Option<RowLayout>,
+        convert: Box<dyn FnOnce(Vec<u8>) -> T + Send>,
+    ) -> ReadbackHandle<T> {
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        self.state.lock().unwrap().recorded.push(Request {
+            staging,
+            size,
+            rows,
+            mapped: Arc::new(Mutex::new(None)),
+            slot: slot.clone(),
+        });
+        ReadbackHandle {
+            slot,
+            convert: Some(convert),
+        }
+    }
+
+    /// The smallest free staging buffer that fits, or a new one with a power of two size.
+    fn staging_buffer(&self, size: u64) -> wgpu::Buffer {
+        let mut state = self.state.lock().unwrap();
+        let best = state
+            .free
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.size() >= size)
+            .min_by_key(|(_, b)| b.size())
+            .map(|(i, _)| i);
+        if let Some(i) = best {
+            return state.free.swap_remove(i);
+        }
+        self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Staging Buffer"),
+            size: staging_size(size),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn resolve_mapped(&self) {
+        let mut state = self.state.lock().unwrap();
+        let in_flight = std::mem::take(&mut state.in_flight);
+        for request in in_flight {
+            let mapped = request.mapped.lock().unwrap().take();
+            match mapped {
+                None => state.in_flight.push(request),
+                Some(Ok(())) => {
+                    let view = request.staging.slice(..request.size).get_mapped_range();
+                    let data = match &request.rows {
+                        Some(rows) => rows.unpad(&view),
+                        None => view.to_vec(),
+                    };
+                    drop(view);
+                    request.staging.unmap();
+                    request.slot.lock().unwrap().resolve(Ok(data));
+                    state.release(request.staging);
+                }
+                Some(Err(err)) => {
+                    request
+                        .slot
+                        .lock()
+                        .unwrap()
+                        .resolve(Err(anyhow!("cannot map readback buffer: {err}")));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Readback {
+    /// Pending handles resolve with an error, e.g. when the device is recreated.
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap();
+        for request in state.recorded.drain(..).chain(state.in_flight.drain(..)) {
+            request
+                .slot
+                .lock()
+                .unwrap()
+                .resolve(Err(anyhow!("readback cancelled, the device was recreated")));
+        }
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Staging
+// /////////////////////////////////////////////////////////////////////////////
+
+#[derive(Default)]
+struct ReadbackState {
+    /// copies in an encoder that is not submitted yet.
+    recorded: Vec<Request>,
+    /// copies that are being mapped.
+    in_flight: Vec<Request>,
+    /// staging buffers for reuse, unmapped.
+    free: Vec<wgpu::Buffer>,
+}
+
+impl ReadbackState {
+    fn release(&mut self, staging: wgpu::Buffer) {
+        self.free.push(staging);
+        if self.free.len() > MAX_FREE_STAGING_BUFFERS {
+            let largest = (0..self.free.len())
+                .max_by_key(|i| self.free[*i].size())
+                .unwrap();
+            self.free.swap_remove(largest);
+        }
+    }
+}
+
+struct Request {
+    staging: wgpu::Buffer,
+    /// bytes copied into the staging buffer, which might be larger.
+    size: u64,
+    /// Some for textures, whose rows are padded in the staging buffer.
+    rows: Option<RowLayout>,
+    mapped: Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>,
+    slot: Arc<Mutex<Slot>>,
+}
+
+#[derive(Default)]
+struct Slot {
+    result: Option<anyhow::Result<Vec<u8>>>,
+    waker: Option<Waker>,
+}
+
+impl Slot {
+    fn resolve(&mut self, result: anyhow::Result<Vec<u8>>) {
+        self.result = Some(result);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The size of a copy of `range` from a buffer of `buffer_size` bytes, if wgpu can copy it.
+fn check_buffer_range(buffer_size: u64, range: &Range<u64>) -> anyhow::Result<u64> {
+    if range.start >= range.end {
+        anyhow::bail!("cannot read back the empty buffer range {range:?}");
+    }
+    if range.end > buffer_size {
+        anyhow::bail!("range {range:?} is out of the buffer of {buffer_size} bytes");
+    }
+    let size = range.end - range.start;
+    let align = wgpu::COPY_BUFFER_ALIGNMENT;
+    if !range.start.is_multiple_of(align) || !size.is_multiple_of(align) {
+        anyhow::bail!("start and length of range {range:?} need to be multiples of 4");
+    }
+    Ok(size)
+}
+
+fn staging_size(size: u64) -> u64 {
+    size.next_power_of_two().max(MIN_STAGING_SIZE)
+}
+
+/// Rows of a texture to buffer copy need to be aligned to `COPY_BYTES_PER_ROW_ALIGNMENT`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RowLayout {
+    row_bytes: u32,
+    padded_row_bytes: u32,
+    height: u32,
+}
+
+impl RowLayout {
+    /// None for formats without a fixed block size per aspect.
+    fn new(format: wgpu::TextureFormat, width: u32, height: u32) -> Option<Self> {
+        let bytes_per_pixel = format
+            .block_size(Some(wgpu::TextureAspect::All))
+            .or_else(|| format.block_size(Some(wgpu::TextureAspect::DepthOnly)))?;
+        let row_bytes = width * bytes_per_pixel;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        Some(RowLayout {
+            row_bytes,
+            padded_row_bytes: row_bytes.div_ceil(align) * align,
+            height,
+        })
+    }
+
+    fn padded_size(&self) -> u64 {
+        self.padded_row_bytes as u64 * self.height as u64
+    }
+
+    fn unpad(&self, padded: &[u8]) -> Vec<u8> {
+        let mut data = Vec::with_capacity((self.row_bytes * self.height) as usize);
+        for row in padded.chunks(self.padded_row_bytes as usize) {
+            data.extend_from_slice(&row[..self.row_bytes as usize]);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texture_rows_are_unpadded() {
+        let rows = RowLayout::new(wgpu::TextureFormat::Rgba8Unorm, 3, 2).unwrap();
+        assert_eq!((rows.row_bytes, rows.padded_row_bytes), (12, 256));
+        let mut padded = vec![0u8; rows.padded_size() as usize];
+        padded[..12].fill(1);
+        padded[256..268].fill(2);
+        let data = rows.unpad(&padded);
+        assert_eq!(data.len(), 24);
+        assert!(data[..12].iter().all(|b| *b == 1) && data[12..].iter().all(|b| *b == 2));
+
+        assert_eq!(staging_size(4), MIN_STAGING_SIZE);
+        assert_eq!(staging_size(5000), 8192);
+    }
+
+    #[test]
+    fn invalid_copies_fail_up_front() {
+        assert_eq!(check_buffer_range(64, &(8..24)).unwrap(), 16);
+        assert!(check_buffer_range(64, &(8..8)).is_err());
+        assert!(check_buffer_range(64, &(8..4)).is_err());
+        assert!(check_buffer_range(64, &(60..68)).is_err());
+        assert!(check_buffer_range(64, &(2..10)).is_err());
+        assert!(RowLayout::new(wgpu::TextureFormat::Depth24PlusStencil8, 4, 4).is_none());
+
+        let mut handle = ReadbackHandle::<Vec<u8>>::failed(anyhow!("empty"));
+        assert!(handle.is_ready());
+        assert!(handle.try_take().unwrap().is_err());
+        assert!(handle.try_take().is_none());
+    }
+}
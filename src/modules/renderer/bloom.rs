@@ -1,9 +1,13 @@
-use std::{borrow::Cow, sync::Arc};
+use std::sync::Arc;
 
-use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState, Device};
+use image::RgbaImage;
+use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState, Device, ShaderStages};
 
 use crate::{
-    elements::{texture::rgba_bind_group_layout, ScreenGR},
+    elements::{
+        texture::{create_white_px_texture, rgba_bind_group_layout},
+        BindableTexture, ScreenGR, Texture,
+    },
     modules::{
         renderer::{screen_textures::HdrTexture, HDR_COLOR_FORMAT},
         GraphicsContext,
@@ -11,12 +15,53 @@ use crate::{
     Resize,
 };
 
-use super::ScreenVertexShader;
+use super::{PushConstantBlock, ScreenVertexShader};
+
+/// Bloom downsamples at most this often, down to 1/512 of the screen size.
+pub const MAX_BLOOM_MIPS: usize = 9;
+
+/// Presets for `BloomSettings::mip_count`. Fewer mips are cheaper, but the bloom does not spread as far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BloomQuality {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+impl BloomQuality {
+    pub const ALL: [BloomQuality; 4] = [
+        BloomQuality::Low,
+        BloomQuality::Medium,
+        BloomQuality::High,
+        BloomQuality::Ultra,
+    ];
+
+    pub fn mip_count(self) -> usize {
+        match self {
+            BloomQuality::Low => 4,
+            BloomQuality::Medium => 6,
+            BloomQuality::High => 8,
+            BloomQuality::Ultra => MAX_BLOOM_MIPS,
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct BloomSettings {
     pub activated: bool,
     pub blend_factor: f64,
+    /// number of downsampled textures (1/2, 1/4, ...), see `BloomQuality`. Clamped to `MAX_BLOOM_MIPS`
+    /// and to what the screen size allows.
+    pub mip_count: usize,
+    /// brightness above which pixels bloom.
+    pub threshold: f32,
+    /// softness of the threshold, as a fraction of it: 0 is a hard cut, 1 fades the bloom in from black.
+    pub knee: f32,
+    /// multiplies each mip when it is added to the next larger one, index 0 is the 1/2 mip.
+    pub mip_intensities: [f64; MAX_BLOOM_MIPS],
+    /// how much the lens dirt texture (see `Bloom::set_lens_dirt`) brightens the bloom, 0 disables it.
+    pub lens_dirt_intensity: f32,
 }
 
 impl Default for BloomSettings {
@@ -24,10 +69,28 @@ impl Default for BloomSettings {
         Self {
             activated: true,
             blend_factor: 0.10,
+            mip_count: BloomQuality::Ultra.mip_count(),
+            threshold: 0.5,
+            knee: 0.0,
+            mip_intensities: [1.0; MAX_BLOOM_MIPS],
+            lens_dirt_intensity: 0.0,
         }
     }
 }
 
+impl BloomSettings {
+    pub fn set_quality(&mut self, quality: BloomQuality) {
+        self.mip_count = quality.mip_count();
+    }
+
+    /// The preset with the same mip count, if any.
+    pub fn quality(&self) -> Option<BloomQuality> {
+        BloomQuality::ALL
+            .into_iter()
+            .find(|q| q.mip_count() == self.mip_count)
+    }
+}
+
 /// The input to the BloomPipeline is an HDR texture A that has a bindgroup.
 /// We need to be able to use this texture A as a render attachment.
 /// The steps this bloom pipeline takes, each bullet point is one render pass:
@@ -41,19 +104,28 @@ impl Default for BloomSettings {
 /// - downsample B2 store the result in B3
 /// - downsample B3 store the result in B4
 ///
+/// (4 mips here, `BloomSettings::mip_count` decides how many)
+///
 /// note: we need to be able to use B1..BX as bindgroups of textures, to sample them in fragment shaders.
 /// # 2. Upsampling:
 ///
 /// - upsample B4 and add it to B3
 /// - upsample B3 and add it to B2
 /// - upsample B2 and add it to B1
-/// - upsample B1 and add it to the original HDR image A.
+/// - upsample B1 and add it to the original HDR image A, multiplied with the lens dirt texture.
+///
+/// Each upsampled mip is weighted by its `BloomSettings::mip_intensities`.
 ///
 /// This should result in a bloom.
 pub struct Bloom {
     bloom_textures: BloomTextures,
     bloom_pipelines: BloomPipelines,
+    push_constants: PushConstantBlock<PushConstants>,
     settings: BloomSettings,
+    /// white if no lens dirt is set.
+    lens_dirt: BindableTexture,
+    /// kept to upload the lens dirt again after a device loss.
+    lens_dirt_image: Option<RgbaImage>,
     device: Arc<Device>,
 }
 
@@ -65,25 +137,66 @@ impl Bloom {
     ) -> Self {
         let width = ctx.surface_config.width;
         let height = ctx.surface_config.height;
-        let bloom_textures = BloomTextures::create(&ctx.device, width, height);
+        let bloom_textures = BloomTextures::new(width, height);
+        // after the screen, input and lens dirt bind groups.
+        let push_constants = PushConstantBlock::new(
+            &ctx.device,
+            ctx.pipeline_settings(),
+            ShaderStages::FRAGMENT,
+            3,
+            bytemuck::Zeroable::zeroed(),
+        );
         let bloom_pipelines = BloomPipelines::new(
             include_str!("bloom.wgsl"),
             &ctx.device,
             screen_vertex_shader,
             screen,
+            &push_constants,
         );
 
         Bloom {
             bloom_textures,
             bloom_pipelines,
+            push_constants,
             settings: Default::default(),
+            lens_dirt: create_white_px_texture(&ctx.device, &ctx.queue),
+            lens_dirt_image: None,
             device: ctx.device.clone(),
         }
     }
 
+    /// The texture is stretched over the screen and multiplied with the bloom, see `BloomSettings::lens_dirt_intensity`.
+    /// None removes it.
+    pub fn set_lens_dirt(&mut self, ctx: &GraphicsContext, lens_dirt: Option<RgbaImage>) {
+        self.lens_dirt = match &lens_dirt {
+            Some(image) => {
+                let texture = Texture::from_image(&ctx.device, &ctx.queue, image);
+                BindableTexture::new(&ctx.device, texture)
+            }
+            None => create_white_px_texture(&ctx.device, &ctx.queue),
+        };
+        self.lens_dirt_image = lens_dirt;
+    }
+
+    pub fn lens_dirt(&self) -> Option<&RgbaImage> {
+        self.lens_dirt_image.as_ref()
+    }
+
+    /// Takes the lens dirt out, e.g. to set it on a new `Bloom` after a device loss.
+    pub fn take_lens_dirt(&mut self) -> Option<RgbaImage> {
+        self.lens_dirt_image.take()
+    }
+
+    /// The number of mips that is actually used, see `BloomSettings::mip_count`.
+    pub fn mip_count(&self) -> usize {
+        let max = max_mip_count(self.bloom_textures.width, self.bloom_textures.height);
+        self.settings.mip_count.clamp(1, max)
+    }
+
     pub fn apply<'e>(
         &'e mut self,
         encoder: &'e mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
         input_texture: &wgpu::BindGroup,
         output_texture: &wgpu::TextureView,
         screen: &ScreenGR,
@@ -91,34 +204,61 @@ impl Bloom {
         if !self.settings.activated {
             return;
         }
-
-        fn run_screen_render_pass<'e>(
-            label: &str,
-            encoder: &'e mut wgpu::CommandEncoder,
-            input_texture: &'e wgpu::BindGroup,
-            output_texture: &'e wgpu::TextureView,
-            screen: &'e ScreenGR,
-            pipeline: &'e wgpu::RenderPipeline,
-        ) {
-            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some(label),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: output_texture,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-            pass.set_pipeline(pipeline);
-            pass.set_bind_group(0, screen.bind_group(), &[]);
-            pass.set_bind_group(1, input_texture, &[]);
-            pass.draw(0..3, 0..1);
-        }
+        let mip_count = self.mip_count();
+        self.bloom_textures.ensure_mips(&self.device, mip_count);
+
+        self.push_constants.update(
+            PushConstants {
+                threshold: self.settings.threshold,
+                knee: self.settings.knee,
+                lens_dirt_intensity: self.settings.lens_dirt_intensity,
+                _pad: 0.0,
+            },
+            queue,
+        );
+        let push_constants = &self.push_constants;
+        // only sampled in the final pass, but bound in all of them, such that they share a pipeline layout.
+        let lens_dirt = &self.lens_dirt.bind_group;
+
+        let run_screen_render_pass =
+            |label: &str,
+             encoder: &mut wgpu::CommandEncoder,
+             input_texture: &wgpu::BindGroup,
+             output_texture: &wgpu::TextureView,
+             pipeline: &wgpu::RenderPipeline,
+             blend_factor: Option<f64>| {
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(label),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: output_texture,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                pass.set_pipeline(pipeline);
+                if let Some(blend_factor) = blend_factor {
+                    pass.set_blend_constant(wgpu::Color {
+                        r: blend_factor,
+                        g: blend_factor,
+                        b: blend_factor,
+                        a: blend_factor,
+                    });
+                }
+                push_constants.set(&mut pass);
+                pass.set_bind_group(0, screen.bind_group(), &[]);
+                pass.set_bind_group(1, input_texture, &[]);
+                pass.set_bind_group(2, lens_dirt, &[]);
+                pass.draw(0..3, 0..1);
+            };
+
+        let mips = &self.bloom_textures.mips;
+        let pipelines = &self.bloom_pipelines;
 
         // /////////////////////////////////////////////////////////////////////////////
         // downsample
@@ -128,187 +268,48 @@ impl Bloom {
             "1 -> 1/2 downsample and threshold",
             encoder,
             input_texture,
-            self.bloom_textures.b2.view(),
-            screen,
-            &self.bloom_pipelines.downsample_threshold_pipeline,
-        );
-        run_screen_render_pass(
-            "1/2 -> 1/4 downsample",
-            encoder,
-            self.bloom_textures.b2.bind_group(),
-            self.bloom_textures.b4.view(),
-            screen,
-            &self.bloom_pipelines.downsample_pipeline,
-        );
-        run_screen_render_pass(
-            "1/4 -> 1/8 downsample",
-            encoder,
-            self.bloom_textures.b4.bind_group(),
-            self.bloom_textures.b8.view(),
-            screen,
-            &self.bloom_pipelines.downsample_pipeline,
-        );
-        run_screen_render_pass(
-            "1/8 -> 1/16 downsample",
-            encoder,
-            self.bloom_textures.b8.bind_group(),
-            self.bloom_textures.b16.view(),
-            screen,
-            &self.bloom_pipelines.downsample_pipeline,
-        );
-
-        run_screen_render_pass(
-            "1/16 -> 1/32 downsample",
-            encoder,
-            self.bloom_textures.b16.bind_group(),
-            self.bloom_textures.b32.view(),
-            screen,
-            &self.bloom_pipelines.downsample_pipeline,
-        );
-
-        run_screen_render_pass(
-            "1/32 -> 1/64 downsample",
-            encoder,
-            self.bloom_textures.b32.bind_group(),
-            self.bloom_textures.b64.view(),
-            screen,
-            &self.bloom_pipelines.downsample_pipeline,
-        );
-
-        run_screen_render_pass(
-            "1/64 -> 1/128 downsample",
-            encoder,
-            self.bloom_textures.b64.bind_group(),
-            self.bloom_textures.b128.view(),
-            screen,
-            &self.bloom_pipelines.downsample_pipeline,
-        );
-
-        run_screen_render_pass(
-            "1/128 -> 1/256 downsample",
-            encoder,
-            self.bloom_textures.b128.bind_group(),
-            self.bloom_textures.b256.view(),
-            screen,
-            &self.bloom_pipelines.downsample_pipeline,
-        );
-
-        run_screen_render_pass(
-            "1/256 -> 1/512 downsample",
-            encoder,
-            self.bloom_textures.b256.bind_group(),
-            self.bloom_textures.b512.view(),
-            screen,
-            &self.bloom_pipelines.downsample_pipeline,
+            mips[0].view(),
+            &pipelines.downsample_threshold_pipeline,
+            None,
         );
+        for i in 1..mip_count {
+            run_screen_render_pass(
+                &format!("1/{} -> 1/{} downsample", 1 << i, 1 << (i + 1)),
+                encoder,
+                mips[i - 1].bind_group(),
+                mips[i].view(),
+                &pipelines.downsample_pipeline,
+                None,
+            );
+        }
 
         // /////////////////////////////////////////////////////////////////////////////
         // upsample
         // /////////////////////////////////////////////////////////////////////////////
 
-        run_screen_render_pass(
-            "1/512 -> 1/256 upsample and add",
-            encoder,
-            self.bloom_textures.b512.bind_group(),
-            self.bloom_textures.b256.view(),
-            screen,
-            &self.bloom_pipelines.upsample_pipeline,
-        );
-
-        run_screen_render_pass(
-            "1/256 -> 1/128 upsample and add",
-            encoder,
-            self.bloom_textures.b256.bind_group(),
-            self.bloom_textures.b128.view(),
-            screen,
-            &self.bloom_pipelines.upsample_pipeline,
-        );
-
-        run_screen_render_pass(
-            "1/128 -> 1/64 upsample and add",
-            encoder,
-            self.bloom_textures.b128.bind_group(),
-            self.bloom_textures.b64.view(),
-            screen,
-            &self.bloom_pipelines.upsample_pipeline,
-        );
-
-        run_screen_render_pass(
-            "1/64 -> 1/32 upsample and add",
-            encoder,
-            self.bloom_textures.b64.bind_group(),
-            self.bloom_textures.b32.view(),
-            screen,
-            &self.bloom_pipelines.upsample_pipeline,
-        );
-
-        run_screen_render_pass(
-            "1/32 -> 1/16 upsample and add",
-            encoder,
-            self.bloom_textures.b32.bind_group(),
-            self.bloom_textures.b16.view(),
-            screen,
-            &self.bloom_pipelines.upsample_pipeline,
-        );
-
-        run_screen_render_pass(
-            "1/16 -> 1/8 upsample and add",
-            encoder,
-            self.bloom_textures.b16.bind_group(),
-            self.bloom_textures.b8.view(),
-            screen,
-            &self.bloom_pipelines.upsample_pipeline,
-        );
-
-        run_screen_render_pass(
-            "1/8 -> 1/4 upsample and add",
-            encoder,
-            self.bloom_textures.b8.bind_group(),
-            self.bloom_textures.b4.view(),
-            screen,
-            &self.bloom_pipelines.upsample_pipeline,
-        );
-
-        run_screen_render_pass(
-            "1/4 -> 1/2 upsample and add",
-            encoder,
-            self.bloom_textures.b4.bind_group(),
-            self.bloom_textures.b2.view(),
-            screen,
-            &self.bloom_pipelines.upsample_pipeline,
-        );
+        for i in (1..mip_count).rev() {
+            run_screen_render_pass(
+                &format!("1/{} -> 1/{} upsample and add", 1 << (i + 1), 1 << i),
+                encoder,
+                mips[i].bind_group(),
+                mips[i - 1].view(),
+                &pipelines.upsample_pipeline,
+                Some(self.settings.mip_intensities[i]),
+            );
+        }
 
         // /////////////////////////////////////////////////////////////////////////////
         // Final pass, now with blend factor to add to original image
         // /////////////////////////////////////////////////////////////////////////////
 
-        let blend_factor = self.settings.blend_factor;
-        let blend_factor = wgpu::Color {
-            r: blend_factor,
-            g: blend_factor,
-            b: blend_factor,
-            a: blend_factor,
-        };
-
-        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("1/2 -> 1 upsample and add"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: output_texture,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
-        pass.set_pipeline(&self.bloom_pipelines.final_upsample_pipeline);
-        pass.set_blend_constant(blend_factor);
-        pass.set_bind_group(0, screen.bind_group(), &[]);
-        pass.set_bind_group(1, self.bloom_textures.b2.bind_group(), &[]);
-        pass.draw(0..3, 0..1);
+        run_screen_render_pass(
+            "1/2 -> 1 upsample and add",
+            encoder,
+            mips[0].bind_group(),
+            output_texture,
+            &pipelines.final_upsample_pipeline,
+            Some(self.settings.blend_factor * self.settings.mip_intensities[0]),
+        );
     }
 }
 
@@ -321,10 +322,10 @@ impl Bloom {
 impl Resize for Bloom {
     /// make sure this is called after graphics context is reconfigured (to match the ctx configs size)
     fn resize(&mut self, resized: crate::Resized) {
-        // recreate the textures on the gpu with the appropriate sizes
+        // recreate the textures on the gpu with the appropriate sizes, only the mips in use.
         let width = resized.new_size.width;
         let height = resized.new_size.height;
-        self.bloom_textures = BloomTextures::create(&self.device, width, height);
+        self.bloom_textures.resize(&self.device, width, height);
     }
 }
 
@@ -341,25 +342,32 @@ impl BloomPipelines {
         device: &wgpu::Device,
         screen_vertex_shader: &ScreenVertexShader,
         screen: &ScreenGR,
+        push_constants: &PushConstantBlock<PushConstants>,
     ) -> Self {
+        // the lens dirt texture is only sampled by the final pass.
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[screen.bind_group_layout(), rgba_bind_group_layout(device)],
-            push_constant_ranges: &[],
+            bind_group_layouts: &push_constants.bind_group_layouts(&[
+                screen.bind_group_layout(),
+                &rgba_bind_group_layout(device),
+                &rgba_bind_group_layout(device),
+            ]),
+            push_constant_ranges: &push_constants.push_constant_ranges(),
         });
 
         let fragment_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Bloom Shader"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(shader_wgsl)),
+            source: wgpu::ShaderSource::Wgsl(push_constants.wgsl(shader_wgsl)),
         });
 
         let create_pipeline = |label: &str,
+                               layout: &wgpu::PipelineLayout,
                                entry_point: &str,
                                blend: Option<wgpu::BlendState>|
          -> wgpu::RenderPipeline {
             device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                 label: Some(label),
-                layout: Some(&pipeline_layout),
+                layout: Some(layout),
                 vertex: screen_vertex_shader.vertex_state(),
                 fragment: Some(wgpu::FragmentState {
                     module: &fragment_shader,
@@ -385,13 +393,19 @@ impl BloomPipelines {
             })
         };
 
-        let downsample_threshold_pipeline =
-            create_pipeline("Downsample Threshold", "threshold_downsample", None);
-        let downsample_pipeline = create_pipeline("Downsample", "downsample", None);
+        let downsample_threshold_pipeline = create_pipeline(
+            "Downsample Threshold",
+            &pipeline_layout,
+            "threshold_downsample",
+            None,
+        );
+        let downsample_pipeline =
+            create_pipeline("Downsample", &pipeline_layout, "downsample", None);
 
+        // the constant is the intensity of the mip.
         let up_blend_state = Some(BlendState {
             color: BlendComponent {
-                src_factor: BlendFactor::One,
+                src_factor: BlendFactor::Constant,
                 dst_factor: BlendFactor::One,
                 operation: BlendOperation::Add,
             },
@@ -407,10 +421,15 @@ impl BloomPipelines {
             alpha: BlendComponent::OVER,
         });
 
-        let upsample_pipeline = create_pipeline("Bloom shader", "upsample", up_blend_state);
-        // only differs from upsample pipeline in the use of a constant for blending it back into the orginial image (the render target of this pipeline)
-        let final_upsample_pipeline =
-            create_pipeline("Bloom shader", "upsample", final_up_blend_state);
+        let upsample_pipeline =
+            create_pipeline("Bloom shader", &pipeline_layout, "upsample", up_blend_state);
+        // differs from upsample pipeline in the lens dirt, and the blend factor is the settings blend factor.
+        let final_upsample_pipeline = create_pipeline(
+            "Bloom shader",
+            &pipeline_layout,
+            "final_upsample",
+            final_up_blend_state,
+        );
 
         Self {
             downsample_threshold_pipeline,
@@ -421,40 +440,60 @@ impl BloomPipelines {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Zeroable, bytemuck::Pod)]
+struct PushConstants {
+    threshold: f32,
+    knee: f32,
+    lens_dirt_intensity: f32,
+    _pad: f32,
+}
+
+/// How many mips fit into a screen of this size, such that the smallest one is still at least 1 pixel.
+fn max_mip_count(width: u32, height: u32) -> usize {
+    let min_side = width.min(height).max(2);
+    (min_side.ilog2() as usize).min(MAX_BLOOM_MIPS)
+}
+
+/// mips[0] has 1/2 the screen size, mips[1] 1/4 and so on. Only as many mips as used are created.
 pub struct BloomTextures {
-    b2: HdrTexture,
-    b4: HdrTexture,
-    b8: HdrTexture,
-    b16: HdrTexture,
-    b32: HdrTexture,
-    b64: HdrTexture,
-    b128: HdrTexture,
-    b256: HdrTexture,
-    b512: HdrTexture,
+    mips: Vec<HdrTexture>,
+    width: u32,
+    height: u32,
 }
 
 impl BloomTextures {
-    pub fn create(device: &wgpu::Device, width: u32, height: u32) -> Self {
-        let b2 = HdrTexture::create(device, width / 2, height / 2, 1, "b2");
-        let b4 = HdrTexture::create(device, width / 4, height / 4, 1, "b4");
-        let b8 = HdrTexture::create(device, width / 8, height / 8, 1, "b8");
-        let b16 = HdrTexture::create(device, width / 16, height / 16, 1, "b16");
-        let b32 = HdrTexture::create(device, width / 32, height / 32, 1, "b32");
-        let b64 = HdrTexture::create(device, width / 64, height / 64, 1, "b64");
-        let b128 = HdrTexture::create(device, width / 128, height / 128, 1, "b128");
-        let b256 = HdrTexture::create(device, width / 256, height / 256, 1, "b256");
-        let b512 = HdrTexture::create(device, width / 512, height / 512, 1, "b512");
+    pub fn new(width: u32, height: u32) -> Self {
+        BloomTextures {
+            mips: vec![],
+            width,
+            height,
+        }
+    }
 
-        Self {
-            b2,
-            b4,
-            b8,
-            b16,
-            b32,
-            b64,
-            b128,
-            b256,
-            b512,
+    /// Creates the missing mips and drops the ones that are not needed anymore.
+    pub fn ensure_mips(&mut self, device: &wgpu::Device, mip_count: usize) {
+        self.mips.truncate(mip_count);
+        while self.mips.len() < mip_count {
+            let divisor = 1 << (self.mips.len() + 1);
+            self.mips.push(HdrTexture::create(
+                device,
+                self.width / divisor,
+                self.height / divisor,
+                1,
+                format!("b{divisor}"),
+            ));
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if (width, height) == (self.width, self.height) {
+            return;
         }
+        self.width = width;
+        self.height = height;
+        let mip_count = self.mips.len().min(max_mip_count(width, height));
+        self.mips.clear();
+        self.ensure_mips(device, mip_count);
     }
 }
@@ -23,6 +23,25 @@ var hdr_image: texture_2d<f32>;
 @binding(1)
 var hdr_sampler: sampler;
 
+// only sampled in the final pass.
+@group(2)
+@binding(0)
+var lens_dirt: texture_2d<f32>;
+
+@group(2)
+@binding(1)
+var lens_dirt_sampler: sampler;
+
+struct PushConstants {
+    // brightness above which pixels bloom.
+    threshold: f32,
+    // softness of the threshold, as a fraction of the threshold.
+    knee: f32,
+    lens_dirt_intensity: f32,
+    _pad: f32,
+}
+var<push_constant> pc: PushConstants;
+
 @fragment
 fn downsample(vs: VertexOutput) -> @location(0) vec4<f32> {
     let sample = sample_input_13_tap(vs.uv);
@@ -41,6 +60,13 @@ fn upsample(vs: VertexOutput) -> @location(0) vec4<f32> {
     return vec4(sample,1.0);
 }
 
+@fragment
+fn final_upsample(vs: VertexOutput) -> @location(0) vec4<f32> {
+    let sample = sample_input_3x3_tent(vs.uv);
+    let dirt = textureSample(lens_dirt, lens_dirt_sampler, vs.uv).rgb;
+    return vec4(sample * (1.0 + dirt * pc.lens_dirt_intensity), 1.0);
+}
+
 
 // // [COD] slide 162
 fn sample_input_3x3_tent(uv: vec2<f32>) -> vec3<f32> {
@@ -67,18 +93,14 @@ fn sample_input_3x3_tent(uv: vec2<f32>) -> vec3<f32> {
     return sample;
 }
 
-/// Todo! look at bevy code where this comes from and 
-const x: f32 = 0.5;
-const y: f32 = 1.0;
-const z: f32 = 1.0;
-const w: f32 = 1.0;
-
+// Quadratic soft knee: below threshold - knee nothing blooms, above threshold + knee it is linear,
+// in between a quadratic curve blends the two. A knee of 0 is a hard cut at the threshold.
 fn soft_threshold(color: vec3<f32>) -> vec3<f32> {
     let brightness = max(color.r, max(color.g, color.b));
-    var softness = brightness - y;
-    softness = clamp(softness, 0.0, z);
-    softness = softness * softness * w;
-    var contribution = max(brightness - x, softness);
+    let knee = pc.threshold * pc.knee;
+    var softness = clamp(brightness - pc.threshold + knee, 0.0, 2.0 * knee);
+    softness = softness * softness / (4.0 * knee + 0.00001);
+    var contribution = max(brightness - pc.threshold, softness);
     contribution /= max(brightness, 0.00001); // Prevent division by 0
     return color * contribution;
 }
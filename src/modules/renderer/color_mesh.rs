@@ -1,3 +1,4 @@
+use glam::{vec3, Vec3};
 use wgpu::{
     BufferUsages, FragmentState, PrimitiveState, RenderPipelineDescriptor, ShaderModuleDescriptor,
     VertexState,
@@ -6,13 +7,13 @@ use wgpu::{
 use crate::{
     elements::{
         camera3d::Camera3dGR, Color, GrowableBuffer, ImmediateMeshQueue, ImmediateMeshRanges,
-        Transform, TransformRaw,
+        InterpolatedTransform, MeshData, ToRaw, Transform, TransformRaw,
     },
     modules::{
-        renderer::{Attribute, VertexT, DEPTH_FORMAT, HDR_COLOR_FORMAT, MSAA_SAMPLE_COUNT},
+        renderer::{Attribute, VertexT, DEPTH_FORMAT, HDR_COLOR_FORMAT},
         GraphicsContext,
     },
-    Prepare,
+    GpuRecreate, GpuRecreated, Prepare, Ptr,
 };
 
 // /////////////////////////////////////////////////////////////////////////////
@@ -27,43 +28,235 @@ impl ColorMeshRenderer {
         indices: &[u32],
         transforms: &[Transform],
     ) {
+        let instances = transforms
+            .iter()
+            .map(|t| ColorMeshInstance::new(*t).to_raw());
         self.color_mesh_queue
-            .add_mesh(vertices, indices, transforms);
+            .add_mesh_raw(vertices, indices, instances);
     }
 
-    pub fn draw_cubes(&mut self, transforms: &[Transform], color: Option<Color>) {
-        const P: f32 = 0.5;
-        const M: f32 = -0.5;
-        let positions = vec![
-            [M, M, M],
-            [P, M, M],
-            [P, M, P],
-            [M, M, P],
-            [M, P, M],
-            [P, P, M],
-            [P, P, P],
-            [M, P, P],
-        ];
+    /// Like `draw_geometry`, but each instance has its own tint, emissive factor and enabled flag.
+    #[inline(always)]
+    pub fn draw_geometry_instances(
+        &mut self,
+        vertices: &[Vertex],
+        indices: &[u32],
+        instances: &[ColorMeshInstance],
+    ) {
+        self.color_mesh_queue.add_mesh(vertices, indices, instances);
+    }
 
-        let vertices: Vec<Vertex> = positions
-            .into_iter()
-            .map(|p| {
-                let x = p[0];
-                let y = p[1];
-                let z = p[2];
-                Vertex {
-                    pos: [x, y, z],
-                    color: color.unwrap_or_else(|| Color::new(x, y, z)),
-                }
-            })
-            .collect();
-
-        let indices = vec![
-            0, 1, 2, 0, 2, 3, 4, 7, 6, 4, 6, 5, 1, 5, 6, 1, 6, 2, 0, 3, 7, 0, 7, 4, 2, 6, 3, 6, 7,
-            3, 0, 4, 1, 4, 5, 1,
-        ];
+    pub fn draw_cubes(&mut self, transforms: &[Transform], color: Option<Color>) {
+        let (vertices, indices) = cube_geometry(color);
         self.draw_geometry(&vertices, &indices, transforms)
     }
+
+    /// Draws a mesh from `elements::shapes` in one color. The color mesh shader has no lighting, so the color is
+    /// shaded by the normals with the `light` on the cpu, such that the shape stays readable.
+    pub fn draw_mesh(&mut self, mesh: &MeshData, color: Color, transforms: &[Transform]) {
+        let vertices = shaded_vertices(mesh, color, &self.light);
+        self.draw_geometry(&vertices, &mesh.indices, transforms)
+    }
+
+    pub fn draw_mesh_instances(
+        &mut self,
+        mesh: &MeshData,
+        color: Color,
+        instances: &[ColorMeshInstance],
+    ) {
+        let vertices = shaded_vertices(mesh, color, &self.light);
+        self.draw_geometry_instances(&vertices, &mesh.indices, instances)
+    }
+
+    /// Draws a mesh built with a `StaticMeshBuilder` this frame.
+    pub fn draw_static(&mut self, mesh: Ptr<StaticColorMesh>) {
+        self.static_meshes.push(mesh);
+    }
+
+    /// Draws cubes blended between their last two fixed step transforms by the `fixed_alpha` of this frame.
+    pub fn draw_cubes_interpolated(
+        &mut self,
+        transforms: &[InterpolatedTransform],
+        color: Option<Color>,
+    ) {
+        let (vertices, indices) = cube_geometry(color);
+        let alpha = self.fixed_alpha;
+        let instances = transforms
+            .iter()
+            .map(|t| ColorMeshInstance::new(t.interpolate(alpha)).to_raw());
+        self.color_mesh_queue
+            .add_mesh_raw(&vertices, &indices, instances);
+    }
+
+    /// How far the frame is between the last two fixed steps, set from `Time::fixed_alpha` by `DefaultModules`
+    /// at the start of every frame.
+    pub fn set_fixed_alpha(&mut self, alpha: f32) {
+        self.fixed_alpha = alpha;
+    }
+}
+
+/// Vertices and indices of a unit cube. Without a color, the corners are colored by their position.
+fn cube_geometry(color: Option<Color>) -> ([Vertex; 8], [u32; 36]) {
+    const P: f32 = 0.5;
+    const M: f32 = -0.5;
+    let positions = [
+        [M, M, M],
+        [P, M, M],
+        [P, M, P],
+        [M, M, P],
+        [M, P, M],
+        [P, P, M],
+        [P, P, P],
+        [M, P, P],
+    ];
+
+    let vertices = positions.map(|p| {
+        let x = p[0];
+        let y = p[1];
+        let z = p[2];
+        Vertex {
+            pos: [x, y, z],
+            color: color.unwrap_or_else(|| Color::new(x, y, z)),
+        }
+    });
+
+    let indices = [
+        0, 1, 2, 0, 2, 3, 4, 7, 6, 4, 6, 5, 1, 5, 6, 1, 6, 2, 0, 3, 7, 0, 7, 4, 2, 6, 3, 6, 7, 3,
+        0, 4, 1, 4, 5, 1,
+    ];
+    (vertices, indices)
+}
+
+/// The light that `draw_mesh` shades with, e.g. the sun of a `DayNightCycle`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    /// towards the light, normalized.
+    pub direction: Vec3,
+    pub color: Color,
+    /// added on all sides, also on the ones facing away from the light.
+    pub ambient: Color,
+}
+
+impl Default for DirectionalLight {
+    /// Light from above.
+    fn default() -> Self {
+        DirectionalLight {
+            direction: vec3(0.3, 1.0, 0.5).normalize(),
+            color: Color::new(0.45, 0.45, 0.45),
+            ambient: Color::new(0.55, 0.55, 0.55),
+        }
+    }
+}
+
+/// The color shaded by the normals.
+fn shaded_vertices(mesh: &MeshData, color: Color, light: &DirectionalLight) -> Vec<Vertex> {
+    mesh.map_vertices(|pos, normal, _| {
+        let lit = normal.dot(light.direction).max(0.0);
+        Vertex {
+            pos: pos.into(),
+            color: Color {
+                r: color.r * (light.ambient.r + light.color.r * lit),
+                g: color.g * (light.ambient.g + light.color.g * lit),
+                b: color.b * (light.ambient.b + light.color.b * lit),
+                a: color.a,
+            },
+        }
+    })
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Static Meshes
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Merges many static color meshes (e.g. the walls and props of a level) into one `StaticColorMesh` at load time.
+/// The transforms are baked into the vertices, so the whole level is one draw call instead of one per mesh, and
+/// nothing has to be uploaded again each frame.
+///
+/// All color meshes share the same pipeline, so any meshes can be merged. Only merge geometry that does not move.
+#[derive(Debug, Clone, Default)]
+pub struct StaticMeshBuilder {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+impl StaticMeshBuilder {
+    pub fn new() -> Self {
+        StaticMeshBuilder::default()
+    }
+
+    /// Adds the geometry once for each transform.
+    pub fn add_geometry(&mut self, vertices: &[Vertex], indices: &[u32], transforms: &[Transform]) {
+        for transform in transforms {
+            let affine = transform.affine();
+            let offset = self.vertices.len() as u32;
+            self.vertices.extend(vertices.iter().map(|v| Vertex {
+                pos: affine.transform_point3(v.pos.into()).into(),
+                color: v.color,
+            }));
+            self.indices.extend(indices.iter().map(|i| i + offset));
+        }
+    }
+
+    pub fn add_cubes(&mut self, transforms: &[Transform], color: Option<Color>) {
+        let (vertices, indices) = cube_geometry(color);
+        self.add_geometry(&vertices, &indices, transforms)
+    }
+
+    /// Adds a mesh from `elements::shapes`, shaded like `ColorMeshRenderer::draw_mesh` with the default light.
+    /// The shading is baked, so it does not follow later changes of the light.
+    pub fn add_mesh(&mut self, mesh: &MeshData, color: Color, transforms: &[Transform]) {
+        let vertices = shaded_vertices(mesh, color, &DirectionalLight::default());
+        self.add_geometry(&vertices, &mesh.indices, transforms)
+    }
+
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Uploads the merged geometry. Keep the result in an `OwnedPtr` and draw it each frame with
+    /// `ColorMeshRenderer::draw_static`. After a device loss, call `StaticColorMesh::gpu_recreated`, e.g. from
+    /// `Plugin::gpu_recreated`.
+    pub fn build(&self, device: &wgpu::Device) -> StaticColorMesh {
+        StaticColorMesh {
+            vertex_buffer: GrowableBuffer::new_from_data(
+                device,
+                BufferUsages::VERTEX,
+                &self.vertices,
+            ),
+            index_buffer: GrowableBuffer::new_from_data(device, BufferUsages::INDEX, &self.indices),
+            index_count: self.indices.len() as u32,
+            geometry: self.clone(),
+        }
+    }
+}
+
+/// Merged geometry on the gpu, see `StaticMeshBuilder`.
+#[derive(Debug)]
+pub struct StaticColorMesh {
+    vertex_buffer: GrowableBuffer<Vertex>,
+    index_buffer: GrowableBuffer<u32>,
+    index_count: u32,
+    /// kept to upload the geometry again after a device loss.
+    geometry: StaticMeshBuilder,
+}
+
+impl StaticColorMesh {
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+}
+
+impl GpuRecreate for StaticColorMesh {
+    /// Uploads the geometry again in place, such that `Ptr`s to the mesh stay valid.
+    fn gpu_recreated(&mut self, ctx: &GraphicsContext, event: GpuRecreated) {
+        if event.device_lost {
+            *self = self.geometry.build(&ctx.device);
+        }
+    }
 }
 
 // /////////////////////////////////////////////////////////////////////////////
@@ -74,20 +267,43 @@ impl ColorMeshRenderer {
 pub struct ColorMeshRenderer {
     pipeline: wgpu::RenderPipeline,
     /// immediate geometry, cleared every frame
-    color_mesh_queue: ImmediateMeshQueue<Vertex, Transform>,
+    color_mesh_queue: ImmediateMeshQueue<Vertex, ColorMeshInstance>,
     /// information about index ranges
     render_data: RenderData,
+    /// static meshes drawn this frame, cleared in `prepare`.
+    static_meshes: Vec<Ptr<StaticColorMesh>>,
+    /// drawn static meshes, rendered with `identity_instance`.
+    render_static_meshes: Vec<Ptr<StaticColorMesh>>,
+    identity_instance: GrowableBuffer<ColorMeshInstanceRaw>,
+    /// see `set_fixed_alpha`.
+    fixed_alpha: f32,
+    /// shades the meshes of `draw_mesh`.
+    pub light: DirectionalLight,
 }
 
 impl ColorMeshRenderer {
     pub fn new(ctx: &GraphicsContext, camera: &Camera3dGR) -> Self {
         let _device = &ctx.device;
-        let pipeline = create_render_pipeline(&ctx.device, include_str!("color_mesh.wgsl"), camera);
+        let pipeline = create_render_pipeline(
+            &ctx.device,
+            include_str!("color_mesh.wgsl"),
+            camera,
+            ctx.msaa_sample_count,
+        );
 
         ColorMeshRenderer {
             pipeline,
             color_mesh_queue: ImmediateMeshQueue::default(),
             render_data: RenderData::new(&ctx.device),
+            static_meshes: vec![],
+            render_static_meshes: vec![],
+            identity_instance: GrowableBuffer::new_from_data(
+                &ctx.device,
+                BufferUsages::VERTEX,
+                &[ColorMeshInstance::new(Transform::ZERO).to_raw()],
+            ),
+            fixed_alpha: 0.0,
+            light: DirectionalLight::default(),
         }
     }
 
@@ -107,6 +323,22 @@ impl ColorMeshRenderer {
         for mesh in self.render_data.mesh_ranges.iter() {
             render_pass.draw_indexed(mesh.index_range.clone(), 0, mesh.instance_range.clone())
         }
+
+        if self.render_static_meshes.is_empty() {
+            return;
+        }
+        render_pass.set_vertex_buffer(1, self.identity_instance.buffer().slice(..));
+        for mesh in self.render_static_meshes.iter() {
+            if mesh.index_count == 0 {
+                continue;
+            }
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.buffer().slice(..));
+            render_pass.set_index_buffer(
+                mesh.index_buffer.buffer().slice(..),
+                wgpu::IndexFormat::Uint32,
+            );
+            render_pass.draw_indexed(0..mesh.index_count, 0, 0..1);
+        }
     }
 }
 
@@ -128,6 +360,8 @@ impl Prepare for ColorMeshRenderer {
             .prepare(self.color_mesh_queue.instances(), device, queue);
         self.color_mesh_queue
             .clear_and_take_meshes(&mut self.render_data.mesh_ranges);
+        self.render_static_meshes.clear();
+        std::mem::swap(&mut self.render_static_meshes, &mut self.static_meshes);
     }
 }
 
@@ -141,7 +375,7 @@ struct RenderData {
     mesh_ranges: Vec<ImmediateMeshRanges>,
     vertex_buffer: GrowableBuffer<Vertex>,
     index_buffer: GrowableBuffer<u32>,
-    instance_buffer: GrowableBuffer<TransformRaw>,
+    instance_buffer: GrowableBuffer<ColorMeshInstanceRaw>,
 }
 
 impl RenderData {
@@ -169,10 +403,78 @@ impl VertexT for Vertex {
     ];
 }
 
+/// Per instance data of the `ColorMeshRenderer`. Changing the tint, emissive factor or enabled flag does not need
+/// a different mesh, so instances can flash or fade while sharing their vertices.
+#[derive(Debug, Clone, Copy)]
+pub struct ColorMeshInstance {
+    pub transform: Transform,
+    /// multiplied with the vertex colors.
+    pub tint: Color,
+    /// the color is brightened by `1.0 + emissive`, values above 0 make the instance glow with bloom.
+    pub emissive: f32,
+    /// disabled instances are not drawn.
+    pub enabled: bool,
+}
+
+impl ColorMeshInstance {
+    pub fn new(transform: Transform) -> Self {
+        ColorMeshInstance {
+            transform,
+            tint: Color::WHITE,
+            emissive: 0.0,
+            enabled: true,
+        }
+    }
+}
+
+impl From<Transform> for ColorMeshInstance {
+    fn from(transform: Transform) -> Self {
+        ColorMeshInstance::new(transform)
+    }
+}
+
+impl ToRaw for ColorMeshInstance {
+    type Raw = ColorMeshInstanceRaw;
+
+    fn to_raw(&self) -> Self::Raw {
+        ColorMeshInstanceRaw {
+            transform: self.transform.to_raw(),
+            tint: self.tint,
+            emissive: self.emissive,
+            enabled: if self.enabled { 1.0 } else { 0.0 },
+            _pad: [0.0; 2],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ColorMeshInstanceRaw {
+    transform: TransformRaw,
+    tint: Color,
+    emissive: f32,
+    enabled: f32,
+    /// the `Mat4` of the transform is 16 byte aligned.
+    _pad: [f32; 2],
+}
+
+impl VertexT for ColorMeshInstanceRaw {
+    const ATTRIBUTES: &'static [Attribute] = &[
+        Attribute::new("col1", wgpu::VertexFormat::Float32x4),
+        Attribute::new("col2", wgpu::VertexFormat::Float32x4),
+        Attribute::new("col3", wgpu::VertexFormat::Float32x4),
+        Attribute::new("translation", wgpu::VertexFormat::Float32x4),
+        Attribute::new("tint", wgpu::VertexFormat::Float32x4),
+        Attribute::new("emissive", wgpu::VertexFormat::Float32),
+        Attribute::new("enabled", wgpu::VertexFormat::Float32),
+    ];
+}
+
 fn create_render_pipeline(
     device: &wgpu::Device,
     wgsl: &str,
     camera: &Camera3dGR,
+    msaa_sample_count: u32,
 ) -> wgpu::RenderPipeline {
     let label = "ColorMeshRenderer";
     let shader_module = device.create_shader_module(ShaderModuleDescriptor {
@@ -184,7 +486,7 @@ fn create_render_pipeline(
     let _empty2 = &mut vec![];
     let vertex_buffers_layout = &[
         Vertex::vertex_buffer_layout(0, false, _empty1),
-        TransformRaw::vertex_buffer_layout(Vertex::ATTRIBUTES.len(), true, _empty2),
+        ColorMeshInstanceRaw::vertex_buffer_layout(Vertex::ATTRIBUTES.len(), true, _empty2),
     ];
 
     let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -230,9 +532,57 @@ fn create_render_pipeline(
             bias: wgpu::DepthBiasState::default(),
         }),
         multisample: wgpu::MultisampleState {
-            count: MSAA_SAMPLE_COUNT,
+            count: msaa_sample_count,
             ..Default::default()
         },
         multiview: None,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn static_mesh_builder_bakes_transforms() {
+        let mut builder = StaticMeshBuilder::new();
+        let transforms = [
+            Transform::new(10.0, 0.0, 0.0),
+            Transform::new(0.0, 0.0, -5.0).with_scale(2.0),
+        ];
+        builder.add_cubes(&transforms, Some(Color::WHITE));
+        assert_eq!(builder.vertices().len(), 16);
+        assert_eq!(builder.indices().len(), 72);
+        // the indices of the second cube point to its own vertices:
+        assert!(builder.indices()[36..].iter().all(|i| (8..16).contains(i)));
+        assert_eq!(builder.vertices()[0].pos, [9.5, -0.5, -0.5]);
+        assert_eq!(builder.vertices()[8].pos, [-1.0, -1.0, -6.0]);
+    }
+
+    #[test]
+    fn interpolated_instances_are_queued_in_place() {
+        let (vertices, indices) = cube_geometry(None);
+        let mut queue: ImmediateMeshQueue<Vertex, ColorMeshInstance> = Default::default();
+        queue.add_mesh(
+            &vertices,
+            &indices,
+            &[ColorMeshInstance::new(Transform::IDENTITY)],
+        );
+        let mut moving = InterpolatedTransform::new(Transform::new(0.0, 0.0, 0.0));
+        moving.push(Transform::new(2.0, 0.0, 0.0));
+        let instances = [moving, moving]
+            .into_iter()
+            .map(|t| ColorMeshInstance::new(t.interpolate(0.25)).to_raw());
+        queue.add_mesh_raw(&vertices, &indices, instances);
+        assert_eq!(queue.instances().len(), 3);
+        assert_eq!(
+            queue.instances()[1],
+            ColorMeshInstance::new(Transform::new(0.5, 0.0, 0.0)).to_raw()
+        );
+        let mut meshes = vec![];
+        queue.clear_and_take_meshes(&mut meshes);
+        assert_eq!(meshes[0].instance_range, 0..1);
+        assert_eq!(meshes[1].instance_range, 1..3);
+        assert_eq!(meshes[1].index_range, 36..72);
+    }
+}
@@ -14,6 +14,9 @@ struct Instance {
     @location(3) col2: vec4<f32>,
     @location(4) col3: vec4<f32>,
     @location(5) translation: vec4<f32>,
+    @location(6) tint: vec4<f32>,
+    @location(7) emissive: f32,
+    @location(8) enabled: f32,
 }
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
@@ -34,8 +37,15 @@ fn vs_main(
     let world_position = vec4<f32>(vertex.position, 1.0);
 
     var out: VertexOutput;
+    if instance.enabled == 0.0 {
+        // all vertices of disabled instances end up on the same point, such that no triangles are rasterized.
+        out.clip_position = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+        out.color = vec4<f32>(0.0);
+        return out;
+    }
     out.clip_position = camera.view_proj * model_matrix * world_position;
-    out.color = vertex.color;
+    let color = vertex.color * instance.tint;
+    out.color = vec4<f32>(color.rgb * (1.0 + instance.emissive), color.a);
     return out;
 }
  
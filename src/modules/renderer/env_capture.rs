@@ -0,0 +1,307 @@
+use glam::{vec3, Mat4, Vec3};
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    elements::{camera3d::Camera3dGR, texture::cube_bind_group_layout, Camera3d, Color},
+    modules::GraphicsContext,
+};
+
+use super::{DepthTexture, HdrTexture, HDR_COLOR_FORMAT};
+
+// /////////////////////////////////////////////////////////////////////////////
+// Interface
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Everything that can be rendered into an `EnvironmentCapture`, like `Plugin::render`.
+/// Implemented by the `DefaultModules`, which render the world without gizmos.
+pub trait CaptureScene {
+    fn render_capture<'e>(&'e self, render_pass: &mut wgpu::RenderPass<'e>, camera: &'e Camera3dGR);
+}
+
+/// Renders the scene from a point into the 6 faces of a `HdrCubemap`, e.g. for reflection probes, skybox baking
+/// or impostors. Reuse it for captures of the same size, it holds the depth and msaa textures.
+///
+/// ```rust,ignore
+/// let mut capture = EnvironmentCapture::new(&modules.ctx, 256);
+/// let probe = HdrCubemap::new(&modules.ctx, 256);
+/// modules.capture_environment(&mut capture, vec3(0.0, 2.0, 0.0), &probe);
+/// ```
+pub struct EnvironmentCapture {
+    pub near: f32,
+    pub far: f32,
+    pub clear_color: Color,
+    size: u32,
+    cameras: [Camera3dGR; 6],
+    depth_texture: DepthTexture,
+    /// None without msaa, then the faces are rendered to directly.
+    msaa_target: Option<HdrTexture>,
+}
+
+impl EnvironmentCapture {
+    pub fn new(ctx: &GraphicsContext, size: u32) -> Self {
+        let camera = Camera3d::new(size, size);
+        let msaa_target = (ctx.msaa_sample_count > 1).then(|| {
+            HdrTexture::create(
+                &ctx.device,
+                size,
+                size,
+                ctx.msaa_sample_count,
+                "EnvironmentCapture msaa",
+            )
+        });
+        EnvironmentCapture {
+            near: 0.1,
+            far: 1000.0,
+            clear_color: Color::BLACK,
+            size,
+            cameras: std::array::from_fn(|_| Camera3dGR::new(ctx, &camera)),
+            depth_texture: DepthTexture::create_sized(ctx, PhysicalSize::new(size, size)),
+            msaa_target,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Renders all 6 faces and submits them right away. Uses whatever the renderers prepared last, so call it after
+    /// `DefaultModules::prepare` for the current frame, or any time when baking a static scene.
+    pub fn capture(
+        &mut self,
+        ctx: &GraphicsContext,
+        position: Vec3,
+        target: &HdrCubemap,
+        scene: &dyn CaptureScene,
+    ) {
+        assert_eq!(target.size, self.size, "capture and cubemap sizes differ");
+        for (face, camera) in CubeFace::ALL.iter().zip(self.cameras.iter_mut()) {
+            let view_proj = face.view_proj(position, self.near, self.far);
+            camera.prepare_view_proj(&ctx.queue, position, view_proj);
+        }
+
+        let mut encoder = ctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("EnvironmentCapture"),
+            });
+        for (face, camera) in CubeFace::ALL.iter().zip(self.cameras.iter()) {
+            let face_view = target.face_view(*face);
+            let (view, resolve_target) = match &self.msaa_target {
+                Some(msaa) => (msaa.view(), Some(face_view)),
+                None => (face_view, None),
+            };
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("EnvironmentCapture face"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.clear_color.into()),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: self.depth_texture.view(),
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            scene.render_capture(&mut render_pass, camera);
+        }
+        ctx.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Cubemap
+// /////////////////////////////////////////////////////////////////////////////
+
+/// An hdr texture with 6 layers, one per `CubeFace`, bound as `texture_cube<f32>` (see `cube_bind_group_layout`).
+///
+/// Cube textures are sampled in a left handed space, the world is right handed. The faces are rendered such that
+/// sampling with `direction * HdrCubemap::LOOKUP_SCALE` (z flipped) returns what is seen in that world direction.
+pub struct HdrCubemap {
+    texture: wgpu::Texture,
+    face_views: [wgpu::TextureView; 6],
+    cube_view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+    size: u32,
+}
+
+impl HdrCubemap {
+    pub const LOOKUP_SCALE: Vec3 = vec3(1.0, 1.0, -1.0);
+
+    pub fn new(ctx: &GraphicsContext, size: u32) -> Self {
+        let size = size.max(1);
+        let texture = ctx.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("HdrCubemap"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_COLOR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let face_views = std::array::from_fn(|layer| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("HdrCubemap face"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer as u32,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        });
+        let cube_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("HdrCubemap cube"),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = ctx.device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("HdrCubemap BindGroup"),
+            layout: &cube_bind_group_layout(&ctx.device),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&cube_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        HdrCubemap {
+            texture,
+            face_views,
+            cube_view,
+            bind_group,
+            size,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// All 6 layers, e.g. to copy them out of the gpu.
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// The cube view, for sampling.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.cube_view
+    }
+
+    /// A single face as 2d texture view.
+    pub fn face_view(&self, face: CubeFace) -> &wgpu::TextureView {
+        &self.face_views[face as usize]
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+/// The faces of a cube texture in layer order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeFace {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PosX,
+        CubeFace::NegX,
+        CubeFace::PosY,
+        CubeFace::NegY,
+        CubeFace::PosZ,
+        CubeFace::NegZ,
+    ];
+
+    /// The sampling direction of a texel of this face, `u` and `v` from 0 to 1 with v down.
+    /// Multiply with `HdrCubemap::LOOKUP_SCALE` for the world direction.
+    pub fn direction(self, u: f32, v: f32) -> Vec3 {
+        let (s, t) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        match self {
+            CubeFace::PosX => vec3(1.0, -t, -s),
+            CubeFace::NegX => vec3(-1.0, -t, s),
+            CubeFace::PosY => vec3(s, 1.0, t),
+            CubeFace::NegY => vec3(s, -1.0, -t),
+            CubeFace::PosZ => vec3(s, -t, 1.0),
+            CubeFace::NegZ => vec3(-s, -t, -1.0),
+        }
+    }
+
+    /// Forward and up of the camera that renders this face, in world space.
+    pub fn camera_forward_up(self) -> (Vec3, Vec3) {
+        match self {
+            CubeFace::PosX => (Vec3::X, Vec3::Y),
+            CubeFace::NegX => (Vec3::NEG_X, Vec3::Y),
+            CubeFace::PosY => (Vec3::Y, Vec3::Z),
+            CubeFace::NegY => (Vec3::NEG_Y, Vec3::NEG_Z),
+            // flipped by `HdrCubemap::LOOKUP_SCALE`.
+            CubeFace::PosZ => (Vec3::NEG_Z, Vec3::Y),
+            CubeFace::NegZ => (Vec3::Z, Vec3::Y),
+        }
+    }
+
+    /// 90° field of view, such that the 6 faces cover all directions.
+    pub fn view_proj(self, position: Vec3, near: f32, far: f32) -> Mat4 {
+        let (forward, up) = self.camera_forward_up();
+        let view = Mat4::look_to_rh(position, forward, up);
+        let proj = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, near, far);
+        proj * view
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec2;
+
+    use super::*;
+
+    #[test]
+    fn faces_match_cube_sampling() {
+        let position = vec3(1.0, 2.0, 3.0);
+        for face in CubeFace::ALL {
+            let view_proj = face.view_proj(position, 0.1, 100.0);
+            for (u, v) in [(0.5, 0.5), (0.9, 0.2), (0.1, 0.7)] {
+                let world_direction = face.direction(u, v) * HdrCubemap::LOOKUP_SCALE;
+                let ndc = view_proj.project_point3(position + world_direction);
+                // texel rows go down, ndc y goes up.
+                let expected = vec2(2.0 * u - 1.0, 1.0 - 2.0 * v);
+                assert!(
+                    ndc.truncate().distance(expected) < 1e-4,
+                    "{face:?} at {u},{v}: {ndc}"
+                );
+                assert!((0.0..1.0).contains(&ndc.z));
+            }
+        }
+    }
+}
@@ -1,4 +1,3 @@
-use glam::vec3;
 use glam::Vec3;
 use wgpu::BufferUsages;
 use wgpu::FragmentState;
@@ -9,11 +8,11 @@ use wgpu::VertexState;
 use crate::elements::camera3d::Camera3dGR;
 use crate::elements::Color;
 use crate::elements::GrowableBuffer;
+use crate::elements::{Aabb3d, Curve3d, Obb};
 use crate::modules::renderer::Attribute;
 use crate::modules::renderer::VertexT;
 use crate::modules::renderer::DEPTH_FORMAT;
 use crate::modules::renderer::HDR_COLOR_FORMAT;
-use crate::modules::renderer::MSAA_SAMPLE_COUNT;
 use crate::modules::GraphicsContext;
 
 use crate::Prepare;
@@ -23,81 +22,111 @@ use crate::Prepare;
 // /////////////////////////////////////////////////////////////////////////////
 
 impl Gizmos {
+    /// Sets the depth test for the following draw calls of this frame. With `GizmoDepth::XRay` the gizmos are
+    /// drawn through other geometry, e.g. to highlight a selected object. Is reset to `GizmoDepth::Tested` every frame.
+    pub fn set_depth(&mut self, depth: GizmoDepth) {
+        self.depth = depth;
+    }
+
+    pub fn depth(&self) -> GizmoDepth {
+        self.depth
+    }
+
+    /// Calls `f` with `GizmoDepth::XRay` and restores the previous depth afterwards.
+    pub fn xray(&mut self, f: impl FnOnce(&mut Gizmos)) {
+        let depth = self.depth;
+        self.depth = GizmoDepth::XRay;
+        f(self);
+        self.depth = depth;
+    }
+
     pub fn draw_line(&mut self, from: Vec3, to: Vec3, color: Color) {
-        self.vertex_queue.push(Vertex {
+        self.queue().push(Vertex {
             pos: [from.x, from.y, from.z],
             color,
         });
-        self.vertex_queue.push(Vertex {
+        self.queue().push(Vertex {
             pos: [to.x, to.y, to.z],
             color,
         });
     }
 
     pub fn draw_xyz(&mut self) {
-        self.vertex_queue.push(Vertex {
+        self.queue().push(Vertex {
             pos: [0.0, 0.0, 0.0],
             color: Color::RED,
         });
-        self.vertex_queue.push(Vertex {
+        self.queue().push(Vertex {
             pos: [1.0, 0.0, 0.0],
             color: Color::RED,
         });
 
-        self.vertex_queue.push(Vertex {
+        self.queue().push(Vertex {
             pos: [0.0, 0.0, 0.0],
             color: Color::GREEN,
         });
-        self.vertex_queue.push(Vertex {
+        self.queue().push(Vertex {
             pos: [0.0, 1.0, 0.0],
             color: Color::GREEN,
         });
 
-        self.vertex_queue.push(Vertex {
+        self.queue().push(Vertex {
             pos: [0.0, 0.0, 0.0],
             color: Color::BLUE,
         });
-        self.vertex_queue.push(Vertex {
+        self.queue().push(Vertex {
             pos: [0.0, 0.0, 1.0],
             color: Color::BLUE,
         });
     }
 
     pub fn draw_cube(&mut self, position: Vec3, side_len: f32, color: Color) {
-        let l = side_len / 2.0;
-
-        let v1 = position + vec3(-l, -l, -l);
-        let v2 = position + vec3(l, -l, -l);
-        let v3 = position + vec3(l, -l, l);
-        let v4 = position + vec3(-l, -l, l);
-        let v5 = position + vec3(-l, l, -l);
-        let v6 = position + vec3(l, l, -l);
-        let v7 = position + vec3(l, l, l);
-        let v8 = position + vec3(-l, l, l);
-        let lines = [
-            (v1, v2),
-            (v2, v3),
-            (v3, v4),
-            (v4, v1),
-            (v5, v6),
-            (v6, v7),
-            (v7, v8),
-            (v8, v5),
-            (v1, v5),
-            (v2, v6),
-            (v3, v7),
-            (v4, v8),
-        ];
-
-        for (from, to) in lines {
-            self.vertex_queue.push(Vertex {
-                pos: [from.x, from.y, from.z],
-                color,
-            });
-            self.vertex_queue.push(Vertex {
-                pos: [to.x, to.y, to.z],
-                color,
-            });
+        let half_extents = Vec3::splat(side_len / 2.0);
+        self.draw_aabb(
+            &Aabb3d::from_center_half_extents(position, half_extents),
+            color,
+        );
+    }
+
+    pub fn draw_aabb(&mut self, aabb: &Aabb3d, color: Color) {
+        self.draw_box_edges(aabb.corners(), color);
+    }
+
+    pub fn draw_obb(&mut self, obb: &Obb, color: Color) {
+        self.draw_box_edges(obb.corners(), color);
+    }
+
+    pub fn draw_curve(&mut self, curve: &Curve3d, color: Color) {
+        for segment in curve.polyline().windows(2) {
+            self.draw_line(segment[0], segment[1], color);
+        }
+    }
+
+    /// The curve with its frames every `spacing` units: tangent red, normal green, binormal blue.
+    /// Without frames if `spacing` is not positive.
+    pub fn draw_curve_frames(&mut self, curve: &Curve3d, up: Vec3, spacing: f32, color: Color) {
+        self.draw_curve(curve, color);
+        if spacing <= 0.0 || spacing.is_nan() {
+            return;
+        }
+        let count = (curve.length() / spacing) as usize;
+        for i in 0..=count {
+            let frame = curve.frame_at_distance(i as f32 * spacing, up);
+            let p = frame.position;
+            self.draw_line(p, p + frame.tangent * 0.5, Color::RED);
+            self.draw_line(p, p + frame.normal * 0.5, Color::GREEN);
+            self.draw_line(p, p + frame.binormal * 0.5, Color::BLUE);
+        }
+    }
+
+    /// Corners ordered like `Aabb3d::corners`: the edges connect corners whose index differs in one bit.
+    fn draw_box_edges(&mut self, corners: [Vec3; 8], color: Color) {
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.draw_line(corners[i], corners[i | bit], color);
+                }
+            }
         }
     }
 }
@@ -106,20 +135,47 @@ impl Gizmos {
 // Module
 // /////////////////////////////////////////////////////////////////////////////
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GizmoDepth {
+    /// hidden behind other geometry.
+    #[default]
+    Tested,
+    /// always visible, drawn on top of other geometry.
+    XRay,
+}
+
 pub struct Gizmos {
     /// immediate vertices, written to vertex_buffer every frame.
     vertex_queue: Vec<Vertex>,
+    /// immediate vertices drawn without depth test, appended to the `vertex_queue` in the vertex buffer.
+    xray_queue: Vec<Vertex>,
+    depth: GizmoDepth,
     pipeline: wgpu::RenderPipeline,
+    xray_pipeline: wgpu::RenderPipeline,
     vertex_buffer: GrowableBuffer<Vertex>,
+    /// vertices from here on in the vertex buffer are drawn with the `xray_pipeline`.
+    xray_start: u32,
 }
 impl Gizmos {
     pub fn new(ctx: &GraphicsContext, camera: &Camera3dGR) -> Self {
         let vertex_buffer = GrowableBuffer::new(&ctx.device, 256, BufferUsages::VERTEX);
-        let pipeline = create_pipeline(&ctx.device, camera);
+        let pipeline = create_pipeline(&ctx.device, camera, ctx.msaa_sample_count, false);
+        let xray_pipeline = create_pipeline(&ctx.device, camera, ctx.msaa_sample_count, true);
         Gizmos {
             pipeline,
+            xray_pipeline,
             vertex_queue: vec![],
+            xray_queue: vec![],
+            depth: GizmoDepth::Tested,
             vertex_buffer,
+            xray_start: 0,
+        }
+    }
+
+    fn queue(&mut self) -> &mut Vec<Vertex> {
+        match self.depth {
+            GizmoDepth::Tested => &mut self.vertex_queue,
+            GizmoDepth::XRay => &mut self.xray_queue,
         }
     }
 
@@ -131,10 +187,17 @@ impl Gizmos {
         if self.vertex_buffer.len() == 0 {
             return;
         }
-        render_pass.set_pipeline(&self.pipeline);
+        let len = self.vertex_buffer.len() as u32;
         render_pass.set_bind_group(0, camera.bind_group(), &[]);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.buffer().slice(..));
-        render_pass.draw(0..self.vertex_buffer.len() as u32, 0..1);
+        if self.xray_start > 0 {
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.draw(0..self.xray_start, 0..1);
+        }
+        if self.xray_start < len {
+            render_pass.set_pipeline(&self.xray_pipeline);
+            render_pass.draw(self.xray_start..len, 0..1);
+        }
     }
 }
 
@@ -145,9 +208,12 @@ impl Prepare for Gizmos {
         queue: &wgpu::Queue,
         _encoder: &mut wgpu::CommandEncoder,
     ) {
+        self.xray_start = self.vertex_queue.len() as u32;
+        self.vertex_queue.append(&mut self.xray_queue);
         self.vertex_buffer
             .prepare(&self.vertex_queue, device, queue);
         self.vertex_queue.clear();
+        self.depth = GizmoDepth::Tested;
     }
 }
 
@@ -169,8 +235,13 @@ impl VertexT for Vertex {
     ];
 }
 
-fn create_pipeline(device: &wgpu::Device, camera: &Camera3dGR) -> wgpu::RenderPipeline {
-    let label = "Gizmos";
+fn create_pipeline(
+    device: &wgpu::Device,
+    camera: &Camera3dGR,
+    msaa_sample_count: u32,
+    xray: bool,
+) -> wgpu::RenderPipeline {
+    let label = if xray { "Gizmos XRay" } else { "Gizmos" };
 
     let shader = device.create_shader_module(ShaderModuleDescriptor {
         label: Some(&format!("{label} ShaderModule")),
@@ -218,12 +289,16 @@ fn create_pipeline(device: &wgpu::Device, camera: &Camera3dGR) -> wgpu::RenderPi
         depth_stencil: Some(wgpu::DepthStencilState {
             format: DEPTH_FORMAT,
             depth_write_enabled: false,
-            depth_compare: wgpu::CompareFunction::LessEqual,
+            depth_compare: if xray {
+                wgpu::CompareFunction::Always
+            } else {
+                wgpu::CompareFunction::LessEqual
+            },
             stencil: wgpu::StencilState::default(),
             bias: wgpu::DepthBiasState::default(),
         }),
         multisample: wgpu::MultisampleState {
-            count: MSAA_SAMPLE_COUNT,
+            count: msaa_sample_count,
             ..Default::default()
         },
         multiview: None,
@@ -0,0 +1,742 @@
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use wgpu::{
+    naga, BufferUsages, FragmentState, PrimitiveState, RenderPipelineDescriptor,
+    ShaderModuleDescriptor, VertexState,
+};
+
+use crate::{
+    elements::{camera3d::Camera3dGR, GrowableBuffer, Texture},
+    modules::{
+        renderer::{DEPTH_FORMAT, HDR_COLOR_FORMAT},
+        GraphicsContext,
+    },
+    Prepare,
+};
+
+// /////////////////////////////////////////////////////////////////////////////
+// Reflection
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Bind groups and vertex inputs of a wgsl shader, read with naga.
+///
+/// Expects the entry points `vs_main` and `fs_main`, like all other shaders in vert. Shaders without a vertex
+/// stage (e.g. compute shaders) have no vertex inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderReflection {
+    /// sorted by group index.
+    pub bind_groups: Vec<BindGroupReflection>,
+    /// inputs of `vs_main`, sorted by location.
+    pub vertex_inputs: Vec<VertexInputReflection>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindGroupReflection {
+    pub group: u32,
+    /// sorted by binding index.
+    pub entries: Vec<BindingReflection>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindingReflection {
+    pub binding: u32,
+    pub name: String,
+    pub ty: BindingReflectionType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindingReflectionType {
+    /// `var<uniform>`. `members` is empty if the uniform is not a struct.
+    Uniform {
+        size: u32,
+        members: Vec<UniformMember>,
+    },
+    Storage {
+        size: u32,
+        read_only: bool,
+    },
+    Texture(wgpu::TextureSampleType, wgpu::TextureViewDimension),
+    Sampler(wgpu::SamplerBindingType),
+}
+
+/// Field of a uniform struct, with its offset according to the wgsl layout rules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UniformMember {
+    pub name: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexInputReflection {
+    pub location: u32,
+    pub name: String,
+    pub format: wgpu::VertexFormat,
+}
+
+impl ShaderReflection {
+    pub fn from_wgsl(wgsl: &str) -> anyhow::Result<Self> {
+        let module = naga::front::wgsl::parse_str(wgsl)
+            .map_err(|err| anyhow!("{}", err.emit_to_string(wgsl)))?;
+        Self::from_module(&module)
+    }
+
+    pub fn from_module(module: &naga::Module) -> anyhow::Result<Self> {
+        let mut groups: BTreeMap<u32, Vec<BindingReflection>> = BTreeMap::new();
+        for (_, global) in module.global_variables.iter() {
+            let Some(binding) = &global.binding else {
+                continue;
+            };
+            let name = global.name.clone().unwrap_or_default();
+            let ty = binding_type(module, global).map_err(|err| {
+                anyhow!(
+                    "binding {name} (@group({}) @binding({})): {err}",
+                    binding.group,
+                    binding.binding
+                )
+            })?;
+            groups
+                .entry(binding.group)
+                .or_default()
+                .push(BindingReflection {
+                    binding: binding.binding,
+                    name,
+                    ty,
+                });
+        }
+        let bind_groups = groups
+            .into_iter()
+            .map(|(group, mut entries)| {
+                entries.sort_by_key(|e| e.binding);
+                BindGroupReflection { group, entries }
+            })
+            .collect();
+
+        // compute shaders and fragment shaders that share a vertex shader have no vertex inputs.
+        let has_vertex_stage = module
+            .entry_points
+            .iter()
+            .any(|e| e.stage == naga::ShaderStage::Vertex);
+        let vs_main = module
+            .entry_points
+            .iter()
+            .find(|e| e.stage == naga::ShaderStage::Vertex && e.name == "vs_main");
+        if vs_main.is_none() && has_vertex_stage {
+            anyhow::bail!("shader has no vertex entry point vs_main");
+        }
+        let mut vertex_inputs: Vec<VertexInputReflection> = vec![];
+        for arg in vs_main.iter().flat_map(|e| e.function.arguments.iter()) {
+            let name = arg.name.clone().unwrap_or_default();
+            match (&arg.binding, &module.types[arg.ty].inner) {
+                (Some(binding), inner) => {
+                    collect_vertex_input(&mut vertex_inputs, binding, name, inner)?
+                }
+                // vertex input struct, e.g. `fn vs_main(vertex: Vertex)`
+                (None, naga::TypeInner::Struct { members, .. }) => {
+                    for member in members {
+                        let Some(binding) = &member.binding else {
+                            continue;
+                        };
+                        let name = member.name.clone().unwrap_or_default();
+                        let inner = &module.types[member.ty].inner;
+                        collect_vertex_input(&mut vertex_inputs, binding, name, inner)?;
+                    }
+                }
+                (None, _) => anyhow::bail!("vs_main argument {name} has no binding"),
+            }
+        }
+        vertex_inputs.sort_by_key(|e| e.location);
+
+        Ok(ShaderReflection {
+            bind_groups,
+            vertex_inputs,
+        })
+    }
+
+    /// Stride of a tightly packed vertex with all vertex inputs in location order.
+    pub fn vertex_stride(&self) -> u64 {
+        self.vertex_inputs.iter().map(|e| e.format.size()).sum()
+    }
+
+    pub fn bind_group(&self, group: u32) -> Option<&BindGroupReflection> {
+        self.bind_groups.iter().find(|g| g.group == group)
+    }
+}
+
+fn binding_type(
+    module: &naga::Module,
+    global: &naga::GlobalVariable,
+) -> anyhow::Result<BindingReflectionType> {
+    let inner = &module.types[global.ty].inner;
+    let ty = match global.space {
+        naga::AddressSpace::Uniform => {
+            let members = match inner {
+                naga::TypeInner::Struct { members, .. } => members
+                    .iter()
+                    .map(|m| UniformMember {
+                        name: m.name.clone().unwrap_or_default(),
+                        offset: m.offset,
+                        size: module.types[m.ty].inner.size(module.to_ctx()),
+                    })
+                    .collect(),
+                _ => vec![],
+            };
+            BindingReflectionType::Uniform {
+                size: inner.size(module.to_ctx()),
+                members,
+            }
+        }
+        naga::AddressSpace::Storage { access } => BindingReflectionType::Storage {
+            size: inner.size(module.to_ctx()),
+            read_only: !access.contains(naga::StorageAccess::STORE),
+        },
+        naga::AddressSpace::Handle => match *inner {
+            naga::TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            } => {
+                let sample_type = match class {
+                    naga::ImageClass::Sampled { kind, multi: false } => match kind {
+                        naga::ScalarKind::Float => {
+                            wgpu::TextureSampleType::Float { filterable: true }
+                        }
+                        naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                        naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                        naga::ScalarKind::Bool => anyhow::bail!("bool textures do not exist"),
+                    },
+                    naga::ImageClass::Depth { multi: false } => wgpu::TextureSampleType::Depth,
+                    _ => anyhow::bail!("multisampled and storage textures are not supported"),
+                };
+                let view_dimension = match (dim, arrayed) {
+                    (naga::ImageDimension::D1, false) => wgpu::TextureViewDimension::D1,
+                    (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                    (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                    (naga::ImageDimension::D3, false) => wgpu::TextureViewDimension::D3,
+                    (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                    (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+                    _ => anyhow::bail!("unsupported texture dimension {dim:?}"),
+                };
+                BindingReflectionType::Texture(sample_type, view_dimension)
+            }
+            naga::TypeInner::Sampler { comparison } => {
+                BindingReflectionType::Sampler(match comparison {
+                    true => wgpu::SamplerBindingType::Comparison,
+                    false => wgpu::SamplerBindingType::Filtering,
+                })
+            }
+            _ => anyhow::bail!("unsupported handle type"),
+        },
+        space => anyhow::bail!("unsupported address space {space:?}"),
+    };
+    Ok(ty)
+}
+
+fn collect_vertex_input(
+    inputs: &mut Vec<VertexInputReflection>,
+    binding: &naga::Binding,
+    name: String,
+    inner: &naga::TypeInner,
+) -> anyhow::Result<()> {
+    // builtins like `@builtin(vertex_index)` are not part of the vertex buffer.
+    let naga::Binding::Location { location, .. } = *binding else {
+        return Ok(());
+    };
+    let format = vertex_format(inner)
+        .ok_or_else(|| anyhow!("vertex input {name} has an unsupported type {inner:?}"))?;
+    inputs.push(VertexInputReflection {
+        location,
+        name,
+        format,
+    });
+    Ok(())
+}
+
+fn vertex_format(inner: &naga::TypeInner) -> Option<wgpu::VertexFormat> {
+    use naga::{ScalarKind as K, VectorSize as S};
+    use wgpu::VertexFormat as F;
+    let format = match *inner {
+        naga::TypeInner::Scalar { kind, width: 4 } => match kind {
+            K::Float => F::Float32,
+            K::Sint => F::Sint32,
+            K::Uint => F::Uint32,
+            K::Bool => return None,
+        },
+        naga::TypeInner::Vector {
+            size,
+            kind,
+            width: 4,
+        } => match (kind, size) {
+            (K::Float, S::Bi) => F::Float32x2,
+            (K::Float, S::Tri) => F::Float32x3,
+            (K::Float, S::Quad) => F::Float32x4,
+            (K::Sint, S::Bi) => F::Sint32x2,
+            (K::Sint, S::Tri) => F::Sint32x3,
+            (K::Sint, S::Quad) => F::Sint32x4,
+            (K::Uint, S::Bi) => F::Uint32x2,
+            (K::Uint, S::Tri) => F::Uint32x3,
+            (K::Uint, S::Quad) => F::Uint32x4,
+            (K::Bool, _) => return None,
+        },
+        _ => return None,
+    };
+    Some(format)
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Interface
+// /////////////////////////////////////////////////////////////////////////////
+
+impl CustomMaterial {
+    /// Sets a uniform, either a whole binding by its name (`"settings"`), or a single struct field (`"settings.tint"`).
+    ///
+    /// Panics if there is no such uniform or the size of `value` does not match.
+    pub fn set_uniform<T: bytemuck::Pod>(&mut self, name: &str, value: T) {
+        self.set_uniform_bytes(name, bytemuck::bytes_of(&value))
+    }
+
+    pub fn set_uniform_bytes(&mut self, name: &str, bytes: &[u8]) {
+        let (binding_name, member_name) = match name.split_once('.') {
+            Some((b, m)) => (b, Some(m)),
+            None => (name, None),
+        };
+        let uniform = self
+            .groups
+            .iter_mut()
+            .flat_map(|g| g.uniforms.iter_mut())
+            .find(|u| u.name == binding_name)
+            .unwrap_or_else(|| panic!("material {} has no uniform {binding_name}", self.label));
+        let (offset, size) = match member_name {
+            None => (0, uniform.data.len()),
+            Some(member_name) => {
+                let member = uniform
+                    .members
+                    .iter()
+                    .find(|m| m.name == member_name)
+                    .unwrap_or_else(|| panic!("uniform {binding_name} has no field {member_name}"));
+                (member.offset as usize, member.size as usize)
+            }
+        };
+        assert_eq!(
+            bytes.len(),
+            size,
+            "uniform {name} has a size of {size} bytes, but got {} bytes",
+            bytes.len()
+        );
+        uniform.data[offset..offset + size].copy_from_slice(bytes);
+        uniform.changed = true;
+    }
+
+    /// Binds textures to a bind group that contains texture bindings. The textures are assigned to the texture
+    /// bindings in binding order, every sampler binding uses the sampler of the texture bound right before it.
+    pub fn set_textures(&mut self, device: &wgpu::Device, group: u32, textures: &[&Texture]) {
+        let label = &self.label;
+        let g = self
+            .groups
+            .iter_mut()
+            .find(|g| g.reflection.group == group)
+            .unwrap_or_else(|| panic!("material {label} has no bind group {group}"));
+        let texture_count = g
+            .reflection
+            .entries
+            .iter()
+            .filter(|e| matches!(e.ty, BindingReflectionType::Texture(..)))
+            .count();
+        assert_eq!(
+            textures.len(),
+            texture_count,
+            "bind group {group} of material {label} has {texture_count} textures"
+        );
+        g.bind_group = Some(g.create_bind_group(device, label, textures));
+    }
+
+    /// Draws immediate geometry this frame. `V` needs to match the vertex inputs of `vs_main` (tightly packed, in
+    /// location order).
+    pub fn draw<V: bytemuck::Pod>(&mut self, vertices: &[V], indices: &[u32]) {
+        assert_eq!(
+            std::mem::size_of::<V>() as u64,
+            self.reflection.vertex_stride(),
+            "vertex type does not match the vertex inputs of material {}",
+            self.label
+        );
+        let base = (self.vertices.len() as u64 / self.reflection.vertex_stride().max(1)) as u32;
+        self.vertices
+            .extend_from_slice(bytemuck::cast_slice(vertices));
+        self.indices.extend(indices.iter().map(|i| i + base));
+    }
+
+    pub fn reflection(&self) -> &ShaderReflection {
+        &self.reflection
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Module
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Renderer for a user provided wgsl shader. The pipeline layout, uniform buffers and vertex layout are built from
+/// the reflection of the shader, so no rust code is needed per shader.
+///
+/// Conventions: `@group(0)` is the camera (like in all other shaders), all other groups are owned by the material.
+/// Uniforms are set by name with `set_uniform`, textures with `set_textures`.
+pub struct CustomMaterial {
+    label: String,
+    reflection: ShaderReflection,
+    pipeline: wgpu::RenderPipeline,
+    groups: Vec<MaterialBindGroup>,
+    /// immediate geometry, cleared every frame
+    vertices: Vec<u8>,
+    indices: Vec<u32>,
+    render_data: RenderData,
+}
+
+impl CustomMaterial {
+    pub fn new(
+        ctx: &GraphicsContext,
+        camera: &Camera3dGR,
+        label: impl Into<String>,
+        wgsl: &str,
+    ) -> anyhow::Result<Self> {
+        let label: String = label.into();
+        let device = &ctx.device;
+        let reflection = ShaderReflection::from_wgsl(wgsl)?;
+
+        let mut groups: Vec<MaterialBindGroup> = vec![];
+        for (i, group) in reflection.bind_groups.iter().enumerate() {
+            if group.group != i as u32 {
+                anyhow::bail!(
+                    "material {label}: bind groups need to be contiguous, but group {i} is missing"
+                );
+            }
+            if group.group == 0 {
+                let is_camera = matches!(
+                    group.entries.as_slice(),
+                    [BindingReflection {
+                        binding: 0,
+                        ty: BindingReflectionType::Uniform { .. },
+                        ..
+                    }]
+                );
+                if !is_camera {
+                    anyhow::bail!("material {label}: @group(0) is reserved for the camera uniform");
+                }
+                continue;
+            }
+            groups.push(MaterialBindGroup::new(device, &label, group)?);
+        }
+
+        let pipeline = create_render_pipeline(
+            device,
+            &label,
+            wgsl,
+            &reflection,
+            camera,
+            &groups,
+            ctx.msaa_sample_count,
+        );
+        Ok(CustomMaterial {
+            label,
+            reflection,
+            pipeline,
+            groups,
+            vertices: vec![],
+            indices: vec![],
+            render_data: RenderData::new(device),
+        })
+    }
+
+    pub fn render<'encoder>(
+        &'encoder self,
+        render_pass: &mut wgpu::RenderPass<'encoder>,
+        camera: &'encoder Camera3dGR,
+    ) {
+        if self.render_data.index_count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera.bind_group(), &[]);
+        for g in self.groups.iter() {
+            let bind_group = g.bind_group.as_ref().unwrap_or_else(|| {
+                panic!(
+                    "material {}: no textures set for bind group {}",
+                    self.label, g.reflection.group
+                )
+            });
+            render_pass.set_bind_group(g.reflection.group, bind_group, &[]);
+        }
+        render_pass.set_vertex_buffer(0, self.render_data.vertex_buffer.buffer().slice(..));
+        render_pass.set_index_buffer(
+            self.render_data.index_buffer.buffer().slice(..),
+            wgpu::IndexFormat::Uint32,
+        );
+        render_pass.draw_indexed(0..self.render_data.index_count, 0, 0..1);
+    }
+}
+
+impl Prepare for CustomMaterial {
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _encoder: &mut wgpu::CommandEncoder,
+    ) {
+        for uniform in self.groups.iter_mut().flat_map(|g| g.uniforms.iter_mut()) {
+            if uniform.changed {
+                uniform.changed = false;
+                queue.write_buffer(&uniform.buffer, 0, &uniform.data);
+            }
+        }
+        self.render_data
+            .vertex_buffer
+            .prepare(&self.vertices, device, queue);
+        self.render_data
+            .index_buffer
+            .prepare(&self.indices, device, queue);
+        self.render_data.index_count = self.indices.len() as u32;
+        self.vertices.clear();
+        self.indices.clear();
+    }
+}
+
+/// A bind group of the material (not the camera), with cpu side copies of its uniforms.
+struct MaterialBindGroup {
+    reflection: BindGroupReflection,
+    layout: wgpu::BindGroupLayout,
+    uniforms: Vec<MaterialUniform>,
+    /// None until textures are set, if the group contains textures.
+    bind_group: Option<wgpu::BindGroup>,
+}
+
+struct MaterialUniform {
+    binding: u32,
+    name: String,
+    members: Vec<UniformMember>,
+    data: Vec<u8>,
+    buffer: wgpu::Buffer,
+    changed: bool,
+}
+
+impl MaterialBindGroup {
+    fn new(
+        device: &wgpu::Device,
+        label: &str,
+        reflection: &BindGroupReflection,
+    ) -> anyhow::Result<Self> {
+        let mut entries: Vec<wgpu::BindGroupLayoutEntry> = vec![];
+        let mut uniforms: Vec<MaterialUniform> = vec![];
+        let mut has_textures = false;
+        for e in reflection.entries.iter() {
+            let ty = match &e.ty {
+                BindingReflectionType::Uniform { size, members } => {
+                    uniforms.push(MaterialUniform {
+                        binding: e.binding,
+                        name: e.name.clone(),
+                        members: members.clone(),
+                        data: vec![0; *size as usize],
+                        buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                            label: Some(&format!("{label} {} UniformBuffer", e.name)),
+                            size: *size as u64,
+                            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                            mapped_at_creation: false,
+                        }),
+                        changed: true,
+                    });
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    }
+                }
+                BindingReflectionType::Storage { .. } => anyhow::bail!(
+                    "material {label}: storage buffers are not supported ({})",
+                    e.name
+                ),
+                BindingReflectionType::Texture(sample_type, view_dimension) => {
+                    has_textures = true;
+                    wgpu::BindingType::Texture {
+                        sample_type: *sample_type,
+                        view_dimension: *view_dimension,
+                        multisampled: false,
+                    }
+                }
+                BindingReflectionType::Sampler(sampler) => {
+                    has_textures = true;
+                    wgpu::BindingType::Sampler(*sampler)
+                }
+            };
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: e.binding,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty,
+                count: None,
+            });
+        }
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(&format!("{label} BindGroupLayout {}", reflection.group)),
+            entries: &entries,
+        });
+        let mut group = MaterialBindGroup {
+            reflection: reflection.clone(),
+            layout,
+            uniforms,
+            bind_group: None,
+        };
+        if !has_textures {
+            group.bind_group = Some(group.create_bind_group(device, label, &[]));
+        }
+        Ok(group)
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        textures: &[&Texture],
+    ) -> wgpu::BindGroup {
+        let mut textures = textures.iter();
+        let mut last_texture: Option<&Texture> = None;
+        let entries: Vec<wgpu::BindGroupEntry> = self
+            .reflection
+            .entries
+            .iter()
+            .map(|e| {
+                let resource = match e.ty {
+                    BindingReflectionType::Uniform { .. } => self
+                        .uniforms
+                        .iter()
+                        .find(|u| u.binding == e.binding)
+                        .unwrap()
+                        .buffer
+                        .as_entire_binding(),
+                    BindingReflectionType::Texture(..) => {
+                        let texture = *textures.next().unwrap();
+                        last_texture = Some(texture);
+                        wgpu::BindingResource::TextureView(&texture.view)
+                    }
+                    BindingReflectionType::Sampler(_) => {
+                        let texture = last_texture.unwrap_or_else(|| {
+                            panic!("sampler {} has no texture bound before it", e.name)
+                        });
+                        wgpu::BindingResource::Sampler(&texture.sampler)
+                    }
+                    BindingReflectionType::Storage { .. } => unreachable!(),
+                };
+                wgpu::BindGroupEntry {
+                    binding: e.binding,
+                    resource,
+                }
+            })
+            .collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{label} BindGroup {}", self.reflection.group)),
+            layout: &self.layout,
+            entries: &entries,
+        })
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Render Pipeline
+// /////////////////////////////////////////////////////////////////////////////
+
+/// buffers for immediate geometry
+struct RenderData {
+    index_count: u32,
+    vertex_buffer: GrowableBuffer<u8>,
+    index_buffer: GrowableBuffer<u32>,
+}
+
+impl RenderData {
+    fn new(device: &wgpu::Device) -> Self {
+        Self {
+            index_count: 0,
+            vertex_buffer: GrowableBuffer::new(device, 4096, BufferUsages::VERTEX),
+            index_buffer: GrowableBuffer::new(device, 512, BufferUsages::INDEX),
+        }
+    }
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    wgsl: &str,
+    reflection: &ShaderReflection,
+    camera: &Camera3dGR,
+    groups: &[MaterialBindGroup],
+    msaa_sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let shader_module = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(&format!("{label} ShaderModule")),
+        source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+    });
+
+    let mut offset: u64 = 0;
+    let attributes: Vec<wgpu::VertexAttribute> = reflection
+        .vertex_inputs
+        .iter()
+        .map(|e| {
+            let attribute = wgpu::VertexAttribute {
+                format: e.format,
+                offset,
+                shader_location: e.location,
+            };
+            offset += e.format.size();
+            attribute
+        })
+        .collect();
+    let vertex_buffers_layout = &[wgpu::VertexBufferLayout {
+        array_stride: reflection.vertex_stride(),
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &attributes,
+    }];
+
+    let mut bind_group_layouts: Vec<&wgpu::BindGroupLayout> = vec![camera.bind_group_layout()];
+    bind_group_layouts.extend(groups.iter().map(|g| &g.layout));
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(&format!("{label} PipelineLayout")),
+        bind_group_layouts: &bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(&format!("{label} Pipeline")),
+        layout: Some(&layout),
+        vertex: VertexState {
+            module: &shader_module,
+            entry_point: "vs_main",
+            buffers: vertex_buffers_layout,
+        },
+        fragment: Some(FragmentState {
+            module: &shader_module,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: HDR_COLOR_FORMAT,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: msaa_sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
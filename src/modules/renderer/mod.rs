@@ -4,16 +4,33 @@ pub mod screen_textures;
 pub use screen_textures::{DepthTexture, HdrTexture, ScreenTextures, ScreenVertexShader};
 
 pub mod bloom;
-pub use bloom::{Bloom, BloomSettings};
+pub use bloom::{Bloom, BloomQuality, BloomSettings};
+
+pub mod push_constants;
+pub use push_constants::{PipelineSettings, PushConstantBlock};
 
 pub mod tone_mapping;
 pub use tone_mapping::AcesToneMapping;
 
+pub mod render_targets;
+pub use render_targets::{RenderTarget, RenderTargetBinding, RenderTargets};
+
+pub mod render_scale;
+pub use render_scale::{AutoRenderScale, RenderScale, UpscaleFilter};
+
 pub mod gizmos;
-pub use gizmos::Gizmos;
+pub use gizmos::{GizmoDepth, Gizmos};
 
 pub mod color_mesh;
-pub use color_mesh::ColorMeshRenderer;
+pub use color_mesh::{
+    ColorMeshInstance, ColorMeshRenderer, DirectionalLight, StaticColorMesh, StaticMeshBuilder,
+};
+
+pub mod trail;
+pub use trail::{StripPoint, Trail, TrailRenderer};
+
+pub mod tilemap;
+pub use tilemap::{TilemapChunks, TilemapRenderer};
 
 pub mod ui_rect;
 pub use ui_rect::UiRectRenderer;
@@ -21,12 +38,24 @@ pub use ui_rect::UiRectRenderer;
 pub mod world_rect;
 pub use world_rect::WorldRectRenderer;
 
+pub mod world_text;
+pub use world_text::{DistanceFade, WorldLabel, WorldTextRenderer, WorldTextStats};
+
 pub mod text_renderer;
 pub use text_renderer::TextRenderer;
 
-pub const SURFACE_COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+pub mod env_capture;
+pub use env_capture::{CaptureScene, CubeFace, EnvironmentCapture, HdrCubemap};
+
+pub mod texture_debug;
+pub use texture_debug::{DebugChannel, TextureDebugSettings, TextureDebugView};
+
+pub mod material;
+pub use material::{CustomMaterial, ShaderReflection};
+
 pub const HDR_COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
 pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// Preferred msaa sample count, the actual one is `GraphicsContext::msaa_sample_count`.
 pub const MSAA_SAMPLE_COUNT: u32 = 4;
 
 pub struct Attribute {
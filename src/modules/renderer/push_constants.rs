@@ -0,0 +1,195 @@
+use std::borrow::Cow;
+
+use crate::elements::UniformBuffer;
+
+/// Capabilities of the device that decide how pipelines are built, see `GraphicsContext::pipeline_settings`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineSettings {
+    /// max size of a push constant block, 0 if push constants are not supported (e.g. WebGPU and some gl devices).
+    pub max_push_constant_size: u32,
+}
+
+impl PipelineSettings {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let max_push_constant_size =
+            match device.features().contains(wgpu::Features::PUSH_CONSTANTS) {
+                true => device.limits().max_push_constant_size,
+                false => 0,
+            };
+        PipelineSettings {
+            max_push_constant_size,
+        }
+    }
+
+    /// Forces the uniform fallback of every `PushConstantBlock`.
+    pub const WITHOUT_PUSH_CONSTANTS: PipelineSettings = PipelineSettings {
+        max_push_constant_size: 0,
+    };
+
+    pub fn supports_push_constants(&self, size: u32) -> bool {
+        size <= self.max_push_constant_size
+    }
+}
+
+/// A small block of per-draw data, e.g. flags or an index, passed as push constants where the device supports
+/// them and as a uniform buffer otherwise.
+///
+/// The shader declares it as push constant, `PushConstantBlock::wgsl` turns that into a uniform at `group` if needed:
+/// ```wgsl
+/// var<push_constant> pc: PushConstants;
+/// ```
+/// In the fallback only the last `update` before a submit is seen by all passes of it, so the value can change
+/// per frame, but not per draw. Use a `DynamicUniformBuffer` for values that differ per draw.
+pub struct PushConstantBlock<T: bytemuck::Pod + PartialEq> {
+    stages: wgpu::ShaderStages,
+    value: T,
+    fallback: Option<UniformFallback<T>>,
+}
+
+struct UniformFallback<T: bytemuck::Pod + PartialEq> {
+    group: u32,
+    uniform: UniformBuffer<T>,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl<T: bytemuck::Pod + PartialEq> PushConstantBlock<T> {
+    const SIZE: u32 = std::mem::size_of::<T>() as u32;
+
+    /// `group` is the bind group index of the uniform in the fallback, the one after the other bind groups.
+    pub fn new(
+        device: &wgpu::Device,
+        settings: PipelineSettings,
+        stages: wgpu::ShaderStages,
+        group: u32,
+        value: T,
+    ) -> Self {
+        let fallback = (!settings.supports_push_constants(Self::SIZE)).then(|| {
+            let uniform = UniformBuffer::new(value, device);
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("PushConstantBlock fallback BindGroupLayout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: stages,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("PushConstantBlock fallback BindGroup"),
+                layout: &layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.buffer().as_entire_binding(),
+                }],
+            });
+            UniformFallback {
+                group,
+                uniform,
+                layout,
+                bind_group,
+            }
+        });
+        PushConstantBlock {
+            stages,
+            value,
+            fallback,
+        }
+    }
+
+    pub fn is_push_constant(&self) -> bool {
+        self.fallback.is_none()
+    }
+
+    /// Replaces the `var<push_constant>` declaration with a uniform binding in the fallback.
+    pub fn wgsl<'a>(&self, wgsl: &'a str) -> Cow<'a, str> {
+        match &self.fallback {
+            None => Cow::Borrowed(wgsl),
+            Some(fallback) => Cow::Owned(uniform_wgsl(wgsl, fallback.group)),
+        }
+    }
+
+    /// The bind group layouts of a pipeline that uses the block, `layouts` are the groups before it.
+    pub fn bind_group_layouts<'a>(
+        &'a self,
+        layouts: &[&'a wgpu::BindGroupLayout],
+    ) -> Vec<&'a wgpu::BindGroupLayout> {
+        let mut layouts = layouts.to_vec();
+        if let Some(fallback) = &self.fallback {
+            assert_eq!(
+                layouts.len(),
+                fallback.group as usize,
+                "the fallback uniform of a PushConstantBlock must come after the other bind groups"
+            );
+            layouts.push(&fallback.layout);
+        }
+        layouts
+    }
+
+    /// The push constant ranges of a pipeline that uses the block, empty in the fallback.
+    pub fn push_constant_ranges(&self) -> Vec<wgpu::PushConstantRange> {
+        match self.fallback {
+            Some(_) => vec![],
+            None => vec![wgpu::PushConstantRange {
+                stages: self.stages,
+                range: 0..Self::SIZE,
+            }],
+        }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn update(&mut self, value: T, queue: &wgpu::Queue) {
+        self.value = value;
+        if let Some(fallback) = &mut self.fallback {
+            fallback.uniform.update_and_prepare(value, queue);
+        }
+    }
+
+    /// Sets the push constants or binds the uniform, after `set_pipeline`.
+    pub fn set<'e>(&'e self, render_pass: &mut wgpu::RenderPass<'e>) {
+        match &self.fallback {
+            None => {
+                render_pass.set_push_constants(self.stages, 0, bytemuck::cast_slice(&[self.value]))
+            }
+            Some(fallback) => render_pass.set_bind_group(fallback.group, &fallback.bind_group, &[]),
+        }
+    }
+}
+
+fn uniform_wgsl(wgsl: &str, group: u32) -> String {
+    wgsl.replace(
+        "var<push_constant>",
+        &format!("@group({group}) @binding(0) var<uniform>"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_fallback_shaders_are_valid() {
+        for (wgsl, group) in [
+            (include_str!("bloom.wgsl"), 3),
+            (include_str!("tonemapping.wgsl"), 1),
+        ] {
+            let wgsl = uniform_wgsl(wgsl, group);
+            assert!(!wgsl.contains("push_constant"));
+            let module = wgpu::naga::front::wgsl::parse_str(&wgsl)
+                .unwrap_or_else(|e| panic!("{}", e.emit_to_string(&wgsl)));
+            wgpu::naga::valid::Validator::new(
+                wgpu::naga::valid::ValidationFlags::all(),
+                wgpu::naga::valid::Capabilities::all(),
+            )
+            .validate(&module)
+            .unwrap();
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use winit::dpi::PhysicalSize;
+
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+pub const MAX_RENDER_SCALE: f32 = 2.0;
+
+/// How the hdr texture is scaled to the surface in the tone mapping pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpscaleFilter {
+    Bilinear,
+    /// Bilinear, followed by a contrast adaptive sharpening (like the RCAS pass of FSR1).
+    /// `sharpness` is between 0 and 1.
+    Sharpen {
+        sharpness: f32,
+    },
+}
+
+/// Lowers the render scale if frames take longer than `target_frame_time`, and raises it again if there is headroom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoRenderScale {
+    pub target_frame_time: Duration,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// the scale is changed by this much at most once per `interval`.
+    pub step: f32,
+    pub interval: Duration,
+}
+
+impl Default for AutoRenderScale {
+    fn default() -> Self {
+        Self {
+            target_frame_time: Duration::from_secs_f64(1.0 / 60.0),
+            min_scale: MIN_RENDER_SCALE,
+            max_scale: 1.0,
+            step: 0.05,
+            interval: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Sizes the hdr and depth textures independently of the window: the main pass renders at `scale` times the
+/// surface size and the tone mapping pass scales the result to the surface with `upscale`.
+#[derive(Debug, Clone)]
+pub struct RenderScale {
+    /// between `MIN_RENDER_SCALE` and `MAX_RENDER_SCALE`, 1.0 is the surface size.
+    pub scale: f32,
+    pub upscale: UpscaleFilter,
+    /// if set, `scale` is adjusted every frame from the frame times.
+    pub auto: Option<AutoRenderScale>,
+    /// the scale the screen textures currently have.
+    applied_scale: f32,
+    /// the virtual resolution of `AspectMode::PixelPerfect`, replaces the scaled size.
+    fixed_size: Option<PhysicalSize<u32>>,
+    smoothed_frame_time: f64,
+    since_adjustment: Duration,
+}
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            upscale: UpscaleFilter::Bilinear,
+            auto: None,
+            applied_scale: 1.0,
+            fixed_size: None,
+            smoothed_frame_time: 0.0,
+            since_adjustment: Duration::ZERO,
+        }
+    }
+}
+
+impl RenderScale {
+    /// The size of the screen textures for this surface size. Scaled down (keeping the aspect ratio) to fit into
+    /// `max_dimension`, the `max_texture_dimension_2d` limit of the device.
+    pub fn render_size(
+        &self,
+        surface_size: PhysicalSize<u32>,
+        max_dimension: u32,
+    ) -> PhysicalSize<u32> {
+        let (width, height) = match self.fixed_size {
+            Some(size) => (size.width as f32, size.height as f32),
+            None => (
+                surface_size.width as f32 * self.applied_scale,
+                surface_size.height as f32 * self.applied_scale,
+            ),
+        };
+        let fit = (max_dimension as f32 / width.max(height)).min(1.0);
+        PhysicalSize::new(
+            ((width * fit).round() as u32).clamp(1, max_dimension),
+            ((height * fit).round() as u32).clamp(1, max_dimension),
+        )
+    }
+
+    /// Called once per frame by `DefaultModules::begin_frame`. Adjusts the scale if `auto` is set and returns
+    /// true if the scale changed, such that the screen textures have to be recreated.
+    pub fn update(&mut self, frame_time: Duration) -> bool {
+        if let Some(auto) = &self.auto {
+            // exponential moving average, such that single slow frames do not change the scale.
+            let frame_time = frame_time.as_secs_f64();
+            self.smoothed_frame_time = if self.smoothed_frame_time == 0.0 {
+                frame_time
+            } else {
+                self.smoothed_frame_time * 0.9 + frame_time * 0.1
+            };
+            self.since_adjustment += Duration::from_secs_f64(frame_time);
+            if self.since_adjustment >= auto.interval {
+                self.since_adjustment = Duration::ZERO;
+                let target = auto.target_frame_time.as_secs_f64();
+                if self.smoothed_frame_time > target * 1.05 {
+                    self.scale -= auto.step;
+                } else if self.smoothed_frame_time < target * 0.8 {
+                    self.scale += auto.step;
+                }
+                self.scale = self.scale.clamp(auto.min_scale, auto.max_scale);
+            }
+        }
+        self.scale = self.scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        if (self.scale - self.applied_scale).abs() < 0.001 {
+            return false;
+        }
+        self.applied_scale = self.scale;
+        true
+    }
+
+    /// Called once per frame by `DefaultModules::begin_frame` with the virtual resolution of
+    /// `AspectMode::PixelPerfect`. Returns true if it changed, such that the screen textures have to be recreated.
+    pub fn set_fixed_size(&mut self, size: Option<(u32, u32)>) -> bool {
+        let size = size.map(|(width, height)| PhysicalSize::new(width, height));
+        if size == self.fixed_size {
+            return false;
+        }
+        self.fixed_size = size;
+        true
+    }
+
+    /// The screen textures have the virtual resolution of `AspectMode::PixelPerfect`.
+    pub fn is_pixel_perfect(&self) -> bool {
+        self.fixed_size.is_some()
+    }
+
+    /// The sharpness passed to the tone mapping pass, 0 for bilinear upscaling.
+    pub fn sharpness(&self) -> f32 {
+        match self.upscale {
+            UpscaleFilter::Bilinear => 0.0,
+            UpscaleFilter::Sharpen { sharpness } => sharpness.clamp(0.0, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_scale_follows_frame_times() {
+        let mut render_scale = RenderScale {
+            auto: Some(AutoRenderScale::default()),
+            ..Default::default()
+        };
+        let slow = Duration::from_millis(30);
+        let fast = Duration::from_millis(5);
+
+        // frames are too slow: the scale goes down once per interval.
+        let changes = (0..60).filter(|_| render_scale.update(slow)).count();
+        assert_eq!(changes, 3);
+        assert!((render_scale.scale - 0.85).abs() < 0.001);
+
+        // a single fast frame does not change anything.
+        assert!(!render_scale.update(fast));
+        for _ in 0..1000 {
+            render_scale.update(fast);
+        }
+        // not above the `max_scale` of the auto settings.
+        assert_eq!(render_scale.scale, 1.0);
+        assert!(!render_scale.update(fast));
+    }
+
+    #[test]
+    fn render_size_fits_into_the_texture_limit() {
+        let mut render_scale = RenderScale {
+            scale: 2.0,
+            ..Default::default()
+        };
+        assert!(render_scale.update(Duration::ZERO));
+        let surface = PhysicalSize::new(3000, 1500);
+        assert_eq!(
+            render_scale.render_size(surface, 8192),
+            PhysicalSize::new(6000, 3000)
+        );
+        assert_eq!(
+            render_scale.render_size(surface, 4096),
+            PhysicalSize::new(4096, 2048)
+        );
+    }
+}
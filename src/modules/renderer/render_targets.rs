@@ -0,0 +1,101 @@
+use std::{borrow::Cow, collections::HashMap};
+
+/// Textures that passes publish by name, such that other modules and plugins can read them without hard-wired
+/// fields, e.g. a plugin that needs the depth buffer or the ssao result of another plugin.
+///
+/// The `DefaultModules` publish `DEPTH` and `HDR_RESOLVE` whenever the screen textures are (re)created. Consumers keep
+/// a `RenderTargetBinding`, which recreates their bind group when the texture was published again, e.g. on resize.
+#[derive(Debug, Default)]
+pub struct RenderTargets {
+    targets: HashMap<Cow<'static, str>, RenderTarget>,
+    /// incremented for every publish, such that bindings notice replaced textures.
+    generation: u64,
+}
+
+/// A published texture. The view keeps the texture alive, even if the publisher drops it.
+#[derive(Debug)]
+pub struct RenderTarget {
+    pub view: wgpu::TextureView,
+    pub size: wgpu::Extent3d,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    generation: u64,
+}
+
+impl RenderTargets {
+    /// depth buffer of the main pass, multisampled if msaa is enabled.
+    pub const DEPTH: &'static str = "depth";
+    /// the resolved hdr color of the main pass, the input of the post effects.
+    pub const HDR_RESOLVE: &'static str = "hdr_resolve";
+
+    pub fn new() -> Self {
+        RenderTargets::default()
+    }
+
+    /// Publishes a texture under `name`, replacing the previous one. Call again whenever the texture is recreated.
+    pub fn publish(&mut self, name: impl Into<Cow<'static, str>>, texture: &wgpu::Texture) {
+        self.generation += 1;
+        let target = RenderTarget {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            size: texture.size(),
+            format: texture.format(),
+            sample_count: texture.sample_count(),
+            generation: self.generation,
+        };
+        self.targets.insert(name.into(), target);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<RenderTarget> {
+        self.targets.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RenderTarget> {
+        self.targets.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.targets.keys().map(|e| e.as_ref())
+    }
+}
+
+/// A bind group for a render target that is recreated when the target is published again.
+#[derive(Debug)]
+pub struct RenderTargetBinding {
+    pub name: Cow<'static, str>,
+    bind_group: Option<(u64, wgpu::BindGroup)>,
+}
+
+impl RenderTargetBinding {
+    pub fn new(name: impl Into<Cow<'static, str>>) -> Self {
+        RenderTargetBinding {
+            name: name.into(),
+            bind_group: None,
+        }
+    }
+
+    /// Returns the bind group, created with `create` if the target changed since the last call.
+    /// None if nothing is published under the name.
+    pub fn get(
+        &mut self,
+        targets: &RenderTargets,
+        create: impl FnOnce(&RenderTarget) -> wgpu::BindGroup,
+    ) -> Option<&wgpu::BindGroup> {
+        let Some(target) = targets.get(&self.name) else {
+            self.bind_group = None;
+            return None;
+        };
+        if self.generation() != Some(target.generation) {
+            self.bind_group = Some((target.generation, create(target)));
+        }
+        self.bind_group.as_ref().map(|(_, e)| e)
+    }
+
+    /// The bind group of the last `get`, e.g. for `Plugin::render`, where the `RenderTargets` are not available.
+    pub fn current(&self) -> Option<&wgpu::BindGroup> {
+        self.bind_group.as_ref().map(|(_, e)| e)
+    }
+
+    fn generation(&self) -> Option<u64> {
+        self.bind_group.as_ref().map(|(g, _)| *g)
+    }
+}
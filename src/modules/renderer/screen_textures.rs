@@ -1,4 +1,6 @@
-use super::{DEPTH_FORMAT, HDR_COLOR_FORMAT, MSAA_SAMPLE_COUNT};
+use winit::dpi::PhysicalSize;
+
+use super::{RenderTargets, DEPTH_FORMAT, HDR_COLOR_FORMAT};
 use crate::{
     elements::{
         texture::{rgba_bind_group_layout, rgba_bind_group_layout_msaa4},
@@ -9,19 +11,29 @@ use crate::{
 
 pub struct ScreenTextures {
     pub depth_texture: DepthTexture,
+    /// Only used if msaa is enabled, see `GraphicsContext::msaa_sample_count`.
     pub hdr_msaa_texture: HdrTexture,
     pub hdr_resolve_target: HdrTexture,
     pub screen_vertex_shader: ScreenVertexShader,
+    msaa: bool,
+    /// can differ from the surface size, see `RenderScale`.
+    size: PhysicalSize<u32>,
 }
 
 impl ScreenTextures {
     pub fn new(ctx: &GraphicsContext) -> Self {
-        let depth_texture = DepthTexture::create(ctx);
-        let hdr_msaa_texture = HdrTexture::create_screen_sized(ctx, 4);
-        let hdr_resolve_target = HdrTexture::create_screen_sized(ctx, 1);
+        Self::new_sized(ctx, ctx.size)
+    }
+
+    pub fn new_sized(ctx: &GraphicsContext, size: PhysicalSize<u32>) -> Self {
+        let depth_texture = DepthTexture::create_sized(ctx, size);
+        let hdr_msaa_texture = HdrTexture::create_sized(ctx, size, ctx.msaa_sample_count);
+        let hdr_resolve_target = HdrTexture::create_sized(ctx, size, 1);
         let screen_vertex_shader = ScreenVertexShader::new(&ctx.device);
 
         Self {
+            size,
+            msaa: ctx.msaa_sample_count > 1,
             depth_texture,
             hdr_msaa_texture,
             hdr_resolve_target,
@@ -34,9 +46,17 @@ impl ScreenTextures {
         encoder: &'e mut wgpu::CommandEncoder,
         color: Color,
     ) -> wgpu::RenderPass<'e> {
+        // without msaa, render directly into the resolve target.
+        let (view, resolve_target) = match self.msaa {
+            true => (
+                self.hdr_msaa_texture.view(),
+                Some(self.hdr_resolve_target.view()),
+            ),
+            false => (self.hdr_resolve_target.view(), None),
+        };
         let color_attachment = wgpu::RenderPassColorAttachment {
-            view: self.hdr_msaa_texture.view(),
-            resolve_target: Some(self.hdr_resolve_target.view()),
+            view,
+            resolve_target,
             ops: wgpu::Operations {
                 load: wgpu::LoadOp::Clear(color.into()),
                 store: wgpu::StoreOp::Store,
@@ -59,10 +79,28 @@ impl ScreenTextures {
         main_render_pass
     }
 
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Publishes the depth texture and the hdr resolve target, call after the textures are (re)created.
+    pub fn publish(&self, targets: &mut RenderTargets) {
+        targets.publish(RenderTargets::DEPTH, self.depth_texture.texture());
+        targets.publish(
+            RenderTargets::HDR_RESOLVE,
+            self.hdr_resolve_target.texture(),
+        );
+    }
+
     pub fn resize(&mut self, ctx: &GraphicsContext) {
-        self.depth_texture.recreate(ctx);
-        self.hdr_msaa_texture = HdrTexture::create_screen_sized(ctx, MSAA_SAMPLE_COUNT);
-        self.hdr_resolve_target = HdrTexture::create_screen_sized(ctx, 1);
+        self.resize_to(ctx, ctx.size);
+    }
+
+    pub fn resize_to(&mut self, ctx: &GraphicsContext, size: PhysicalSize<u32>) {
+        self.size = size;
+        self.depth_texture = DepthTexture::create_sized(ctx, size);
+        self.hdr_msaa_texture = HdrTexture::create_sized(ctx, size, ctx.msaa_sample_count);
+        self.hdr_resolve_target = HdrTexture::create_sized(ctx, size, 1);
     }
 }
 
@@ -75,19 +113,27 @@ impl DepthTexture {
         &self.0.view
     }
 
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.0.texture
+    }
+
     pub fn create(context: &GraphicsContext) -> Self {
         let config = &context.surface_config;
+        Self::create_sized(context, PhysicalSize::new(config.width, config.height))
+    }
+
+    pub fn create_sized(context: &GraphicsContext, size: PhysicalSize<u32>) -> Self {
         let format = DEPTH_FORMAT;
         let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
+            width: size.width.max(1),
+            height: size.height.max(1),
             depth_or_array_layers: 1,
         };
         let desc = wgpu::TextureDescriptor {
             label: Some("Depth texture"),
             size,
             mip_level_count: 1,
-            sample_count: MSAA_SAMPLE_COUNT,
+            sample_count: context.msaa_sample_count,
             dimension: wgpu::TextureDimension::D2,
             format,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
@@ -135,6 +181,10 @@ impl HdrTexture {
         &self.texture.texture.view
     }
 
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture.texture.texture
+    }
+
     pub fn bind_group(&self) -> &wgpu::BindGroup {
         &self.texture.bind_group
     }
@@ -149,6 +199,19 @@ impl HdrTexture {
         )
     }
 
+    pub fn create_sized(ctx: &GraphicsContext, size: PhysicalSize<u32>, sample_count: u32) -> Self {
+        Self::create(
+            &ctx.device,
+            size.width,
+            size.height,
+            sample_count,
+            format!(
+                "HDR with size {}x{} and sample_count: {sample_count}",
+                size.width, size.height
+            ),
+        )
+    }
+
     pub fn create(
         device: &wgpu::Device,
         mut width: u32,
@@ -204,13 +267,13 @@ impl HdrTexture {
 
         let layout = match sample_count {
             1 => rgba_bind_group_layout(device),
-            4 => rgba_bind_group_layout_msaa4(device),
-            _ => panic!("Sample count {sample_count} not supported"),
+            // the layout does not depend on the count, only on the texture being multisampled.
+            _ => rgba_bind_group_layout_msaa4(device),
         };
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some(&label),
-            layout,
+            layout: &layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -44,6 +44,7 @@ impl TextRenderer {
         }
     }
 
+    /// Lays out and rasterizes the text on every call. For many labels, use `WorldTextRenderer`.
     pub fn draw_world_text(
         &mut self,
         text: DrawText,
@@ -0,0 +1,554 @@
+use std::collections::HashMap;
+
+use crate::{
+    elements::{screen::set_viewport_and_scissor, Rect, UniformBuffer, WgslLayout},
+    modules::GraphicsContext,
+};
+
+use super::{RenderTargetBinding, RenderTargets, ScreenVertexShader};
+
+const HISTOGRAM_BINS: usize = 64;
+/// must match the fraction of the overlay in the shader.
+const HISTOGRAM_HEIGHT: f32 = 0.2;
+
+// /////////////////////////////////////////////////////////////////////////////
+// Interface
+// /////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugChannel {
+    R,
+    G,
+    B,
+    A,
+    #[default]
+    Rgb,
+    /// also used for the histogram of `Rgb`.
+    Luminance,
+}
+
+impl DebugChannel {
+    pub const ALL: [DebugChannel; 6] = [
+        DebugChannel::R,
+        DebugChannel::G,
+        DebugChannel::B,
+        DebugChannel::A,
+        DebugChannel::Rgb,
+        DebugChannel::Luminance,
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureDebugSettings {
+    /// name in the `RenderTargets`, None hides the overlay.
+    pub target: Option<String>,
+    pub channel: DebugChannel,
+    /// values mapped to black and white, e.g. `[0.99, 1.0]` to see details in a depth buffer.
+    /// None maps the min and max of the texture.
+    pub range: Option<[f32; 2]>,
+    /// height of the overlay relative to the viewport, the width follows the aspect ratio of the texture.
+    pub size: f32,
+}
+
+impl Default for TextureDebugSettings {
+    fn default() -> Self {
+        TextureDebugSettings {
+            target: None,
+            channel: DebugChannel::default(),
+            range: None,
+            size: 0.4,
+        }
+    }
+}
+
+impl TextureDebugView {
+    pub fn show(&mut self, target: impl Into<String>) {
+        self.settings.target = Some(target.into());
+    }
+
+    pub fn hide(&mut self) {
+        self.settings.target = None;
+    }
+
+    pub fn egui_texture_debug(&mut self, mut egui_ctx: egui::Context, targets: &RenderTargets) {
+        let mut names: Vec<&str> = targets.names().collect();
+        names.sort();
+        let settings = &mut self.settings;
+        egui::Window::new("Texture Debug").show(&mut egui_ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut settings.target, None, "None");
+                for name in names {
+                    ui.selectable_value(&mut settings.target, Some(name.to_string()), name);
+                }
+            });
+            ui.horizontal(|ui| {
+                for channel in DebugChannel::ALL {
+                    ui.radio_value(&mut settings.channel, channel, format!("{channel:?}"));
+                }
+            });
+            let mut auto_range = settings.range.is_none();
+            if ui.checkbox(&mut auto_range, "Auto Range").changed() {
+                settings.range = (!auto_range).then_some([0.0, 1.0]);
+            }
+            if let Some([min, max]) = &mut settings.range {
+                ui.horizontal(|ui| {
+                    ui.label("Range");
+                    ui.add(egui::DragValue::new(min).speed(0.01));
+                    ui.add(egui::DragValue::new(max).speed(0.01));
+                });
+            }
+            ui.add(egui::Slider::new(&mut settings.size, 0.1..=1.0).text("Size"));
+        });
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Module
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Shows any texture of the `RenderTargets` (depth, hdr, or whatever passes and plugins publish) as an overlay in the
+/// bottom left corner, with a histogram of the selected channel below. The min, max and histogram are computed on
+/// the gpu every frame, from the contents of the previous frame.
+pub struct TextureDebugView {
+    pub settings: TextureDebugSettings,
+    params: UniformBuffer<Params>,
+    stats: wgpu::Buffer,
+    compute_stats: wgpu::BindGroup,
+    render_stats: wgpu::BindGroup,
+    compute_stats_layout: wgpu::BindGroupLayout,
+    render_stats_layout: wgpu::BindGroupLayout,
+    pipelines: HashMap<TextureKind, Pipelines>,
+    binding: RenderTargetBinding,
+    /// kind and size of the texture bound last in `prepare`, None if nothing is shown.
+    current: Option<(TextureKind, [u32; 2])>,
+    surface_format: wgpu::TextureFormat,
+}
+
+impl TextureDebugView {
+    pub fn new(ctx: &GraphicsContext) -> Self {
+        let device = &ctx.device;
+        let stats = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("TextureDebug stats"),
+            size: std::mem::size_of::<Stats>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let stats_layout = |read_only: bool, visibility: wgpu::ShaderStages| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("TextureDebug stats BindGroupLayout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            })
+        };
+        let compute_stats_layout = stats_layout(false, wgpu::ShaderStages::COMPUTE);
+        let render_stats_layout = stats_layout(true, wgpu::ShaderStages::FRAGMENT);
+        let stats_bind_group = |layout: &wgpu::BindGroupLayout| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("TextureDebug stats BindGroup"),
+                layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: stats.as_entire_binding(),
+                }],
+            })
+        };
+        TextureDebugView {
+            settings: TextureDebugSettings::default(),
+            params: UniformBuffer::new(Params::default(), device),
+            compute_stats: stats_bind_group(&compute_stats_layout),
+            render_stats: stats_bind_group(&render_stats_layout),
+            stats,
+            compute_stats_layout,
+            render_stats_layout,
+            pipelines: HashMap::new(),
+            binding: RenderTargetBinding::new(""),
+            current: None,
+            surface_format: ctx.surface_format,
+        }
+    }
+
+    /// Binds the selected texture and computes its min, max and histogram.
+    pub fn prepare(
+        &mut self,
+        ctx: &GraphicsContext,
+        encoder: &mut wgpu::CommandEncoder,
+        screen_vertex_shader: &ScreenVertexShader,
+        targets: &RenderTargets,
+    ) {
+        self.current = None;
+        let Some(name) = &self.settings.target else {
+            return;
+        };
+        let Some(target) = targets.get(name) else {
+            return;
+        };
+        let Some(kind) = TextureKind::of(target) else {
+            log::warn!(
+                "texture debug view can not show {name} with format {:?}",
+                target.format
+            );
+            self.settings.target = None;
+            return;
+        };
+        if self.binding.name != name.as_str() {
+            self.binding = RenderTargetBinding::new(name.clone());
+        }
+        let size = [target.size.width, target.size.height];
+
+        let pipelines = self.pipelines.entry(kind).or_insert_with(|| {
+            Pipelines::new(
+                &ctx.device,
+                kind,
+                &self.compute_stats_layout,
+                &self.render_stats_layout,
+                screen_vertex_shader,
+                self.surface_format,
+            )
+        });
+        let params = &self.params;
+        let Some(texture_bind_group) = self.binding.get(targets, |target| {
+            ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("TextureDebug texture BindGroup"),
+                layout: &pipelines.texture_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&target.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: params.buffer().as_entire_binding(),
+                    },
+                ],
+            })
+        }) else {
+            return;
+        };
+
+        let range = self.settings.range.unwrap_or([0.0, 1.0]);
+        self.params.update_and_prepare(
+            Params {
+                size,
+                channel: self.settings.channel as u32,
+                auto_range: self.settings.range.is_none() as u32,
+                range,
+                _pad: [0.0; 2],
+            },
+            &ctx.queue,
+        );
+        ctx.queue
+            .write_buffer(&self.stats, 0, bytemuck::bytes_of(&Stats::reset()));
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("TextureDebug stats"),
+            timestamp_writes: None,
+        });
+        pass.set_bind_group(0, texture_bind_group, &[]);
+        pass.set_bind_group(1, &self.compute_stats, &[]);
+        let workgroups = [size[0].div_ceil(8), size[1].div_ceil(8)];
+        // the histogram needs the min and max of the first dispatch.
+        for pipeline in [&pipelines.min_max, &pipelines.histogram] {
+            pass.set_pipeline(pipeline);
+            pass.dispatch_workgroups(workgroups[0], workgroups[1], 1);
+        }
+        drop(pass);
+        self.current = Some((kind, size));
+    }
+
+    /// Draws the overlay on top of the surface, in the bottom left corner of the `viewport`.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        surface_view: &wgpu::TextureView,
+        viewport: Rect,
+    ) {
+        let Some((kind, [width, height])) = self.current else {
+            return;
+        };
+        let (Some(pipelines), Some(texture_bind_group)) =
+            (self.pipelines.get(&kind), self.binding.current())
+        else {
+            return;
+        };
+        let rect = overlay_rect(viewport, width, height, self.settings.size);
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("TextureDebug overlay"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: surface_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        set_viewport_and_scissor(&mut pass, rect);
+        pass.set_pipeline(&pipelines.overlay);
+        pass.set_bind_group(0, texture_bind_group, &[]);
+        pass.set_bind_group(1, &self.render_stats, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+/// In the bottom left corner of the viewport, with a small margin. The texture keeps its aspect ratio, the
+/// histogram is added below.
+fn overlay_rect(viewport: Rect, width: u32, height: u32, size: f32) -> Rect {
+    const MARGIN: f32 = 8.0;
+    let total_height = (viewport.height * size).min(viewport.height - 2.0 * MARGIN);
+    let image_height = total_height * (1.0 - HISTOGRAM_HEIGHT);
+    let aspect = width as f32 / height.max(1) as f32;
+    let overlay_width = (image_height * aspect).min(viewport.width - 2.0 * MARGIN);
+    Rect::new(
+        viewport.min_x + MARGIN,
+        viewport.min_y + viewport.height - total_height - MARGIN,
+        overlay_width,
+        total_height,
+    )
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Pipelines
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Textures need a different binding type in the shader, depending on their format and sample count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum TextureKind {
+    Color,
+    ColorMsaa,
+    Depth,
+    DepthMsaa,
+}
+
+impl TextureKind {
+    /// None for integer formats, which can not be shown.
+    fn of(target: &super::RenderTarget) -> Option<Self> {
+        let msaa = target.sample_count > 1;
+        let kind = match target.format.sample_type(None)? {
+            wgpu::TextureSampleType::Depth if msaa => TextureKind::DepthMsaa,
+            wgpu::TextureSampleType::Depth => TextureKind::Depth,
+            wgpu::TextureSampleType::Float { .. } if msaa => TextureKind::ColorMsaa,
+            wgpu::TextureSampleType::Float { .. } => TextureKind::Color,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    fn wgsl_texture_type(self) -> &'static str {
+        match self {
+            TextureKind::Color => "texture_2d<f32>",
+            TextureKind::ColorMsaa => "texture_multisampled_2d<f32>",
+            TextureKind::Depth => "texture_depth_2d",
+            TextureKind::DepthMsaa => "texture_depth_multisampled_2d",
+        }
+    }
+
+    /// the last argument is the mip level, or the sample index for msaa textures.
+    fn wgsl_load(self) -> &'static str {
+        match self {
+            TextureKind::Color | TextureKind::ColorMsaa => "textureLoad(t_input, coord, 0)",
+            TextureKind::Depth | TextureKind::DepthMsaa => {
+                "vec4<f32>(vec3<f32>(textureLoad(t_input, coord, 0)), 1.0)"
+            }
+        }
+    }
+
+    fn binding_type(self) -> wgpu::BindingType {
+        let (sample_type, multisampled) = match self {
+            TextureKind::Color => (wgpu::TextureSampleType::Float { filterable: false }, false),
+            TextureKind::ColorMsaa => (wgpu::TextureSampleType::Float { filterable: false }, true),
+            TextureKind::Depth => (wgpu::TextureSampleType::Depth, false),
+            TextureKind::DepthMsaa => (wgpu::TextureSampleType::Depth, true),
+        };
+        wgpu::BindingType::Texture {
+            sample_type,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled,
+        }
+    }
+
+    /// The shared part and the compute or overlay part of the shader.
+    fn shader_sources(self) -> (String, String) {
+        let wgsl = include_str!("texture_debug.wgsl")
+            .replace("TEXTURE_TYPE", self.wgsl_texture_type())
+            .replace("LOAD", self.wgsl_load());
+        let (common, rest) = wgsl.split_once("// COMPUTE").unwrap();
+        let (compute, overlay) = rest.split_once("// OVERLAY").unwrap();
+        (format!("{common}{compute}"), format!("{common}{overlay}"))
+    }
+}
+
+struct Pipelines {
+    texture_layout: wgpu::BindGroupLayout,
+    min_max: wgpu::ComputePipeline,
+    histogram: wgpu::ComputePipeline,
+    overlay: wgpu::RenderPipeline,
+}
+
+impl Pipelines {
+    fn new(
+        device: &wgpu::Device,
+        kind: TextureKind,
+        compute_stats_layout: &wgpu::BindGroupLayout,
+        render_stats_layout: &wgpu::BindGroupLayout,
+        screen_vertex_shader: &ScreenVertexShader,
+        surface_format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("TextureDebug texture BindGroupLayout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+                    ty: kind.binding_type(),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let (compute_wgsl, overlay_wgsl) = kind.shader_sources();
+        // the reflection does not support multisampled textures, the params are the same for all kinds.
+        UniformBuffer::<Params>::debug_check_layout(&TextureKind::Color.shader_sources().1, 0, 1);
+
+        let compute_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("TextureDebug compute ShaderModule"),
+            source: wgpu::ShaderSource::Wgsl(compute_wgsl.into()),
+        });
+        let compute_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("TextureDebug compute PipelineLayout"),
+            bind_group_layouts: &[&texture_layout, compute_stats_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = |entry_point: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(&format!("TextureDebug {entry_point}")),
+                layout: Some(&compute_layout),
+                module: &compute_module,
+                entry_point,
+            })
+        };
+        let min_max = compute_pipeline("min_max");
+        let histogram = compute_pipeline("histogram");
+
+        let overlay_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("TextureDebug overlay ShaderModule"),
+            source: wgpu::ShaderSource::Wgsl(overlay_wgsl.into()),
+        });
+        let overlay_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("TextureDebug overlay PipelineLayout"),
+            bind_group_layouts: &[&texture_layout, render_stats_layout],
+            push_constant_ranges: &[],
+        });
+        let overlay = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("TextureDebug overlay"),
+            layout: Some(&overlay_layout),
+            vertex: screen_vertex_shader.vertex_state(),
+            fragment: Some(wgpu::FragmentState {
+                module: &overlay_module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Pipelines {
+            texture_layout,
+            min_max,
+            histogram,
+            overlay,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, bytemuck::Zeroable, bytemuck::Pod, WgslLayout)]
+struct Params {
+    size: [u32; 2],
+    channel: u32,
+    auto_range: u32,
+    range: [f32; 2],
+    _pad: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct Stats {
+    /// min and max as order preserving u32, see `to_ordered` in the shader.
+    min: u32,
+    max: u32,
+    max_bin: u32,
+    _pad: u32,
+    bins: [u32; HISTOGRAM_BINS],
+}
+
+impl Stats {
+    fn reset() -> Self {
+        Stats {
+            min: u32::MAX,
+            max: 0,
+            max_bin: 0,
+            _pad: 0,
+            bins: [0; HISTOGRAM_BINS],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shader_variants_are_valid() {
+        for kind in [
+            TextureKind::Color,
+            TextureKind::ColorMsaa,
+            TextureKind::Depth,
+            TextureKind::DepthMsaa,
+        ] {
+            let (compute, overlay) = kind.shader_sources();
+            for wgsl in [compute, overlay] {
+                let module = wgpu::naga::front::wgsl::parse_str(&wgsl)
+                    .unwrap_or_else(|e| panic!("{kind:?}: {}", e.emit_to_string(&wgsl)));
+                wgpu::naga::valid::Validator::new(
+                    wgpu::naga::valid::ValidationFlags::all(),
+                    wgpu::naga::valid::Capabilities::all(),
+                )
+                .validate(&module)
+                .unwrap_or_else(|e| panic!("{kind:?}: {e:?}"));
+            }
+        }
+
+        let viewport = Rect::new(0.0, 0.0, 1000.0, 500.0);
+        let rect = overlay_rect(viewport, 200, 100, 0.5);
+        assert_eq!(rect.height, 250.0);
+        assert_eq!(rect.width, 400.0);
+        assert_eq!(rect.min_y + rect.height, 492.0);
+    }
+}
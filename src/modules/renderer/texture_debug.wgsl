@@ -0,0 +1,163 @@
+// TEXTURE_TYPE and LOAD are replaced per kind of texture (color or depth, with or without msaa).
+// The compute and the overlay part are compiled as separate modules, because they bind the stats differently.
+
+struct Params {
+    size: vec2<u32>,
+    // 0 r, 1 g, 2 b, 3 a, 4 rgb, 5 luminance
+    channel: u32,
+    // 1 maps the min and max of the texture to black and white
+    auto_range: u32,
+    range: vec2<f32>,
+    _pad: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var t_input: TEXTURE_TYPE;
+@group(0) @binding(1)
+var<uniform> params: Params;
+
+const BINS: u32 = 64u;
+
+fn load(coord: vec2<i32>) -> vec4<f32> {
+    return LOAD;
+}
+
+fn channel_value(color: vec4<f32>) -> f32 {
+    switch params.channel {
+        case 0u: {
+            return color.r;
+        }
+        case 1u: {
+            return color.g;
+        }
+        case 2u: {
+            return color.b;
+        }
+        case 3u: {
+            return color.a;
+        }
+        default: {
+            return dot(color.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+        }
+    }
+}
+
+// floats mapped to u32, such that their order is kept and atomicMin/atomicMax can be used.
+fn to_ordered(value: f32) -> u32 {
+    let bits = bitcast<u32>(value);
+    if (bits & 0x80000000u) != 0u {
+        return ~bits;
+    }
+    return bits | 0x80000000u;
+}
+
+fn from_ordered(ordered: u32) -> f32 {
+    if (ordered & 0x80000000u) != 0u {
+        return bitcast<f32>(ordered & 0x7fffffffu);
+    }
+    return bitcast<f32>(~ordered);
+}
+
+// COMPUTE
+
+struct Stats {
+    min: atomic<u32>,
+    max: atomic<u32>,
+    max_bin: atomic<u32>,
+    _pad: u32,
+    bins: array<atomic<u32>, 64>,
+}
+
+@group(1) @binding(0)
+var<storage, read_write> stats: Stats;
+
+fn value_range() -> vec2<f32> {
+    if params.auto_range == 1u {
+        return vec2<f32>(from_ordered(atomicLoad(&stats.min)), from_ordered(atomicLoad(&stats.max)));
+    }
+    return params.range;
+}
+
+@compute @workgroup_size(8, 8)
+fn min_max(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= params.size) {
+        return;
+    }
+    let value = channel_value(load(vec2<i32>(id.xy)));
+    // skips nan
+    if value == value {
+        let ordered = to_ordered(value);
+        atomicMin(&stats.min, ordered);
+        atomicMax(&stats.max, ordered);
+    }
+}
+
+@compute @workgroup_size(8, 8)
+fn histogram(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= params.size) {
+        return;
+    }
+    let value = channel_value(load(vec2<i32>(id.xy)));
+    if value != value {
+        return;
+    }
+    let range = value_range();
+    let t = (value - range.x) / max(range.y - range.x, 1e-20);
+    let bin = u32(clamp(t, 0.0, 0.9999) * f32(BINS));
+    let count = atomicAdd(&stats.bins[bin], 1u) + 1u;
+    atomicMax(&stats.max_bin, count);
+}
+
+// OVERLAY
+
+struct StatsRead {
+    min: u32,
+    max: u32,
+    max_bin: u32,
+    _pad: u32,
+    bins: array<u32, 64>,
+}
+
+@group(1) @binding(0)
+var<storage, read> stats: StatsRead;
+
+/// the lowest part of the overlay shows the histogram.
+const HISTOGRAM_HEIGHT: f32 = 0.2;
+
+struct VertexOutput {
+    @location(0) uv: vec2<f32>,
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+fn value_range() -> vec2<f32> {
+    if params.auto_range == 1u {
+        return vec2<f32>(from_ordered(stats.min), from_ordered(stats.max));
+    }
+    return params.range;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let image_height = 1.0 - HISTOGRAM_HEIGHT;
+    if in.uv.y > image_height {
+        let bin = min(u32(in.uv.x * f32(BINS)), BINS - 1u);
+        let height = f32(stats.bins[bin]) / f32(max(stats.max_bin, 1u));
+        let y = (1.0 - in.uv.y) / HISTOGRAM_HEIGHT;
+        if y < height {
+            return vec4<f32>(0.9, 0.6, 0.1, 1.0);
+        }
+        return vec4<f32>(0.05, 0.05, 0.05, 1.0);
+    }
+
+    let uv = vec2<f32>(in.uv.x, in.uv.y / image_height);
+    let size = vec2<i32>(params.size);
+    let coord = clamp(vec2<i32>(uv * vec2<f32>(params.size)), vec2<i32>(0), size - 1);
+    let color = load(coord);
+    let range = value_range();
+    let scale = 1.0 / max(range.y - range.x, 1e-20);
+    if params.channel == 4u {
+        return vec4<f32>((color.rgb - range.x) * scale, 1.0);
+    }
+    let value = (channel_value(color) - range.x) * scale;
+    return vec4<f32>(vec3<f32>(value), 1.0);
+}
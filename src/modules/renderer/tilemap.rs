@@ -0,0 +1,333 @@
+use wgpu::{BufferUsages, FragmentState, PrimitiveState, ShaderModuleDescriptor, VertexState};
+
+use crate::{
+    elements::{
+        camera3d::Camera3dGR, texture::rgba_bind_group_layout, BindableTexture, GrowableBuffer,
+        Tile, Tilemap, ToRaw, TransformRaw,
+    },
+    modules::{
+        renderer::{Attribute, VertexT, DEPTH_FORMAT, HDR_COLOR_FORMAT},
+        GraphicsContext,
+    },
+    Prepare, Ptr,
+};
+
+// /////////////////////////////////////////////////////////////////////////////
+// Interface
+// /////////////////////////////////////////////////////////////////////////////
+
+impl TilemapRenderer {
+    /// Draws a tilemap uploaded with `TilemapChunks::new` this frame. The map lies in the xy plane of the
+    /// transform, with its top left corner at the origin.
+    pub fn draw(&mut self, chunks: Ptr<TilemapChunks>, transform: impl ToRaw<Raw = TransformRaw>) {
+        self.maps.push((chunks, transform.to_raw()));
+    }
+}
+
+/// Width and height of a chunk in tiles.
+pub const CHUNK_SIZE: u32 = 16;
+
+/// The tiles of a `Tilemap` on the gpu, split into chunks of `CHUNK_SIZE` x `CHUNK_SIZE` tiles per layer, such that
+/// changing a tile only rebuilds its chunk. Keep it in an `OwnedPtr` and draw it with `TilemapRenderer::draw`.
+#[derive(Debug)]
+pub struct TilemapChunks {
+    /// one atlas texture per tileset of the map.
+    atlases: Vec<Ptr<BindableTexture>>,
+    /// size of a pixel of the tilesets in world units.
+    units_per_pixel: f32,
+    chunks_x: u32,
+    chunks_y: u32,
+    /// per layer, row by row.
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Default)]
+struct Chunk {
+    /// one vertex buffer for each atlas that is used in the chunk.
+    draws: Vec<(usize, GrowableBuffer<Vertex>)>,
+}
+
+impl TilemapChunks {
+    /// `atlases` are the images of the `map.tilesets`, in the same order. Tiles of tilesets without an atlas are
+    /// not drawn. `pixels_per_unit` is the number of tileset pixels per world unit, e.g. the tile width for maps
+    /// where a tile is 1x1 world units.
+    pub fn new(
+        device: &wgpu::Device,
+        map: &Tilemap,
+        atlases: Vec<Ptr<BindableTexture>>,
+        pixels_per_unit: f32,
+    ) -> Self {
+        let mut chunks = TilemapChunks {
+            atlases,
+            units_per_pixel: 1.0 / pixels_per_unit,
+            chunks_x: map.width.div_ceil(CHUNK_SIZE),
+            chunks_y: map.height.div_ceil(CHUNK_SIZE),
+            chunks: vec![],
+        };
+        let count = chunks.chunks_x * chunks.chunks_y * map.layers.len() as u32;
+        chunks.chunks = (0..count).map(|_| Chunk::default()).collect();
+        for i in 0..count {
+            chunks.rebuild_chunk(device, map, i as usize);
+        }
+        chunks
+    }
+
+    /// Rebuilds the chunk with the tile at `x`, `y` of a layer, call after changing the tile in the map.
+    pub fn update_tile(
+        &mut self,
+        device: &wgpu::Device,
+        map: &Tilemap,
+        layer: usize,
+        x: u32,
+        y: u32,
+    ) {
+        if x >= map.width || y >= map.height {
+            return;
+        }
+        let index = self.chunk_index(layer, x / CHUNK_SIZE, y / CHUNK_SIZE);
+        self.rebuild_chunk(device, map, index);
+    }
+
+    /// Rebuilds all chunks of a layer, e.g. after changing its opacity or visibility.
+    pub fn update_layer(&mut self, device: &wgpu::Device, map: &Tilemap, layer: usize) {
+        for cy in 0..self.chunks_y {
+            for cx in 0..self.chunks_x {
+                let index = self.chunk_index(layer, cx, cy);
+                self.rebuild_chunk(device, map, index);
+            }
+        }
+    }
+
+    fn chunk_index(&self, layer: usize, cx: u32, cy: u32) -> usize {
+        layer * (self.chunks_x * self.chunks_y) as usize + (cy * self.chunks_x + cx) as usize
+    }
+
+    fn rebuild_chunk(&mut self, device: &wgpu::Device, map: &Tilemap, index: usize) {
+        let per_layer = (self.chunks_x * self.chunks_y) as usize;
+        let (layer_index, in_layer) = (index / per_layer, index % per_layer);
+        let Some(chunk) = self.chunks.get_mut(index) else {
+            return;
+        };
+        chunk.draws.clear();
+        let layer = &map.layers[layer_index];
+        if !layer.visible {
+            return;
+        }
+        let cx = in_layer as u32 % self.chunks_x;
+        let cy = in_layer as u32 / self.chunks_x;
+
+        let mut vertices: Vec<Vec<Vertex>> = vec![vec![]; self.atlases.len()];
+        for y in cy * CHUNK_SIZE..((cy + 1) * CHUNK_SIZE).min(layer.height) {
+            for x in cx * CHUNK_SIZE..((cx + 1) * CHUNK_SIZE).min(layer.width) {
+                let tile = Tile::from_raw(layer.tiles[(y * layer.width + x) as usize]);
+                if tile.is_empty() {
+                    continue;
+                }
+                let Some(tileset_index) =
+                    map.tilesets.iter().rposition(|t| t.first_gid <= tile.gid)
+                else {
+                    continue;
+                };
+                let Some(vertices) = vertices.get_mut(tileset_index) else {
+                    continue;
+                };
+                let tileset = &map.tilesets[tileset_index];
+                let id = tile.gid - tileset.first_gid;
+                let columns = tileset.columns.max(1);
+                let src_x =
+                    tileset.margin + (id % columns) * (tileset.tile_width + tileset.spacing);
+                let src_y =
+                    tileset.margin + (id / columns) * (tileset.tile_height + tileset.spacing);
+                let image_size = [tileset.image_width as f32, tileset.image_height as f32];
+
+                // Tiled anchors tiles at the bottom left of their cell, y points up in the world.
+                let upp = self.units_per_pixel;
+                let left = (x * map.tile_width) as f32 * upp;
+                let bottom = -(((y + 1) * map.tile_height) as f32) * upp;
+                let (width, height) = (
+                    tileset.tile_width as f32 * upp,
+                    tileset.tile_height as f32 * upp,
+                );
+                // corners as (right, down) in the cell: top left, top right, bottom left, bottom right.
+                let corners = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(cx, cy): (u32, u32)| {
+                    let pos = [left + cx as f32 * width, bottom + (1 - cy) as f32 * height];
+                    // which corner of the source tile is shown here, diagonal flip after horizontal and vertical.
+                    let (mut sx, mut sy) = (cx ^ tile.flip_h as u32, cy ^ tile.flip_v as u32);
+                    if tile.flip_d {
+                        std::mem::swap(&mut sx, &mut sy);
+                    }
+                    let uv = [
+                        (src_x + sx * tileset.tile_width) as f32 / image_size[0],
+                        (src_y + sy * tileset.tile_height) as f32 / image_size[1],
+                    ];
+                    Vertex {
+                        pos,
+                        uv,
+                        opacity: layer.opacity,
+                    }
+                });
+                let [tl, tr, bl, br] = corners;
+                vertices.extend([tl, bl, br, tl, br, tr]);
+            }
+        }
+        for (atlas, vertices) in vertices.into_iter().enumerate() {
+            if !vertices.is_empty() {
+                let buffer = GrowableBuffer::new_from_data(device, BufferUsages::VERTEX, &vertices);
+                chunk.draws.push((atlas, buffer));
+            }
+        }
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Module
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Renders tilemaps, see `TilemapChunks`. The layers are alpha blended in order, without writing depth.
+pub struct TilemapRenderer {
+    pipeline: wgpu::RenderPipeline,
+    /// maps drawn this frame, cleared in `prepare`.
+    maps: Vec<(Ptr<TilemapChunks>, TransformRaw)>,
+    render_maps: Vec<Ptr<TilemapChunks>>,
+    /// one transform per map in `render_maps`.
+    instance_buffer: GrowableBuffer<TransformRaw>,
+}
+
+impl TilemapRenderer {
+    pub fn new(ctx: &GraphicsContext, camera: &Camera3dGR) -> Self {
+        TilemapRenderer {
+            pipeline: create_pipeline(&ctx.device, camera, ctx.msaa_sample_count),
+            maps: vec![],
+            render_maps: vec![],
+            instance_buffer: GrowableBuffer::new(&ctx.device, 16, BufferUsages::VERTEX),
+        }
+    }
+
+    pub fn render<'encoder>(
+        &'encoder self,
+        render_pass: &mut wgpu::RenderPass<'encoder>,
+        camera: &'encoder Camera3dGR,
+    ) {
+        if self.render_maps.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera.bind_group(), &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.buffer().slice(..));
+        for (i, map) in self.render_maps.iter().enumerate() {
+            let instance = i as u32..i as u32 + 1;
+            for chunk in map.chunks.iter() {
+                for (atlas, vertices) in chunk.draws.iter() {
+                    render_pass.set_bind_group(1, &map.atlases[*atlas].bind_group, &[]);
+                    render_pass.set_vertex_buffer(0, vertices.buffer().slice(..));
+                    render_pass.draw(0..vertices.len() as u32, instance.clone());
+                }
+            }
+        }
+    }
+}
+
+impl Prepare for TilemapRenderer {
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let transforms: Vec<TransformRaw> = self.maps.iter().map(|(_, t)| *t).collect();
+        self.instance_buffer.prepare(&transforms, device, queue);
+        self.render_maps.clear();
+        self.render_maps
+            .extend(self.maps.drain(..).map(|(map, _)| map));
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Renderer
+// /////////////////////////////////////////////////////////////////////////////
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Vertex {
+    /// in the xy plane of the map.
+    pos: [f32; 2],
+    uv: [f32; 2],
+    /// of the layer.
+    opacity: f32,
+}
+
+impl VertexT for Vertex {
+    const ATTRIBUTES: &'static [Attribute] = &[
+        Attribute::new("pos", wgpu::VertexFormat::Float32x2),
+        Attribute::new("uv", wgpu::VertexFormat::Float32x2),
+        Attribute::new("opacity", wgpu::VertexFormat::Float32),
+    ];
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    camera: &Camera3dGR,
+    msaa_sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let label = "TilemapRenderer";
+
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(&format!("{label} ShaderModule")),
+        source: wgpu::ShaderSource::Wgsl(include_str!("tilemap.wgsl").into()),
+    });
+
+    let _empty1 = &mut vec![];
+    let _empty2 = &mut vec![];
+    let vertex_buffers_layout = &[
+        Vertex::vertex_buffer_layout(0, false, _empty1),
+        TransformRaw::vertex_buffer_layout(Vertex::ATTRIBUTES.len(), true, _empty2),
+    ];
+
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(&format!("{label} PipelineLayout")),
+        bind_group_layouts: &[camera.bind_group_layout(), &rgba_bind_group_layout(device)],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("{label} Pipeline")),
+        layout: Some(&layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: vertex_buffers_layout,
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: HDR_COLOR_FORMAT,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // flipped maps (negative scale) should stay visible.
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        // layers are blended in draw order, tested against the depth buffer, but do not write to it.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: msaa_sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
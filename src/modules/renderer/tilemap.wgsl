@@ -0,0 +1,55 @@
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@group(1) @binding(0)
+var t_atlas: texture_2d<f32>;
+@group(1) @binding(1)
+var s_atlas: sampler;
+
+struct Vertex {
+    @location(0) pos: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) opacity: f32,
+}
+
+struct Instance {
+    @location(3) col1: vec4<f32>,
+    @location(4) col2: vec4<f32>,
+    @location(5) col3: vec4<f32>,
+    @location(6) translation: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) opacity: f32,
+};
+
+@vertex
+fn vs_main(vertex: Vertex, instance: Instance) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.col1,
+        instance.col2,
+        instance.col3,
+        instance.translation,
+    );
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(vertex.pos, 0.0, 1.0);
+    out.uv = vertex.uv;
+    out.opacity = vertex.opacity;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_atlas, s_atlas, in.uv);
+    if color.a <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(color.rgb, color.a * in.opacity);
+}
@@ -1,26 +1,52 @@
-use wgpu::{PushConstantRange, ShaderStages};
+use wgpu::ShaderStages;
 
 use crate::{
-    elements::texture::rgba_bind_group_layout,
-    modules::{renderer::SURFACE_COLOR_FORMAT, GraphicsContext},
+    elements::{
+        screen::{set_scissor, set_viewport_and_scissor},
+        texture::rgba_bind_group_layout,
+        Rect,
+    },
+    modules::{GraphicsContext, SurfaceEncoding},
 };
 
-use super::ScreenVertexShader;
+use super::{PushConstantBlock, ScreenVertexShader};
 
 pub struct AcesToneMapping {
     enabled: bool,
+    encoding: SurfaceEncoding,
+    /// contrast adaptive sharpening after upscaling, see `RenderScale::sharpness`.
+    sharpness: f32,
+    /// hdr colors are multiplied with it before tone mapping, see `Camera3d::exposure`.
+    exposure: f32,
+    /// the hdr texture is scaled to the viewport with nearest neighbor sampling, see `AspectMode::PixelPerfect`.
+    pixel_perfect: bool,
+    push_constants: PushConstantBlock<PushContants>,
     pipeline: wgpu::RenderPipeline,
 }
 
 impl AcesToneMapping {
     pub fn new(ctx: &GraphicsContext, screen_vertex_shader: &ScreenVertexShader) -> Self {
+        let push_constants = PushConstantBlock::new(
+            &ctx.device,
+            ctx.pipeline_settings(),
+            ShaderStages::FRAGMENT,
+            1,
+            bytemuck::Zeroable::zeroed(),
+        );
         let pipeline = create_pipeline(
             include_str!("tonemapping.wgsl"),
             &ctx.device,
             screen_vertex_shader,
+            ctx.surface_format,
+            &push_constants,
         );
         Self {
             enabled: true,
+            encoding: ctx.surface_encoding(),
+            sharpness: 0.0,
+            exposure: 1.0,
+            pixel_perfect: false,
+            push_constants,
             pipeline,
         }
     }
@@ -29,20 +55,48 @@ impl AcesToneMapping {
         &mut self.enabled
     }
 
-    /// Note: input texture should be hdr, output sdr
+    /// between 0 (plain bilinear scaling) and 1.
+    pub fn set_sharpness(&mut self, sharpness: f32) {
+        self.sharpness = sharpness.clamp(0.0, 1.0);
+    }
+
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure.max(0.0);
+    }
+
+    pub fn set_pixel_perfect(&mut self, pixel_perfect: bool) {
+        self.pixel_perfect = pixel_perfect;
+    }
+
+    /// Note: input texture should be hdr, output sdr. The input is stretched to the output, so this also upscales
+    /// if the hdr textures are smaller than the surface (see `RenderScale`).
+    /// Everything outside of the `viewport` is cleared to black (letterbox / pillarbox bars).
     pub fn apply<'e>(
         &'e mut self,
         encoder: &'e mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
         input_texture: &wgpu::BindGroup,
         output_texture: &wgpu::TextureView,
+        viewport: Rect,
     ) {
+        self.push_constants.update(
+            PushContants {
+                enabled: if self.enabled { 1 } else { 0 },
+                encoding: self.encoding.shader_index(),
+                sharpness: self.sharpness,
+                exposure: self.exposure,
+                nearest: if self.pixel_perfect { 1 } else { 0 },
+                _pad: [0; 3],
+            },
+            queue,
+        );
         let mut tone_mapping_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("AcesToneMapping"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                 view: output_texture,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                     store: wgpu::StoreOp::Store,
                 },
             })],
@@ -51,15 +105,16 @@ impl AcesToneMapping {
             timestamp_writes: None,
         });
 
+        if self.pixel_perfect {
+            // the hdr texture only has the size of the viewport.
+            set_viewport_and_scissor(&mut tone_mapping_pass, viewport);
+        } else {
+            // no viewport, because the full screen triangle should still sample the entire hdr texture.
+            set_scissor(&mut tone_mapping_pass, viewport);
+        }
         tone_mapping_pass.set_pipeline(&self.pipeline);
         tone_mapping_pass.set_bind_group(0, input_texture, &[]);
-        tone_mapping_pass.set_push_constants(
-            ShaderStages::FRAGMENT,
-            0,
-            bytemuck::cast_slice(&[PushContants {
-                enabled: if self.enabled { 1 } else { 0 },
-            }]),
-        );
+        self.push_constants.set(&mut tone_mapping_pass);
         tone_mapping_pass.draw(0..3, 0..1);
     }
 }
@@ -68,18 +123,17 @@ fn create_pipeline(
     shader_wgsl: &str,
     device: &wgpu::Device,
     screen_vertex_shader: &ScreenVertexShader,
+    surface_format: wgpu::TextureFormat,
+    push_constants: &PushConstantBlock<PushContants>,
 ) -> wgpu::RenderPipeline {
     let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: Some("Tonemapping Shader"),
-        source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(shader_wgsl)),
+        source: wgpu::ShaderSource::Wgsl(push_constants.wgsl(shader_wgsl)),
     });
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
-        bind_group_layouts: &[rgba_bind_group_layout(device)],
-        push_constant_ranges: &[PushConstantRange {
-            stages: ShaderStages::FRAGMENT,
-            range: 0..16,
-        }],
+        bind_group_layouts: &push_constants.bind_group_layouts(&[&rgba_bind_group_layout(device)]),
+        push_constant_ranges: &push_constants.push_constant_ranges(),
     });
 
     let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
@@ -90,7 +144,7 @@ fn create_pipeline(
             module: &shader,
             entry_point: "fs_main",
             targets: &[Some(wgpu::ColorTargetState {
-                format: SURFACE_COLOR_FORMAT,
+                format: surface_format,
                 blend: None,
                 write_mask: wgpu::ColorWrites::ALL,
             })],
@@ -113,8 +167,17 @@ fn create_pipeline(
 }
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Zeroable, bytemuck::Pod)]
 pub struct PushContants {
     // 0 is off, 1 is enabled
     enabled: u32,
+    // 0 is srgb surface, 1 is unorm surface without srgb conversion, 2 is hdr float surface
+    encoding: u32,
+    // 0 is no sharpening
+    sharpness: f32,
+    // multiplied with the hdr color
+    exposure: f32,
+    // 1 samples the nearest texel, for pixel perfect upscaling
+    nearest: u32,
+    _pad: [u32; 3],
 }
@@ -8,8 +8,22 @@ var hdr_image: texture_2d<f32>;
 @binding(1)
 var hdr_sampler: sampler;
 
-// 0 is off, 1 is aces
-var<push_constant> enabled: u32;
+struct PushConstants {
+    // 0 is off, 1 is aces
+    enabled: u32,
+    // 0 is srgb surface, 1 is unorm surface without srgb conversion, 2 is hdr float surface
+    encoding: u32,
+    // 0 is no sharpening, 1 is the maximum
+    sharpness: f32,
+    // multiplied with the hdr color, see `Camera3d::exposure`
+    exposure: f32,
+    // 1 samples the nearest texel, for pixel perfect upscaling
+    nearest: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+var<push_constant> pc: PushConstants;
 
 // Taken from learn-wgpu example for hdr.
 struct VertexOutput {
@@ -19,15 +33,51 @@ struct VertexOutput {
 
 @fragment
 fn fs_main(vs: VertexOutput) -> @location(0) vec4<f32> {
-    let color_with_a: vec4<f32> = textureSample(hdr_image, hdr_sampler, vs.uv);
-    if enabled == 1u{
-        return color_with_a;
+    let color_with_a: vec4<f32> = sample_sharpened(vs.uv);
+    let exposed = color_with_a.rgb * pc.exposure;
+    var color: vec3<f32>;
+    if pc.enabled == 1u || pc.encoding == 2u {
+        // hdr surfaces take the linear values directly.
+        color = exposed;
     }else{
-        let color = aces_tone_map(color_with_a.rgb);
-        return vec4(color, color_with_a.a);
+        color = aces_tone_map(exposed);
+    }
+    if pc.encoding == 1u {
+        color = linear_to_srgb(clamp(color, vec3(0.0), vec3(1.0)));
     }
+    return vec4(color, color_with_a.a);
+}
+
+// Contrast adaptive sharpening in the style of the RCAS pass of FSR1: the 4 neighbours are subtracted with a
+// negative lobe, that gets smaller where the local contrast is already high, to avoid ringing.
+fn sample_sharpened(uv: vec2<f32>) -> vec4<f32> {
+    if pc.nearest == 1u {
+        let size = vec2<i32>(textureDimensions(hdr_image));
+        let texel_pos = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2(0), size - 1);
+        return textureLoad(hdr_image, texel_pos, 0);
+    }
+    let texel = 1.0 / vec2<f32>(textureDimensions(hdr_image));
+    let c = textureSample(hdr_image, hdr_sampler, uv);
+    // the sharpness is a push constant, so this branch keeps the control flow uniform.
+    if pc.sharpness <= 0.0 {
+        return c;
+    }
+    let n = textureSample(hdr_image, hdr_sampler, uv + vec2(0.0, -texel.y)).rgb;
+    let s = textureSample(hdr_image, hdr_sampler, uv + vec2(0.0, texel.y)).rgb;
+    let e = textureSample(hdr_image, hdr_sampler, uv + vec2(texel.x, 0.0)).rgb;
+    let w = textureSample(hdr_image, hdr_sampler, uv + vec2(-texel.x, 0.0)).rgb;
+    let min_rgb = min(min(min(n, s), min(e, w)), c.rgb);
+    let max_rgb = max(max(max(n, s), max(e, w)), c.rgb);
+    let contrast = (max_rgb - min_rgb) / (max_rgb + vec3(0.00001));
+    let lobe = -0.1875 * pc.sharpness * (vec3(1.0) - clamp(contrast, vec3(0.0), vec3(1.0)));
+    let sharpened = (c.rgb + lobe * (n + s + e + w)) / (vec3(1.0) + 4.0 * lobe);
+    return vec4(max(sharpened, vec3(0.0)), c.a);
+}
 
-    
+fn linear_to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let lower = linear * 12.92;
+    let higher = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return select(higher, lower, linear <= vec3(0.0031308));
 }
 
 // Maps HDR values to linear values
@@ -0,0 +1,318 @@
+use std::collections::VecDeque;
+
+use glam::Vec3;
+use wgpu::{BufferUsages, FragmentState, PrimitiveState, ShaderModuleDescriptor, VertexState};
+
+use crate::{
+    elements::{camera3d::Camera3dGR, Color, GrowableBuffer},
+    modules::{
+        renderer::{Attribute, VertexT, DEPTH_FORMAT, HDR_COLOR_FORMAT},
+        GraphicsContext,
+    },
+    Prepare,
+};
+
+// /////////////////////////////////////////////////////////////////////////////
+// Interface
+// /////////////////////////////////////////////////////////////////////////////
+
+impl TrailRenderer {
+    /// Draws a polyline as a ribbon that always faces the camera. Width and color are blended between the points.
+    pub fn draw_strip(&mut self, points: &[StripPoint]) {
+        if points.len() < 2 {
+            return;
+        }
+        let first = self.vertices.len() as u32;
+        for (i, point) in points.iter().enumerate() {
+            let prev = points[i.saturating_sub(1)].pos;
+            let next = points[(i + 1).min(points.len() - 1)].pos;
+            let tangent = (next - prev).normalize_or_zero();
+            for side in [-1.0, 1.0] {
+                self.vertices.push(Vertex {
+                    pos: point.pos.into(),
+                    tangent: tangent.into(),
+                    offset: side * point.width * 0.5,
+                    color: point.color,
+                });
+            }
+        }
+        for i in 0..points.len() as u32 - 1 {
+            let a = first + i * 2;
+            self.indices.extend([a, a + 1, a + 2, a + 2, a + 1, a + 3]);
+        }
+    }
+
+    /// Draws a `Trail`, older points are thinner and more transparent.
+    pub fn draw_trail(&mut self, trail: &Trail) {
+        let points: Vec<StripPoint> = trail.strip_points().collect();
+        self.draw_strip(&points);
+    }
+}
+
+/// A point of a polyline drawn by `TrailRenderer::draw_strip`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StripPoint {
+    pub pos: Vec3,
+    /// in world units.
+    pub width: f32,
+    pub color: Color,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TrailPoint {
+    point: StripPoint,
+    age: f32,
+}
+
+/// The last positions of a moving object (a sword tip, a projectile, a car wheel), kept in a ring buffer.
+/// Call `push` every frame with the current position and `update` with the frame time, then draw it with
+/// `TrailRenderer::draw_trail`.
+#[derive(Debug, Clone)]
+pub struct Trail {
+    points: VecDeque<TrailPoint>,
+    /// the oldest point is dropped when a new one is pushed.
+    pub capacity: usize,
+    /// seconds until a point has faded out completely and is removed.
+    pub lifetime: f32,
+    /// points get thinner with age, down to 0 at the end of their lifetime.
+    pub shrink: bool,
+    /// a point is only added if it is at least this far from the last one, such that a resting object does not
+    /// fill the buffer.
+    pub min_distance: f32,
+}
+
+impl Trail {
+    pub fn new(capacity: usize, lifetime: f32) -> Self {
+        Trail {
+            points: VecDeque::with_capacity(capacity),
+            capacity,
+            lifetime,
+            shrink: true,
+            min_distance: 0.01,
+        }
+    }
+
+    pub fn push(&mut self, pos: Vec3, width: f32, color: Color) {
+        if let Some(last) = self.points.back() {
+            if last.point.pos.distance(pos) < self.min_distance {
+                return;
+            }
+        }
+        while self.points.len() >= self.capacity.max(1) {
+            self.points.pop_front();
+        }
+        self.points.push_back(TrailPoint {
+            point: StripPoint { pos, width, color },
+            age: 0.0,
+        });
+    }
+
+    /// Ages all points by `dt` seconds and removes the ones that are older than `lifetime`.
+    pub fn update(&mut self, dt: f32) {
+        for p in self.points.iter_mut() {
+            p.age += dt;
+        }
+        while self.points.front().is_some_and(|p| p.age >= self.lifetime) {
+            self.points.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// The points from oldest to newest, with the fade applied to their color and width.
+    pub fn strip_points(&self) -> impl Iterator<Item = StripPoint> + '_ {
+        self.points.iter().map(|p| {
+            let fade = if self.lifetime > 0.0 {
+                (1.0 - p.age / self.lifetime).clamp(0.0, 1.0)
+            } else {
+                1.0
+            };
+            let mut point = p.point;
+            point.color.a *= fade;
+            if self.shrink {
+                point.width *= fade;
+            }
+            point
+        })
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Module
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Renders world space polylines as camera facing ribbons with a width, e.g. for trails and beams. Unlike the 1px
+/// lines of the `Gizmos`, the ribbons are alpha blended and can be brighter than 1.0 to glow with bloom.
+pub struct TrailRenderer {
+    /// immediate geometry, written to the buffers every frame.
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: GrowableBuffer<Vertex>,
+    index_buffer: GrowableBuffer<u32>,
+}
+
+impl TrailRenderer {
+    pub fn new(ctx: &GraphicsContext, camera: &Camera3dGR) -> Self {
+        let pipeline = create_pipeline(&ctx.device, camera, ctx.msaa_sample_count);
+        TrailRenderer {
+            vertices: vec![],
+            indices: vec![],
+            pipeline,
+            vertex_buffer: GrowableBuffer::new(&ctx.device, 256, BufferUsages::VERTEX),
+            index_buffer: GrowableBuffer::new(&ctx.device, 256, BufferUsages::INDEX),
+        }
+    }
+
+    pub fn render<'encoder>(
+        &'encoder self,
+        render_pass: &mut wgpu::RenderPass<'encoder>,
+        camera: &'encoder Camera3dGR,
+    ) {
+        if self.index_buffer.len() == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.buffer().slice(..));
+        render_pass.set_index_buffer(
+            self.index_buffer.buffer().slice(..),
+            wgpu::IndexFormat::Uint32,
+        );
+        render_pass.draw_indexed(0..self.index_buffer.len() as u32, 0, 0..1);
+    }
+}
+
+impl Prepare for TrailRenderer {
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.vertex_buffer.prepare(&self.vertices, device, queue);
+        self.index_buffer.prepare(&self.indices, device, queue);
+        self.vertices.clear();
+        self.indices.clear();
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Renderer
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Both vertices of a point have the same position, the shader moves them apart by `offset` perpendicular to the
+/// tangent and the view direction.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Vertex {
+    pos: [f32; 3],
+    tangent: [f32; 3],
+    offset: f32,
+    color: Color,
+}
+
+impl VertexT for Vertex {
+    const ATTRIBUTES: &'static [Attribute] = &[
+        Attribute::new("pos", wgpu::VertexFormat::Float32x3),
+        Attribute::new("tangent", wgpu::VertexFormat::Float32x3),
+        Attribute::new("offset", wgpu::VertexFormat::Float32),
+        Attribute::new("color", wgpu::VertexFormat::Float32x4),
+    ];
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    camera: &Camera3dGR,
+    msaa_sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let label = "TrailRenderer";
+
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(&format!("{label} ShaderModule")),
+        source: wgpu::ShaderSource::Wgsl(include_str!("trail.wgsl").into()),
+    });
+
+    let _empty = &mut vec![];
+    let vertex_buffers_layout = &[Vertex::vertex_buffer_layout(0, false, _empty)];
+
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(&format!("{label} PipelineLayout")),
+        bind_group_layouts: &[camera.bind_group_layout()],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("{label} Pipeline")),
+        layout: Some(&layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: vertex_buffers_layout,
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: HDR_COLOR_FORMAT,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // the ribbon turns with the camera, both sides can be visible.
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        // transparent: tested against the depth buffer, but does not write to it.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: msaa_sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trail_fades_and_drops_old_points() {
+        let mut trail = Trail::new(3, 1.0);
+        for i in 0..4 {
+            trail.push(Vec3::new(i as f32, 0.0, 0.0), 1.0, Color::WHITE);
+            trail.update(0.3);
+        }
+        // the ring buffer keeps the newest 3 points:
+        assert_eq!(trail.len(), 3);
+        let points: Vec<StripPoint> = trail.strip_points().collect();
+        assert_eq!(points[0].pos.x, 1.0);
+        assert!((points[0].color.a - 0.1).abs() < 1e-4);
+        assert!((points[2].width - 0.7).abs() < 1e-4);
+        trail.update(0.2);
+        assert_eq!(trail.len(), 2);
+    }
+}
@@ -0,0 +1,43 @@
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct Vertex {
+    @location(0) pos: vec3<f32>,
+    @location(1) tangent: vec3<f32>,
+    @location(2) offset: f32,
+    @location(3) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    vertex: Vertex,
+) -> VertexOutput {
+    // move the vertex sideways, perpendicular to the line and to the direction to the camera:
+    let to_camera = camera.view_pos.xyz - vertex.pos;
+    var side = cross(vertex.tangent, to_camera);
+    let side_len = length(side);
+    if side_len > 0.0001 {
+        side = side / side_len;
+    }
+    let world_position = vec4<f32>(vertex.pos + side * vertex.offset, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.color = vertex.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
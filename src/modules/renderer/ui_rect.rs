@@ -10,10 +10,10 @@ use crate::{
     elements::{
         immediate_geometry::TexturedInstancesQueue,
         texture::{create_white_px_texture, rgba_bind_group_layout},
-        BindableTexture, Color, GrowableBuffer, Rect, ScreenGR,
+        BindableTexture, Color, GrowableBuffer, Rect, ScreenGR, ToRaw,
     },
     modules::{
-        renderer::{DEPTH_FORMAT, HDR_COLOR_FORMAT, MSAA_SAMPLE_COUNT},
+        renderer::{DEPTH_FORMAT, HDR_COLOR_FORMAT},
         Attribute, GraphicsContext, VertexT,
     },
     OwnedPtr, Prepare, Ptr,
@@ -49,7 +49,12 @@ impl UiRectRenderer {
     pub fn new(ctx: &GraphicsContext, screen: &ScreenGR) -> Self {
         let white_texture = OwnedPtr::new(create_white_px_texture(&ctx.device, &ctx.queue));
 
-        let pipeline = create_render_pipeline(&ctx.device, include_str!("ui_rect.wgsl"), screen);
+        let pipeline = create_render_pipeline(
+            &ctx.device,
+            include_str!("ui_rect.wgsl"),
+            screen,
+            ctx.msaa_sample_count,
+        );
 
         UiRectRenderer {
             pipeline,
@@ -102,7 +107,7 @@ impl Prepare for UiRectRenderer {
 // /////////////////////////////////////////////////////////////////////////////
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct UiRect {
     pub pos: Rect,
     pub uv: Rect,
@@ -110,6 +115,14 @@ pub struct UiRect {
     pub border_radius: [f32; 4],
 }
 
+impl ToRaw for UiRect {
+    type Raw = UiRect;
+
+    fn to_raw(&self) -> Self::Raw {
+        *self
+    }
+}
+
 impl VertexT for UiRect {
     const ATTRIBUTES: &'static [Attribute] = &[
         Attribute::new("pos", wgpu::VertexFormat::Float32x4),
@@ -123,6 +136,7 @@ fn create_render_pipeline(
     device: &wgpu::Device,
     wgsl: &str,
     screen: &ScreenGR,
+    msaa_sample_count: u32,
 ) -> wgpu::RenderPipeline {
     let label = "UiRect";
     let shader_module = device.create_shader_module(ShaderModuleDescriptor {
@@ -135,7 +149,7 @@ fn create_render_pipeline(
 
     let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some(&format!("{label} PipelineLayout")),
-        bind_group_layouts: &[screen.bind_group_layout(), rgba_bind_group_layout(device)],
+        bind_group_layouts: &[screen.bind_group_layout(), &rgba_bind_group_layout(device)],
         push_constant_ranges: &[],
     });
 
@@ -168,7 +182,7 @@ fn create_render_pipeline(
             bias: wgpu::DepthBiasState::default(),
         }),
         multisample: MultisampleState {
-            count: MSAA_SAMPLE_COUNT,
+            count: msaa_sample_count,
             alpha_to_coverage_enabled: true,
             ..Default::default()
         },
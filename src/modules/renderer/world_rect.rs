@@ -14,7 +14,7 @@ use crate::{
         BindableTexture, GrowableBuffer, ToRaw, Transform, TransformRaw,
     },
     modules::{
-        renderer::{DEPTH_FORMAT, HDR_COLOR_FORMAT, MSAA_SAMPLE_COUNT},
+        renderer::{DEPTH_FORMAT, HDR_COLOR_FORMAT},
         Attribute, GraphicsContext, VertexT,
     },
     OwnedPtr, Prepare, Ptr,
@@ -30,11 +30,11 @@ impl WorldRectRenderer {
     pub fn draw_textured_rect(
         &mut self,
         rect: UiRect,
-        transform: Transform,
+        transform: impl ToRaw<Raw = TransformRaw>,
         texture: Ptr<BindableTexture>,
     ) {
         self.queue.add(
-            WorldRect {
+            WorldRectRaw {
                 ui_rect: rect,
                 transform: transform.to_raw(),
             },
@@ -42,9 +42,14 @@ impl WorldRectRenderer {
         );
     }
 
-    pub fn draw_rect(&mut self, rect: UiRect, transform: Transform) {
+    pub fn draw_world_rect(&mut self, rect: &WorldRect, texture: Ptr<BindableTexture>) {
+        self.queue.add(rect.to_raw(), texture);
+    }
+
+    /// `transform` is a `Transform`, `CachedTransform` or `TransformRaw`.
+    pub fn draw_rect(&mut self, rect: UiRect, transform: impl ToRaw<Raw = TransformRaw>) {
         self.queue.add(
-            WorldRect {
+            WorldRectRaw {
                 ui_rect: rect,
                 transform: transform.to_raw(),
             },
@@ -58,15 +63,20 @@ impl WorldRectRenderer {
 pub struct WorldRectRenderer {
     pipeline: wgpu::RenderPipeline,
     white_texture: OwnedPtr<BindableTexture>,
-    queue: TexturedInstancesQueue<WorldRect>,
+    queue: TexturedInstancesQueue<WorldRectRaw>,
     instance_ranges: Vec<(Range<u32>, Ptr<BindableTexture>)>,
-    instance_buffer: GrowableBuffer<WorldRect>,
+    instance_buffer: GrowableBuffer<WorldRectRaw>,
 }
 
 impl WorldRectRenderer {
     pub fn new(ctx: &GraphicsContext, camera: &Camera3dGR) -> Self {
         let white_texture = OwnedPtr::new(create_white_px_texture(&ctx.device, &ctx.queue));
-        let pipeline = create_render_pipeline(&ctx.device, include_str!("world_rect.wgsl"), camera);
+        let pipeline = create_render_pipeline(
+            &ctx.device,
+            include_str!("world_rect.wgsl"),
+            camera,
+            ctx.msaa_sample_count,
+        );
 
         WorldRectRenderer {
             pipeline,
@@ -118,14 +128,15 @@ impl Prepare for WorldRectRenderer {
 // Rendering
 // /////////////////////////////////////////////////////////////////////////////
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+/// A rect placed in the world by a transform. Its raw version `WorldRectRaw` is the instance data of the
+/// `WorldRectRenderer`.
+#[derive(Debug, Clone, Copy, ToRaw)]
 pub struct WorldRect {
     pub ui_rect: UiRect,
-    pub transform: TransformRaw,
+    pub transform: Transform,
 }
 
-impl VertexT for WorldRect {
+impl VertexT for WorldRectRaw {
     const ATTRIBUTES: &'static [Attribute] = &[
         Attribute::new("pos", wgpu::VertexFormat::Float32x4),
         Attribute::new("uv", wgpu::VertexFormat::Float32x4),
@@ -142,6 +153,7 @@ fn create_render_pipeline(
     device: &wgpu::Device,
     wgsl: &str,
     camera: &Camera3dGR,
+    msaa_sample_count: u32,
 ) -> wgpu::RenderPipeline {
     let label = "WorldRect";
     let shader_module = device.create_shader_module(ShaderModuleDescriptor {
@@ -150,11 +162,11 @@ fn create_render_pipeline(
     });
 
     let _empty = &mut vec![];
-    let vertex_buffers_layout = &[WorldRect::vertex_buffer_layout(0, true, _empty)];
+    let vertex_buffers_layout = &[WorldRectRaw::vertex_buffer_layout(0, true, _empty)];
 
     let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some(&format!("{label} PipelineLayout")),
-        bind_group_layouts: &[camera.bind_group_layout(), rgba_bind_group_layout(device)],
+        bind_group_layouts: &[camera.bind_group_layout(), &rgba_bind_group_layout(device)],
         push_constant_ranges: &[],
     });
 
@@ -187,10 +199,34 @@ fn create_render_pipeline(
             bias: wgpu::DepthBiasState::default(),
         }),
         multisample: MultisampleState {
-            count: MSAA_SAMPLE_COUNT,
+            count: msaa_sample_count,
             alpha_to_coverage_enabled: true,
             ..Default::default()
         },
         multiview: None,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elements::{Color, Rect};
+
+    #[test]
+    fn derived_raw_matches_fields() {
+        let rect = WorldRect {
+            ui_rect: UiRect {
+                pos: Rect::new(0.0, 0.0, 2.0, 1.0),
+                uv: Rect::UNIT,
+                color: Color::RED,
+                border_radius: [0.5; 4],
+            },
+            transform: Transform::new(1.0, 2.0, 3.0),
+        };
+        let raw = rect.to_raw();
+        assert_eq!(raw.ui_rect, rect.ui_rect);
+        assert_eq!(raw.transform, rect.transform.to_raw());
+        // one instance is the ui rect followed by the affine matrix, without padding.
+        assert_eq!(std::mem::size_of::<WorldRectRaw>(), 128);
+    }
+}
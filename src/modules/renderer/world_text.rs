@@ -0,0 +1,563 @@
+use std::{collections::HashMap, rc::Rc};
+
+use etagere::{size2, AtlasAllocator};
+use fontdue::{
+    layout::{CoordinateSystem, Layout, LayoutSettings, TextStyle},
+    Font,
+};
+use glam::{vec2, Vec2, Vec4};
+use image::RgbaImage;
+use wgpu::{
+    BufferUsages, FragmentState, MultisampleState, RenderPipelineDescriptor,
+    ShaderModuleDescriptor, VertexState,
+};
+
+use crate::{
+    elements::{
+        camera3d::Camera3dGR, texture::rgba_bind_group_layout, BindableTexture, Camera3d, Color,
+        GrowableBuffer, Sphere, Texture, ToRaw, Transform, TransformRaw,
+    },
+    modules::{
+        renderer::{DEPTH_FORMAT, HDR_COLOR_FORMAT},
+        Attribute, GraphicsContext, VertexT,
+    },
+    OwnedPtr,
+};
+
+/// Glyphs are rasterized at this size in pixels, the distance field keeps them sharp when scaled up.
+const SDF_SIZE: f32 = 48.0;
+/// How far (in pixels at `SDF_SIZE`) the distance field reaches out of and into a glyph.
+const SDF_SPREAD: usize = 6;
+const ATLAS_SIZE: u32 = 1024;
+/// Layouts of texts that were not drawn for this many frames are dropped.
+const EVICT_LAYOUT_AFTER_FRAMES: u64 = 120;
+
+// /////////////////////////////////////////////////////////////////////////////
+// Interface
+// /////////////////////////////////////////////////////////////////////////////
+
+/// How a text is placed in the world, see `WorldTextRenderer::draw`.
+#[derive(Debug, Clone, Copy)]
+pub struct WorldLabel {
+    /// The text is centered on the position and lies in the xy plane, facing +z.
+    pub transform: Transform,
+    /// Height of an em in world units.
+    pub size: f32,
+    pub color: Color,
+    /// rotated towards the camera, e.g. for names and health above heads. Only the position and scale of the
+    /// transform are used.
+    pub billboard: bool,
+    pub fade: Option<DistanceFade>,
+}
+
+impl WorldLabel {
+    pub fn new(transform: Transform) -> Self {
+        WorldLabel {
+            transform,
+            size: 0.5,
+            color: Color::WHITE,
+            billboard: false,
+            fade: None,
+        }
+    }
+
+    pub fn billboard(transform: Transform) -> Self {
+        WorldLabel {
+            billboard: true,
+            ..WorldLabel::new(transform)
+        }
+    }
+
+    pub fn size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn fade(mut self, start: f32, end: f32) -> Self {
+        self.fade = Some(DistanceFade { start, end });
+        self
+    }
+}
+
+/// Fades a label out between `start` and `end` distance to the closest camera. Labels further away are not drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DistanceFade {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl DistanceFade {
+    /// 1.0 up to `start`, 0.0 from `end` on.
+    pub fn opacity(&self, distance: f32) -> f32 {
+        if distance <= self.start {
+            return 1.0;
+        }
+        let range = (self.end - self.start).max(f32::EPSILON);
+        (1.0 - (distance - self.start) / range).clamp(0.0, 1.0)
+    }
+}
+
+/// Labels and glyphs of the last frame, see `WorldTextRenderer::stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorldTextStats {
+    pub labels: usize,
+    /// outside of the view of all cameras or faded out.
+    pub culled: usize,
+    pub glyphs: usize,
+}
+
+impl WorldTextRenderer {
+    /// Layouts are cached per text, drawing the same texts every frame does not lay them out again.
+    pub fn draw(&mut self, text: &str, label: WorldLabel) {
+        let layout = self.layout(text);
+        self.labels.push((layout, label));
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Module
+// /////////////////////////////////////////////////////////////////////////////
+
+/// Renders many labels in world space (names, damage numbers, signs) with signed distance field glyphs.
+///
+/// All glyphs of all labels are drawn in one instanced draw call. Labels outside of the view of the cameras are
+/// culled on the cpu before upload. Unlike `TextRenderer::draw_world_text`, the text stays sharp up close.
+pub struct WorldTextRenderer {
+    pipeline: wgpu::RenderPipeline,
+    atlas: SdfAtlas,
+    layouter: Layout,
+    layouts: HashMap<String, (Rc<LabelLayout>, u64)>,
+    labels: Vec<(Rc<LabelLayout>, WorldLabel)>,
+    instances: Vec<GlyphInstance>,
+    instance_buffer: GrowableBuffer<GlyphInstance>,
+    instance_count: u32,
+    frame: u64,
+    stats: WorldTextStats,
+}
+
+impl WorldTextRenderer {
+    pub fn new(ctx: &GraphicsContext, camera: &Camera3dGR) -> Self {
+        let pipeline = create_render_pipeline(
+            &ctx.device,
+            include_str!("world_text.wgsl"),
+            camera,
+            ctx.msaa_sample_count,
+        );
+        WorldTextRenderer {
+            pipeline,
+            atlas: SdfAtlas::new(Some(ctx)),
+            layouter: Layout::new(CoordinateSystem::PositiveYDown),
+            layouts: HashMap::new(),
+            labels: vec![],
+            instances: vec![],
+            instance_buffer: GrowableBuffer::new(&ctx.device, 512, BufferUsages::VERTEX),
+            instance_count: 0,
+            frame: 0,
+            stats: WorldTextStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> WorldTextStats {
+        self.stats
+    }
+
+    /// Culls the labels drawn this frame against the `cameras` (e.g. all split screen players) and uploads the glyphs.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, cameras: &[&Camera3d]) {
+        self.atlas.upload(queue);
+
+        let frustums: Vec<_> = cameras.iter().map(|c| c.frustum()).collect();
+        self.instances.clear();
+        self.stats = WorldTextStats {
+            labels: self.labels.len(),
+            ..Default::default()
+        };
+        for (layout, label) in self.labels.drain(..) {
+            let position = label.transform.position;
+            let radius = layout.radius * label.size * label.transform.scale.max_element();
+            let bounds = Sphere::new(position, radius);
+            let distance = cameras
+                .iter()
+                .map(|c| c.transform.position().distance(position))
+                .fold(f32::INFINITY, f32::min);
+            let opacity = label.fade.map_or(1.0, |fade| fade.opacity(distance));
+            if opacity <= 0.0 || !frustums.iter().any(|f| f.intersects_sphere(&bounds)) {
+                self.stats.culled += 1;
+                continue;
+            }
+
+            let mut color = label.color;
+            color.a *= opacity;
+            let transform = label.transform.to_raw();
+            let params = [label.size, label.billboard as u32 as f32, 0.0, 0.0];
+            self.instances
+                .extend(layout.glyphs.iter().map(|glyph| GlyphInstance {
+                    pos: glyph.pos.to_array(),
+                    uv: glyph.uv.to_array(),
+                    color,
+                    transform,
+                    params,
+                }));
+        }
+        self.stats.glyphs = self.instances.len();
+        self.instance_count = self.instances.len() as u32;
+        self.instance_buffer.prepare(&self.instances, device, queue);
+
+        self.frame += 1;
+        let frame = self.frame;
+        self.layouts
+            .retain(|_, (_, last_used)| frame - *last_used < EVICT_LAYOUT_AFTER_FRAMES);
+    }
+
+    pub fn render<'encoder>(
+        &'encoder self,
+        render_pass: &mut wgpu::RenderPass<'encoder>,
+        camera: &'encoder Camera3dGR,
+    ) {
+        if self.instance_count == 0 {
+            return;
+        }
+        let Some(texture) = &self.atlas.texture else {
+            return;
+        };
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera.bind_group(), &[]);
+        render_pass.set_bind_group(1, &texture.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.buffer().slice(..));
+        render_pass.draw(0..6, 0..self.instance_count);
+    }
+
+    fn layout(&mut self, text: &str) -> Rc<LabelLayout> {
+        if let Some((layout, last_used)) = self.layouts.get_mut(text) {
+            *last_used = self.frame;
+            return layout.clone();
+        }
+        let layout = Rc::new(layout_text(&mut self.layouter, &mut self.atlas, text));
+        self.layouts
+            .insert(text.to_string(), (layout.clone(), self.frame));
+        layout
+    }
+}
+
+/// Glyph quads of a text, centered on the origin, in ems.
+struct LabelLayout {
+    glyphs: Vec<LayoutGlyph>,
+    /// of the bounding circle.
+    radius: f32,
+}
+
+struct LayoutGlyph {
+    /// top left corner and size in ems, y down.
+    pos: Vec4,
+    /// top left corner and size in the atlas.
+    uv: Vec4,
+}
+
+fn layout_text(layouter: &mut Layout, atlas: &mut SdfAtlas, text: &str) -> LabelLayout {
+    layouter.reset(&LayoutSettings::default());
+    layouter.append(&[&atlas.font], &TextStyle::new(text, SDF_SIZE, 0));
+
+    let mut glyphs = vec![];
+    let mut max = Vec2::ZERO;
+    for glyph in layouter.glyphs() {
+        max = max.max(vec2(
+            glyph.x + glyph.width as f32,
+            glyph.y + glyph.height as f32,
+        ));
+        if glyph.width == 0 || glyph.height == 0 {
+            continue;
+        }
+        let Some(atlas_glyph) = atlas.glyph(glyph.key.glyph_index) else {
+            continue;
+        };
+        let padding = SDF_SPREAD as f32;
+        let pos = vec2(glyph.x - padding, glyph.y - padding);
+        glyphs.push((pos, atlas_glyph.size, atlas_glyph.uv));
+    }
+    let size = vec2(max.x, max.y.max(layouter.height()));
+    let center = size * 0.5;
+    LabelLayout {
+        glyphs: glyphs
+            .into_iter()
+            .map(|(pos, glyph_size, uv)| LayoutGlyph {
+                pos: ((pos - center) / SDF_SIZE).extend(0.0).extend(0.0)
+                    + Vec4::new(0.0, 0.0, glyph_size.x, glyph_size.y) / SDF_SIZE,
+                uv,
+            })
+            .collect(),
+        radius: (center / SDF_SIZE).length(),
+    }
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Signed distance field atlas
+// /////////////////////////////////////////////////////////////////////////////
+
+struct SdfAtlas {
+    font: Font,
+    allocator: AtlasAllocator,
+    /// None without gpu, for tests.
+    texture: Option<OwnedPtr<BindableTexture>>,
+    /// None if the glyph did not fit into the atlas anymore.
+    glyphs: HashMap<u16, Option<AtlasGlyph>>,
+    texture_writes: Vec<(RgbaImage, [u32; 2])>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AtlasGlyph {
+    /// size of the distance field in pixels, including the spread.
+    size: Vec2,
+    /// top left corner and size, normalized.
+    uv: Vec4,
+}
+
+impl SdfAtlas {
+    fn new(ctx: Option<&GraphicsContext>) -> Self {
+        const DEFAULT_FONT_BYTES: &[u8] = include_bytes!("../../../assets/Oswald-Medium.ttf");
+        let font = Font::from_bytes(DEFAULT_FONT_BYTES, Default::default())
+            .expect("could not load default font");
+        let texture = ctx.map(|ctx| {
+            let image = RgbaImage::new(ATLAS_SIZE, ATLAS_SIZE);
+            let texture = Texture::from_linear_image(&ctx.device, &ctx.queue, &image);
+            OwnedPtr::new(BindableTexture::new(&ctx.device, texture))
+        });
+        SdfAtlas {
+            font,
+            allocator: AtlasAllocator::new(size2(ATLAS_SIZE as i32, ATLAS_SIZE as i32)),
+            texture,
+            glyphs: HashMap::new(),
+            texture_writes: vec![],
+        }
+    }
+
+    /// Rasterizes the glyph and computes its distance field the first time it is used.
+    fn glyph(&mut self, glyph_index: u16) -> Option<AtlasGlyph> {
+        if let Some(glyph) = self.glyphs.get(&glyph_index) {
+            return *glyph;
+        }
+        let (metrics, coverage) = self.font.rasterize_indexed(glyph_index, SDF_SIZE);
+        let (field, width, height) =
+            distance_field(&coverage, metrics.width, metrics.height, SDF_SPREAD);
+        // one pixel of space between glyphs, such that they do not bleed into each other.
+        let allocation = self
+            .allocator
+            .allocate(size2(width as i32 + 1, height as i32 + 1));
+        let glyph = allocation.map(|allocation| {
+            let min = allocation.rectangle.min;
+            let image = RgbaImage::from_fn(width as u32, height as u32, |x, y| {
+                let d = field[y as usize * width + x as usize];
+                image::Rgba([d, d, d, 255])
+            });
+            self.texture_writes
+                .push((image, [min.x as u32, min.y as u32]));
+            let size = vec2(width as f32, height as f32);
+            AtlasGlyph {
+                size,
+                uv: Vec4::new(min.x as f32, min.y as f32, size.x, size.y) / ATLAS_SIZE as f32,
+            }
+        });
+        if glyph.is_none() {
+            log::warn!("world text atlas is full, glyph {glyph_index} is not drawn");
+        }
+        self.glyphs.insert(glyph_index, glyph);
+        glyph
+    }
+
+    fn upload(&mut self, queue: &wgpu::Queue) {
+        let Some(texture) = &self.texture else {
+            self.texture_writes.clear();
+            return;
+        };
+        for (image, [x, y]) in self.texture_writes.drain(..) {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture.texture.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x, y, z: 0 },
+                },
+                &image,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * image.width()),
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    width: image.width(),
+                    height: image.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+}
+
+/// Signed distance field of a coverage bitmap, padded by `spread` on each side. 128 is the edge, larger values are
+/// inside. Brute force within the spread, which is fast enough for glyphs that are computed once.
+fn distance_field(
+    coverage: &[u8],
+    width: usize,
+    height: usize,
+    spread: usize,
+) -> (Vec<u8>, usize, usize) {
+    let (out_width, out_height) = (width + 2 * spread, height + 2 * spread);
+    let inside = |x: isize, y: isize| -> bool {
+        let (x, y) = (x - spread as isize, y - spread as isize);
+        x >= 0
+            && y >= 0
+            && (x as usize) < width
+            && (y as usize) < height
+            && coverage[y as usize * width + x as usize] >= 128
+    };
+    let spread_i = spread as isize;
+    let mut field = vec![0; out_width * out_height];
+    for y in 0..out_height as isize {
+        for x in 0..out_width as isize {
+            let is_inside = inside(x, y);
+            // distance to the closest pixel on the other side of the edge.
+            let mut closest_sq = ((spread + 1) * (spread + 1)) as isize;
+            for dy in -spread_i..=spread_i {
+                for dx in -spread_i..=spread_i {
+                    let distance_sq = dx * dx + dy * dy;
+                    if distance_sq < closest_sq && inside(x + dx, y + dy) != is_inside {
+                        closest_sq = distance_sq;
+                    }
+                }
+            }
+            // the edge is half way between the pixels.
+            let distance = (closest_sq as f32).sqrt() - 0.5;
+            let signed = if is_inside { distance } else { -distance };
+            let value = 0.5 + signed / (2.0 * spread as f32);
+            field[y as usize * out_width + x as usize] = (value.clamp(0.0, 1.0) * 255.0) as u8;
+        }
+    }
+    (field, out_width, out_height)
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Rendering
+// /////////////////////////////////////////////////////////////////////////////
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GlyphInstance {
+    pos: [f32; 4],
+    uv: [f32; 4],
+    color: Color,
+    transform: TransformRaw,
+    params: [f32; 4],
+}
+
+impl VertexT for GlyphInstance {
+    const ATTRIBUTES: &'static [Attribute] = &[
+        Attribute::new("pos", wgpu::VertexFormat::Float32x4),
+        Attribute::new("uv", wgpu::VertexFormat::Float32x4),
+        Attribute::new("color", wgpu::VertexFormat::Float32x4),
+        Attribute::new("col1", wgpu::VertexFormat::Float32x4),
+        Attribute::new("col2", wgpu::VertexFormat::Float32x4),
+        Attribute::new("col3", wgpu::VertexFormat::Float32x4),
+        Attribute::new("translation", wgpu::VertexFormat::Float32x4),
+        Attribute::new("params", wgpu::VertexFormat::Float32x4),
+    ];
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    wgsl: &str,
+    camera: &Camera3dGR,
+    msaa_sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let label = "WorldText";
+    let shader_module = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(&format!("{label} ShaderModule")),
+        source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+    });
+
+    let _empty = &mut vec![];
+    let vertex_buffers_layout = &[GlyphInstance::vertex_buffer_layout(0, true, _empty)];
+
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(&format!("{label} PipelineLayout")),
+        bind_group_layouts: &[camera.bind_group_layout(), &rgba_bind_group_layout(device)],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(&format!("{label} Pipeline")),
+        layout: Some(&layout),
+        vertex: VertexState {
+            module: &shader_module,
+            entry_point: "vs_main",
+            buffers: vertex_buffers_layout,
+        },
+        fragment: Some(FragmentState {
+            module: &shader_module,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: HDR_COLOR_FORMAT,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: Default::default(),
+        // tested against, but not written to the depth buffer, such that overlapping labels blend.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: MultisampleState {
+            count: msaa_sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_field_and_centered_layout() {
+        // a 4x4 square in an 8x8 bitmap.
+        let coverage: Vec<u8> = (0..64)
+            .map(
+                |i| match (2..6).contains(&(i % 8)) && (2..6).contains(&(i / 8)) {
+                    true => 255,
+                    false => 0,
+                },
+            )
+            .collect();
+        let (field, width, height) = distance_field(&coverage, 8, 8, 3);
+        assert_eq!((width, height), (14, 14));
+        let at = |x: usize, y: usize| field[(y + 3) * width + x + 3];
+        assert!(at(3, 3) > 128 && at(3, 3) > at(2, 3));
+        assert!(at(1, 3) < 128 && at(0, 3) < at(1, 3));
+        assert_eq!(field[0], 0);
+
+        let mut atlas = SdfAtlas::new(None);
+        let mut layouter = Layout::new(CoordinateSystem::PositiveYDown);
+        let layout = layout_text(&mut layouter, &mut atlas, "Hi you");
+        // the space has no glyph.
+        assert_eq!(layout.glyphs.len(), 5);
+        let min_x = layout
+            .glyphs
+            .iter()
+            .map(|g| g.pos.x)
+            .fold(f32::MAX, f32::min);
+        let max_x = layout
+            .glyphs
+            .iter()
+            .map(|g| g.pos.x + g.pos.z)
+            .fold(f32::MIN, f32::max);
+        assert!((min_x + max_x).abs() < 0.1);
+        assert_eq!(atlas.texture_writes.len(), 5);
+    }
+}
@@ -0,0 +1,109 @@
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+/// signed distance field of the glyphs in the red channel, 0.5 is the edge.
+@group(1) @binding(0)
+var t_sdf: texture_2d<f32>;
+@group(1) @binding(1)
+var s_sdf: sampler;
+
+struct Instance {
+    /// glyph rect in ems relative to the center of the label, y down: top left corner and size
+    @location(0) pos: vec4<f32>,
+    /// uv rect in the atlas: top left corner and size
+    @location(1) uv: vec4<f32>,
+    @location(2) color: vec4<f32>,
+    /// transform of the label
+    @location(3) col1: vec4<f32>,
+    @location(4) col2: vec4<f32>,
+    @location(5) col3: vec4<f32>,
+    @location(6) translation: vec4<f32>,
+    /// x: size of an em in world units, y: 1.0 if the label faces the camera
+    @location(7) params: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    instance: Instance,
+) -> VertexOutput {
+    let corner = quad_corner(vertex_index);
+    let pos = instance.pos.xy + corner * instance.pos.zw;
+    // in the xy plane of the label, y up.
+    let local = vec2<f32>(pos.x, -pos.y) * instance.params.x;
+
+    var world_position: vec3<f32>;
+    if instance.params.y > 0.5 {
+        // billboard: rotated towards the camera, only the scale of the transform is used.
+        let center = instance.translation.xyz;
+        let to_camera = normalize(camera.view_pos.xyz - center);
+        var right = cross(vec3<f32>(0.0, 1.0, 0.0), to_camera);
+        if length(right) < 0.0001 {
+            // looking straight down or up.
+            right = vec3<f32>(1.0, 0.0, 0.0);
+        }
+        right = normalize(right);
+        let up = cross(to_camera, right);
+        let scale = vec2<f32>(length(instance.col1.xyz), length(instance.col2.xyz));
+        world_position = center + right * local.x * scale.x + up * local.y * scale.y;
+    } else {
+        let model_matrix = mat4x4<f32>(
+            instance.col1,
+            instance.col2,
+            instance.col3,
+            instance.translation,
+        );
+        world_position = (model_matrix * vec4<f32>(local, 0.0, 1.0)).xyz;
+    }
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.color = instance.color;
+    out.uv = instance.uv.xy + corner * instance.uv.zw;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let distance = textureSample(t_sdf, s_sdf, in.uv).r;
+    // about one pixel of anti aliasing, at any distance to the camera.
+    let width = max(fwidth(distance) * 0.7, 0.0001);
+    let opacity = smoothstep(0.5 - width, 0.5 + width, distance) * in.color.a;
+    if opacity < 0.01 {
+        discard;
+    }
+    return vec4(in.color.rgb, opacity);
+}
+
+// corners of a quad as two triangles, (0,0) is the top left:
+// 0 ------ 1 4
+// |     .  |
+// |  .     |
+// 2 3 ---- 5
+fn quad_corner(idx: u32) -> vec2<f32> {
+    switch idx {
+        case 0u: {
+            return vec2<f32>(0.0, 0.0);
+        }
+        case 1u, 4u: {
+            return vec2<f32>(1.0, 0.0);
+        }
+        case 2u, 3u: {
+            return vec2<f32>(0.0, 1.0);
+        }
+        case 5u, default: {
+            return vec2<f32>(1.0, 1.0);
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! User facing options that persist between runs, stored as json in the config directory of the platform:
+//! `$XDG_CONFIG_HOME/<app>` or `~/.config/<app>` on linux, `~/Library/Application Support/<app>` on macos and
+//! `%APPDATA%\<app>` on windows.
+//!
+//! The file is written atomically: to a temporary file first, which then replaces the old one. A crash while
+//! saving leaves the old settings intact. A corrupted file is moved aside and the defaults are used.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{KeyChord, Shortcuts};
+
+/// A typed key into the `Settings`, with the value that is used if the user did not change it.
+///
+/// ```rust,ignore
+/// const SHOW_FPS: Setting<bool> = Setting::new("show_fps", || false);
+/// if mods.settings.get(SHOW_FPS) { ... }
+/// ```
+pub struct Setting<T> {
+    pub name: &'static str,
+    default: fn() -> T,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> Setting<T> {
+    pub const fn new(name: &'static str, default: fn() -> T) -> Self {
+        Setting {
+            name,
+            default,
+            _value: PhantomData,
+        }
+    }
+
+    pub fn default_value(&self) -> T {
+        (self.default)()
+    }
+}
+
+impl<T> Clone for Setting<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Setting<T> {}
+
+/// Prefix of the settings that hold key bindings, followed by the name of the shortcut.
+const KEY_BINDING_PREFIX: &str = "keys.";
+
+/// Settings are applied by `DefaultModules` in `begin_frame`, the frame after they were set:
+/// `RESOLUTION` to the window, `VSYNC` to the surface, `VOLUME` to the master bus and `UI_SCALE` to the `Screen`.
+/// Key bindings are applied with `apply_key_bindings`, after the shortcuts were registered.
+#[derive(Debug)]
+pub struct Settings {
+    /// None keeps the settings in memory only.
+    path: Option<PathBuf>,
+    values: BTreeMap<String, Value>,
+    /// set since the last `update`.
+    pending_changes: Vec<String>,
+    /// visible in this frame.
+    changed: Vec<String>,
+    /// when the first change that is not saved yet was made.
+    unsaved_since: Option<Instant>,
+    /// Changes are saved once no change was made for this long, such that dragging a slider does not write
+    /// the file every frame.
+    pub save_delay: Duration,
+}
+
+impl Settings {
+    /// Window size in physical pixels. None keeps the size of the `WinitConfig`.
+    pub const RESOLUTION: Setting<Option<[u32; 2]>> = Setting::new("resolution", || None);
+    pub const VSYNC: Setting<bool> = Setting::new("vsync", || false);
+    /// Volume of the master bus.
+    pub const VOLUME: Setting<f32> = Setting::new("volume", || 1.0);
+    /// `Screen::ui_scale`
+    pub const UI_SCALE: Setting<f32> = Setting::new("ui_scale", || 1.0);
+
+    /// Settings that are not persisted, e.g. for tests.
+    pub fn in_memory() -> Self {
+        Settings {
+            path: None,
+            values: BTreeMap::new(),
+            pending_changes: vec![],
+            changed: vec![],
+            unsaved_since: None,
+            save_delay: Duration::from_millis(500),
+        }
+    }
+
+    /// Loads `settings.json` from the config directory of the app. If there is no config directory,
+    /// the settings are kept in memory.
+    pub fn load_for_app(app_name: &str) -> Self {
+        match config_dir(app_name) {
+            Some(dir) => Self::load(dir.join("settings.json")),
+            None => {
+                log::warn!("no config directory, settings are not saved");
+                Self::in_memory()
+            }
+        }
+    }
+
+    /// Starts with the defaults if the file does not exist or cannot be read.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let mut settings = Settings {
+            path: Some(path.clone()),
+            ..Self::in_memory()
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return settings,
+            Err(err) => {
+                log::error!("could not read settings {path:?}, using defaults: {err}");
+                return settings;
+            }
+        };
+        match serde_json::from_str(&text) {
+            Ok(values) => settings.values = values,
+            Err(err) => {
+                // kept for the user to look at, the next save would overwrite it.
+                let corrupt = path.with_extension("json.corrupt");
+                log::error!(
+                    "settings {path:?} are corrupted, moved to {corrupt:?}, using defaults: {err}"
+                );
+                _ = fs::rename(&path, corrupt);
+            }
+        }
+        settings
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The stored value, or the default if there is none or it has the wrong type.
+    pub fn get<T: DeserializeOwned>(&self, setting: Setting<T>) -> T {
+        let Some(value) = self.values.get(setting.name) else {
+            return setting.default_value();
+        };
+        match T::deserialize(value) {
+            Ok(value) => value,
+            Err(err) => {
+                log::warn!("invalid setting {}: {err}", setting.name);
+                setting.default_value()
+            }
+        }
+    }
+
+    /// Records a change event if the value is different from before.
+    pub fn set<T: Serialize>(&mut self, setting: Setting<T>, value: T) {
+        match serde_json::to_value(value) {
+            Ok(value) => self.set_value(setting.name, value),
+            Err(err) => log::error!("could not serialize setting {}: {err}", setting.name),
+        }
+    }
+
+    /// Goes back to the default value.
+    pub fn reset<T>(&mut self, setting: Setting<T>) {
+        if self.values.remove(setting.name).is_some() {
+            self.mark_changed(setting.name);
+        }
+    }
+
+    fn set_value(&mut self, name: &str, value: Value) {
+        if self.values.get(name) != Some(&value) {
+            self.values.insert(name.to_string(), value);
+            self.mark_changed(name);
+        }
+    }
+
+    fn mark_changed(&mut self, name: &str) {
+        if !self.pending_changes.iter().any(|c| c == name) {
+            self.pending_changes.push(name.to_string());
+        }
+        self.unsaved_since = Some(Instant::now());
+    }
+
+    /// true if the setting was changed in the last frame.
+    pub fn was_changed<T>(&self, setting: Setting<T>) -> bool {
+        self.changed.iter().any(|c| c == setting.name)
+    }
+
+    /// Names of the settings changed in the last frame.
+    pub fn changed(&self) -> &[String] {
+        &self.changed
+    }
+
+    pub fn key_binding(&self, shortcut: &str) -> Option<Vec<KeyChord>> {
+        let value = self
+            .values
+            .get(&format!("{KEY_BINDING_PREFIX}{shortcut}"))?;
+        Vec::<KeyChord>::deserialize(value).ok()
+    }
+
+    pub fn set_key_binding(&mut self, shortcut: &str, chords: &[KeyChord]) {
+        match serde_json::to_value(chords) {
+            Ok(value) => self.set_value(&format!("{KEY_BINDING_PREFIX}{shortcut}"), value),
+            Err(err) => log::error!("could not serialize key binding {shortcut}: {err}"),
+        }
+    }
+
+    /// `Settings::VSYNC` as present mode of the surface.
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        match self.get(Settings::VSYNC) {
+            true => wgpu::PresentMode::AutoVsync,
+            false => wgpu::PresentMode::AutoNoVsync,
+        }
+    }
+
+    /// Rebinds all shortcuts the user chose other keys for. Call after registering the shortcuts and
+    /// whenever `changed` contains key bindings.
+    pub fn apply_key_bindings(&self, shortcuts: &mut Shortcuts) {
+        for (name, value) in self.values.iter() {
+            let Some(shortcut) = name.strip_prefix(KEY_BINDING_PREFIX) else {
+                continue;
+            };
+            match Vec::<KeyChord>::deserialize(value) {
+                Ok(chords) => {
+                    shortcuts.rebind(shortcut, &chords);
+                }
+                Err(err) => log::warn!("invalid key binding {shortcut}: {err}"),
+            }
+        }
+    }
+
+    /// Makes the changes since the last call visible and saves them once `save_delay` passed.
+    /// Called by `DefaultModules::begin_frame`.
+    pub fn update(&mut self) {
+        self.changed.clear();
+        std::mem::swap(&mut self.changed, &mut self.pending_changes);
+        if self
+            .unsaved_since
+            .is_some_and(|since| since.elapsed() >= self.save_delay)
+        {
+            self.save_logged();
+        }
+    }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.unsaved_since.is_some()
+    }
+
+    /// Writes the settings to a temporary file next to the settings file and then moves it over the old one.
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        self.unsaved_since = None;
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = serde_json::to_string_pretty(&self.values)?;
+        let tmp_path = path.with_extension("json.tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(text.as_bytes())?;
+        // on disk before the rename, otherwise a power loss can leave an empty file behind.
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Saves and logs errors, used where there is no one to return them to.
+    pub(crate) fn save_logged(&mut self) {
+        if let Err(err) = self.save() {
+            log::error!("could not save settings to {:?}: {err}", self.path);
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::in_memory()
+    }
+}
+
+/// The directory for config files of the app, None if the home directory is unknown.
+pub fn config_dir(app_name: &str) -> Option<PathBuf> {
+    let non_empty = |var: &str| std::env::var_os(var).filter(|v| !v.is_empty());
+    let base = if cfg!(windows) {
+        PathBuf::from(non_empty("APPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(non_empty("HOME")?).join("Library/Application Support")
+    } else {
+        match non_empty("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(non_empty("HOME")?).join(".config"),
+        }
+    };
+    Some(base.join(app_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::keyboard::KeyCode;
+
+    use super::*;
+
+    #[test]
+    fn typed_settings_change_events_and_persistence() {
+        const SHOW_FPS: Setting<bool> = Setting::new("show_fps", || false);
+        let dir = std::env::temp_dir().join(format!("vert_settings_test_{}", std::process::id()));
+        let path = dir.join("settings.json");
+        _ = fs::remove_dir_all(&dir);
+
+        let mut settings = Settings::load(&path);
+        assert_eq!(settings.get(Settings::VOLUME), 1.0);
+        settings.set(Settings::VOLUME, 0.5);
+        settings.set(SHOW_FPS, false);
+        settings.set_key_binding("save", &[KeyChord::new(KeyCode::KeyS).ctrl()]);
+        assert!(!settings.was_changed(Settings::VOLUME));
+        settings.update();
+        assert!(settings.was_changed(Settings::VOLUME));
+        assert_eq!(settings.changed(), ["volume", "show_fps", "keys.save"]);
+        // setting the same value again is no change.
+        settings.set(Settings::VOLUME, 0.5);
+        settings.update();
+        assert!(settings.changed().is_empty());
+        settings.save().unwrap();
+        assert!(!path.with_extension("json.tmp").exists());
+
+        let mut loaded = Settings::load(&path);
+        assert_eq!(loaded.get(Settings::VOLUME), 0.5);
+        assert!(!loaded.get(SHOW_FPS));
+        let mut shortcuts = Shortcuts::new();
+        shortcuts
+            .register("save", KeyChord::new(KeyCode::F5))
+            .priority(3);
+        loaded.apply_key_bindings(&mut shortcuts);
+        assert_eq!(
+            loaded.key_binding("save"),
+            Some(vec![KeyChord::new(KeyCode::KeyS).ctrl()])
+        );
+
+        // a value of the wrong type falls back to the default.
+        loaded.set_value("volume", Value::String("loud".into()));
+        assert_eq!(loaded.get(Settings::VOLUME), 1.0);
+
+        fs::write(&path, "{ not json").unwrap();
+        let corrupted = Settings::load(&path);
+        assert_eq!(corrupted.get(Settings::VOLUME), 1.0);
+        assert!(path.with_extension("json.corrupt").exists());
+        _ = fs::remove_dir_all(&dir);
+    }
+}
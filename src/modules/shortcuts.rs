@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use winit::keyboard::{KeyCode, ModifiersState};
+
+use super::Input;
+
+/// Registry of keyboard shortcuts like `Ctrl+S` or `F5`, dispatched once per frame in `DefaultModules::begin_frame`.
+///
+/// Every pressed key triggers at most one shortcut: of all shortcuts matching the key and modifiers whose context
+/// is active, the one with the highest priority wins and swallows the key. Ties are broken by registration order.
+/// Swallowed keys are consumed in `Input` (see `KeyState::consume`) and removed from the egui input, such that
+/// nothing else handles them.
+/// While a text field has focus (see `DefaultModules::request_text_input`), shortcuts without ctrl, alt or super
+/// are not triggered, such that typing does not fire them, unless they are registered with `allow_in_text_input`.
+#[derive(Debug, Default)]
+pub struct Shortcuts {
+    entries: Vec<ShortcutEntry>,
+    active_contexts: Vec<&'static str>,
+    /// names of the shortcuts triggered this frame.
+    triggered: SmallVec<[&'static str; 2]>,
+    /// keys that triggered a shortcut this frame.
+    swallowed: SmallVec<[KeyCode; 2]>,
+}
+
+#[derive(Debug)]
+pub struct ShortcutEntry {
+    name: &'static str,
+    chord: KeyChord,
+    priority: i32,
+    context: Option<&'static str>,
+    allow_in_text_input: bool,
+}
+
+impl ShortcutEntry {
+    /// Shortcuts with higher priority win if multiple shortcuts match the same key. Default is 0.
+    pub fn priority(&mut self, priority: i32) -> &mut Self {
+        self.priority = priority;
+        self
+    }
+
+    /// The shortcut is only triggered while the context is active, see `Shortcuts::set_context_active`.
+    /// Without a context, the shortcut is always active.
+    pub fn context(&mut self, context: &'static str) -> &mut Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Also trigger while a text field has focus, e.g. for `Escape` or `Enter`.
+    pub fn allow_in_text_input(&mut self) -> &mut Self {
+        self.allow_in_text_input = true;
+        self
+    }
+}
+
+/// A key with the modifiers that need to be held. Other modifiers than the required ones must not be held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct KeyChord {
+    pub key: KeyCode,
+    pub modifiers: ModifiersState,
+}
+
+impl KeyChord {
+    pub const fn new(key: KeyCode) -> Self {
+        KeyChord {
+            key,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    pub fn ctrl(mut self) -> Self {
+        self.modifiers |= ModifiersState::CONTROL;
+        self
+    }
+
+    pub fn shift(mut self) -> Self {
+        self.modifiers |= ModifiersState::SHIFT;
+        self
+    }
+
+    pub fn alt(mut self) -> Self {
+        self.modifiers |= ModifiersState::ALT;
+        self
+    }
+
+    pub fn super_key(mut self) -> Self {
+        self.modifiers |= ModifiersState::SUPER;
+        self
+    }
+
+    fn has_command_modifier(&self) -> bool {
+        self.modifiers
+            .intersects(ModifiersState::CONTROL | ModifiersState::ALT | ModifiersState::SUPER)
+    }
+}
+
+impl Shortcuts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Multiple chords can be registered under the same name, e.g. `Ctrl+S` and `F5` for "save".
+    pub fn register(&mut self, name: &'static str, chord: KeyChord) -> &mut ShortcutEntry {
+        self.entries.push(ShortcutEntry {
+            name,
+            chord,
+            priority: 0,
+            context: None,
+            allow_in_text_input: false,
+        });
+        self.entries.last_mut().unwrap()
+    }
+
+    /// Removes all chords registered under the name.
+    pub fn unregister(&mut self, name: &'static str) {
+        self.entries.retain(|e| e.name != name);
+    }
+
+    /// Replaces the chords registered under the name, e.g. with key bindings the user chose (see `Settings`).
+    /// The new chords keep priority, context and text input behavior of the first old one.
+    /// Returns false if nothing is registered under the name.
+    pub fn rebind(&mut self, name: &str, chords: &[KeyChord]) -> bool {
+        let Some(index) = self.entries.iter().position(|e| e.name == name) else {
+            return false;
+        };
+        let first = &self.entries[index];
+        let (name, priority, context, allow_in_text_input) = (
+            first.name,
+            first.priority,
+            first.context,
+            first.allow_in_text_input,
+        );
+        self.entries.retain(|e| e.name != name);
+        let rebound = chords.iter().map(|chord| ShortcutEntry {
+            name,
+            chord: *chord,
+            priority,
+            context,
+            allow_in_text_input,
+        });
+        // at the old position, such that ties are still broken the same way.
+        self.entries.splice(index..index, rebound);
+        true
+    }
+
+    /// Contexts, e.g. "editor", "gameplay" or "console", enable and disable groups of shortcuts.
+    pub fn set_context_active(&mut self, context: &'static str, active: bool) {
+        let is_active = self.is_context_active(context);
+        if active && !is_active {
+            self.active_contexts.push(context);
+        } else if !active && is_active {
+            self.active_contexts.retain(|c| *c != context);
+        }
+    }
+
+    pub fn is_context_active(&self, context: &'static str) -> bool {
+        self.active_contexts.contains(&context)
+    }
+
+    /// true if a chord registered under this name was pressed this frame.
+    pub fn triggered(&self, name: &'static str) -> bool {
+        self.triggered.contains(&name)
+    }
+
+    /// true if the key triggered a shortcut this frame.
+    pub fn is_swallowed(&self, key: KeyCode) -> bool {
+        self.swallowed.contains(&key)
+    }
+
+    pub fn swallowed(&self) -> &[KeyCode] {
+        &self.swallowed
+    }
+
+    /// Determines the triggered shortcuts of this frame from the keys just pressed.
+    pub fn dispatch(&mut self, input: &Input, text_input_active: bool) {
+        self.triggered.clear();
+        self.swallowed.clear();
+        let modifiers = input.modifiers();
+        for key in input.keys().iter_just_pressed() {
+            let winner = self
+                .entries
+                .iter()
+                .filter(|e| e.chord.key == key && e.chord.modifiers == modifiers)
+                .filter(|e| e.context.is_none_or(|c| self.is_context_active(c)))
+                .filter(|e| {
+                    !text_input_active || e.allow_in_text_input || e.chord.has_command_modifier()
+                })
+                // max_by_key returns the last max element, so reverse to prefer the first registered.
+                .rev()
+                .max_by_key(|e| e.priority);
+            if let Some(entry) = winner {
+                self.triggered.push(entry.name);
+                self.swallowed.push(key);
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use glam::{vec2, Vec2};
+use smallvec::SmallVec;
+use winit::keyboard::KeyCode;
+
+use crate::elements::{camera3d::Camera3dGR, Camera3d, Rect, Screen};
+
+use crate::{GpuRecreate, GpuRecreated};
+
+use super::{GraphicsContext, Input};
+
+/// Renders the world once per local player, each into its own viewport of the screen.
+///
+/// As long as no players are added, the main camera of the `DefaultModules` is used.
+pub struct SplitScreen {
+    pub layout: SplitScreenLayout,
+    players: Vec<LocalPlayer>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitScreenLayout {
+    /// Viewports next to each other, left to right.
+    Horizontal,
+    /// Viewports on top of each other, top to bottom.
+    Vertical,
+    /// Viewports in a grid that is as square as possible, e.g. 2x2 for 4 players.
+    #[default]
+    Grid,
+}
+
+pub struct LocalPlayer {
+    pub camera: Camera3d,
+    camera_gr: Camera3dGR,
+    pub keys: PlayerKeys,
+}
+
+impl LocalPlayer {
+    pub fn camera_gr(&self) -> &Camera3dGR {
+        &self.camera_gr
+    }
+}
+
+/// The part of the keyboard a local player uses, such that multiple players can share one keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerKeys {
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+}
+
+impl PlayerKeys {
+    pub const WASD: PlayerKeys = PlayerKeys {
+        up: KeyCode::KeyW,
+        down: KeyCode::KeyS,
+        left: KeyCode::KeyA,
+        right: KeyCode::KeyD,
+    };
+
+    pub const ARROWS: PlayerKeys = PlayerKeys {
+        up: KeyCode::ArrowUp,
+        down: KeyCode::ArrowDown,
+        left: KeyCode::ArrowLeft,
+        right: KeyCode::ArrowRight,
+    };
+
+    pub const IJKL: PlayerKeys = PlayerKeys {
+        up: KeyCode::KeyI,
+        down: KeyCode::KeyK,
+        left: KeyCode::KeyJ,
+        right: KeyCode::KeyL,
+    };
+
+    /// Normalized movement vector of this player, like `Input::wasd_vec`.
+    pub fn move_vec(&self, input: &Input) -> Vec2 {
+        let keys = input.keys();
+        let mut v = Vec2::ZERO;
+        if keys.is_pressed(self.up) {
+            v.y += 1.0;
+        }
+        if keys.is_pressed(self.down) {
+            v.y -= 1.0;
+        }
+        if keys.is_pressed(self.left) {
+            v.x -= 1.0;
+        }
+        if keys.is_pressed(self.right) {
+            v.x += 1.0;
+        }
+        v.normalize_or_zero()
+    }
+}
+
+impl Default for SplitScreen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SplitScreen {
+    pub fn new() -> Self {
+        SplitScreen {
+            layout: SplitScreenLayout::default(),
+            players: vec![],
+        }
+    }
+
+    /// Returns the index of the new player.
+    pub fn add_player(
+        &mut self,
+        ctx: &GraphicsContext,
+        camera: Camera3d,
+        keys: PlayerKeys,
+    ) -> usize {
+        let camera_gr = Camera3dGR::new(ctx, &camera);
+        self.players.push(LocalPlayer {
+            camera,
+            camera_gr,
+            keys,
+        });
+        self.players.len() - 1
+    }
+
+    pub fn remove_player(&mut self, index: usize) -> LocalPlayer {
+        self.players.remove(index)
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.players.is_empty()
+    }
+
+    pub fn players(&self) -> &[LocalPlayer] {
+        &self.players
+    }
+
+    pub fn player_mut(&mut self, index: usize) -> &mut LocalPlayer {
+        &mut self.players[index]
+    }
+
+    /// Index of the player whose viewport contains the `window_pos`, e.g. to route mouse input to that player.
+    pub fn player_at(&self, window_pos: Vec2, screen: &Screen) -> Option<usize> {
+        self.players
+            .iter()
+            .position(|p| p.camera.viewport_or_screen(screen).contains(window_pos))
+    }
+
+    /// Viewports for `player_count` players inside of the `area`.
+    pub fn viewports(&self, area: Rect, player_count: usize) -> SmallVec<[Rect; 4]> {
+        if player_count == 0 {
+            return SmallVec::new();
+        }
+        let (cols, rows) = match self.layout {
+            SplitScreenLayout::Horizontal => (player_count, 1),
+            SplitScreenLayout::Vertical => (1, player_count),
+            SplitScreenLayout::Grid => {
+                let cols = (player_count as f32).sqrt().ceil() as usize;
+                (cols, player_count.div_ceil(cols))
+            }
+        };
+        let cell = vec2(area.width / cols as f32, area.height / rows as f32);
+        (0..player_count)
+            .map(|i| {
+                let (col, row) = (i % cols, i / cols);
+                Rect::new(
+                    area.min_x + col as f32 * cell.x,
+                    area.min_y + row as f32 * cell.y,
+                    cell.x,
+                    cell.y,
+                )
+            })
+            .collect()
+    }
+
+    /// Assigns the viewports to the player cameras and updates their uniform buffers.
+    pub fn prepare(&mut self, queue: &wgpu::Queue, screen: &Screen) {
+        let viewports = self.viewports(screen.viewport(), self.players.len());
+        for (player, viewport) in self.players.iter_mut().zip(viewports) {
+            player.camera.viewport = Some(viewport);
+            player.camera.fit_to_viewport(screen);
+            player.camera_gr.prepare(queue, &player.camera);
+        }
+    }
+
+    /// The viewport and camera bind group of each player, in the order they should be rendered.
+    pub fn views(&self, screen: &Screen) -> SmallVec<[(Rect, &Camera3dGR); 4]> {
+        self.players
+            .iter()
+            .map(|p| (p.camera.viewport_or_screen(screen), &p.camera_gr))
+            .collect()
+    }
+}
+
+impl GpuRecreate for SplitScreen {
+    fn gpu_recreated(&mut self, ctx: &GraphicsContext, event: GpuRecreated) {
+        if !event.device_lost {
+            return;
+        }
+        for player in self.players.iter_mut() {
+            player.camera_gr = Camera3dGR::new(ctx, &player.camera);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::{collections::HashMap, fmt::Debug, hash::Hash, time::Duration};
+
+use crate::utils::{
+    scheduler::{Schedulable, SystemEntry},
+    Scheduler,
+};
+
+/// Callback of a state hook. Gets some context (e.g. the app) and can request a transition to another state.
+pub type StateCallback<S, C> = Box<dyn FnMut(&mut C, &mut NextState<S>)>;
+
+/// A game state machine (e.g. `MainMenu`, `Loading`, `Playing`) with hooks that run when a state is entered, exited
+/// or active. Transitions are queued and applied at the start of the next `update`, never in the middle of a frame.
+pub struct States<S: Copy + Eq + Hash + Debug, C> {
+    current: S,
+    previous: Option<S>,
+    /// false until the `OnEnter` hooks of the initial state ran.
+    entered: bool,
+    next: NextState<S>,
+    just_entered: bool,
+    hooks: HashMap<S, StateHooks<S, C>>,
+}
+
+struct StateHooks<S, C> {
+    on_enter: Scheduler<StateCallback<S, C>>,
+    on_exit: Scheduler<StateCallback<S, C>>,
+    on_update: Scheduler<StateCallback<S, C>>,
+}
+
+impl<S, C> Default for StateHooks<S, C> {
+    fn default() -> Self {
+        Self {
+            on_enter: Scheduler::new(),
+            on_exit: Scheduler::new(),
+            on_update: Scheduler::new(),
+        }
+    }
+}
+
+impl<S, C> Schedulable for StateCallback<S, C> {
+    type Context = C;
+}
+
+/// Requested transition, applied at the start of the next `States::update`.
+#[derive(Debug, Clone, Copy)]
+pub struct NextState<S>(Option<S>);
+
+impl<S> NextState<S> {
+    pub fn set(&mut self, state: S) {
+        self.0 = Some(state);
+    }
+}
+
+impl<S: Copy + Eq + Hash + Debug, C> States<S, C> {
+    pub fn new(initial: S) -> Self {
+        States {
+            current: initial,
+            previous: None,
+            entered: false,
+            next: NextState(None),
+            just_entered: false,
+            hooks: HashMap::new(),
+        }
+    }
+
+    pub fn current(&self) -> S {
+        self.current
+    }
+
+    pub fn previous(&self) -> Option<S> {
+        self.previous
+    }
+
+    /// true in the frame the current state was entered.
+    pub fn just_entered(&self) -> bool {
+        self.just_entered
+    }
+
+    /// Queues a transition. If called multiple times in one frame, the last one wins.
+    pub fn set(&mut self, state: S) {
+        self.next.set(state);
+    }
+
+    /// Errors if `state` already has an `on_enter` hook with this name, the same goes for `on_exit` and `on_update`.
+    pub fn on_enter(
+        &mut self,
+        state: S,
+        name: &'static str,
+        f: impl FnMut(&mut C, &mut NextState<S>) + 'static,
+    ) -> anyhow::Result<&mut SystemEntry<StateCallback<S, C>>> {
+        let hooks = self.hooks.entry(state).or_default();
+        hooks.on_enter.add(name, Box::new(f))
+    }
+
+    pub fn on_exit(
+        &mut self,
+        state: S,
+        name: &'static str,
+        f: impl FnMut(&mut C, &mut NextState<S>) + 'static,
+    ) -> anyhow::Result<&mut SystemEntry<StateCallback<S, C>>> {
+        let hooks = self.hooks.entry(state).or_default();
+        hooks.on_exit.add(name, Box::new(f))
+    }
+
+    /// Runs every frame while `state` is the current state.
+    pub fn on_update(
+        &mut self,
+        state: S,
+        name: &'static str,
+        f: impl FnMut(&mut C, &mut NextState<S>) + 'static,
+    ) -> anyhow::Result<&mut SystemEntry<StateCallback<S, C>>> {
+        let hooks = self.hooks.entry(state).or_default();
+        hooks.on_update.add(name, Box::new(f))
+    }
+
+    /// Applies the queued transition (running `OnExit` of the old and `OnEnter` of the new state)
+    /// and then runs the `OnUpdate` hooks of the current state. Call once per frame.
+    pub fn update(&mut self, ctx: &mut C, delta: Duration) -> anyhow::Result<()> {
+        self.just_entered = false;
+        // at most one state is entered per frame: a transition requested by `OnEnter` hooks waits for the next
+        // update, such that states that keep requesting each other can not hang the frame.
+        if !self.entered {
+            self.entered = true;
+            self.just_entered = true;
+            self.run_hooks(self.current, ctx, delta, |h| &mut h.on_enter)?;
+        } else if let Some(next) = self.next.0.take() {
+            if next != self.current {
+                self.run_hooks(self.current, ctx, delta, |h| &mut h.on_exit)?;
+                self.previous = Some(self.current);
+                self.current = next;
+                self.just_entered = true;
+                self.run_hooks(self.current, ctx, delta, |h| &mut h.on_enter)?;
+            }
+        }
+
+        self.run_hooks(self.current, ctx, delta, |h| &mut h.on_update)
+    }
+
+    fn run_hooks(
+        &mut self,
+        state: S,
+        ctx: &mut C,
+        delta: Duration,
+        schedule: impl Fn(&mut StateHooks<S, C>) -> &mut Scheduler<StateCallback<S, C>>,
+    ) -> anyhow::Result<()> {
+        let Some(hooks) = self.hooks.get_mut(&state) else {
+            return Ok(());
+        };
+        let next = &mut self.next;
+        schedule(hooks).run(ctx, delta, |f, ctx| f(ctx, next))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum Mode {
+        Menu,
+        Loading,
+        Playing,
+    }
+
+    /// States that log their hooks into the context.
+    fn logged_states() -> States<Mode, Vec<String>> {
+        let mut states = States::new(Mode::Menu);
+        for mode in [Mode::Menu, Mode::Loading, Mode::Playing] {
+            states
+                .on_enter(mode, "log", move |log: &mut Vec<String>, _| {
+                    log.push(format!("enter {mode:?}"))
+                })
+                .unwrap();
+            states
+                .on_exit(mode, "log", move |log: &mut Vec<String>, _| {
+                    log.push(format!("exit {mode:?}"))
+                })
+                .unwrap();
+            states
+                .on_update(mode, "log", move |log: &mut Vec<String>, _| {
+                    log.push(format!("update {mode:?}"))
+                })
+                .unwrap();
+        }
+        states
+    }
+
+    #[test]
+    fn exit_runs_before_enter() {
+        let mut states = logged_states();
+        let mut log = vec![];
+        states.update(&mut log, Duration::ZERO).unwrap();
+        assert!(states.just_entered());
+        states.update(&mut log, Duration::ZERO).unwrap();
+        assert!(!states.just_entered());
+        states.set(Mode::Playing);
+        states.update(&mut log, Duration::ZERO).unwrap();
+        assert!(states.just_entered());
+        assert_eq!(states.previous(), Some(Mode::Menu));
+        assert_eq!(
+            log,
+            [
+                "enter Menu",
+                "update Menu",
+                "update Menu",
+                "exit Menu",
+                "enter Playing",
+                "update Playing"
+            ]
+        );
+    }
+
+    #[test]
+    fn same_state_transitions_are_ignored() {
+        let mut states = logged_states();
+        let mut log = vec![];
+        states.update(&mut log, Duration::ZERO).unwrap();
+        log.clear();
+        states.set(Mode::Menu);
+        states.update(&mut log, Duration::ZERO).unwrap();
+        assert!(!states.just_entered());
+        assert_eq!(states.previous(), None);
+        assert_eq!(log, ["update Menu"]);
+    }
+
+    #[test]
+    fn one_transition_per_update() {
+        let mut states = logged_states();
+        states
+            .on_enter(Mode::Loading, "done", |_, next| next.set(Mode::Playing))
+            .unwrap();
+        let mut log = vec![];
+        states.update(&mut log, Duration::ZERO).unwrap();
+        log.clear();
+        states.set(Mode::Loading);
+        states.update(&mut log, Duration::ZERO).unwrap();
+        assert_eq!(states.current(), Mode::Loading);
+        states.update(&mut log, Duration::ZERO).unwrap();
+        assert_eq!(states.current(), Mode::Playing);
+        assert_eq!(
+            log,
+            [
+                "exit Menu",
+                "enter Loading",
+                "update Loading",
+                "exit Loading",
+                "enter Playing",
+                "update Playing"
+            ]
+        );
+    }
+
+    #[test]
+    fn ping_pong_does_not_hang() {
+        let mut states = logged_states();
+        states
+            .on_enter(Mode::Menu, "pong", |_, next| next.set(Mode::Playing))
+            .unwrap();
+        states
+            .on_enter(Mode::Playing, "ping", |_, next| next.set(Mode::Menu))
+            .unwrap();
+        let mut log = vec![];
+        for expected in [Mode::Menu, Mode::Playing, Mode::Menu, Mode::Playing] {
+            states.update(&mut log, Duration::ZERO).unwrap();
+            assert_eq!(states.current(), expected);
+            assert!(states.just_entered());
+        }
+    }
+}
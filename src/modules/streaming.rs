@@ -0,0 +1,469 @@
+//! Streaming of large assets, governed by a VRAM budget.
+//!
+//! Textures are uploaded coarsest mip first and refined by one mip level per frame while they are drawn.
+//! Meshes are split into LODs that are loaded in the background once the camera gets close enough.
+//! When the resident detail exceeds `StreamingSettings::vram_budget`, the detail that was used least recently is
+//! evicted: the finest mip of a texture or a LOD of a mesh that is not needed at the moment.
+
+use std::{mem::size_of, sync::Arc};
+
+use glam::{Vec2, Vec3};
+use image::{imageops::FilterType, RgbaImage};
+use slotmap::{new_key_type, SlotMap};
+use tokio::runtime::Handle;
+
+use crate::{
+    elements::{shapes::MeshData, BindableTexture, Texture},
+    GpuRecreated,
+};
+
+use super::{jobs::JobHandle, GraphicsContext, Jobs};
+
+new_key_type! {
+    pub struct StreamingTextureKey;
+    pub struct StreamingMeshKey;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamingSettings {
+    /// Bytes that streamed textures and meshes may take. The coarsest mip of each texture is always resident.
+    pub vram_budget: u64,
+    /// How many mip levels are uploaded per frame at most, to spread the upload cost.
+    pub uploads_per_frame: usize,
+    /// Detail that was not used for this many frames is not refined anymore.
+    pub keep_refining_frames: u64,
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        StreamingSettings {
+            vram_budget: 512 * 1024 * 1024,
+            uploads_per_frame: 4,
+            keep_refining_frames: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamingStats {
+    pub resident_bytes: u64,
+    pub textures: usize,
+    /// textures that have all of their mips resident.
+    pub textures_at_full_detail: usize,
+    pub resident_mesh_lods: usize,
+    pub loading_mesh_lods: usize,
+}
+
+pub struct Streaming {
+    pub settings: StreamingSettings,
+    jobs: Jobs,
+    textures: SlotMap<StreamingTextureKey, StreamingTexture>,
+    meshes: SlotMap<StreamingMeshKey, StreamingMesh>,
+    frame: u64,
+    /// set when the device was lost, everything is uploaded again from the coarsest mip.
+    reupload: bool,
+}
+
+struct StreamingTexture {
+    /// mip chain on the cpu, index 0 is the full resolution.
+    mips: Vec<RgbaImage>,
+    /// finest mip level that is on the gpu, `mips.len()` if nothing is uploaded yet.
+    resident: usize,
+    gpu: Option<BindableTexture>,
+    last_used: u64,
+}
+
+impl StreamingTexture {
+    fn bytes_from(&self, level: usize) -> u64 {
+        self.mips[level.min(self.mips.len())..]
+            .iter()
+            .map(|m| m.as_raw().len() as u64)
+            .sum()
+    }
+}
+
+/// One level of detail of a streamed mesh.
+#[derive(Clone)]
+pub struct MeshLod {
+    /// used up to this camera distance, the LODs of a mesh are sorted from near (finest) to far.
+    pub max_distance: f32,
+    /// called on a background thread, e.g. to read and parse a file.
+    pub load: Arc<dyn Fn() -> anyhow::Result<MeshData> + Send + Sync>,
+}
+
+struct StreamingMesh {
+    lods: Vec<MeshLod>,
+    states: Vec<LodState>,
+}
+
+enum LodState {
+    Unloaded,
+    Loading(JobHandle<anyhow::Result<MeshData>>),
+    Resident {
+        mesh: MeshData,
+        last_used: u64,
+    },
+    /// logged once, not retried.
+    Failed,
+}
+
+fn mesh_bytes(mesh: &MeshData) -> u64 {
+    (mesh.positions.len() * (2 * size_of::<Vec3>() + size_of::<Vec2>())
+        + mesh.indices.len() * size_of::<u32>()) as u64
+}
+
+impl Streaming {
+    pub fn new(handle: Handle) -> Self {
+        Streaming {
+            settings: StreamingSettings::default(),
+            jobs: Jobs::new(handle),
+            textures: SlotMap::with_key(),
+            meshes: SlotMap::with_key(),
+            frame: 0,
+            reupload: false,
+        }
+    }
+
+    /// The mips are generated on the cpu right away, the upload starts in the next `prepare`.
+    pub fn add_texture(&mut self, image: RgbaImage) -> StreamingTextureKey {
+        let mut mips = vec![image];
+        loop {
+            let last = mips.last().unwrap();
+            if last.width() == 1 && last.height() == 1 {
+                break;
+            }
+            let (width, height) = ((last.width() / 2).max(1), (last.height() / 2).max(1));
+            let mip = image::imageops::resize(last, width, height, FilterType::Triangle);
+            mips.push(mip);
+        }
+        let resident = mips.len();
+        self.textures.insert(StreamingTexture {
+            mips,
+            resident,
+            gpu: None,
+            last_used: self.frame,
+        })
+    }
+
+    pub fn remove_texture(&mut self, key: StreamingTextureKey) {
+        self.textures.remove(key);
+    }
+
+    /// The texture with the detail that is resident right now, None before the first upload.
+    /// Marks the texture as used, such that it is refined and not evicted.
+    pub fn texture(&mut self, key: StreamingTextureKey) -> Option<&BindableTexture> {
+        let texture = self.textures.get_mut(key)?;
+        texture.last_used = self.frame;
+        texture.gpu.as_ref()
+    }
+
+    /// Finest mip level that is resident, 0 is full detail.
+    pub fn texture_detail(&self, key: StreamingTextureKey) -> Option<usize> {
+        self.textures.get(key).map(|t| t.resident)
+    }
+
+    pub fn add_mesh(&mut self, lods: Vec<MeshLod>) -> StreamingMeshKey {
+        let states = lods.iter().map(|_| LodState::Unloaded).collect();
+        self.meshes.insert(StreamingMesh { lods, states })
+    }
+
+    pub fn remove_mesh(&mut self, key: StreamingMeshKey) {
+        self.meshes.remove(key);
+    }
+
+    /// The LOD for the camera `distance` if it is resident, otherwise starts loading it and returns the closest
+    /// resident one, preferring coarser LODs. None if no LOD is loaded yet.
+    pub fn mesh(&mut self, key: StreamingMeshKey, distance: f32) -> Option<&MeshData> {
+        let mesh = self.meshes.get_mut(key)?;
+        let wanted = mesh
+            .lods
+            .iter()
+            .position(|l| distance <= l.max_distance)
+            .unwrap_or(mesh.lods.len().checked_sub(1)?);
+        if let LodState::Unloaded = mesh.states[wanted] {
+            let load = mesh.lods[wanted].load.clone();
+            mesh.states[wanted] = LodState::Loading(self.jobs.spawn(move || load()));
+        }
+        let n = mesh.lods.len();
+        let fallback = (wanted..n)
+            .chain((0..wanted).rev())
+            .find(|i| matches!(mesh.states[*i], LodState::Resident { .. }))?;
+        match &mut mesh.states[fallback] {
+            LodState::Resident { mesh, last_used } => {
+                *last_used = self.frame;
+                Some(mesh)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn stats(&self) -> StreamingStats {
+        let mut stats = StreamingStats {
+            resident_bytes: self.resident_bytes(),
+            textures: self.textures.len(),
+            ..Default::default()
+        };
+        for texture in self.textures.values() {
+            if texture.resident == 0 {
+                stats.textures_at_full_detail += 1;
+            }
+        }
+        for state in self.meshes.values().flat_map(|m| m.states.iter()) {
+            match state {
+                LodState::Resident { .. } => stats.resident_mesh_lods += 1,
+                LodState::Loading(_) => stats.loading_mesh_lods += 1,
+                _ => {}
+            }
+        }
+        stats
+    }
+
+    fn resident_bytes(&self) -> u64 {
+        let textures: u64 = self
+            .textures
+            .values()
+            .map(|t| t.bytes_from(t.resident))
+            .sum();
+        let meshes: u64 = self
+            .meshes
+            .values()
+            .flat_map(|m| m.states.iter())
+            .map(|s| match s {
+                LodState::Resident { mesh, .. } => mesh_bytes(mesh),
+                _ => 0,
+            })
+            .sum();
+        textures + meshes
+    }
+
+    pub fn gpu_recreated(&mut self, _ctx: &GraphicsContext, event: GpuRecreated) {
+        if event.device_lost {
+            self.reupload = true;
+        }
+    }
+
+    /// Finishes mesh loads, evicts detail if over budget and uploads the next mips of recently used textures.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.frame += 1;
+        if std::mem::take(&mut self.reupload) {
+            for texture in self.textures.values_mut() {
+                texture.gpu = None;
+                texture.resident = texture.mips.len();
+            }
+        }
+        self.finish_mesh_loads();
+
+        // every texture needs at least its coarsest mip.
+        for texture in self.textures.values_mut() {
+            if texture.gpu.is_none() {
+                let level = texture.mips.len() - 1;
+                texture.gpu = Some(upload_level(device, queue, texture, level));
+                texture.resident = level;
+            }
+        }
+
+        let mut resident_bytes = self.resident_bytes();
+        for key in self.evict(&mut resident_bytes) {
+            let texture = &mut self.textures[key];
+            texture.gpu = Some(upload_level(device, queue, texture, texture.resident));
+        }
+
+        // refine the most recently used textures first.
+        let mut candidates: Vec<(u64, StreamingTextureKey)> = self
+            .textures
+            .iter()
+            .filter(|(_, t)| {
+                t.resident > 0 && self.frame - t.last_used <= self.settings.keep_refining_frames
+            })
+            .map(|(k, t)| (t.last_used, k))
+            .collect();
+        candidates.sort_by_key(|(last_used, _)| std::cmp::Reverse(*last_used));
+        for (_, key) in candidates.into_iter().take(self.settings.uploads_per_frame) {
+            let texture = &mut self.textures[key];
+            let level = texture.resident - 1;
+            let extra = texture.mips[level].as_raw().len() as u64;
+            if resident_bytes + extra > self.settings.vram_budget {
+                continue;
+            }
+            texture.gpu = Some(upload_level(device, queue, texture, level));
+            texture.resident = level;
+            resident_bytes += extra;
+        }
+    }
+
+    fn finish_mesh_loads(&mut self) {
+        for mesh in self.meshes.values_mut() {
+            for state in mesh.states.iter_mut() {
+                if !matches!(state, LodState::Loading(job) if job.is_finished()) {
+                    continue;
+                }
+                let LodState::Loading(job) = std::mem::replace(state, LodState::Failed) else {
+                    unreachable!()
+                };
+                *state = match job.join() {
+                    Ok(mesh) => LodState::Resident {
+                        mesh,
+                        last_used: self.frame,
+                    },
+                    Err(err) => {
+                        log::error!("could not load mesh lod: {err}");
+                        LodState::Failed
+                    }
+                };
+            }
+        }
+    }
+
+    /// Drops the least recently used detail until the resident bytes fit into the budget. Mesh LODs and texture
+    /// mips used in this frame are kept. Returns the textures that lost mips.
+    fn evict(&mut self, resident_bytes: &mut u64) -> Vec<StreamingTextureKey> {
+        let mut shrunk: Vec<StreamingTextureKey> = vec![];
+        if *resident_bytes <= self.settings.vram_budget {
+            return shrunk;
+        }
+        enum Detail {
+            Mip(StreamingTextureKey),
+            Lod(StreamingMeshKey, usize),
+        }
+        let mut details: Vec<(u64, Detail)> = vec![];
+        for (key, texture) in self.textures.iter() {
+            // the coarsest mip is never evicted.
+            let evictable = (texture.mips.len() - 1).saturating_sub(texture.resident);
+            details.extend((0..evictable).map(|_| (texture.last_used, Detail::Mip(key))));
+        }
+        for (key, mesh) in self.meshes.iter() {
+            for (i, state) in mesh.states.iter().enumerate() {
+                if let LodState::Resident { last_used, .. } = state {
+                    details.push((*last_used, Detail::Lod(key, i)));
+                }
+            }
+        }
+        details.sort_by_key(|(last_used, _)| *last_used);
+
+        let frame = self.frame;
+        for (last_used, detail) in details {
+            if *resident_bytes <= self.settings.vram_budget || last_used + 1 >= frame {
+                break;
+            }
+            match detail {
+                Detail::Mip(key) => {
+                    let texture = &mut self.textures[key];
+                    *resident_bytes -= texture.mips[texture.resident].as_raw().len() as u64;
+                    texture.resident += 1;
+                    if !shrunk.contains(&key) {
+                        shrunk.push(key);
+                    }
+                }
+                Detail::Lod(key, i) => {
+                    let state = &mut self.meshes[key].states[i];
+                    if let LodState::Resident { mesh, .. } = state {
+                        *resident_bytes -= mesh_bytes(mesh);
+                    }
+                    *state = LodState::Unloaded;
+                }
+            }
+        }
+        shrunk
+    }
+}
+
+/// Creates a texture with the mips from `level` to the coarsest one and uploads all of them. Uploading the coarser
+/// mips again costs a third of the new mip, but the textures never need to be copied on the gpu.
+fn upload_level(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &StreamingTexture,
+    level: usize,
+) -> BindableTexture {
+    let mips = &texture.mips[level..];
+    let size = wgpu::Extent3d {
+        width: mips[0].width(),
+        height: mips[0].height(),
+        depth_or_array_layers: 1,
+    };
+    let gpu = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Streaming Texture"),
+        size,
+        mip_level_count: mips.len() as u32,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    for (i, mip) in mips.iter().enumerate() {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &gpu,
+                mip_level: i as u32,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            mip.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * mip.width()),
+                rows_per_image: Some(mip.height()),
+            },
+            wgpu::Extent3d {
+                width: mip.width(),
+                height: mip.height(),
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+    let view = gpu.create_view(&Default::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        address_mode_w: wgpu::AddressMode::Repeat,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    let texture = Texture {
+        label: Some("Streaming Texture".into()),
+        id: rand::random(),
+        texture: gpu,
+        view,
+        sampler,
+        size,
+    };
+    BindableTexture::new(device, texture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elements::shapes;
+
+    #[test]
+    fn mesh_lods_load_on_demand() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut streaming = Streaming::new(rt.handle().clone());
+        let lod = |max_distance: f32, subdivisions: u32| MeshLod {
+            max_distance,
+            load: Arc::new(move || Ok(shapes::icosphere(1.0, subdivisions))),
+        };
+        let key = streaming.add_mesh(vec![lod(10.0, 3), lod(f32::MAX, 0)]);
+        let mut load = |streaming: &mut Streaming, distance: f32| {
+            streaming.mesh(key, distance);
+            while streaming.stats().loading_mesh_lods > 0 {
+                std::thread::yield_now();
+                streaming.finish_mesh_loads();
+            }
+            streaming.mesh(key, distance).map(|m| m.vertex_count())
+        };
+
+        let far = load(&mut streaming, 100.0).unwrap();
+        // the near lod is loading, the far one is drawn until then.
+        assert_eq!(
+            streaming.mesh(key, 1.0).map(|m| m.vertex_count()),
+            Some(far)
+        );
+        let near = load(&mut streaming, 1.0).unwrap();
+        assert!(near > far);
+        assert_eq!(streaming.stats().resident_mesh_lods, 2);
+    }
+}
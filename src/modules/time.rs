@@ -6,16 +6,30 @@ use std::{
 use smallvec::{smallvec, SmallVec};
 
 const CACHED_DELTA_TIMES_COUNT: usize = 20;
+/// more fixed steps than this in one frame are dropped, to not spiral into ever longer frames.
+const MAX_FIXED_STEPS_PER_FRAME: u32 = 8;
 
 #[derive(Debug)]
 pub struct Time {
     frame_count: usize,
     last_frame: Instant,
+    /// real delta time, not affected by pause or time scale.
     delta_time: Duration,
     total_time: Duration,
     start_time: Instant,
     delta_times: VecDeque<Duration>,
     stats: TimeStats,
+    /// delta time after applying pause and time scale.
+    scaled_delta_time: Duration,
+    scaled_total_time: Duration,
+    time_scale: f64,
+    paused: bool,
+    step_requested: bool,
+    fixed_timestep: Duration,
+    fixed_accumulator: Duration,
+    fixed_steps: u32,
+    /// every frame advances the scaled time by exactly one fixed timestep, see `set_deterministic`.
+    deterministic: bool,
 }
 
 #[derive(Debug, Default)]
@@ -50,6 +64,15 @@ impl Time {
             delta_time: Duration::from_millis(10),
             delta_times,
             stats: TimeStats::default(),
+            scaled_delta_time: Duration::from_millis(10),
+            scaled_total_time: Duration::ZERO,
+            time_scale: 1.0,
+            paused: false,
+            step_requested: false,
+            fixed_timestep: Duration::from_secs_f64(1.0 / 60.0),
+            fixed_accumulator: Duration::ZERO,
+            fixed_steps: 0,
+            deterministic: false,
         }
     }
 
@@ -64,22 +87,132 @@ impl Time {
         self.last_frame = this_frame;
         self.frame_count += 1;
         self.stats.recalculate(&self.delta_times);
+
+        // scaled time:
+        self.scaled_delta_time = if self.step_requested {
+            // stepping while paused advances exactly one fixed step.
+            self.step_requested = false;
+            self.fixed_timestep
+        } else if self.paused {
+            Duration::ZERO
+        } else if self.deterministic {
+            self.fixed_timestep
+        } else {
+            self.delta_time.mul_f64(self.time_scale)
+        };
+        self.scaled_total_time += self.scaled_delta_time;
+
+        // fixed steps:
+        self.fixed_accumulator += self.scaled_delta_time;
+        self.fixed_steps = 0;
+        while self.fixed_accumulator >= self.fixed_timestep {
+            self.fixed_accumulator -= self.fixed_timestep;
+            if self.fixed_steps < MAX_FIXED_STEPS_PER_FRAME {
+                self.fixed_steps += 1;
+            }
+        }
     }
 }
 
 impl Time {
-    pub fn fps(&self) -> f64 {
-        self.stats.fps.avg
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Advances the scaled time by exactly one fixed timestep in the next frame, even if paused. For debugging.
+    pub fn step_one_frame(&mut self) {
+        self.step_requested = true;
+    }
+
+    /// In deterministic mode, every frame (that is not paused) is exactly one fixed step: `delta` is the fixed timestep,
+    /// `fixed_steps` is 1 and `fixed_alpha` is 0, no matter how long the frame really took. The simulation then only
+    /// depends on the inputs per frame, e.g. for lockstep networking and replays. The time scale is ignored.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+        self.fixed_accumulator = Duration::ZERO;
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// 1.0 is normal speed, 0.5 is half speed (slow motion), 0.0 freezes time (e.g. for hit-stop).
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        self.time_scale = time_scale.max(0.0);
     }
 
+    /// Scaled delta time of this frame, zero if paused. Use `real_delta` for things that should ignore pause, like debug cameras.
     pub fn delta(&self) -> &Duration {
+        &self.scaled_delta_time
+    }
+
+    /// Real delta time of this frame, not affected by pause or time scale.
+    pub fn real_delta(&self) -> &Duration {
         &self.delta_time
     }
 
+    /// Scaled time since the start.
     pub fn total(&self) -> &Duration {
+        &self.scaled_total_time
+    }
+
+    /// Real time since the start.
+    pub fn real_total(&self) -> &Duration {
         &self.total_time
     }
 
+    pub fn fixed_timestep(&self) -> Duration {
+        self.fixed_timestep
+    }
+
+    pub fn set_fixed_timestep(&mut self, fixed_timestep: Duration) {
+        assert!(!fixed_timestep.is_zero());
+        self.fixed_timestep = fixed_timestep;
+    }
+
+    /// How many fixed updates should run this frame. Zero while paused. `DefaultModules::fixed_schedule` runs this
+    /// often, apps without it can loop over their own fixed update.
+    pub fn fixed_steps(&self) -> u32 {
+        self.fixed_steps
+    }
+
+    /// How far (0.0 to 1.0) the scaled time is between the last and the next fixed step.
+    pub fn fixed_alpha(&self) -> f32 {
+        (self.fixed_accumulator.as_secs_f64() / self.fixed_timestep.as_secs_f64()) as f32
+    }
+}
+
+impl Time {
+    /// True in the frames in which the scaled time passes a multiple of `interval`, e.g. `time.every(0.5)` to spawn
+    /// an enemy twice per second. Frames longer than `interval` still return true only once.
+    pub fn every(&self, interval: f64) -> bool {
+        let now = self.scaled_total_time.as_secs_f64();
+        let before = now - self.scaled_delta_time.as_secs_f64();
+        interval > 0.0 && (now / interval).floor() > (before / interval).floor()
+    }
+}
+
+impl Time {
+    pub fn fps(&self) -> f64 {
+        self.stats.fps.avg
+    }
+
     pub fn frame_count(&self) -> usize {
         self.frame_count
     }
@@ -93,6 +226,16 @@ impl Time {
             if ui.button("Log Time Stats").clicked() {
                 dbg!(&self);
             }
+            ui.horizontal(|ui| {
+                let pause_label = if self.paused { "Resume" } else { "Pause" };
+                if ui.button(pause_label).clicked() {
+                    self.toggle_pause();
+                }
+                if ui.button("Step").clicked() {
+                    self.step_one_frame();
+                }
+            });
+            ui.add(egui::Slider::new(&mut self.time_scale, 0.0..=2.0).text("Time Scale"));
         });
     }
 }
@@ -139,3 +282,266 @@ impl Stats {
         Stats { max, min, avg, std }
     }
 }
+
+// /////////////////////////////////////////////////////////////////////////////
+// Timer and Stopwatch
+// /////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMode {
+    /// finishes once and stays finished until `reset`.
+    Once,
+    /// starts over when finished, e.g. for cooldowns and spawners.
+    Repeating,
+}
+
+/// Counts down a duration of scaled time. Tick it once per frame:
+///
+/// ```rust,ignore
+/// if self.shoot_cooldown.tick(&modules.time).finished() && input.keys().is_pressed(KeyCode::Space) {
+///     shoot();
+///     self.shoot_cooldown.reset();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Timer {
+    duration: Duration,
+    elapsed: Duration,
+    mode: TimerMode,
+    paused: bool,
+    finished: bool,
+    /// how often a repeating timer finished in the last tick, more than once if the tick was longer than `duration`.
+    times_finished_this_tick: u32,
+}
+
+impl Timer {
+    pub fn new(duration: Duration, mode: TimerMode) -> Self {
+        Timer {
+            duration,
+            elapsed: Duration::ZERO,
+            mode,
+            paused: false,
+            finished: false,
+            times_finished_this_tick: 0,
+        }
+    }
+
+    pub fn once(secs: f64) -> Self {
+        Timer::new(Duration::from_secs_f64(secs), TimerMode::Once)
+    }
+
+    pub fn repeating(secs: f64) -> Self {
+        Timer::new(Duration::from_secs_f64(secs), TimerMode::Repeating)
+    }
+
+    /// Advances by the scaled delta time of this frame, so the timer stops while the game is paused.
+    pub fn tick(&mut self, time: &Time) -> &Self {
+        self.tick_by(*time.delta())
+    }
+
+    pub fn tick_by(&mut self, delta: Duration) -> &Self {
+        self.times_finished_this_tick = 0;
+        if self.paused {
+            return self;
+        }
+        if self.mode == TimerMode::Once && self.finished {
+            // stays finished, but does not fire again.
+            return self;
+        }
+        self.elapsed += delta;
+        if self.elapsed < self.duration {
+            self.finished = false;
+            return self;
+        }
+        self.finished = true;
+        match self.mode {
+            TimerMode::Once => {
+                self.elapsed = self.duration;
+                self.times_finished_this_tick = 1;
+            }
+            TimerMode::Repeating if self.duration.is_zero() => {
+                self.elapsed = Duration::ZERO;
+                self.times_finished_this_tick = 1;
+            }
+            TimerMode::Repeating => {
+                let times = self.elapsed.as_nanos() / self.duration.as_nanos();
+                self.times_finished_this_tick = times as u32;
+                self.elapsed -= self.duration * self.times_finished_this_tick;
+            }
+        }
+        self
+    }
+
+    /// A `Once` timer stays finished, a repeating timer is only finished in the tick in which it wrapped around.
+    pub fn finished(&self) -> bool {
+        match self.mode {
+            TimerMode::Once => self.finished,
+            TimerMode::Repeating => self.times_finished_this_tick > 0,
+        }
+    }
+
+    /// Only true in the tick in which the timer finished.
+    pub fn just_finished(&self) -> bool {
+        self.times_finished_this_tick > 0
+    }
+
+    pub fn times_finished_this_tick(&self) -> u32 {
+        self.times_finished_this_tick
+    }
+
+    /// 0.0 when started, 1.0 when finished.
+    pub fn percent(&self) -> f32 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        (self.elapsed.as_secs_f64() / self.duration.as_secs_f64()).min(1.0) as f32
+    }
+
+    pub fn percent_left(&self) -> f32 {
+        1.0 - self.percent()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.duration.saturating_sub(self.elapsed)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Keeps the elapsed time, e.g. to shorten a running cooldown.
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+
+    pub fn mode(&self) -> TimerMode {
+        self.mode
+    }
+
+    /// Starts over from zero, not paused.
+    pub fn reset(&mut self) {
+        self.elapsed = Duration::ZERO;
+        self.finished = false;
+        self.times_finished_this_tick = 0;
+        self.paused = false;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+}
+
+/// Counts up scaled time, e.g. for the time of a speedrun or how long a button was charged.
+#[derive(Debug, Clone, Default)]
+pub struct Stopwatch {
+    elapsed: Duration,
+    paused: bool,
+}
+
+impl Stopwatch {
+    pub fn new() -> Self {
+        Stopwatch::default()
+    }
+
+    pub fn tick(&mut self, time: &Time) -> &Self {
+        self.tick_by(*time.delta())
+    }
+
+    pub fn tick_by(&mut self, delta: Duration) -> &Self {
+        if !self.paused {
+            self.elapsed += delta;
+        }
+        self
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn elapsed_secs(&self) -> f32 {
+        self.elapsed.as_secs_f32()
+    }
+
+    pub fn reset(&mut self) {
+        self.elapsed = Duration::ZERO;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timers() {
+        let ms = Duration::from_millis;
+
+        let mut once = Timer::once(1.0);
+        assert!(!once.tick_by(ms(600)).finished());
+        assert!((once.percent() - 0.6).abs() < 1e-6);
+        assert!(once.tick_by(ms(600)).just_finished());
+        assert!(once.tick_by(ms(600)).finished());
+        assert!(!once.just_finished());
+        assert_eq!(once.remaining(), Duration::ZERO);
+
+        let mut repeating = Timer::repeating(0.25);
+        assert_eq!(repeating.tick_by(ms(600)).times_finished_this_tick(), 2);
+        assert_eq!(repeating.elapsed(), ms(100));
+        assert!(!repeating.tick_by(ms(100)).finished());
+        repeating.pause();
+        assert!(!repeating.tick_by(ms(100)).finished());
+        assert_eq!(repeating.elapsed(), ms(200));
+
+        let mut stopwatch = Stopwatch::new();
+        stopwatch.tick_by(ms(300));
+        stopwatch.pause();
+        stopwatch.tick_by(ms(300));
+        assert_eq!(stopwatch.elapsed(), ms(300));
+
+        let mut time = Time::new();
+        time.scaled_total_time = ms(1050);
+        time.scaled_delta_time = ms(100);
+        assert!(time.every(0.5));
+        assert!(!time.every(0.3));
+    }
+
+    #[test]
+    fn fixed_steps_respect_pause() {
+        let mut time = Time::new();
+        time.last_frame = Instant::now() - Duration::from_millis(100);
+        time.update();
+        assert_eq!(time.fixed_steps(), 6);
+
+        time.pause();
+        time.last_frame = Instant::now() - Duration::from_millis(100);
+        time.update();
+        assert_eq!(time.fixed_steps(), 0);
+
+        time.step_one_frame();
+        time.update();
+        assert_eq!(time.fixed_steps(), 1);
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::{Duration, Instant};
+
+use glam::{vec2, Vec2};
+use smallvec::SmallVec;
+use winit::event::{Touch, TouchPhase};
+
+/// The fingers currently on the screen and the gestures recognized from them. Owned by the `Input`, see `Input::touches`.
+///
+/// Gestures:
+/// - `Tap`: a single finger is lifted quickly without moving much.
+/// - `LongPress`: a single finger rests on the screen for `long_press_duration`. Fired once, it is not a `Tap` anymore.
+/// - pinch and pan: two fingers moving, see `pinch_scale` and `pan_delta`.
+#[derive(Debug)]
+pub struct Touches {
+    pub settings: GestureSettings,
+    points: Vec<TouchPoint>,
+    gestures: SmallVec<[Gesture; 2]>,
+    /// product of the pinch scales of all two finger moves this frame.
+    pinch_scale: f32,
+    pan_delta: Vec2,
+    /// false as soon as a second finger touches the screen, until all fingers are lifted.
+    single_finger: bool,
+    long_press_fired: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GestureSettings {
+    /// a touch that is held longer than this is not a tap.
+    pub tap_max_duration: Duration,
+    /// in physical pixels. A touch that moves further than this is neither a tap nor a long press.
+    pub tap_max_distance: f32,
+    pub long_press_duration: Duration,
+}
+
+impl Default for GestureSettings {
+    fn default() -> Self {
+        Self {
+            tap_max_duration: Duration::from_millis(300),
+            tap_max_distance: 12.0,
+            long_press_duration: Duration::from_millis(500),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TouchPoint {
+    pub id: u64,
+    /// physical pixels, relative to the window.
+    pub pos: Vec2,
+    pub start_pos: Vec2,
+    pub start_time: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    Tap { pos: Vec2 },
+    LongPress { pos: Vec2 },
+}
+
+impl Default for Touches {
+    fn default() -> Self {
+        Touches {
+            settings: GestureSettings::default(),
+            points: vec![],
+            gestures: SmallVec::new(),
+            pinch_scale: 1.0,
+            pan_delta: Vec2::ZERO,
+            single_finger: true,
+            long_press_fired: false,
+        }
+    }
+}
+
+impl Touches {
+    pub fn points(&self) -> &[TouchPoint] {
+        &self.points
+    }
+
+    /// The first finger that touched the screen, if it is the only one. Emulates the mouse cursor, see `Input`.
+    pub fn primary(&self) -> Option<&TouchPoint> {
+        match self.single_finger {
+            true => self.points.first(),
+            false => None,
+        }
+    }
+
+    /// Taps and long presses recognized this frame.
+    pub fn gestures(&self) -> &[Gesture] {
+        &self.gestures
+    }
+
+    /// Ratio of the distance between two fingers now and at the start of the frame, if two fingers moved this frame.
+    /// Greater than 1.0 means the fingers moved apart (zoom in).
+    pub fn pinch_scale(&self) -> Option<f32> {
+        (self.pinch_scale != 1.0).then_some(self.pinch_scale)
+    }
+
+    /// Movement of the center between two fingers this frame, in physical pixels.
+    pub fn pan_delta(&self) -> Option<Vec2> {
+        (self.pan_delta != Vec2::ZERO).then_some(self.pan_delta)
+    }
+
+    pub fn receive_touch(&mut self, touch: &Touch) {
+        let pos = vec2(touch.location.x as f32, touch.location.y as f32);
+        match touch.phase {
+            TouchPhase::Started => {
+                if self.points.is_empty() {
+                    self.single_finger = true;
+                    self.long_press_fired = false;
+                } else {
+                    self.single_finger = false;
+                }
+                self.points.push(TouchPoint {
+                    id: touch.id,
+                    pos,
+                    start_pos: pos,
+                    start_time: Instant::now(),
+                });
+            }
+            TouchPhase::Moved => {
+                let two_finger = self.two_finger_center_and_distance();
+                let Some(point) = self.points.iter_mut().find(|p| p.id == touch.id) else {
+                    return;
+                };
+                point.pos = pos;
+                if let (Some((old_center, old_dist)), Some((center, dist))) =
+                    (two_finger, self.two_finger_center_and_distance())
+                {
+                    if old_dist > 0.0 {
+                        self.pinch_scale *= dist / old_dist;
+                    }
+                    self.pan_delta += center - old_center;
+                }
+            }
+            TouchPhase::Ended => {
+                let Some(index) = self.points.iter().position(|p| p.id == touch.id) else {
+                    return;
+                };
+                let point = self.points.remove(index);
+                if self.single_finger
+                    && !self.long_press_fired
+                    && point.start_time.elapsed() <= self.settings.tap_max_duration
+                    && point.start_pos.distance(pos) <= self.settings.tap_max_distance
+                {
+                    self.gestures.push(Gesture::Tap { pos });
+                }
+            }
+            TouchPhase::Cancelled => {
+                self.points.retain(|p| p.id != touch.id);
+            }
+        }
+    }
+
+    /// Clears the gestures of this frame and recognizes long presses. Called in `Input::end_frame`,
+    /// so long presses show up at the start of the next frame.
+    pub fn end_frame(&mut self) {
+        self.gestures.clear();
+        self.pinch_scale = 1.0;
+        self.pan_delta = Vec2::ZERO;
+
+        if !self.single_finger || self.long_press_fired {
+            return;
+        }
+        let Some(point) = self.points.first() else {
+            return;
+        };
+        if point.start_time.elapsed() >= self.settings.long_press_duration
+            && point.start_pos.distance(point.pos) <= self.settings.tap_max_distance
+        {
+            self.long_press_fired = true;
+            self.gestures.push(Gesture::LongPress { pos: point.pos });
+        }
+    }
+
+    fn two_finger_center_and_distance(&self) -> Option<(Vec2, f32)> {
+        let [a, b] = self.points.as_slice() else {
+            return None;
+        };
+        Some(((a.pos + b.pos) * 0.5, a.pos.distance(b.pos)))
+    }
+}
@@ -10,11 +10,29 @@ use crate::{
     Ptr,
 };
 
-use super::board::{Board, BorderRadius, Div, DivContent, DivTexture, TextEntry};
+use super::board::{Axis, Board, BorderRadius, Div, DivContent, DivTexture, Gradient, TextEntry};
+use super::navigation::FocusRing;
+
+/// How many batches back a primitive may be moved to join a batch with the same texture.
+const MAX_BATCH_LOOKBACK: usize = 16;
 
 /// Warning: call only after layout has been performed on the billboard (for all rects and the text in them)
-/// Todo! introduce aabb overlap violation based batching, for even less batches, like Nical wrote about in his Web-Render Blogpost.
+///
+/// Primitives are sorted by z index and then grouped by texture (the atlas page for text): a primitive joins an
+/// earlier batch with the same texture, if it does not overlap any batch that is drawn in between. This is the aabb
+/// overlap based batching Nical wrote about in his Web-Render Blogpost, it gives the same image with less draw calls.
+///
+/// The result is cached on the board and reused while nothing that is drawn changes, e.g. for static menus.
 pub fn get_batches(board: &Board) -> BatchingResult {
+    if let Some(batches) = board.cached_batches() {
+        return batches.clone();
+    }
+    let batches = compute_batches(board);
+    board.set_cached_batches(batches.clone());
+    batches
+}
+
+fn compute_batches(board: &Board) -> BatchingResult {
     // fill a vec of sort primitives
     // todo! reuse allocated vec next frame!
     let mut sort_primitives: Vec<SortPrimitive> = vec![];
@@ -38,100 +56,200 @@ pub fn get_batches(board: &Board) -> BatchingResult {
             // add the text glyphs as primitives.
         }
     }
-
-    // sort them and then do batching accordingly: each sequence of Rects is a Rect Batch, each sequence of texts of the same font is a text batch.
-    sort_primitives.sort_by_key(|a| a.z_index());
-
-    // create continous batches that refer to either a bunch of rect or glyph instances.
-    let mut rects: Vec<RectRaw> = vec![];
-    let mut textured_rects: Vec<RectRawTextured> = vec![];
-    let mut glyphs: Vec<GlyphRaw> = vec![];
-    let mut batches: Vec<BatchRegion> = vec![];
-
-    if let Some(first) = sort_primitives.first() {
-        let batch = match first {
-            SortPrimitive::Rect { .. } => BatchRegion::Rect(0..0),
-            SortPrimitive::Text { text, .. } => BatchRegion::Text(0..0, text.text.font),
-            SortPrimitive::TexturedRect {
-                div: _,
-                div_texture,
-            } => BatchRegion::TexturedRect(0..0, div_texture.texture),
-        };
-        batches.push(batch);
+    if let Some((div, ring)) = board.nav_focus_ring() {
+        sort_primitives.push(SortPrimitive::FocusRing { div, ring });
     }
+    let primitive_count = sort_primitives.len();
+    sort_primitives.sort_by_key(|a| a.z_index());
 
+    // assign the primitives to batches. Text is split into runs of glyphs on the same atlas page.
+    let mut pending: Vec<PendingBatch> = vec![];
     for prim in sort_primitives {
-        // if the last batch is incompatible, set its end index correctly and start a new batch.
-        let batch = batches.last_mut().unwrap();
-        let incompatible = batch.batch_key() != prim.batch_key();
-        if incompatible {
-            // end the current batch:
-            match batch {
-                BatchRegion::Rect(r) => r.end = rects.len(),
-                BatchRegion::Text(r, _) => r.end = glyphs.len(),
-                BatchRegion::TexturedRect(r, _) => r.end = textured_rects.len(),
-            }
-            // create a new batch:
-            let new_batch = match prim {
-                SortPrimitive::Rect { .. } => BatchRegion::Rect(rects.len()..0),
-                SortPrimitive::Text { text, .. } => {
-                    BatchRegion::Text(glyphs.len()..0, text.text.font)
-                }
-                SortPrimitive::TexturedRect {
-                    div: _,
-                    div_texture,
-                } => BatchRegion::TexturedRect(textured_rects.len()..0, div_texture.texture),
-            };
-            batches.push(new_batch);
-        }
-
-        // add the rect / the glyphs to the buffers.
         match prim {
             SortPrimitive::Rect { div } => {
-                let rect_raw = RectRaw::from_div(div);
-                rects.push(rect_raw);
+                push_item(&mut pending, BatchKey::Rect, div.computed_aabb(), prim)
             }
-            SortPrimitive::TexturedRect { div, div_texture } => {
-                let rect_raw_textured = RectRawTextured {
-                    rect: RectRaw::from_div(div),
-                    uv: div_texture.uv,
-                };
-                textured_rects.push(rect_raw_textured);
+            SortPrimitive::FocusRing { div, ring } => {
+                let aabb = focus_ring_rect(div, ring).pos;
+                push_item(&mut pending, BatchKey::Rect, aabb, prim)
             }
+            SortPrimitive::TexturedRect { div, div_texture } => push_item(
+                &mut pending,
+                BatchKey::TexturedRect(div_texture.texture.as_u64_hash()),
+                div.computed_aabb(),
+                prim,
+            ),
             SortPrimitive::Text { div: _, text } => {
-                // todo! add text pos to glyphs
-                let text_pos = text.c_pos.get();
+                let text_pos = text.c_pos.get().as_vec2();
                 let layouted_glyphs = &text.c_text_layout.get().result.layouted_glyphs;
-                for glyph in layouted_glyphs.iter() {
-                    glyphs.push(GlyphRaw {
-                        pos: glyph.bounds + text_pos.as_vec2(),
-                        color: glyph.color,
-                        uv: glyph.uv,
-                    });
+                let font = text.text.font.map(|e| e.as_u64_hash()).unwrap_or(0);
+                let mut start = 0;
+                while start < layouted_glyphs.len() {
+                    let page = layouted_glyphs[start].page;
+                    let mut end = start;
+                    let mut bounds = layouted_glyphs[start].bounds;
+                    while end < layouted_glyphs.len() && layouted_glyphs[end].page == page {
+                        bounds = bounds.union(&layouted_glyphs[end].bounds);
+                        end += 1;
+                    }
+                    push_item(
+                        &mut pending,
+                        BatchKey::Text { font, page },
+                        bounds + text_pos,
+                        SortPrimitive::Glyphs {
+                            text,
+                            glyphs: start..end,
+                        },
+                    );
+                    start = end;
                 }
             }
+            SortPrimitive::Glyphs { .. } => unreachable!("only created above"),
         }
     }
 
-    // end the last batch:
-    if !batches.is_empty() {
-        let region = batches.last_mut().unwrap();
-        match region {
-            BatchRegion::Rect(r) => r.end = rects.len(),
-            BatchRegion::Text(r, _) => r.end = glyphs.len(),
-            BatchRegion::TexturedRect(r, _) => r.end = textured_rects.len(),
-        }
+    // write the instances of each batch into one continuous range.
+    let mut rects: Vec<RectRaw> = vec![];
+    let mut textured_rects: Vec<RectRawTextured> = vec![];
+    let mut glyphs: Vec<GlyphRaw> = vec![];
+    let mut batches: Vec<BatchRegion> = vec![];
+    for batch in pending {
+        let region = match &batch.items[0] {
+            SortPrimitive::Rect { .. } | SortPrimitive::FocusRing { .. } => {
+                let start = rects.len();
+                for item in batch.items.iter() {
+                    match item {
+                        SortPrimitive::Rect { div } => rects.push(RectRaw::from_div(div)),
+                        SortPrimitive::FocusRing { div, ring } => {
+                            rects.push(focus_ring_rect(div, ring))
+                        }
+                        _ => {}
+                    }
+                }
+                BatchRegion::Rect(start..rects.len())
+            }
+            SortPrimitive::TexturedRect { div_texture, .. } => {
+                let start = textured_rects.len();
+                for item in batch.items.iter() {
+                    if let SortPrimitive::TexturedRect { div, div_texture } = item {
+                        textured_rects.push(RectRawTextured {
+                            rect: RectRaw::from_div(div),
+                            uv: div_texture.uv,
+                        });
+                    }
+                }
+                BatchRegion::TexturedRect(start..textured_rects.len(), div_texture.texture)
+            }
+            SortPrimitive::Glyphs {
+                text,
+                glyphs: first_glyphs,
+            } => {
+                let page = text.c_text_layout.get().result.layouted_glyphs[first_glyphs.start].page;
+                let start = glyphs.len();
+                for item in batch.items.iter() {
+                    if let SortPrimitive::Glyphs {
+                        text,
+                        glyphs: range,
+                    } = item
+                    {
+                        let text_pos = text.c_pos.get().as_vec2();
+                        let layouted_glyphs = &text.c_text_layout.get().result.layouted_glyphs;
+                        for glyph in layouted_glyphs[range.clone()].iter() {
+                            glyphs.push(GlyphRaw {
+                                pos: glyph.bounds + text_pos,
+                                color: glyph.color,
+                                uv: glyph.uv,
+                            });
+                        }
+                    }
+                }
+                BatchRegion::Text(start..glyphs.len(), text.text.font, page)
+            }
+            SortPrimitive::Text { .. } => unreachable!("split into glyphs above"),
+        };
+        batches.push(region);
     }
 
+    let stats = BatchingStats {
+        primitives: primitive_count,
+        draw_calls: batches.len(),
+        rect_instances: rects.len(),
+        textured_rect_instances: textured_rects.len(),
+        glyph_instances: glyphs.len(),
+    };
     BatchingResult {
         rects,
         textured_rects,
         glyphs,
         batches,
+        stats,
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// A transparent rect with a border around the div.
+fn focus_ring_rect(div: &Div, ring: &FocusRing) -> RectRaw {
+    let aabb = div.computed_aabb();
+    let offset = ring.offset;
+    RectRaw {
+        pos: Aabb::new(
+            aabb.min_x - offset,
+            aabb.min_y - offset,
+            aabb.max_x + offset,
+            aabb.max_y + offset,
+        ),
+        color: Color::TRANSPARENT,
+        border_radius: div.style.border_radius,
+        border_color: ring.color,
+        border_thickness: ring.thickness,
+        border_softness: 1.0,
+        gradient_axis: 0.0,
+        _unused3: 0.0,
+        gradient_color: Color::TRANSPARENT,
+    }
+}
+
+/// Primitives with the same key can be drawn in one draw call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BatchKey {
+    Rect,
+    TexturedRect(u64),
+    Text { font: u64, page: u32 },
+}
+
+struct PendingBatch<'a> {
+    key: BatchKey,
+    /// union of the bounds of all items.
+    bounds: Aabb,
+    items: Vec<SortPrimitive<'a>>,
+}
+
+/// Adds the item to the latest batch with the same key, if no batch after that one overlaps the item.
+/// Otherwise starts a new batch.
+fn push_item<'a>(
+    pending: &mut Vec<PendingBatch<'a>>,
+    key: BatchKey,
+    bounds: Aabb,
+    item: SortPrimitive<'a>,
+) {
+    for batch in pending.iter_mut().rev().take(MAX_BATCH_LOOKBACK) {
+        if batch.key == key {
+            batch.bounds = batch.bounds.union(&bounds);
+            batch.items.push(item);
+            return;
+        }
+        if batch.bounds.intersects(&bounds) {
+            // the item has to be drawn after this batch.
+            break;
+        }
+    }
+    pending.push(PendingBatch {
+        key,
+        bounds,
+        items: vec![item],
+    });
+}
+
+#[derive(Debug, Clone)]
 enum SortPrimitive<'a> {
     Rect {
         div: &'a Div,
@@ -144,6 +262,16 @@ enum SortPrimitive<'a> {
         div: &'a Div,
         text: &'a TextEntry,
     },
+    /// drawn around the `Board::nav_focused` div.
+    FocusRing {
+        div: &'a Div,
+        ring: &'a FocusRing,
+    },
+    /// glyphs of a text on the same atlas page.
+    Glyphs {
+        text: &'a TextEntry,
+        glyphs: Range<usize>,
+    },
 }
 
 impl<'a> SortPrimitive<'a> {
@@ -152,25 +280,14 @@ impl<'a> SortPrimitive<'a> {
     fn z_index(&self) -> i32 {
         match self {
             SortPrimitive::Rect { div } => div.z_index.get(),
+            // above the text of the div.
+            SortPrimitive::FocusRing { div, .. } => div.z_index.get() + 17,
             SortPrimitive::Text { div, .. } => div.z_index.get() + 16,
             SortPrimitive::TexturedRect {
                 div,
                 div_texture: _,
             } => div.z_index.get(),
-        }
-    }
-
-    #[inline]
-    fn batch_key(&self) -> u64 {
-        match self {
-            SortPrimitive::Rect { .. } => u64::MAX,
-            SortPrimitive::TexturedRect {
-                div: _,
-                div_texture,
-            } => div_texture.texture.as_u64_hash(),
-            SortPrimitive::Text { text, .. } => {
-                text.text.font.map(|e| e.as_u64_hash()).unwrap_or(0)
-            }
+            SortPrimitive::Glyphs { .. } => unreachable!("only created after sorting"),
         }
     }
 }
@@ -179,17 +296,28 @@ impl<'a> SortPrimitive<'a> {
 pub enum BatchRegion {
     Rect(Range<usize>),
     TexturedRect(Range<usize>, Ptr<BindableTexture>),
-    Text(Range<usize>, Option<Ptr<Font>>),
+    /// the u32 is the page of the font atlas.
+    Text(Range<usize>, Option<Ptr<Font>>, u32),
 }
 
-impl BatchRegion {
-    #[inline]
-    fn batch_key(&self) -> u64 {
-        match self {
-            BatchRegion::Rect(_) => u64::MAX,
-            BatchRegion::TexturedRect(_, texture) => texture.as_u64_hash(),
-            BatchRegion::Text(_, font) => font.map(|e| e.as_u64_hash()).unwrap_or(0),
-        }
+/// Draw calls and instances of the ui in one frame, see `UiRenderer::stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchingStats {
+    /// rects, textured rects and texts.
+    pub primitives: usize,
+    pub draw_calls: usize,
+    pub rect_instances: usize,
+    pub textured_rect_instances: usize,
+    pub glyph_instances: usize,
+}
+
+impl BatchingStats {
+    fn add(&mut self, other: &BatchingStats) {
+        self.primitives += other.primitives;
+        self.draw_calls += other.draw_calls;
+        self.rect_instances += other.rect_instances;
+        self.textured_rect_instances += other.textured_rect_instances;
+        self.glyph_instances += other.glyph_instances;
     }
 }
 
@@ -199,6 +327,7 @@ pub struct BatchingResult {
     pub textured_rects: Vec<RectRawTextured>,
     pub glyphs: Vec<GlyphRaw>,
     pub batches: Vec<BatchRegion>,
+    pub stats: BatchingStats,
 }
 
 impl Default for BatchingResult {
@@ -214,6 +343,7 @@ impl BatchingResult {
             textured_rects: vec![],
             glyphs: vec![],
             batches: vec![],
+            stats: BatchingStats::default(),
         }
     }
 
@@ -226,6 +356,39 @@ impl BatchingResult {
         empty
     }
 
+    /// Adds a single textured rect on top of everything added before, e.g. a software cursor.
+    pub fn push_textured_rect(
+        &mut self,
+        pos: Aabb,
+        uv: Aabb,
+        color: Color,
+        texture: Ptr<BindableTexture>,
+    ) {
+        let start = self.textured_rects.len();
+        self.textured_rects.push(RectRawTextured {
+            rect: RectRaw {
+                pos,
+                color,
+                border_radius: BorderRadius::default(),
+                border_color: Color::TRANSPARENT,
+                border_thickness: 0.0,
+                border_softness: 0.0,
+                gradient_axis: 0.0,
+                _unused3: 0.0,
+                gradient_color: Color::TRANSPARENT,
+            },
+            uv,
+        });
+        self.batches
+            .push(BatchRegion::TexturedRect(start..start + 1, texture));
+        self.stats.add(&BatchingStats {
+            primitives: 1,
+            draw_calls: 1,
+            textured_rect_instances: 1,
+            ..Default::default()
+        });
+    }
+
     pub fn combine(&mut self, mut other: BatchingResult) {
         if self.is_empty() {
             *self = other;
@@ -236,7 +399,9 @@ impl BatchingResult {
         let textured_rects_before: usize = self.textured_rects.len();
         let glyphs_before: usize = self.glyphs.len();
         self.rects.append(&mut other.rects);
+        self.textured_rects.append(&mut other.textured_rects);
         self.glyphs.append(&mut other.glyphs);
+        self.stats.add(&other.stats);
         // adjust indices of batch regions, because they now point to later regions in the other two vectors.
         self.batches.extend(other.batches.into_iter().map(|mut e| {
             // offset the range of each batch region:
@@ -249,7 +414,7 @@ impl BatchingResult {
                     r.start += textured_rects_before;
                     r.end += textured_rects_before;
                 }
-                BatchRegion::Text(r, _) => {
+                BatchRegion::Text(r, _, _) => {
                     r.start += glyphs_before;
                     r.end += glyphs_before;
                 }
@@ -269,8 +434,10 @@ pub struct RectRaw {
     // these are bundled together into another 16 byte chunk.
     border_thickness: f32,
     border_softness: f32,
-    _unused2: f32,
+    /// 0 for no gradient, 1 for `Axis::X`, 2 for `Axis::Y`.
+    gradient_axis: f32,
     _unused3: f32,
+    gradient_color: Color,
 }
 
 impl VertexT for RectRaw {
@@ -280,6 +447,7 @@ impl VertexT for RectRaw {
         Attribute::new("border_radius", VertexFormat::Float32x4),
         Attribute::new("border_color", VertexFormat::Float32x4),
         Attribute::new("others", VertexFormat::Float32x4),
+        Attribute::new("gradient_color", VertexFormat::Float32x4),
     ];
 }
 
@@ -292,8 +460,13 @@ impl RectRaw {
             border_color: div.style.border_color,
             border_thickness: div.style.border_thickness,
             border_softness: div.style.border_softness,
-            _unused2: 0.0,
+            gradient_axis: match div.style.gradient {
+                None => 0.0,
+                Some(Gradient { axis: Axis::X, .. }) => 1.0,
+                Some(Gradient { axis: Axis::Y, .. }) => 2.0,
+            },
             _unused3: 0.0,
+            gradient_color: div.style.gradient.map_or(div.style.color, |g| g.color),
         }
     }
 }
@@ -312,6 +485,7 @@ impl VertexT for RectRawTextured {
         Attribute::new("border_radius", VertexFormat::Float32x4),
         Attribute::new("border_color", VertexFormat::Float32x4),
         Attribute::new("others", VertexFormat::Float32x4),
+        Attribute::new("gradient_color", VertexFormat::Float32x4),
         Attribute::new("uv", VertexFormat::Float32x4),
     ];
 }
@@ -331,3 +505,33 @@ impl VertexT for GlyphRaw {
         Attribute::new("uv", VertexFormat::Float32x4),
     ];
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::dvec2;
+
+    use crate::modules::ui::{BoardTestHarness, Button, Id};
+
+    use super::get_batches;
+
+    #[test]
+    fn non_overlapping_primitives_share_batches() {
+        let mut harness = BoardTestHarness::new(dvec2(800.0, 2000.0));
+        harness.frame(&[], |board| {
+            let column = board.add_div("column", None).id;
+            // enough buttons, that the rects and texts are interleaved when sorted by z.
+            for i in 0..20 {
+                let button = Button {
+                    text: "Hi".into(),
+                    ..Default::default()
+                };
+                board.add(button, Id(1000 * (i + 1)), Some(column));
+            }
+        });
+        let stats = get_batches(&harness.board).stats;
+        assert_eq!(stats.primitives, 40);
+        assert_eq!(stats.rect_instances, 20);
+        // one draw call for all rects, one for all glyphs.
+        assert_eq!(stats.draw_calls, 2);
+    }
+}
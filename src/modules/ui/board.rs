@@ -15,9 +15,12 @@ use std::{
 };
 
 use crate::{
-    elements::{rect::Aabb, BindableTexture, Color, Rect},
+    elements::{rect::Aabb, BindableTexture, Color, Rect, Screen},
     ext::glam::Vec2,
-    modules::{input::MouseButtonState, Input},
+    modules::{
+        input::{ImePreedit, KeyState, MouseButtonState},
+        Input,
+    },
     utils::ChillCell,
     Ptr,
 };
@@ -30,9 +33,13 @@ use fontdue::{
 use glam::{dvec2, DVec2, IVec2};
 use rand::Rng;
 use smallvec::smallvec;
+use winit::window::CursorIcon;
 
 use super::{
-    font_cache::{FontCache, FontSize, TextLayoutItem, TextLayoutResult},
+    batching::BatchingResult,
+    font_cache::{FontCache, FontSize, TextLayoutItem, TextLayoutOptions, TextLayoutResult},
+    navigation::{navigate, FocusRing, NavDirection, NavInput},
+    sounds::{Theme, UiSoundEvent, UiSoundTrigger},
     widgets::Widget,
 };
 
@@ -77,6 +84,16 @@ impl Add<u64> for Id {
     }
 }
 
+impl Id {
+    /// The id of the `index`th child div of a widget. Unlike `id + index`, it does not collide with the ids of
+    /// neighboring widgets, e.g. `Id(1)` and `Id(2)`.
+    pub fn child(self, index: u64) -> Id {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (self, index).hash(&mut hasher);
+        Id(hasher.finish())
+    }
+}
+
 impl From<&'static str> for Id {
     fn from(value: &'static str) -> Self {
         let mut hasher = std::collections::hash_map::DefaultHasher::new();
@@ -110,9 +127,25 @@ pub struct Board {
     top_level_children: Vec<Id>,
     divs: HashMap<Id, Div>,
     divs_added_this_frame: usize,
+    layout_stats: LayoutStats,
+    /// hash of everything the batches depend on, computed in `end_frame`.
+    render_hash: u64,
+    /// the batches of the last frame, reused by `get_batches` if `render_hash` did not change.
+    batch_cache: ChillCell<Option<(u64, BatchingResult)>>,
 
     // experimental:
     hot_active: HotActiveWithId,
+    /// requested by the widgets in this frame, e.g. a hand over buttons. Forward to `Cursor::set_icon`.
+    cursor_icon: Option<CursorIcon>,
+    /// the widget that receives text input, e.g. a `TextEdit`.
+    focused: Option<Id>,
+    /// the widget selected with keyboard or gamepad navigation, see `Response::focusable`.
+    nav_focused: Option<Id>,
+    /// the focusable divs of the last frame in the order they were added, with their rects.
+    focusables: Vec<(Id, Aabb)>,
+    focus_ring: Option<FocusRing>,
+    theme: Theme,
+    sound_events: Vec<UiSoundEvent>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -169,12 +202,133 @@ impl Board {
         self.phase = BoardPhase::AddDivs;
         self.top_level_children.clear();
         self.top_level_size = top_level_size;
+        self.cursor_icon = None;
+        self.sound_events.clear();
+
+        // navigate with the focusables of the last frame. Clicking hands the control back to the mouse.
+        if self.input.mouse_buttons.left().just_pressed() {
+            self.nav_focused = None;
+        }
+        if self.focused.is_some() {
+            // arrows, Enter and Space edit the text of the focused text field.
+            self.input.nav.confirm = false;
+            self.input.nav.direction = self
+                .input
+                .nav
+                .direction
+                .filter(|d| matches!(d, NavDirection::Next | NavDirection::Previous));
+        }
+        if let Some(direction) = self.input.nav.direction {
+            self.nav_focused = navigate(&self.focusables, self.nav_focused, direction);
+        }
+    }
+
+    /// Requests a cursor icon for this frame, usually called by widgets depending on their hover state.
+    pub fn set_cursor_icon(&mut self, icon: CursorIcon) {
+        self.cursor_icon = Some(icon);
+    }
+
+    pub fn focused(&self) -> Option<Id> {
+        self.focused
+    }
+
+    pub fn set_focused(&mut self, id: Option<Id>) {
+        self.focused = id;
+    }
+
+    /// The widget selected with keyboard or gamepad navigation.
+    pub fn nav_focused(&self) -> Option<Id> {
+        self.nav_focused
+    }
+
+    pub fn set_nav_focused(&mut self, id: Option<Id>) {
+        self.nav_focused = id;
+    }
+
+    /// The ring drawn around the `nav_focused` widget, None to not draw any.
+    pub fn set_focus_ring(&mut self, focus_ring: Option<FocusRing>) {
+        self.focus_ring = focus_ring;
+    }
+
+    /// The focused div and the ring to draw around it, if any.
+    pub(super) fn nav_focus_ring(&self) -> Option<(&Div, &FocusRing)> {
+        let div = self.divs.get(&self.nav_focused?)?;
+        Some((div, self.focus_ring.as_ref()?))
+    }
+
+    /// true if a widget is focused that takes text input. Enable the ime in that case, see `DefaultModules::request_text_input`.
+    pub fn wants_text_input(&self) -> bool {
+        self.focused.is_some()
+    }
+
+    /// The cursor icon requested by the widgets in this frame, if any.
+    pub fn cursor_icon(&self) -> Option<CursorIcon> {
+        self.cursor_icon
     }
 
     pub fn iter_divs(&self) -> impl Iterator<Item = &Div> {
         self.divs.values()
     }
 
+    /// The computed rects of all divs as an indented tree in the order they were added, for snapshot tests
+    /// (see `utils::snapshot`). Ids are left out, they are random for divs added with `()`.
+    pub fn layout_snapshot(&self) -> String {
+        fn write_div(board: &Board, id: Id, depth: usize, out: &mut String) {
+            let Some(div) = board.divs.get(&id) else {
+                return;
+            };
+            let r = div.computed_rect();
+            let indent = "  ".repeat(depth);
+            let kind = match &div.content {
+                DivContent::Text(entry) => {
+                    let text: String = entry
+                        .text
+                        .spans
+                        .iter()
+                        .map(|span| match span {
+                            Span::Text(section) => section.string.as_ref(),
+                            Span::FixedSizeDiv { .. } => "[div]",
+                        })
+                        .collect();
+                    format!("text {text:?}")
+                }
+                DivContent::Children(_) => "div".to_string(),
+            };
+            out.push_str(&format!(
+                "{indent}{kind} x: {:.1} y: {:.1} w: {:.1} h: {:.1}\n",
+                r.min_x, r.min_y, r.width, r.height
+            ));
+            if let DivContent::Children(children) = &div.content {
+                for child in children.iter() {
+                    write_div(board, *child, depth + 1, out);
+                }
+            }
+        }
+
+        let mut out = String::new();
+        for id in self.top_level_children.iter() {
+            write_div(self, *id, 0, &mut out);
+        }
+        out
+    }
+
+    /// How many divs were laid out in the last `end_frame`, and how many were skipped because nothing changed.
+    pub fn layout_stats(&self) -> LayoutStats {
+        self.layout_stats
+    }
+
+    /// The batches of the previous frame, if nothing that is drawn changed since then.
+    pub(super) fn cached_batches(&self) -> Option<&BatchingResult> {
+        match self.batch_cache.get() {
+            Some((hash, batches)) if *hash == self.render_hash => Some(batches),
+            _ => None,
+        }
+    }
+
+    pub(super) fn set_cached_batches(&self, batches: BatchingResult) {
+        *self.batch_cache.get_mut() = Some((self.render_hash, batches));
+    }
+
     pub fn input(&self) -> &BoardInput {
         &self.input
     }
@@ -188,6 +342,7 @@ impl Board {
     }
 
     pub fn set_hot_active(&mut self, id: Id, state: HotActive) {
+        let old = self.hot_active(id);
         match state {
             HotActive::Nil => {
                 // dont allow change to none if currently other item is hot or active
@@ -200,6 +355,27 @@ impl Board {
             HotActive::Hot => self.hot_active = HotActiveWithId::Hot(id),
             HotActive::Active => self.hot_active = HotActiveWithId::Active(id),
         }
+        // every widget that uses the hot-active state gets sounds, without playing them itself.
+        let trigger = UiSoundTrigger::for_transition(old, state);
+        if let Some((trigger, sound)) =
+            trigger.and_then(|t| Some((t, self.theme.sounds.get(t)?.clone())))
+        {
+            self.sound_events.push(UiSoundEvent { id, trigger, sound });
+        }
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// The sounds of the widgets are read from it, changes apply to the next hot-active transitions.
+    pub fn theme_mut(&mut self) -> &mut Theme {
+        &mut self.theme
+    }
+
+    /// The sounds of the widgets in this frame, play them with `Audio::play_ui_sounds`.
+    pub fn sound_events(&self) -> &[UiSoundEvent] {
+        &self.sound_events
     }
 
     pub fn new(board_size: DVec2) -> Self {
@@ -213,7 +389,17 @@ impl Board {
             top_level_size: board_size,
             top_level_children: vec![],
             hot_active: HotActiveWithId::None,
+            cursor_icon: None,
+            focused: None,
+            nav_focused: None,
+            focusables: vec![],
+            focus_ring: Some(FocusRing::default()),
+            theme: Theme::default(),
+            sound_events: vec![],
             divs_added_this_frame: 0,
+            layout_stats: LayoutStats::default(),
+            render_hash: 0,
+            batch_cache: ChillCell::new(None),
         }
     }
 
@@ -337,6 +523,7 @@ impl Board {
                     panic!("Div with id {id:?} inserted twice in one frame! {div:?}");
                 }
                 div.last_frame = self.last_frame;
+                div.focusable = false;
 
                 match text {
                     Some(new_text) => match &mut div.content {
@@ -373,6 +560,10 @@ impl Board {
                     c_pos: Cell::new(DVec2::ZERO),
                     c_content_size: Cell::new(DVec2::ZERO),
                     c_padding: Cell::new(ComputedPadding::ZERO),
+                    c_hash: Cell::new(0),
+                    c_layout_key: Cell::new(None),
+                    c_layout_cached: Cell::new(false),
+                    focusable: false,
                 });
 
                 // rect not known yet.
@@ -381,8 +572,11 @@ impl Board {
         };
 
         // build up the response
+        let nav_focused = self.nav_focused == Some(id);
         let mut comm = Comm {
             mouse_in_rect: false,
+            nav_focused,
+            activated: nav_focused && self.input.nav.confirm,
         };
 
         if let Some(rect) = rect {
@@ -403,18 +597,87 @@ impl Board {
 
         // Remove Nodes that have not been added/updated this frame
         self.divs.retain(|_, v| v.last_frame == self.last_frame);
+        if self.focused.is_some_and(|id| !self.divs.contains_key(&id)) {
+            self.focused = None;
+        }
+        if self
+            .nav_focused
+            .is_some_and(|id| !self.divs.get(&id).is_some_and(|div| div.focusable))
+        {
+            self.nav_focused = None;
+        }
         self.divs_added_this_frame = 0;
         self.last_frame += 1;
 
+        // Hash the layout inputs of each subtree, unchanged subtrees are not laid out again:
+        let atlas_generation = fonts.atlas_generation();
+        let mut frame_hasher = std::collections::hash_map::DefaultHasher::new();
+        atlas_generation.hash(&mut frame_hasher);
+        hash_dvec2(self.top_level_size, &mut frame_hasher);
+        for id in self.top_level_children.iter() {
+            let div = self.divs.get(id).unwrap();
+            hash_subtree(&self.divs, div, atlas_generation).hash(&mut frame_hasher);
+        }
+        // the batches also depend on the colors, textures and z indices of all divs. Summed up, such that
+        // the iteration order of the hashmap does not matter.
+        self.nav_focused.hash(&mut frame_hasher);
+        if let Some(ring) = &self.focus_ring {
+            hash_color(&ring.color, &mut frame_hasher);
+            ring.thickness.to_bits().hash(&mut frame_hasher);
+            ring.offset.to_bits().hash(&mut frame_hasher);
+        }
+        let mut render_hash = frame_hasher.finish();
+        for (id, div) in self.divs.iter() {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            id.hash(&mut hasher);
+            div.z_index.get().hash(&mut hasher);
+            div.style.hash_render(&mut hasher);
+            if let DivContent::Text(text) = &div.content {
+                for span in text.text.spans.iter() {
+                    if let Span::Text(section) = span {
+                        hash_color(&section.color, &mut hasher);
+                    }
+                }
+            }
+            render_hash = render_hash.wrapping_add(hasher.finish());
+        }
+        self.render_hash = render_hash;
+
         // Perform Layout (set sizes and positions for all divs in the tree)
-        let mut layouter = Layouter::new(&self.divs, fonts);
+        let mut layouter = Layouter::new(&self.divs, fonts, true);
         layouter.perform_layout(&self.top_level_children, self.top_level_size);
+        self.layout_stats = LayoutStats {
+            divs: self.divs.len(),
+            laid_out: layouter.laid_out,
+        };
+
+        // glyphs were moved during the layout, texts laid out before that point to their old place in the atlas.
+        if fonts.atlas_generation() != atlas_generation {
+            for div in self.divs.values_mut() {
+                if let DivContent::Text(text) = &mut div.content {
+                    fonts.refresh_uvs(&mut text.c_text_layout.get_mut().result);
+                }
+            }
+            self.render_hash = self.render_hash.wrapping_add(fonts.atlas_generation());
+        }
+
+        // the order of navigation with Next/Previous is the order the divs were added in.
+        self.focusables.clear();
+        self.focusables.extend(
+            self.divs
+                .iter()
+                .filter(|(_, div)| div.focusable)
+                .map(|(id, div)| (*id, div.computed_aabb())),
+        );
+        self.focusables
+            .sort_by_key(|(id, _)| self.divs.get(id).unwrap().z_index.get());
     }
 
     /// Warning: this performs an entire layout run down from this child!
     #[allow(non_snake_case)]
     pub fn HACKY_query_size(&self, fonts: &mut FontCache, id: DivId, max_size: DVec2) -> DVec2 {
-        let mut layouter = Layouter::new(&self.divs, fonts);
+        // not cached: the hashes of the divs are not up to date while divs are added.
+        let mut layouter = Layouter::new(&self.divs, fonts, false);
         let div = layouter.divs.get(&id._priv).unwrap();
         let size = layouter.get_and_set_size(div, max_size);
         size
@@ -486,6 +749,23 @@ impl<'a, I: AsDivId> Response<'a, I> {
         self.comm.mouse_in_rect
     }
 
+    /// Makes the div reachable with keyboard and gamepad navigation (Tab, arrows or d-pad, see `NavInput`) in the
+    /// next frames. Call it every frame, like setting the style.
+    pub fn focusable(&mut self) {
+        self.entry.get_mut().focusable = true;
+    }
+
+    /// true if the div is selected with keyboard or gamepad navigation. A `FocusRing` is drawn around it.
+    pub fn nav_focused(&self) -> bool {
+        self.comm.nav_focused
+    }
+
+    /// true if the div is `nav_focused` and the confirm button (Enter, Space or the gamepad a button) was pressed,
+    /// widgets should handle this like a click.
+    pub fn activated(&self) -> bool {
+        self.comm.activated
+    }
+
     pub fn add_z_bias(&mut self, z_bias: i32) {
         let entry = self.entry.get_mut();
         entry.z_index.set(entry.z_index.get() + z_bias);
@@ -508,17 +788,52 @@ pub struct BoardInput {
     pub scroll: f32,
     pub cursor_pos: Option<Vec2>,
     pub cursor_delta: Vec2,
+    pub keys: KeyState,
+    /// typed or committed by the ime this frame.
+    pub text: String,
+    pub ime_preedit: Option<ImePreedit>,
+    pub nav: NavInput,
 }
 
 impl BoardInput {
     /// todo! other function from input module + camera + plane in 3d space => 3d game world ui!
+    ///
+    /// Input captured by egui (see `Input::capture`) is left out, such that clicks on egui windows do not reach
+    /// the widgets behind them.
     pub fn from_input_module(input: &Input) -> Self {
-        BoardInput {
+        let mut board_input = BoardInput {
             mouse_buttons: *input.mouse_buttons(),
             scroll: input.scroll().unwrap_or(0.0),
             cursor_pos: Some(input.cursor_pos()),
             cursor_delta: input.cursor_delta(),
+            keys: input.keys().clone(),
+            text: input.text().to_owned(),
+            ime_preedit: input.ime_preedit().cloned(),
+            nav: NavInput::from_input(input),
+        };
+        let capture = input.capture();
+        if capture.pointer {
+            board_input.mouse_buttons = MouseButtonState::default();
+            board_input.scroll = 0.0;
+            board_input.cursor_pos = None;
+            board_input.cursor_delta = Vec2::ZERO;
         }
+        if capture.keyboard {
+            board_input.keys = KeyState::default();
+            board_input.text.clear();
+            board_input.ime_preedit = None;
+            board_input.nav = NavInput::default();
+        }
+        board_input
+    }
+
+    /// Like `from_input_module`, but maps the cursor into the ui coordinates of the screen (see `Screen::window_to_ui`).
+    pub fn from_input_and_screen(input: &Input, screen: &Screen) -> Self {
+        let mut board_input = Self::from_input_module(input);
+        if !input.capture().pointer {
+            board_input.cursor_pos = screen.window_to_ui(input.cursor_pos());
+        }
+        board_input
     }
 }
 
@@ -526,16 +841,29 @@ impl BoardInput {
 pub struct Comm {
     // Some, if the mouse is hovering, clicking or releasing?
     pub mouse_in_rect: bool,
+    /// selected with keyboard or gamepad navigation.
+    pub nav_focused: bool,
+    /// nav_focused and the confirm button was pressed this frame.
+    pub activated: bool,
 }
 
 struct Layouter<'a> {
     divs: &'a HashMap<Id, Div>,
     fonts: &'a mut FontCache,
+    /// if true, subtrees with the same `LayoutKey` as in the last layout are skipped.
+    cache: bool,
+    /// number of divs whose size was computed.
+    laid_out: usize,
 }
 
 impl<'a> Layouter<'a> {
-    fn new(divs: &'a HashMap<Id, Div>, fonts: &'a mut FontCache) -> Self {
-        Self { divs, fonts }
+    fn new(divs: &'a HashMap<Id, Div>, fonts: &'a mut FontCache, cache: bool) -> Self {
+        Self {
+            divs,
+            fonts,
+            cache,
+            laid_out: 0,
+        }
     }
 
     /// determine the Rect of each div on this board.
@@ -570,9 +898,38 @@ impl<'a> Layouter<'a> {
                 top_div.style.offset_y,
                 top_level_size,
             );
-            top_div.c_pos.set(offset);
             // set the position of each div in the tree:
-            self.set_child_positions(top_div);
+            self.set_pos_and_child_positions(top_div, offset);
+        }
+    }
+
+    /// Sets the position of the div and then of its children, unless the layout of its subtree was reused and
+    /// the div did not move. Then the positions of the children are still correct.
+    fn set_pos_and_child_positions(&self, div: &Div, pos: DVec2) {
+        if div.c_layout_cached.get() && div.c_pos.get() == pos {
+            return;
+        }
+        div.c_pos.set(pos);
+        self.set_child_positions(div);
+    }
+
+    /// Marks the glyphs of all texts in the subtree as used, such that they are not evicted from the atlas
+    /// while the layout of the subtree is reused.
+    fn touch_texts(&mut self, div: &Div) {
+        match &div.content {
+            DivContent::Text(text_entry) => {
+                self.fonts.touch(&text_entry.c_text_layout.get().result);
+                for span in text_entry.text.spans.iter() {
+                    if let Span::FixedSizeDiv { id, .. } = span {
+                        self.touch_texts(self.divs.get(&id._div_id._priv).unwrap());
+                    }
+                }
+            }
+            DivContent::Children(children) => {
+                for id in children.iter() {
+                    self.touch_texts(self.divs.get(id).unwrap());
+                }
+            }
         }
     }
 
@@ -583,6 +940,19 @@ impl<'a> Layouter<'a> {
     /// 2. figure out own size and content size
     /// 3. sache own size and content size in the div, then return own size.
     fn get_and_set_size(&mut self, div: &Div, parent_max_size: DVec2) -> DVec2 {
+        let key = LayoutKey {
+            hash: div.c_hash.get(),
+            max_size: parent_max_size,
+        };
+        if self.cache && div.c_layout_key.get() == Some(key) {
+            div.c_layout_cached.set(true);
+            self.touch_texts(div);
+            return div.c_size.get();
+        }
+        div.c_layout_cached.set(false);
+        div.c_layout_key.set(self.cache.then_some(key));
+        self.laid_out += 1;
+
         // fixed width
         let mut fixed_width: Option<f64> = None;
         let mut fixed_height: Option<f64> = None;
@@ -739,7 +1109,9 @@ impl<'a> Layouter<'a> {
         let i_max_size = max_size.as_ivec2();
         // look for cached value and return it:
         let cached = text_entry.c_text_layout.get_mut();
-        if cached.max_size == i_max_size {
+        if cached.max_size == i_max_size && self.fonts.is_layout_current(&cached.result) {
+            // such that the glyphs of the text are not evicted from the atlas.
+            self.fonts.touch(&cached.result);
             return cached.result.total_rect.d_size();
         }
 
@@ -751,12 +1123,13 @@ impl<'a> Layouter<'a> {
             y: 0.0,
             max_width: Some(max_size.x as f32),
             max_height: Some(max_size.y as f32),
-            // We only support Left right now, because there are issues with fontdues text layout:
-            // If you specify e.g. Center, it will always center it to the provided max_size. This is a bit bad,
-            // because it then returns a way bigger size than the text actually takes and the text is then drawn in the top right corner.
+            // Always Left: fontdue would align to the provided max_size. This is a bit bad, because it then returns
+            // a way bigger size than the text actually takes. Instead the font cache aligns the lines to each other
+            // (`TextLayoutOptions::align_x`) and the text block is positioned in the div in `set_child_positions`.
             horizontal_align: HorizontalAlign::Left,
             vertical_align: VerticalAlign::Top,
             line_height: text_entry.text.line_height,
+            // set from the `TextWrap` by the font cache.
             wrap_style: fontdue::layout::WrapStyle::Word,
             wrap_hard_breaks: true, // todo!() needle expose these functions
         };
@@ -775,9 +1148,12 @@ impl<'a> Layouter<'a> {
             },
         });
 
-        let result = self
-            .fonts
-            .perform_text_layout(spans, &layout_settings, text_entry.text.font);
+        let result = self.fonts.perform_text_layout(
+            spans,
+            &layout_settings,
+            text_entry.text.font,
+            text_entry.text.layout_options(),
+        );
         // dbg!(&result);
         let text_size = result.total_rect.d_size();
         *cached = CachedTextLayout {
@@ -880,7 +1256,14 @@ impl<'a> Layouter<'a> {
             match &div.content {
                 DivContent::Text(t) => {
                     let cross = calc_cross_offset(cross_size, cross_content_size);
-                    let text_pos = A::assemble(main_offset, cross);
+                    let mut text_pos = A::assemble(main_offset, cross);
+                    // the alignment of the text overrides the one of the div.
+                    if let Some(align) = t.text.align_x {
+                        text_pos.x = align_offset(align, div_size.x, content_size.x);
+                    }
+                    if let Some(align) = t.text.align_y {
+                        text_pos.y = align_offset(align, div_size.y, content_size.y);
+                    }
                     let text_offset = offset_dvec2(t.text.offset_x, t.text.offset_y, div_size);
                     let absolute_text_pos = text_pos + text_offset + div_pos;
                     t.c_pos.set(absolute_text_pos);
@@ -896,9 +1279,10 @@ impl<'a> Layouter<'a> {
                             t.c_text_layout.get().result.space_sections[i].as_dvec2();
                         let div_offset =
                             offset_dvec2(div.style.offset_x, div.style.offset_y, div_size);
-                        div.c_pos
-                            .set(absolute_text_pos + div_pos_relative_in_text + div_offset);
-                        sel.set_child_positions(div);
+                        sel.set_pos_and_child_positions(
+                            div,
+                            absolute_text_pos + div_pos_relative_in_text + div_offset,
+                        );
                         i += 1;
                     }
                 }
@@ -925,8 +1309,7 @@ impl<'a> Layouter<'a> {
                         let ch_offset =
                             offset_dvec2(ch.style.offset_x, ch.style.offset_y, div_size);
 
-                        ch.c_pos.set(ch_rel_pos + ch_offset + div_pos);
-                        sel.set_child_positions(ch);
+                        sel.set_pos_and_child_positions(ch, ch_rel_pos + ch_offset + div_pos);
                     }
                 }
             }
@@ -1010,6 +1393,14 @@ pub struct Div {
     pub c_content_size: Cell<DVec2>,
     pub c_pos: Cell<DVec2>,
     pub c_padding: Cell<ComputedPadding>,
+    /// can be selected with keyboard or gamepad navigation, set every frame with `Response::focusable`.
+    focusable: bool,
+    /// hash of the layout inputs of this div and its subtree in this frame, see `hash_subtree`.
+    c_hash: Cell<u64>,
+    /// the inputs of the last layout, the computed values above are reused if they are the same.
+    c_layout_key: Cell<Option<LayoutKey>>,
+    /// true if the layout of the subtree was reused in this frame.
+    c_layout_cached: Cell<bool>,
 }
 
 impl Div {
@@ -1040,6 +1431,87 @@ impl Div {
     }
 }
 
+/// The inputs of the layout of a div, see `Div::c_layout_key`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LayoutKey {
+    hash: u64,
+    max_size: DVec2,
+}
+
+/// Shows how well the layout cache works, see `Board::layout_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LayoutStats {
+    pub divs: usize,
+    /// divs whose size was computed, all others were in subtrees that did not change since the last frame.
+    pub laid_out: usize,
+}
+
+/// Hashes everything that the layout of the div and its subtree depends on and stores it in `c_hash`.
+///
+/// The size and position of a div only depend on its style and content, the hashes of its children and the max size
+/// its parent gives it, so a subtree with the same hash and max size as in the last layout can be skipped.
+/// The atlas generation is part of the hash of texts, because text layouts are invalid after the atlas was compacted.
+fn hash_subtree(divs: &HashMap<Id, Div>, div: &Div, atlas_generation: u64) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    div.style.hash_layout(&mut hasher);
+    match &div.content {
+        DivContent::Text(text_entry) => {
+            let text = &text_entry.text;
+            atlas_generation.hash(&mut hasher);
+            text.font.hash(&mut hasher);
+            hash_len(text.offset_x, &mut hasher);
+            hash_len(text.offset_y, &mut hasher);
+            text.line_height.to_bits().hash(&mut hasher);
+            text.layout_options().hash(&mut hasher);
+            text.align_y.hash(&mut hasher);
+            for span in text.spans.iter() {
+                match span {
+                    Span::Text(section) => {
+                        section.size.hash(&mut hasher);
+                        section.string.hash(&mut hasher);
+                    }
+                    Span::FixedSizeDiv {
+                        id,
+                        width,
+                        height,
+                        font_size,
+                    } => {
+                        id._div_id._priv.hash(&mut hasher);
+                        width.to_bits().hash(&mut hasher);
+                        height.to_bits().hash(&mut hasher);
+                        font_size.hash(&mut hasher);
+                        let span_div = divs.get(&id._div_id._priv).expect("Div was inserted");
+                        hash_subtree(divs, span_div, atlas_generation).hash(&mut hasher);
+                    }
+                }
+            }
+        }
+        DivContent::Children(children) => {
+            for id in children.iter() {
+                let child = divs.get(id).unwrap();
+                hash_subtree(divs, child, atlas_generation).hash(&mut hasher);
+            }
+        }
+    }
+    let hash = hasher.finish();
+    div.c_hash.set(hash);
+    hash
+}
+
+fn hash_len(len: Len, state: &mut impl Hasher) {
+    len.px.to_bits().hash(state);
+    len.parent_fraction.to_bits().hash(state);
+}
+
+fn hash_dvec2(v: DVec2, state: &mut impl Hasher) {
+    v.x.to_bits().hash(state);
+    v.y.to_bits().hash(state);
+}
+
+fn hash_color(color: &Color, state: &mut impl Hasher) {
+    bytemuck::bytes_of(color).hash(state);
+}
+
 #[derive(Debug)]
 pub struct DivStyle {
     /// None means, the div has a non-fixed width, the children dictate the size of this div
@@ -1066,6 +1538,59 @@ pub struct DivStyle {
     // set to 0.0 for very crisp inner border. set to 20.0 for like an inset shadow effect.
     pub border_softness: f32,
     pub texture: Option<DivTexture>,
+    pub gradient: Option<Gradient>,
+}
+
+impl DivStyle {
+    /// Hashes the fields that influence the size and position of the div and its children.
+    fn hash_layout(&self, state: &mut impl Hasher) {
+        for len in [self.width, self.height] {
+            match len {
+                Some(len) => hash_len(len, state),
+                None => u64::MAX.hash(state),
+            }
+        }
+        self.axis.hash(state);
+        self.main_align.hash(state);
+        self.cross_align.hash(state);
+        for len in [
+            self.padding.left,
+            self.padding.right,
+            self.padding.top,
+            self.padding.bottom,
+        ] {
+            hash_len(len, state);
+        }
+        self.absolute.hash(state);
+        hash_len(self.offset_x, state);
+        hash_len(self.offset_y, state);
+    }
+
+    /// Hashes the fields that only influence how the div is drawn.
+    fn hash_render(&self, state: &mut impl Hasher) {
+        hash_color(&self.color, state);
+        hash_color(&self.border_color, state);
+        bytemuck::bytes_of(&self.border_radius).hash(state);
+        self.border_thickness.to_bits().hash(state);
+        self.border_softness.to_bits().hash(state);
+        if let Some(texture) = &self.texture {
+            texture.texture.as_u64_hash().hash(state);
+            bytemuck::bytes_of(&texture.uv).hash(state);
+        }
+        if let Some(gradient) = &self.gradient {
+            gradient.axis.hash(state);
+            hash_color(&gradient.color, state);
+        }
+    }
+}
+
+/// A linear gradient from `DivStyle::color` at the start of the axis (left for `Axis::X`, top for `Axis::Y`) to
+/// `color` at its end. Interpolated between the corners of the rect, so it costs nothing extra.
+/// Not applied to divs with a texture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gradient {
+    pub axis: Axis,
+    pub color: Color,
 }
 
 impl Default for DivStyle {
@@ -1086,6 +1611,7 @@ impl Default for DivStyle {
             offset_x: Len::ZERO,
             offset_y: Len::ZERO,
             texture: None,
+            gradient: None,
         }
     }
 }
@@ -1365,6 +1891,37 @@ pub struct Text {
     pub offset_y: Len,
     // factor, default is 1.0
     pub line_height: f32,
+    pub wrap: TextWrap,
+    pub overflow: TextOverflow,
+    /// None uses the alignment of the div (`main_align` / `cross_align`) for the text block, and aligns the
+    /// lines at the start. Some also aligns the lines relative to each other.
+    pub align_x: Option<Align>,
+    /// None uses the alignment of the div.
+    pub align_y: Option<Align>,
+}
+
+/// Where lines of a `Text` are broken if they are wider than the div.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextWrap {
+    /// between words, words longer than a line are broken between characters.
+    #[default]
+    Word,
+    /// after any character.
+    Char,
+    /// only at explicit line breaks.
+    None,
+}
+
+/// What happens to text that does not fit into its div.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextOverflow {
+    /// the text is drawn outside of the div.
+    #[default]
+    Visible,
+    /// glyphs that do not fit completely are not drawn.
+    Clip,
+    /// like `Clip`, but the last visible line ends with "…".
+    Ellipsis,
 }
 
 impl Text {
@@ -1401,8 +1958,34 @@ impl Text {
         self
     }
 
+    pub fn wrap(mut self, wrap: TextWrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn overflow(mut self, overflow: TextOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn align(mut self, align_x: Align, align_y: Align) -> Self {
+        self.align_x = Some(align_x);
+        self.align_y = Some(align_y);
+        self
+    }
+
+    fn layout_options(&self) -> TextLayoutOptions {
+        TextLayoutOptions {
+            wrap: self.wrap,
+            overflow: self.overflow,
+            align_x: self.align_x.unwrap_or_default(),
+        }
+    }
+
     fn same_glyphs(&self, other: &Self) -> bool {
-        let same = self.font == other.font && self.spans.len() == other.spans.len();
+        let same = self.font == other.font
+            && self.spans.len() == other.spans.len()
+            && self.layout_options() == other.layout_options();
         if !same {
             return false;
         }
@@ -1450,6 +2033,10 @@ impl Default for Text {
             offset_x: Len::ZERO,
             offset_y: Len::ZERO,
             line_height: 1.0,
+            wrap: TextWrap::Word,
+            overflow: TextOverflow::Visible,
+            align_x: None,
+            align_y: None,
         }
     }
 }
@@ -1494,6 +2081,7 @@ impl CachedTextLayout {
             max_size: IVec2::ZERO,
             result: TextLayoutResult {
                 layouted_glyphs: vec![],
+                atlas_generation: 0,
                 total_rect: Rect::ZERO,
                 space_sections: smallvec![],
             },
@@ -1510,14 +2098,14 @@ impl Debug for CachedTextLayout {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Axis {
     X,
     #[default]
     Y,
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MainAlign {
     #[default]
     Start,
@@ -1527,7 +2115,7 @@ pub enum MainAlign {
     SpaceAround,
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Align {
     #[default]
     Start,
@@ -1535,6 +2123,15 @@ pub enum Align {
     End,
 }
 
+/// Offset of an item of size `item` inside of `parent`.
+fn align_offset(align: Align, parent: f64, item: f64) -> f64 {
+    match align {
+        Align::Start => 0.0,
+        Align::Center => (parent - item) * 0.5,
+        Align::End => parent - item,
+    }
+}
+
 /// An explicitly set Length
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Len {
@@ -1673,3 +2270,45 @@ fn _unsued_egui_inspect_board(ctx: &mut egui::Context, board: &mut Board) {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::{dvec2, vec2};
+
+    use crate::modules::ui::{batching::get_batches, BoardTestHarness, Button, Id};
+
+    #[test]
+    fn unchanged_subtrees_are_not_laid_out_again() {
+        let mut harness = BoardTestHarness::new(dvec2(800.0, 2000.0));
+        let build = |board: &mut super::Board, last_label: &str| {
+            let column = board.add_div("column", None).id;
+            let slots = board.add_div("slots", Some(column)).id;
+            for i in 0..50 {
+                board.add(Button::default(), Id(1000 * (i + 1)), Some(slots));
+            }
+            let button = Button {
+                text: last_label.to_string().into(),
+                ..Default::default()
+            };
+            board.add(button, "last", Some(column));
+        };
+        // keep the cursor away from the buttons, such that their colors do not change.
+        harness.hover(vec2(790.0, 1990.0), |board| build(board, "A"));
+        let first = harness.board.layout_stats();
+        assert_eq!(first.laid_out, first.divs);
+        let batches = get_batches(&harness.board);
+
+        // nothing changed: nothing is laid out and the batches are reused.
+        harness.frame(&[], |board| build(board, "A"));
+        assert_eq!(harness.board.layout_stats().laid_out, 0);
+        assert!(harness.board.cached_batches().is_some());
+        assert_eq!(get_batches(&harness.board).rects.len(), batches.rects.len());
+
+        // only the changed button and the column are laid out, the slots are skipped.
+        harness.frame(&[], |board| build(board, "Longer label"));
+        assert_eq!(harness.board.layout_stats().laid_out, 2);
+        assert!(harness.board.cached_batches().is_none());
+        let last = harness.board.divs.get(&Id::from("last")).unwrap();
+        assert!(last.c_size.get().x > 0.0);
+    }
+}
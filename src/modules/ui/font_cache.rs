@@ -1,6 +1,6 @@
-use etagere::{AllocId, AtlasAllocator};
+use etagere::{AllocId, Allocation, AtlasAllocator};
 use fontdue::{
-    layout::{CoordinateSystem, Layout, LayoutSettings, TextStyle},
+    layout::{CoordinateSystem, Layout, LayoutSettings, TextStyle, WrapStyle},
     Font,
 };
 use glam::{ivec2, vec2, IVec2, Vec2};
@@ -12,81 +12,251 @@ use std::collections::HashMap;
 use crate::{
     elements::{rect::Aabb, BindableTexture, Color, Rect, Texture},
     modules::GraphicsContext,
-    OwnedPtr, Ptr,
+    GpuRecreate, GpuRecreated, OwnedPtr, Ptr,
 };
 
-use super::board::{Span, TextSection};
+use super::board::{Align, TextOverflow, TextSection, TextWrap};
 
 // const PREALLOCATED_CHARACTERS: &str =
 //     "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890,./\\?<>{}[]!@#$%^&*()_-=+|~` \n\tÄäÖöÜüß";
 
-const ATLAS_SIZE: u32 = 4096;
+/// Settings for the glyph atlas of the `FontCache`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphCacheSettings {
+    /// width and height of each atlas page in pixels. Only used for pages that are created afterwards.
+    pub page_size: u32,
+    /// a new page is added when all pages are full and evicting cold glyphs did not help.
+    pub max_pages: usize,
+    /// glyphs that were not used for this many frames are evicted when the atlas is full.
+    pub evict_after_frames: u64,
+}
+
+impl Default for GlyphCacheSettings {
+    fn default() -> Self {
+        Self {
+            page_size: 2048,
+            max_pages: 4,
+            evict_after_frames: 120,
+        }
+    }
+}
+
+/// Occupancy of the glyph atlas, see `FontCache::metrics`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GlyphCacheMetrics {
+    pub pages: usize,
+    pub glyphs: usize,
+    /// allocated area of all pages divided by their total area, between 0 and 1.
+    pub occupancy: f32,
+    pub evicted_glyphs: usize,
+    pub compactions: usize,
+}
+
+struct AtlasPage {
+    allocator: AtlasAllocator,
+    /// None for a headless font cache.
+    texture: Option<OwnedPtr<BindableTexture>>,
+}
 
-/// Todo! Currently no glyph cleanup, which is quite bad. Glyph cleanup can be pretty hard though.
+/// Rasterizes glyphs on demand into atlas pages and lays out text.
+///
+/// When the pages are full, glyphs that were not used for `GlyphCacheSettings::evict_after_frames` frames are
+/// evicted (least recently used first). If that is not enough, a new page is added (up to `max_pages`), and as a last
+/// resort the pages are compacted: all glyphs still in use are packed again from scratch.
+/// Eviction and compaction bump the `atlas_generation`, such that cached text layouts are laid out again.
 pub struct FontCache {
-    atlas_texture: OwnedPtr<BindableTexture>,
-    atlas_allocator: AtlasAllocator,
+    pages: Vec<AtlasPage>,
+    /// false for a headless font cache, see `FontCache::headless`.
+    has_gpu: bool,
+    settings: GlyphCacheSettings,
     default_font: OwnedPtr<Font>,
     glyphs: HashMap<GlyphKey, Glyph>,
     texture_writes: Vec<GlyphKey>,
+    /// physical pixels per layout unit. Glyphs are rasterized at this density, such that text stays sharp on high dpi screens.
+    raster_scale: f32,
+    frame: u64,
+    atlas_generation: u64,
+    evicted_glyphs: usize,
+    compactions: usize,
 }
 
 impl FontCache {
     pub fn new(ctx: &GraphicsContext) -> Self {
+        Self::with_settings(ctx, GlyphCacheSettings::default())
+    }
+
+    pub fn with_settings(ctx: &GraphicsContext, settings: GlyphCacheSettings) -> Self {
+        let mut fonts = Self::headless_with_settings(settings);
+        fonts.has_gpu = true;
+        let size = fonts.settings.page_size;
+        fonts.pages[0].texture = Some(OwnedPtr::new(create_atlas_texture(ctx, size)));
+        fonts
+    }
+
+    /// A font cache without gpu atlas texture. It can lay out text, but not render it. Used for tests.
+    pub fn headless() -> Self {
+        Self::headless_with_settings(GlyphCacheSettings::default())
+    }
+
+    pub fn headless_with_settings(settings: GlyphCacheSettings) -> Self {
         const DEFAULT_FONT_BYTES: &[u8] = include_bytes!("../../../assets/Oswald-Medium.ttf");
         let default_font = fontdue::Font::from_bytes(DEFAULT_FONT_BYTES, Default::default())
             .expect("could not load default font");
         let default_font = OwnedPtr::new(default_font);
 
-        let atlas_width: u32 = ATLAS_SIZE;
-        let atlas_height: u32 = ATLAS_SIZE;
-        let atlas_allocator =
-            AtlasAllocator::new(etagere::size2(atlas_width as i32, atlas_height as i32));
-
-        let image = RgbaImage::new(atlas_width, atlas_height);
-        let atlas_texture = Texture::from_image(&ctx.device, &ctx.queue, &image);
-        let atlas_texture = BindableTexture::new(&ctx.device, atlas_texture);
-        let atlas_texture = OwnedPtr::new(atlas_texture);
+        let page = AtlasPage {
+            allocator: new_atlas_allocator(settings.page_size),
+            texture: None,
+        };
 
         FontCache {
             default_font,
-            atlas_texture,
-            atlas_allocator,
+            pages: vec![page],
+            has_gpu: false,
+            settings,
             glyphs: HashMap::new(),
             texture_writes: vec![],
+            raster_scale: 1.0,
+            frame: 0,
+            atlas_generation: 0,
+            evicted_glyphs: 0,
+            compactions: 0,
         }
     }
 
-    pub fn prepare(&mut self, queue: &wgpu::Queue) {
+    /// Creates the textures of pages added since the last frame and uploads new glyphs.
+    pub fn prepare(&mut self, ctx: &GraphicsContext) {
+        if self.has_gpu {
+            for page in self.pages.iter_mut() {
+                if page.texture.is_none() {
+                    let size = page.allocator.size().width as u32;
+                    page.texture = Some(OwnedPtr::new(create_atlas_texture(ctx, size)));
+                }
+            }
+        }
+        // a glyph can be queued twice after the atlas was recreated.
+        self.texture_writes.dedup();
         for key in self.texture_writes.iter() {
-            let glyph = self.glyphs.get(key).unwrap();
+            // the glyph could have been evicted again before it was uploaded.
+            let Some(glyph) = self.glyphs.get(key) else {
+                continue;
+            };
+            let Some(texture) = &self.pages[glyph.page as usize].texture else {
+                continue;
+            };
             let glyph_image = glyph_to_rgba_image(glyph);
             update_texture_region(
-                &self.atlas_texture.texture,
+                &texture.texture,
                 &glyph_image,
                 glyph.offset_in_atlas,
-                queue,
+                &ctx.queue,
             );
         }
         self.texture_writes.clear();
     }
 
+    /// Called once per frame, glyphs are evicted based on the frames they were last used in.
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Usually `Screen::ui_scale_factor`. Glyphs rasterized at the old scale stay in the atlas, because
+    /// already laid out text still references them. They are evicted once they are not used anymore.
+    pub fn set_raster_scale(&mut self, raster_scale: f32) {
+        self.raster_scale = raster_scale;
+    }
+
+    pub fn raster_scale(&self) -> f32 {
+        self.raster_scale
+    }
+
     pub fn default_font(&self) -> &OwnedPtr<Font> {
         &self.default_font
     }
 
+    pub fn settings_mut(&mut self) -> &mut GlyphCacheSettings {
+        &mut self.settings
+    }
+
+    /// The texture of the first atlas page. Panics for a headless font cache.
     pub fn atlas_texture(&self) -> &OwnedPtr<BindableTexture> {
-        &self.atlas_texture
+        self.atlas_page(0)
+    }
+
+    /// Panics for a headless font cache, or if the page does not exist or was added this frame before `prepare`.
+    pub fn atlas_page(&self, page: u32) -> &OwnedPtr<BindableTexture> {
+        self.pages[page as usize]
+            .texture
+            .as_ref()
+            .expect("headless font cache has no atlas texture")
+    }
+
+    pub fn atlas_page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Changes whenever glyphs are evicted or moved, see `TextLayoutResult::atlas_generation`.
+    pub fn atlas_generation(&self) -> u64 {
+        self.atlas_generation
+    }
+
+    /// false if glyphs were evicted or moved since the text was laid out, then it needs to be laid out again.
+    pub fn is_layout_current(&self, layout: &TextLayoutResult) -> bool {
+        layout.atlas_generation == self.atlas_generation
+    }
+
+    /// Points the glyphs of a layout to their current place in the atlas, e.g. after the atlas was compacted later
+    /// in the same frame. Glyphs that are not in the atlas anymore are dropped from the layout.
+    pub fn refresh_uvs(&self, layout: &mut TextLayoutResult) {
+        layout
+            .layouted_glyphs
+            .retain_mut(|layouted| match self.glyphs.get(&layouted.key) {
+                Some(glyph) => {
+                    layouted.page = glyph.page;
+                    layouted.uv = glyph.atlas_uv;
+                    true
+                }
+                None => false,
+            });
+        layout.atlas_generation = self.atlas_generation;
+    }
+
+    /// Marks the glyphs of a cached layout as used in this frame, such that they are not evicted.
+    pub fn touch(&mut self, layout: &TextLayoutResult) {
+        for layouted in layout.layouted_glyphs.iter() {
+            if let Some(glyph) = self.glyphs.get_mut(&layouted.key) {
+                glyph.last_used = self.frame;
+            }
+        }
+    }
+
+    pub fn metrics(&self) -> GlyphCacheMetrics {
+        let (allocated, total) = self.pages.iter().fold((0i64, 0i64), |(a, t), p| {
+            let size = p.allocator.size();
+            (
+                a + p.allocator.allocated_space() as i64,
+                t + size.width as i64 * size.height as i64,
+            )
+        });
+        GlyphCacheMetrics {
+            pages: self.pages.len(),
+            glyphs: self.glyphs.len(),
+            occupancy: allocated as f32 / total.max(1) as f32,
+            evicted_glyphs: self.evicted_glyphs,
+            compactions: self.compactions,
+        }
     }
 
     // pub fn atlas_texture_obj(&self) -> &BindableTexture {
     //     &self.deps.arenas[&self.atlas_texture]
     // }
 
-    /// Returns non if there is no glyph that can be assigned to the char (e.g. for space)
-    fn get_glyph_atlas_uv_or_rasterize(&mut self, key: GlyphKey) -> Option<Aabb> {
+    /// Returns non if there is no glyph that can be assigned to the char (e.g. for space), or if it does not
+    /// fit into the atlas anymore.
+    fn get_glyph_atlas_uv_or_rasterize(&mut self, key: GlyphKey) -> Option<(u32, Aabb)> {
         if let Some(glyph) = self.glyphs.get_mut(&key) {
-            return Some(glyph.atlas_uv);
+            glyph.last_used = self.frame;
+            return Some((glyph.page, glyph.atlas_uv));
         }
 
         let font = key.font;
@@ -98,46 +268,133 @@ impl FontCache {
             return None;
         }
 
-        // pixel padding in texture atlas around rasterized fonts
-        const PAD_PX: usize = 2;
         // find some space where to put this glyph
-        let allocation = self
-            .atlas_allocator
-            .allocate(etagere::size2(
-                (metrics.width + 2 * PAD_PX) as i32,
-                (metrics.height + 2 * PAD_PX) as i32,
-            ))
-            .expect("Allocation in atlas allocator failed!");
-        let offset_in_atlas = ivec2(
-            allocation.rectangle.min.x + PAD_PX as i32,
-            allocation.rectangle.min.y + PAD_PX as i32,
-        );
-        let uv = Aabb::new(
-            offset_in_atlas.x as f32 / ATLAS_SIZE as f32,
-            offset_in_atlas.y as f32 / ATLAS_SIZE as f32,
-            (offset_in_atlas.x + metrics.width as i32) as f32 / ATLAS_SIZE as f32,
-            (offset_in_atlas.y + metrics.height as i32) as f32 / ATLAS_SIZE as f32,
-        );
+        let Some((page, allocation)) = self.allocate(metrics.width, metrics.height) else {
+            log::warn!(
+                "glyph {:?} at size {} does not fit into the font atlas anymore",
+                key.char,
+                key.font_size.0
+            );
+            return None;
+        };
 
-        self.texture_writes.push(key.clone());
+        self.texture_writes.push(key);
         // store glyph:
-        let glyph = Glyph {
+        let mut glyph = Glyph {
             metrics,
             bitmap,
-            offset_in_atlas,
-            atlas_uv: uv,
-            _alloc_id: allocation.id,
+            page,
+            offset_in_atlas: IVec2::ZERO,
+            atlas_uv: Aabb::new(0.0, 0.0, 0.0, 0.0),
+            alloc_id: allocation.id,
+            last_used: self.frame,
         };
+        glyph.set_allocation(&allocation, self.pages[page as usize].allocator.size());
+        let uv = glyph.atlas_uv;
         self.glyphs.insert(key, glyph);
-        Some(uv)
+        Some((page, uv))
+    }
+
+    /// Finds space for a glyph of this size, evicting, growing and compacting the atlas if needed.
+    fn allocate(&mut self, width: usize, height: usize) -> Option<(u32, Allocation)> {
+        let size = padded_size(width, height);
+        if let Some(found) = self.try_allocate(size) {
+            return Some(found);
+        }
+        // 1. evict glyphs that were not used for a while.
+        let cold_before = self.frame.saturating_sub(self.settings.evict_after_frames);
+        if self.evict(|glyph| glyph.last_used < cold_before) > 0 {
+            if let Some(found) = self.try_allocate(size) {
+                return Some(found);
+            }
+        }
+        // 2. add a page.
+        if self.pages.len() < self.settings.max_pages {
+            self.pages.push(AtlasPage {
+                allocator: new_atlas_allocator(self.settings.page_size),
+                texture: None,
+            });
+            if let Some(found) = self.try_allocate(size) {
+                return Some(found);
+            }
+        }
+        // 3. evict everything not used this frame and pack the rest again, to get rid of fragmentation.
+        let frame = self.frame;
+        self.evict(|glyph| glyph.last_used < frame);
+        self.compact();
+        self.try_allocate(size)
+    }
+
+    fn try_allocate(&mut self, size: etagere::Size) -> Option<(u32, Allocation)> {
+        self.pages
+            .iter_mut()
+            .enumerate()
+            .find_map(|(i, page)| Some((i as u32, page.allocator.allocate(size)?)))
+    }
+
+    /// Removes all glyphs matching `f` from the atlas, returns how many.
+    fn evict(&mut self, f: impl Fn(&Glyph) -> bool) -> usize {
+        let before = self.glyphs.len();
+        let pages = &mut self.pages;
+        self.glyphs.retain(|_, glyph| {
+            let evict = f(glyph);
+            if evict {
+                pages[glyph.page as usize]
+                    .allocator
+                    .deallocate(glyph.alloc_id);
+            }
+            !evict
+        });
+        let evicted = before - self.glyphs.len();
+        if evicted > 0 {
+            self.evicted_glyphs += evicted;
+            self.atlas_generation += 1;
+        }
+        evicted
+    }
+
+    /// Packs all glyphs into the pages again, largest first, and uploads them to their new positions.
+    /// Text laid out earlier in the frame still points to the old positions, see `refresh_uvs`.
+    fn compact(&mut self) {
+        for page in self.pages.iter_mut() {
+            page.allocator.clear();
+        }
+        let mut keys: Vec<GlyphKey> = self.glyphs.keys().copied().collect();
+        keys.sort_by_key(|k| {
+            let m = &self.glyphs[k].metrics;
+            std::cmp::Reverse((m.height, m.width))
+        });
+        for key in keys {
+            let metrics = self.glyphs[&key].metrics;
+            match self.try_allocate(padded_size(metrics.width, metrics.height)) {
+                Some((page, allocation)) => {
+                    let page_size = self.pages[page as usize].allocator.size();
+                    let glyph = self.glyphs.get_mut(&key).unwrap();
+                    glyph.page = page;
+                    glyph.alloc_id = allocation.id;
+                    glyph.set_allocation(&allocation, page_size);
+                }
+                None => {
+                    self.glyphs.remove(&key);
+                    self.evicted_glyphs += 1;
+                }
+            }
+        }
+        self.texture_writes = self.glyphs.keys().copied().collect();
+        self.atlas_generation += 1;
+        self.compactions += 1;
     }
 
     /// if layout_font_size_px is None, the size at which the font was rasterized font is used for layout
+    ///
+    /// `max_width` and `max_height` of the `layout_settings` are the box the text is wrapped, clipped and aligned in,
+    /// see `TextLayoutOptions`. Its `wrap_style` is overwritten by the options.
     pub fn perform_text_layout<'a>(
         &'a mut self,
         texts: impl Iterator<Item = TextLayoutItem<'a>>, // this is a bit leaky because it should be an iterator over strings instead, but should be fine for now.
         layout_settings: &LayoutSettings,
         font: Option<Ptr<Font>>,
+        options: TextLayoutOptions,
     ) -> TextLayoutResult {
         // Note: (layout_settings.x, layout_settings.y) is the top left corner where the text starts.
         let font = font.unwrap_or_else(|| self.default_font.ptr());
@@ -151,7 +408,13 @@ impl FontCache {
         }
 
         let mut layout: Layout<UserData> = Layout::new(CoordinateSystem::PositiveYDown);
-        layout.reset(layout_settings);
+        let mut wrapped_settings = layout_settings.clone();
+        match options.wrap {
+            TextWrap::Word => wrapped_settings.wrap_style = WrapStyle::Word,
+            TextWrap::Char => wrapped_settings.wrap_style = WrapStyle::Letter,
+            TextWrap::None => wrapped_settings.max_width = None,
+        }
+        layout.reset(&wrapped_settings);
 
         let mut space_sections: SmallVec<[Vec2; 2]> = smallvec![];
         let mut i: usize = 0;
@@ -198,11 +461,129 @@ impl FontCache {
             layout.append(&[font], &text_style);
         }
 
+        // glyphs with the index of their line, such that overflow and alignment can be handled per line.
+        #[derive(Clone, Copy)]
+        struct Placed {
+            line: usize,
+            x: f32,
+            y: f32,
+            width: f32,
+            height: f32,
+            char: char,
+            user_data: UserData,
+        }
+        let lines = layout.lines().map(|e| e.as_slice()).unwrap_or(&[]);
+        let mut placed: Vec<Placed> = vec![];
+        for (line, line_pos) in lines.iter().enumerate() {
+            for glyph_pos in &layout.glyphs()[line_pos.glyph_start..=line_pos.glyph_end] {
+                placed.push(Placed {
+                    line,
+                    x: glyph_pos.x,
+                    y: glyph_pos.y,
+                    width: glyph_pos.width as f32,
+                    height: glyph_pos.height as f32,
+                    char: glyph_pos.parent,
+                    user_data: glyph_pos.user_data,
+                });
+            }
+        }
+
+        // /////////////////////////////////////////////////////////////////////////////
+        // Overflow: drop glyphs outside of the box, and end the last visible line with an ellipsis
+        // /////////////////////////////////////////////////////////////////////////////
+
+        if options.overflow != TextOverflow::Visible {
+            let max_x = layout_settings.x + layout_settings.max_width.unwrap_or(f32::INFINITY);
+            let max_y = layout_settings.y + layout_settings.max_height.unwrap_or(f32::INFINITY);
+            let visible_lines = lines
+                .iter()
+                .take_while(|l| l.baseline_y - l.min_descent <= max_y)
+                .count()
+                // always show the first line, such that a too small box does not hide the text completely.
+                .max(1);
+            let is_visible = |p: &Placed| {
+                matches!(p.user_data, UserData::Space { .. })
+                    || (p.line < visible_lines && p.x + p.width <= max_x)
+            };
+            let overflows = placed.iter().any(|p| !is_visible(p));
+            placed.retain(is_visible);
+
+            if overflows && options.overflow == TextOverflow::Ellipsis {
+                let last_line = visible_lines - 1;
+                let last_text = placed
+                    .iter()
+                    .rev()
+                    .find(|p| p.line == last_line && matches!(p.user_data, UserData::Text { .. }))
+                    .copied();
+                if let Some(Placed {
+                    user_data: user_data @ UserData::Text { font_size, .. },
+                    ..
+                }) = last_text
+                {
+                    let px = font_size.0 as f32;
+                    // fall back to 3 dots for fonts without the ellipsis character.
+                    let (ellipsis, count) = match font.lookup_glyph_index('…') {
+                        0 => ('.', 3),
+                        _ => ('…', 1),
+                    };
+                    let metrics = font.metrics(ellipsis, px);
+                    let ellipsis_width = metrics.advance_width * count as f32;
+                    // remove glyphs from the end of the last line until the ellipsis fits.
+                    while let Some(last) = placed.iter().rposition(|p| {
+                        p.line == last_line && matches!(p.user_data, UserData::Text { .. })
+                    }) {
+                        let p = placed[last];
+                        if p.x + p.width + ellipsis_width <= max_x && !p.char.is_whitespace() {
+                            break;
+                        }
+                        placed.remove(last);
+                    }
+                    let start_x = placed
+                        .iter()
+                        .filter(|p| p.line == last_line)
+                        .map(|p| p.x + font.metrics(p.char, px).advance_width)
+                        .fold(layout_settings.x, f32::max);
+                    let baseline_y = lines[last_line].baseline_y;
+                    for n in 0..count {
+                        placed.push(Placed {
+                            line: last_line,
+                            x: start_x + metrics.advance_width * n as f32 + metrics.xmin as f32,
+                            y: baseline_y - (metrics.height as i32 + metrics.ymin) as f32,
+                            width: metrics.width as f32,
+                            height: metrics.height as f32,
+                            char: ellipsis,
+                            user_data,
+                        });
+                    }
+                }
+            }
+        }
+
+        // /////////////////////////////////////////////////////////////////////////////
+        // Alignment of each line inside of the text block (the widest line)
+        // /////////////////////////////////////////////////////////////////////////////
+
+        let align_factor = match options.align_x {
+            Align::Start => 0.0,
+            Align::Center => 0.5,
+            Align::End => 1.0,
+        };
+        if align_factor != 0.0 {
+            let mut line_right: Vec<f32> = vec![layout_settings.x; lines.len()];
+            for p in placed.iter() {
+                line_right[p.line] = line_right[p.line].max(p.x + p.width);
+            }
+            let block_right = line_right.iter().copied().fold(layout_settings.x, f32::max);
+            for p in placed.iter_mut() {
+                p.x += (block_right - line_right[p.line]) * align_factor;
+            }
+        }
+
         let mut layouted_glyphs: Vec<LayoutedGlyph> = vec![];
         let mut max_x: f32 = layout_settings.x; // top left corner
         let mut max_y: f32 = layout_settings.y; // top left corner
 
-        for glyph_pos in layout.glyphs() {
+        for glyph_pos in placed {
             let (font_size, color) = match glyph_pos.user_data {
                 UserData::Text { font_size, color } => (font_size, color),
                 UserData::Space { i, minus_y } => {
@@ -214,32 +595,40 @@ impl FontCache {
                 }
             };
 
+            // the glyph is laid out at font_size, but rasterized at the physical size.
             let key = GlyphKey {
                 font,
-                font_size,
-                char: glyph_pos.parent,
+                font_size: FontSize((font_size.0 as f32 * self.raster_scale).round() as u32),
+                char: glyph_pos.char,
             };
 
-            let Some(uv) = self.get_glyph_atlas_uv_or_rasterize(key) else {
+            let Some((page, uv)) = self.get_glyph_atlas_uv_or_rasterize(key) else {
                 // empty character, or unknown character, just skip// todo!(warn user if non empty cahr skipped)
                 continue;
             };
 
-            max_x = max_x.max(glyph_pos.x + glyph_pos.width as f32);
-            max_y = max_y.max(glyph_pos.y + glyph_pos.height as f32);
+            max_x = max_x.max(glyph_pos.x + glyph_pos.width);
+            max_y = max_y.max(glyph_pos.y + glyph_pos.height);
 
             let bounds = Aabb::new(
                 glyph_pos.x,
                 glyph_pos.y,
-                glyph_pos.x + glyph_pos.width as f32,
-                glyph_pos.y + glyph_pos.height as f32,
+                glyph_pos.x + glyph_pos.width,
+                glyph_pos.y + glyph_pos.height,
             );
 
-            layouted_glyphs.push(LayoutedGlyph { bounds, uv, color });
+            layouted_glyphs.push(LayoutedGlyph {
+                bounds,
+                uv,
+                page,
+                color,
+                key,
+            });
         }
 
         TextLayoutResult {
             layouted_glyphs,
+            atlas_generation: self.atlas_generation,
             space_sections,
             total_rect: Rect::new(
                 layout_settings.x,
@@ -251,6 +640,47 @@ impl FontCache {
     }
 }
 
+/// pixel padding in texture atlas around rasterized fonts
+const PAD_PX: usize = 2;
+
+fn padded_size(width: usize, height: usize) -> etagere::Size {
+    etagere::size2((width + 2 * PAD_PX) as i32, (height + 2 * PAD_PX) as i32)
+}
+
+fn new_atlas_allocator(page_size: u32) -> AtlasAllocator {
+    AtlasAllocator::new(etagere::size2(page_size as i32, page_size as i32))
+}
+
+fn create_atlas_texture(ctx: &GraphicsContext, size: u32) -> BindableTexture {
+    let image = RgbaImage::new(size, size);
+    let atlas_texture = Texture::from_image(&ctx.device, &ctx.queue, &image);
+    BindableTexture::new(&ctx.device, atlas_texture)
+}
+
+impl GpuRecreate for FontCache {
+    /// Recreates the atlas textures in place (such that `Ptr`s to them stay valid) and uploads all glyphs again.
+    fn gpu_recreated(&mut self, ctx: &GraphicsContext, event: GpuRecreated) {
+        if !event.device_lost {
+            return;
+        }
+        for page in self.pages.iter_mut() {
+            if let Some(texture) = &mut page.texture {
+                **texture = create_atlas_texture(ctx, page.allocator.size().width as u32);
+            }
+        }
+        self.texture_writes = self.glyphs.keys().copied().collect();
+    }
+}
+
+/// How text is wrapped, clipped and aligned in `FontCache::perform_text_layout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TextLayoutOptions {
+    pub wrap: TextWrap,
+    pub overflow: TextOverflow,
+    /// alignment of the lines relative to each other.
+    pub align_x: Align,
+}
+
 pub enum TextLayoutItem<'a> {
     Text(&'a TextSection),
     // whole point of this is that we want to embed non-text divs, e.g. small images into the flow of the text.
@@ -261,7 +691,7 @@ pub enum TextLayoutItem<'a> {
     },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct GlyphKey {
     font: Ptr<fontdue::Font>,
     font_size: FontSize,
@@ -293,21 +723,43 @@ impl From<FontSize> for f32 {
 }
 
 struct Glyph {
-    /// currently not used, but could be used to deallocate the glyph from the shelf atlas.
-    _alloc_id: AllocId,
+    /// to deallocate the glyph from the atlas page when it is evicted.
+    alloc_id: AllocId,
     metrics: fontdue::Metrics,
     bitmap: Vec<u8>,
+    page: u32,
     /// minx and miny in px in the atlas texture.
     offset_in_atlas: IVec2,
     /// UV coordinates in the text atlas texture (always in range 0.0 to 1.0)
     atlas_uv: Aabb,
+    /// frame of the `FontCache` in which the glyph was last laid out.
+    last_used: u64,
+}
+
+impl Glyph {
+    fn set_allocation(&mut self, allocation: &Allocation, page_size: etagere::Size) {
+        self.offset_in_atlas = ivec2(
+            allocation.rectangle.min.x + PAD_PX as i32,
+            allocation.rectangle.min.y + PAD_PX as i32,
+        );
+        let (w, h) = (page_size.width as f32, page_size.height as f32);
+        self.atlas_uv = Aabb::new(
+            self.offset_in_atlas.x as f32 / w,
+            self.offset_in_atlas.y as f32 / h,
+            (self.offset_in_atlas.x + self.metrics.width as i32) as f32 / w,
+            (self.offset_in_atlas.y + self.metrics.height as i32) as f32 / h,
+        );
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct LayoutedGlyph {
     pub bounds: Aabb,
     pub uv: Aabb,
+    /// the atlas page that `uv` refers to.
+    pub page: u32,
     pub color: Color,
+    pub key: GlyphKey,
 }
 
 #[derive(Debug)]
@@ -315,6 +767,8 @@ pub struct TextLayoutResult {
     /// glyph position and their uv position in the texture atlas
     /// Todo! make pos a rect instead, because it is easier to add to it.
     pub layouted_glyphs: Vec<LayoutedGlyph>,
+    /// `FontCache::atlas_generation` at the time of the layout.
+    pub atlas_generation: u64,
     // total bounding rect of the text. Can be used e.g. for centering all of the glyphs by shifting them by half the size or so.
     pub total_rect: Rect,
     /// sections of explicitly inserted space inside of the text. This is for spans that are part of the text, so to say, even though they might contain e.g. Icons.
@@ -357,3 +811,130 @@ fn glyph_to_rgba_image(glyph: &Glyph) -> RgbaImage {
     }
     image
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(fonts: &mut FontCache, text: &str, size: u32) -> TextLayoutResult {
+        layout_with(fonts, text, size, None, TextLayoutOptions::default())
+    }
+
+    fn layout_with(
+        fonts: &mut FontCache,
+        text: &str,
+        size: u32,
+        max_width: Option<f32>,
+        options: TextLayoutOptions,
+    ) -> TextLayoutResult {
+        let section = TextSection {
+            color: Color::WHITE,
+            string: text.to_string().into(),
+            size: FontSize(size),
+        };
+        let settings = LayoutSettings {
+            max_width,
+            ..Default::default()
+        };
+        fonts.perform_text_layout(
+            std::iter::once(TextLayoutItem::Text(&section)),
+            &settings,
+            None,
+            options,
+        )
+    }
+
+    #[test]
+    fn eviction_and_growth() {
+        let mut fonts = FontCache::headless_with_settings(GlyphCacheSettings {
+            page_size: 512,
+            max_pages: 2,
+            evict_after_frames: 20,
+        });
+        let text = "abcdefghijklmnopqrstuvwxyz";
+        // every frame new glyphs (other sizes), way more than fit into 2 pages.
+        for size in 10..50 {
+            fonts.begin_frame();
+            let result = layout(&mut fonts, text, size);
+            assert_eq!(result.layouted_glyphs.len(), text.len());
+        }
+        let metrics = fonts.metrics();
+        assert_eq!(metrics.pages, 2);
+        assert!(metrics.evicted_glyphs > 0);
+        assert!(metrics.occupancy > 0.0 && metrics.occupancy <= 1.0);
+
+        // a cached layout is outdated after eviction, but touching keeps its glyphs alive.
+        let kept = layout(&mut fonts, "xyz", 20);
+        for _ in 0..10 {
+            fonts.begin_frame();
+            fonts.touch(&kept);
+            layout(&mut fonts, text, 30);
+        }
+        assert!(kept
+            .layouted_glyphs
+            .iter()
+            .all(|g| fonts.glyphs.contains_key(&g.key)));
+    }
+
+    #[test]
+    fn compaction_refreshes_uvs() {
+        let mut fonts = FontCache::headless_with_settings(GlyphCacheSettings {
+            page_size: 256,
+            max_pages: 1,
+            evict_after_frames: 1000,
+        });
+        fonts.begin_frame();
+        let mut earlier = layout(&mut fonts, "abc", 20);
+        // the atlas runs full in the same frame, so it is compacted.
+        for size in 21..80 {
+            layout(&mut fonts, "abcdefghijklmnopqrstuvwxyz", size);
+            if fonts.metrics().compactions > 0 {
+                break;
+            }
+        }
+        assert!(fonts.metrics().compactions > 0);
+        assert!(!fonts.is_layout_current(&earlier));
+
+        fonts.refresh_uvs(&mut earlier);
+        assert!(fonts.is_layout_current(&earlier));
+        assert_eq!(earlier.layouted_glyphs.len(), 3);
+        for layouted in earlier.layouted_glyphs.iter() {
+            let glyph = &fonts.glyphs[&layouted.key];
+            assert_eq!(layouted.page, glyph.page);
+            assert_eq!(
+                bytemuck::bytes_of(&layouted.uv),
+                bytemuck::bytes_of(&glyph.atlas_uv)
+            );
+        }
+    }
+
+    #[test]
+    fn overflow_and_alignment() {
+        let mut fonts = FontCache::headless();
+        let text = "a long line of text that does not fit";
+        let ellipsis = TextLayoutOptions {
+            wrap: TextWrap::None,
+            overflow: TextOverflow::Ellipsis,
+            ..Default::default()
+        };
+        let result = layout_with(&mut fonts, text, 24, Some(100.0), ellipsis);
+        assert!(result.total_rect.width <= 100.0);
+        assert!(matches!(
+            result.layouted_glyphs.last().unwrap().key.char,
+            '…' | '.'
+        ));
+
+        let wrapped = layout_with(&mut fonts, text, 24, Some(100.0), Default::default());
+        assert!(wrapped.total_rect.height > result.total_rect.height);
+
+        // the short first line is moved to the right end of the block.
+        let end = TextLayoutOptions {
+            align_x: Align::End,
+            ..Default::default()
+        };
+        let left = layout_with(&mut fonts, "ab\nabcdefgh", 24, None, Default::default());
+        let right = layout_with(&mut fonts, "ab\nabcdefgh", 24, None, end);
+        assert!(right.layouted_glyphs[0].bounds.min_x > left.layouted_glyphs[0].bounds.min_x);
+        assert_eq!(left.total_rect.width, right.total_rect.width);
+    }
+}
@@ -3,15 +3,28 @@ pub mod batching;
 mod board;
 pub use board::{
     Align, AsDivId, Axis, Board, BoardInput, BoardPhase, BorderRadius, Div, DivId, DivStyle,
-    DivTexture, HotActive, Id, Len, MainAlign, Padding, Response, Span, Text, TextSection,
-    UnboundDivId,
+    DivTexture, Gradient, HotActive, Id, LayoutStats, Len, MainAlign, Padding, Response, Span,
+    Text, TextOverflow, TextSection, TextWrap, UnboundDivId,
 };
 
+mod navigation;
+pub use navigation::{FocusRing, NavDirection, NavInput};
+
+mod sounds;
+pub use sounds::{Theme, UiSoundEvent, UiSoundTrigger, UiSounds};
+
 mod font_cache;
-pub use font_cache::{FontCache, FontSize};
+pub use font_cache::{FontCache, FontSize, GlyphCacheMetrics, GlyphCacheSettings};
 
 mod ui_renderer;
 pub use ui_renderer::UiRenderer;
 
+mod test_harness;
+pub use test_harness::BoardTestHarness;
+
 mod widgets;
-pub use widgets::{h_fill, next_hot_active, v_fill, Button, Slider, Widget};
+pub use widgets::{
+    h_fill, next_hot_active, v_fill, Button, ColorPicker, ColorPickerResponse, ColorPickerState,
+    ProgressBar, Separator, Slider, Tabs, TabsResponse, TextEdit, TextEditResponse, VirtualList,
+    VirtualListResponse, VirtualListState, Widget,
+};
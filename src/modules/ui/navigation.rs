@@ -0,0 +1,166 @@
+//! Keyboard and gamepad navigation between focusable widgets of a `Board`, see `Response::focusable`.
+
+use winit::keyboard::KeyCode;
+
+use crate::{
+    elements::{rect::Aabb, Color},
+    modules::Input,
+};
+
+use super::Id;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NavDirection {
+    /// the next focusable widget in the order they were added, e.g. Tab.
+    Next,
+    /// e.g. Shift + Tab.
+    Previous,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Navigation requested this frame, part of the `BoardInput`.
+///
+/// `from_input` maps Tab, Shift + Tab, the arrow keys and Enter/Space. For gamepads, set the direction from the d-pad
+/// and `confirm` from the a button.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NavInput {
+    pub direction: Option<NavDirection>,
+    /// activates the focused widget, see `Response::activated`.
+    pub confirm: bool,
+}
+
+impl NavInput {
+    pub fn from_input(input: &Input) -> Self {
+        let keys = input.keys();
+        let direction = if keys.just_pressed(KeyCode::Tab) {
+            if input.modifiers().shift_key() {
+                Some(NavDirection::Previous)
+            } else {
+                Some(NavDirection::Next)
+            }
+        } else if keys.just_pressed(KeyCode::ArrowUp) {
+            Some(NavDirection::Up)
+        } else if keys.just_pressed(KeyCode::ArrowDown) {
+            Some(NavDirection::Down)
+        } else if keys.just_pressed(KeyCode::ArrowLeft) {
+            Some(NavDirection::Left)
+        } else if keys.just_pressed(KeyCode::ArrowRight) {
+            Some(NavDirection::Right)
+        } else {
+            None
+        };
+        NavInput {
+            direction,
+            confirm: keys.just_pressed(KeyCode::Enter) || keys.just_pressed(KeyCode::Space),
+        }
+    }
+}
+
+/// Border drawn around the focused widget, on top of its own border. Uses the border radius of the widget.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FocusRing {
+    pub color: Color,
+    pub thickness: f32,
+    /// the ring is this many pixels bigger than the widget on each side.
+    pub offset: f32,
+}
+
+impl Default for FocusRing {
+    fn default() -> Self {
+        FocusRing {
+            color: Color::from_hex("#f5c542"),
+            thickness: 2.0,
+            offset: 3.0,
+        }
+    }
+}
+
+/// Returns the widget that gets the focus. `focusables` are the focusable widgets of the last frame in the order
+/// they were added, with their rects.
+///
+/// Next and Previous wrap around. The arrows go to the closest widget in that direction, widgets that are offset
+/// sideways count as further away. Without a focused widget, any direction focuses the first one (the last one for
+/// Previous).
+pub fn navigate(
+    focusables: &[(Id, Aabb)],
+    focused: Option<Id>,
+    direction: NavDirection,
+) -> Option<Id> {
+    if focusables.is_empty() {
+        return None;
+    }
+    let current = focused.and_then(|id| focusables.iter().position(|(e, _)| *e == id));
+    let Some(current) = current else {
+        return match direction {
+            NavDirection::Previous => focusables.last().map(|e| e.0),
+            _ => Some(focusables[0].0),
+        };
+    };
+
+    let len = focusables.len();
+    let center = |aabb: &Aabb| {
+        (
+            (aabb.min_x + aabb.max_x) * 0.5,
+            (aabb.min_y + aabb.max_y) * 0.5,
+        )
+    };
+    let (x, y) = center(&focusables[current].1);
+    let next = match direction {
+        NavDirection::Next => (current + 1) % len,
+        NavDirection::Previous => (current + len - 1) % len,
+        _ => {
+            let mut best: Option<(usize, f32)> = None;
+            for (i, (_, aabb)) in focusables.iter().enumerate() {
+                let (cx, cy) = center(aabb);
+                let (forward, sideways) = match direction {
+                    NavDirection::Up => (y - cy, cx - x),
+                    NavDirection::Down => (cy - y, cx - x),
+                    NavDirection::Left => (x - cx, cy - y),
+                    NavDirection::Right => (cx - x, cy - y),
+                    _ => unreachable!(),
+                };
+                if i == current || forward <= 0.0 {
+                    continue;
+                }
+                let score = forward + sideways.abs() * 2.0;
+                if best.map_or(true, |(_, best_score)| score < best_score) {
+                    best = Some((i, score));
+                }
+            }
+            match best {
+                Some((i, _)) => i,
+                None => current,
+            }
+        }
+    };
+    Some(focusables[next].0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arrows_and_tab() {
+        // a 2x2 grid of widgets, added row by row:
+        let rect = |x: f32, y: f32| Aabb::new(x, y, x + 100.0, y + 40.0);
+        let focusables = [
+            (Id(1), rect(0.0, 0.0)),
+            (Id(2), rect(120.0, 0.0)),
+            (Id(3), rect(0.0, 60.0)),
+            (Id(4), rect(120.0, 60.0)),
+        ];
+        let nav = |focused: u64, direction| navigate(&focusables, Some(Id(focused)), direction);
+        assert_eq!(navigate(&focusables, None, NavDirection::Down), Some(Id(1)));
+        assert_eq!(nav(1, NavDirection::Right), Some(Id(2)));
+        assert_eq!(nav(2, NavDirection::Down), Some(Id(4)));
+        assert_eq!(nav(4, NavDirection::Left), Some(Id(3)));
+        // nothing further down, the focus stays:
+        assert_eq!(nav(3, NavDirection::Down), Some(Id(3)));
+        assert_eq!(nav(4, NavDirection::Next), Some(Id(1)));
+        assert_eq!(nav(1, NavDirection::Previous), Some(Id(4)));
+    }
+}
@@ -0,0 +1,60 @@
+//! Sounds for ui feedback, configured in the `Theme` of a board. The board collects `UiSoundEvent`s while the
+//! widgets are added and `Audio::play_ui_sounds` plays them on the ui bus, such that its volume applies.
+
+use crate::modules::Sound;
+
+use super::{HotActive, Id};
+
+/// Settings shared by all widgets of a board, see `Board::theme_mut`.
+#[derive(Debug, Clone, Default)]
+pub struct Theme {
+    pub sounds: UiSounds,
+}
+
+/// Sounds for the hot-active transitions of widgets (see `Board::set_hot_active`). None plays no sound.
+#[derive(Debug, Clone, Default)]
+pub struct UiSounds {
+    /// the mouse enters a widget.
+    pub hover: Option<Sound>,
+    /// a widget is pressed.
+    pub press: Option<Sound>,
+    /// a pressed widget is released, e.g. a button is clicked.
+    pub release: Option<Sound>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiSoundTrigger {
+    Hover,
+    Press,
+    Release,
+}
+
+#[derive(Debug, Clone)]
+pub struct UiSoundEvent {
+    /// the widget that changed its hot-active state.
+    pub id: Id,
+    pub trigger: UiSoundTrigger,
+    pub sound: Sound,
+}
+
+impl UiSoundTrigger {
+    /// The trigger for the transition of a widget from `old` to `new`, if any.
+    pub fn for_transition(old: HotActive, new: HotActive) -> Option<UiSoundTrigger> {
+        match (old, new) {
+            (HotActive::Nil, HotActive::Hot) => Some(UiSoundTrigger::Hover),
+            (HotActive::Nil | HotActive::Hot, HotActive::Active) => Some(UiSoundTrigger::Press),
+            (HotActive::Active, HotActive::Nil | HotActive::Hot) => Some(UiSoundTrigger::Release),
+            _ => None,
+        }
+    }
+}
+
+impl UiSounds {
+    pub fn get(&self, trigger: UiSoundTrigger) -> Option<&Sound> {
+        match trigger {
+            UiSoundTrigger::Hover => self.hover.as_ref(),
+            UiSoundTrigger::Press => self.press.as_ref(),
+            UiSoundTrigger::Release => self.release.as_ref(),
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use glam::{vec2, DVec2, Vec2};
+
+use crate::modules::{
+    input::{InputEvent, InputRecording, MouseButton},
+    Input,
+};
+
+use super::{Board, BoardInput, FontCache};
+
+/// Runs a `Board` without window and gpu, driven by synthetic or recorded `InputEvent`s, such that widget
+/// interactions can be tested. Only the board and the `Input` run: there is no `App` and no `DefaultModules`, so
+/// shortcuts, egui and the rest of the frame loop are not part of it (see todo.md).
+///
+/// ```rust,ignore
+/// let mut harness = BoardTestHarness::new(dvec2(800.0, 600.0));
+/// let build = |board: &mut Board| board.add(Button::default(), "btn", None).clicked;
+/// harness.frame(&[], build); // first frame, lays out the button
+/// let clicked = harness.click(vec2(10.0, 10.0), build);
+/// assert!(clicked);
+/// ```
+///
+/// Hit testing uses the layout of the previous frame, so a widget needs to be added once before it can be interacted with.
+/// The board size is in window pixels, a scale factor of 1.0 is assumed.
+pub struct BoardTestHarness {
+    pub input: Input,
+    pub board: Board,
+    pub fonts: FontCache,
+    pub size: DVec2,
+    frame: usize,
+}
+
+impl BoardTestHarness {
+    pub fn new(size: DVec2) -> Self {
+        BoardTestHarness {
+            input: Input::new(),
+            board: Board::new(size),
+            fonts: FontCache::headless(),
+            size,
+            frame: 0,
+        }
+    }
+
+    /// Number of frames run so far.
+    pub fn frame_count(&self) -> usize {
+        self.frame
+    }
+
+    /// Runs one frame: feeds the events into the input, calls `build` to add the widgets to the board and lays it out.
+    pub fn frame<R>(&mut self, events: &[InputEvent], build: impl FnOnce(&mut Board) -> R) -> R {
+        for event in events {
+            self.input.receive_input_event(event);
+        }
+        self.board
+            .start_frame(BoardInput::from_input_module(&self.input), self.size);
+        let result = build(&mut self.board);
+        self.fonts.begin_frame();
+        self.board.end_frame(&mut self.fonts);
+        self.input.end_frame();
+        self.frame += 1;
+        result
+    }
+
+    /// Runs one frame per recorded frame and returns the results of `build` for each of them.
+    pub fn replay<R>(
+        &mut self,
+        recording: &InputRecording,
+        mut build: impl FnMut(&mut Board) -> R,
+    ) -> Vec<R> {
+        recording
+            .frames
+            .iter()
+            .map(|events| self.frame(events, &mut build))
+            .collect()
+    }
+
+    /// Moves the cursor to `pos` in one frame (widgets usually need to be hovered before they can be pressed),
+    /// presses the left mouse button in the next one and releases it in the third one.
+    /// Returns the result of `build` in the frame of the release.
+    pub fn click<R>(&mut self, pos: Vec2, mut build: impl FnMut(&mut Board) -> R) -> R {
+        self.frame(&[InputEvent::CursorMoved(pos)], &mut build);
+        let press = [InputEvent::MouseButton {
+            button: MouseButton::Left,
+            pressed: true,
+        }];
+        self.frame(&press, &mut build);
+        let release = [InputEvent::MouseButton {
+            button: MouseButton::Left,
+            pressed: false,
+        }];
+        self.frame(&release, &mut build)
+    }
+
+    /// Moves the cursor without pressing anything, e.g. to hover a widget.
+    pub fn hover<R>(&mut self, pos: Vec2, build: impl FnOnce(&mut Board) -> R) -> R {
+        self.frame(&[InputEvent::CursorMoved(pos)], build)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::dvec2;
+    use winit::keyboard::KeyCode;
+
+    use crate::modules::input::InputCapture;
+
+    use crate::{
+        elements::Color,
+        modules::{
+            ui::{
+                Button, ColorPicker, ColorPickerState, Id, Tabs, TextEdit, UiSoundTrigger,
+                UiSounds, VirtualList, VirtualListState,
+            },
+            Sound,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn button_click() {
+        let mut harness = BoardTestHarness::new(dvec2(800.0, 600.0));
+        let build = |board: &mut Board| board.add(Button::default(), "button", None).clicked;
+        assert!(!harness.frame(&[], build));
+        // the button is 200 px wide at the top left corner:
+        assert!(harness.click(vec2(20.0, 20.0), build));
+        assert!(!harness.click(vec2(600.0, 500.0), build));
+        // an egui window over the button takes the click:
+        harness.input.set_capture(InputCapture {
+            pointer: true,
+            keyboard: false,
+        });
+        assert!(!harness.click(vec2(20.0, 20.0), build));
+    }
+
+    #[test]
+    fn text_edit_typing() {
+        let mut harness = BoardTestHarness::new(dvec2(800.0, 600.0));
+        let mut value = String::new();
+        let mut build =
+            |board: &mut Board| board.add(TextEdit::new(&mut value), "edit", None).focused;
+        harness.frame(&[], &mut build);
+        assert!(harness.click(vec2(20.0, 10.0), &mut build));
+        harness.frame(&[InputEvent::Text("hello".into())], &mut build);
+        harness.frame(
+            &InputEvent::key_click(winit::keyboard::KeyCode::Backspace),
+            &mut build,
+        );
+        drop(build);
+        assert_eq!(value, "hell");
+    }
+
+    #[test]
+    fn text_edit_ids_do_not_collide() {
+        let mut harness = BoardTestHarness::new(dvec2(800.0, 600.0));
+        let mut a = String::from("a");
+        let mut b = String::from("b");
+        harness.frame(&[], |board| {
+            board.add(TextEdit::new(&mut a), Id(1), None);
+            board.add(TextEdit::new(&mut b), Id(2), None);
+        });
+        // a field and a text div each.
+        assert_eq!(harness.board.layout_stats().divs, 4);
+    }
+
+    #[test]
+    fn widgets_layout_snapshot() {
+        let mut harness = BoardTestHarness::new(dvec2(800.0, 600.0));
+        let mut selected = 1;
+        harness.frame(&[], |board| {
+            board.add(Tabs::new(&mut selected, ["A", "B"]), "tabs", None);
+            board.add(Button::default(), "button", None);
+        });
+        let snapshots = crate::utils::Snapshots::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/modules/ui/snapshots"
+        ));
+        snapshots.assert_text("widgets_layout", &harness.board.layout_snapshot());
+    }
+
+    #[test]
+    fn tabs_select_on_click() {
+        let mut harness = BoardTestHarness::new(dvec2(800.0, 600.0));
+        let mut selected = 0;
+        let mut build = |board: &mut Board| {
+            board
+                .add(Tabs::new(&mut selected, ["A", "B"]), "tabs", None)
+                .selected
+        };
+        assert_eq!(harness.frame(&[], &mut build), 0);
+        // the tabs are 400 px wide together, the second one starts at 200 px:
+        assert_eq!(harness.click(vec2(300.0, 10.0), &mut build), 1);
+        assert_eq!(harness.click(vec2(100.0, 10.0), &mut build), 0);
+    }
+
+    #[test]
+    fn color_picker_square_sets_saturation_and_value() {
+        let mut harness = BoardTestHarness::new(dvec2(800.0, 600.0));
+        let mut color = Color::WHITE;
+        let mut state = ColorPickerState::default();
+        let mut build = |board: &mut Board| {
+            board
+                .add(ColorPicker::new(&mut color, &mut state), "picker", None)
+                .color
+        };
+        harness.frame(&[], &mut build);
+        // the gradients are drawn by a few layers, not by one div per cell.
+        assert!(harness.board.iter_divs().count() < 40);
+        // the top right corner of the square is full saturation and value, the hue of white is 0 (red).
+        let picked = harness.click(vec2(149.0, 7.0), &mut build);
+        let [hue, saturation, value] = picked.to_hsv();
+        assert!(hue < 1.0 && saturation > 0.95 && value > 0.95, "{picked:?}");
+    }
+
+    #[test]
+    fn virtual_list_adds_only_visible_rows() {
+        let mut harness = BoardTestHarness::new(dvec2(800.0, 600.0));
+        let mut state = VirtualListState::default();
+        let mut build = |board: &mut Board| {
+            let list = VirtualList::new(&mut state, 10_000, 200.0, |_| 20.0, |_, _, _| {});
+            board.add(list, "list", None).visible_rows
+        };
+        assert_eq!(harness.frame(&[], &mut build), 0..10);
+        // the list, the rows and the scrollbar:
+        assert_eq!(harness.board.iter_divs().count(), 12);
+        harness.frame(&[InputEvent::CursorMoved(vec2(20.0, 20.0))], &mut build);
+        // scrolling down by 3 lines moves 120 px, 6 rows:
+        assert_eq!(
+            harness.frame(&[InputEvent::Scroll(-3.0)], &mut build),
+            6..16
+        );
+    }
+
+    #[test]
+    fn keyboard_navigation_activates_buttons() {
+        let mut harness = BoardTestHarness::new(dvec2(800.0, 600.0));
+        let build = |board: &mut Board| {
+            let column = board.add_div("column", None).id;
+            let a = board.add(Button::default(), "a", Some(column)).clicked;
+            let b = board.add(Button::default(), "b", Some(column)).clicked;
+            (a, b)
+        };
+        harness.frame(&[], build);
+        harness.frame(&InputEvent::key_click(KeyCode::Tab), build);
+        assert_eq!(harness.board.nav_focused(), Some(Id::from("a")));
+        harness.frame(&InputEvent::key_click(KeyCode::ArrowDown), build);
+        assert_eq!(harness.board.nav_focused(), Some(Id::from("b")));
+        let clicked = harness.frame(&InputEvent::key_click(KeyCode::Enter), build);
+        assert_eq!(clicked, (false, true));
+    }
+
+    #[test]
+    fn button_sounds() {
+        let mut harness = BoardTestHarness::new(dvec2(800.0, 600.0));
+        let sound = Sound::from_samples(vec![0i16; 64], 1, 44100);
+        harness.board.theme_mut().sounds = UiSounds {
+            hover: Some(sound.clone()),
+            press: None,
+            release: Some(sound),
+        };
+        let build = |board: &mut Board| {
+            board.add(Button::default(), "button", None);
+            board
+                .sound_events()
+                .iter()
+                .map(|e| e.trigger)
+                .collect::<Vec<_>>()
+        };
+        harness.frame(&[], build);
+        assert_eq!(
+            harness.hover(vec2(20.0, 20.0), build),
+            [UiSoundTrigger::Hover]
+        );
+        assert_eq!(
+            harness.click(vec2(20.0, 20.0), build),
+            [UiSoundTrigger::Release]
+        );
+    }
+}
@@ -18,8 +18,10 @@ struct RectInstance {
     @location(1) color: vec4<f32>,
     @location(2) border_radius: vec4<f32>,
     @location(3) border_color: vec4<f32>,
-    // border_thickness, border_softness, _unused, _unused
+    // border_thickness, border_softness, gradient_axis (0 none, 1 x, 2 y), _unused
     @location(4) others: vec4<f32>,
+    // the color at the end of the gradient axis
+    @location(5) gradient_color: vec4<f32>,
 }
 
 struct TexturedRectInstance {
@@ -29,8 +31,10 @@ struct TexturedRectInstance {
     @location(3) border_color: vec4<f32>,
     // border_thickness, border_softness, _unused, _unused
     @location(4) others: vec4<f32>,
+    // gradients are not supported for textured rects
+    @location(5) gradient_color: vec4<f32>,
     // for the texture
-    @location(5) uv: vec4<f32>,
+    @location(6) uv: vec4<f32>,
 }
 
 struct GlyphInstance {
@@ -96,7 +100,14 @@ fn rect_vs(
     out.offset = vertex.pos - center;
     out.size = instance.aabb.zw - instance.aabb.xy;
 
-    out.color = instance.color;
+    // the gradient is interpolated between the vertices: start color on the min side, end color on the max side.
+    var t = 0.0;
+    if instance.others.z == 1.0 {
+        t = select(0.0, 1.0, vertex.pos.x > center.x);
+    } else if instance.others.z == 2.0 {
+        t = select(0.0, 1.0, vertex.pos.y > center.y);
+    }
+    out.color = mix(instance.color, instance.gradient_color, t);
     out.border_radius = instance.border_radius;
     out.border_color = instance.border_color;
     out.others = instance.others;
@@ -110,7 +121,7 @@ fn rect_fs(in: RectVertexOutput) -> @location(0) vec4<f32> {
 
     let alpha = min(color.a, smoothstep(1.0, 0.0, sdf + 0.5)); // the + 0.5 makes the edge a bit smoother
     // return vec4(in.color.rgb, alpha);
-    return vec4(color.rgb, alpha);
+    return encode_surface(vec4(color.rgb, alpha));
 }
 
 
@@ -148,7 +159,7 @@ fn textured_rect_fs(in: TexturedRectVertexOutput) -> @location(0) vec4<f32> {
 
     let color: vec4<f32> = mix(image_color_final, in.border_color, smoothstep(0.0, 1.0, ((sdf + in.others[0]) / in.others[1]) ));
     // todo! add borders and other fancy stuff from above in rect_fs
-    return color;
+    return encode_surface(color);
 }
 
 @vertex
@@ -173,7 +184,19 @@ fn glyph_vs(
 fn glyph_fs(in: GlyphVertexOutput) -> @location(0) vec4<f32> {
     let image_color = textureSample(t_diffuse, s_diffuse, in.uv);
     let color = mix(image_color.rgb, image_color.rgb * in.color.rgb, in.color.a);
-    return vec4(color, image_color.a);
+    return encode_surface(vec4(color, image_color.a));
+}
+
+// `SURFACE_ENCODING` is prepended by the UiRenderer: 0 is srgb surface, 1 is unorm surface without srgb conversion,
+// 2 is hdr float surface.
+fn encode_surface(color: vec4<f32>) -> vec4<f32> {
+    if SURFACE_ENCODING == 1u {
+        let linear = clamp(color.rgb, vec3(0.0), vec3(1.0));
+        let lower = linear * 12.92;
+        let higher = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+        return vec4(select(higher, lower, linear <= vec3(0.0031308)), color.a);
+    }
+    return color;
 }
 
 // given some bounding box aabb [f32;4] being min x, min y, max x, max y,
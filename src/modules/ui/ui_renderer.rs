@@ -10,10 +10,14 @@ use wgpu::RenderPassDescriptor;
 use wgpu::ShaderModule;
 use wgpu::ShaderModuleDescriptor;
 
+use crate::elements::screen::set_viewport_and_scissor;
 use crate::elements::screen::ScreenGR;
 use crate::elements::texture::rgba_bind_group_layout;
 use crate::elements::BindableTexture;
 use crate::elements::GrowableBuffer;
+use crate::elements::Rect;
+use crate::elements::{rect::Aabb, Color};
+use crate::Ptr;
 
 use crate::modules::ui::board::BoardPhase;
 use crate::modules::GraphicsContext;
@@ -25,6 +29,7 @@ use crate::Prepare;
 use super::batching::get_batches;
 use super::batching::BatchRegion;
 use super::batching::BatchingResult;
+use super::batching::BatchingStats;
 use super::batching::GlyphRaw;
 use super::batching::RectRaw;
 use super::batching::RectRawTextured;
@@ -39,6 +44,8 @@ pub struct UiRenderer {
     // textured_rect_pipeline: wgpu::RenderPipeline,
     collected_batches: BatchingResult,
     draw_batches: Vec<BatchRegion>,
+    /// of the batches in `draw_batches`.
+    stats: BatchingStats,
     rect_buffer: GrowableBuffer<RectRaw>,
     textured_rect_buffer: GrowableBuffer<RectRawTextured>,
     glyph_buffer: GrowableBuffer<GlyphRaw>,
@@ -52,15 +59,22 @@ impl UiRenderer {
         let textured_rect_buffer = GrowableBuffer::new(device, 256, BufferUsages::VERTEX);
 
         let shader_watcher = None;
+        // unorm surfaces without srgb conversion need the shader to encode the colors, like the tonemapping does.
+        let wgsl = format!(
+            "const SURFACE_ENCODING: u32 = {}u;\n{}",
+            ctx.surface_encoding().shader_index(),
+            include_str!("ui.wgsl")
+        );
         let shader_module = ctx.device.create_shader_module(ShaderModuleDescriptor {
             label: Some("Ui Renderer Shaders"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("ui.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(wgsl.into()),
         });
 
-        let glyph_pipeline = create_glyph_pipeline(&shader_module, &ctx.device, screen);
-        let rect_pipeline = create_rect_pipeline(&shader_module, &ctx.device, screen);
+        let format = ctx.surface_format;
+        let glyph_pipeline = create_glyph_pipeline(&shader_module, &ctx.device, screen, format);
+        let rect_pipeline = create_rect_pipeline(&shader_module, &ctx.device, screen, format);
         let textured_rect_pipeline =
-            create_textured_rect_pipeline(&shader_module, &ctx.device, screen);
+            create_textured_rect_pipeline(&shader_module, &ctx.device, screen, format);
 
         UiRenderer {
             shader_watcher,
@@ -69,12 +83,18 @@ impl UiRenderer {
             textured_rect_pipeline,
             collected_batches: BatchingResult::new(),
             draw_batches: vec![],
+            stats: BatchingStats::default(),
             rect_buffer,
             textured_rect_buffer,
             glyph_buffer,
         }
     }
 
+    /// Draw calls and instances of the ui boards drawn in the last frame.
+    pub fn stats(&self) -> BatchingStats {
+        self.stats
+    }
+
     pub fn watch_shader_file(&mut self, path: &str) {
         self.shader_watcher = Some(ShaderFileWatcher::new(path));
     }
@@ -88,12 +108,25 @@ impl UiRenderer {
         self.collected_batches.combine(batches);
     }
 
+    /// Draws a textured rect in ui coordinates on top of the boards drawn so far, in the same pass after tone mapping.
+    pub fn draw_textured_rect(
+        &mut self,
+        pos: Aabb,
+        uv: Aabb,
+        color: Color,
+        texture: Ptr<BindableTexture>,
+    ) {
+        self.collected_batches
+            .push_textured_rect(pos, uv, color, texture);
+    }
+
     pub fn render<'e>(
         &'e self,
         encoder: &'e mut wgpu::CommandEncoder,
         view: &wgpu::TextureView,
         screen: &ScreenGR,
         fonts: &FontCache,
+        viewport: Rect,
     ) {
         let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
             label: Some("Ui Render Pass"),
@@ -111,12 +144,12 @@ impl UiRenderer {
         });
         assert!(self.collected_batches.is_empty()); // only information left should be in draw_batches.
                                                     // println!("render UiRenderer");
+        set_viewport_and_scissor(&mut render_pass, viewport);
         render_pass.set_bind_group(0, screen.bind_group(), &[]);
 
         // 6 indices to draw two triangles
 
         const VERTEX_COUNT: u32 = 6;
-        let atlas_texture = fonts.atlas_texture();
         for batch in self.draw_batches.iter() {
             match batch {
                 BatchRegion::Rect(r) => {
@@ -126,7 +159,8 @@ impl UiRenderer {
                     // todo!() maybe not set entire buffer and then adjust the instance indexes that are drawn???
                     render_pass.draw(0..VERTEX_COUNT, r.start as u32..r.end as u32);
                 }
-                BatchRegion::Text(r, _font) => {
+                BatchRegion::Text(r, _font, page) => {
+                    let atlas_texture = fonts.atlas_page(*page);
                     render_pass.set_bind_group(1, &atlas_texture.bind_group, &[]);
                     render_pass.set_pipeline(&self.glyph_pipeline);
                     // set the instance buffer (no vertex buffer used, vertex positions computed from instances)
@@ -181,6 +215,7 @@ impl Prepare for UiRenderer {
             .prepare(&self.collected_batches.glyphs, device, queue);
         self.draw_batches.clear();
         std::mem::swap(&mut self.draw_batches, &mut self.collected_batches.batches);
+        self.stats = std::mem::take(&mut self.collected_batches.stats);
         self.collected_batches.glyphs.clear();
         self.collected_batches.rects.clear();
         self.collected_batches.textured_rects.clear();
@@ -193,6 +228,7 @@ fn create_rect_pipeline(
     shader_module: &ShaderModule,
     device: &wgpu::Device,
     screen: &ScreenGR,
+    format: wgpu::TextureFormat,
 ) -> wgpu::RenderPipeline {
     create_pipeline::<RectRaw>(
         shader_module,
@@ -200,6 +236,7 @@ fn create_rect_pipeline(
         "rect_fs",
         "Rect",
         device,
+        format,
         &[screen.bind_group_layout()],
     )
 }
@@ -208,6 +245,7 @@ fn create_textured_rect_pipeline(
     shader_module: &ShaderModule,
     device: &wgpu::Device,
     screen: &ScreenGR,
+    format: wgpu::TextureFormat,
 ) -> wgpu::RenderPipeline {
     create_pipeline::<RectRawTextured>(
         shader_module,
@@ -215,7 +253,8 @@ fn create_textured_rect_pipeline(
         "textured_rect_fs",
         "Textured Rect",
         device,
-        &[screen.bind_group_layout(), rgba_bind_group_layout(device)],
+        format,
+        &[screen.bind_group_layout(), &rgba_bind_group_layout(device)],
     )
 }
 
@@ -223,6 +262,7 @@ fn create_glyph_pipeline(
     shader_module: &ShaderModule,
     device: &wgpu::Device,
     screen: &ScreenGR,
+    format: wgpu::TextureFormat,
 ) -> wgpu::RenderPipeline {
     create_pipeline::<GlyphRaw>(
         shader_module,
@@ -230,7 +270,8 @@ fn create_glyph_pipeline(
         "glyph_fs",
         "Glyph",
         device,
-        &[screen.bind_group_layout(), rgba_bind_group_layout(device)],
+        format,
+        &[screen.bind_group_layout(), &rgba_bind_group_layout(device)],
     )
 }
 
@@ -241,6 +282,7 @@ fn create_pipeline<I: VertexT>(
     fragment_entry: &str,
     label: &str,
     device: &wgpu::Device,
+    format: wgpu::TextureFormat,
     bind_group_layouts: &[&wgpu::BindGroupLayout],
 ) -> wgpu::RenderPipeline {
     let _empty = &mut vec![];
@@ -264,7 +306,7 @@ fn create_pipeline<I: VertexT>(
             module: shader_module,
             entry_point: fragment_entry,
             targets: &[Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                format,
                 blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                 write_mask: wgpu::ColorWrites::ALL,
             })],
@@ -18,6 +18,7 @@ use crate::{
 
 use super::{next_hot_active, Widget};
 use smallvec::smallvec;
+use winit::window::CursorIcon;
 
 pub struct Button {
     pub text: Cow<'static, str>,
@@ -66,6 +67,8 @@ impl Widget for Button {
         );
 
         let mouse_in_rect = btn.mouse_in_rect();
+        btn.focusable();
+        let activated = btn.activated();
 
         btn.width(Len::px(200.0));
         // add padding
@@ -80,7 +83,7 @@ impl Widget for Button {
         btn.padding = Padding::new().top(Len::px(8.0)).bottom(Len::px(16.0));
 
         let next_hot_active = next_hot_active(hot_active, mouse_in_rect, left_button);
-        let clicked = hot_active == Active && next_hot_active == Hot;
+        let clicked = (hot_active == Active && next_hot_active == Hot) || activated;
 
         // we can now update the style immediately. Using the hot_active only on insertion instead of next_hot_active
         // would always be 1 frame behind. Just add a 150ms of workload on each frame (7fps) and you will feel the different.
@@ -90,6 +93,9 @@ impl Widget for Button {
             HotActive::Active => self.click_color,
         };
 
+        if next_hot_active != Nil {
+            board.set_cursor_icon(CursorIcon::Pointer);
+        }
         if next_hot_active != hot_active {
             board.set_hot_active(id, next_hot_active);
         }
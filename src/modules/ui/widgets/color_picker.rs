@@ -0,0 +1,305 @@
+use super::{next_hot_active, TextEdit, Widget};
+use crate::{
+    elements::{Color, Rect},
+    ext::glam::Vec2,
+    modules::ui::{
+        board::{Align, Axis, Board, BorderRadius, DivId, Gradient, HotActive, Id, Len},
+        FontSize, Padding,
+    },
+};
+use winit::window::CursorIcon;
+
+const SQUARE_SIZE: f64 = 144.0;
+const STRIP_THICKNESS: f64 = 16.0;
+const GAP: f64 = 6.0;
+
+/// State of a `ColorPicker` that has to be kept by the caller between frames.
+///
+/// The picker edits in hsv, which cannot be recovered from the color alone (the hue of grey is lost), and the
+/// hex field needs the text that is typed.
+#[derive(Debug, Clone, Default)]
+pub struct ColorPickerState {
+    /// hue in degrees, saturation and value.
+    hsv: [f32; 3],
+    hex: String,
+    /// the color `hsv` was taken from or written to. If the color is changed from outside, `hsv` is updated.
+    synced: Option<Color>,
+}
+
+/// Saturation/value square, hue strip, alpha strip and a hex field for a `Color`, see `ColorPickerState`.
+pub struct ColorPicker<'v> {
+    value: &'v mut Color,
+    state: &'v mut ColorPickerState,
+}
+
+impl<'v> ColorPicker<'v> {
+    pub fn new(value: &'v mut Color, state: &'v mut ColorPickerState) -> Self {
+        Self { value, state }
+    }
+}
+
+pub struct ColorPickerResponse {
+    pub color: Color,
+    /// true if the color was edited this frame.
+    pub changed: bool,
+}
+
+impl<'v> Widget for ColorPicker<'v> {
+    type Response<'a> = ColorPickerResponse;
+
+    fn add_to_board(self, board: &mut Board, id: Id, parent: Option<DivId>) -> ColorPickerResponse {
+        let ColorPicker { value, state } = self;
+        if state.synced != Some(*value) {
+            let [h, s, v] = value.to_hsv();
+            // keep the hue of the state for greys, it is undefined for them.
+            state.hsv = if s == 0.0 {
+                [state.hsv[0], 0.0, v]
+            } else {
+                [h, s, v]
+            };
+            state.synced = Some(*value);
+        }
+        let [mut hue, mut saturation, mut brightness] = state.hsv;
+        let mut alpha = value.a;
+        let mut changed = false;
+        let opaque = Color::from_hsv(hue, saturation, brightness);
+
+        let mut container = board.add_div(id, parent);
+        container.axis = Axis::Y;
+        container.padding = Padding::all(Len::px(GAP));
+        container.color = Color::DARKGREY;
+        container.border_radius = BorderRadius::all(4.0);
+        let container = Some(container.id);
+
+        let mut row = board.add_div(id.child(1), container);
+        row.axis = Axis::X;
+        let row = Some(row.id);
+
+        // saturation (x) / value (y) square for the current hue:
+        let square_id = id.child(2);
+        let mut square = board.add_div(square_id, row);
+        square.width(Len::px(SQUARE_SIZE));
+        square.height(Len::px(SQUARE_SIZE));
+        square.axis = Axis::Y;
+        let (hovered, rect) = (square.mouse_in_rect(), square.entry.get().computed_rect());
+        let square = Some(square.id);
+        if let Some(pos) = drag(board, square_id, hovered, rect) {
+            saturation = pos.x;
+            brightness = 1.0 - pos.y;
+            changed = true;
+        }
+        // the pure hue, whitened to the left and darkened to the bottom by two gradients on top.
+        let saturation_overlay = Gradient {
+            axis: Axis::X,
+            color: Color::WHITE.alpha(0.0),
+        };
+        let value_overlay = Gradient {
+            axis: Axis::Y,
+            color: Color::BLACK,
+        };
+        let pure_hue = Color::from_hsv(hue, 1.0, 1.0);
+        add_layer(board, id.child(10), square, pure_hue, None);
+        add_layer(
+            board,
+            id.child(11),
+            square,
+            Color::WHITE,
+            Some(saturation_overlay),
+        );
+        let transparent_black = Color::BLACK.alpha(0.0);
+        add_layer(
+            board,
+            id.child(12),
+            square,
+            transparent_black,
+            Some(value_overlay),
+        );
+        add_knob(
+            board,
+            id.child(3),
+            square,
+            [8.0, 8.0],
+            [
+                saturation as f64 * SQUARE_SIZE - 4.0,
+                (1.0 - brightness as f64) * SQUARE_SIZE - 4.0,
+            ],
+        );
+
+        h_gap(board, id.child(4), row);
+
+        // hue strip, top to bottom:
+        let hue_id = id.child(5);
+        let mut hue_strip = board.add_div(hue_id, row);
+        hue_strip.width(Len::px(STRIP_THICKNESS));
+        hue_strip.height(Len::px(SQUARE_SIZE));
+        hue_strip.axis = Axis::Y;
+        let (hovered, rect) = (
+            hue_strip.mouse_in_rect(),
+            hue_strip.entry.get().computed_rect(),
+        );
+        let hue_strip = Some(hue_strip.id);
+        if let Some(pos) = drag(board, hue_id, hovered, rect) {
+            hue = pos.y * 360.0;
+            changed = true;
+        }
+        // between the primary and secondary colors the hue is a linear gradient.
+        for i in 0..6 {
+            let mut segment = board.add_div(id.child(20 + i), hue_strip);
+            segment.width(Len::PARENT);
+            segment.height(Len::parent(1.0 / 6.0));
+            segment.color = Color::from_hsv(i as f32 * 60.0, 1.0, 1.0);
+            segment.gradient = Some(Gradient {
+                axis: Axis::Y,
+                color: Color::from_hsv((i + 1) as f32 * 60.0, 1.0, 1.0),
+            });
+        }
+        add_knob(
+            board,
+            id.child(6),
+            hue_strip,
+            [STRIP_THICKNESS, 3.0],
+            [0.0, hue as f64 / 360.0 * SQUARE_SIZE - 1.5],
+        );
+
+        v_gap(board, id.child(7), container);
+
+        // alpha strip, left to right:
+        let alpha_id = id.child(8);
+        let mut alpha_strip = board.add_div(alpha_id, container);
+        alpha_strip.width(Len::px(SQUARE_SIZE + GAP + STRIP_THICKNESS));
+        alpha_strip.height(Len::px(STRIP_THICKNESS));
+        alpha_strip.axis = Axis::X;
+        alpha_strip.color = Color::WHITE;
+        let (hovered, rect) = (
+            alpha_strip.mouse_in_rect(),
+            alpha_strip.entry.get().computed_rect(),
+        );
+        let alpha_strip = Some(alpha_strip.id);
+        if let Some(pos) = drag(board, alpha_id, hovered, rect) {
+            alpha = pos.x;
+            changed = true;
+        }
+        let alpha_gradient = Gradient {
+            axis: Axis::X,
+            color: opaque,
+        };
+        add_layer(
+            board,
+            id.child(30),
+            alpha_strip,
+            opaque.alpha(0.0),
+            Some(alpha_gradient),
+        );
+        add_knob(
+            board,
+            id.child(9),
+            alpha_strip,
+            [3.0, STRIP_THICKNESS],
+            [
+                alpha as f64 * (SQUARE_SIZE + GAP + STRIP_THICKNESS) - 1.5,
+                0.0,
+            ],
+        );
+
+        v_gap(board, id.child(500), container);
+
+        // preview and hex field:
+        let mut bottom = board.add_div(id.child(501), container);
+        bottom.axis = Axis::X;
+        bottom.cross_align = Align::Center;
+        let bottom = Some(bottom.id);
+        let mut preview = board.add_div(id.child(502), bottom);
+        preview.width(Len::px(STRIP_THICKNESS * 2.0));
+        preview.height(Len::px(STRIP_THICKNESS * 2.0));
+        preview.color = value.alpha(1.0);
+        preview.border_radius = BorderRadius::all(4.0);
+        h_gap(board, id.child(503), bottom);
+
+        let mut hex_edit = TextEdit::new(&mut state.hex);
+        hex_edit.width = SQUARE_SIZE - STRIP_THICKNESS;
+        hex_edit.font_size = FontSize(18);
+        let hex_response = board.add(hex_edit, id.child(1000), bottom);
+
+        if changed {
+            *value = Color::from_hsv(hue, saturation, brightness).alpha(alpha);
+            state.hsv = [hue, saturation, brightness];
+        } else if hex_response.changed {
+            if let Some(color) = Color::try_from_hex(&state.hex) {
+                *value = color;
+                state.hsv = color.to_hsv();
+                changed = true;
+            }
+        }
+        state.synced = Some(*value);
+        // the hex field shows the current color, unless the user is typing into it.
+        if !hex_response.focused {
+            state.hex = value.to_hex();
+        }
+
+        ColorPickerResponse {
+            color: *value,
+            changed,
+        }
+    }
+}
+
+/// Hot-active handling of the square and the strips. While the area is dragged, returns the cursor position in it,
+/// from (0,0) at the top left to (1,1) at the bottom right.
+fn drag(board: &mut Board, id: Id, hovered: bool, rect: Rect) -> Option<Vec2> {
+    let hot_active = board.hot_active(id);
+    let next = next_hot_active(hot_active, hovered, board.input().mouse_buttons.left());
+    if next != hot_active {
+        board.set_hot_active(id, next);
+    }
+    if next != HotActive::Nil {
+        board.set_cursor_icon(CursorIcon::Crosshair);
+    }
+    if next != HotActive::Active || rect.width <= 0.0 || rect.height <= 0.0 {
+        return None;
+    }
+    let cursor = board.input().cursor_pos?;
+    Some(Vec2::new(
+        ((cursor.x - rect.min_x) / rect.width).clamp(0.0, 1.0),
+        ((cursor.y - rect.min_y) / rect.height).clamp(0.0, 1.0),
+    ))
+}
+
+/// A white marker with a black border, positioned relative to the top left corner of the parent.
+fn add_knob(board: &mut Board, id: Id, parent: Option<DivId>, size: [f64; 2], offset: [f64; 2]) {
+    let mut knob = board.add_div(id, parent);
+    knob.width(Len::px(size[0]));
+    knob.height(Len::px(size[1]));
+    knob.absolute = true;
+    knob.offset_x = Len::px(offset[0]);
+    knob.offset_y = Len::px(offset[1]);
+    knob.color = Color::WHITE;
+    knob.border_color = Color::BLACK;
+    knob.border_thickness = 1.0;
+    knob.border_radius = BorderRadius::all(2.0);
+    // drawn on top of the cells that are added after it.
+    knob.add_z_bias(1000);
+}
+
+/// Covers the whole parent. Layers added later are drawn on top.
+fn add_layer(
+    board: &mut Board,
+    id: Id,
+    parent: Option<DivId>,
+    color: Color,
+    gradient: Option<Gradient>,
+) {
+    let mut layer = board.add_div(id, parent);
+    layer.width(Len::PARENT);
+    layer.height(Len::PARENT);
+    layer.absolute = true;
+    layer.color = color;
+    layer.gradient = gradient;
+}
+
+fn h_gap(board: &mut Board, id: Id, parent: Option<DivId>) {
+    board.add_div(id, parent).width(Len::px(GAP));
+}
+
+fn v_gap(board: &mut Board, id: Id, parent: Option<DivId>) {
+    board.add_div(id, parent).height(Len::px(GAP));
+}
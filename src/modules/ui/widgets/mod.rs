@@ -8,13 +8,31 @@ use super::{
 mod button;
 pub use button::Button;
 
+mod color_picker;
+pub use color_picker::{ColorPicker, ColorPickerResponse, ColorPickerState};
+
 mod fill;
 pub use fill::{h_fill, v_fill};
 
+mod progress_bar;
+pub use progress_bar::ProgressBar;
+
+mod separator;
+pub use separator::Separator;
+
 mod slider;
 use fontdue::Font;
 pub use slider::Slider;
+
+mod tabs;
+pub use tabs::{Tabs, TabsResponse};
+
+mod virtual_list;
+pub use virtual_list::{VirtualList, VirtualListResponse, VirtualListState};
+
+mod text_edit;
 use smallvec::smallvec;
+pub use text_edit::{TextEdit, TextEditResponse};
 
 pub trait Widget {
     /// lifetime to allow mutable entries inserted into the hashmap be returned.
@@ -77,6 +95,7 @@ impl Widget for (TextSection, Ptr<Font>) {
                 offset_x: Len::ZERO,
                 offset_y: Len::ZERO,
                 line_height: 1.0,
+                ..Default::default()
             },
             id,
             parent,
@@ -0,0 +1,153 @@
+use std::{borrow::Cow, time::Duration};
+
+use super::Widget;
+use crate::{
+    elements::Color,
+    modules::ui::{
+        board::{Align, Axis, Board, BorderRadius, DivId, Id, Len, Text},
+        FontSize, Span, TextSection,
+    },
+};
+use smallvec::smallvec;
+
+/// A horizontal bar that is filled from the left, e.g. for loading screens or health bars.
+pub struct ProgressBar<'v> {
+    /// between 0.0 and 1.0
+    pub progress: f32,
+    /// If set, the fill moves towards `progress` over a few frames instead of jumping. The value is the fill that is
+    /// currently shown and has to be kept by the caller between frames, the duration is the frame time
+    /// (e.g. `Time::delta`), such that the animation is equally fast at any frame rate.
+    pub animated: Option<(&'v mut f32, Duration)>,
+    /// drawn centered on top of the bar, e.g. "42%".
+    pub text: Option<Cow<'static, str>>,
+    pub width: Len,
+    pub height: Len,
+    pub color: Color,
+    pub fill_color: Color,
+    pub text_color: Color,
+}
+
+impl<'v> ProgressBar<'v> {
+    /// the remaining distance of the animated fill shrinks by `exp(-ANIMATION_RATE * seconds)`, that is about 15% per
+    /// frame at 60 fps.
+    const ANIMATION_RATE: f32 = 9.75;
+
+    pub fn new(progress: f32) -> Self {
+        ProgressBar {
+            progress,
+            animated: None,
+            text: None,
+            width: Len::px(200.0),
+            height: Len::px(24.0),
+            color: Color::DARKGREY,
+            fill_color: Color::from_hex("#32a852"),
+            text_color: Color::WHITE,
+        }
+    }
+
+    pub fn animated(mut self, shown_progress: &'v mut f32, delta: Duration) -> Self {
+        self.animated = Some((shown_progress, delta));
+        self
+    }
+
+    pub fn text(mut self, text: impl Into<Cow<'static, str>>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// shows the progress as a percentage on top of the bar.
+    pub fn percentage(self) -> Self {
+        let text = format!("{:.0}%", self.progress.clamp(0.0, 1.0) * 100.0);
+        self.text(text)
+    }
+}
+
+impl<'v> Widget for ProgressBar<'v> {
+    type Response<'a> = ();
+
+    fn add_to_board(self, board: &mut Board, id: Id, parent: Option<DivId>) -> Self::Response<'_> {
+        let progress = self.progress.clamp(0.0, 1.0);
+        let fill = match self.animated {
+            Some((shown, delta)) => {
+                let distance = progress - *shown;
+                // snap when close, such that the animation ends at some point.
+                if distance.abs() < 0.001 {
+                    *shown = progress;
+                } else {
+                    let remaining = (-Self::ANIMATION_RATE * delta.as_secs_f32()).exp();
+                    *shown += distance * (1.0 - remaining);
+                }
+                shown.clamp(0.0, 1.0)
+            }
+            None => progress,
+        };
+
+        let mut bar = board.add_div(id, parent);
+        bar.width(self.width);
+        bar.height(self.height);
+        bar.axis = Axis::X;
+        bar.cross_align = Align::Center;
+        bar.color = self.color;
+        bar.border_radius = BorderRadius::all(4.0);
+        let bar = Some(bar.id);
+
+        let mut fill_div = board.add_div(id + 1, bar);
+        fill_div.width(Len::parent(fill as f64));
+        fill_div.height(Len::PARENT);
+        fill_div.color = self.fill_color;
+        fill_div.border_radius = BorderRadius::all(4.0);
+
+        if let Some(text) = self.text {
+            let mut text_div = board.add_text_div(
+                Text {
+                    spans: smallvec![Span::Text(TextSection {
+                        color: self.text_color,
+                        string: text,
+                        size: FontSize(18)
+                    })],
+                    font: None,
+                    ..Default::default()
+                }
+                .align(Align::Center, Align::Center),
+                id + 2,
+                bar,
+            );
+            text_div.width(Len::PARENT);
+            text_div.height(Len::PARENT);
+            text_div.absolute = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use glam::dvec2;
+
+    use super::ProgressBar;
+    use crate::modules::ui::BoardTestHarness;
+
+    #[test]
+    fn animation_is_frame_rate_independent() {
+        let mut harness = BoardTestHarness::new(dvec2(800.0, 600.0));
+        let mut animate = |frames: u32, fps: u32| {
+            let mut shown = 0.0;
+            for _ in 0..frames {
+                let delta = Duration::from_secs(1) / fps;
+                harness.frame(&[], |board| {
+                    board.add(
+                        ProgressBar::new(1.0).animated(&mut shown, delta),
+                        "bar",
+                        None,
+                    );
+                });
+            }
+            shown
+        };
+        let at_30_fps = animate(3, 30);
+        let at_120_fps = animate(12, 120);
+        assert!(at_30_fps > 0.5 && at_30_fps < 1.0);
+        assert!((at_30_fps - at_120_fps).abs() < 1e-4);
+    }
+}
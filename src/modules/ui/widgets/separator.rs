@@ -0,0 +1,75 @@
+use super::Widget;
+use crate::{
+    elements::Color,
+    modules::ui::{
+        board::{Board, DivId, Id, Len, Padding},
+        Axis,
+    },
+};
+
+/// A thin line between widgets. `Axis::X` is a horizontal line (for a column of widgets), `Axis::Y` a vertical one
+/// (for a row of widgets). It spans the whole parent.
+pub struct Separator {
+    pub axis: Axis,
+    pub thickness: f64,
+    /// empty space on both sides of the line.
+    pub margin: f64,
+    pub color: Color,
+}
+
+impl Separator {
+    pub fn horizontal() -> Self {
+        Separator {
+            axis: Axis::X,
+            thickness: 1.0,
+            margin: 4.0,
+            color: Color::GREY,
+        }
+    }
+
+    pub fn vertical() -> Self {
+        Separator {
+            axis: Axis::Y,
+            ..Self::horizontal()
+        }
+    }
+}
+
+impl Default for Separator {
+    fn default() -> Self {
+        Self::horizontal()
+    }
+}
+
+impl Widget for Separator {
+    type Response<'a> = ();
+
+    fn add_to_board(self, board: &mut Board, id: Id, parent: Option<DivId>) -> Self::Response<'_> {
+        let margin = Len::px(self.margin);
+        let mut container = board.add_div(id, parent);
+        match self.axis {
+            Axis::X => {
+                container.width(Len::PARENT);
+                container.padding = Padding::new().vertical(margin);
+            }
+            Axis::Y => {
+                container.height(Len::PARENT);
+                container.padding = Padding::new().horizontal(margin);
+            }
+        }
+        let container = Some(container.id);
+
+        let mut line = board.add_div(id + 1, container);
+        match self.axis {
+            Axis::X => {
+                line.width(Len::PARENT);
+                line.height(Len::px(self.thickness));
+            }
+            Axis::Y => {
+                line.width(Len::px(self.thickness));
+                line.height(Len::PARENT);
+            }
+        }
+        line.color = self.color;
+    }
+}
@@ -8,6 +8,7 @@ use crate::{
     },
 };
 use smallvec::smallvec;
+use winit::window::CursorIcon;
 
 /// This is a very rudimentary slider for float values. Nothing fancy. Mainly to show how things can be done in Immediate Mode UI.
 /// No Customization options here. Just copy it and make your own adjustments.
@@ -101,6 +102,11 @@ impl<'v> Widget for Slider<'v> {
         let fraction = ((*self.value - self.min) / value_range) as f64;
         knob.offset_x = Len::px(fraction * PX_TOTAL_RANGE);
         board.set_hot_active(knob_id, knob_next_hot_active);
+        match knob_next_hot_active {
+            HotActive::Nil => {}
+            HotActive::Hot => board.set_cursor_icon(CursorIcon::Grab),
+            HotActive::Active => board.set_cursor_icon(CursorIcon::Grabbing),
+        }
 
         let mut text_div = board.add_text_div(
             Text {
@@ -0,0 +1,137 @@
+use std::borrow::Cow;
+
+use super::{next_hot_active, Widget};
+use crate::{
+    elements::Color,
+    modules::ui::{
+        board::{
+            Align, Axis, Board, BorderRadius, DivId, HotActive, Id, Len, MainAlign, Padding, Text,
+        },
+        FontSize, Span, TextSection,
+    },
+};
+use smallvec::smallvec;
+use winit::window::CursorIcon;
+
+/// A row of tab headers above a content div. Only one tab is selected at a time, add the widgets of the selected tab
+/// to `TabsResponse::content`.
+///
+/// ```ignore
+/// let tabs = board.add(Tabs::new(&mut self.tab, ["Video", "Audio"]), "settings_tabs", None);
+/// match tabs.selected {
+///     0 => { board.add(Button::default(), "vsync", Some(tabs.content)); }
+///     _ => { board.add(Slider::new(&mut self.volume, 0.0, 1.0), "volume", Some(tabs.content)); }
+/// }
+/// ```
+pub struct Tabs<'v> {
+    pub selected: &'v mut usize,
+    pub labels: Vec<Cow<'static, str>>,
+    pub width: Len,
+    pub color: Color,
+    pub selected_color: Color,
+    pub hover_color: Color,
+    pub text_color: Color,
+}
+
+pub struct TabsResponse {
+    /// the div the content of the selected tab should be added to.
+    pub content: DivId,
+    pub selected: usize,
+    /// true if `selected` changed this frame, e.g. because another tab was clicked.
+    pub changed: bool,
+}
+
+impl<'v> Tabs<'v> {
+    pub fn new<L: Into<Cow<'static, str>>>(
+        selected: &'v mut usize,
+        labels: impl IntoIterator<Item = L>,
+    ) -> Self {
+        Tabs {
+            selected,
+            labels: labels.into_iter().map(Into::into).collect(),
+            width: Len::px(400.0),
+            color: Color::DARKGREY,
+            selected_color: Color::GREY,
+            hover_color: Color::from_hex("#4d528a"),
+            text_color: Color::WHITE,
+        }
+    }
+}
+
+impl<'v> Widget for Tabs<'v> {
+    type Response<'a> = TabsResponse;
+
+    fn add_to_board(self, board: &mut Board, id: Id, parent: Option<DivId>) -> Self::Response<'_> {
+        let left_button = board.input().mouse_buttons.left();
+        let old_selected = *self.selected;
+        if !self.labels.is_empty() {
+            *self.selected = (*self.selected).min(self.labels.len() - 1);
+        }
+
+        let mut container = board.add_div(id, parent);
+        container.width(self.width);
+        container.axis = Axis::Y;
+        let container = Some(container.id);
+
+        let mut header = board.add_div(id + 1, container);
+        header.width(Len::PARENT);
+        header.axis = Axis::X;
+        let header = Some(header.id);
+
+        let tab_width = Len::parent(1.0 / self.labels.len().max(1) as f64);
+        for (i, label) in self.labels.into_iter().enumerate() {
+            // ids of the tabs start after the ids of the container, header and content.
+            let tab_id = id + 3 + i as u64;
+            let hot_active = board.hot_active(tab_id);
+            let mut tab = board.add_text_div(
+                Text {
+                    spans: smallvec![Span::Text(TextSection {
+                        color: self.text_color,
+                        string: label,
+                        size: FontSize(20)
+                    })],
+                    font: None,
+                    ..Default::default()
+                },
+                tab_id,
+                header,
+            );
+            tab.width(tab_width);
+            tab.main_align = MainAlign::Center;
+            tab.cross_align = Align::Center;
+            tab.padding = Padding::new().vertical(Len::px(6.0));
+            tab.border_radius = BorderRadius::new(8.0, 8.0, 0.0, 0.0);
+
+            tab.focusable();
+            let activated = tab.activated();
+            let next_hot_active = next_hot_active(hot_active, tab.mouse_in_rect(), left_button);
+            if (hot_active == HotActive::Active && next_hot_active == HotActive::Hot) || activated {
+                *self.selected = i;
+            }
+            tab.color = if *self.selected == i {
+                self.selected_color
+            } else if next_hot_active != HotActive::Nil {
+                self.hover_color
+            } else {
+                self.color
+            };
+
+            if next_hot_active != HotActive::Nil {
+                board.set_cursor_icon(CursorIcon::Pointer);
+            }
+            if next_hot_active != hot_active {
+                board.set_hot_active(tab_id, next_hot_active);
+            }
+        }
+
+        let mut content = board.add_div(id + 2, container);
+        content.width(Len::PARENT);
+        content.padding = Padding::all(Len::px(8.0));
+        content.color = self.selected_color;
+        TabsResponse {
+            content: content.id,
+            selected: *self.selected,
+            changed: *self.selected != old_selected,
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use super::Widget;
+use crate::{
+    elements::Color,
+    modules::ui::{
+        board::{Align, Axis, Board, BorderRadius, DivId, Id, Len, MainAlign, Text},
+        FontSize, Padding, Span, TextSection,
+    },
+};
+use smallvec::smallvec;
+use winit::{keyboard::KeyCode, window::CursorIcon};
+
+/// A single line text field. Clicking or activating it (see `Response::activated`) focuses it (see `Board::focused`),
+/// clicking somewhere else unfocuses it.
+/// While focused, text typed or committed by the ime is appended and backspace removes the last character.
+/// Text that is still being composed by the ime is shown underlined after the cursor.
+///
+/// Just like the `Slider` this is rudimentary: no selection, no moving the cursor.
+pub struct TextEdit<'v> {
+    value: &'v mut String,
+    pub width: f64,
+    pub font_size: FontSize,
+    pub text_color: Color,
+}
+
+impl<'v> TextEdit<'v> {
+    pub fn new(value: &'v mut String) -> Self {
+        Self {
+            value,
+            width: 200.0,
+            font_size: FontSize(24),
+            text_color: Color::BLACK,
+        }
+    }
+}
+
+pub struct TextEditResponse {
+    pub changed: bool,
+    pub focused: bool,
+}
+
+impl<'v> Widget for TextEdit<'v> {
+    type Response<'a> = TextEditResponse;
+
+    fn add_to_board(self, board: &mut Board, id: Id, parent: Option<DivId>) -> TextEditResponse {
+        let mut field = board.add_div(id, parent);
+        field.width(Len::px(self.width));
+        // one line high, even if empty.
+        field.height(Len::px(self.font_size.0 as f64 + 8.0));
+        field.axis = Axis::X;
+        field.main_align = MainAlign::Start;
+        field.cross_align = Align::Center;
+        field.padding = Padding::all(Len::px(4.0));
+        field.color = Color::WHITE;
+        field.border_radius = BorderRadius::all(4.0);
+        field.border_thickness = 1.0;
+        let hovered = field.mouse_in_rect();
+        field.focusable();
+        let activated = field.activated();
+        let field = Some(field.id);
+
+        if hovered {
+            board.set_cursor_icon(CursorIcon::Text);
+        }
+        if activated {
+            board.set_focused(Some(id));
+        } else if board.input().mouse_buttons.left().just_pressed() {
+            if hovered {
+                board.set_focused(Some(id));
+            } else if board.focused() == Some(id) {
+                board.set_focused(None);
+            }
+        }
+
+        let focused = board.focused() == Some(id);
+        let mut changed = false;
+        // the Space or Enter that activated the field is not typed into it.
+        if focused && !activated {
+            let input = board.input();
+            if !input.text.is_empty() {
+                self.value.push_str(&input.text);
+                changed = true;
+            }
+            // while composing, backspace edits the preedit text instead.
+            if input.keys.just_pressed(KeyCode::Backspace) && input.ime_preedit.is_none() {
+                changed |= self.value.pop().is_some();
+            }
+        }
+        let preedit = match focused {
+            true => board.input().ime_preedit.clone(),
+            false => None,
+        };
+
+        let text = |string: String| Text {
+            spans: smallvec![Span::Text(TextSection {
+                color: self.text_color,
+                string: string.into(),
+                size: self.font_size,
+            })],
+            font: None,
+            ..Default::default()
+        };
+        board.add_text_div(text(self.value.clone()), id.child(1), field);
+
+        if let Some(preedit) = preedit {
+            let mut composing = board.add_div(id.child(2), field);
+            composing.axis = Axis::Y;
+            let composing = Some(composing.id);
+            board.add_text_div(text(preedit.text), id.child(3), composing);
+            let mut underline = board.add_div(id.child(4), composing);
+            underline.width(Len::PARENT);
+            underline.height(Len::px(2.0));
+            underline.color = self.text_color;
+        }
+
+        if focused {
+            let mut caret = board.add_div(id.child(5), field);
+            caret.width(Len::px(2.0));
+            caret.height(Len::px(self.font_size.0 as f64));
+            caret.color = self.text_color;
+        }
+
+        TextEditResponse { changed, focused }
+    }
+}
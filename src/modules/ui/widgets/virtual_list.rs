@@ -0,0 +1,149 @@
+use std::ops::Range;
+
+use super::Widget;
+use crate::{
+    elements::Color,
+    modules::ui::board::{Align, Axis, Board, BorderRadius, DivId, Id, Len},
+};
+
+/// Pixels scrolled per line of mouse wheel scrolling.
+const SCROLL_SPEED: f64 = 40.0;
+const SCROLLBAR_WIDTH: f64 = 6.0;
+
+/// Scroll position of a `VirtualList`, has to be kept by the caller between frames.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VirtualListState {
+    /// pixels scrolled from the top.
+    pub scroll: f64,
+}
+
+/// A scrollable list of `count` rows that only adds divs for the rows that are visible, so lists with thousands of
+/// entries (e.g. a debug view over all entities) cost as much as the few rows on screen.
+///
+/// `row_height` returns the height of each row and `add_row` is called for each visible row with the div the row
+/// content should be added to. Scrolling with the mouse wheel works while the list is hovered.
+/// Divs are not clipped, so the list scrolls in whole rows: the row at the scroll position is always shown at the top.
+pub struct VirtualList<'v, H, R>
+where
+    H: Fn(usize) -> f64,
+    R: FnMut(&mut Board, usize, DivId),
+{
+    pub state: &'v mut VirtualListState,
+    pub count: usize,
+    pub row_height: H,
+    pub add_row: R,
+    pub width: Len,
+    pub height: f64,
+    pub scrollbar_color: Color,
+}
+
+impl<'v, H, R> VirtualList<'v, H, R>
+where
+    H: Fn(usize) -> f64,
+    R: FnMut(&mut Board, usize, DivId),
+{
+    pub fn new(
+        state: &'v mut VirtualListState,
+        count: usize,
+        height: f64,
+        row_height: H,
+        add_row: R,
+    ) -> Self {
+        VirtualList {
+            state,
+            count,
+            row_height,
+            add_row,
+            width: Len::px(300.0),
+            height,
+            scrollbar_color: Color::GREY,
+        }
+    }
+}
+
+pub struct VirtualListResponse {
+    /// the rows that divs were added for.
+    pub visible_rows: Range<usize>,
+    /// height of all rows together.
+    pub total_height: f64,
+}
+
+impl<'v, H, R> Widget for VirtualList<'v, H, R>
+where
+    H: Fn(usize) -> f64,
+    R: FnMut(&mut Board, usize, DivId),
+{
+    type Response<'a> = VirtualListResponse;
+
+    fn add_to_board(self, board: &mut Board, id: Id, parent: Option<DivId>) -> VirtualListResponse {
+        let VirtualList {
+            state,
+            count,
+            row_height,
+            mut add_row,
+            width,
+            height,
+            scrollbar_color,
+        } = self;
+        let scroll_input = board.input().scroll as f64;
+
+        let mut list = board.add_div(id, parent);
+        list.width(width);
+        list.height(Len::px(height));
+        list.axis = Axis::Y;
+        let hovered = list.mouse_in_rect();
+        let list = Some(list.id);
+
+        // find the first row at the scroll position, summing up the heights is cheap compared to adding divs.
+        let total_height: f64 = (0..count).map(&row_height).sum();
+        if hovered {
+            state.scroll -= scroll_input * SCROLL_SPEED;
+        }
+        state.scroll = state.scroll.clamp(0.0, (total_height - height).max(0.0));
+        let mut first = 0;
+        let mut first_top = 0.0;
+        while first < count {
+            let h = row_height(first);
+            if first_top + h > state.scroll {
+                break;
+            }
+            first_top += h;
+            first += 1;
+        }
+
+        // add rows while they fit:
+        let mut end = first;
+        let mut filled = 0.0;
+        while end < count {
+            let h = row_height(end);
+            if filled + h > height && end > first {
+                break;
+            }
+            let mut row = board.add_div(id + 16 + end as u64, list);
+            row.width(Len::PARENT);
+            row.height(Len::px(h));
+            row.axis = Axis::X;
+            row.cross_align = Align::Center;
+            let row = row.id;
+            add_row(board, end, row);
+            filled += h;
+            end += 1;
+        }
+
+        if total_height > height {
+            let mut thumb = board.add_div(id + 1, list);
+            thumb.width(Len::px(SCROLLBAR_WIDTH));
+            thumb.height(Len::px(height * height / total_height));
+            thumb.absolute = true;
+            thumb.offset_x = Len::PARENT - Len::px(SCROLLBAR_WIDTH);
+            thumb.offset_y = Len::px(first_top / total_height * height);
+            thumb.color = scrollbar_color;
+            thumb.border_radius = BorderRadius::all(SCROLLBAR_WIDTH as f32 / 2.0);
+        }
+
+        VirtualListResponse {
+            visible_rows: first..end,
+            total_height,
+        }
+    }
+}
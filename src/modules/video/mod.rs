@@ -0,0 +1,523 @@
+//! Video playback for intro cutscenes and similar. A `VideoDecoder` decodes frames on a background thread and the
+//! `VideoPlayer` writes the frame that is due into a texture that stays the same for the whole video, such that it
+//! can be drawn like any other texture by the ui and sprite renderers.
+//!
+//! Only `Y4mDecoder` comes with vert, other formats can be played by implementing `VideoDecoder`.
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender, SyncSender, TryRecvError},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use glam::{uvec2, UVec2};
+use image::RgbaImage;
+use rodio::{Sink, Source};
+
+use crate::{
+    elements::{BindableTexture, Texture},
+    GpuRecreate, GpuRecreated, OwnedPtr, Ptr,
+};
+
+use super::{audio::Bus, Audio, GraphicsContext};
+
+pub mod y4m;
+pub use y4m::Y4mDecoder;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VideoInfo {
+    pub width: u32,
+    pub height: u32,
+    /// frames per second.
+    pub frame_rate: f64,
+    /// None if the decoder cannot tell without decoding the whole video.
+    pub frame_count: Option<u64>,
+}
+
+impl VideoInfo {
+    pub fn duration(&self) -> Option<Duration> {
+        self.frame_count
+            .map(|count| Duration::from_secs_f64(count as f64 / self.frame_rate))
+    }
+}
+
+/// Decodes the frames of a video in order. Runs on a background thread.
+pub trait VideoDecoder: Send {
+    fn info(&self) -> VideoInfo;
+    /// The next frame in rgba, None after the last one.
+    fn next_frame(&mut self) -> anyhow::Result<Option<RgbaImage>>;
+    /// After seeking, `next_frame` returns the frame with index `frame`.
+    fn seek(&mut self, frame: u64) -> anyhow::Result<()>;
+}
+
+/// Frames that the decoder thread may decode ahead.
+const DECODE_AHEAD: usize = 4;
+
+enum Command {
+    Seek { frame: u64, generation: u64 },
+}
+
+/// Tagged with the generation of the last seek, frames that were decoded before a seek are dropped.
+enum Decoded {
+    Frame {
+        generation: u64,
+        index: u64,
+        image: RgbaImage,
+    },
+    End {
+        generation: u64,
+    },
+}
+
+impl Decoded {
+    fn generation(&self) -> u64 {
+        match self {
+            Decoded::Frame { generation, .. } | Decoded::End { generation } => *generation,
+        }
+    }
+}
+
+/// Plays a video into a texture, optionally in sync with a sound track. If there is a sound track, the video follows
+/// the audio clock, otherwise the time passed to `update`.
+///
+/// ```rust,ignore
+/// let mut intro = VideoPlayer::new(&ctx, Y4mDecoder::open("assets/intro.y4m")?)
+///     .with_sound_track(&mods.audio, "assets/intro.ogg");
+/// // every frame:
+/// intro.update(mods.time.delta(), &ctx.queue);
+/// mods.ui_rect.draw_textured_rect(rect, intro.texture());
+/// ```
+pub struct VideoPlayer {
+    info: VideoInfo,
+    texture: OwnedPtr<BindableTexture>,
+    /// the frame on the texture, kept to upload it again after the device was lost.
+    frame: Option<(u64, RgbaImage)>,
+    commands: Sender<Command>,
+    decoded: Receiver<Decoded>,
+    /// received from the decoder thread, but not due yet.
+    pending: Option<Decoded>,
+    generation: u64,
+    /// in seconds, replaced by the audio clock while the sound track plays.
+    time: f64,
+    playing: bool,
+    finished: bool,
+    /// Starts from the beginning after the last frame, instead of finishing.
+    pub looping: bool,
+    sound_track: Option<SoundTrack>,
+}
+
+impl VideoPlayer {
+    /// Starts decoding right away, the video plays from the first call to `update` on.
+    pub fn new(ctx: &GraphicsContext, decoder: impl VideoDecoder + 'static) -> Self {
+        let info = decoder.info();
+        let (commands, command_receiver) = mpsc::channel();
+        let (frame_sender, decoded) = mpsc::sync_channel(DECODE_AHEAD);
+        let decoder = Box::new(decoder);
+        std::thread::Builder::new()
+            .name("video decoder".into())
+            .spawn(move || decode_frames(decoder, command_receiver, frame_sender))
+            .expect("could not spawn video decoder thread");
+        VideoPlayer {
+            info,
+            texture: OwnedPtr::new(black_texture(ctx, info)),
+            frame: None,
+            commands,
+            decoded,
+            pending: None,
+            generation: 0,
+            time: 0.0,
+            playing: true,
+            finished: false,
+            looping: false,
+            sound_track: None,
+        }
+    }
+
+    /// Plays an audio file (ogg, mp3 or wav) through the music bus along with the video. The video is timed by it.
+    pub fn with_sound_track(mut self, audio: &Audio, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        match SoundTrack::new(audio, path.clone(), self.position()) {
+            Ok(Some(sound_track)) => {
+                if !self.playing {
+                    sound_track.sink.pause();
+                }
+                self.sound_track = Some(sound_track);
+            }
+            // no audio output, the video plays without sound.
+            Ok(None) => {}
+            Err(err) => log::error!("could not play sound track {path:?} of video: {err}"),
+        }
+        self
+    }
+
+    pub fn info(&self) -> VideoInfo {
+        self.info
+    }
+
+    pub fn size(&self) -> UVec2 {
+        uvec2(self.info.width, self.info.height)
+    }
+
+    /// The texture that the video plays in. Stays the same unless the device is lost.
+    pub fn texture(&self) -> Ptr<BindableTexture> {
+        self.texture.ptr()
+    }
+
+    pub fn play(&mut self) {
+        if self.finished {
+            self.seek(Duration::ZERO);
+        }
+        self.playing = true;
+        if let Some(sound_track) = &self.sound_track {
+            sound_track.sink.play();
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+        if let Some(sound_track) = &self.sound_track {
+            sound_track.sink.pause();
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// True after the last frame was shown, unless the video is looping.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.time)
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.info.duration()
+    }
+
+    /// Jumps to `position`, the frame there is shown with the next `update`, also while paused.
+    pub fn seek(&mut self, position: Duration) {
+        let frame = (position.as_secs_f64() * self.info.frame_rate) as u64;
+        let frame = match self.info.frame_count {
+            Some(count) => frame.min(count.saturating_sub(1)),
+            None => frame,
+        };
+        self.generation += 1;
+        self.time = frame as f64 / self.info.frame_rate;
+        self.finished = false;
+        self.pending = None;
+        let seek = Command::Seek {
+            frame,
+            generation: self.generation,
+        };
+        if self.commands.send(seek).is_err() {
+            log::error!("video decoder thread is gone");
+        }
+        // makes room for the decoder thread if it is blocked on a full channel, such that it sees the seek.
+        while self.decoded.try_recv().is_ok() {}
+        if let Some(sound_track) = &self.sound_track {
+            sound_track.seek(Duration::from_secs_f64(self.time));
+        }
+    }
+
+    /// Advances the video and writes the frame that is due into the texture. Call once per frame.
+    pub fn update(&mut self, delta: Duration, queue: &wgpu::Queue) {
+        if self.playing && !self.finished {
+            self.time += delta.as_secs_f64();
+            if let Some(clock) = self.sound_track.as_ref().and_then(|s| s.clock()) {
+                self.time = clock;
+            }
+        }
+        let due = (self.time * self.info.frame_rate) as u64;
+        let mut show = None;
+        loop {
+            let decoded = match self.pending.take() {
+                Some(decoded) => decoded,
+                None => match self.decoded.try_recv() {
+                    Ok(decoded) => decoded,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.finished = true;
+                        break;
+                    }
+                },
+            };
+            if decoded.generation() != self.generation {
+                continue;
+            }
+            match decoded {
+                Decoded::Frame { index, image, .. } if index <= due => {
+                    // frames that were due at the same time are skipped, the decoder could not keep up.
+                    show = Some((index, image));
+                }
+                Decoded::Frame { .. } => {
+                    self.pending = Some(decoded);
+                    break;
+                }
+                Decoded::End { .. } => {
+                    let last = show
+                        .as_ref()
+                        .or(self.frame.as_ref())
+                        .map(|(index, _)| *index);
+                    let end = last.map(|index| (index + 1) as f64 / self.info.frame_rate);
+                    if end.is_some_and(|end| self.time < end) {
+                        // the last frame is still shown for a while.
+                        self.pending = Some(decoded);
+                    } else if self.looping {
+                        self.seek(Duration::ZERO);
+                    } else {
+                        self.finished = true;
+                        self.playing = false;
+                    }
+                    break;
+                }
+            }
+        }
+        if let Some((index, image)) = show {
+            write_frame(&self.texture.texture, &image, queue);
+            self.frame = Some((index, image));
+        }
+    }
+}
+
+impl GpuRecreate for VideoPlayer {
+    fn gpu_recreated(&mut self, ctx: &GraphicsContext, event: GpuRecreated) {
+        if !event.device_lost {
+            return;
+        }
+        self.texture = OwnedPtr::new(black_texture(ctx, self.info));
+        if let Some((_, image)) = &self.frame {
+            write_frame(&self.texture.texture, image, &ctx.queue);
+        }
+    }
+}
+
+fn black_texture(ctx: &GraphicsContext, info: VideoInfo) -> BindableTexture {
+    let mut black = RgbaImage::new(info.width, info.height);
+    black.pixels_mut().for_each(|p| p.0 = [0, 0, 0, 255]);
+    BindableTexture::new(
+        &ctx.device,
+        Texture::from_image(&ctx.device, &ctx.queue, &black),
+    )
+}
+
+fn write_frame(texture: &Texture, image: &RgbaImage, queue: &wgpu::Queue) {
+    if image.dimensions() != (texture.size.width, texture.size.height) {
+        log::error!("video frame has the wrong size {:?}", image.dimensions());
+        return;
+    }
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            aspect: wgpu::TextureAspect::All,
+            texture: &texture.texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        image,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * image.width()),
+            rows_per_image: None,
+        },
+        texture.size,
+    );
+}
+
+/// Runs on the decoder thread until the `VideoPlayer` is dropped.
+fn decode_frames(
+    mut decoder: Box<dyn VideoDecoder>,
+    commands: Receiver<Command>,
+    frames: SyncSender<Decoded>,
+) {
+    let mut generation = 0;
+    let mut index = 0;
+    loop {
+        loop {
+            match commands.try_recv() {
+                Ok(command) => (generation, index) = seek_decoder(&mut *decoder, command),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        let decoded = match decoder.next_frame() {
+            Ok(Some(image)) => Decoded::Frame {
+                generation,
+                index,
+                image,
+            },
+            Ok(None) => Decoded::End { generation },
+            Err(err) => {
+                log::error!("could not decode video frame {index}: {err}");
+                Decoded::End { generation }
+            }
+        };
+        index += 1;
+        let ended = matches!(decoded, Decoded::End { .. });
+        if frames.send(decoded).is_err() {
+            return;
+        }
+        if ended {
+            // nothing to do until the video is seeked.
+            match commands.recv() {
+                Ok(command) => (generation, index) = seek_decoder(&mut *decoder, command),
+                Err(_) => return,
+            }
+        }
+    }
+}
+
+/// Returns the generation and index of the next frame.
+fn seek_decoder(decoder: &mut dyn VideoDecoder, command: Command) -> (u64, u64) {
+    let Command::Seek { frame, generation } = command;
+    if let Err(err) = decoder.seek(frame) {
+        log::error!("could not seek video to frame {frame}: {err}");
+    }
+    (generation, frame)
+}
+
+// /////////////////////////////////////////////////////////////////////////////
+// Sound track
+// /////////////////////////////////////////////////////////////////////////////
+
+type BoxedSource = Box<dyn Source<Item = i16> + Send>;
+
+/// The sink plays a single `ClockSource` for the whole video, seeking swaps the decoder inside of it, such that no
+/// access to `Audio` is needed after the start.
+struct SoundTrack {
+    path: PathBuf,
+    sink: Sink,
+    clock: Arc<SoundTrackClock>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+#[derive(Default)]
+struct SoundTrackClock {
+    /// samples since the start of the track.
+    played: AtomicU64,
+    ended: AtomicBool,
+    /// set when `replacement` holds a source.
+    seeking: AtomicBool,
+    /// decoder skipped to the seek position and the sample to continue counting from.
+    replacement: Mutex<Option<(BoxedSource, u64)>>,
+}
+
+/// whole frames, such that the channels stay in order.
+fn samples(time: Duration, channels: u16, sample_rate: u32) -> u64 {
+    (time.as_secs_f64() * sample_rate as f64) as u64 * channels as u64
+}
+
+fn open_sound_track(path: &Path, start: Duration) -> anyhow::Result<BoxedSource> {
+    let decoder = rodio::Decoder::new(BufReader::new(File::open(path)?))?;
+    Ok(Box::new(decoder.skip_duration(start)))
+}
+
+impl SoundTrack {
+    /// None if there is no audio output.
+    fn new(audio: &Audio, path: PathBuf, start: Duration) -> anyhow::Result<Option<Self>> {
+        let source = open_sound_track(&path, start)?;
+        let (channels, sample_rate) = (source.channels(), source.sample_rate());
+        let clock = Arc::new(SoundTrackClock::default());
+        clock
+            .played
+            .store(samples(start, channels, sample_rate), Ordering::Relaxed);
+        let source = ClockSource {
+            source,
+            clock: clock.clone(),
+            channels,
+            sample_rate,
+        };
+        Ok(audio
+            .play_source(source, Bus::Music)
+            .map(|sink| SoundTrack {
+                path,
+                sink,
+                clock,
+                channels,
+                sample_rate,
+            }))
+    }
+
+    /// Seconds played, None while seeking and after the end of the track.
+    fn clock(&self) -> Option<f64> {
+        if self.clock.seeking.load(Ordering::Acquire) || self.clock.ended.load(Ordering::Acquire) {
+            return None;
+        }
+        let played = self.clock.played.load(Ordering::Relaxed);
+        Some(played as f64 / (self.sample_rate as f64 * self.channels as f64))
+    }
+
+    /// Decodes up to `position` here, instead of on the audio thread.
+    fn seek(&self, position: Duration) {
+        match open_sound_track(&self.path, position) {
+            Ok(source) => {
+                let played = samples(position, self.channels, self.sample_rate);
+                let mut replacement = self.clock.replacement.lock().unwrap();
+                *replacement = Some((source, played));
+                self.clock.seeking.store(true, Ordering::Release);
+            }
+            Err(err) => log::error!("could not seek sound track {:?}: {err}", self.path),
+        }
+    }
+}
+
+/// Counts the samples that were played and plays silence after the end, such that the sink stays alive for seeking.
+struct ClockSource {
+    source: BoxedSource,
+    clock: Arc<SoundTrackClock>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl Iterator for ClockSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.clock.seeking.load(Ordering::Acquire) {
+            // the flag is only changed with the lock held, such that no replacement is missed.
+            let mut replacement = self.clock.replacement.lock().unwrap();
+            if let Some((source, played)) = replacement.take() {
+                self.source = source;
+                self.clock.played.store(played, Ordering::Relaxed);
+                self.clock.ended.store(false, Ordering::Release);
+            }
+            self.clock.seeking.store(false, Ordering::Release);
+        }
+        match self.source.next() {
+            Some(sample) => {
+                self.clock.played.fetch_add(1, Ordering::Relaxed);
+                Some(sample)
+            }
+            None => {
+                self.clock.ended.store(true, Ordering::Release);
+                Some(0)
+            }
+        }
+    }
+}
+
+impl Source for ClockSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
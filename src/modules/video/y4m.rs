@@ -0,0 +1,213 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail};
+use image::RgbaImage;
+
+use super::{VideoDecoder, VideoInfo};
+
+/// Decodes YUV4MPEG2 (.y4m) files, uncompressed video that ffmpeg can write:
+/// `ffmpeg -i intro.mp4 -pix_fmt yuv420p intro.y4m`.
+///
+/// Frames are read one at a time while the video plays. Supports 4:2:0 and 4:4:4 chroma with 8 bits.
+/// Compressed formats (e.g. VP9 or AV1) need to be converted first, vert has no decoder for them.
+pub struct Y4mDecoder<R> {
+    reader: R,
+    info: VideoInfo,
+    chroma: Chroma,
+    /// byte offset of the first frame.
+    data_start: u64,
+    next_frame: u64,
+    buffer: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Chroma {
+    C420,
+    C444,
+}
+
+impl Chroma {
+    fn plane_size(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Chroma::C420 => (width.div_ceil(2), height.div_ceil(2)),
+            Chroma::C444 => (width, height),
+        }
+    }
+
+    /// Index of the chroma sample of a pixel, in a plane that is `plane_width` wide.
+    fn index(self, x: u32, y: u32, plane_width: u32) -> usize {
+        match self {
+            Chroma::C420 => ((y / 2) * plane_width + x / 2) as usize,
+            Chroma::C444 => (y * plane_width + x) as usize,
+        }
+    }
+}
+
+impl Y4mDecoder<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead + Seek> Y4mDecoder<R> {
+    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let mut params = header.trim_end().split(' ');
+        if params.next() != Some("YUV4MPEG2") {
+            bail!("not a y4m file");
+        }
+        let (mut width, mut height, mut frame_rate) = (0, 0, 25.0);
+        let mut chroma = Chroma::C420;
+        for param in params {
+            let (key, value) = param.split_at(1);
+            match key {
+                "W" => width = value.parse()?,
+                "H" => height = value.parse()?,
+                "F" => {
+                    let (num, den) = value
+                        .split_once(':')
+                        .ok_or_else(|| anyhow!("invalid frame rate {value}"))?;
+                    frame_rate = num.parse::<f64>()? / den.parse::<f64>()?.max(1.0);
+                }
+                "C" => {
+                    chroma = match value {
+                        "420" | "420jpeg" | "420paldv" | "420mpeg2" => Chroma::C420,
+                        "444" => Chroma::C444,
+                        _ => bail!("unsupported y4m colorspace {value}, use yuv420p"),
+                    }
+                }
+                _ => {}
+            }
+        }
+        if width == 0 || height == 0 {
+            bail!("y4m header without size");
+        }
+        let data_start = reader.stream_position()?;
+        let (cw, ch) = chroma.plane_size(width, height);
+        let frame_bytes = (width * height + 2 * cw * ch) as u64;
+        let data_len = reader.seek(SeekFrom::End(0))? - data_start;
+        reader.seek(SeekFrom::Start(data_start))?;
+        Ok(Y4mDecoder {
+            reader,
+            info: VideoInfo {
+                width,
+                height,
+                frame_rate,
+                // assumes frame headers without parameters, which is what ffmpeg writes.
+                frame_count: Some(data_len / (FRAME_HEADER.len() as u64 + frame_bytes)),
+            },
+            chroma,
+            data_start,
+            next_frame: 0,
+            buffer: vec![0; frame_bytes as usize],
+        })
+    }
+
+    fn frame_stride(&self) -> u64 {
+        FRAME_HEADER.len() as u64 + self.buffer.len() as u64
+    }
+}
+
+const FRAME_HEADER: &[u8] = b"FRAME\n";
+
+impl<R: BufRead + Seek + Send> VideoDecoder for Y4mDecoder<R> {
+    fn info(&self) -> VideoInfo {
+        self.info
+    }
+
+    fn next_frame(&mut self) -> anyhow::Result<Option<RgbaImage>> {
+        let mut line = vec![];
+        if self.reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.starts_with(b"FRAME") {
+            bail!("invalid y4m frame header in frame {}", self.next_frame);
+        }
+        self.reader.read_exact(&mut self.buffer)?;
+        self.next_frame += 1;
+
+        let VideoInfo { width, height, .. } = self.info;
+        let (cw, ch) = self.chroma.plane_size(width, height);
+        let (y_plane, chroma) = self.buffer.split_at((width * height) as usize);
+        let (u_plane, v_plane) = chroma.split_at((cw * ch) as usize);
+        let mut image = RgbaImage::new(width, height);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let c = self.chroma.index(x, y, cw);
+            pixel.0 = yuv_to_rgba(y_plane[(y * width + x) as usize], u_plane[c], v_plane[c]);
+        }
+        Ok(Some(image))
+    }
+
+    fn seek(&mut self, frame: u64) -> anyhow::Result<()> {
+        let offset = self.data_start + frame * self.frame_stride();
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.next_frame = frame;
+        Ok(())
+    }
+}
+
+/// BT.601 with limited range, what ffmpeg uses for yuv420p by default.
+fn yuv_to_rgba(y: u8, u: u8, v: u8) -> [u8; 4] {
+    let y = (y as f32 - 16.0) * 1.164;
+    let (u, v) = (u as f32 - 128.0, v as f32 - 128.0);
+    let r = y + 1.596 * v;
+    let g = y - 0.392 * u - 0.813 * v;
+    let b = y + 2.017 * u;
+    let to_u8 = |c: f32| c.round().clamp(0.0, 255.0) as u8;
+    [to_u8(r), to_u8(g), to_u8(b), 255]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn decode_and_seek() {
+        // 2x2 pixels, 4:2:0, three frames: black, white, red.
+        let mut file = b"YUV4MPEG2 W2 H2 F30:1 Ip A1:1 C420jpeg\n".to_vec();
+        for (y, u, v) in [(16, 128, 128), (235, 128, 128), (81, 90, 240)] {
+            file.extend_from_slice(b"FRAME\n");
+            file.extend_from_slice(&[y, y, y, y, u, v]);
+        }
+        let mut decoder = Y4mDecoder::new(Cursor::new(file)).unwrap();
+        let info = decoder.info();
+        assert_eq!((info.width, info.height, info.frame_count), (2, 2, Some(3)));
+        assert_eq!(info.frame_rate, 30.0);
+
+        let pixel = |d: &mut Y4mDecoder<_>| d.next_frame().unwrap().map(|f| f.get_pixel(1, 1).0);
+        assert_eq!(pixel(&mut decoder), Some([0, 0, 0, 255]));
+        assert_eq!(pixel(&mut decoder), Some([255, 255, 255, 255]));
+        let [r, g, b, _] = pixel(&mut decoder).unwrap();
+        assert!(r > 250 && g < 5 && b < 5, "{r} {g} {b}");
+        assert_eq!(pixel(&mut decoder), None);
+
+        decoder.seek(1).unwrap();
+        assert_eq!(pixel(&mut decoder), Some([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn decode_odd_size() {
+        // 3x3 pixels, 4:2:0 has 2x2 chroma samples, the last row and column share the rounded up ones.
+        let mut file = b"YUV4MPEG2 W3 H3 F30:1 C420jpeg\n".to_vec();
+        file.extend_from_slice(b"FRAME\n");
+        file.extend_from_slice(&[235; 9]);
+        // u then v, only the bottom right sample is red.
+        file.extend_from_slice(&[128, 128, 128, 90]);
+        file.extend_from_slice(&[128, 128, 128, 240]);
+        let mut decoder = Y4mDecoder::new(Cursor::new(file)).unwrap();
+        assert_eq!(decoder.info().frame_count, Some(1));
+
+        let frame = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(frame.get_pixel(1, 1).0, [255, 255, 255, 255]);
+        let [r, g, b, _] = frame.get_pixel(2, 2).0;
+        assert!(r == 255 && g < 200 && b < 200, "{r} {g} {b}");
+        assert_eq!(frame.get_pixel(2, 1).0, [255, 255, 255, 255]);
+    }
+}
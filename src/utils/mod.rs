@@ -5,7 +5,11 @@ use std::{
 
 pub mod timing_queue;
 pub use timing_queue::{EntryKey, Timing, TimingQueue};
+pub mod scheduler;
+pub use scheduler::{Scheduler, System};
+pub mod snapshot;
 pub mod watcher;
+pub use snapshot::{HeadlessGpu, ImageTolerance, Snapshots};
 
 /// Returns the file location of a .wgsl file with the same name as the .rs file, this was invoked in.
 #[macro_export]
@@ -0,0 +1,536 @@
+use std::{collections::HashMap, fmt::Write, time::Duration};
+
+use smallvec::SmallVec;
+
+use super::{EntryKey, Timing};
+
+/// Ordered list of elements (usually callbacks), grouped into named stages.
+///
+/// Unlike the `TimingQueue`, elements can declare explicit `before`/`after` relationships by name.
+/// Within a stage, elements are ordered topologically, ties are broken by `Timing` and then by insertion order.
+/// `DefaultModules::schedule` runs `System`s with access to the modules every frame, see `Scheduler::run_on`.
+#[derive(Debug)]
+pub struct Scheduler<T: Schedulable> {
+    next_key: i32,
+    stages: Vec<&'static str>,
+    systems: Vec<SystemEntry<T>>,
+    /// false if systems were added or removed since the last resolve.
+    resolved: bool,
+    /// elements that are run once at the start of the next `run` and then dropped.
+    one_shots: Vec<T>,
+    /// names of the systems of the scheduler this one stands in for while it runs, see `Scheduler::run_on`.
+    reserved_names: Vec<&'static str>,
+}
+
+/// A callback that gets mutable access to the state that owns its scheduler, see `Scheduler::run_on`.
+pub type System<S> = Box<dyn FnMut(&mut S)>;
+
+/// Elements of a `Scheduler` are called with a context, which is also given to their run conditions.
+pub trait Schedulable {
+    type Context;
+}
+
+impl<S> Schedulable for System<S> {
+    type Context = S;
+}
+
+#[derive(Debug)]
+pub struct SystemEntry<T: Schedulable> {
+    name: &'static str,
+    key: EntryKey,
+    stage: &'static str,
+    timing: Timing,
+    before: SmallVec<[&'static str; 2]>,
+    after: SmallVec<[&'static str; 2]>,
+    run_if: Option<RunCondition<T>>,
+    timer: Option<RepeatingTimer>,
+    pub element: T,
+}
+
+type ConditionFn<C> = dyn Fn(&C) -> bool;
+
+pub struct RunCondition<T: Schedulable>(Box<ConditionFn<T::Context>>);
+
+impl<T: Schedulable> std::fmt::Debug for RunCondition<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RunCondition")
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RepeatingTimer {
+    interval: Duration,
+    elapsed: Duration,
+}
+
+impl<T: Schedulable> SystemEntry<T> {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn key(&self) -> EntryKey {
+        self.key
+    }
+
+    pub fn in_stage(&mut self, stage: &'static str) -> &mut Self {
+        self.stage = stage;
+        self
+    }
+
+    pub fn timing(&mut self, timing: Timing) -> &mut Self {
+        self.timing = timing;
+        self
+    }
+
+    /// This system runs before the system with the given name (if both are in the same stage).
+    pub fn before(&mut self, name: &'static str) -> &mut Self {
+        self.before.push(name);
+        self
+    }
+
+    /// This system runs after the system with the given name (if both are in the same stage).
+    pub fn after(&mut self, name: &'static str) -> &mut Self {
+        self.after.push(name);
+        self
+    }
+
+    /// The system is skipped in `Scheduler::run` while the condition returns false for the context.
+    pub fn run_if(&mut self, condition: impl Fn(&T::Context) -> bool + 'static) -> &mut Self {
+        self.run_if = Some(RunCondition(Box::new(condition)));
+        self
+    }
+
+    /// The system only runs once every `interval` instead of every frame.
+    pub fn every(&mut self, interval: Duration) -> &mut Self {
+        self.timer = Some(RepeatingTimer {
+            interval,
+            elapsed: Duration::ZERO,
+        });
+        self
+    }
+
+    /// Checks the run condition and advances the timer.
+    fn should_run(&mut self, ctx: &T::Context, delta: Duration) -> bool {
+        if let Some(RunCondition(condition)) = &self.run_if {
+            if !condition(ctx) {
+                return false;
+            }
+        }
+        if let Some(timer) = &mut self.timer {
+            timer.elapsed += delta;
+            if timer.elapsed < timer.interval {
+                return false;
+            }
+            // no catching up, if multiple intervals passed in one frame.
+            timer.elapsed = Duration::from_nanos(
+                (timer.elapsed.as_nanos() % timer.interval.as_nanos().max(1)) as u64,
+            );
+        }
+        true
+    }
+}
+
+impl<T: Schedulable> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Schedulable> Scheduler<T> {
+    pub const FIRST: &'static str = "first";
+    pub const UPDATE: &'static str = "update";
+    pub const LAST: &'static str = "last";
+
+    /// Scheduler with the stages `first`, `update` and `last`.
+    pub fn new() -> Self {
+        Scheduler {
+            next_key: 0,
+            stages: vec![Self::FIRST, Self::UPDATE, Self::LAST],
+            systems: vec![],
+            resolved: true,
+            one_shots: vec![],
+            reserved_names: vec![],
+        }
+    }
+
+    /// Adds a new stage right after the stage `after`.
+    pub fn add_stage_after(&mut self, stage: &'static str, after: &'static str) {
+        assert!(
+            !self.stages.contains(&stage),
+            "stage {stage} already exists"
+        );
+        let index = self
+            .stages
+            .iter()
+            .position(|s| *s == after)
+            .unwrap_or_else(|| panic!("stage {after} does not exist"));
+        self.stages.insert(index + 1, stage);
+        self.resolved = false;
+    }
+
+    /// Adds a system to the `update` stage. Use the returned entry to configure stage and ordering.
+    /// Errors if there is already a system with this name, because `before`/`after` refer to systems by name.
+    pub fn add(&mut self, name: &'static str, element: T) -> anyhow::Result<&mut SystemEntry<T>> {
+        if self.systems.iter().any(|e| e.name == name) || self.reserved_names.contains(&name) {
+            anyhow::bail!("there is already a system named {name}");
+        }
+        let key = EntryKey(self.next_key);
+        self.next_key += 1;
+        self.resolved = false;
+        self.systems.push(SystemEntry {
+            name,
+            key,
+            stage: Self::UPDATE,
+            timing: Timing::DEFAULT,
+            before: SmallVec::new(),
+            after: SmallVec::new(),
+            run_if: None,
+            timer: None,
+            element,
+        });
+        Ok(self.systems.last_mut().unwrap())
+    }
+
+    /// The key of the next added system. Keys are handed out in increasing order.
+    pub fn next_key(&self) -> EntryKey {
+        EntryKey(self.next_key)
+    }
+
+    /// Keys of the systems that were added since `next_key` returned `key`.
+    pub fn keys_since(&self, key: EntryKey) -> Vec<EntryKey> {
+        self.systems
+            .iter()
+            .map(|e| e.key)
+            .filter(|k| *k >= key)
+            .collect()
+    }
+
+    pub fn remove(&mut self, key: EntryKey) -> Option<T> {
+        let index = self.systems.iter().position(|e| e.key == key)?;
+        Some(self.systems.remove(index).element)
+    }
+
+    pub fn get_mut(&mut self, key: EntryKey) -> Option<&mut SystemEntry<T>> {
+        self.resolved = false;
+        self.systems.iter_mut().find(|e| e.key == key)
+    }
+
+    /// `element` is run once at the start of the next `run` and then dropped.
+    pub fn run_next_frame(&mut self, element: T) {
+        self.one_shots.push(element);
+    }
+
+    /// All systems in the resolved order, ignoring run conditions and timers.
+    /// Errors if the ordering constraints contain a cycle.
+    pub fn iter_mut(&mut self) -> anyhow::Result<impl Iterator<Item = &mut T>> {
+        self.resolve()?;
+        Ok(self.systems.iter_mut().map(|e| &mut e.element))
+    }
+
+    /// Calls `f` for the pending one-shot elements, then for all systems in order whose run condition is met
+    /// for `ctx` and whose timer (see `SystemEntry::every`) has elapsed. `delta` is usually `Time::delta`.
+    pub fn run(
+        &mut self,
+        ctx: &mut T::Context,
+        delta: Duration,
+        mut f: impl FnMut(&mut T, &mut T::Context),
+    ) -> anyhow::Result<()> {
+        self.resolve()?;
+        for mut one_shot in std::mem::take(&mut self.one_shots) {
+            f(&mut one_shot, ctx);
+        }
+        for entry in self.systems.iter_mut() {
+            if entry.should_run(ctx, delta) {
+                f(&mut entry.element, ctx);
+            }
+        }
+        Ok(())
+    }
+
+    /// An empty scheduler with the same stages, that hands out the next keys of this one
+    /// and rejects the names of its systems.
+    fn empty_copy(&self) -> Self {
+        Scheduler {
+            next_key: self.next_key,
+            stages: self.stages.clone(),
+            systems: vec![],
+            resolved: true,
+            one_shots: vec![],
+            reserved_names: self.systems.iter().map(|e| e.name).collect(),
+        }
+    }
+
+    /// Moves the systems, one-shots and stages that were added to `other` into this scheduler.
+    fn absorb(&mut self, other: Self) {
+        self.next_key = self.next_key.max(other.next_key);
+        self.resolved &= other.systems.is_empty() && self.stages == other.stages;
+        self.stages = other.stages;
+        self.systems.extend(other.systems);
+        self.one_shots.extend(other.one_shots);
+    }
+
+    /// Sorts the systems according to stages and ordering constraints.
+    pub fn resolve(&mut self) -> anyhow::Result<()> {
+        if self.resolved {
+            return Ok(());
+        }
+        let order = self.resolved_order()?;
+        let mut systems: Vec<Option<SystemEntry<T>>> = std::mem::take(&mut self.systems)
+            .into_iter()
+            .map(Some)
+            .collect();
+        self.systems = order
+            .into_iter()
+            .map(|i| systems[i].take().unwrap())
+            .collect();
+        self.resolved = true;
+        Ok(())
+    }
+
+    /// Indices into `self.systems` in the order they should run.
+    fn resolved_order(&self) -> anyhow::Result<Vec<usize>> {
+        let by_name: HashMap<&'static str, usize> = self
+            .systems
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.name, i))
+            .collect();
+
+        let mut order: Vec<usize> = Vec::with_capacity(self.systems.len());
+        for stage in self.stages.iter() {
+            let mut in_stage: Vec<usize> = (0..self.systems.len())
+                .filter(|i| self.systems[*i].stage == *stage)
+                .collect();
+            // ties are broken by timing, then by insertion order (= key):
+            in_stage.sort_by_key(|i| (self.systems[*i].timing, self.systems[*i].key));
+
+            // edges[a] contains b, if a has to run before b.
+            let mut edges: HashMap<usize, SmallVec<[usize; 4]>> = HashMap::new();
+            let mut in_degree: HashMap<usize, usize> = in_stage.iter().map(|i| (*i, 0)).collect();
+            for &i in in_stage.iter() {
+                let entry = &self.systems[i];
+                let afters = entry.after.iter().map(|n| (*n, true));
+                let befores = entry.before.iter().map(|n| (*n, false));
+                for (other_name, is_after) in afters.chain(befores) {
+                    let Some(&other) = by_name.get(other_name) else {
+                        log::warn!(
+                            "system {} references unknown system {other_name}",
+                            entry.name
+                        );
+                        continue;
+                    };
+                    if self.systems[other].stage != *stage {
+                        // ordering across stages is already determined by the stage order.
+                        continue;
+                    }
+                    let (from, to) = if is_after { (other, i) } else { (i, other) };
+                    edges.entry(from).or_default().push(to);
+                    *in_degree.get_mut(&to).unwrap() += 1;
+                }
+            }
+
+            // Kahn's algorithm, always taking the first ready system in tie-break order:
+            let mut remaining = in_stage;
+            while !remaining.is_empty() {
+                let Some(pos) = remaining.iter().position(|i| in_degree[i] == 0) else {
+                    let names: Vec<&str> =
+                        remaining.iter().map(|i| self.systems[*i].name).collect();
+                    anyhow::bail!(
+                        "cycle in ordering constraints of stage {stage} between systems: {}",
+                        names.join(", ")
+                    );
+                };
+                let next = remaining.remove(pos);
+                for to in edges.get(&next).into_iter().flatten() {
+                    *in_degree.get_mut(to).unwrap() -= 1;
+                }
+                order.push(next);
+            }
+        }
+
+        if order.len() != self.systems.len() {
+            let unknown: Vec<&str> = self
+                .systems
+                .iter()
+                .filter(|e| !self.stages.contains(&e.stage))
+                .map(|e| e.stage)
+                .collect();
+            anyhow::bail!("systems in unknown stages: {}", unknown.join(", "));
+        }
+        Ok(order)
+    }
+
+    /// Human readable listing of the resolved order, for debugging.
+    pub fn dump(&mut self) -> String {
+        let mut out = String::new();
+        if let Err(err) = self.resolve() {
+            writeln!(out, "unresolved: {err}").unwrap();
+            return out;
+        }
+        for stage in self.stages.iter() {
+            writeln!(out, "{stage}:").unwrap();
+            for (i, entry) in self
+                .systems
+                .iter()
+                .filter(|e| e.stage == *stage)
+                .enumerate()
+            {
+                write!(out, "  {}. {}", i + 1, entry.name).unwrap();
+                if !entry.after.is_empty() {
+                    write!(out, " after: {:?}", entry.after.as_slice()).unwrap();
+                }
+                if !entry.before.is_empty() {
+                    write!(out, " before: {:?}", entry.before.as_slice()).unwrap();
+                }
+                writeln!(out).unwrap();
+            }
+        }
+        out
+    }
+}
+
+impl<S> Scheduler<System<S>> {
+    /// Runs the systems of the scheduler that `get` returns from `state`, giving each of them mutable access to
+    /// `state`. The scheduler is taken out of `state` while it runs: systems can add new systems (they run from the
+    /// next frame on) but removing a system from within a system has no effect.
+    pub fn run_on(
+        state: &mut S,
+        get: impl Fn(&mut S) -> &mut Self,
+        delta: Duration,
+    ) -> anyhow::Result<()> {
+        let placeholder = get(state).empty_copy();
+        let mut scheduler = std::mem::replace(get(state), placeholder);
+        let result = scheduler.run(state, delta, |system, state| system(state));
+        let added = std::mem::replace(get(state), scheduler);
+        get(state).absorb(added);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::States;
+
+    impl Schedulable for &'static str {
+        type Context = ();
+    }
+
+    impl Schedulable for i32 {
+        type Context = ();
+    }
+
+    fn names(scheduler: &mut Scheduler<&'static str>) -> Vec<&'static str> {
+        scheduler.iter_mut().unwrap().map(|e| *e).collect()
+    }
+
+    #[test]
+    fn ordering() {
+        let mut scheduler = Scheduler::new();
+        scheduler.add("c", "c").unwrap().after("b");
+        scheduler
+            .add("a", "a")
+            .unwrap()
+            .in_stage(Scheduler::<&str>::FIRST);
+        scheduler.add("b", "b").unwrap();
+        scheduler.add("d", "d").unwrap().before("b");
+        assert_eq!(names(&mut scheduler), ["a", "d", "b", "c"]);
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let mut scheduler = Scheduler::new();
+        scheduler.add("physics", 1).unwrap();
+        assert!(scheduler.add("physics", 2).is_err());
+        assert_eq!(scheduler.iter_mut().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let mut scheduler = Scheduler::new();
+        scheduler.add("a", "a").unwrap().after("c");
+        scheduler.add("b", "b").unwrap().after("a");
+        scheduler.add("c", "c").unwrap().after("b");
+        scheduler.add("d", "d").unwrap();
+        let err = scheduler.resolve().unwrap_err().to_string();
+        assert!(err.contains("a, b, c"), "{err}");
+        assert!(scheduler.run(&mut (), Duration::ZERO, |_, _| {}).is_err());
+        assert!(scheduler.dump().starts_with("unresolved"));
+    }
+
+    #[test]
+    fn systems_get_their_owner() {
+        #[derive(Default)]
+        struct State {
+            counter: u32,
+            add_errors: u32,
+            schedule: Scheduler<System<State>>,
+        }
+        let mut state = State::default();
+        state
+            .schedule
+            .add("count", Box::new(|s: &mut State| s.counter += 1))
+            .unwrap();
+        state
+            .schedule
+            .add(
+                "add",
+                Box::new(|s: &mut State| {
+                    let added = s
+                        .schedule
+                        .add("count twice", Box::new(|s: &mut State| s.counter += 2));
+                    if added.is_err() {
+                        s.add_errors += 1;
+                    }
+                }),
+            )
+            .unwrap();
+        Scheduler::run_on(&mut state, |s| &mut s.schedule, Duration::ZERO).unwrap();
+        assert_eq!((state.counter, state.add_errors), (1, 0));
+        // "count twice" is taken by now, even though the scheduler is swapped out while it runs:
+        Scheduler::run_on(&mut state, |s| &mut s.schedule, Duration::ZERO).unwrap();
+        assert_eq!((state.counter, state.add_errors), (4, 1));
+        // so there is only one "count twice" system.
+        Scheduler::run_on(&mut state, |s| &mut s.schedule, Duration::ZERO).unwrap();
+        assert_eq!((state.counter, state.add_errors), (7, 2));
+        assert_eq!(state.schedule.iter_mut().unwrap().count(), 3);
+    }
+
+    #[test]
+    fn run_conditions_read_the_context() {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        enum Mode {
+            Menu,
+            Playing,
+        }
+        struct State {
+            mode: States<Mode, ()>,
+            ticks: u32,
+            schedule: Scheduler<System<State>>,
+        }
+        let mut state = State {
+            mode: States::new(Mode::Menu),
+            ticks: 0,
+            schedule: Scheduler::new(),
+        };
+        state
+            .schedule
+            .add("tick", Box::new(|s: &mut State| s.ticks += 1))
+            .unwrap()
+            .run_if(|s: &State| s.mode.current() == Mode::Playing);
+
+        let frame = |state: &mut State| {
+            state.mode.update(&mut (), Duration::ZERO).unwrap();
+            Scheduler::run_on(state, |s| &mut s.schedule, Duration::ZERO).unwrap();
+        };
+        frame(&mut state);
+        assert_eq!(state.ticks, 0);
+        state.mode.set(Mode::Playing);
+        frame(&mut state);
+        frame(&mut state);
+        assert_eq!(state.ticks, 2);
+        state.mode.set(Mode::Menu);
+        frame(&mut state);
+        assert_eq!(state.ticks, 2);
+    }
+}
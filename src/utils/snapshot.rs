@@ -0,0 +1,406 @@
+//! Snapshot testing: compares text (e.g. `Board::layout_snapshot`) and rendered images against reference files
+//! that are checked into the repository.
+//!
+//! If a reference is missing or does not match, the actual result is written next to it (`.snap.new`, `.new.png`
+//! and a `.diff.png` that marks the differing pixels) and the test fails, so a deleted reference is not silently
+//! recreated on ci. Run the tests with `UPDATE_SNAPSHOTS=1` to write new references and accept all changes, e.g.
+//! `UPDATE_SNAPSHOTS=1 cargo test widgets_layout_snapshot` regenerates `src/modules/ui/snapshots/widgets_layout.snap`.
+//!
+//! `HeadlessGpu` is only a device and a queue: the modules need a `GraphicsContext`, which requires a window
+//! surface, so renderers like `UiRenderer` or the `Bloom` cannot draw into it yet (see todo.md). Image snapshots
+//! are limited to passes that are recorded by hand.
+//!
+//! ```rust,ignore
+//! let snapshots = Snapshots::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots"));
+//! snapshots.assert_text("main_menu_layout", &harness.board.layout_snapshot());
+//!
+//! let Some(gpu) = HeadlessGpu::new() else { return }; // no adapter on this machine
+//! let image = gpu.render_to_image(uvec2(64, 64), |encoder, view| { /* draw into view */ });
+//! snapshots.assert_image("scene", &image, ImageTolerance::default());
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use glam::UVec2;
+use image::{Rgba, RgbaImage};
+
+/// Reference files are read from and written to `dir`.
+#[derive(Debug, Clone)]
+pub struct Snapshots {
+    dir: PathBuf,
+}
+
+impl Snapshots {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Snapshots { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Panics if `actual` differs from `<dir>/<name>.snap`.
+    pub fn assert_text(&self, name: &str, actual: &str) {
+        let path = self.dir.join(format!("{name}.snap"));
+        let new_path = self.dir.join(format!("{name}.snap.new"));
+        let expected = std::fs::read_to_string(&path).ok();
+        // line endings can be converted by git on checkout.
+        if expected
+            .as_deref()
+            .map(|e| e.replace("\r\n", "\n"))
+            .as_deref()
+            == Some(actual)
+        {
+            let _ = std::fs::remove_file(&new_path);
+            return;
+        }
+        if update_snapshots() {
+            self.write(&path, actual.as_bytes());
+            return;
+        }
+        self.write(&new_path, actual.as_bytes());
+        let Some(expected) = expected else {
+            panic!(
+                "snapshot `{name}` has no reference {}, the new one is written to {}, run with UPDATE_SNAPSHOTS=1 to accept it",
+                path.display(),
+                new_path.display()
+            );
+        };
+        panic!(
+            "snapshot `{name}` does not match {}, the new one is written to {}:\n{}",
+            path.display(),
+            new_path.display(),
+            line_diff(&expected, actual)
+        );
+    }
+
+    /// Panics if `actual` differs from `<dir>/<name>.png` by more than the `tolerance`.
+    pub fn assert_image(&self, name: &str, actual: &RgbaImage, tolerance: ImageTolerance) {
+        let path = self.dir.join(format!("{name}.png"));
+        let new_path = self.dir.join(format!("{name}.new.png"));
+        let diff_path = self.dir.join(format!("{name}.diff.png"));
+        let expected = image::open(&path).ok().map(|e| e.to_rgba8());
+        let diff = expected
+            .as_ref()
+            .map(|e| compare_images(e, actual, tolerance));
+        if diff.as_ref().is_some_and(|d| d.passes(tolerance)) {
+            let _ = std::fs::remove_file(&new_path);
+            let _ = std::fs::remove_file(&diff_path);
+            return;
+        }
+        if update_snapshots() {
+            self.create_dir();
+            actual.save(&path).expect("cannot write image snapshot");
+            return;
+        }
+        self.create_dir();
+        actual.save(&new_path).expect("cannot write image snapshot");
+        let Some(diff) = diff else {
+            panic!(
+                "image snapshot `{name}` has no reference {}, the new one is written to {}, run with UPDATE_SNAPSHOTS=1 to accept it",
+                path.display(),
+                new_path.display()
+            );
+        };
+        if let Some(diff_image) = &diff.image {
+            diff_image
+                .save(&diff_path)
+                .expect("cannot write image diff");
+        }
+        panic!(
+            "image snapshot `{name}` does not match {}: {diff}, the new one is written to {}",
+            path.display(),
+            new_path.display(),
+        );
+    }
+
+    fn create_dir(&self) {
+        std::fs::create_dir_all(&self.dir).expect("cannot create snapshot directory");
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) {
+        self.create_dir();
+        std::fs::write(path, contents).expect("cannot write snapshot");
+    }
+}
+
+fn update_snapshots() -> bool {
+    std::env::var_os("UPDATE_SNAPSHOTS").is_some_and(|v| v != "0")
+}
+
+/// The lines that differ, prefixed with `-` (expected) and `+` (actual).
+fn line_diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let (e, a) = (expected.get(i), actual.get(i));
+        if e == a {
+            continue;
+        }
+        if let Some(e) = e {
+            out.push_str(&format!("{:>4} - {e}\n", i + 1));
+        }
+        if let Some(a) = a {
+            out.push_str(&format!("{:>4} + {a}\n", i + 1));
+        }
+    }
+    out
+}
+
+/// How much a rendered image may differ from its reference, e.g. because of different gpu drivers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageTolerance {
+    /// A pixel counts as different if any channel differs by more than this.
+    pub channel: u8,
+    /// Fraction of pixels that may be different.
+    pub pixels: f32,
+}
+
+impl Default for ImageTolerance {
+    fn default() -> Self {
+        ImageTolerance {
+            channel: 2,
+            pixels: 0.001,
+        }
+    }
+}
+
+impl ImageTolerance {
+    pub const EXACT: ImageTolerance = ImageTolerance {
+        channel: 0,
+        pixels: 0.0,
+    };
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageDiff {
+    /// None if the sizes match.
+    pub size_mismatch: Option<(UVec2, UVec2)>,
+    pub differing_pixels: usize,
+    pub total_pixels: usize,
+    pub max_channel_diff: u8,
+    /// Differing pixels in red over a faded version of the expected image, None if the sizes do not match.
+    pub image: Option<RgbaImage>,
+}
+
+impl ImageDiff {
+    pub fn passes(&self, tolerance: ImageTolerance) -> bool {
+        self.size_mismatch.is_none()
+            && self.differing_pixels as f32 <= tolerance.pixels * self.total_pixels as f32
+    }
+}
+
+impl std::fmt::Display for ImageDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((expected, actual)) = self.size_mismatch {
+            return write!(f, "size is {actual}, expected {expected}");
+        }
+        write!(
+            f,
+            "{} of {} pixels differ, by up to {}",
+            self.differing_pixels, self.total_pixels, self.max_channel_diff
+        )
+    }
+}
+
+pub fn compare_images(
+    expected: &RgbaImage,
+    actual: &RgbaImage,
+    tolerance: ImageTolerance,
+) -> ImageDiff {
+    let (expected_size, actual_size) = (
+        UVec2::from(expected.dimensions()),
+        UVec2::from(actual.dimensions()),
+    );
+    if expected_size != actual_size {
+        return ImageDiff {
+            size_mismatch: Some((expected_size, actual_size)),
+            differing_pixels: 0,
+            total_pixels: 0,
+            max_channel_diff: 0,
+            image: None,
+        };
+    }
+    let mut image = RgbaImage::new(expected_size.x, expected_size.y);
+    let mut differing_pixels = 0;
+    let mut max_channel_diff = 0;
+    for ((e, a), out) in expected
+        .pixels()
+        .zip(actual.pixels())
+        .zip(image.pixels_mut())
+    {
+        let diff = (0..4).map(|c| e[c].abs_diff(a[c])).max().unwrap();
+        max_channel_diff = max_channel_diff.max(diff);
+        *out = if diff > tolerance.channel {
+            differing_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([e[0] / 4, e[1] / 4, e[2] / 4, 255])
+        };
+    }
+    ImageDiff {
+        size_mismatch: None,
+        differing_pixels,
+        total_pixels: (expected_size.x * expected_size.y) as usize,
+        max_channel_diff,
+        image: Some(image),
+    }
+}
+
+/// A device without a window, for rendering in tests. There is no `GraphicsContext` for it, so it can only run
+/// render passes that are recorded directly on its device, not the renderers of the modules.
+pub struct HeadlessGpu {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+impl HeadlessGpu {
+    /// Same color format as the images that are compared.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// None if there is no adapter, e.g. on ci machines without gpu. Tests should return early then.
+    pub fn new() -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        block_on(async {
+            let adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::LowPower,
+                    force_fallback_adapter: false,
+                    compatible_surface: None,
+                })
+                .await?;
+            let (device, queue) = adapter
+                .request_device(&wgpu::DeviceDescriptor::default(), None)
+                .await
+                .ok()?;
+            Some(HeadlessGpu { device, queue })
+        })
+    }
+
+    /// Creates a `FORMAT` texture of `size`, lets `render` record passes into it and reads it back.
+    pub fn render_to_image(
+        &self,
+        size: UVec2,
+        render: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::TextureView),
+    ) -> RgbaImage {
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Texture"),
+            size: wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        // rows of a texture to buffer copy need to be aligned to 256 bytes.
+        let row_bytes = size.x * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_bytes = row_bytes.div_ceil(align) * align;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Readback Buffer"),
+            size: (padded_row_bytes * size.y) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Offscreen Encoder"),
+            });
+        render(&mut encoder, &view);
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: Some(size.y),
+                },
+            },
+            texture.size(),
+        );
+        self.queue.submit([encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("cannot map readback buffer")
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        let data = slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((row_bytes * size.y) as usize);
+        for row in data.chunks(padded_row_bytes as usize) {
+            pixels.extend_from_slice(&row[..row_bytes as usize]);
+        }
+        drop(data);
+        buffer.unmap();
+        RgbaImage::from_raw(size.x, size.y, pixels).unwrap()
+    }
+}
+
+/// Blocks on a future on the current thread, wgpu futures resolve right away on native.
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_tolerance() {
+        let expected = RgbaImage::from_pixel(10, 10, Rgba([100, 100, 100, 255]));
+        let mut actual = expected.clone();
+        actual.put_pixel(0, 0, Rgba([102, 100, 100, 255]));
+        let diff = compare_images(&expected, &actual, ImageTolerance::default());
+        assert!(diff.passes(ImageTolerance::default()));
+        assert!(!compare_images(&expected, &actual, ImageTolerance::EXACT)
+            .passes(ImageTolerance::EXACT));
+
+        actual.put_pixel(1, 0, Rgba([0, 0, 0, 255]));
+        let diff = compare_images(&expected, &actual, ImageTolerance::default());
+        assert_eq!((diff.differing_pixels, diff.max_channel_diff), (1, 100));
+        assert!(diff.passes(ImageTolerance {
+            channel: 2,
+            pixels: 0.01
+        }));
+        assert!(!diff.passes(ImageTolerance::default()));
+    }
+
+    #[test]
+    fn offscreen_clear_color() {
+        let Some(gpu) = HeadlessGpu::new() else {
+            return;
+        };
+        let image = gpu.render_to_image(UVec2::new(70, 3), |encoder, view| {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::RED),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        });
+        let expected = RgbaImage::from_pixel(70, 3, Rgba([255, 0, 0, 255]));
+        let diff = compare_images(&expected, &image, ImageTolerance::default());
+        assert!(diff.passes(ImageTolerance::default()), "{diff}");
+    }
+}
@@ -71,7 +71,7 @@ impl<T> TimingQueue<T> {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct EntryKey(i32);
+pub struct EntryKey(pub(super) i32);
 
 /// Timing can be thought of as the inverse of Priority.
 /// A high timing value means, a function will be executed later in a schedule.